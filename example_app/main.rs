@@ -1,174 +1,277 @@
 use log::{error, info};
-pub mod jr_image;
-mod vulkan;
+mod mouse;
+#[cfg(feature = "python")]
+mod python;
+
+use std::{sync::mpsc::Receiver, time::Duration};
 
 use winit::{
-    event::{DeviceEvent, ElementState, Event, VirtualKeyCode, WindowEvent},
-    event_loop::EventLoop,
+    event::{MouseButton, VirtualKeyCode, WindowEvent},
     window::WindowBuilder,
 };
 
-use crate::vulkan::Vulkan;
+use juryrig::prelude::*;
+use juryrig::vulkan;
+
+// Counts the demo's heap allocations for `Vulkan::allocation_stats`.
+#[cfg(feature = "alloc-telemetry")]
+#[global_allocator]
+static GLOBAL: juryrig::alloc_telemetry::TrackingAllocator =
+    juryrig::alloc_telemetry::TrackingAllocator;
+
+// Longest step the camera controller takes, so a stall or a paused window doesn't send the camera
+// flying.
+const MAX_CAMERA_STEP: f32 = 0.1;
+
+// The demo's keys, on top of the camera controller's.
+const ACTIONS: [(&str, VirtualKeyCode); 7] = [
+    ("mouse_look", VirtualKeyCode::M),
+    ("test_pattern", VirtualKeyCode::F1),
+    ("debug_view", VirtualKeyCode::F2),
+    ("sky", VirtualKeyCode::K),
+    ("advance_sky", VirtualKeyCode::T),
+    ("present_mode", VirtualKeyCode::V),
+    ("quit", VirtualKeyCode::Escape),
+];
 
 fn main() {
     pretty_env_logger::init_custom_env("JR_LOG_LEVEL");
+    #[cfg(feature = "python")]
+    python::init();
 
     info!("Logs initialised.");
-    let event_loop = EventLoop::new();
-
-    match WindowBuilder::new()
-        .with_title("Rara se window")
-        .build(&event_loop)
-    {
-        Ok(window) => {
-            let cached_window_id = window.id().clone();
-            let mut vulkan: Option<Vulkan> = None;
-
-            let mut atlas_image = image::io::Reader::open("MC_Atlas.png")
-                .expect("could not open image")
-                .decode()
-                .expect("could not decode image")
-                .flipv();
-            let atlas = jr_image::RGBAImage::new(atlas_image.width(), atlas_image.height());
-            unsafe {
-                std::ptr::copy_nonoverlapping(
-                    atlas_image.as_rgba8().unwrap().as_ptr(),
-                    atlas.data.as_ptr() as *mut u8,
-                    (atlas.width * atlas.height * 4) as usize,
-                );
+    // `--bake environment.hdr out.probes` bakes the environment's light without opening a window,
+    // run the demo with JR_LIGHT_PROBES=out.probes to light the scene with it.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let [flag, environment, output] = args.as_slice() {
+        if flag == "--bake" {
+            bake(environment, output);
+            return;
+        }
+    }
+    run(Demo::default());
+}
+
+fn bake(environment: &str, output: &str) {
+    let baked = vulkan::ComputeContext::new("Juryrig bake")
+        .map_err(|e| format!("{:?}", e))
+        .and_then(|mut context| {
+            vulkan::bake(&mut context, environment, output).map_err(|e| format!("{:?}", e))
+        });
+    match baked {
+        Ok(_) => info!("Baked {} into {}", environment, output),
+        Err(e) => error!("Could not bake {}. {}", environment, e),
+    }
+}
+
+#[derive(Default)]
+struct Demo {
+    mouse: mouse::RelativeMouse,
+    controller: Option<CameraController>,
+    // Textures are collected once their last handle is dropped.
+    textures: Vec<TextureHandle>,
+    engine_events: Option<Receiver<EngineEvent>>,
+    // The spinning cubes, with where they sit and how fast they turn around x, y and z.
+    demo_cubes: Vec<(NodeId, Vector3<f32>, Vector3<f32>)>,
+    // How far they've turned, advanced a fixed step at a time.
+    turned: Interpolated<f32>,
+    // The cubes' textures, decoded in the background. The cubes are added once both are ready.
+    demo_textures: Option<[AssetHandle<TextureHandle>; 2]>,
+}
+
+impl JuryrigApp for Demo {
+    fn window(&self) -> WindowBuilder {
+        WindowBuilder::new().with_title("Rara se window")
+    }
+
+    // The simulation runs at 60Hz whatever the display does, frames in between are interpolated.
+    fn fixed_timestep(&self) -> Option<Duration> {
+        Some(Duration::from_secs(1) / 60)
+    }
+
+    fn start(&mut self, context: &mut AppContext) {
+        for monitor in vulkan::monitors(context.window) {
+            info!(
+                "Monitor {} {}x{} at {:?} Hz",
+                monitor.name.as_deref().unwrap_or("unnamed"),
+                monitor.width,
+                monitor.height,
+                monitor.refresh_rate
+            );
+        }
+        for (action, key) in ACTIONS {
+            context.input.bind(action, Binding::Key(key));
+        }
+        context
+            .input
+            .bind("pick", Binding::Mouse(MouseButton::Right));
+        let v = &mut *context.vulkan;
+        self.controller = Some(CameraController::new(&v.camera));
+        self.engine_events = Some(v.subscribe());
+        self.demo_textures = Some([
+            v.load_texture("MC_Atlas.png", false),
+            v.load_texture("MC_Atlas.png", true),
+        ]);
+        if let Ok(path) = std::env::var("JR_LIGHT_PROBES") {
+            match LightProbeGrid::load(&path) {
+                Ok(grid) => v.light_probes = grid,
+                Err(e) => error!("Could not load light probes from {}. {:?}", path, e),
             }
-            drop(atlas_image);
-
-            let mut other_image = image::io::Reader::open("MC_Atlas.png")
-                .expect("could not open image")
-                .decode()
-                .expect("could not decode image");
-            let other = jr_image::RGBAImage::new(other_image.width(), other_image.height());
-            unsafe {
-                std::ptr::copy_nonoverlapping(
-                    other_image.as_rgba8().unwrap().as_ptr(),
-                    other.data.as_ptr() as *mut u8,
-                    (other.width * other.height * 4) as usize,
-                );
+        }
+        #[cfg(feature = "python")]
+        if let Ok(script) = std::env::var("JR_SCENE_SCRIPT") {
+            self.textures.extend(python::run_scene_script(&script, v));
+        }
+    }
+
+    fn window_event(&mut self, context: &mut AppContext, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::Focused(focused) => {
+                self.mouse.focus_changed(context.window, *focused);
+                if let (false, Some(controller)) = (focused, &mut self.controller) {
+                    controller.release_all();
+                }
             }
-            drop(other_image);
-
-            event_loop.run(move |event, _, control_flow| {
-                control_flow.set_poll();
-                match event {
-                    Event::WindowEvent {
-                        window_id,
-                        event: WindowEvent::CloseRequested,
-                    } => {
-                        if cached_window_id == window_id {
-                            control_flow.set_exit()
-                        }
-                    }
-                    Event::WindowEvent {
-                        window_id,
-                        event: WindowEvent::Resized(size),
-                    } => {
-                        if cached_window_id == window_id {
-                            match &mut vulkan {
-                                Some(v) => {
-                                    v.resize_surface(size.width, size.height);
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                    Event::WindowEvent {
-                        event: WindowEvent::KeyboardInput { input, .. },
-                        ..
-                    } => {
-                        if let winit::event::KeyboardInput {
-                            state: winit::event::ElementState::Pressed,
-                            virtual_keycode: Some(keycode),
-                            ..
-                        } = input
-                        {
-                            match &mut vulkan {
-                                Some(v) => match keycode {
-                                    winit::event::VirtualKeyCode::Right => {
-                                        v.camera.turn_right(0.1);
-                                    }
-                                    winit::event::VirtualKeyCode::Left => {
-                                        v.camera.turn_left(0.1);
-                                    }
-                                    winit::event::VirtualKeyCode::Up => {
-                                        v.camera.move_forward(0.05);
-                                    }
-                                    winit::event::VirtualKeyCode::Down => {
-                                        v.camera.move_backward(0.05);
-                                    }
-                                    winit::event::VirtualKeyCode::PageUp => {
-                                        v.camera.turn_up(0.02);
-                                    }
-                                    winit::event::VirtualKeyCode::PageDown => {
-                                        v.camera.turn_down(0.02);
-                                    }
-                                    _ => {}
-                                },
-                                _ => {}
-                            }
-                        }
-                    }
-                    Event::DeviceEvent {
-                        device_id: _device_id,
-                        event,
-                    } => {
-                        // info!("Id: {:?}", _device_id);
-                        match event {
-                            DeviceEvent::Key(input) => {
-                                // info!("Key Event {:?}", input.virtual_keycode.unwrap());
-                                if input.state == ElementState::Pressed
-                                    && input.virtual_keycode == Some(VirtualKeyCode::Escape)
-                                {
-                                    control_flow.set_exit()
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-                    Event::Resumed => {
-                        //TODO: Initialise graphics context
-                        info!("Event-Startup");
-                        vulkan = Some(Vulkan::new(&window).expect("Could not init vulkan!"));
-                        match &mut vulkan {
-                            Some(v) => {
-                                // let mesh_handle = v.register_mesh(vertex_data, index_data);
-                                let texture_handle = v.register_texture(&other);
-                                let texture_handle = v.register_texture(&atlas);
-                                //let model = Entity::new(mesh_handle, texture_handle);
-                            }
-                            _ => {}
-                        }
-                    }
-                    Event::LoopDestroyed => {
-                        // TODO: Destroy everything here
-                        info!("Event-End");
-                        vulkan = None
-                    }
-                    Event::MainEventsCleared => {
-                        // Event processing happens here
-                        window.request_redraw();
-                    }
-                    Event::RedrawRequested(_) => match &mut vulkan {
-                        Some(v) => match v.swap_framebuffers() {
-                            Err(e) => {
-                                error!("Could not render frame! {:?}", e)
-                            }
-                            Ok(_) => {}
-                        },
-                        None => {}
-                    },
-
-                    _ => {}
+            WindowEvent::KeyboardInput { input, .. } => {
+                if let Some(controller) = &mut self.controller {
+                    controller.keyboard_input(input);
                 }
+            }
+            _ => {}
+        }
+        false
+    }
+
+    fn update(&mut self, context: &mut AppContext, delta_time: f32) {
+        self.actions(context);
+        let delta_time = delta_time.min(MAX_CAMERA_STEP);
+        self.mouse.motion(context.input.mouse_delta());
+        let mouse_delta = self.mouse.take_delta(context.window);
+        for event in self
+            .engine_events
+            .iter()
+            .flat_map(|events| events.try_iter())
+        {
+            info!("Engine event {:?}", event);
+        }
+        let v = &mut *context.vulkan;
+        if let Some(loads) = &self.demo_textures {
+            if let Some(error) = loads.iter().find_map(|load| load.error()) {
+                error!("Could not load the demo textures. {}", error);
+                self.demo_textures = None;
+            } else if let [Some(other), Some(atlas)] = loads.clone().map(|load| load.get()) {
+                let cube = v.cube_mesh();
+                let root = v.scene.add(SceneNode::new(Matrix4::identity()), None);
+                for (position, spin, texture) in [
+                    (Vector3::zeros(), Vector3::zeros(), &other),
+                    (Vector3::z() * 3.0, Vector3::y() / 3.0, &other),
+                    (Vector3::y() * 3.0, Vector3::z() / 2.5, &atlas),
+                    (
+                        Vector3::x() * 3.0,
+                        Vector3::new(0.0, 0.5, 1.0 / 3.0),
+                        &atlas,
+                    ),
+                ] {
+                    let entity = Entity::new(cube.clone(), texture.clone());
+                    let node = SceneNode::with_entity(Matrix4::new_translation(&position), entity);
+                    self.demo_cubes
+                        .push((v.scene.add(node, Some(root)), position, spin));
+                }
+                self.textures.extend([other, atlas]);
+                self.demo_textures = None;
+            }
+        }
+        self.turned.set(self.turned.current() + 0.06 * delta_time);
+        if let Some(controller) = &mut self.controller {
+            controller.gamepad_input(context.input);
+            controller.mouse_motion(mouse_delta);
+            controller.update(&mut v.camera, delta_time);
+        }
+    }
+
+    fn render(&mut self, context: &mut AppContext, alpha: f32) {
+        let turned = self.turned.get(alpha);
+        for (id, position, spin) in &self.demo_cubes {
+            if let Some(node) = context.vulkan.scene.get_mut(id) {
+                let angles = spin * turned;
+                node.transform = Matrix4::new_translation(position)
+                    * Matrix4::from_euler_angles(angles.x, angles.y, angles.z);
+            }
+        }
+    }
+
+    fn frame_finished(&mut self, context: &mut AppContext, timing: &FrameTiming) {
+        // Twice a second is plenty for a counter.
+        if timing.frame % 30 == 0 {
+            context.window.set_title(&format!(
+                "Rara se window - {:.0} fps, {:.2} ms cpu, {} draws",
+                timing.fps,
+                timing.cpu_time.as_secs_f64() * 1000.0,
+                timing.draw_calls
+            ));
+        }
+    }
+}
+
+impl Demo {
+    // Runs whatever the bound keys asked for this frame.
+    fn actions(&mut self, context: &mut AppContext) {
+        let input = &*context.input;
+        let v = &mut *context.vulkan;
+        if input.just_pressed("mouse_look") {
+            self.mouse
+                .set_enabled(context.window, !self.mouse.is_enabled());
+        }
+        if input.just_pressed("test_pattern") {
+            v.show_test_pattern(match v.test_pattern() {
+                None => Some(TestPattern::GradientRamps),
+                Some(TestPattern::GradientRamps) => Some(TestPattern::Checkerboard),
+                Some(TestPattern::Checkerboard) => None,
             });
         }
-        Err(e) => {
-            error!("Failed to initialise window. {}", e.to_string());
+        if input.just_pressed("debug_view") {
+            let mut debug_view = v.debug_view().next();
+            while !v.supports_debug_view(debug_view) {
+                debug_view = debug_view.next();
+            }
+            if let Err(e) = v.set_debug_view(debug_view) {
+                error!("Could not switch debug view. {:?}", e);
+            }
+        }
+        if input.just_pressed("sky") {
+            v.sky = match v.sky {
+                Some(_) => None,
+                None => Some(Sky::default()),
+            };
+        }
+        if input.just_pressed("advance_sky") {
+            if let Some(sky) = &mut v.sky {
+                sky.advance(1.0);
+            }
+        }
+        if input.just_pressed("present_mode") {
+            let present_mode = if v.present_mode() == ash::vk::PresentModeKHR::FIFO {
+                ash::vk::PresentModeKHR::IMMEDIATE
+            } else {
+                ash::vk::PresentModeKHR::FIFO
+            };
+            if let Err(e) = v.set_present_mode(present_mode) {
+                error!("Could not switch present mode. {:?}", e);
+            }
+        }
+        if input.just_pressed("pick") {
+            if let Some((x, y)) = input.cursor_position() {
+                match v.pick(x as f32, y as f32) {
+                    Some(hit) => info!(
+                        "Picked {:?} at ({:.2}, {:.2}, {:.2}), {:.2} away",
+                        hit.picked, hit.position.x, hit.position.y, hit.position.z, hit.distance
+                    ),
+                    None => info!("Picked nothing"),
+                }
+            }
+        }
+        if input.just_pressed("quit") {
+            context.exit();
         }
     }
 }
@@ -0,0 +1,96 @@
+// Relative mouse mode for FPS style camera control.
+//
+// While active the cursor is hidden and grabbed, and raw mouse motion is accumulated until the
+// frame takes it. Platforms disagree on grabbing: macOS only supports locking the cursor in place
+// and Windows only supports confining it to the window, so we fall back from one to the other
+// and keep a confined cursor recentred ourselves. Losing focus releases the cursor, regaining it
+// grabs it again from the centre of the window.
+
+use log::warn;
+use winit::{
+    dpi::PhysicalPosition,
+    window::{CursorGrabMode, Window},
+};
+
+#[derive(Default)]
+pub struct RelativeMouse {
+    // Whether the user asked for relative mode, kept across focus changes.
+    enabled: bool,
+    // The grab currently in effect, None while released.
+    grab: Option<CursorGrabMode>,
+    delta: (f64, f64),
+}
+
+impl RelativeMouse {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, window: &Window, enabled: bool) {
+        self.enabled = enabled;
+        if enabled {
+            self.grab(window);
+        } else {
+            self.release(window);
+        }
+    }
+
+    pub fn focus_changed(&mut self, window: &Window, focused: bool) {
+        if !self.enabled {
+            return;
+        }
+        if focused {
+            self.grab(window);
+        } else {
+            self.release(window);
+        }
+    }
+
+    // Feed with the raw deltas from `DeviceEvent::MouseMotion`.
+    pub fn motion(&mut self, delta: (f64, f64)) {
+        if self.grab.is_some() {
+            self.delta.0 += delta.0;
+            self.delta.1 += delta.1;
+        }
+    }
+
+    // The motion accumulated since the last call, in pixels.
+    pub fn take_delta(&mut self, window: &Window) -> (f64, f64) {
+        if self.grab == Some(CursorGrabMode::Confined) {
+            Self::recenter(window);
+        }
+        std::mem::take(&mut self.delta)
+    }
+
+    fn grab(&mut self, window: &Window) {
+        let grab = [CursorGrabMode::Locked, CursorGrabMode::Confined]
+            .into_iter()
+            .find(|mode| window.set_cursor_grab(*mode).is_ok());
+        if grab.is_none() {
+            warn!("Could not grab the cursor, relative mouse motion may stop at the window edge.");
+        }
+        window.set_cursor_visible(false);
+        Self::recenter(window);
+        // Motion from before the grab would show up as a jump.
+        self.delta = (0.0, 0.0);
+        self.grab = grab.or(Some(CursorGrabMode::None));
+    }
+
+    fn release(&mut self, window: &Window) {
+        if let Err(e) = window.set_cursor_grab(CursorGrabMode::None) {
+            warn!("Could not release the cursor. {}", e);
+        }
+        window.set_cursor_visible(true);
+        self.delta = (0.0, 0.0);
+        self.grab = None;
+    }
+
+    fn recenter(window: &Window) {
+        let size = window.inner_size();
+        // Not every platform can move the cursor, a locked cursor doesn't need it anyway.
+        let _ = window.set_cursor_position(PhysicalPosition::new(
+            size.width as f64 / 2.0,
+            size.height as f64 / 2.0,
+        ));
+    }
+}
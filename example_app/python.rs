@@ -0,0 +1,346 @@
+// Embedded Python scripting for scene setup.
+//
+// Scripts import the `juryrig` module and describe the scene through it. Calls are recorded as
+// commands and applied to the renderer once the script finishes, so Python never holds on to
+// renderer state. Handles handed back to Python are plain integers indexing the textures, meshes,
+// materials and scene nodes created by the script, in creation order. Entities are added to a
+// node of `Vulkan::scene`, or drawn on their own when no node is given.
+
+use std::cell::RefCell;
+
+use log::{error, info};
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+use ::juryrig::alloc_telemetry::{self, Subsystem};
+use ::juryrig::jr_mesh;
+use ::juryrig::prelude::{
+    Entity, Material, MaterialParam, Matrix4, MeshHandle, RGBAImage, SamplerDesc, SceneNode,
+    TextureHandle, Vector3, Vulkan, WindSway,
+};
+
+enum SceneCommand {
+    LoadTexture(String),
+    LoadMesh(String),
+    CubeMesh,
+    RegisterMaterial(Material),
+    AddNode {
+        name: Option<String>,
+        transform: Matrix4<f32>,
+        parent: Option<usize>,
+    },
+    AddEntity {
+        mesh: usize,
+        texture: usize,
+        material: Option<usize>,
+        transform: Matrix4<f32>,
+        node: Option<usize>,
+    },
+    SetWind {
+        direction: (f32, f32),
+        strength: f32,
+    },
+    MoveCamera(f32),
+    TurnCamera {
+        right: f32,
+        up: f32,
+    },
+}
+
+#[derive(Default)]
+struct Recorder {
+    commands: Vec<SceneCommand>,
+    textures: usize,
+    meshes: usize,
+    materials: usize,
+    nodes: usize,
+}
+
+thread_local! {
+    static RECORDER: RefCell<Recorder> = RefCell::new(Recorder::default());
+}
+
+fn record(command: SceneCommand) {
+    RECORDER.with(|recorder| recorder.borrow_mut().commands.push(command));
+}
+
+#[pyfunction]
+fn load_texture(path: String) -> usize {
+    RECORDER.with(|recorder| {
+        let mut recorder = recorder.borrow_mut();
+        recorder.commands.push(SceneCommand::LoadTexture(path));
+        recorder.textures += 1;
+        recorder.textures - 1
+    })
+}
+
+// Reads an OBJ file through the mesh cache.
+#[pyfunction]
+fn load_mesh(path: String) -> usize {
+    RECORDER.with(|recorder| {
+        let mut recorder = recorder.borrow_mut();
+        recorder.commands.push(SceneCommand::LoadMesh(path));
+        recorder.meshes += 1;
+        recorder.meshes - 1
+    })
+}
+
+// The renderer's built-in unit cube.
+#[pyfunction]
+fn cube_mesh() -> usize {
+    RECORDER.with(|recorder| {
+        let mut recorder = recorder.borrow_mut();
+        recorder.commands.push(SceneCommand::CubeMesh);
+        recorder.meshes += 1;
+        recorder.meshes - 1
+    })
+}
+
+#[pyfunction]
+#[pyo3(signature = (wind_amplitude = 0.0, wind_frequency = 0.0))]
+fn register_material(wind_amplitude: f32, wind_frequency: f32) -> usize {
+    let wind = if wind_amplitude > 0.0 {
+        Some(WindSway {
+            amplitude: wind_amplitude,
+            frequency: wind_frequency,
+        })
+    } else {
+        None
+    };
+    let mut material = Material::default();
+    material.set_param(MaterialParam::Wind(wind));
+    RECORDER.with(|recorder| {
+        let mut recorder = recorder.borrow_mut();
+        recorder
+            .commands
+            .push(SceneCommand::RegisterMaterial(material));
+        recorder.materials += 1;
+        recorder.materials - 1
+    })
+}
+
+// Moved to `position`, turned `yaw` radians about +y and scaled evenly by `scale`.
+fn transform(position: (f32, f32, f32), yaw: f32, scale: f32) -> PyResult<Matrix4<f32>> {
+    let (x, y, z) = position;
+    if ![x, y, z, yaw, scale].iter().all(|value| value.is_finite()) {
+        return Err(PyValueError::new_err("transforms must be finite"));
+    }
+    Ok(Matrix4::new_translation(&Vector3::new(x, y, z))
+        * Matrix4::from_euler_angles(0.0, yaw, 0.0)
+        * Matrix4::new_scaling(scale))
+}
+
+// A scene node placed relative to `parent`, another node, or the world when None. Returns the
+// node for `add_node` and `add_entity`.
+#[pyfunction]
+#[pyo3(signature = (position = (0.0, 0.0, 0.0), yaw = 0.0, scale = 1.0, parent = None, name = None))]
+fn add_node(
+    position: (f32, f32, f32),
+    yaw: f32,
+    scale: f32,
+    parent: Option<usize>,
+    name: Option<String>,
+) -> PyResult<usize> {
+    let transform = transform(position, yaw, scale)?;
+    RECORDER.with(|recorder| {
+        let mut recorder = recorder.borrow_mut();
+        if parent.is_some_and(|parent| parent >= recorder.nodes) {
+            return Err(PyValueError::new_err("no such parent node"));
+        }
+        recorder.commands.push(SceneCommand::AddNode {
+            name,
+            transform,
+            parent,
+        });
+        recorder.nodes += 1;
+        Ok(recorder.nodes - 1)
+    })
+}
+
+// Draws `mesh` with `texture` every frame, placed relative to `node` when given, with
+// `material` or the default one.
+#[pyfunction]
+#[pyo3(signature = (mesh, texture, position = (0.0, 0.0, 0.0), yaw = 0.0, scale = 1.0, material = None, node = None))]
+fn add_entity(
+    mesh: usize,
+    texture: usize,
+    position: (f32, f32, f32),
+    yaw: f32,
+    scale: f32,
+    material: Option<usize>,
+    node: Option<usize>,
+) -> PyResult<()> {
+    let transform = transform(position, yaw, scale)?;
+    RECORDER.with(|recorder| {
+        let mut recorder = recorder.borrow_mut();
+        if mesh >= recorder.meshes || texture >= recorder.textures {
+            return Err(PyValueError::new_err("no such mesh or texture"));
+        }
+        if material.is_some_and(|material| material >= recorder.materials) {
+            return Err(PyValueError::new_err("no such material"));
+        }
+        if node.is_some_and(|node| node >= recorder.nodes) {
+            return Err(PyValueError::new_err("no such node"));
+        }
+        recorder.commands.push(SceneCommand::AddEntity {
+            mesh,
+            texture,
+            material,
+            transform,
+            node,
+        });
+        Ok(())
+    })
+}
+
+#[pyfunction]
+fn set_wind(direction: (f32, f32), strength: f32) {
+    record(SceneCommand::SetWind {
+        direction,
+        strength,
+    });
+}
+
+#[pyfunction]
+fn move_camera(distance: f32) {
+    record(SceneCommand::MoveCamera(distance));
+}
+
+// Turns the camera by the given angles in radians, positive values turn right and up.
+#[pyfunction]
+#[pyo3(signature = (right = 0.0, up = 0.0))]
+fn turn_camera(right: f32, up: f32) -> PyResult<()> {
+    if !right.is_finite() || !up.is_finite() {
+        return Err(PyValueError::new_err("camera angles must be finite"));
+    }
+    record(SceneCommand::TurnCamera { right, up });
+    Ok(())
+}
+
+#[pymodule]
+fn juryrig(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(load_texture, m)?)?;
+    m.add_function(wrap_pyfunction!(load_mesh, m)?)?;
+    m.add_function(wrap_pyfunction!(cube_mesh, m)?)?;
+    m.add_function(wrap_pyfunction!(register_material, m)?)?;
+    m.add_function(wrap_pyfunction!(add_node, m)?)?;
+    m.add_function(wrap_pyfunction!(add_entity, m)?)?;
+    m.add_function(wrap_pyfunction!(set_wind, m)?)?;
+    m.add_function(wrap_pyfunction!(move_camera, m)?)?;
+    m.add_function(wrap_pyfunction!(turn_camera, m)?)?;
+    Ok(())
+}
+
+// Makes the `juryrig` module importable, must be called before the interpreter starts.
+pub fn init() {
+    pyo3::append_to_inittab!(juryrig);
+}
+
+// Runs the script at `path` and applies the scene it describes to the renderer. The textures it
+// loaded are collected once the returned handles and the entities drawing them are dropped.
+pub fn run_scene_script(path: &str, vulkan: &mut Vulkan) -> Vec<TextureHandle> {
+    let _scope = alloc_telemetry::scope(Subsystem::Scripting);
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            error!("Could not read scene script {}. {}", path, e);
+            return vec![];
+        }
+    };
+    RECORDER.with(|recorder| *recorder.borrow_mut() = Recorder::default());
+    let result = Python::with_gil(|py| {
+        py.run(&source, None, None).map_err(|e| {
+            e.print(py);
+            e
+        })
+    });
+    if let Err(e) = result {
+        error!("Scene script {} failed. {}", path, e);
+        return vec![];
+    }
+
+    let commands = RECORDER.with(|recorder| std::mem::take(&mut recorder.borrow_mut().commands));
+    info!("Applying {} commands from {}.", commands.len(), path);
+    // Indexed by the handles the script was given, None for the ones that failed to load.
+    let mut textures: Vec<Option<TextureHandle>> = vec![];
+    let mut meshes: Vec<Option<MeshHandle>> = vec![];
+    let mut materials = vec![];
+    let mut nodes = vec![];
+    for command in commands {
+        match command {
+            SceneCommand::LoadTexture(path) => textures.push(match RGBAImage::from_file(&path) {
+                Ok(image) => match vulkan.register_texture(&image, SamplerDesc::default()) {
+                    Ok(handle) => Some(handle),
+                    Err(e) => {
+                        error!("Could not register texture {}. {:?}", path, e);
+                        None
+                    }
+                },
+                Err(e) => {
+                    error!("Could not load texture {}. {}", path, e);
+                    None
+                }
+            }),
+            SceneCommand::LoadMesh(path) => meshes.push(match jr_mesh::load(&path) {
+                Ok(mesh) => match vulkan.register_mesh(&mesh.vertices, &mesh.indices) {
+                    Ok(handle) => Some(handle),
+                    Err(e) => {
+                        error!("Could not register mesh {}. {:?}", path, e);
+                        None
+                    }
+                },
+                Err(e) => {
+                    error!("Could not load mesh {}. {:?}", path, e);
+                    None
+                }
+            }),
+            SceneCommand::CubeMesh => meshes.push(Some(vulkan.cube_mesh())),
+            SceneCommand::RegisterMaterial(material) => {
+                materials.push(vulkan.register_material(material));
+            }
+            SceneCommand::AddNode {
+                name,
+                transform,
+                parent,
+            } => {
+                let mut node = SceneNode::new(transform);
+                node.name = name;
+                let parent = parent.map(|parent: usize| nodes[parent]);
+                nodes.push(vulkan.scene.add(node, parent));
+            }
+            SceneCommand::AddEntity {
+                mesh,
+                texture,
+                material,
+                transform,
+                node,
+            } => {
+                let (Some(mesh), Some(texture)) = (&meshes[mesh], &textures[texture]) else {
+                    error!("Skipping an entity whose mesh or texture failed to load.");
+                    continue;
+                };
+                let mut entity = Entity::new(mesh.clone(), texture.clone());
+                entity.transform = transform;
+                entity.material = material.map(|material: usize| materials[material]);
+                match node.and_then(|node: usize| vulkan.scene.get_mut(&nodes[node])) {
+                    Some(node) => node.entities.push(entity),
+                    None => {
+                        vulkan.add_entity(entity);
+                    }
+                }
+            }
+            SceneCommand::SetWind {
+                direction,
+                strength,
+            } => {
+                vulkan.wind.direction = na::Vector2::new(direction.0, direction.1);
+                vulkan.wind.strength = strength;
+            }
+            SceneCommand::MoveCamera(distance) => vulkan.camera.move_forward(distance),
+            SceneCommand::TurnCamera { right, up } => {
+                vulkan.camera.turn_right(right);
+                vulkan.camera.turn_up(up);
+            }
+        }
+    }
+    textures.into_iter().flatten().collect()
+}
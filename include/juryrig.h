@@ -0,0 +1,52 @@
+/* The C ABI of juryrig, built with
+ *   cargo build --lib --release --features ffi
+ * See juryrig/ffi.rs for what each function does. Ids are nonzero, 0 means the call failed and
+ * the log says why. Every call has to come from the thread that created the context. */
+
+#ifndef JURYRIG_H
+#define JURYRIG_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct JrContext JrContext;
+
+/* A vertex of the built-in pipeline. */
+typedef struct JrVertex {
+    float position[3];
+    float uv[2];
+    float normal[3];
+} JrVertex;
+
+JrContext *jr_create(const char *title, uint32_t width, uint32_t height);
+void jr_destroy(JrContext *context);
+
+/* False once the window has been asked to close. */
+bool jr_poll_events(JrContext *context);
+bool jr_draw_frame(JrContext *context);
+void jr_set_camera(JrContext *context, const float position[3], float yaw, float pitch);
+
+uint64_t jr_register_mesh(JrContext *context, const JrVertex *vertices, size_t vertex_count,
+                          const uint32_t *indices, size_t index_count);
+/* 8 bit sRGB RGBA, row by row from the top. */
+uint64_t jr_register_texture(JrContext *context, const uint8_t *pixels, uint32_t width,
+                             uint32_t height);
+void jr_release_mesh(JrContext *context, uint64_t mesh);
+void jr_release_texture(JrContext *context, uint64_t texture);
+
+/* Transforms are column major 4x4 matrices. */
+uint64_t jr_add_entity(JrContext *context, uint64_t mesh, uint64_t texture,
+                       const float transform[16]);
+bool jr_set_entity_transform(JrContext *context, uint64_t entity, const float transform[16]);
+bool jr_remove_entity(JrContext *context, uint64_t entity);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
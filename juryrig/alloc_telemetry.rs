@@ -0,0 +1,182 @@
+//! Per-frame heap allocation counts, split by the subsystem that made them.
+//!
+//! Only counts with the `alloc-telemetry` feature, and only once the application installs
+//! `TrackingAllocator` as its global allocator, which tallies every allocation against the
+//! subsystem of the innermost `scope` on the current thread:
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static GLOBAL: alloc_telemetry::TrackingAllocator = alloc_telemetry::TrackingAllocator;
+//! ```
+//!
+//! The library never installs it, so it can't clash with an allocator the application picked.
+//! Without either `scope` does nothing and every frame reports zero.
+
+#[cfg(feature = "alloc-telemetry")]
+pub use tracking::TrackingAllocator;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Subsystem {
+    /// Anything outside a scope.
+    Other = 0,
+    Renderer,
+    Textures,
+    Meshes,
+    Materials,
+    Middleware,
+    Events,
+    Scripting,
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; SUBSYSTEM_COUNT] = [
+        Subsystem::Other,
+        Subsystem::Renderer,
+        Subsystem::Textures,
+        Subsystem::Meshes,
+        Subsystem::Materials,
+        Subsystem::Middleware,
+        Subsystem::Events,
+        Subsystem::Scripting,
+    ];
+}
+
+const SUBSYSTEM_COUNT: usize = 8;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AllocationCount {
+    pub allocations: u64,
+    pub bytes: u64,
+}
+
+/// What was allocated between two calls to `take_frame`.
+#[derive(Clone, Debug, Default)]
+pub struct AllocationStats {
+    counts: [AllocationCount; SUBSYSTEM_COUNT],
+}
+
+impl AllocationStats {
+    pub fn get(&self, subsystem: Subsystem) -> AllocationCount {
+        self.counts[subsystem as usize]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Subsystem, AllocationCount)> + '_ {
+        Subsystem::ALL
+            .iter()
+            .map(move |subsystem| (*subsystem, self.get(*subsystem)))
+    }
+
+    pub fn total(&self) -> AllocationCount {
+        self.counts
+            .iter()
+            .fold(AllocationCount::default(), |total, count| AllocationCount {
+                allocations: total.allocations + count.allocations,
+                bytes: total.bytes + count.bytes,
+            })
+    }
+}
+
+/// Allocations on this thread count against `subsystem` until the guard is dropped.
+pub fn scope(subsystem: Subsystem) -> Scope {
+    #[cfg(feature = "alloc-telemetry")]
+    return Scope {
+        previous: tracking::enter(subsystem),
+    };
+    #[cfg(not(feature = "alloc-telemetry"))]
+    {
+        let _ = subsystem;
+        Scope {}
+    }
+}
+
+pub struct Scope {
+    #[cfg(feature = "alloc-telemetry")]
+    previous: Subsystem,
+}
+
+// Always implemented, so dropping a guard early reads the same with or without the feature.
+impl Drop for Scope {
+    fn drop(&mut self) {
+        #[cfg(feature = "alloc-telemetry")]
+        tracking::enter(self.previous);
+    }
+}
+
+/// Returns the counts since the last call and starts counting from zero.
+pub fn take_frame() -> AllocationStats {
+    #[cfg(feature = "alloc-telemetry")]
+    return tracking::take();
+    #[cfg(not(feature = "alloc-telemetry"))]
+    AllocationStats::default()
+}
+
+#[cfg(feature = "alloc-telemetry")]
+mod tracking {
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        cell::Cell,
+        sync::atomic::{AtomicU64, Ordering},
+    };
+
+    use super::{AllocationCount, AllocationStats, Subsystem, SUBSYSTEM_COUNT};
+
+    thread_local! {
+        static CURRENT: Cell<Subsystem> = const { Cell::new(Subsystem::Other) };
+    }
+
+    static ALLOCATIONS: [AtomicU64; SUBSYSTEM_COUNT] = [ZERO; SUBSYSTEM_COUNT];
+    static BYTES: [AtomicU64; SUBSYSTEM_COUNT] = [ZERO; SUBSYSTEM_COUNT];
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicU64 = AtomicU64::new(0);
+
+    /// Forwards to the system allocator, counting as it goes.
+    pub struct TrackingAllocator;
+
+    unsafe impl GlobalAlloc for TrackingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            record(layout.size());
+            System.alloc(layout)
+        }
+
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            record(layout.size());
+            System.alloc_zeroed(layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            record(new_size);
+            System.realloc(ptr, layout, new_size)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    fn record(size: usize) {
+        // The thread local is gone while a thread is being torn down.
+        let subsystem = CURRENT
+            .try_with(|current| current.get())
+            .unwrap_or(Subsystem::Other) as usize;
+        ALLOCATIONS[subsystem].fetch_add(1, Ordering::Relaxed);
+        BYTES[subsystem].fetch_add(size as u64, Ordering::Relaxed);
+    }
+
+    // Returns the subsystem that was current before.
+    pub(super) fn enter(subsystem: Subsystem) -> Subsystem {
+        CURRENT
+            .try_with(|current| current.replace(subsystem))
+            .unwrap_or(Subsystem::Other)
+    }
+
+    pub(super) fn take() -> AllocationStats {
+        let mut stats = AllocationStats::default();
+        for (index, count) in stats.counts.iter_mut().enumerate() {
+            *count = AllocationCount {
+                allocations: ALLOCATIONS[index].swap(0, Ordering::Relaxed),
+                bytes: BYTES[index].swap(0, Ordering::Relaxed),
+            };
+        }
+        stats
+    }
+}
@@ -0,0 +1,261 @@
+//! Runs an application on a window and event loop the engine owns, so a game only writes its own
+//! callbacks. `run` opens the window, creates the renderer once the platform says it can draw,
+//! keeps the swapchain in step with the window and hands input, updates and frames to the
+//! `JuryrigApp`. Input is also gathered into `AppContext::input`, reset after every update.
+
+use std::time::{Duration, Instant};
+
+use log::{error, info};
+use winit::{
+    event::{DeviceEvent, Event, WindowEvent},
+    event_loop::EventLoop,
+    window::{Window, WindowBuilder},
+};
+
+use crate::{
+    input::Input,
+    timestep::FixedTimestep,
+    vulkan::{FrameTiming, RendererConfig, RuntimeError, Vulkan},
+};
+
+/// What every callback gets: the renderer, the window it draws to, the input so far this frame,
+/// and a way to stop.
+pub struct AppContext<'a> {
+    pub vulkan: &'a mut Vulkan,
+    pub window: &'a Window,
+    pub input: &'a mut Input,
+    exit: &'a mut bool,
+}
+
+impl AppContext<'_> {
+    /// Ends the event loop after the current event, `JuryrigApp::shutdown` still runs.
+    pub fn exit(&mut self) {
+        *self.exit = true;
+    }
+}
+
+/// The callbacks `run` drives. Only `update` is required, everything else has a default that does
+/// nothing, or the obvious thing for `window` and `config`.
+pub trait JuryrigApp: 'static {
+    /// The window to open, before the renderer exists.
+    fn window(&self) -> WindowBuilder {
+        WindowBuilder::new().with_title("juryrig")
+    }
+
+    fn config(&self) -> RendererConfig {
+        RendererConfig::default()
+    }
+
+    /// Once the renderer has been created, to register textures and meshes, add entities and bind
+    /// input actions.
+    fn start(&mut self, _context: &mut AppContext) {}
+
+    /// Every window event, after the runner has handled resizes, focus, occlusion and moves
+    /// between monitors. Closing the window exits unless this returns true.
+    fn window_event(&mut self, _context: &mut AppContext, _event: &WindowEvent) -> bool {
+        false
+    }
+
+    /// Raw device input, e.g. mouse motion for a captured cursor.
+    fn device_event(&mut self, _context: &mut AppContext, _event: &DeviceEvent) {}
+
+    /// Some to `update` in steps of this size instead of once a frame, see `timestep`. Asked once,
+    /// before `start`.
+    fn fixed_timestep(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Once per frame before it's drawn, `delta_time` is the seconds since the last update. With
+    /// a `fixed_timestep` it's called as many times as there are whole steps to catch up on,
+    /// maybe none, and `delta_time` is always the step. Input pressed since the last frame is
+    /// seen by the first of them.
+    fn update(&mut self, context: &mut AppContext, delta_time: f32);
+
+    /// Right before the frame is submitted, e.g. to `draw_ui` or move entities to where
+    /// `Interpolated` state puts them. `alpha` is how far the frame is from the last fixed step to
+    /// the next, always 1 without a `fixed_timestep`.
+    fn render(&mut self, _context: &mut AppContext, _alpha: f32) {}
+
+    /// After the frame was submitted, with what it cost.
+    fn frame_finished(&mut self, _context: &mut AppContext, _timing: &FrameTiming) {}
+
+    /// Before the renderer is dropped, the last chance to use it.
+    fn shutdown(&mut self, _context: &mut AppContext) {}
+}
+
+/// Opens `app`'s window and runs the event loop until the app exits or the window is closed. Only
+/// returns if the window can't be created, everything else is reported through the log.
+pub fn run(mut app: impl JuryrigApp) {
+    let event_loop = EventLoop::new();
+    let window = match app.window().build(&event_loop) {
+        Ok(window) => window,
+        Err(e) => {
+            error!("Failed to initialise window. {}", e);
+            return;
+        }
+    };
+    let mut vulkan: Option<Vulkan> = None;
+    let mut input = Input::default();
+    let mut timestep = app.fixed_timestep().map(FixedTimestep::new);
+    let mut alpha = 1.0;
+    let mut last_update = Instant::now();
+
+    event_loop.run(move |event, _, control_flow| {
+        let mut exit = false;
+        match event {
+            // Can come more than once on mobile, the renderer is kept from the first.
+            Event::Resumed if vulkan.is_none() => {
+                info!("Event-Startup");
+                match Vulkan::with_config(&window, app.config()) {
+                    Ok(mut v) => {
+                        app.start(&mut AppContext {
+                            vulkan: &mut v,
+                            window: &window,
+                            input: &mut input,
+                            exit: &mut exit,
+                        });
+                        vulkan = Some(v);
+                        last_update = Instant::now();
+                    }
+                    Err(e) => {
+                        error!("Could not init vulkan! {:?}", e);
+                        exit = true;
+                    }
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } if vulkan.is_none() => exit = true,
+            Event::WindowEvent { window_id, event } if window_id == window.id() => {
+                let Some(v) = &mut vulkan else { return };
+                input.window_event(&event);
+                match &event {
+                    WindowEvent::Resized(size) => {
+                        if let Err(e) = v.resize_surface(size.width, size.height) {
+                            error!("Could not recreate the swapchain! {:?}", e)
+                        }
+                    }
+                    WindowEvent::Focused(focused) => v.set_focused(*focused),
+                    WindowEvent::Occluded(occluded) => v.set_occluded(*occluded),
+                    WindowEvent::Moved(_) | WindowEvent::ScaleFactorChanged { .. } => {
+                        if let Err(e) = v.refresh_display(&window) {
+                            error!("Could not update the display settings. {:?}", e);
+                        }
+                    }
+                    _ => {}
+                }
+                let mut context = AppContext {
+                    vulkan: v,
+                    window: &window,
+                    input: &mut input,
+                    exit: &mut exit,
+                };
+                let handled = app.window_event(&mut context, &event);
+                if !handled && matches!(event, WindowEvent::CloseRequested) {
+                    context.exit();
+                }
+            }
+            Event::DeviceEvent { event, .. } => {
+                input.device_event(&event);
+                if let Some(v) = &mut vulkan {
+                    app.device_event(
+                        &mut AppContext {
+                            vulkan: v,
+                            window: &window,
+                            input: &mut input,
+                            exit: &mut exit,
+                        },
+                        &event,
+                    );
+                }
+            }
+            Event::MainEventsCleared => {
+                let Some(v) = &mut vulkan else { return };
+                #[cfg(feature = "gamepad")]
+                input.poll_gamepads();
+                let now = Instant::now();
+                let elapsed = now - last_update;
+                last_update = now;
+                let mut context = AppContext {
+                    vulkan: v,
+                    window: &window,
+                    input: &mut input,
+                    exit: &mut exit,
+                };
+                match &mut timestep {
+                    Some(timestep) => {
+                        let step = timestep.step().as_secs_f32();
+                        for index in 0..timestep.advance(elapsed) {
+                            app.update(&mut context, step);
+                            // A frame without steps keeps its input for the next one.
+                            if index == 0 {
+                                context.input.end_frame();
+                            }
+                        }
+                        alpha = timestep.alpha();
+                    }
+                    None => {
+                        app.update(&mut context, elapsed.as_secs_f32());
+                        context.input.end_frame();
+                    }
+                }
+                match context.vulkan.next_frame_at() {
+                    // Paused, focus and occlusion events will wake the loop back up.
+                    None => control_flow.set_wait(),
+                    Some(at) if at > Instant::now() => control_flow.set_wait_until(at),
+                    _ => {
+                        control_flow.set_poll();
+                        window.request_redraw();
+                    }
+                }
+            }
+            Event::RedrawRequested(window_id) if window_id == window.id() => {
+                let Some(v) = &mut vulkan else { return };
+                let mut context = AppContext {
+                    vulkan: v,
+                    window: &window,
+                    input: &mut input,
+                    exit: &mut exit,
+                };
+                app.render(&mut context, alpha);
+                match context.vulkan.swap_framebuffers() {
+                    Ok(timing) => app.frame_finished(&mut context, &timing),
+                    // The window changed size before winit told us.
+                    Err(RuntimeError::SwapchainOutOfDate) => {
+                        let size = window.inner_size();
+                        if let Err(e) = context.vulkan.resize_surface(size.width, size.height) {
+                            error!("Could not recreate the swapchain! {:?}", e)
+                        }
+                    }
+                    Err(RuntimeError::DeviceLost) => {
+                        vulkan = vulkan.take().and_then(|v| {
+                            v.recover(&window)
+                                .map_err(|e| error!("Could not recover the device! {:?}", e))
+                                .ok()
+                        });
+                        exit = vulkan.is_none();
+                    }
+                    Err(e) => error!("Could not render frame! {:?}", e),
+                }
+            }
+            // The app goes first so it can still use the renderer, which has to go before the
+            // window it draws to.
+            Event::LoopDestroyed => {
+                info!("Event-End");
+                if let Some(mut v) = vulkan.take() {
+                    app.shutdown(&mut AppContext {
+                        vulkan: &mut v,
+                        window: &window,
+                        input: &mut input,
+                        exit: &mut exit,
+                    });
+                }
+            }
+            _ => {}
+        }
+        if exit {
+            control_flow.set_exit();
+        }
+    });
+}
@@ -0,0 +1,424 @@
+//! A C ABI over the renderer, for engines and tools written in other languages. Built as a shared
+//! library with `cargo build --lib --release --features ffi`, declared for C in
+//! include/juryrig.h.
+//!
+//! A `JrContext` owns a window, its event loop and the renderer drawing to it. Meshes, textures
+//! and entities are handed out as nonzero integer ids, 0 meaning the call failed, which the log
+//! says more about. Every function takes the context first and must be called from the thread
+//! that created it, the main thread on most platforms. Pointers have to be null or valid for the
+//! lengths given, those rules are the same everywhere so aren't repeated on each function. A panic
+//! never unwinds into the caller: it's logged and the function returns as if it had failed.
+#![allow(clippy::missing_safety_doc)]
+
+use std::{
+    collections::HashMap,
+    ffi::{c_char, CStr},
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use log::error;
+use winit::{
+    dpi::PhysicalSize,
+    event::{Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    platform::run_return::EventLoopExtRunReturn,
+    window::{Window, WindowBuilder},
+};
+
+use crate::{
+    jr_image::{RGBAImage, RGBAPixel},
+    vulkan::{
+        Entity, EntityId, MeshHandle, RuntimeError, SamplerDesc, ShaderVertexData, TextureHandle,
+        Vulkan,
+    },
+};
+
+/// A vertex of the built-in pipeline, the same as `ShaderVertexData`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct JrVertex {
+    pub position: [f32; 3],
+    pub uv: [f32; 2],
+    pub normal: [f32; 3],
+}
+
+pub struct JrContext {
+    // Declared first so it's dropped before the window it draws to. None once the device was
+    // lost and couldn't be recovered.
+    vulkan: Option<Vulkan>,
+    window: Window,
+    event_loop: EventLoop<()>,
+    // Set by the events of the last `jr_poll_events`.
+    resized: Option<PhysicalSize<u32>>,
+    closed: bool,
+    meshes: HashMap<u64, MeshHandle>,
+    textures: HashMap<u64, TextureHandle>,
+    entities: HashMap<u64, EntityId>,
+    next_id: u64,
+}
+
+impl JrContext {
+    fn insert<T>(next_id: &mut u64, map: &mut HashMap<u64, T>, value: T) -> u64 {
+        *next_id += 1;
+        map.insert(*next_id, value);
+        *next_id
+    }
+}
+
+// Set while a context exists, winit only allows one event loop per process.
+static CONTEXT_EXISTS: AtomicBool = AtomicBool::new(false);
+
+// Runs the body of an exported function, returning `failed` if it panics. Unwinding across the C
+// ABI is undefined behaviour.
+fn guard<T>(failed: T, body: impl FnOnce() -> T) -> T {
+    catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|_| {
+        error!("A juryrig call panicked, see the message above.");
+        failed
+    })
+}
+
+// Reads a column major 4x4 matrix.
+unsafe fn matrix(transform: *const f32) -> Option<na::Matrix4<f32>> {
+    (!transform.is_null())
+        .then(|| na::Matrix4::from_column_slice(std::slice::from_raw_parts(transform, 16)))
+}
+
+/// Opens a window of `width` x `height` pixels titled `title` and creates a renderer for it. Null
+/// if either fails or another context still exists.
+#[no_mangle]
+pub unsafe extern "C" fn jr_create(
+    title: *const c_char,
+    width: u32,
+    height: u32,
+) -> *mut JrContext {
+    if CONTEXT_EXISTS.swap(true, Ordering::AcqRel) {
+        error!("Only one juryrig context can exist at a time.");
+        return std::ptr::null_mut();
+    }
+    let context = guard(std::ptr::null_mut(), || {
+        let title = match title.is_null() {
+            true => "juryrig".into(),
+            false => CStr::from_ptr(title).to_string_lossy(),
+        };
+        let event_loop = EventLoop::new();
+        let window = match WindowBuilder::new()
+            .with_title(title)
+            .with_inner_size(PhysicalSize::new(width, height))
+            .build(&event_loop)
+        {
+            Ok(window) => window,
+            Err(e) => {
+                error!("Failed to initialise window. {}", e);
+                return std::ptr::null_mut();
+            }
+        };
+        let vulkan = match Vulkan::new(&window) {
+            Ok(vulkan) => vulkan,
+            Err(e) => {
+                error!("Could not init vulkan! {:?}", e);
+                return std::ptr::null_mut();
+            }
+        };
+        Box::into_raw(Box::new(JrContext {
+            vulkan: Some(vulkan),
+            window,
+            event_loop,
+            resized: None,
+            closed: false,
+            meshes: HashMap::new(),
+            textures: HashMap::new(),
+            entities: HashMap::new(),
+            next_id: 0,
+        }))
+    });
+    if context.is_null() {
+        CONTEXT_EXISTS.store(false, Ordering::Release);
+    }
+    context
+}
+
+/// Destroys the renderer and closes the window. Every id from the context is invalid after.
+#[no_mangle]
+pub unsafe extern "C" fn jr_destroy(context: *mut JrContext) {
+    guard((), || {
+        if !context.is_null() {
+            drop(Box::from_raw(context));
+            CONTEXT_EXISTS.store(false, Ordering::Release);
+        }
+    })
+}
+
+/// Handles the window's pending events without waiting for more. False once the window has been
+/// asked to close, after which the caller should destroy the context.
+#[no_mangle]
+pub unsafe extern "C" fn jr_poll_events(context: *mut JrContext) -> bool {
+    guard(false, || {
+        let Some(context) = context.as_mut() else {
+            return false;
+        };
+        let JrContext {
+            event_loop,
+            window,
+            resized,
+            closed,
+            ..
+        } = context;
+        event_loop.run_return(|event, _, control_flow| match event {
+            Event::WindowEvent { window_id, event } if window_id == window.id() => match event {
+                WindowEvent::CloseRequested => *closed = true,
+                WindowEvent::Resized(size) => *resized = Some(size),
+                _ => {}
+            },
+            Event::MainEventsCleared => *control_flow = ControlFlow::Exit,
+            _ => {}
+        });
+        if let (Some(size), Some(vulkan)) = (context.resized.take(), &mut context.vulkan) {
+            if let Err(e) = vulkan.resize_surface(size.width, size.height) {
+                error!("Could not recreate the swapchain! {:?}", e);
+            }
+        }
+        !context.closed
+    })
+}
+
+/// Draws a frame of every entity. False if it couldn't be drawn, which the log explains; a
+/// swapchain out of date is recreated and a lost device recovered for the next frame.
+#[no_mangle]
+pub unsafe extern "C" fn jr_draw_frame(context: *mut JrContext) -> bool {
+    guard(false, || {
+        let Some(context) = context.as_mut() else {
+            return false;
+        };
+        let Some(vulkan) = &mut context.vulkan else {
+            return false;
+        };
+        match vulkan.swap_framebuffers() {
+            Ok(_) => true,
+            Err(RuntimeError::SwapchainOutOfDate) => {
+                let size = context.window.inner_size();
+                if let Err(e) = vulkan.resize_surface(size.width, size.height) {
+                    error!("Could not recreate the swapchain! {:?}", e)
+                }
+                false
+            }
+            Err(RuntimeError::DeviceLost) => {
+                context.vulkan = context.vulkan.take().and_then(|vulkan| {
+                    vulkan
+                        .recover(&context.window)
+                        .map_err(|e| error!("Could not recover the device! {:?}", e))
+                        .ok()
+                });
+                false
+            }
+            Err(e) => {
+                error!("Could not render frame! {:?}", e);
+                false
+            }
+        }
+    })
+}
+
+/// Places the camera at `position`, a pointer to 3 floats, looking `yaw` radians to the right of
+/// +z and `pitch` radians up, see `Camera::set_orientation`.
+#[no_mangle]
+pub unsafe extern "C" fn jr_set_camera(
+    context: *mut JrContext,
+    position: *const f32,
+    yaw: f32,
+    pitch: f32,
+) {
+    guard((), || {
+        let Some(vulkan) = context.as_mut().and_then(|context| context.vulkan.as_mut()) else {
+            return;
+        };
+        if !position.is_null() {
+            let position = std::slice::from_raw_parts(position, 3);
+            vulkan
+                .camera
+                .set_position(na::Vector3::from_column_slice(position));
+        }
+        vulkan.camera.set_orientation(yaw, pitch);
+    })
+}
+
+/// Uploads a mesh for the built-in pipeline, triangles indexing into `vertices`.
+#[no_mangle]
+pub unsafe extern "C" fn jr_register_mesh(
+    context: *mut JrContext,
+    vertices: *const JrVertex,
+    vertex_count: usize,
+    indices: *const u32,
+    index_count: usize,
+) -> u64 {
+    guard(0, || {
+        let Some(context) = context.as_mut() else {
+            return 0;
+        };
+        let Some(vulkan) = &mut context.vulkan else {
+            return 0;
+        };
+        if vertices.is_null() || indices.is_null() {
+            return 0;
+        }
+        let vertices: Vec<ShaderVertexData> = std::slice::from_raw_parts(vertices, vertex_count)
+            .iter()
+            .map(|vertex| ShaderVertexData {
+                position: vertex.position.into(),
+                uv: vertex.uv.into(),
+                normal: vertex.normal.into(),
+            })
+            .collect();
+        let indices = std::slice::from_raw_parts(indices, index_count);
+        match vulkan.register_mesh(&vertices, indices) {
+            Ok(mesh) => JrContext::insert(&mut context.next_id, &mut context.meshes, mesh),
+            Err(e) => {
+                error!("Could not register mesh. {:?}", e);
+                0
+            }
+        }
+    })
+}
+
+/// Uploads `width` x `height` 8 bit sRGB RGBA pixels, row by row from the top.
+#[no_mangle]
+pub unsafe extern "C" fn jr_register_texture(
+    context: *mut JrContext,
+    pixels: *const u8,
+    width: u32,
+    height: u32,
+) -> u64 {
+    guard(0, || {
+        let Some(context) = context.as_mut() else {
+            return 0;
+        };
+        let Some(vulkan) = &mut context.vulkan else {
+            return 0;
+        };
+        if pixels.is_null() {
+            return 0;
+        }
+        let Some(length) = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|texels| texels.checked_mul(4))
+        else {
+            error!(
+                "Could not register texture. {} x {} is too large.",
+                width, height
+            );
+            return 0;
+        };
+        let bytes = std::slice::from_raw_parts(pixels, length);
+        let image = RGBAImage {
+            width,
+            height,
+            data: bytes
+                .chunks_exact(4)
+                .map(|pixel| RGBAPixel {
+                    r: pixel[0],
+                    g: pixel[1],
+                    b: pixel[2],
+                    a: pixel[3],
+                })
+                .collect(),
+        };
+        match vulkan.register_texture(&image, SamplerDesc::default()) {
+            Ok(texture) => JrContext::insert(&mut context.next_id, &mut context.textures, texture),
+            Err(e) => {
+                error!("Could not register texture. {:?}", e);
+                0
+            }
+        }
+    })
+}
+
+/// The mesh is freed once no entity draws it any more.
+#[no_mangle]
+pub unsafe extern "C" fn jr_release_mesh(context: *mut JrContext, mesh: u64) {
+    guard((), || {
+        if let Some(context) = context.as_mut() {
+            context.meshes.remove(&mesh);
+        }
+    })
+}
+
+/// The texture is freed once no entity draws with it any more.
+#[no_mangle]
+pub unsafe extern "C" fn jr_release_texture(context: *mut JrContext, texture: u64) {
+    guard((), || {
+        if let Some(context) = context.as_mut() {
+            context.textures.remove(&texture);
+        }
+    })
+}
+
+/// Draws `mesh` with `texture` every frame, placed by `transform`, a column major 4x4 matrix, or
+/// at the origin when null.
+#[no_mangle]
+pub unsafe extern "C" fn jr_add_entity(
+    context: *mut JrContext,
+    mesh: u64,
+    texture: u64,
+    transform: *const f32,
+) -> u64 {
+    guard(0, || {
+        let Some(context) = context.as_mut() else {
+            return 0;
+        };
+        let (Some(vulkan), Some(mesh), Some(texture)) = (
+            &mut context.vulkan,
+            context.meshes.get(&mesh),
+            context.textures.get(&texture),
+        ) else {
+            return 0;
+        };
+        let mut entity = Entity::new(mesh.clone(), texture.clone());
+        if let Some(transform) = matrix(transform) {
+            entity.transform = transform;
+        }
+        let id = vulkan.add_entity(entity);
+        JrContext::insert(&mut context.next_id, &mut context.entities, id)
+    })
+}
+
+/// False if there's no such entity or `transform` is null.
+#[no_mangle]
+pub unsafe extern "C" fn jr_set_entity_transform(
+    context: *mut JrContext,
+    entity: u64,
+    transform: *const f32,
+) -> bool {
+    guard(false, || {
+        let Some(context) = context.as_mut() else {
+            return false;
+        };
+        let (Some(vulkan), Some(id), Some(transform)) = (
+            &mut context.vulkan,
+            context.entities.get(&entity),
+            matrix(transform),
+        ) else {
+            return false;
+        };
+        match vulkan.entity_mut(id) {
+            Some(entity) => {
+                entity.transform = transform;
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+/// Stops drawing the entity. False if there's no such entity.
+#[no_mangle]
+pub unsafe extern "C" fn jr_remove_entity(context: *mut JrContext, entity: u64) -> bool {
+    guard(false, || {
+        let Some(context) = context.as_mut() else {
+            return false;
+        };
+        match (&mut context.vulkan, context.entities.remove(&entity)) {
+            (Some(vulkan), Some(id)) => vulkan.remove_entity(&id).is_some(),
+            _ => false,
+        }
+    })
+}
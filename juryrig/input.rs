@@ -0,0 +1,518 @@
+//! Keyboard, mouse and gamepad state built from winit's events and gilrs, queried by key or by
+//! named action instead of matching on events as they arrive. `app::run` keeps one up to date and
+//! hands it to every callback in `AppContext::input`, an application running its own event loop
+//! feeds `window_event` and `device_event`, calls `poll_gamepads` before and `end_frame` after
+//! each update.
+
+use std::collections::{HashMap, HashSet};
+
+#[cfg(feature = "gamepad")]
+use log::warn;
+use winit::event::{
+    DeviceEvent, ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode,
+    WindowEvent,
+};
+
+// Trackpads report scrolling in pixels, counted as a wheel notch per this many.
+const PIXELS_PER_LINE: f64 = 40.0;
+
+/// A key or button that can be held, what actions and axes are bound to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+    /// Held while it's held on any connected gamepad.
+    Gamepad(GamepadButton),
+}
+
+/// Buttons by where they sit on a standard controller, whatever they're labelled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GamepadButton {
+    /// A on an Xbox controller, cross on a PlayStation one.
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    /// Triggers count as pressed past a point the driver picks, `GamepadAxis` has how far.
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    /// The logo button in the middle.
+    Mode,
+    /// Clicking the sticks in.
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+/// Sticks go from -1 to 1 with up and right positive, triggers from 0 to 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+/// Which gamepad, stable for as long as it stays connected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct GamepadId(pub usize);
+
+/// One source for `Input::axis`, between -1 and 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AxisBinding {
+    /// -1 while `negative` is held, 1 while `positive` is, 0 for both or neither.
+    Buttons {
+        negative: Binding,
+        positive: Binding,
+    },
+    /// As `Input::gamepad_axis` reads it.
+    Gamepad(GamepadAxis),
+}
+
+struct Gamepad {
+    name: String,
+    buttons: HashSet<GamepadButton>,
+    axes: HashMap<GamepadAxis, f32>,
+}
+
+pub struct Input {
+    /// Stick and trigger values closer to rest than this read as 0, the rest of the range is
+    /// stretched to still reach 1. Worn sticks rarely settle exactly at the centre.
+    pub deadzone: f32,
+    down: HashSet<Binding>,
+    // Since the last `end_frame`.
+    pressed: HashSet<Binding>,
+    released: HashSet<Binding>,
+    mouse_delta: (f64, f64),
+    scroll: f32,
+    // In physical pixels from the window's top left, None while the cursor is outside it.
+    cursor: Option<(f64, f64)>,
+    actions: HashMap<String, Vec<Binding>>,
+    axes: HashMap<String, Vec<AxisBinding>>,
+    gamepads: HashMap<GamepadId, Gamepad>,
+    // Started by the first `poll_gamepads`, None after it failed.
+    #[cfg(feature = "gamepad")]
+    gilrs: Option<Option<gilrs::Gilrs>>,
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Input {
+            deadzone: 0.15,
+            down: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            mouse_delta: (0.0, 0.0),
+            scroll: 0.0,
+            cursor: None,
+            actions: HashMap::new(),
+            axes: HashMap::new(),
+            gamepads: HashMap::new(),
+            #[cfg(feature = "gamepad")]
+            gilrs: None,
+        }
+    }
+}
+
+impl Input {
+    pub fn window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => self.button(Binding::Key(*key), *state),
+            WindowEvent::MouseInput { state, button, .. } => {
+                self.button(Binding::Mouse(*button), *state)
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll += match delta {
+                    MouseScrollDelta::LineDelta(_, lines) => *lines,
+                    MouseScrollDelta::PixelDelta(pixels) => (pixels.y / PIXELS_PER_LINE) as f32,
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = Some((position.x, position.y))
+            }
+            WindowEvent::CursorLeft { .. } => self.cursor = None,
+            // The releases would go to whichever window has focus now.
+            WindowEvent::Focused(false) => self.release_all(),
+            _ => {}
+        }
+    }
+
+    pub fn device_event(&mut self, event: &DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta } = event {
+            self.mouse_delta.0 += delta.0;
+            self.mouse_delta.1 += delta.1;
+        }
+    }
+
+    /// Starts the next frame: clears what was pressed and released, the mouse motion and the
+    /// scrolling. Held keys stay held.
+    pub fn end_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+        self.mouse_delta = (0.0, 0.0);
+        self.scroll = 0.0;
+    }
+
+    /// Lets go of everything held, as if each had been released.
+    pub fn release_all(&mut self) {
+        self.released.extend(self.down.drain());
+    }
+
+    /// Records a gamepad plugged in, `poll_gamepads` calls it for every gamepad gilrs finds. For
+    /// applications reading gamepads some other way, as do the other `gamepad_` calls.
+    pub fn gamepad_connected(&mut self, id: GamepadId, name: &str) {
+        self.gamepads.insert(
+            id,
+            Gamepad {
+                name: name.to_owned(),
+                buttons: HashSet::new(),
+                axes: HashMap::new(),
+            },
+        );
+    }
+
+    /// Releases whatever the gamepad was holding.
+    pub fn gamepad_disconnected(&mut self, id: GamepadId) {
+        let Some(gamepad) = self.gamepads.remove(&id) else {
+            return;
+        };
+        for button in gamepad.buttons {
+            self.gamepad_button_changed(button);
+        }
+    }
+
+    pub fn gamepad_button(&mut self, id: GamepadId, button: GamepadButton, state: ElementState) {
+        let Some(gamepad) = self.gamepads.get_mut(&id) else {
+            return;
+        };
+        match state {
+            ElementState::Pressed => gamepad.buttons.insert(button),
+            ElementState::Released => gamepad.buttons.remove(&button),
+        };
+        self.gamepad_button_changed(button);
+    }
+
+    pub fn gamepad_axis_changed(&mut self, id: GamepadId, axis: GamepadAxis, value: f32) {
+        if let Some(gamepad) = self.gamepads.get_mut(&id) {
+            gamepad.axes.insert(axis, value);
+        }
+    }
+
+    // The button's binding is held while any gamepad holds it.
+    fn gamepad_button_changed(&mut self, button: GamepadButton) {
+        let held = self
+            .gamepads
+            .values()
+            .any(|gamepad| gamepad.buttons.contains(&button));
+        let state = match held {
+            true => ElementState::Pressed,
+            false => ElementState::Released,
+        };
+        self.button(Binding::Gamepad(button), state);
+    }
+
+    fn button(&mut self, binding: Binding, state: ElementState) {
+        match state {
+            // Key repeat sends more presses without releases in between.
+            ElementState::Pressed => {
+                if self.down.insert(binding) {
+                    self.pressed.insert(binding);
+                }
+            }
+            ElementState::Released => {
+                if self.down.remove(&binding) {
+                    self.released.insert(binding);
+                }
+            }
+        }
+    }
+
+    pub fn is_down(&self, binding: Binding) -> bool {
+        self.down.contains(&binding)
+    }
+
+    /// Went down this frame.
+    pub fn was_pressed(&self, binding: Binding) -> bool {
+        self.pressed.contains(&binding)
+    }
+
+    /// Came up this frame.
+    pub fn was_released(&self, binding: Binding) -> bool {
+        self.released.contains(&binding)
+    }
+
+    /// Raw motion since the last frame, in pixels, whether or not the cursor moved.
+    pub fn mouse_delta(&self) -> (f64, f64) {
+        self.mouse_delta
+    }
+
+    /// Wheel notches since the last frame, positive away from the user.
+    pub fn scroll_delta(&self) -> f32 {
+        self.scroll
+    }
+
+    pub fn cursor_position(&self) -> Option<(f64, f64)> {
+        self.cursor
+    }
+
+    /// The connected gamepads and their names.
+    pub fn gamepads(&self) -> impl Iterator<Item = (GamepadId, &str)> {
+        self.gamepads
+            .iter()
+            .map(|(id, gamepad)| (*id, gamepad.name.as_str()))
+    }
+
+    /// Whichever connected gamepad pushes the axis furthest, past the deadzone. 0 without any.
+    pub fn gamepad_axis(&self, axis: GamepadAxis) -> f32 {
+        self.gamepads
+            .values()
+            .filter_map(|gamepad| gamepad.axes.get(&axis))
+            .map(|value| {
+                let magnitude = (value.abs() - self.deadzone).max(0.0) / (1.0 - self.deadzone);
+                magnitude.min(1.0).copysign(*value)
+            })
+            .fold(0.0, |furthest: f32, value| {
+                match value.abs() > furthest.abs() {
+                    true => value,
+                    false => furthest,
+                }
+            })
+    }
+
+    /// Adds `binding` to the action, which is held while any of its bindings are.
+    pub fn bind(&mut self, action: &str, binding: Binding) {
+        let bindings = self.actions.entry(action.to_owned()).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    /// Takes every binding off the action, e.g. before rebinding it from a settings menu.
+    pub fn unbind(&mut self, action: &str) {
+        self.actions.remove(action);
+    }
+
+    pub fn bindings(&self, action: &str) -> &[Binding] {
+        self.actions.get(action).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn is_pressed(&self, action: &str) -> bool {
+        self.bindings(action)
+            .iter()
+            .any(|binding| self.is_down(*binding))
+    }
+
+    /// One of the action's bindings went down this frame. Unbound actions are never pressed.
+    pub fn just_pressed(&self, action: &str) -> bool {
+        self.bindings(action)
+            .iter()
+            .any(|binding| self.was_pressed(*binding))
+    }
+
+    /// One of the action's bindings came up this frame and none are still held.
+    pub fn just_released(&self, action: &str) -> bool {
+        let bindings = self.bindings(action);
+        bindings.iter().any(|binding| self.was_released(*binding))
+            && !bindings.iter().any(|binding| self.is_down(*binding))
+    }
+
+    pub fn bind_axis(&mut self, axis: &str, binding: AxisBinding) {
+        self.axes.entry(axis.to_owned()).or_default().push(binding);
+    }
+
+    pub fn unbind_axis(&mut self, axis: &str) {
+        self.axes.remove(axis);
+    }
+
+    /// The sum of the axis' bindings, kept between -1 and 1. 0 for an unbound axis.
+    pub fn axis(&self, axis: &str) -> f32 {
+        let Some(bindings) = self.axes.get(axis) else {
+            return 0.0;
+        };
+        bindings
+            .iter()
+            .map(|binding| match binding {
+                AxisBinding::Buttons { negative, positive } => {
+                    self.is_down(*positive) as i32 as f32 - self.is_down(*negative) as i32 as f32
+                }
+                AxisBinding::Gamepad(axis) => self.gamepad_axis(*axis),
+            })
+            .sum::<f32>()
+            .clamp(-1.0, 1.0)
+    }
+}
+
+#[cfg(feature = "gamepad")]
+impl Input {
+    /// Reads what gilrs saw since the last call, before the frame's update. The first call opens
+    /// gilrs and picks up the gamepads already connected.
+    pub fn poll_gamepads(&mut self) {
+        let opening = self.gilrs.is_none();
+        let gilrs = self.gilrs.get_or_insert_with(|| match gilrs::Gilrs::new() {
+            Ok(gilrs) => Some(gilrs),
+            // No backend for this platform, gilrs stands in one that never sees a gamepad.
+            Err(gilrs::Error::NotImplemented(gilrs)) => {
+                warn!("Gamepads aren't supported on this platform.");
+                Some(gilrs)
+            }
+            Err(e) => {
+                warn!("Could not open gamepads. {}", e);
+                None
+            }
+        });
+        // Taken out while its events are applied, they need the rest of `self`.
+        let Some(mut gilrs) = gilrs.take() else {
+            return;
+        };
+        if opening {
+            for (id, gamepad) in gilrs.gamepads() {
+                self.gamepad_connected(GamepadId(id.into()), gamepad.name());
+            }
+        }
+        while let Some(gilrs::Event { id, event, .. }) = gilrs.next_event() {
+            let gamepad = GamepadId(id.into());
+            match event {
+                gilrs::EventType::Connected => {
+                    self.gamepad_connected(gamepad, gilrs.gamepad(id).name())
+                }
+                gilrs::EventType::Disconnected => self.gamepad_disconnected(gamepad),
+                gilrs::EventType::ButtonPressed(button, _) => {
+                    if let Some(button) = gilrs_button(button) {
+                        self.gamepad_button(gamepad, button, ElementState::Pressed);
+                    }
+                }
+                gilrs::EventType::ButtonReleased(button, _) => {
+                    if let Some(button) = gilrs_button(button) {
+                        self.gamepad_button(gamepad, button, ElementState::Released);
+                    }
+                }
+                // Analog triggers come as buttons with a value.
+                gilrs::EventType::ButtonChanged(gilrs::Button::LeftTrigger2, value, _) => {
+                    self.gamepad_axis_changed(gamepad, GamepadAxis::LeftTrigger, value)
+                }
+                gilrs::EventType::ButtonChanged(gilrs::Button::RightTrigger2, value, _) => {
+                    self.gamepad_axis_changed(gamepad, GamepadAxis::RightTrigger, value)
+                }
+                gilrs::EventType::AxisChanged(axis, value, _) => {
+                    if let Some(axis) = gilrs_axis(axis) {
+                        self.gamepad_axis_changed(gamepad, axis, value);
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.gilrs = Some(Some(gilrs));
+    }
+}
+
+#[cfg(feature = "gamepad")]
+fn gilrs_button(button: gilrs::Button) -> Option<GamepadButton> {
+    use gilrs::Button;
+    Some(match button {
+        Button::South => GamepadButton::South,
+        Button::East => GamepadButton::East,
+        Button::North => GamepadButton::North,
+        Button::West => GamepadButton::West,
+        Button::LeftTrigger => GamepadButton::LeftBumper,
+        Button::RightTrigger => GamepadButton::RightBumper,
+        Button::LeftTrigger2 => GamepadButton::LeftTrigger,
+        Button::RightTrigger2 => GamepadButton::RightTrigger,
+        Button::Select => GamepadButton::Select,
+        Button::Start => GamepadButton::Start,
+        Button::Mode => GamepadButton::Mode,
+        Button::LeftThumb => GamepadButton::LeftStick,
+        Button::RightThumb => GamepadButton::RightStick,
+        Button::DPadUp => GamepadButton::DPadUp,
+        Button::DPadDown => GamepadButton::DPadDown,
+        Button::DPadLeft => GamepadButton::DPadLeft,
+        Button::DPadRight => GamepadButton::DPadRight,
+        _ => return None,
+    })
+}
+
+#[cfg(feature = "gamepad")]
+fn gilrs_axis(axis: gilrs::Axis) -> Option<GamepadAxis> {
+    use gilrs::Axis;
+    Some(match axis {
+        Axis::LeftStickX => GamepadAxis::LeftStickX,
+        Axis::LeftStickY => GamepadAxis::LeftStickY,
+        Axis::RightStickX => GamepadAxis::RightStickX,
+        Axis::RightStickY => GamepadAxis::RightStickY,
+        Axis::LeftZ => GamepadAxis::LeftTrigger,
+        Axis::RightZ => GamepadAxis::RightTrigger,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JUMP: Binding = Binding::Key(VirtualKeyCode::Space);
+
+    #[test]
+    fn presses_and_releases_last_one_frame() {
+        let mut input = Input::default();
+        input.button(JUMP, ElementState::Pressed);
+        assert!(input.is_down(JUMP) && input.was_pressed(JUMP));
+        input.end_frame();
+        assert!(input.is_down(JUMP) && !input.was_pressed(JUMP));
+        input.button(JUMP, ElementState::Released);
+        assert!(!input.is_down(JUMP) && input.was_released(JUMP));
+        input.end_frame();
+        assert!(!input.was_released(JUMP));
+    }
+
+    #[test]
+    fn key_repeat_is_not_another_press() {
+        let mut input = Input::default();
+        input.button(JUMP, ElementState::Pressed);
+        input.end_frame();
+        input.button(JUMP, ElementState::Pressed);
+        assert!(!input.was_pressed(JUMP));
+    }
+
+    #[test]
+    fn action_is_released_once_no_binding_is_held() {
+        let mut input = Input::default();
+        let south = Binding::Gamepad(GamepadButton::South);
+        input.bind("jump", JUMP);
+        input.bind("jump", south);
+        input.gamepad_connected(GamepadId(0), "pad");
+        input.button(JUMP, ElementState::Pressed);
+        input.gamepad_button(GamepadId(0), GamepadButton::South, ElementState::Pressed);
+        assert!(input.just_pressed("jump"));
+        input.end_frame();
+        input.button(JUMP, ElementState::Released);
+        assert!(input.is_pressed("jump") && !input.just_released("jump"));
+        input.end_frame();
+        // Unplugging the gamepad lets go of what it held.
+        input.gamepad_disconnected(GamepadId(0));
+        assert!(input.just_released("jump") && !input.is_pressed("jump"));
+    }
+
+    #[test]
+    fn release_all_releases_everything_held() {
+        let mut input = Input::default();
+        input.button(JUMP, ElementState::Pressed);
+        input.release_all();
+        assert!(!input.is_down(JUMP) && input.was_released(JUMP));
+    }
+}
@@ -0,0 +1,281 @@
+//! CPU side glTF 2.0 loading, for both .gltf (with its buffers and images next to it) and .glb.
+//!
+//! A `GltfScene` keeps glTF's indices between meshes, materials, images and nodes, so it can be
+//! inspected or edited before `Vulkan::register_gltf` uploads it and turns the indices into
+//! handles. Only what the built-in pipeline can draw is read: triangle lists, the first uv set,
+//! and the base colour factor and texture of each material. The triangle lists go through the mesh
+//! cache like `jr_mesh::load`, so later loads only read the document and its images.
+
+use std::path::Path;
+
+use log::warn;
+use na::Matrix4;
+
+use crate::jr_image::{RGBAImage, RGBAPixel};
+use crate::jr_mesh::{self, MeshData, MeshVertex};
+
+#[derive(Clone, Debug)]
+pub struct GltfPrimitive {
+    pub mesh: MeshData,
+    /// Index into `GltfScene::materials`, glTF's default material when None.
+    pub material: Option<usize>,
+}
+
+/// One glTF mesh, each of its primitives becomes a separate renderer mesh since they can have
+/// different materials.
+#[derive(Clone, Debug)]
+pub struct GltfMesh {
+    pub name: Option<String>,
+    pub primitives: Vec<GltfPrimitive>,
+}
+
+#[derive(Clone, Debug)]
+pub struct GltfMaterial {
+    pub name: Option<String>,
+    pub base_colour: [f32; 4],
+    /// Index into `GltfScene::images`.
+    pub base_colour_image: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct GltfNode {
+    pub name: Option<String>,
+    /// Relative to the parent node.
+    pub transform: Matrix4<f32>,
+    /// Index into `GltfScene::meshes`.
+    pub mesh: Option<usize>,
+    /// Indices into `GltfScene::nodes`.
+    pub children: Vec<usize>,
+}
+
+pub struct GltfScene {
+    pub meshes: Vec<GltfMesh>,
+    pub materials: Vec<GltfMaterial>,
+    pub images: Vec<RGBAImage>,
+    pub nodes: Vec<GltfNode>,
+    /// The nodes without a parent in the file's default scene.
+    pub roots: Vec<usize>,
+}
+
+#[derive(Debug)]
+pub enum GltfError {
+    /// Reading or parsing the file, or one of the buffers or images it refers to.
+    Import(gltf::Error),
+    /// A triangle primitive without vertex positions.
+    MissingPositions { mesh: usize },
+}
+
+impl From<gltf::Error> for GltfError {
+    fn from(value: gltf::Error) -> Self {
+        GltfError::Import(value)
+    }
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<GltfScene, GltfError> {
+    let path = path.as_ref();
+    let gltf::Gltf { document, blob } = gltf::Gltf::open(path)?;
+    let base = Some(path.parent().unwrap_or_else(|| Path::new("./")));
+
+    // Every triangle list, with the index of the mesh it's in.
+    let triangles = || {
+        document.meshes().flat_map(|mesh| {
+            let index = mesh.index();
+            mesh.primitives()
+                .filter(|primitive| primitive.mode() == gltf::mesh::Mode::Triangles)
+                .map(move |primitive| (index, primitive))
+        })
+    };
+    let cached = jr_mesh::read_cache(path).filter(|meshes| meshes.len() == triangles().count());
+    // Images can be stored in the buffers too, otherwise a fresh cache means they aren't needed.
+    let images_in_buffers = document
+        .images()
+        .any(|image| matches!(image.source(), gltf::image::Source::View { .. }));
+    let buffers = match cached.is_none() || images_in_buffers {
+        true => gltf::import_buffers(&document, base, blob)?,
+        false => vec![],
+    };
+    let images = gltf::import_images(&document, base, &buffers)?;
+    let mut triangle_meshes = match cached {
+        Some(meshes) => meshes,
+        None => {
+            let meshes = triangles()
+                .map(|(mesh, primitive)| read_triangles(mesh, &primitive, &buffers))
+                .collect::<Result<Vec<_>, _>>()?;
+            jr_mesh::write_cache(path, &meshes);
+            meshes
+        }
+    }
+    .into_iter();
+
+    let mut meshes = Vec::with_capacity(document.meshes().len());
+    for mesh in document.meshes() {
+        let mut primitives = vec![];
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                warn!(
+                    "Skipping {:?} primitive in glTF mesh {}, only triangle lists are supported",
+                    primitive.mode(),
+                    mesh.index()
+                );
+                continue;
+            }
+            primitives.push(GltfPrimitive {
+                // There's one for every triangle list, in the same order.
+                mesh: triangle_meshes.next().unwrap(),
+                material: primitive.material().index(),
+            });
+        }
+        meshes.push(GltfMesh {
+            name: mesh.name().map(String::from),
+            primitives,
+        });
+    }
+
+    let materials = document
+        .materials()
+        .map(|material| {
+            let pbr = material.pbr_metallic_roughness();
+            let base_colour_image = pbr.base_color_texture().map(|info| {
+                if info.tex_coord() != 0 {
+                    warn!(
+                        "glTF material {:?} samples uv set {}, only the first is loaded",
+                        material.index(),
+                        info.tex_coord()
+                    );
+                }
+                info.texture().source().index()
+            });
+            GltfMaterial {
+                name: material.name().map(String::from),
+                base_colour: pbr.base_color_factor(),
+                base_colour_image,
+            }
+        })
+        .collect();
+
+    let nodes = document
+        .nodes()
+        .map(|node| GltfNode {
+            name: node.name().map(String::from),
+            transform: Matrix4::from(node.transform().matrix()),
+            mesh: node.mesh().map(|mesh| mesh.index()),
+            children: node.children().map(|child| child.index()).collect(),
+        })
+        .collect();
+
+    let roots = match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(scene) => scene.nodes().map(|node| node.index()).collect(),
+        // A file without scenes is a library of nodes, show every one that isn't a child.
+        None => {
+            let mut is_child = vec![false; document.nodes().len()];
+            for child in document.nodes().flat_map(|node| node.children()) {
+                is_child[child.index()] = true;
+            }
+            (0..is_child.len())
+                .filter(|&node| !is_child[node])
+                .collect()
+        }
+    };
+
+    Ok(GltfScene {
+        meshes,
+        materials,
+        images: images.iter().map(rgba_image).collect(),
+        nodes,
+        roots,
+    })
+}
+
+fn read_triangles(
+    mesh: usize,
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+) -> Result<MeshData, GltfError> {
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+    let positions = reader
+        .read_positions()
+        .ok_or(GltfError::MissingPositions { mesh })?;
+    let mut vertices: Vec<MeshVertex> = positions
+        .map(|position| MeshVertex {
+            position,
+            ..Default::default()
+        })
+        .collect();
+    // glTF's uv origin is the top left like Vulkan's, so unlike OBJ there's no flip.
+    if let Some(uvs) = reader.read_tex_coords(0) {
+        for (vertex, uv) in vertices.iter_mut().zip(uvs.into_f32()) {
+            vertex.uv = uv;
+        }
+    }
+    if let Some(normals) = reader.read_normals() {
+        for (vertex, normal) in vertices.iter_mut().zip(normals) {
+            vertex.normal = normal;
+        }
+    }
+    let indices = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..vertices.len() as u32).collect(),
+    };
+    Ok(MeshData { vertices, indices })
+}
+
+// Converts any of glTF's decoded image formats to 8 bit RGBA. Missing colour channels are
+// repeated from red like a greyscale image, missing alpha is opaque.
+fn rgba_image(image: &gltf::image::Data) -> RGBAImage {
+    use gltf::image::Format;
+
+    let (channels, channel_size) = match image.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+    let channel = |bytes: &[u8]| match channel_size {
+        1 => bytes[0],
+        2 => (u16::from_ne_bytes([bytes[0], bytes[1]]) >> 8) as u8,
+        _ => {
+            let value = f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            (value.clamp(0.0, 1.0) * 255.0).round() as u8
+        }
+    };
+
+    let mut rgba = RGBAImage::new(image.width, image.height);
+    for (pixel, bytes) in rgba
+        .data
+        .iter_mut()
+        .zip(image.pixels.chunks_exact(channels * channel_size))
+    {
+        let mut values = [0; 4];
+        for (value, bytes) in values.iter_mut().zip(bytes.chunks_exact(channel_size)) {
+            *value = channel(bytes);
+        }
+        let [r, g, b, a] = values;
+        *pixel = match channels {
+            1 => RGBAPixel {
+                r,
+                g: r,
+                b: r,
+                a: 255,
+            },
+            // Luminance and alpha.
+            2 => RGBAPixel {
+                r,
+                g: r,
+                b: r,
+                a: g,
+            },
+            3 => RGBAPixel { r, g, b, a: 255 },
+            _ => RGBAPixel { r, g, b, a },
+        };
+    }
+    rgba
+}
@@ -0,0 +1,207 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use image::codecs::hdr::HdrDecoder;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RGBAPixel {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct HDRPixel {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+#[derive(Clone)]
+pub struct HDRImage {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<HDRPixel>,
+}
+
+#[derive(Clone)]
+pub struct RGBAImage {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<RGBAPixel>,
+}
+
+impl HDRImage {
+    /// Black and fully transparent.
+    pub fn new(width: u32, height: u32) -> HDRImage {
+        HDRImage {
+            width,
+            height,
+            data: vec![
+                HDRPixel {
+                    r: 0.0,
+                    g: 0.0,
+                    b: 0.0,
+                    a: 0.0,
+                };
+                (width * height) as usize
+            ],
+        }
+    }
+
+    /// Reads a Radiance `.hdr` file, the usual format of HDR environment maps. Alpha is always 1.
+    pub fn from_radiance_hdr(path: impl AsRef<Path>) -> Result<HDRImage, image::ImageError> {
+        let reader = BufReader::new(File::open(path)?);
+        let decoder = HdrDecoder::new(reader)?;
+        let metadata = decoder.metadata();
+        let data = decoder
+            .read_image_hdr()?
+            .into_iter()
+            .map(|pixel| HDRPixel {
+                r: pixel[0],
+                g: pixel[1],
+                b: pixel[2],
+                a: 1.0,
+            })
+            .collect();
+        Ok(HDRImage {
+            width: metadata.width,
+            height: metadata.height,
+            data,
+        })
+    }
+}
+
+impl RGBAImage {
+    pub fn get_pixel(&self, x: u32, y: u32) -> RGBAPixel {
+        return self.data[(y * self.width + x) as usize];
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, pixel: RGBAPixel) {
+        self.data[(y * self.width + x) as usize] = pixel;
+    }
+
+    /// Transparent black.
+    pub fn new(width: u32, height: u32) -> RGBAImage {
+        RGBAImage {
+            width,
+            height,
+            data: vec![
+                RGBAPixel {
+                    r: 0,
+                    g: 0,
+                    b: 0,
+                    a: 0,
+                };
+                (width * height) as usize
+            ],
+        }
+    }
+
+    /// Takes tightly packed 8 bit RGBA, row by row from the top, e.g. a decoder's or a video
+    /// frame's output. None if `bytes` isn't exactly `width` x `height` pixels.
+    pub fn from_raw(width: u32, height: u32, bytes: Vec<u8>) -> Option<RGBAImage> {
+        let pixels = (width as usize).checked_mul(height as usize)?;
+        if pixels.checked_mul(4)? != bytes.len() {
+            return None;
+        }
+        let data = bytes
+            .chunks_exact(4)
+            .map(|pixel| RGBAPixel {
+                r: pixel[0],
+                g: pixel[1],
+                b: pixel[2],
+                a: pixel[3],
+            })
+            .collect();
+        Some(RGBAImage {
+            width,
+            height,
+            data,
+        })
+    }
+
+    /// Every pixel row by row from the top, as `data` holds them.
+    pub fn pixels(&self) -> &[RGBAPixel] {
+        &self.data
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [RGBAPixel] {
+        &mut self.data
+    }
+
+    /// One slice of `width` pixels per row, from the top.
+    pub fn rows(&self) -> impl Iterator<Item = &[RGBAPixel]> {
+        self.data.chunks_exact(self.width.max(1) as usize)
+    }
+
+    pub fn rows_mut(&mut self) -> impl Iterator<Item = &mut [RGBAPixel]> {
+        self.data.chunks_exact_mut(self.width.max(1) as usize)
+    }
+
+    /// Decodes anything the `image` crate reads, e.g. PNG or JPEG, picked by the extension.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<RGBAImage, image::ImageError> {
+        Ok(Self::from_decoded(image::io::Reader::open(path)?.decode()?))
+    }
+
+    /// Like `from_file` for a file already in memory, e.g. one embedded with `include_bytes!`. The
+    /// format is guessed from the data.
+    pub fn from_bytes(bytes: &[u8]) -> Result<RGBAImage, image::ImageError> {
+        Ok(Self::from_decoded(image::load_from_memory(bytes)?))
+    }
+
+    fn from_decoded(decoded: image::DynamicImage) -> RGBAImage {
+        let decoded = decoded.into_rgba8();
+        RGBAImage {
+            width: decoded.width(),
+            height: decoded.height(),
+            data: decoded
+                .pixels()
+                .map(|pixel| RGBAPixel {
+                    r: pixel[0],
+                    g: pixel[1],
+                    b: pixel[2],
+                    a: pixel[3],
+                })
+                .collect(),
+        }
+    }
+
+    /// Swaps the rows top to bottom, for atlases laid out with v pointing up.
+    pub fn flip_vertical(&mut self) {
+        let width = self.width as usize;
+        let rows = self.height as usize;
+        for row in 0..rows / 2 {
+            let (top, bottom) = self.data.split_at_mut((rows - row - 1) * width);
+            top[row * width..(row + 1) * width].swap_with_slice(&mut bottom[..width]);
+        }
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> Result<(), image::ImageError> {
+        let bytes: Vec<u8> = self
+            .data
+            .iter()
+            .flat_map(|pixel| [pixel.r, pixel.g, pixel.b, pixel.a])
+            .collect();
+        image::save_buffer_with_format(
+            path,
+            &bytes,
+            self.width,
+            self.height,
+            image::ColorType::Rgba8,
+            image::ImageFormat::Png,
+        )
+    }
+}
+
+/// `RGBAImage::from_file`, then `flip_vertically` puts the first row of the file at the bottom.
+pub fn load(path: impl AsRef<Path>, flip_vertically: bool) -> Result<RGBAImage, image::ImageError> {
+    let mut image = RGBAImage::from_file(path)?;
+    if flip_vertically {
+        image.flip_vertical();
+    }
+    Ok(image)
+}
@@ -0,0 +1,320 @@
+use super::{MeshData, MeshError, MeshVertex};
+
+// Layout of a cache file, all values little endian:
+//     "JRMESH", u16 version, then LZ4 compressed (size prepended):
+//     u32 mesh count, then per mesh:
+//     u32 vertex count, u32 index count
+//     f32x3 position min, f32x3 position extent, f32x2 uv min, f32x2 uv extent
+//     per vertex: u16x3 position, u16x2 uv, i16x2 octahedral normal
+//     indices as u16 when every vertex fits, u32 otherwise
+// Positions and uvs are quantised to 16 bits across the mesh's bounds, so precision scales with
+// the size of the mesh.
+
+const MAGIC: &[u8; 6] = b"JRMESH";
+const VERSION: u16 = 2;
+
+// The counts and bounds every mesh starts with.
+const MESH_HEADER_BYTES: usize = 48;
+
+// LZ4 can't expand data by more than this, so a size prefix claiming more is corrupt and mustn't
+// be allocated.
+const MAX_COMPRESSION_RATIO: usize = 255;
+
+pub(super) fn encode(meshes: &[MeshData]) -> Vec<u8> {
+    let mut payload = Vec::new();
+    payload.extend_from_slice(&(meshes.len() as u32).to_le_bytes());
+    for mesh in meshes {
+        encode_mesh(mesh, &mut payload);
+    }
+
+    let mut file = Vec::with_capacity(MAGIC.len() + 2 + payload.len() / 2);
+    file.extend_from_slice(MAGIC);
+    file.extend_from_slice(&VERSION.to_le_bytes());
+    file.extend_from_slice(&lz4_flex::compress_prepend_size(&payload));
+    file
+}
+
+fn encode_mesh(mesh: &MeshData, payload: &mut Vec<u8>) {
+    let (position_min, position_extent) = bounds(mesh.vertices.iter().map(|v| v.position));
+    let (uv_min, uv_extent) = bounds(mesh.vertices.iter().map(|v| v.uv));
+    let small_indices = mesh.vertices.len() <= u16::MAX as usize + 1;
+
+    payload.reserve(MESH_HEADER_BYTES + mesh.vertices.len() * 14 + mesh.indices.len() * 4);
+    payload.extend_from_slice(&(mesh.vertices.len() as u32).to_le_bytes());
+    payload.extend_from_slice(&(mesh.indices.len() as u32).to_le_bytes());
+    for value in position_min
+        .iter()
+        .chain(&position_extent)
+        .chain(&uv_min)
+        .chain(&uv_extent)
+    {
+        payload.extend_from_slice(&value.to_le_bytes());
+    }
+    for vertex in &mesh.vertices {
+        for i in 0..3 {
+            let q = quantise(vertex.position[i], position_min[i], position_extent[i]);
+            payload.extend_from_slice(&q.to_le_bytes());
+        }
+        for i in 0..2 {
+            let q = quantise(vertex.uv[i], uv_min[i], uv_extent[i]);
+            payload.extend_from_slice(&q.to_le_bytes());
+        }
+        for value in encode_normal(vertex.normal) {
+            payload.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    for index in &mesh.indices {
+        if small_indices {
+            payload.extend_from_slice(&(*index as u16).to_le_bytes());
+        } else {
+            payload.extend_from_slice(&index.to_le_bytes());
+        }
+    }
+}
+
+pub(super) fn decode(file: &[u8]) -> Result<Vec<MeshData>, MeshError> {
+    if file.len() < MAGIC.len() + 2 || &file[..MAGIC.len()] != MAGIC {
+        return Err(MeshError::InvalidCache("not a mesh cache"));
+    }
+    if u16::from_le_bytes([file[6], file[7]]) != VERSION {
+        return Err(MeshError::InvalidCache("unsupported version"));
+    }
+    let compressed = &file[MAGIC.len() + 2..];
+    let mut reader = Reader { bytes: compressed };
+    let size = reader.u32()? as usize;
+    if size > reader.bytes.len().saturating_mul(MAX_COMPRESSION_RATIO) {
+        return Err(MeshError::InvalidCache("corrupt data"));
+    }
+    let payload = lz4_flex::decompress_size_prepended(compressed)
+        .map_err(|_| MeshError::InvalidCache("corrupt data"))?;
+    let mut reader = Reader { bytes: &payload };
+
+    let mesh_count = reader.u32()? as usize;
+    if mesh_count > reader.bytes.len() / MESH_HEADER_BYTES {
+        return Err(MeshError::InvalidCache("wrong size"));
+    }
+    let mut meshes = Vec::with_capacity(mesh_count);
+    for _ in 0..mesh_count {
+        meshes.push(decode_mesh(&mut reader)?);
+    }
+    if !reader.bytes.is_empty() {
+        return Err(MeshError::InvalidCache("wrong size"));
+    }
+    Ok(meshes)
+}
+
+fn decode_mesh(reader: &mut Reader) -> Result<MeshData, MeshError> {
+    let vertex_count = reader.u32()? as usize;
+    let index_count = reader.u32()? as usize;
+    let position_min = [reader.f32()?, reader.f32()?, reader.f32()?];
+    let position_extent = [reader.f32()?, reader.f32()?, reader.f32()?];
+    let uv_min = [reader.f32()?, reader.f32()?];
+    let uv_extent = [reader.f32()?, reader.f32()?];
+    let small_indices = vertex_count <= u16::MAX as usize + 1;
+    let expected =
+        vertex_count as u64 * 14 + index_count as u64 * if small_indices { 2 } else { 4 };
+    if (reader.bytes.len() as u64) < expected {
+        return Err(MeshError::InvalidCache("wrong size"));
+    }
+
+    let mut mesh = MeshData {
+        vertices: Vec::with_capacity(vertex_count),
+        indices: Vec::with_capacity(index_count),
+    };
+    for _ in 0..vertex_count {
+        let mut vertex = MeshVertex::default();
+        for i in 0..3 {
+            vertex.position[i] = dequantise(reader.u16()?, position_min[i], position_extent[i]);
+        }
+        for i in 0..2 {
+            vertex.uv[i] = dequantise(reader.u16()?, uv_min[i], uv_extent[i]);
+        }
+        vertex.normal = decode_normal([reader.i16()?, reader.i16()?]);
+        mesh.vertices.push(vertex);
+    }
+    for _ in 0..index_count {
+        let index = if small_indices {
+            reader.u16()? as u32
+        } else {
+            reader.u32()?
+        };
+        if index as usize >= vertex_count {
+            return Err(MeshError::InvalidCache("index out of range"));
+        }
+        mesh.indices.push(index);
+    }
+    Ok(mesh)
+}
+
+fn bounds<const N: usize>(values: impl Iterator<Item = [f32; N]>) -> ([f32; N], [f32; N]) {
+    let mut min = [f32::MAX; N];
+    let mut max = [f32::MIN; N];
+    let mut any = false;
+    for value in values {
+        any = true;
+        for i in 0..N {
+            min[i] = min[i].min(value[i]);
+            max[i] = max[i].max(value[i]);
+        }
+    }
+    if !any {
+        return ([0.0; N], [0.0; N]);
+    }
+    let mut extent = [0.0; N];
+    for i in 0..N {
+        extent[i] = max[i] - min[i];
+    }
+    (min, extent)
+}
+
+fn quantise(value: f32, min: f32, extent: f32) -> u16 {
+    if extent <= 0.0 {
+        return 0;
+    }
+    (((value - min) / extent).clamp(0.0, 1.0) * u16::MAX as f32).round() as u16
+}
+
+fn dequantise(value: u16, min: f32, extent: f32) -> f32 {
+    min + value as f32 / u16::MAX as f32 * extent
+}
+
+// Octahedral encoding: the unit sphere is folded onto a square, which spends the bits evenly
+// over every direction.
+pub(super) fn encode_normal(normal: [f32; 3]) -> [i16; 2] {
+    let length = normal[0].abs() + normal[1].abs() + normal[2].abs();
+    if length == 0.0 {
+        return [0, 0];
+    }
+    let (mut x, mut y) = (normal[0] / length, normal[1] / length);
+    if normal[2] < 0.0 {
+        (x, y) = ((1.0 - y.abs()) * x.signum(), (1.0 - x.abs()) * y.signum());
+    }
+    [
+        (x * i16::MAX as f32).round() as i16,
+        (y * i16::MAX as f32).round() as i16,
+    ]
+}
+
+fn decode_normal(encoded: [i16; 2]) -> [f32; 3] {
+    let mut x = (encoded[0] as f32 / i16::MAX as f32).clamp(-1.0, 1.0);
+    let mut y = (encoded[1] as f32 / i16::MAX as f32).clamp(-1.0, 1.0);
+    let z = 1.0 - x.abs() - y.abs();
+    if z < 0.0 {
+        (x, y) = ((1.0 - y.abs()) * x.signum(), (1.0 - x.abs()) * y.signum());
+    }
+    let length = (x * x + y * y + z * z).sqrt();
+    [x / length, y / length, z / length]
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], MeshError> {
+        if self.bytes.len() < N {
+            return Err(MeshError::InvalidCache("truncated"));
+        }
+        let (head, rest) = self.bytes.split_at(N);
+        self.bytes = rest;
+        Ok(head.try_into().unwrap())
+    }
+
+    fn u16(&mut self) -> Result<u16, MeshError> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+
+    fn i16(&mut self) -> Result<i16, MeshError> {
+        Ok(i16::from_le_bytes(self.take()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, MeshError> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, MeshError> {
+        Ok(f32::from_le_bytes(self.take()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mesh(vertex_count: u32) -> MeshData {
+        MeshData {
+            vertices: (0..vertex_count)
+                .map(|i| {
+                    let t = i as f32 / vertex_count as f32;
+                    MeshVertex {
+                        position: [t * 4.0 - 2.0, t * t, -3.0],
+                        uv: [t, 1.0 - t],
+                        normal: [t - 0.5, 0.5, -1.0],
+                    }
+                })
+                .collect(),
+            indices: (0..vertex_count).rev().collect(),
+        }
+    }
+
+    #[test]
+    fn quantise_round_trips_within_a_step() {
+        let (min, extent) = (-2.0, 5.0);
+        for value in [-2.0, -1.234, 0.0, 2.5, 3.0] {
+            let step = extent / u16::MAX as f32;
+            let decoded = dequantise(quantise(value, min, extent), min, extent);
+            assert!(
+                (decoded - value).abs() <= step,
+                "{} became {}",
+                value,
+                decoded
+            );
+        }
+        assert_eq!(quantise(7.0, 1.0, 0.0), 0);
+    }
+
+    #[test]
+    fn normals_round_trip() {
+        for normal in [[0.0, 0.0, 1.0], [0.0, 0.0, -1.0], [0.6, -0.48, -0.64]] {
+            let decoded = decode_normal(encode_normal(normal));
+            for i in 0..3 {
+                assert!((decoded[i] - normal[i]).abs() < 1e-3, "{:?}", decoded);
+            }
+        }
+    }
+
+    #[test]
+    fn meshes_round_trip_through_lz4() {
+        // The second needs 32 bit indices.
+        let meshes = [mesh(100), mesh(u16::MAX as u32 + 2), MeshData::default()];
+        let decoded = decode(&encode(&meshes)).unwrap();
+        assert_eq!(decoded.len(), meshes.len());
+        for (decoded, mesh) in decoded.iter().zip(&meshes) {
+            assert_eq!(decoded.indices, mesh.indices);
+            assert_eq!(decoded.vertices.len(), mesh.vertices.len());
+            for (decoded, vertex) in decoded.vertices.iter().zip(&mesh.vertices) {
+                for i in 0..3 {
+                    assert!((decoded.position[i] - vertex.position[i]).abs() < 1e-3);
+                }
+                for i in 0..2 {
+                    assert!((decoded.uv[i] - vertex.uv[i]).abs() < 1e-3);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn corrupt_caches_are_errors() {
+        let file = encode(&[mesh(10)]);
+        for length in 0..file.len() {
+            assert!(decode(&file[..length]).is_err(), "{} bytes", length);
+        }
+        // A size prefix far past what the rest of the file could decompress to.
+        let mut file = file;
+        file[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            decode(&file),
+            Err(MeshError::InvalidCache("corrupt data"))
+        ));
+    }
+}
@@ -0,0 +1,47 @@
+use half::f16;
+
+use super::{cache::encode_normal, MeshData};
+
+/// Half the size of `MeshVertex`, matching the renderer's `VertexLayout::compressed`:
+/// ```text
+/// f16x4 position (w is unused padding), f16x2 uv, i16x2 octahedral normal as snorm
+/// ```
+/// Half floats keep about three significant digits, so positions drift by up to a few
+/// millimetres a couple of metres from the origin and noticeably further out. Keep meshes
+/// centred on their origin and place them with the instance transform.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CompressedMeshVertex {
+    pub position: [u16; 4],
+    pub uv: [u16; 2],
+    pub normal: [i16; 2],
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CompressedMeshData {
+    pub vertices: Vec<CompressedMeshVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    pub fn compress(&self) -> CompressedMeshData {
+        let half = |value: f32| f16::from_f32(value).to_bits();
+        CompressedMeshData {
+            vertices: self
+                .vertices
+                .iter()
+                .map(|vertex| CompressedMeshVertex {
+                    position: [
+                        half(vertex.position[0]),
+                        half(vertex.position[1]),
+                        half(vertex.position[2]),
+                        0,
+                    ],
+                    uv: [half(vertex.uv[0]), half(vertex.uv[1])],
+                    normal: encode_normal(vertex.normal),
+                })
+                .collect(),
+            indices: self.indices.clone(),
+        }
+    }
+}
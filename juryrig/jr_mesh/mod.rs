@@ -0,0 +1,121 @@
+//! CPU side mesh data and loading from disk.
+//!
+//! Imported meshes are written to a compressed cache file next to the source (`<source>.jrmesh`)
+//! the first time they are loaded. Later loads read the cache instead of parsing the source again,
+//! unless the source has been modified since. Sources holding several meshes, like glTF files,
+//! cache them all in one file with `read_cache` and `write_cache`.
+//!
+//! Large meshes can be converted to `CompressedMeshData` after loading, which halves their vertex
+//! memory at the cost of some position precision. They need a pipeline built from
+//! `VertexFormat::compressed`.
+
+mod cache;
+mod compressed;
+mod obj;
+
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+
+pub use self::compressed::{CompressedMeshData, CompressedMeshVertex};
+
+/// Same layout as the renderer's `ShaderVertexData`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub uv: [f32; 2],
+    pub normal: [f32; 3],
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+}
+
+#[derive(Debug)]
+pub enum MeshError {
+    Io(std::io::Error),
+    /// A malformed line in a source file.
+    Parse {
+        line: usize,
+        message: String,
+    },
+    /// The file extension isn't one we can import.
+    UnsupportedFormat(PathBuf),
+    /// The cache file is corrupt or from an incompatible version.
+    InvalidCache(&'static str),
+}
+
+impl From<std::io::Error> for MeshError {
+    fn from(value: std::io::Error) -> Self {
+        MeshError::Io(value)
+    }
+}
+
+/// Loads a mesh, going through the cache when it is up to date.
+pub fn load(path: impl AsRef<Path>) -> Result<MeshData, MeshError> {
+    let path = path.as_ref();
+    let cached = read_cache(path)
+        .filter(|meshes| meshes.len() == 1)
+        .and_then(|mut meshes| meshes.pop());
+    if let Some(mesh) = cached {
+        return Ok(mesh);
+    }
+    let mesh = import(path)?;
+    write_cache(path, std::slice::from_ref(&mesh));
+    Ok(mesh)
+}
+
+/// The meshes cached for the source at `path`, None when there's no cache, it's older than the
+/// source, or it can't be read.
+pub fn read_cache(path: impl AsRef<Path>) -> Option<Vec<MeshData>> {
+    let path = path.as_ref();
+    let cache_path = cache_path(path);
+    if !cache_is_fresh(path, &cache_path) {
+        return None;
+    }
+    std::fs::read(&cache_path)
+        .map_err(MeshError::from)
+        .and_then(|bytes| cache::decode(&bytes))
+        .map_err(|e| warn!("Ignoring mesh cache {}. {:?}", cache_path.display(), e))
+        .ok()
+}
+
+/// Caches the meshes imported from the source at `path` for `read_cache`. Failing to write it is
+/// only logged, the source can still be imported next time.
+pub fn write_cache(path: impl AsRef<Path>, meshes: &[MeshData]) {
+    let cache_path = cache_path(path.as_ref());
+    match std::fs::write(&cache_path, cache::encode(meshes)) {
+        Ok(()) => info!("Wrote mesh cache {}", cache_path.display()),
+        Err(e) => warn!("Could not write mesh cache {}. {}", cache_path.display(), e),
+    }
+}
+
+/// Parses a source file directly, skipping the cache.
+pub fn import(path: impl AsRef<Path>) -> Result<MeshData, MeshError> {
+    let path = path.as_ref();
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) if extension.eq_ignore_ascii_case("obj") => {
+            obj::parse(&std::fs::read_to_string(path)?)
+        }
+        _ => Err(MeshError::UnsupportedFormat(path.to_path_buf())),
+    }
+}
+
+fn cache_path(path: &Path) -> PathBuf {
+    let mut cache_path = path.as_os_str().to_owned();
+    cache_path.push(".jrmesh");
+    PathBuf::from(cache_path)
+}
+
+fn cache_is_fresh(path: &Path, cache_path: &Path) -> bool {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|metadata| metadata.modified());
+    match (modified(path), modified(cache_path)) {
+        (Ok(source), Ok(cache)) => cache >= source,
+        // A cache without its source is still usable, e.g. when only caches are shipped.
+        (Err(_), Ok(_)) => true,
+        _ => false,
+    }
+}
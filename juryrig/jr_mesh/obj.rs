@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+use super::{MeshData, MeshError, MeshVertex};
+
+// Reads the geometry of a Wavefront OBJ file. Faces with more than three corners are split into
+// a fan, materials, groups and everything else are ignored.
+pub(super) fn parse(source: &str) -> Result<MeshData, MeshError> {
+    let mut positions: Vec<[f32; 3]> = vec![];
+    let mut uvs: Vec<[f32; 2]> = vec![];
+    let mut normals: Vec<[f32; 3]> = vec![];
+
+    let mut mesh = MeshData::default();
+    // OBJ indexes positions, uvs and normals separately, each distinct combination is a vertex.
+    let mut vertex_indices: HashMap<(usize, Option<usize>, Option<usize>), u32> = HashMap::new();
+
+    for (line_index, line) in source.lines().enumerate() {
+        let line_number = line_index + 1;
+        let error = |message: &str| MeshError::Parse {
+            line: line_number,
+            message: message.to_owned(),
+        };
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("v") => positions.push(floats(words, line_number)?),
+            Some("vt") => {
+                let [u, v] = floats(words, line_number)?;
+                // OBJ puts the origin at the bottom left, Vulkan at the top left.
+                uvs.push([u, 1.0 - v]);
+            }
+            Some("vn") => normals.push(floats(words, line_number)?),
+            Some("f") => {
+                let mut corners = vec![];
+                for corner in words {
+                    let mut parts = corner.split('/');
+                    let position = resolve(parts.next(), positions.len(), line_number)?
+                        .ok_or_else(|| error("face corner without a position"))?;
+                    let uv = resolve(parts.next(), uvs.len(), line_number)?;
+                    let normal = resolve(parts.next(), normals.len(), line_number)?;
+
+                    let key = (position, uv, normal);
+                    let index = match vertex_indices.get(&key) {
+                        Some(index) => *index,
+                        None => {
+                            let index = mesh.vertices.len() as u32;
+                            mesh.vertices.push(MeshVertex {
+                                position: positions[position],
+                                uv: uv.map(|uv| uvs[uv]).unwrap_or_default(),
+                                normal: normal.map(|normal| normals[normal]).unwrap_or_default(),
+                            });
+                            vertex_indices.insert(key, index);
+                            index
+                        }
+                    };
+                    corners.push(index);
+                }
+                if corners.len() < 3 {
+                    return Err(error("face with fewer than three corners"));
+                }
+                for i in 1..corners.len() - 1 {
+                    mesh.indices
+                        .extend_from_slice(&[corners[0], corners[i], corners[i + 1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(mesh)
+}
+
+// Reads the first N numbers on a line, extra values (such as a w coordinate) are ignored.
+fn floats<'a, const N: usize>(
+    mut words: impl Iterator<Item = &'a str>,
+    line: usize,
+) -> Result<[f32; N], MeshError> {
+    let mut values = [0f32; N];
+    for value in values.iter_mut() {
+        *value = words
+            .next()
+            .and_then(|word| word.parse().ok())
+            .ok_or_else(|| MeshError::Parse {
+                line,
+                message: format!("expected {} numbers", N),
+            })?;
+    }
+    Ok(values)
+}
+
+// Turns a 1-based (or negative, relative to the end) OBJ index into a 0-based one. Empty parts,
+// as in `1//3`, are None.
+fn resolve(part: Option<&str>, count: usize, line: usize) -> Result<Option<usize>, MeshError> {
+    let part = match part {
+        Some(part) if !part.is_empty() => part,
+        _ => return Ok(None),
+    };
+    let error = || MeshError::Parse {
+        line,
+        message: format!("invalid index {}", part),
+    };
+    let index: i64 = part.parse().map_err(|_| error())?;
+    let resolved = if index < 0 {
+        count as i64 + index
+    } else {
+        index - 1
+    };
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(error());
+    }
+    Ok(Some(resolved as usize))
+}
@@ -0,0 +1,516 @@
+//! Block compressed textures from KTX2 and DDS files, kept compressed so the GPU can sample them
+//! as they are. Only plain containers are read: no Basis Universal or Zstandard supercompression,
+//! no cubemaps, arrays or 3D textures.
+
+use std::path::Path;
+
+use crate::jr_image::{RGBAImage, RGBAPixel};
+
+/// The block compressed formats the loaders understand. The `Srgb` variants hold colour that the
+/// GPU decodes to linear when it samples.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockFormat {
+    Bc1,
+    Bc1Srgb,
+    Bc2,
+    Bc2Srgb,
+    Bc3,
+    Bc3Srgb,
+    Bc4,
+    Bc5,
+    Bc7,
+    Bc7Srgb,
+    Astc4x4,
+    Astc4x4Srgb,
+    Astc6x6,
+    Astc6x6Srgb,
+    Astc8x8,
+    Astc8x8Srgb,
+}
+
+impl BlockFormat {
+    /// Width and height in texels of one block.
+    pub fn block_size(&self) -> (u32, u32) {
+        match self {
+            BlockFormat::Astc6x6 | BlockFormat::Astc6x6Srgb => (6, 6),
+            BlockFormat::Astc8x8 | BlockFormat::Astc8x8Srgb => (8, 8),
+            _ => (4, 4),
+        }
+    }
+
+    pub fn block_bytes(&self) -> usize {
+        match self {
+            BlockFormat::Bc1 | BlockFormat::Bc1Srgb | BlockFormat::Bc4 => 8,
+            _ => 16,
+        }
+    }
+
+    pub fn is_astc(&self) -> bool {
+        matches!(
+            self,
+            BlockFormat::Astc4x4
+                | BlockFormat::Astc4x4Srgb
+                | BlockFormat::Astc6x6
+                | BlockFormat::Astc6x6Srgb
+                | BlockFormat::Astc8x8
+                | BlockFormat::Astc8x8Srgb
+        )
+    }
+
+    /// Bytes in a `width` x `height` level, partial blocks at the edges count as whole ones. None
+    /// when that doesn't fit in a usize.
+    pub fn level_bytes(&self, width: u32, height: u32) -> Option<usize> {
+        let (block_width, block_height) = self.block_size();
+        let blocks = width.div_ceil(block_width) as u64 * height.div_ceil(block_height) as u64;
+        usize::try_from(blocks)
+            .ok()?
+            .checked_mul(self.block_bytes())
+    }
+
+    // The format's value in KTX2's vkFormat field, the same as Vulkan's.
+    fn from_vk_format(format: u32) -> Option<BlockFormat> {
+        Some(match format {
+            131 | 133 => BlockFormat::Bc1,
+            132 | 134 => BlockFormat::Bc1Srgb,
+            135 => BlockFormat::Bc2,
+            136 => BlockFormat::Bc2Srgb,
+            137 => BlockFormat::Bc3,
+            138 => BlockFormat::Bc3Srgb,
+            139 => BlockFormat::Bc4,
+            141 => BlockFormat::Bc5,
+            145 => BlockFormat::Bc7,
+            146 => BlockFormat::Bc7Srgb,
+            157 => BlockFormat::Astc4x4,
+            158 => BlockFormat::Astc4x4Srgb,
+            165 => BlockFormat::Astc6x6,
+            166 => BlockFormat::Astc6x6Srgb,
+            171 => BlockFormat::Astc8x8,
+            172 => BlockFormat::Astc8x8Srgb,
+            _ => return None,
+        })
+    }
+
+    // DDS files name their format with a DXGI_FORMAT in the DX10 header.
+    fn from_dxgi_format(format: u32) -> Option<BlockFormat> {
+        Some(match format {
+            71 => BlockFormat::Bc1,
+            72 => BlockFormat::Bc1Srgb,
+            74 => BlockFormat::Bc2,
+            75 => BlockFormat::Bc2Srgb,
+            77 => BlockFormat::Bc3,
+            78 => BlockFormat::Bc3Srgb,
+            80 => BlockFormat::Bc4,
+            83 => BlockFormat::Bc5,
+            98 => BlockFormat::Bc7,
+            99 => BlockFormat::Bc7Srgb,
+            _ => return None,
+        })
+    }
+
+    // Or with a four character code in older files.
+    fn from_four_cc(four_cc: &[u8; 4]) -> Option<BlockFormat> {
+        Some(match four_cc {
+            b"DXT1" => BlockFormat::Bc1,
+            b"DXT2" | b"DXT3" => BlockFormat::Bc2,
+            b"DXT4" | b"DXT5" => BlockFormat::Bc3,
+            b"ATI1" | b"BC4U" => BlockFormat::Bc4,
+            b"ATI2" | b"BC5U" => BlockFormat::Bc5,
+            _ => return None,
+        })
+    }
+}
+
+/// A 2D texture with its mip chain, still block compressed.
+#[derive(Clone, Debug)]
+pub struct CompressedImage {
+    pub width: u32,
+    pub height: u32,
+    pub format: BlockFormat,
+    /// Largest first, each level half the size of the one before and at least one texel.
+    pub levels: Vec<Vec<u8>>,
+}
+
+#[derive(Debug)]
+pub enum TextureFileError {
+    Io(std::io::Error),
+    /// Not a KTX2 or DDS file, or one that's cut short or contradicts itself.
+    Invalid(&'static str),
+    /// A valid file using something the loaders don't read, e.g. supercompression or a format
+    /// that isn't block compressed.
+    Unsupported(String),
+}
+
+impl From<std::io::Error> for TextureFileError {
+    fn from(value: std::io::Error) -> Self {
+        TextureFileError::Io(value)
+    }
+}
+
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+
+/// Whether `path` names a file `load` reads, going by its extension.
+pub fn is_texture_file(path: impl AsRef<Path>) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("ktx2") || extension.eq_ignore_ascii_case("dds")
+        })
+}
+
+/// Reads a KTX2 or DDS file, told apart by their first bytes rather than the extension.
+pub fn load(path: impl AsRef<Path>) -> Result<CompressedImage, TextureFileError> {
+    from_bytes(&std::fs::read(path)?)
+}
+
+pub fn from_bytes(file: &[u8]) -> Result<CompressedImage, TextureFileError> {
+    if file.starts_with(&KTX2_IDENTIFIER) {
+        parse_ktx2(file)
+    } else if file.starts_with(DDS_MAGIC) {
+        parse_dds(file)
+    } else {
+        Err(TextureFileError::Invalid("not a KTX2 or DDS file"))
+    }
+}
+
+fn parse_ktx2(file: &[u8]) -> Result<CompressedImage, TextureFileError> {
+    let header = |index: usize| read_u32(file, 12 + index * 4);
+    let vk_format = header(0)?;
+    let (width, height, depth) = (header(2)?, header(3)?, header(4)?);
+    let (layers, faces, level_count) = (header(5)?, header(6)?, header(7)?);
+    let supercompression = header(8)?;
+    if supercompression != 0 {
+        return Err(TextureFileError::Unsupported(format!(
+            "supercompression scheme {}",
+            supercompression
+        )));
+    }
+    if depth > 0 || layers > 0 || faces != 1 {
+        return Err(TextureFileError::Unsupported(
+            "cubemaps, arrays and 3D textures".to_owned(),
+        ));
+    }
+    let format = BlockFormat::from_vk_format(vk_format)
+        .ok_or_else(|| TextureFileError::Unsupported(format!("vkFormat {}", vk_format)))?;
+    // Zero asks the loader to generate mips, the file still holds the first level.
+    let level_count = level_count.max(1);
+    check_extent(width, height, level_count)?;
+    let mut levels = Vec::with_capacity(level_count as usize);
+    for level in 0..level_count {
+        // The level index follows the 80 byte header, 24 bytes per level.
+        let entry = 80 + level as usize * 24;
+        let offset = read_usize(file, entry)?;
+        let length = read_usize(file, entry + 8)?;
+        let (level_width, level_height) = level_extent(width, height, level);
+        if Some(length) != format.level_bytes(level_width, level_height) {
+            return Err(TextureFileError::Invalid(
+                "level size doesn't match its extent",
+            ));
+        }
+        levels.push(slice(file, offset, length)?.to_vec());
+    }
+    image(width, height, format, levels)
+}
+
+fn parse_dds(file: &[u8]) -> Result<CompressedImage, TextureFileError> {
+    if read_u32(file, 4)? != 124 {
+        return Err(TextureFileError::Invalid("wrong DDS header size"));
+    }
+    let (height, width) = (read_u32(file, 12)?, read_u32(file, 16)?);
+    let level_count = read_u32(file, 28)?.max(1);
+    let four_cc: [u8; 4] = slice(file, 84, 4)?.try_into().unwrap();
+    // DDSCAPS2_CUBEMAP
+    if read_u32(file, 112)? & 0x200 != 0 {
+        return Err(TextureFileError::Unsupported("cubemaps".to_owned()));
+    }
+    let (format, mut offset) = if &four_cc == b"DX10" {
+        let dxgi_format = read_u32(file, 128)?;
+        if read_u32(file, 128 + 12)? > 1 {
+            return Err(TextureFileError::Unsupported("texture arrays".to_owned()));
+        }
+        let format = BlockFormat::from_dxgi_format(dxgi_format)
+            .ok_or_else(|| TextureFileError::Unsupported(format!("DXGI_FORMAT {}", dxgi_format)))?;
+        (format, 148)
+    } else {
+        let format = BlockFormat::from_four_cc(&four_cc).ok_or_else(|| {
+            TextureFileError::Unsupported(format!("format {}", String::from_utf8_lossy(&four_cc)))
+        })?;
+        (format, 128)
+    };
+    check_extent(width, height, level_count)?;
+    // Levels are packed one after the other, largest first.
+    let mut levels = Vec::with_capacity(level_count as usize);
+    for level in 0..level_count {
+        let (level_width, level_height) = level_extent(width, height, level);
+        let length = format
+            .level_bytes(level_width, level_height)
+            .ok_or(TextureFileError::Invalid("truncated"))?;
+        levels.push(slice(file, offset, length)?.to_vec());
+        offset += length;
+    }
+    image(width, height, format, levels)
+}
+
+// Rejects headers with no texels or more levels than halving the extent down to one texel gives,
+// before anything is allocated for them.
+fn check_extent(width: u32, height: u32, level_count: u32) -> Result<(), TextureFileError> {
+    if width == 0 || height == 0 {
+        return Err(TextureFileError::Invalid("no texels"));
+    }
+    if level_count > 32 - width.max(height).leading_zeros() {
+        return Err(TextureFileError::Invalid(
+            "more levels than the extent allows",
+        ));
+    }
+    Ok(())
+}
+
+fn image(
+    width: u32,
+    height: u32,
+    format: BlockFormat,
+    levels: Vec<Vec<u8>>,
+) -> Result<CompressedImage, TextureFileError> {
+    check_extent(width, height, levels.len() as u32)?;
+    Ok(CompressedImage {
+        width,
+        height,
+        format,
+        levels,
+    })
+}
+
+fn level_extent(width: u32, height: u32, level: u32) -> (u32, u32) {
+    (
+        (width >> level.min(31)).max(1),
+        (height >> level.min(31)).max(1),
+    )
+}
+
+fn slice(file: &[u8], offset: usize, length: usize) -> Result<&[u8], TextureFileError> {
+    offset
+        .checked_add(length)
+        .and_then(|end| file.get(offset..end))
+        .ok_or(TextureFileError::Invalid("truncated"))
+}
+
+fn read_u32(file: &[u8], offset: usize) -> Result<u32, TextureFileError> {
+    Ok(u32::from_le_bytes(
+        slice(file, offset, 4)?.try_into().unwrap(),
+    ))
+}
+
+// A u64 offset or length, which can't be inside the file if it doesn't fit in a usize.
+fn read_usize(file: &[u8], offset: usize) -> Result<usize, TextureFileError> {
+    let value = u64::from_le_bytes(slice(file, offset, 8)?.try_into().unwrap());
+    usize::try_from(value).map_err(|_| TextureFileError::Invalid("truncated"))
+}
+
+impl CompressedImage {
+    /// Decompresses the first level on the CPU, for devices that can't sample the format. Only
+    /// BC1 to BC3 are decoded, None for the rest. The texels come out as stored, so linear data
+    /// will be read as sRGB by `register_texture`.
+    pub fn decode(&self) -> Option<RGBAImage> {
+        let (alpha, colour_offset) = match self.format {
+            BlockFormat::Bc1 | BlockFormat::Bc1Srgb => (None, 0),
+            BlockFormat::Bc2 | BlockFormat::Bc2Srgb => {
+                (Some(decode_explicit_alpha as fn(_) -> _), 8)
+            }
+            BlockFormat::Bc3 | BlockFormat::Bc3Srgb => {
+                (Some(decode_interpolated_alpha as fn(_) -> _), 8)
+            }
+            _ => return None,
+        };
+        let level = self.levels.first()?;
+        let mut image = RGBAImage::new(self.width, self.height);
+        let blocks_wide = self.width.div_ceil(4);
+        for (index, block) in level.chunks_exact(self.format.block_bytes()).enumerate() {
+            let (block_x, block_y) = (
+                index as u32 % blocks_wide * 4,
+                index as u32 / blocks_wide * 4,
+            );
+            let colour_block: &[u8; 8] =
+                block[colour_offset..colour_offset + 8].try_into().unwrap();
+            let mut texels = decode_colour(colour_block, alpha.is_none());
+            if let Some(decode_alpha) = alpha {
+                let alphas = decode_alpha(block[..8].try_into().unwrap());
+                for (texel, alpha) in texels.iter_mut().zip(alphas) {
+                    texel.a = alpha;
+                }
+            }
+            for (texel_index, texel) in texels.into_iter().enumerate() {
+                let x = block_x + texel_index as u32 % 4;
+                let y = block_y + texel_index as u32 / 4;
+                if x < self.width && y < self.height {
+                    image.set_pixel(x, y, texel);
+                }
+            }
+        }
+        Some(image)
+    }
+}
+
+// The colour half of a BC1 to BC3 block, two RGB565 endpoints and a 2 bit index per texel. Only
+// BC1 has the three colour mode with transparent black.
+fn decode_colour(block: &[u8; 8], bc1: bool) -> [RGBAPixel; 16] {
+    let endpoints = [
+        u16::from_le_bytes([block[0], block[1]]),
+        u16::from_le_bytes([block[2], block[3]]),
+    ];
+    let [c0, c1] = endpoints.map(|colour| {
+        let expand = |value: u16, bits: u32| {
+            ((value as u32 * 255 + ((1 << bits) - 1) / 2) / ((1 << bits) - 1)) as u8
+        };
+        [
+            expand(colour >> 11, 5),
+            expand((colour >> 5) & 0x3F, 6),
+            expand(colour & 0x1F, 5),
+        ]
+    });
+    let mix = |a: u8, b: u8, wa: u32, wb: u32| ((a as u32 * wa + b as u32 * wb) / (wa + wb)) as u8;
+    let opaque = |rgb: [u8; 3]| RGBAPixel {
+        r: rgb[0],
+        g: rgb[1],
+        b: rgb[2],
+        a: 255,
+    };
+    let blend = |wa, wb| opaque([0, 1, 2].map(|i| mix(c0[i], c1[i], wa, wb)));
+    let palette = if !bc1 || endpoints[0] > endpoints[1] {
+        [opaque(c0), opaque(c1), blend(2, 1), blend(1, 2)]
+    } else {
+        let black = RGBAPixel {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 0,
+        };
+        [opaque(c0), opaque(c1), blend(1, 1), black]
+    };
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    std::array::from_fn(|texel| palette[(indices >> (texel * 2)) as usize & 3])
+}
+
+// BC2's alpha, 4 bits per texel.
+fn decode_explicit_alpha(block: &[u8; 8]) -> [u8; 16] {
+    let bits = u64::from_le_bytes(*block);
+    std::array::from_fn(|texel| ((bits >> (texel * 4)) & 0xF) as u8 * 17)
+}
+
+// BC3's alpha, two endpoints and a 3 bit index per texel.
+fn decode_interpolated_alpha(block: &[u8; 8]) -> [u8; 16] {
+    let (a0, a1) = (block[0] as u32, block[1] as u32);
+    let palette: [u8; 8] = if a0 > a1 {
+        std::array::from_fn(|i| match i {
+            0 => a0 as u8,
+            1 => a1 as u8,
+            i => (((8 - i as u32) * a0 + (i as u32 - 1) * a1) / 7) as u8,
+        })
+    } else {
+        std::array::from_fn(|i| match i {
+            0 => a0 as u8,
+            1 => a1 as u8,
+            6 => 0,
+            7 => 255,
+            i => (((6 - i as u32) * a0 + (i as u32 - 1) * a1) / 5) as u8,
+        })
+    };
+    let mut bits = [0u8; 8];
+    bits[..6].copy_from_slice(&block[2..]);
+    let bits = u64::from_le_bytes(bits);
+    std::array::from_fn(|texel| palette[((bits >> (texel * 3)) & 7) as usize])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A BC1 KTX2 file with `levels`, its header claiming `width` x `height` and `level_count`.
+    fn ktx2(width: u32, height: u32, level_count: u32, levels: &[Vec<u8>]) -> Vec<u8> {
+        let mut file = KTX2_IDENTIFIER.to_vec();
+        for value in [131, 1, width, height, 0, 0, 1, level_count, 0, 0, 0, 0, 0] {
+            file.extend_from_slice(&u32::to_le_bytes(value));
+        }
+        file.resize(80, 0);
+        let mut offset = 80 + levels.len() * 24;
+        for level in levels {
+            for value in [offset, level.len(), level.len()] {
+                file.extend_from_slice(&u64::to_le_bytes(value as u64));
+            }
+            offset += level.len();
+        }
+        levels
+            .iter()
+            .for_each(|level| file.extend_from_slice(level));
+        file
+    }
+
+    // A DXT1 DDS file, laid out the same way.
+    fn dds(width: u32, height: u32, level_count: u32, levels: &[Vec<u8>]) -> Vec<u8> {
+        let mut file = vec![0; 128];
+        file[..4].copy_from_slice(DDS_MAGIC);
+        for (offset, value) in [(4, 124), (12, height), (16, width), (28, level_count)] {
+            file[offset..offset + 4].copy_from_slice(&u32::to_le_bytes(value));
+        }
+        file[84..88].copy_from_slice(b"DXT1");
+        levels
+            .iter()
+            .for_each(|level| file.extend_from_slice(level));
+        file
+    }
+
+    // An 8x8 chain, 2x2 blocks and then one block per level.
+    fn chain() -> Vec<Vec<u8>> {
+        vec![vec![1; 32], vec![2; 8], vec![3; 8], vec![4; 8]]
+    }
+
+    #[test]
+    fn level_bytes_rounds_up_to_whole_blocks() {
+        assert_eq!(BlockFormat::Bc1.level_bytes(5, 1), Some(16));
+        assert_eq!(BlockFormat::Astc6x6.level_bytes(12, 7), Some(64));
+        assert_eq!(BlockFormat::Bc7.level_bytes(u32::MAX, u32::MAX), None);
+    }
+
+    #[test]
+    fn reads_both_containers() {
+        for file in [ktx2(8, 8, 4, &chain()), dds(8, 8, 4, &chain())] {
+            let image = from_bytes(&file).unwrap();
+            assert_eq!((image.width, image.height), (8, 8));
+            assert_eq!(image.format, BlockFormat::Bc1);
+            assert_eq!(image.levels, chain());
+        }
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        for file in [ktx2(8, 8, 4, &chain()), dds(8, 8, 4, &chain())] {
+            for length in 0..file.len() {
+                assert!(from_bytes(&file[..length]).is_err(), "{} bytes", length);
+            }
+        }
+    }
+
+    #[test]
+    fn oversized_headers_are_errors() {
+        for file in [
+            ktx2(u32::MAX, u32::MAX, u32::MAX, &[]),
+            ktx2(8, 8, 5, &chain()),
+            ktx2(0, 8, 1, &chain()[..1]),
+            ktx2(u32::MAX, u32::MAX, 1, &chain()[..1]),
+            dds(u32::MAX, u32::MAX, u32::MAX, &[]),
+            dds(8, 8, 5, &chain()),
+            dds(u32::MAX, u32::MAX, 1, &chain()),
+        ] {
+            assert!(matches!(
+                from_bytes(&file),
+                Err(TextureFileError::Invalid(_))
+            ));
+        }
+        // A level index pointing past the end of the file.
+        let mut file = ktx2(8, 8, 1, &chain()[..1]);
+        file[80..88].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(from_bytes(&file).is_err());
+    }
+}
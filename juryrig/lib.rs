@@ -1 +1,26 @@
+//! A small Vulkan game engine.
+//!
+//! `vulkan::Vulkan` is the renderer: create it from a winit window, register textures, meshes
+//! and materials with it, add entities or instances and call `swap_framebuffers` once per frame.
+//! `app::run` does the window and event loop side for a `JuryrigApp`, and `input` turns winit's
+//! keyboard and mouse events into state and named actions. `timestep` runs the simulation in
+//! fixed steps and interpolates what's drawn between them.
+//! `use juryrig::prelude::*` brings in the types most applications need. `jr_image` and
+//! `jr_mesh` hold the CPU side image and mesh formats, `jr_texture` reads block compressed KTX2
+//! and DDS textures, `jr_gltf` loads glTF scenes, and `alloc_telemetry` the per-subsystem
+//! allocation counts. `ffi`, behind the feature of the same name, is a C ABI for embedding the
+//! renderer in other languages. The example app in this repository shows everything wired
+//! together.
 
+pub mod alloc_telemetry;
+pub mod app;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod input;
+pub mod jr_gltf;
+pub mod jr_image;
+pub mod jr_mesh;
+pub mod jr_texture;
+pub mod prelude;
+pub mod timestep;
+pub mod vulkan;
@@ -0,0 +1,19 @@
+//! The types and traits most applications touch, so `use juryrig::prelude::*` stands in for a long
+//! list of imports. Rarer pieces (compute, display queries, vertex layouts) stay in `vulkan`.
+
+pub use na::{Matrix4, Vector2, Vector3, Vector4};
+
+pub use crate::app::{run, AppContext, JuryrigApp};
+pub use crate::input::{AxisBinding, Binding, GamepadAxis, GamepadButton, Input};
+pub use crate::jr_image::{HDRImage, HDRPixel, RGBAImage, RGBAPixel};
+pub use crate::jr_mesh::{MeshData, MeshVertex};
+pub use crate::timestep::{FixedTimestep, Interpolated};
+pub use crate::vulkan::{
+    Animatable, AnimationHandle, AssetHandle, AssetKind, Atlas, BlendMode, Camera,
+    CameraController, CullMode, DebugView, DepthState, Easing, EngineEvent, Entity, EntityId, Font,
+    FrameTiming, InitError, InstanceData, InstanceSlot, Light, LightProbeGrid, LightingSettings,
+    Material, MaterialDesc, MaterialHandle, MaterialParam, MeshHandle, Model, NodeId,
+    PipelineHandle, PostEffect, Registrar, RenderMiddleware, RenderState, RenderTarget,
+    RendererConfig, Repeat, RuntimeError, SamplerDesc, Scene, SceneNode, Sky, Specular,
+    SpriteHandle, TestPattern, TextureHandle, TextureRegion, Tween, UvRect, Vulkan, Wind, WindSway,
+};
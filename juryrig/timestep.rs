@@ -0,0 +1,176 @@
+//! Fixed size simulation steps decoupled from the frame rate. The simulation advances in whole
+//! `step`s however long frames take, and frames draw state interpolated between the last two
+//! steps, so behaviour is the same at 30 and 240 frames a second. `app::run` does this for a
+//! `JuryrigApp` that asks for a `fixed_timestep`.
+
+use std::time::Duration;
+
+use na::{Matrix4, Vector2, Vector3, Vector4};
+
+/// Counts how many steps each frame owes the simulation.
+pub struct FixedTimestep {
+    step: Duration,
+    // Time not yet simulated, less than a step after `advance`.
+    accumulator: Duration,
+    /// Steps one frame may run at most. Past it, the simulation falls behind rather than taking
+    /// longer each frame to catch up, e.g. after a stall or a breakpoint.
+    pub max_steps: u32,
+}
+
+impl FixedTimestep {
+    pub fn new(step: Duration) -> FixedTimestep {
+        FixedTimestep {
+            step,
+            accumulator: Duration::ZERO,
+            max_steps: 8,
+        }
+    }
+
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    /// Adds a frame's `elapsed` time and returns how many steps to run for it.
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        if self.step.is_zero() {
+            return 0;
+        }
+        self.accumulator += elapsed;
+        let mut steps = 0;
+        while self.accumulator >= self.step {
+            if steps == self.max_steps {
+                // Drops the time that can't be caught up on.
+                self.accumulator = Duration::ZERO;
+                break;
+            }
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+        steps
+    }
+
+    /// How far the frame is between the last step and the next, from 0 to 1, for
+    /// `Interpolated::get`.
+    pub fn alpha(&self) -> f32 {
+        if self.step.is_zero() {
+            return 1.0;
+        }
+        (self.accumulator.as_secs_f32() / self.step.as_secs_f32()).min(1.0)
+    }
+}
+
+/// Values that can be blended, `t` goes from 0 for `self` to 1 for `other`.
+pub trait Lerp {
+    fn lerp(&self, other: &Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Lerp for Vector2<f32> {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Vector2::lerp(self, other, t)
+    }
+}
+
+impl Lerp for Vector3<f32> {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Vector3::lerp(self, other, t)
+    }
+}
+
+impl Lerp for Vector4<f32> {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Vector4::lerp(self, other, t)
+    }
+}
+
+// Element by element, fine between the close transforms of neighbouring steps but not for large
+// rotations, where scale would dip halfway. Interpolate positions and angles for those instead.
+impl Lerp for Matrix4<f32> {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+/// A piece of simulation state with its value from the step before, to draw in between.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Interpolated<T> {
+    previous: T,
+    current: T,
+}
+
+impl<T: Lerp + Clone> Interpolated<T> {
+    /// Starts with nothing to interpolate from.
+    pub fn new(value: T) -> Interpolated<T> {
+        Interpolated {
+            previous: value.clone(),
+            current: value,
+        }
+    }
+
+    /// The value after this step, the current one becomes the previous.
+    pub fn set(&mut self, value: T) {
+        self.previous = std::mem::replace(&mut self.current, value);
+    }
+
+    /// Moves without interpolating, e.g. for a teleport.
+    pub fn reset(&mut self, value: T) {
+        self.previous = value.clone();
+        self.current = value;
+    }
+
+    pub fn current(&self) -> &T {
+        &self.current
+    }
+
+    /// The value to draw, `alpha` is `FixedTimestep::alpha`.
+    pub fn get(&self, alpha: f32) -> T {
+        self.previous.lerp(&self.current, alpha)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn advance_runs_whole_steps_and_keeps_the_rest() {
+        let mut timestep = FixedTimestep::new(STEP);
+        assert_eq!(timestep.advance(Duration::from_millis(25)), 2);
+        assert!((timestep.alpha() - 0.5).abs() < 1e-4);
+        // The leftover 5ms counts towards the next frame.
+        assert_eq!(timestep.advance(Duration::from_millis(5)), 1);
+        assert_eq!(timestep.alpha(), 0.0);
+    }
+
+    #[test]
+    fn advance_stops_at_max_steps_and_drops_the_backlog() {
+        let mut timestep = FixedTimestep::new(STEP);
+        timestep.max_steps = 3;
+        assert_eq!(timestep.advance(Duration::from_secs(1)), 3);
+        assert_eq!(timestep.alpha(), 0.0);
+        assert_eq!(timestep.advance(Duration::from_millis(10)), 1);
+    }
+
+    #[test]
+    fn zero_step_never_runs() {
+        let mut timestep = FixedTimestep::new(Duration::ZERO);
+        assert_eq!(timestep.advance(Duration::from_secs(1)), 0);
+        assert_eq!(timestep.alpha(), 1.0);
+    }
+
+    #[test]
+    fn interpolated_blends_between_the_last_two_values() {
+        let mut value = Interpolated::new(0.0f32);
+        value.set(10.0);
+        assert_eq!(value.get(0.25), 2.5);
+        value.reset(4.0);
+        assert_eq!(value.get(0.5), 4.0);
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::{HashMap, HashSet};
+
+use uuid::Uuid;
+
+use super::Vulkan;
+
+/// A value that can be blended between two endpoints by a tween.
+pub trait Animatable: Copy {
+    fn lerp(from: Self, to: Self, t: f32) -> Self;
+}
+
+impl Animatable for f32 {
+    fn lerp(from: Self, to: Self, t: f32) -> Self {
+        from + (to - from) * t
+    }
+}
+
+impl Animatable for na::Vector2<f32> {
+    fn lerp(from: Self, to: Self, t: f32) -> Self {
+        from.lerp(&to, t)
+    }
+}
+
+impl Animatable for na::Vector3<f32> {
+    fn lerp(from: Self, to: Self, t: f32) -> Self {
+        from.lerp(&to, t)
+    }
+}
+
+impl Animatable for na::Vector4<f32> {
+    fn lerp(from: Self, to: Self, t: f32) -> Self {
+        from.lerp(&to, t)
+    }
+}
+
+impl Animatable for na::UnitQuaternion<f32> {
+    fn lerp(from: Self, to: Self, t: f32) -> Self {
+        from.slerp(&to, t)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    fn apply(&self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Repeat {
+    /// Play once and hold the final value.
+    Once,
+    /// Jump back to the start every time the end is reached.
+    Loop,
+    /// Alternate between playing forwards and backwards.
+    PingPong,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Tween<T: Animatable> {
+    pub from: T,
+    pub to: T,
+    /// Length of one pass in seconds.
+    pub duration: f32,
+    pub easing: Easing,
+    pub repeat: Repeat,
+}
+
+impl<T: Animatable> Tween<T> {
+    pub fn new(from: T, to: T, duration: f32) -> Tween<T> {
+        Tween {
+            from,
+            to,
+            duration,
+            easing: Easing::Linear,
+            repeat: Repeat::Once,
+        }
+    }
+
+    pub fn with_easing(mut self, easing: Easing) -> Tween<T> {
+        self.easing = easing;
+        self
+    }
+
+    pub fn with_repeat(mut self, repeat: Repeat) -> Tween<T> {
+        self.repeat = repeat;
+        self
+    }
+
+    /// The value of the tween `elapsed` seconds after it started.
+    pub fn sample(&self, elapsed: f32) -> T {
+        if self.duration <= 0.0 {
+            return self.to;
+        }
+        let passes = elapsed.max(0.0) / self.duration;
+        let t = match self.repeat {
+            Repeat::Once => passes.min(1.0),
+            Repeat::Loop => passes.fract(),
+            Repeat::PingPong => {
+                let t = passes % 2.0;
+                if t > 1.0 {
+                    2.0 - t
+                } else {
+                    t
+                }
+            }
+        };
+        T::lerp(self.from, self.to, self.easing.apply(t))
+    }
+
+    pub fn finished(&self, elapsed: f32) -> bool {
+        match self.repeat {
+            Repeat::Once => elapsed >= self.duration,
+            Repeat::Loop | Repeat::PingPong => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AnimationHandle {
+    id: Uuid,
+}
+
+trait Track {
+    // Applies the track for the given engine time, returns true once the track has finished.
+    fn update(&mut self, vulkan: &mut Vulkan, time: f32) -> bool;
+}
+
+struct PropertyTrack<T: Animatable, F: FnMut(&mut Vulkan, T)> {
+    tween: Tween<T>,
+    start: f32,
+    apply: F,
+}
+
+impl<T: Animatable, F: FnMut(&mut Vulkan, T)> Track for PropertyTrack<T, F> {
+    fn update(&mut self, vulkan: &mut Vulkan, time: f32) -> bool {
+        let elapsed = time - self.start;
+        (self.apply)(vulkan, self.tween.sample(elapsed));
+        self.tween.finished(elapsed)
+    }
+}
+
+#[derive(Default)]
+pub(super) struct Animator {
+    tracks: HashMap<Uuid, Box<dyn Track>>,
+    // While `update` has the tracks out, the ids of the ones it's running, and those of them
+    // stopped from a track's callback to be removed once they're back.
+    running: HashSet<Uuid>,
+    pending_removals: Vec<Uuid>,
+}
+
+impl Animator {
+    pub(super) fn add<T, F>(&mut self, tween: Tween<T>, start: f32, apply: F) -> AnimationHandle
+    where
+        T: Animatable + 'static,
+        F: FnMut(&mut Vulkan, T) + 'static,
+    {
+        let id = Uuid::new_v4();
+        self.tracks.insert(
+            id,
+            Box::new(PropertyTrack {
+                tween,
+                start,
+                apply,
+            }),
+        );
+        AnimationHandle { id }
+    }
+
+    pub(super) fn remove(&mut self, handle: &AnimationHandle) -> bool {
+        if self.tracks.remove(&handle.id).is_some() {
+            return true;
+        }
+        let running = self.running.remove(&handle.id);
+        if running {
+            self.pending_removals.push(handle.id);
+        }
+        running
+    }
+
+    pub(super) fn contains(&self, handle: &AnimationHandle) -> bool {
+        self.tracks.contains_key(&handle.id) || self.running.contains(&handle.id)
+    }
+
+    // Runs every track against the renderer. The animator is taken out of the renderer for the
+    // duration so tracks are free to start and stop animations, the fresh one left in its place
+    // keeping track of which are running.
+    pub(super) fn update(vulkan: &mut Vulkan, time: f32) {
+        let mut animator = std::mem::take(&mut vulkan.animator);
+        vulkan.animator.running = animator.tracks.keys().copied().collect();
+        animator
+            .tracks
+            .retain(|_, track| !track.update(vulkan, time));
+        let scheduled = std::mem::take(&mut vulkan.animator);
+        for id in &scheduled.pending_removals {
+            animator.tracks.remove(id);
+        }
+        animator.tracks.extend(scheduled.tracks);
+        vulkan.animator = animator;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn easings_start_at_zero_and_end_at_one() {
+        for easing in [
+            Easing::Linear,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::EaseInOut,
+        ] {
+            assert!(close(easing.apply(0.0), 0.0), "{:?}", easing);
+            assert!(close(easing.apply(1.0), 1.0), "{:?}", easing);
+        }
+        assert!(Easing::EaseIn.apply(0.5) < 0.5);
+        assert!(Easing::EaseOut.apply(0.5) > 0.5);
+        assert!(close(Easing::EaseInOut.apply(0.5), 0.5));
+    }
+
+    #[test]
+    fn once_holds_the_final_value() {
+        let tween = Tween::new(2.0, 4.0, 2.0);
+        assert!(close(tween.sample(-1.0), 2.0));
+        assert!(close(tween.sample(0.0), 2.0));
+        assert!(close(tween.sample(1.0), 3.0));
+        assert!(close(tween.sample(2.0), 4.0));
+        assert!(close(tween.sample(10.0), 4.0));
+        assert!(!tween.finished(1.9));
+        assert!(tween.finished(2.0));
+    }
+
+    #[test]
+    fn loop_jumps_back_to_the_start() {
+        let tween = Tween::new(0.0, 1.0, 1.0).with_repeat(Repeat::Loop);
+        assert!(close(tween.sample(0.25), 0.25));
+        assert!(close(tween.sample(1.25), 0.25));
+        assert!(close(tween.sample(7.5), 0.5));
+        assert!(!tween.finished(100.0));
+    }
+
+    #[test]
+    fn ping_pong_plays_back_the_other_way() {
+        let tween = Tween::new(0.0, 1.0, 1.0).with_repeat(Repeat::PingPong);
+        assert!(close(tween.sample(0.25), 0.25));
+        assert!(close(tween.sample(1.0), 1.0));
+        assert!(close(tween.sample(1.25), 0.75));
+        assert!(close(tween.sample(2.25), 0.25));
+        assert!(!tween.finished(100.0));
+    }
+
+    #[test]
+    fn a_zero_duration_is_already_at_the_end() {
+        let tween = Tween::new(0.0, 1.0, 0.0).with_repeat(Repeat::Loop);
+        assert!(close(tween.sample(0.0), 1.0));
+        assert!(close(tween.sample(3.0), 1.0));
+    }
+
+    #[test]
+    fn vectors_and_rotations_are_blended() {
+        let tween = Tween::new(na::Vector2::new(0.0, 2.0), na::Vector2::new(4.0, 0.0), 1.0)
+            .with_easing(Easing::EaseIn);
+        assert!((tween.sample(0.5) - na::Vector2::new(1.0, 1.5)).norm() < 1e-5);
+
+        let axis = na::Vector3::y_axis();
+        let tween = Tween::new(
+            na::UnitQuaternion::identity(),
+            na::UnitQuaternion::from_axis_angle(&axis, 1.0),
+            2.0,
+        );
+        assert!(close(tween.sample(1.0).angle(), 0.5));
+    }
+}
@@ -0,0 +1,250 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+// Watched files are checked for changes at most this often.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Returned by `Vulkan::add_asset_dependency` for a dependency that would make a file depend on
+/// itself. The edge isn't added.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DependencyCycle {
+    /// The files around the cycle, each depending on the next, starting and ending with the
+    /// dependent that was being added.
+    pub cycle: Vec<PathBuf>,
+}
+
+struct WatchedFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    // Indices of the files this one is built from, and of those built from it.
+    dependencies: Vec<usize>,
+    dependents: Vec<usize>,
+}
+
+// The files loaded by path and what each is built from, e.g. a scene from meshes, a mesh from a
+// material and a material from textures. Polled like `ShaderWatcher`, a changed file is reloaded
+// along with everything built from it, and nothing else. Edges that would close a cycle are
+// refused, so there is always an order to reload in.
+pub(super) struct AssetGraph {
+    files: Vec<WatchedFile>,
+    indices: HashMap<PathBuf, usize>,
+    last_poll: Instant,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+impl AssetGraph {
+    pub(super) fn new() -> AssetGraph {
+        AssetGraph {
+            files: vec![],
+            indices: HashMap::new(),
+            last_poll: Instant::now(),
+        }
+    }
+
+    // Starts watching `path` if it isn't already. Paths are compared as given.
+    pub(super) fn watch(&mut self, path: &Path) -> usize {
+        if let Some(&index) = self.indices.get(path) {
+            return index;
+        }
+        let index = self.files.len();
+        self.files.push(WatchedFile {
+            path: path.to_path_buf(),
+            modified: modified(path),
+            dependencies: vec![],
+            dependents: vec![],
+        });
+        self.indices.insert(path.to_path_buf(), index);
+        index
+    }
+
+    // Watches both and reloads `dependent` whenever `dependency` changes.
+    pub(super) fn depend(
+        &mut self,
+        dependent: &Path,
+        dependency: &Path,
+    ) -> Result<(), DependencyCycle> {
+        let dependent = self.watch(dependent);
+        let dependency = self.watch(dependency);
+        if self.files[dependent].dependencies.contains(&dependency) {
+            return Ok(());
+        }
+        if let Some(path) = self.dependency_path(dependency, dependent) {
+            let mut cycle = vec![self.files[dependent].path.clone()];
+            cycle.extend(path.into_iter().map(|index| self.files[index].path.clone()));
+            return Err(DependencyCycle { cycle });
+        }
+        self.files[dependent].dependencies.push(dependency);
+        self.files[dependency].dependents.push(dependent);
+        Ok(())
+    }
+
+    // The files from `from` to `to` following dependencies, both ends included, None if `from`
+    // isn't built from `to`.
+    fn dependency_path(&self, from: usize, to: usize) -> Option<Vec<usize>> {
+        // Where each file was first reached from.
+        let mut reached_from = vec![None; self.files.len()];
+        reached_from[from] = Some(from);
+        let mut stack = vec![from];
+        while let Some(index) = stack.pop() {
+            if index == to {
+                let mut path = vec![to];
+                while let Some(&last) = path.last() {
+                    match reached_from[last] {
+                        Some(previous) if last != from => path.push(previous),
+                        _ => break,
+                    }
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for &dependency in &self.files[index].dependencies {
+                if reached_from[dependency].is_none() {
+                    reached_from[dependency] = Some(index);
+                    stack.push(dependency);
+                }
+            }
+        }
+        None
+    }
+
+    // The watched files that changed since the last poll and everything built from them, each
+    // once and after everything it's built from. Files that can't be read, e.g. because an
+    // editor is halfway through saving them, count as unchanged.
+    pub(super) fn changed(&mut self) -> Vec<PathBuf> {
+        if self.files.is_empty() || self.last_poll.elapsed() < POLL_INTERVAL {
+            return vec![];
+        }
+        self.last_poll = Instant::now();
+        let mut changed = vec![];
+        for (index, file) in self.files.iter_mut().enumerate() {
+            let modified = modified(&file.path);
+            if modified.is_some() && modified != file.modified {
+                file.modified = modified;
+                changed.push(index);
+            }
+        }
+        self.reload_order(&changed)
+    }
+
+    fn reload_order(&self, changed: &[usize]) -> Vec<PathBuf> {
+        let mut affected = vec![false; self.files.len()];
+        let mut stack = changed.to_vec();
+        while let Some(index) = stack.pop() {
+            if !affected[index] {
+                affected[index] = true;
+                stack.extend(&self.files[index].dependents);
+            }
+        }
+        // How many of each file's dependencies still have to be reloaded before it.
+        let mut waiting: Vec<usize> = self
+            .files
+            .iter()
+            .map(|file| {
+                file.dependencies
+                    .iter()
+                    .filter(|&&dependency| affected[dependency])
+                    .count()
+            })
+            .collect();
+        let mut ready: VecDeque<usize> = (0..self.files.len())
+            .filter(|&index| affected[index] && waiting[index] == 0)
+            .collect();
+        let mut order = vec![];
+        while let Some(index) = ready.pop_front() {
+            order.push(self.files[index].path.clone());
+            for &dependent in &self.files[index].dependents {
+                waiting[dependent] -= 1;
+                if waiting[dependent] == 0 {
+                    ready.push_back(dependent);
+                }
+            }
+        }
+        order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // scene <- mesh <- material <- two textures, and a second mesh on its own.
+    fn graph() -> AssetGraph {
+        let mut graph = AssetGraph::new();
+        graph.depend(Path::new("scene"), Path::new("mesh")).unwrap();
+        graph
+            .depend(Path::new("mesh"), Path::new("material"))
+            .unwrap();
+        graph
+            .depend(Path::new("material"), Path::new("albedo"))
+            .unwrap();
+        graph
+            .depend(Path::new("material"), Path::new("normal"))
+            .unwrap();
+        graph.watch(Path::new("other mesh"));
+        graph
+    }
+
+    fn reload(graph: &AssetGraph, changed: &[&str]) -> Vec<PathBuf> {
+        let changed: Vec<usize> = changed
+            .iter()
+            .map(|path| graph.indices[Path::new(path)])
+            .collect();
+        graph.reload_order(&changed)
+    }
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn only_dependents_reload_after_their_dependencies() {
+        let graph = graph();
+        assert_eq!(
+            reload(&graph, &["albedo"]),
+            paths(&["albedo", "material", "mesh", "scene"])
+        );
+        assert_eq!(reload(&graph, &["mesh"]), paths(&["mesh", "scene"]));
+        assert_eq!(reload(&graph, &["other mesh"]), paths(&["other mesh"]));
+    }
+
+    #[test]
+    fn shared_dependents_reload_once() {
+        let graph = graph();
+        assert_eq!(
+            reload(&graph, &["normal", "albedo", "mesh"]),
+            paths(&["albedo", "normal", "material", "mesh", "scene"])
+        );
+    }
+
+    #[test]
+    fn cycles_are_refused() {
+        let mut graph = graph();
+        assert_eq!(
+            graph.depend(Path::new("albedo"), Path::new("scene")),
+            Err(DependencyCycle {
+                cycle: paths(&["albedo", "scene", "mesh", "material", "albedo"]),
+            })
+        );
+        assert_eq!(
+            graph.depend(Path::new("scene"), Path::new("scene")),
+            Err(DependencyCycle {
+                cycle: paths(&["scene", "scene"]),
+            })
+        );
+        // Neither edge was added.
+        assert_eq!(reload(&graph, &["scene"]), paths(&["scene"]));
+        // The same edge twice isn't a cycle.
+        assert_eq!(
+            graph.depend(Path::new("mesh"), Path::new("material")),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,500 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex, Weak},
+    thread,
+    time::Duration,
+};
+
+use log::warn;
+
+use crate::jr_image::{self, RGBAImage};
+use crate::jr_mesh::{self, MeshData};
+use crate::jr_texture::{self, CompressedImage};
+
+use super::{mesh::MeshHandle, texture::TextureHandle};
+
+// Decoding is mostly waiting on the disk and the allocator past this many threads.
+const MAX_WORKERS: usize = 4;
+
+enum Job {
+    Image {
+        path: PathBuf,
+        flip_vertically: bool,
+    },
+    Mesh(PathBuf),
+}
+
+enum Decoded {
+    Image(Result<LoadedImage, String>),
+    Mesh(Result<MeshData, String>),
+}
+
+pub(super) enum AssetState<T> {
+    // Being read and decoded on a worker thread.
+    Loading,
+    // Registered with the renderer, a texture's upload is still running.
+    Uploading(T),
+    Ready(T),
+    Failed(String),
+}
+
+pub(super) type Slot<T> = Arc<Mutex<AssetState<T>>>;
+
+// A file loaded by path, for loading it again into the same handles when it changes.
+enum Reloadable {
+    Texture(Weak<Mutex<AssetState<TextureHandle>>>, bool),
+    Mesh(Weak<Mutex<AssetState<MeshHandle>>>),
+}
+
+// KTX2 and DDS files stay block compressed, everything else is decoded to RGBA.
+pub(super) enum LoadedImage {
+    Rgba(RGBAImage),
+    Compressed(CompressedImage),
+}
+
+/// A texture or mesh from `Vulkan::load_texture`, `Vulkan::load_mesh` or a `Registrar`, filled in
+/// by a later `swap_framebuffers` once it has been decoded and uploaded. Clones share the same
+/// load, and can be sent to other threads.
+pub struct AssetHandle<T> {
+    state: Slot<T>,
+}
+
+impl<T> Clone for AssetHandle<T> {
+    fn clone(&self) -> Self {
+        AssetHandle {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<T: Clone> AssetHandle<T> {
+    pub fn is_ready(&self) -> bool {
+        matches!(&*self.state.lock().unwrap(), AssetState::Ready(_))
+    }
+
+    /// None until `is_ready`, and forever if the load failed.
+    pub fn get(&self) -> Option<T> {
+        match &*self.state.lock().unwrap() {
+            AssetState::Ready(asset) => Some(asset.clone()),
+            _ => None,
+        }
+    }
+
+    /// Why the file couldn't be read, decoded or uploaded.
+    pub fn error(&self) -> Option<String> {
+        match &*self.state.lock().unwrap() {
+            AssetState::Failed(error) => Some(error.clone()),
+            _ => None,
+        }
+    }
+}
+
+// What the workers finished, or other threads handed over, for the renderer to register.
+pub(super) enum Finished {
+    Texture(Slot<TextureHandle>, Result<LoadedImage, String>),
+    Mesh(Slot<MeshHandle>, Result<MeshData, String>),
+}
+
+impl Finished {
+    // Roughly what registering it copies to the GPU, nothing for a failed load.
+    pub(super) fn bytes(&self) -> u64 {
+        let bytes = match self {
+            Finished::Texture(_, Ok(LoadedImage::Rgba(image))) => {
+                std::mem::size_of_val(&image.data[..])
+            }
+            Finished::Texture(_, Ok(LoadedImage::Compressed(image))) => {
+                image.levels.iter().map(Vec::len).sum()
+            }
+            Finished::Mesh(_, Ok(mesh)) => {
+                std::mem::size_of_val(&mesh.vertices[..]) + std::mem::size_of_val(&mesh.indices[..])
+            }
+            Finished::Texture(_, Err(_)) | Finished::Mesh(_, Err(_)) => 0,
+        };
+        bytes as u64
+    }
+
+    // Whether anything still holds a handle to it.
+    fn is_wanted(&self) -> bool {
+        match self {
+            Finished::Texture(slot, _) => Arc::strong_count(slot) > 1,
+            Finished::Mesh(slot, _) => Arc::strong_count(slot) > 1,
+        }
+    }
+}
+
+/// How much of what `Vulkan::load_texture`, `Vulkan::load_mesh` and `Registrar`s hand over is
+/// uploaded per frame, see `RendererConfig::uploads`. The rest waits for the next frame, so a
+/// burst of streamed assets is spread out instead of stalling one frame. At least one upload is
+/// started every frame however big it is. Registering directly, e.g. with
+/// `Vulkan::register_texture`, isn't limited.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UploadBudget {
+    /// Bytes staged for the GPU per frame.
+    pub bytes: Option<u64>,
+    /// Time spent on the CPU registering uploads per frame.
+    pub time: Option<Duration>,
+}
+
+impl Default for UploadBudget {
+    // Half the staging ring, so a frame's uploads don't need staging buffers of their own.
+    fn default() -> Self {
+        UploadBudget {
+            bytes: Some(16 * 1024 * 1024),
+            time: Some(Duration::from_millis(2)),
+        }
+    }
+}
+
+impl UploadBudget {
+    // Whether an upload of `bytes` fits after what `stats` has already spent this frame.
+    pub(super) fn allows(&self, stats: &UploadStats, bytes: u64) -> bool {
+        if stats.uploads == 0 {
+            return true;
+        }
+        let bytes_fit = self
+            .bytes
+            .is_none_or(|budget| stats.bytes + bytes <= budget);
+        let time_left = self.time.is_none_or(|budget| stats.time < budget);
+        bytes_fit && time_left
+    }
+}
+
+/// The streamed uploads started in a frame, see `UploadBudget` and `FrameStats::uploads`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UploadStats {
+    /// Textures and meshes registered.
+    pub uploads: u32,
+    pub bytes: u64,
+    /// Time spent registering them on the CPU.
+    pub time: Duration,
+    /// Finished loads left for later frames by the budget.
+    pub deferred: u32,
+}
+
+/// Hands CPU side textures and meshes made on any thread to the render thread, which registers
+/// them in its next `swap_framebuffers`. From `Vulkan::registrar`, clones share the same queue.
+#[derive(Clone)]
+pub struct Registrar {
+    sender: mpsc::Sender<Registration>,
+}
+
+enum Registration {
+    Texture(Slot<TextureHandle>, RGBAImage),
+    Mesh(Slot<MeshHandle>, MeshData),
+}
+
+impl Registrar {
+    /// Like `Vulkan::register_texture`, the handle is ready once the upload has finished.
+    pub fn register_texture(&self, image: RGBAImage) -> AssetHandle<TextureHandle> {
+        let state = Arc::new(Mutex::new(AssetState::Loading));
+        self.send(Registration::Texture(state.clone(), image));
+        AssetHandle { state }
+    }
+
+    pub fn register_mesh(&self, mesh: MeshData) -> AssetHandle<MeshHandle> {
+        let state = Arc::new(Mutex::new(AssetState::Loading));
+        self.send(Registration::Mesh(state.clone(), mesh));
+        AssetHandle { state }
+    }
+
+    fn send(&self, registration: Registration) {
+        // Only fails once the renderer is gone, the handle then never becomes ready.
+        let _ = self.sender.send(registration);
+    }
+}
+
+// Reads and decodes files on a small pool of worker threads, started by the first load. The
+// renderer itself stays on its thread, so workers only ever see paths and hand back CPU side
+// data.
+#[derive(Default)]
+pub(super) struct AssetLoader {
+    pool: Option<Pool>,
+    // Started by the first `registrar` call.
+    registrations: Option<(mpsc::Sender<Registration>, mpsc::Receiver<Registration>)>,
+    next_job: u64,
+    textures: HashMap<u64, Slot<TextureHandle>>,
+    meshes: HashMap<u64, Slot<MeshHandle>>,
+    // Registered textures waiting on their transfer.
+    uploading: Vec<Slot<TextureHandle>>,
+    // Decoded or handed over, waiting for a frame with room in its `UploadBudget`.
+    queued: VecDeque<Finished>,
+    reloadable: HashMap<PathBuf, Vec<Reloadable>>,
+}
+
+struct Pool {
+    jobs: mpsc::Sender<(u64, Job)>,
+    results: mpsc::Receiver<(u64, Decoded)>,
+}
+
+impl Pool {
+    fn start() -> Pool {
+        let (jobs, job_receiver) = mpsc::channel::<(u64, Job)>();
+        let (result_sender, results) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let workers = thread::available_parallelism()
+            .map_or(1, |count| count.get())
+            .min(MAX_WORKERS);
+        for index in 0..workers {
+            let job_receiver = job_receiver.clone();
+            let result_sender = result_sender.clone();
+            let spawned = thread::Builder::new()
+                .name(format!("juryrig asset loader {}", index))
+                .spawn(move || loop {
+                    // The lock is only held while waiting, never while decoding.
+                    let job = job_receiver.lock().unwrap().recv();
+                    // Gone once the loader is dropped.
+                    let Ok((id, job)) = job else { break };
+                    let decoded = match job {
+                        Job::Image { path, .. } if jr_texture::is_texture_file(&path) => {
+                            Decoded::Image(
+                                jr_texture::load(&path)
+                                    .map(LoadedImage::Compressed)
+                                    .map_err(|e| format!("{}: {:?}", path.display(), e)),
+                            )
+                        }
+                        Job::Image {
+                            path,
+                            flip_vertically,
+                        } => Decoded::Image(
+                            jr_image::load(&path, flip_vertically)
+                                .map(LoadedImage::Rgba)
+                                .map_err(|e| format!("{}: {}", path.display(), e)),
+                        ),
+                        Job::Mesh(path) => Decoded::Mesh(
+                            jr_mesh::load(&path)
+                                .map_err(|e| format!("{}: {:?}", path.display(), e)),
+                        ),
+                    };
+                    if result_sender.send((id, decoded)).is_err() {
+                        break;
+                    }
+                });
+            if let Err(e) = spawned {
+                warn!("Could not start asset loader thread. {}", e);
+            }
+        }
+        Pool { jobs, results }
+    }
+}
+
+impl AssetLoader {
+    pub(super) fn load_texture(
+        &mut self,
+        path: PathBuf,
+        flip_vertically: bool,
+    ) -> AssetHandle<TextureHandle> {
+        let state = Arc::new(Mutex::new(AssetState::Loading));
+        self.reloadable
+            .entry(path.clone())
+            .or_default()
+            .push(Reloadable::Texture(Arc::downgrade(&state), flip_vertically));
+        let id = self.queue(Job::Image {
+            path,
+            flip_vertically,
+        });
+        self.textures.insert(id, state.clone());
+        AssetHandle { state }
+    }
+
+    pub(super) fn load_mesh(&mut self, path: PathBuf) -> AssetHandle<MeshHandle> {
+        let state = Arc::new(Mutex::new(AssetState::Loading));
+        self.reloadable
+            .entry(path.clone())
+            .or_default()
+            .push(Reloadable::Mesh(Arc::downgrade(&state)));
+        let id = self.queue(Job::Mesh(path));
+        self.meshes.insert(id, state.clone());
+        AssetHandle { state }
+    }
+
+    // Loads `path` again into every handle still held from loading it before. The handles read as
+    // loading until the new texture or mesh is registered.
+    pub(super) fn reload(&mut self, path: &Path) {
+        let Some(reloadable) = self.reloadable.remove(path) else {
+            return;
+        };
+        let mut kept = vec![];
+        for reloadable in reloadable {
+            match &reloadable {
+                Reloadable::Texture(slot, flip_vertically) => {
+                    let Some(slot) = slot.upgrade() else { continue };
+                    *slot.lock().unwrap() = AssetState::Loading;
+                    let id = self.queue(Job::Image {
+                        path: path.to_path_buf(),
+                        flip_vertically: *flip_vertically,
+                    });
+                    self.textures.insert(id, slot);
+                }
+                Reloadable::Mesh(slot) => {
+                    let Some(slot) = slot.upgrade() else { continue };
+                    *slot.lock().unwrap() = AssetState::Loading;
+                    let id = self.queue(Job::Mesh(path.to_path_buf()));
+                    self.meshes.insert(id, slot);
+                }
+            }
+            kept.push(reloadable);
+        }
+        if !kept.is_empty() {
+            self.reloadable.insert(path.to_path_buf(), kept);
+        }
+    }
+
+    pub(super) fn registrar(&mut self) -> Registrar {
+        let (sender, _) = self.registrations.get_or_insert_with(mpsc::channel);
+        Registrar {
+            sender: sender.clone(),
+        }
+    }
+
+    fn queue(&mut self, job: Job) -> u64 {
+        let id = self.next_job;
+        self.next_job += 1;
+        // Only fails if every worker panicked, the load then stays pending.
+        let _ = self
+            .pool
+            .get_or_insert_with(Pool::start)
+            .jobs
+            .send((id, job));
+        id
+    }
+
+    // The oldest finished load if it fits in `budget` after `stats`. Loads whose handles were all
+    // dropped are thrown away here rather than registered.
+    pub(super) fn next_upload(
+        &mut self,
+        budget: &UploadBudget,
+        stats: &UploadStats,
+    ) -> Option<Finished> {
+        self.receive();
+        while let Some(next) = self.queued.front() {
+            if !next.is_wanted() {
+                self.queued.pop_front();
+            } else if budget.allows(stats, next.bytes()) {
+                return self.queued.pop_front();
+            } else {
+                return None;
+            }
+        }
+        None
+    }
+
+    // How many finished loads are waiting for a later frame.
+    pub(super) fn queued(&self) -> usize {
+        self.queued.len()
+    }
+
+    // Queues what the workers finished, or other threads handed over, since the last call.
+    fn receive(&mut self) {
+        let queued = &mut self.queued;
+        if let Some((_, receiver)) = &self.registrations {
+            for registration in receiver.try_iter() {
+                match registration {
+                    Registration::Texture(slot, image) => {
+                        queued.push_back(Finished::Texture(slot, Ok(LoadedImage::Rgba(image))));
+                    }
+                    Registration::Mesh(slot, mesh) => {
+                        queued.push_back(Finished::Mesh(slot, Ok(mesh)));
+                    }
+                }
+            }
+        }
+        let Some(pool) = &self.pool else {
+            return;
+        };
+        for (id, decoded) in pool.results.try_iter() {
+            match decoded {
+                Decoded::Image(image) => {
+                    if let Some(slot) = self.textures.remove(&id) {
+                        queued.push_back(Finished::Texture(slot, image));
+                    }
+                }
+                Decoded::Mesh(mesh) => {
+                    if let Some(slot) = self.meshes.remove(&id) {
+                        queued.push_back(Finished::Mesh(slot, mesh));
+                    }
+                }
+            }
+        }
+    }
+
+    pub(super) fn texture_registered(
+        &mut self,
+        slot: Slot<TextureHandle>,
+        result: Result<TextureHandle, String>,
+    ) {
+        match result {
+            Ok(handle) => {
+                *slot.lock().unwrap() = AssetState::Uploading(handle);
+                self.uploading.push(slot);
+            }
+            Err(error) => *slot.lock().unwrap() = AssetState::Failed(error),
+        }
+    }
+
+    pub(super) fn mesh_registered(slot: Slot<MeshHandle>, result: Result<MeshHandle, String>) {
+        *slot.lock().unwrap() = match result {
+            Ok(handle) => AssetState::Ready(handle),
+            Err(error) => AssetState::Failed(error),
+        };
+    }
+
+    // Marks textures ready once `is_ready` says their upload has finished.
+    pub(super) fn check_uploads(&mut self, is_ready: impl Fn(&TextureHandle) -> bool) {
+        self.uploading.retain(|slot| {
+            let mut state = slot.lock().unwrap();
+            match &*state {
+                AssetState::Uploading(handle) if is_ready(handle) => {
+                    *state = AssetState::Ready(handle.clone());
+                    false
+                }
+                AssetState::Uploading(_) => true,
+                _ => false,
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spent(uploads: u32, bytes: u64, millis: u64) -> UploadStats {
+        UploadStats {
+            uploads,
+            bytes,
+            time: Duration::from_millis(millis),
+            deferred: 0,
+        }
+    }
+
+    #[test]
+    fn first_upload_always_fits() {
+        let budget = UploadBudget {
+            bytes: Some(10),
+            time: Some(Duration::ZERO),
+        };
+        assert!(budget.allows(&spent(0, 0, 0), 1000));
+        assert!(!budget.allows(&spent(1, 0, 0), 1));
+    }
+
+    #[test]
+    fn bytes_and_time_both_limit() {
+        let budget = UploadBudget {
+            bytes: Some(100),
+            time: Some(Duration::from_millis(2)),
+        };
+        assert!(budget.allows(&spent(1, 60, 1), 40));
+        assert!(!budget.allows(&spent(1, 60, 1), 41));
+        assert!(!budget.allows(&spent(1, 0, 2), 1));
+    }
+
+    #[test]
+    fn no_limits_allows_everything() {
+        let budget = UploadBudget {
+            bytes: None,
+            time: None,
+        };
+        assert!(budget.allows(&spent(1000, u64::MAX / 2, 1000), u64::MAX / 2));
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use super::{
+    error::RuntimeError,
+    texture::{TextureHandle, TextureRegion, UvRect},
+};
+
+/// One named part of an `Atlas`, the texture to draw and the `UvRect` that picks the sprite out of
+/// it. Holding it keeps the atlas texture alive.
+#[derive(Clone)]
+pub struct SpriteHandle {
+    texture: TextureHandle,
+    uv_rect: UvRect,
+}
+
+impl SpriteHandle {
+    pub fn texture(&self) -> &TextureHandle {
+        &self.texture
+    }
+
+    pub fn uv_rect(&self) -> UvRect {
+        self.uv_rect
+    }
+}
+
+/// Named rectangles of a registered texture, from `Vulkan::create_atlas`. Sprites are plain uv
+/// transforms applied per instance, so any number of them share the texture and a mesh with 0-1
+/// uvs.
+pub struct Atlas {
+    texture: TextureHandle,
+    width: u32,
+    height: u32,
+    sprites: HashMap<String, SpriteHandle>,
+}
+
+impl Atlas {
+    pub(super) fn new(texture: TextureHandle, width: u32, height: u32) -> Atlas {
+        Atlas {
+            texture,
+            width,
+            height,
+            sprites: HashMap::new(),
+        }
+    }
+
+    pub fn texture(&self) -> &TextureHandle {
+        &self.texture
+    }
+
+    /// Names `region`, counted in texels from the top left, replacing any sprite that had the
+    /// name. Like `uv_rect_for_cell` the rect is pulled in by half a texel on every side so
+    /// filtering doesn't bleed in the neighbours.
+    pub fn define(
+        &mut self,
+        name: &str,
+        region: TextureRegion,
+    ) -> Result<SpriteHandle, RuntimeError> {
+        let fits = region.width > 0
+            && region.height > 0
+            && region
+                .x
+                .checked_add(region.width)
+                .is_some_and(|x| x <= self.width)
+            && region
+                .y
+                .checked_add(region.height)
+                .is_some_and(|y| y <= self.height);
+        if !fits {
+            return Err(RuntimeError::InvalidRegion);
+        }
+        let (width, height) = (self.width as f32, self.height as f32);
+        let sprite = SpriteHandle {
+            texture: self.texture.clone(),
+            uv_rect: UvRect {
+                offset: [
+                    (region.x as f32 + 0.5) / width,
+                    (region.y as f32 + 0.5) / height,
+                ],
+                scale: [
+                    (region.width - 1) as f32 / width,
+                    (region.height - 1) as f32 / height,
+                ],
+            },
+        };
+        self.sprites.insert(name.to_owned(), sprite.clone());
+        Ok(sprite)
+    }
+
+    /// Names every cell of the atlas divided into an even `columns` x `rows` grid, `name` is
+    /// called with each cell's column and row. Texels left over past the last full cell are
+    /// skipped.
+    pub fn define_grid(
+        &mut self,
+        columns: u32,
+        rows: u32,
+        name: impl Fn(u32, u32) -> String,
+    ) -> Result<(), RuntimeError> {
+        if columns == 0 || rows == 0 || columns > self.width || rows > self.height {
+            return Err(RuntimeError::InvalidRegion);
+        }
+        let (width, height) = (self.width / columns, self.height / rows);
+        for row in 0..rows {
+            for column in 0..columns {
+                let region = TextureRegion {
+                    x: column * width,
+                    y: row * height,
+                    width,
+                    height,
+                };
+                self.define(&name(column, row), region)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn sprite(&self, name: &str) -> Option<SpriteHandle> {
+        self.sprites.get(name).cloned()
+    }
+
+    pub fn sprites(&self) -> impl Iterator<Item = (&str, &SpriteHandle)> {
+        self.sprites
+            .iter()
+            .map(|(name, sprite)| (name.as_str(), sprite))
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<SpriteHandle> {
+        self.sprites.remove(name)
+    }
+}
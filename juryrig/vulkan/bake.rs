@@ -0,0 +1,176 @@
+use std::path::Path;
+
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+use na::Vector3;
+
+use crate::jr_image::{HDRImage, HDRPixel};
+
+use super::{
+    compute::ComputeContext,
+    error::RuntimeError,
+    light_probe::{LightProbeGrid, ProbeFileError, ShProbe},
+};
+
+// Rows each workgroup of bake_sh.glsl projects.
+const ROWS_PER_GROUP: u32 = 64;
+
+/// What `bake` failed on.
+#[derive(Debug)]
+pub enum BakeError {
+    /// The environment couldn't be read.
+    Environment(image::ImageError),
+    Gpu(RuntimeError),
+    /// The baked probes couldn't be written.
+    Output(ProbeFileError),
+}
+
+impl From<RuntimeError> for BakeError {
+    fn from(value: RuntimeError) -> Self {
+        BakeError::Gpu(value)
+    }
+}
+
+/// Reads an equirectangular Radiance `.hdr` environment, projects it onto a light probe with
+/// `bake_environment` and writes a grid of that one probe to `output` for
+/// `LightProbeGrid::load`. Runs without a window, for build scripts and asset pipelines, so
+/// startup doesn't pay for the projection every launch.
+pub fn bake(
+    context: &mut ComputeContext,
+    environment: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+) -> Result<LightProbeGrid, BakeError> {
+    let environment = HDRImage::from_radiance_hdr(environment).map_err(BakeError::Environment)?;
+    let probe = bake_environment(context, &environment)?;
+    let grid = LightProbeGrid::new(Vector3::zeros(), 1.0, [1; 3], probe);
+    grid.save(output).map_err(BakeError::Output)?;
+    Ok(grid)
+}
+
+/// The diffuse light an equirectangular environment casts, laid out like
+/// `Vulkan::register_equirectangular_cubemap` takes it, projected on the GPU. Blocks until it's
+/// done.
+pub fn bake_environment(
+    context: &mut ComputeContext,
+    environment: &HDRImage,
+) -> Result<ShProbe, RuntimeError> {
+    let (width, height) = (environment.width, environment.height);
+    if width == 0 || height == 0 {
+        return Ok(ShProbe::default());
+    }
+    let mut texels = context.create_buffer::<HDRPixel>(
+        environment.data.len() as u64,
+        MemoryLocation::CpuToGpu,
+        "bake environment",
+    )?;
+    let rows = match context.create_buffer::<[f32; 4]>(
+        height as u64 * 4,
+        MemoryLocation::GpuToCpu,
+        "bake rows",
+    ) {
+        Ok(rows) => rows,
+        Err(e) => {
+            context.destroy_buffer(texels);
+            return Err(e);
+        }
+    };
+    let projected = texels.write(&environment.data).and_then(|()| {
+        let pipeline = context.create_pipeline(
+            vk_shader_macros::include_glsl!("./shaders/bake_sh.glsl", kind: comp),
+            &[vk::DescriptorType::STORAGE_BUFFER; 2],
+            std::mem::size_of::<[u32; 2]>() as u32,
+        )?;
+        let size: Vec<u8> = [width, height]
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect();
+        let dispatched = context.dispatch(
+            &pipeline,
+            &[texels.binding(), rows.binding()],
+            &size,
+            [height.div_ceil(ROWS_PER_GROUP), 1, 1],
+        );
+        context.destroy_pipeline(pipeline);
+        dispatched?;
+        let mut coefficients = vec![[0.0; 4]; height as usize * 4];
+        rows.read(&mut coefficients)?;
+        Ok(coefficients)
+    });
+    context.destroy_buffer(texels);
+    context.destroy_buffer(rows);
+    let mut probe = ShProbe::default();
+    for row in projected?.chunks_exact(4) {
+        for (coefficient, sum) in probe.coefficients.iter_mut().zip(row) {
+            for channel in 0..3 {
+                coefficient[channel] += sum[channel];
+            }
+        }
+    }
+    Ok(probe)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    // The direction texel (`x`, `y`) of a `width` x `height` equirectangular environment is seen
+    // along and the solid angle it covers, the same as bake_sh.glsl works them out.
+    fn texel_direction(x: u32, y: u32, width: u32, height: u32) -> (Vector3<f32>, f32) {
+        let latitude = (0.5 - (y as f32 + 0.5) / height as f32) * PI;
+        let longitude = ((x as f32 + 0.5) / width as f32 - 0.5) * 2.0 * PI;
+        let direction = Vector3::new(
+            latitude.cos() * longitude.sin(),
+            latitude.sin(),
+            latitude.cos() * longitude.cos(),
+        );
+        let weight = latitude.cos() * (PI / height as f32) * (2.0 * PI / width as f32);
+        (direction, weight)
+    }
+
+    // What the shader does, on the CPU.
+    fn project(width: u32, height: u32, radiance: impl Fn(Vector3<f32>) -> [f32; 3]) -> ShProbe {
+        let mut probe = ShProbe::default();
+        for y in 0..height {
+            for x in 0..width {
+                let (direction, weight) = texel_direction(x, y, width, height);
+                probe.add_sample(direction, radiance(direction), weight);
+            }
+        }
+        probe
+    }
+
+    #[test]
+    fn texels_cover_the_sphere_like_the_cubemap() {
+        let (width, height) = (64, 32);
+        let total: f32 = (0..height)
+            .flat_map(|y| (0..width).map(move |x| texel_direction(x, y, width, height).1))
+            .sum();
+        assert!((total - 4.0 * PI).abs() < 0.01, "{}", total);
+        // Centre faces +z, the top row is straight up.
+        let (centre, _) = texel_direction(width / 2, height / 2, width, height);
+        assert!(centre.z > 0.99, "{:?}", centre);
+        let (top, _) = texel_direction(0, 0, width, height);
+        assert!(top.y > 0.99, "{:?}", top);
+    }
+
+    #[test]
+    fn constant_environment_is_ambient() {
+        let probe = project(64, 32, |_| [0.5, 1.0, 2.0]);
+        let ambient = ShProbe::ambient([0.5, 1.0, 2.0]);
+        for (baked, expected) in probe.coefficients.iter().zip(&ambient.coefficients) {
+            for channel in 0..3 {
+                assert!((baked[channel] - expected[channel]).abs() < 0.02);
+            }
+        }
+    }
+
+    #[test]
+    fn bright_sky_lights_from_above() {
+        let probe = project(64, 32, |direction| [direction.y.max(0.0); 3]);
+        let [_, y, z, x] = probe.coefficients;
+        assert!(y[0] > 0.5);
+        assert!(x[0].abs() < 1e-3 && z[0].abs() < 1e-3);
+    }
+}
@@ -0,0 +1,336 @@
+use na::{Matrix4, Vector3, Vector4};
+
+/// What a mesh takes up in its own space, worked out from its vertex positions when it's
+/// registered, see `VertexPosition` and `Vulkan::mesh_bounds`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MeshBounds {
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere,
+}
+
+impl MeshBounds {
+    /// The sphere is centred on the middle of the box, so it's not the tightest sphere but never
+    /// far off. No points gives a point at the origin.
+    pub fn from_points(points: impl Iterator<Item = Vector3<f32>> + Clone) -> MeshBounds {
+        let Some(aabb) = Aabb::from_points(points.clone()) else {
+            return MeshBounds::default();
+        };
+        let centre = aabb.centre();
+        let radius = points
+            .map(|point| (point - centre).norm_squared())
+            .fold(0.0, f32::max)
+            .sqrt();
+        MeshBounds {
+            aabb,
+            sphere: BoundingSphere { centre, radius },
+        }
+    }
+}
+
+/// An axis aligned box from `min` to `max`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Aabb {
+    /// The smallest box around the points, None if there are none.
+    pub fn from_points(points: impl Iterator<Item = Vector3<f32>>) -> Option<Aabb> {
+        points.fold(None, |aabb: Option<Aabb>, point| {
+            Some(match aabb {
+                Some(aabb) => Aabb {
+                    min: aabb.min.inf(&point),
+                    max: aabb.max.sup(&point),
+                },
+                None => Aabb {
+                    min: point,
+                    max: point,
+                },
+            })
+        })
+    }
+
+    pub fn centre(&self) -> Vector3<f32> {
+        (self.min + self.max) / 2.0
+    }
+
+    /// Half the box's size along each axis.
+    pub fn half_extents(&self) -> Vector3<f32> {
+        (self.max - self.min) / 2.0
+    }
+
+    pub fn corners(&self) -> [Vector3<f32>; 8] {
+        let (min, max) = (self.min, self.max);
+        [0, 1, 2, 3, 4, 5, 6, 7].map(|corner| {
+            Vector3::new(
+                if corner & 1 == 0 { min.x } else { max.x },
+                if corner & 2 == 0 { min.y } else { max.y },
+                if corner & 4 == 0 { min.z } else { max.z },
+            )
+        })
+    }
+
+    /// The axis aligned box around this one after `transform`, larger than it once rotated.
+    pub fn transformed(&self, transform: &Matrix4<f32>) -> Aabb {
+        let corners = self
+            .corners()
+            .map(|corner| transform.transform_point(&corner.into()).coords);
+        Aabb::from_points(corners.into_iter()).unwrap_or_default()
+    }
+
+    pub fn contains(&self, point: &Vector3<f32>) -> bool {
+        (0..3).all(|axis| self.min[axis] <= point[axis] && point[axis] <= self.max[axis])
+    }
+}
+
+/// A sphere around everything in a mesh, see `MeshBounds`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BoundingSphere {
+    pub centre: Vector3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// The sphere around this one after `transform`, grown by the transform's largest scale.
+    pub fn transformed(&self, transform: &Matrix4<f32>) -> BoundingSphere {
+        let centre = transform.transform_point(&self.centre.into()).coords;
+        let scale = (0..3)
+            .map(|column| transform.column(column).xyz().norm())
+            .fold(0.0, f32::max);
+        BoundingSphere {
+            centre,
+            radius: self.radius * scale,
+        }
+    }
+}
+
+// The six planes of what a camera sees, pointing inwards.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    // Pulls the planes out of a view projection matrix with depth from 0 to 1. With an infinite
+    // far plane the far one comes out empty and never culls anything.
+    pub(super) fn from_view_projection(matrix: &Matrix4<f32>) -> Frustum {
+        let row = |index: usize| matrix.row(index).transpose();
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        let mut planes = [w + x, w - x, w + y, w - y, z, w - z];
+        for plane in &mut planes {
+            let length = plane.xyz().norm();
+            if length > f32::EPSILON {
+                *plane /= length;
+            }
+        }
+        Frustum { planes }
+    }
+
+    // False only when the sphere is wholly outside one of the planes. Spheres near a corner can
+    // pass without being seen, which just costs a draw.
+    pub(super) fn intersects(&self, sphere: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.xyz().dot(&sphere.centre) + plane.w >= -sphere.radius)
+    }
+
+    // Like `intersects`, for a box. Tighter than a sphere around something long and thin.
+    pub(super) fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane's normal.
+            let corner = Vector3::new(
+                if plane.x >= 0.0 {
+                    aabb.max.x
+                } else {
+                    aabb.min.x
+                },
+                if plane.y >= 0.0 {
+                    aabb.max.y
+                } else {
+                    aabb.min.y
+                },
+                if plane.z >= 0.0 {
+                    aabb.max.z
+                } else {
+                    aabb.min.z
+                },
+            );
+            plane.xyz().dot(&corner) + plane.w >= 0.0
+        })
+    }
+}
+
+/// A half line from `origin` along `direction`, which needn't be unit length. Distances along it
+/// are in multiples of `direction`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Vector3<f32>,
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn at(&self, distance: f32) -> Vector3<f32> {
+        self.origin + self.direction * distance
+    }
+
+    /// The ray in the space `transform` maps from, e.g. a mesh's when `transform` places it.
+    /// Distances along it stay the same. None if `transform` can't be inverted.
+    pub fn into_space(&self, transform: &Matrix4<f32>) -> Option<Ray> {
+        let inverse = transform.try_inverse()?;
+        Some(Ray {
+            origin: inverse.transform_point(&self.origin.into()).coords,
+            direction: inverse.transform_vector(&self.direction),
+        })
+    }
+
+    /// How far along the ray it enters the box, 0 if it starts inside, None if it misses.
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        let (mut near, mut far) = (0.0f32, f32::INFINITY);
+        for axis in 0..3 {
+            let origin = self.origin[axis];
+            // Parallel to this pair of faces, so either always between them or never.
+            if self.direction[axis] == 0.0 {
+                if origin < aabb.min[axis] || origin > aabb.max[axis] {
+                    return None;
+                }
+                continue;
+            }
+            let a = (aabb.min[axis] - origin) / self.direction[axis];
+            let b = (aabb.max[axis] - origin) / self.direction[axis];
+            near = near.max(a.min(b));
+            far = far.min(a.max(b));
+        }
+        (near <= far).then_some(near)
+    }
+
+    /// Like `intersect_aabb`, for a sphere.
+    pub fn intersect_sphere(&self, sphere: &BoundingSphere) -> Option<f32> {
+        let offset = self.origin - sphere.centre;
+        let a = self.direction.norm_squared();
+        let b = offset.dot(&self.direction);
+        let c = offset.norm_squared() - sphere.radius * sphere.radius;
+        let discriminant = b * b - a * c;
+        if a == 0.0 || discriminant < 0.0 {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        let (near, far) = ((-b - root) / a, (-b + root) / a);
+        (far >= 0.0).then_some(near.max(0.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vulkan::{Camera, DepthRange};
+
+    fn frustum(far: Option<f32>, depth_range: DepthRange) -> Frustum {
+        let mut camera = Camera::default();
+        camera.set_clip_planes(0.1, far);
+        Frustum::from_view_projection(
+            &(depth_range.projection_adjustment() * camera.projectionmatrix * camera.viewmatrix),
+        )
+    }
+
+    fn sphere(z: f32, radius: f32) -> BoundingSphere {
+        BoundingSphere {
+            centre: Vector3::new(0.0, 0.0, z),
+            radius,
+        }
+    }
+
+    fn cube(centre: Vector3<f32>, half_size: f32) -> Aabb {
+        Aabb {
+            min: centre.add_scalar(-half_size),
+            max: centre.add_scalar(half_size),
+        }
+    }
+
+    #[test]
+    fn frustum_keeps_inside_and_straddling_bounds() {
+        let frustum = frustum(Some(100.0), DepthRange::Standard);
+        assert!(frustum.intersects(&sphere(10.0, 1.0)));
+        assert!(frustum.intersects_aabb(&cube(Vector3::new(0.0, 0.0, 10.0), 1.0)));
+        // Across the far plane and the near one.
+        assert!(frustum.intersects(&sphere(100.5, 1.0)));
+        assert!(frustum.intersects_aabb(&cube(Vector3::new(0.0, 0.0, 0.0), 1.0)));
+    }
+
+    #[test]
+    fn frustum_culls_outside_bounds() {
+        let frustum = frustum(Some(100.0), DepthRange::Standard);
+        assert!(!frustum.intersects(&sphere(-10.0, 1.0)));
+        assert!(!frustum.intersects(&sphere(200.0, 1.0)));
+        assert!(!frustum.intersects_aabb(&cube(Vector3::new(100.0, 0.0, 10.0), 1.0)));
+    }
+
+    #[test]
+    fn infinite_far_plane_never_culls_distant_bounds() {
+        for depth_range in [DepthRange::Standard, DepthRange::ReverseZ] {
+            let frustum = frustum(None, depth_range);
+            assert!(frustum.intersects(&sphere(1.0e6, 1.0)));
+            assert!(frustum.intersects_aabb(&cube(Vector3::new(0.0, 0.0, 1.0e6), 1.0)));
+            assert!(!frustum.intersects(&sphere(-10.0, 1.0)));
+        }
+    }
+
+    #[test]
+    fn ray_hits_and_misses_a_box() {
+        let aabb = cube(Vector3::new(0.0, 0.0, 5.0), 1.0);
+        let ray = Ray {
+            origin: Vector3::zeros(),
+            direction: Vector3::new(0.0, 0.0, 1.0),
+        };
+        assert_eq!(ray.intersect_aabb(&aabb), Some(4.0));
+        let away = Ray {
+            direction: -ray.direction,
+            ..ray
+        };
+        assert_eq!(away.intersect_aabb(&aabb), None);
+        // Parallel to the x faces and outside them.
+        let beside = Ray {
+            origin: Vector3::new(2.0, 0.0, 0.0),
+            ..ray
+        };
+        assert_eq!(beside.intersect_aabb(&aabb), None);
+    }
+
+    #[test]
+    fn ray_starting_inside_hits_at_zero() {
+        let ray = Ray {
+            origin: Vector3::new(0.0, 0.0, 5.0),
+            direction: Vector3::new(1.0, 0.0, 0.0),
+        };
+        assert_eq!(
+            ray.intersect_aabb(&cube(Vector3::new(0.0, 0.0, 5.0), 1.0)),
+            Some(0.0)
+        );
+        assert_eq!(ray.intersect_sphere(&sphere(5.0, 1.0)), Some(0.0));
+    }
+
+    #[test]
+    fn ray_hits_a_sphere_at_its_surface() {
+        let ray = Ray {
+            origin: Vector3::zeros(),
+            direction: Vector3::new(0.0, 0.0, 2.0),
+        };
+        // Distances are in multiples of the direction.
+        assert_eq!(ray.intersect_sphere(&sphere(10.0, 2.0)), Some(4.0));
+        assert_eq!(ray.intersect_sphere(&sphere(-10.0, 2.0)), None);
+    }
+
+    #[test]
+    fn bounds_from_points() {
+        let points = [Vector3::new(-1.0, 0.0, 0.0), Vector3::new(1.0, 2.0, 0.0)];
+        let bounds = MeshBounds::from_points(points.into_iter());
+        assert_eq!(bounds.aabb.min, Vector3::new(-1.0, 0.0, 0.0));
+        assert_eq!(bounds.aabb.max, Vector3::new(1.0, 2.0, 0.0));
+        assert_eq!(bounds.sphere.centre, Vector3::new(0.0, 1.0, 0.0));
+        assert!((bounds.sphere.radius - 2.0f32.sqrt()).abs() < 1e-6);
+        assert_eq!(
+            MeshBounds::from_points(std::iter::empty()),
+            MeshBounds::default()
+        );
+    }
+}
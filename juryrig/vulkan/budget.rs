@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use log::warn;
+
+use crate::alloc_telemetry::AllocationStats;
+
+/// Frame time limits, a warning is logged for every frame that goes over one of them.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameBudget {
+    pub cpu: Option<Duration>,
+    pub gpu: Option<Duration>,
+    /// Heap allocations per frame, only counted with the `alloc-telemetry` feature.
+    pub allocations: Option<u64>,
+}
+
+impl FrameBudget {
+    pub(super) fn check_cpu(&self, frame: u64, time: Duration) {
+        if let Some(budget) = self.cpu {
+            if time > budget {
+                warn!(
+                    "frame over budget: kind=cpu frame={} time_ms={:.3} budget_ms={:.3}",
+                    frame,
+                    time.as_secs_f64() * 1000.0,
+                    budget.as_secs_f64() * 1000.0
+                );
+            }
+        }
+    }
+
+    // The busiest subsystem is included in the warning.
+    pub(super) fn check_allocations(&self, frame: u64, stats: &AllocationStats) {
+        if let Some(budget) = self.allocations {
+            let total = stats.total();
+            if total.allocations > budget {
+                let (busiest, busiest_count) = stats
+                    .iter()
+                    .max_by_key(|(_, count)| count.allocations)
+                    .unwrap();
+                warn!(
+                    "frame over budget: kind=allocations frame={} allocations={} bytes={} budget={} busiest_subsystem={:?} busiest_allocations={}",
+                    frame,
+                    total.allocations,
+                    total.bytes,
+                    budget,
+                    busiest,
+                    busiest_count.allocations
+                );
+            }
+        }
+    }
+
+    // `passes` is every timed pass of the frame, the slowest one is included in the warning.
+    pub(super) fn check_gpu(&self, frame: u64, passes: &[(&str, Duration)]) {
+        if let Some(budget) = self.gpu {
+            let time: Duration = passes.iter().map(|(_, time)| *time).sum();
+            if time > budget {
+                let (slowest, slowest_time) = passes
+                    .iter()
+                    .max_by_key(|(_, time)| *time)
+                    .copied()
+                    .unwrap_or(("none", Duration::ZERO));
+                warn!(
+                    "frame over budget: kind=gpu frame={} time_ms={:.3} budget_ms={:.3} slowest_pass={} slowest_pass_ms={:.3}",
+                    frame,
+                    time.as_secs_f64() * 1000.0,
+                    budget.as_secs_f64() * 1000.0,
+                    slowest,
+                    slowest_time.as_secs_f64() * 1000.0
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,621 @@
+use std::{collections::VecDeque, marker::PhantomData, mem::size_of};
+
+use ash::{vk, Device};
+use gpu_allocator::vulkan::{Allocation, AllocationCreateDesc, AllocationScheme, Allocator};
+
+use super::{debug, leaks};
+
+// Uploads bigger than this get a staging buffer of their own.
+pub(super) const STAGING_RING_SIZE: u64 = 32 * 1024 * 1024;
+
+// Copies out of the ring start on a multiple of this, enough for any texel size we upload.
+const STAGING_ALIGNMENT: u64 = 16;
+
+// A queue for one-off copies, the command buffers they're recorded in and the staging memory
+// they read from.
+pub(super) struct Transfer<'a> {
+    // The family `commands` submits to and the one that uses what it uploads, they can be the
+    // same.
+    pub(super) queue_families: [u32; 2],
+    pub(super) commands: &'a mut TransientPool,
+    pub(super) staging: &'a mut StagingRing,
+}
+
+impl Transfer<'_> {
+    // Stages `bytes`, lets `record` copy them out of the staging buffer at the given offset and
+    // submits the copy without waiting for it.
+    pub(super) fn submit(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        bytes: &[u8],
+        record: impl FnOnce(vk::CommandBuffer, vk::Buffer, u64),
+    ) -> Result<Submission, vk::Result> {
+        let command = self.commands.begin(logical_device)?;
+        let staged = match self.staging.push(logical_device, bytes, command.fence) {
+            Ok(Some(offset)) => Ok((self.staging.buffer.buffer, offset, None)),
+            Ok(None) => Buffer::<u8>::new(
+                allocator,
+                logical_device,
+                bytes.len() as u64,
+                vk::BufferUsageFlags::TRANSFER_SRC,
+                "staging",
+                gpu_allocator::MemoryLocation::CpuToGpu,
+            )
+            .map(|mut own| {
+                own.copy(bytes).expect("staging buffers are always mapped");
+                (own.buffer, 0, Some(own))
+            }),
+            Err(e) => Err(e),
+        };
+        let (buffer, offset, own_staging) = match staged {
+            Ok(staged) => staged,
+            Err(e) => {
+                self.commands.abandon(logical_device, command);
+                return Err(e);
+            }
+        };
+        record(command.command_buffer, buffer, offset);
+        let mut submission = Submission {
+            fence: command.fence,
+            own_staging,
+        };
+        if let Err(e) = self.commands.submit(logical_device, command) {
+            // Nothing ran, so nothing is reading the staging buffer.
+            unsafe { submission.cleanup(allocator, logical_device) };
+            return Err(e);
+        }
+        Ok(submission)
+    }
+}
+
+// A copy from `Transfer::submit` that may still be running.
+pub(super) struct Submission {
+    fence: vk::Fence,
+    own_staging: Option<Buffer<u8>>,
+}
+
+impl Submission {
+    // The fence can be reused once it signals, which only ever makes this answer later.
+    pub(super) fn is_done(&self, logical_device: &Device) -> Result<bool, vk::Result> {
+        unsafe { logical_device.get_fence_status(self.fence) }
+    }
+
+    pub(super) fn wait(&self, logical_device: &Device) -> Result<(), vk::Result> {
+        unsafe { logical_device.wait_for_fences(&[self.fence], true, u64::MAX) }
+    }
+
+    // Only once it is done.
+    pub(super) unsafe fn cleanup(&mut self, allocator: &mut Allocator, logical_device: &Device) {
+        if let Some(mut staging) = self.own_staging.take() {
+            staging.cleanup(allocator, logical_device);
+        }
+    }
+}
+
+// A command buffer from `TransientPool::begin`, being recorded, and the fence its submission
+// signals.
+#[derive(Clone, Copy)]
+pub(super) struct TransientCommand {
+    pub(super) command_buffer: vk::CommandBuffer,
+    fence: vk::Fence,
+}
+
+// Command buffers for one-off submissions to a queue. They're recycled once their fence has
+// signalled instead of being allocated and freed around every copy.
+pub(super) struct TransientPool {
+    queue: vk::Queue,
+    pool: vk::CommandPool,
+    free: Vec<TransientCommand>,
+    in_flight: Vec<TransientCommand>,
+}
+
+impl TransientPool {
+    pub(super) fn new(
+        logical_device: &Device,
+        queue: vk::Queue,
+        queue_family: u32,
+    ) -> Result<TransientPool, vk::Result> {
+        let pool_info = vk::CommandPoolCreateInfo::builder()
+            .queue_family_index(queue_family)
+            .flags(
+                vk::CommandPoolCreateFlags::TRANSIENT
+                    | vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+            );
+        let pool = unsafe { logical_device.create_command_pool(&pool_info, None) }?;
+        Ok(TransientPool {
+            queue,
+            pool,
+            free: vec![],
+            in_flight: vec![],
+        })
+    }
+
+    // Starts recording a one time submit command buffer. Its fence is unsignalled until it is
+    // submitted and has run.
+    pub(super) fn begin(
+        &mut self,
+        logical_device: &Device,
+    ) -> Result<TransientCommand, vk::Result> {
+        let mut index = 0;
+        while index < self.in_flight.len() {
+            if unsafe { logical_device.get_fence_status(self.in_flight[index].fence) }? {
+                self.free.push(self.in_flight.swap_remove(index));
+            } else {
+                index += 1;
+            }
+        }
+        let command = match self.free.pop() {
+            Some(command) => {
+                unsafe { logical_device.reset_fences(&[command.fence]) }?;
+                command
+            }
+            None => {
+                let allocate_info = vk::CommandBufferAllocateInfo::builder()
+                    .command_pool(self.pool)
+                    .command_buffer_count(1);
+                let command_buffer =
+                    unsafe { logical_device.allocate_command_buffers(&allocate_info) }?[0];
+                debug::set_object_name(logical_device, command_buffer, "transient commands");
+                match unsafe { logical_device.create_fence(&vk::FenceCreateInfo::default(), None) }
+                {
+                    Ok(fence) => TransientCommand {
+                        command_buffer,
+                        fence,
+                    },
+                    Err(e) => {
+                        unsafe {
+                            logical_device.free_command_buffers(self.pool, &[command_buffer])
+                        };
+                        return Err(e);
+                    }
+                }
+            }
+        };
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        if let Err(e) =
+            unsafe { logical_device.begin_command_buffer(command.command_buffer, &begin_info) }
+        {
+            self.abandon(logical_device, command);
+            return Err(e);
+        }
+        Ok(command)
+    }
+
+    pub(super) fn submit(
+        &mut self,
+        logical_device: &Device,
+        command: TransientCommand,
+    ) -> Result<(), vk::Result> {
+        let submitted = unsafe {
+            logical_device
+                .end_command_buffer(command.command_buffer)
+                .and_then(|_| {
+                    let submit_infos = [vk::SubmitInfo::builder()
+                        .command_buffers(&[command.command_buffer])
+                        .build()];
+                    logical_device.queue_submit(self.queue, &submit_infos, command.fence)
+                })
+        };
+        match submitted {
+            Ok(()) => self.in_flight.push(command),
+            Err(_) => self.abandon(logical_device, command),
+        }
+        submitted
+    }
+
+    // Submits and blocks until the command buffer has run.
+    pub(super) fn submit_and_wait(
+        &mut self,
+        logical_device: &Device,
+        command: TransientCommand,
+    ) -> Result<(), vk::Result> {
+        self.submit(logical_device, command)?;
+        unsafe { logical_device.wait_for_fences(&[command.fence], true, u64::MAX) }
+    }
+
+    // Throws away what was recorded. The fence is signalled by an empty submission, so staging
+    // regions guarded by it are freed as usual.
+    pub(super) fn abandon(&mut self, logical_device: &Device, command: TransientCommand) {
+        let signalled = unsafe {
+            logical_device
+                .reset_command_buffer(command.command_buffer, vk::CommandBufferResetFlags::empty())
+                .and_then(|_| logical_device.queue_submit(self.queue, &[], command.fence))
+        };
+        match signalled {
+            Ok(()) => self.in_flight.push(command),
+            // The device is most likely lost, keep the fence out of the way.
+            Err(_) => unsafe {
+                logical_device.free_command_buffers(self.pool, &[command.command_buffer]);
+            },
+        }
+    }
+
+    // Only with the device idle.
+    pub(super) unsafe fn cleanup(&mut self, logical_device: &Device) {
+        for command in self.free.drain(..).chain(self.in_flight.drain(..)) {
+            logical_device.destroy_fence(command.fence, None);
+        }
+        logical_device.destroy_command_pool(self.pool, None);
+    }
+}
+
+// One persistently mapped staging buffer, handed out front to back and reused once the copies
+// reading each region have finished, so streaming uploads don't go back to the allocator.
+pub(super) struct StagingRing {
+    buffer: Buffer<u8>,
+    // Where the newest region ends.
+    head: u64,
+    // Start, end and the fence of the submission reading it, oldest first.
+    regions: VecDeque<(u64, u64, vk::Fence)>,
+}
+
+impl StagingRing {
+    // `queue_families` are the ones copies out of it are submitted to.
+    pub(super) fn new(
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        size: u64,
+        queue_families: &[u32],
+    ) -> Result<StagingRing, vk::Result> {
+        Ok(StagingRing {
+            buffer: Buffer::with_queue_families(
+                allocator,
+                logical_device,
+                size,
+                vk::BufferUsageFlags::TRANSFER_SRC,
+                "staging ring",
+                gpu_allocator::MemoryLocation::CpuToGpu,
+                queue_families,
+            )?,
+            head: 0,
+            regions: VecDeque::new(),
+        })
+    }
+
+    // Copies `bytes` in and returns where they start, or None when they don't fit next to the
+    // regions still being read. `fence` has to be unsignalled until whatever reads them has
+    // run, see `TransientPool::begin`.
+    fn push(
+        &mut self,
+        logical_device: &Device,
+        bytes: &[u8],
+        fence: vk::Fence,
+    ) -> Result<Option<u64>, vk::Result> {
+        while let Some(&(_, _, oldest)) = self.regions.front() {
+            if !unsafe { logical_device.get_fence_status(oldest) }? {
+                break;
+            }
+            self.regions.pop_front();
+        }
+        let size = bytes.len() as u64;
+        let tail = self.regions.front().map(|&(tail, _, _)| tail);
+        let Some(start) = place(self.head, tail, self.buffer.len(), size) else {
+            return Ok(None);
+        };
+        self.buffer
+            .write(start as usize, bytes)
+            .expect("staging buffers are always mapped");
+        self.head = start + size;
+        self.regions.push_back((start, self.head, fence));
+        Ok(Some(start))
+    }
+
+    // Only with the device idle.
+    pub(super) unsafe fn cleanup(&mut self, allocator: &mut Allocator, logical_device: &Device) {
+        self.buffer.cleanup(allocator, logical_device);
+    }
+}
+
+// Where `size` bytes go in a ring of `capacity` bytes whose newest region ends at `head` and
+// whose oldest still being read starts at `tail`, None if there's no room.
+fn place(head: u64, tail: Option<u64>, capacity: u64, size: u64) -> Option<u64> {
+    let aligned_head = head.next_multiple_of(STAGING_ALIGNMENT);
+    match tail {
+        None => (size <= capacity).then_some(0),
+        // Free from the head to the end and from the start to the oldest region.
+        Some(tail) if head > tail => {
+            if aligned_head + size <= capacity {
+                Some(aligned_head)
+            } else {
+                (size <= tail).then_some(0)
+            }
+        }
+        Some(tail) if head < tail => (aligned_head + size <= tail).then_some(aligned_head),
+        // The head has caught up with the oldest region.
+        Some(_) => None,
+    }
+}
+
+pub(super) struct Buffer<T> {
+    pub(super) buffer: vk::Buffer,
+    allocation: Option<Allocation>,
+    phantom: PhantomData<T>,
+    size: u64,
+}
+
+impl<T> Buffer<T> {
+    pub(super) fn new(
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        size: u64,
+        usage: vk::BufferUsageFlags,
+        name: &str,
+        mem_location: gpu_allocator::MemoryLocation,
+    ) -> Result<Buffer<T>, ash::vk::Result> {
+        Self::with_queue_families(
+            allocator,
+            logical_device,
+            size,
+            usage,
+            name,
+            mem_location,
+            &[],
+        )
+    }
+
+    // Shared between `queue_families` when they differ, so the buffer can be filled on one queue
+    // and used on the other without handing it over.
+    pub(super) fn with_queue_families(
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        size: u64,
+        usage: vk::BufferUsageFlags,
+        name: &str,
+        mem_location: gpu_allocator::MemoryLocation,
+        queue_families: &[u32],
+    ) -> Result<Buffer<T>, ash::vk::Result> {
+        let mut buffer_create_info = vk::BufferCreateInfo::builder()
+            .size((size as usize * size_of::<T>()) as u64)
+            .usage(usage);
+        if queue_families.windows(2).any(|pair| pair[0] != pair[1]) {
+            buffer_create_info = buffer_create_info
+                .sharing_mode(vk::SharingMode::CONCURRENT)
+                .queue_family_indices(queue_families);
+        }
+
+        let buffer = unsafe {
+            logical_device
+                .create_buffer(&buffer_create_info, None)
+                .unwrap()
+        };
+
+        let requirements = unsafe { logical_device.get_buffer_memory_requirements(buffer) };
+        let allocation = allocator
+            .allocate(&AllocationCreateDesc {
+                name: name,
+                requirements,
+                linear: true,
+                location: mem_location,
+                allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+            })
+            .unwrap();
+
+        // Bind memory to the buffer
+        unsafe {
+            logical_device.bind_buffer_memory(buffer, allocation.memory(), allocation.offset())?
+        };
+        leaks::track(logical_device, buffer, name, allocation.size());
+        debug::set_object_name(logical_device, buffer, name);
+        Ok(Buffer {
+            buffer,
+            allocation: Some(allocation),
+            size,
+            phantom: PhantomData,
+        })
+    }
+
+    // A buffer in GPU only memory holding `data`, copied in through `transfer`'s staging memory
+    // on its queue. Waits for the copy to finish.
+    pub(super) fn new_gpu_only(
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        data: &[T],
+        usage: vk::BufferUsageFlags,
+        name: &str,
+        transfer: &mut Transfer,
+    ) -> Result<Buffer<T>, vk::Result> {
+        let mut buffer = Self::with_queue_families(
+            allocator,
+            logical_device,
+            data.len() as u64,
+            usage | vk::BufferUsageFlags::TRANSFER_DST,
+            name,
+            gpu_allocator::MemoryLocation::GpuOnly,
+            &transfer.queue_families,
+        )?;
+        let bytes = unsafe {
+            std::slice::from_raw_parts(data.as_ptr() as *const u8, std::mem::size_of_val(data))
+        };
+        let destination = buffer.buffer;
+        let mut submission = match transfer.submit(
+            allocator,
+            logical_device,
+            bytes,
+            |command_buffer, staging, offset| {
+                let region = vk::BufferCopy {
+                    src_offset: offset,
+                    dst_offset: 0,
+                    size: bytes.len() as u64,
+                };
+                unsafe {
+                    logical_device.cmd_copy_buffer(command_buffer, staging, destination, &[region])
+                };
+            },
+        ) {
+            Ok(submission) => submission,
+            Err(e) => {
+                unsafe { buffer.cleanup(allocator, logical_device) };
+                return Err(e);
+            }
+        };
+        let waited = submission.wait(logical_device);
+        unsafe { submission.cleanup(allocator, logical_device) };
+        if let Err(e) = waited {
+            unsafe { buffer.cleanup(allocator, logical_device) };
+            return Err(e);
+        }
+        Ok(buffer)
+    }
+
+    pub(super) fn copy(&mut self, in_data: &[T]) -> Result<(), ()> {
+        match &self.allocation {
+            Some(allocation) => {
+                let data_ptr = allocation.mapped_ptr().unwrap().cast().as_ptr();
+                unsafe {
+                    std::ptr::copy_nonoverlapping(in_data.as_ptr(), data_ptr, in_data.len());
+                }
+                Ok(())
+            }
+            None => Err(()),
+        }
+    }
+
+    // Like `copy` but starting `offset` elements into the buffer.
+    pub(super) fn write(&mut self, offset: usize, in_data: &[T]) -> Result<(), ()> {
+        match self
+            .allocation
+            .as_ref()
+            .and_then(|allocation| allocation.mapped_ptr())
+        {
+            Some(data_ptr) if offset + in_data.len() <= self.size as usize => {
+                let data_ptr: *mut T = data_ptr.cast().as_ptr();
+                unsafe {
+                    std::ptr::copy_nonoverlapping(
+                        in_data.as_ptr(),
+                        data_ptr.add(offset),
+                        in_data.len(),
+                    );
+                }
+                Ok(())
+            }
+            _ => Err(()),
+        }
+    }
+
+    // Copies the start of the buffer into `out_data`, the buffer must be host visible.
+    pub(super) fn read(&self, out_data: &mut [T]) -> Result<(), ()> {
+        match self
+            .allocation
+            .as_ref()
+            .and_then(|allocation| allocation.mapped_ptr())
+        {
+            Some(data_ptr) => {
+                unsafe {
+                    std::ptr::copy_nonoverlapping(
+                        data_ptr.cast().as_ptr(),
+                        out_data.as_mut_ptr(),
+                        out_data.len().min(self.size as usize),
+                    );
+                }
+                Ok(())
+            }
+            None => Err(()),
+        }
+    }
+
+    pub(super) unsafe fn cleanup(&mut self, allocator: &mut Allocator, logical_device: &Device) {
+        logical_device.destroy_buffer(self.buffer, None);
+        leaks::untrack(logical_device, self.buffer);
+
+        allocator.free(self.allocation.take().unwrap()).unwrap();
+    }
+
+    pub fn len(&self) -> u64 {
+        self.size
+    }
+}
+
+pub(super) struct Image {
+    pub(super) image: vk::Image,
+    allocation: Option<Allocation>,
+}
+
+impl Image {
+    pub(super) fn new(
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        create_info: &vk::ImageCreateInfo,
+        location: gpu_allocator::MemoryLocation,
+        name: &str,
+        linear: Option<bool>,
+    ) -> Result<Image, ash::vk::Result> {
+        let image = unsafe { logical_device.create_image(&create_info, None)? };
+
+        let requirements = unsafe { logical_device.get_image_memory_requirements(image) };
+        let allocation = allocator
+            .allocate(&AllocationCreateDesc {
+                name: name,
+                requirements,
+                linear: linear.unwrap_or(false),
+                location,
+                allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+            })
+            .unwrap();
+
+        // Bind memory to the buffer
+        unsafe {
+            logical_device.bind_image_memory(image, allocation.memory(), allocation.offset())?
+        };
+        leaks::track(logical_device, image, name, allocation.size());
+        debug::set_object_name(logical_device, image, name);
+
+        Ok(Image {
+            image,
+            allocation: Some(allocation),
+        })
+    }
+
+    pub(super) fn copy<T>(&mut self, in_data: &Vec<T>) -> Result<(), ()> {
+        match &self.allocation {
+            Some(allocation) => {
+                let data_ptr = allocation.mapped_ptr().unwrap().cast().as_ptr();
+                unsafe {
+                    std::ptr::copy_nonoverlapping(in_data.as_ptr(), data_ptr, in_data.len());
+                }
+                Ok(())
+            }
+            None => Err(()),
+        }
+    }
+
+    pub(super) unsafe fn cleanup(&mut self, allocator: &mut Allocator, logical_device: &Device) {
+        logical_device.destroy_image(self.image, None);
+        leaks::untrack(logical_device, self.image);
+
+        allocator.free(self.allocation.take().unwrap()).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_empty_ring_starts_at_the_front() {
+        assert_eq!(place(40, None, 100, 100), Some(0));
+        assert_eq!(place(40, None, 100, 101), None);
+    }
+
+    #[test]
+    fn regions_follow_the_head_aligned() {
+        // Between the oldest region and the end.
+        assert_eq!(place(20, Some(0), 100, 10), Some(32));
+        assert_eq!(place(32, Some(0), 100, 68), Some(32));
+    }
+
+    #[test]
+    fn regions_wrap_to_the_front_before_the_oldest() {
+        assert_eq!(place(90, Some(40), 100, 30), Some(0));
+        assert_eq!(place(90, Some(40), 100, 41), None);
+        // Once wrapped, up to the oldest region.
+        assert_eq!(place(10, Some(40), 100, 24), Some(16));
+        assert_eq!(place(10, Some(40), 100, 25), None);
+    }
+
+    #[test]
+    fn a_full_ring_has_no_room() {
+        assert_eq!(place(40, Some(40), 100, 1), None);
+    }
+}
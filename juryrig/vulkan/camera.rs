@@ -0,0 +1,303 @@
+use winit::event::{ElementState, KeyboardInput, VirtualKeyCode};
+
+use crate::input::{GamepadAxis, Input};
+
+use super::bounds::Ray;
+
+pub struct Camera {
+    pub(super) viewmatrix: na::Matrix4<f32>,
+    pub(super) position: na::Vector3<f32>,
+    view_direction: na::Unit<na::Vector3<f32>>,
+    down_direction: na::Unit<na::Vector3<f32>>,
+
+    fovy: f32,
+    pub(super) aspect: f32,
+    near: f32,
+    // None puts the far plane at infinity.
+    far: Option<f32>,
+    pub(super) projectionmatrix: na::Matrix4<f32>,
+    // In stops, see `set_exposure`.
+    exposure: f32,
+}
+impl Default for Camera {
+    fn default() -> Self {
+        let mut cam = Camera {
+            viewmatrix: na::Matrix4::identity(),
+            position: na::Vector3::new(0.0, 0.0, 0.0),
+            view_direction: na::Unit::new_normalize(na::Vector3::new(0.0, 0.0, 1.0)),
+            down_direction: na::Unit::new_normalize(na::Vector3::new(0.0, -1.0, 0.0)),
+            fovy: std::f32::consts::FRAC_PI_3,
+            aspect: 800.0 / 600.0,
+            near: 0.1,
+            far: Some(100.0),
+            projectionmatrix: na::Matrix4::identity(),
+            exposure: 0.0,
+        };
+        cam.update_projectionmatrix();
+        cam.update_viewmatrix();
+        cam
+    }
+}
+impl Camera {
+    pub fn update_viewmatrix(&mut self) {
+        let right = na::Unit::new_normalize(self.down_direction.cross(&self.view_direction));
+        let m = na::Matrix4::new(
+            right.x,
+            right.y,
+            right.z,
+            -right.dot(&self.position), //
+            self.down_direction.x,
+            self.down_direction.y,
+            self.down_direction.z,
+            -self.down_direction.dot(&self.position), //
+            self.view_direction.x,
+            self.view_direction.y,
+            self.view_direction.z,
+            -self.view_direction.dot(&self.position), //
+            0.0,
+            0.0,
+            0.0,
+            1.0,
+        );
+        self.viewmatrix = m;
+    }
+    /// Produces depth in the standard Vulkan range, 0 at the near plane and 1 at the far plane. The
+    /// renderer flips it when running with reverse-Z.
+    pub fn update_projectionmatrix(&mut self) {
+        let d = 1.0 / (0.5 * self.fovy).tan();
+        let (depth_scale, depth_offset) = match self.far {
+            Some(far) => (
+                far / (far - self.near),
+                -self.near * far / (far - self.near),
+            ),
+            // The limit of the above as far goes to infinity.
+            None => (1.0, -self.near),
+        };
+        self.projectionmatrix = na::Matrix4::new(
+            d / self.aspect,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            d,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            depth_scale,
+            depth_offset,
+            0.0,
+            0.0,
+            1.0,
+            0.0,
+        );
+    }
+    /// Sets the near and far clip distances, a far plane of None never clips. An infinite far plane
+    /// is best paired with `DepthRange::ReverseZ`, otherwise distant geometry runs out of depth
+    /// precision quickly.
+    pub fn set_clip_planes(&mut self, near: f32, far: Option<f32>) {
+        self.near = near;
+        self.far = far;
+        self.update_projectionmatrix();
+    }
+    pub fn near(&self) -> f32 {
+        self.near
+    }
+    pub fn far(&self) -> Option<f32> {
+        self.far
+    }
+    /// Brightens the picture by `stops` before it's tonemapped, each stop doubles it and 0 leaves
+    /// it as drawn. Only the main camera's is used, render targets are stored as drawn.
+    pub fn set_exposure(&mut self, stops: f32) {
+        self.exposure = stops;
+    }
+    pub fn exposure(&self) -> f32 {
+        self.exposure
+    }
+    pub fn move_forward(&mut self, distance: f32) {
+        self.position += distance * self.view_direction.as_ref();
+        self.update_viewmatrix();
+    }
+    pub fn move_backward(&mut self, distance: f32) {
+        self.move_forward(-distance);
+    }
+    pub fn turn_right(&mut self, angle: f32) {
+        let rotation = na::Rotation3::from_axis_angle(&self.down_direction, angle);
+        self.view_direction = rotation * self.view_direction;
+        self.update_viewmatrix();
+    }
+    pub fn turn_left(&mut self, angle: f32) {
+        self.turn_right(-angle);
+    }
+    pub fn turn_up(&mut self, angle: f32) {
+        let right = na::Unit::new_normalize(self.down_direction.cross(&self.view_direction));
+        let rotation = na::Rotation3::from_axis_angle(&right, angle);
+        self.view_direction = rotation * self.view_direction;
+        self.down_direction = rotation * self.down_direction;
+        self.update_viewmatrix();
+    }
+    pub fn turn_down(&mut self, angle: f32) {
+        self.turn_up(-angle);
+    }
+    pub fn position(&self) -> na::Vector3<f32> {
+        self.position
+    }
+    pub fn set_position(&mut self, position: na::Vector3<f32>) {
+        self.position = position;
+        self.update_viewmatrix();
+    }
+    /// Looks `yaw` radians to the right of +z and `pitch` radians above the horizon, with +y up and
+    /// no roll. `turn_right` after `turn_up` tilts the horizon, this never does.
+    pub fn set_orientation(&mut self, yaw: f32, pitch: f32) {
+        let view = na::Vector3::new(
+            -yaw.sin() * pitch.cos(),
+            pitch.sin(),
+            yaw.cos() * pitch.cos(),
+        );
+        let right = na::Vector3::new(-yaw.cos(), 0.0, -yaw.sin());
+        self.view_direction = na::Unit::new_normalize(view);
+        self.down_direction = na::Unit::new_normalize(view.cross(&right));
+        self.update_viewmatrix();
+    }
+    /// The yaw and pitch `set_orientation` would take to look the way the camera does now.
+    pub fn orientation(&self) -> (f32, f32) {
+        let view = self.view_direction;
+        ((-view.x).atan2(view.z), view.y.clamp(-1.0, 1.0).asin())
+    }
+    /// The ray from the camera through pixel `x`, `y` of a `viewport` of that width and height,
+    /// with 0, 0 at the top left like window coordinates. Its direction is unit length.
+    pub fn screen_point_to_ray(&self, x: f32, y: f32, viewport: (f32, f32)) -> Ray {
+        let d = 1.0 / (0.5 * self.fovy).tan();
+        let ndc_x = 2.0 * x / viewport.0 - 1.0;
+        let ndc_y = 2.0 * y / viewport.1 - 1.0;
+        let right = na::Unit::new_normalize(self.down_direction.cross(&self.view_direction));
+        let direction = right.into_inner() * (ndc_x * self.aspect / d)
+            + self.down_direction.into_inner() * (ndc_y / d)
+            + self.view_direction.into_inner();
+        Ray {
+            origin: self.position,
+            direction: direction.normalize(),
+        }
+    }
+}
+
+// Keeps the pitch short of straight up or down, where yaw stops meaning anything.
+const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+/// First person controls for a `Camera`. WASD moves along the ground, Space and left Shift move up
+/// and down, the arrow keys and Page Up/Down turn, and mouse motion looks around. On a gamepad the
+/// left stick moves, the right stick looks and the triggers go down and up. Feed it input as it
+/// arrives and call `update` once a frame with the time since the last one, so movement doesn't
+/// depend on the frame rate.
+pub struct CameraController {
+    /// Radians turned per pixel of mouse motion.
+    pub sensitivity: f32,
+    /// Units moved per second.
+    pub speed: f32,
+    /// Radians turned per second while an arrow key is held.
+    pub turn_speed: f32,
+    yaw: f32,
+    pitch: f32,
+    mouse_delta: (f64, f64),
+    held: Vec<VirtualKeyCode>,
+    // From `gamepad_input`: right, forward, up, then yaw and pitch, each -1 to 1.
+    sticks: [f32; 5],
+}
+
+impl CameraController {
+    /// Starts out looking the way `camera` does.
+    pub fn new(camera: &Camera) -> CameraController {
+        let (yaw, pitch) = camera.orientation();
+        CameraController {
+            sensitivity: 0.002,
+            speed: 3.0,
+            turn_speed: 1.5,
+            yaw,
+            pitch: pitch.clamp(-MAX_PITCH, MAX_PITCH),
+            mouse_delta: (0.0, 0.0),
+            held: vec![],
+            sticks: [0.0; 5],
+        }
+    }
+
+    /// Feed with the raw deltas from `DeviceEvent::MouseMotion`, only while mouse look should be
+    /// active.
+    pub fn mouse_motion(&mut self, delta: (f64, f64)) {
+        self.mouse_delta.0 += delta.0;
+        self.mouse_delta.1 += delta.1;
+    }
+
+    /// Feed with `WindowEvent::KeyboardInput`. Returns whether the key is one the controller uses.
+    pub fn keyboard_input(&mut self, input: &KeyboardInput) -> bool {
+        let key = match input.virtual_keycode {
+            Some(key) if Self::is_bound(key) => key,
+            _ => return false,
+        };
+        match input.state {
+            ElementState::Pressed if !self.held.contains(&key) => self.held.push(key),
+            ElementState::Pressed => {}
+            ElementState::Released => self.held.retain(|held| *held != key),
+        }
+        true
+    }
+
+    /// Reads the sticks and triggers, once a frame before `update`. Turns at `turn_speed` with a
+    /// stick all the way over.
+    pub fn gamepad_input(&mut self, input: &Input) {
+        self.sticks = [
+            input.gamepad_axis(GamepadAxis::LeftStickX),
+            input.gamepad_axis(GamepadAxis::LeftStickY),
+            input.gamepad_axis(GamepadAxis::RightTrigger)
+                - input.gamepad_axis(GamepadAxis::LeftTrigger),
+            input.gamepad_axis(GamepadAxis::RightStickX),
+            input.gamepad_axis(GamepadAxis::RightStickY),
+        ];
+    }
+
+    /// Forgets every held key, for when the window loses focus and the releases would go missing.
+    pub fn release_all(&mut self) {
+        self.held.clear();
+        self.mouse_delta = (0.0, 0.0);
+        self.sticks = [0.0; 5];
+    }
+
+    /// Applies the input since the last call, `delta_time` is in seconds.
+    pub fn update(&mut self, camera: &mut Camera, delta_time: f32) {
+        let axis = |positive: &[VirtualKeyCode], negative: &[VirtualKeyCode]| {
+            let held = |keys: &[VirtualKeyCode]| keys.iter().any(|key| self.held.contains(key));
+            held(positive) as i32 as f32 - held(negative) as i32 as f32
+        };
+        let turn = self.turn_speed * delta_time;
+        let (dx, dy) = std::mem::take(&mut self.mouse_delta);
+        let [right, forward, up, yaw, pitch] = self.sticks;
+        self.yaw += dx as f32 * self.sensitivity
+            + (axis(&[VirtualKeyCode::Right], &[VirtualKeyCode::Left]) + yaw) * turn;
+        self.pitch = (self.pitch - dy as f32 * self.sensitivity
+            + (axis(&[VirtualKeyCode::PageUp], &[VirtualKeyCode::PageDown]) + pitch) * turn)
+            .clamp(-MAX_PITCH, MAX_PITCH);
+        camera.set_orientation(self.yaw, self.pitch);
+
+        let forward_direction = na::Vector3::new(-self.yaw.sin(), 0.0, self.yaw.cos());
+        let right_direction = na::Vector3::new(-self.yaw.cos(), 0.0, -self.yaw.sin());
+        let direction = forward_direction
+            * (axis(
+                &[VirtualKeyCode::W, VirtualKeyCode::Up],
+                &[VirtualKeyCode::S, VirtualKeyCode::Down],
+            ) + forward)
+            + right_direction * (axis(&[VirtualKeyCode::D], &[VirtualKeyCode::A]) + right)
+            + na::Vector3::y() * (axis(&[VirtualKeyCode::Space], &[VirtualKeyCode::LShift]) + up);
+        // Capped so moving diagonally isn't faster, a stick half over still moves at half speed.
+        let direction = direction.cap_magnitude(1.0);
+        if direction != na::Vector3::zeros() {
+            camera.set_position(camera.position + direction * self.speed * delta_time);
+        }
+    }
+
+    fn is_bound(key: VirtualKeyCode) -> bool {
+        use VirtualKeyCode::*;
+        matches!(
+            key,
+            W | A | S | D | Space | LShift | Up | Down | Left | Right | PageUp | PageDown
+        )
+    }
+}
@@ -0,0 +1,769 @@
+use ash::{vk, Device, Entry, Instance};
+use gpu_allocator::{
+    vulkan::{Allocator, AllocatorCreateDesc},
+    MemoryLocation,
+};
+use log::info;
+
+use super::{
+    buffer::{Buffer, Image},
+    debug::{self, Debug},
+    error::{InitError, RuntimeError},
+    initialisation::{create_instance, init_compute_device, init_physical_device_and_properties},
+    leaks,
+};
+
+/// A device, queue and allocator without any window, surface or swapchain, for tools that only
+/// run compute shaders (image processing, baking and the like). Every call that touches the GPU
+/// blocks until it is done.
+pub struct ComputeContext {
+    instance: Instance,
+    // Keeps the Vulkan library loaded.
+    _entry: Entry,
+    debug: std::mem::ManuallyDrop<Option<Debug>>,
+    logical_device: Device,
+    queue: vk::Queue,
+    pool: vk::CommandPool,
+    allocator: std::mem::ManuallyDrop<Allocator>,
+}
+
+/// A storage buffer, `T` is the element type.
+pub struct ComputeBuffer<T> {
+    buffer: Buffer<T>,
+}
+
+impl<T> ComputeBuffer<T> {
+    /// In elements.
+    pub fn len(&self) -> u64 {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Copies `data` into the start of the buffer. Fails unless it was created host visible and
+    /// `data` fits.
+    pub fn write(&mut self, data: &[T]) -> Result<(), RuntimeError> {
+        self.buffer
+            .write(0, data)
+            .map_err(|()| RuntimeError::InvalidBufferAccess)
+    }
+
+    /// Fills `data` from the start of the buffer, which must be host visible.
+    pub fn read(&self, data: &mut [T]) -> Result<(), RuntimeError> {
+        self.buffer
+            .read(data)
+            .map_err(|()| RuntimeError::InvalidBufferAccess)
+    }
+
+    pub fn binding(&self) -> ComputeBinding {
+        ComputeBinding::Buffer(self.buffer.buffer)
+    }
+}
+
+/// A 2D storage image, kept in the GENERAL layout so shaders and copies can use it at any time.
+pub struct ComputeImage {
+    image: Image,
+    image_view: vk::ImageView,
+    pub width: u32,
+    pub height: u32,
+    pub format: vk::Format,
+}
+
+impl ComputeImage {
+    pub fn binding(&self) -> ComputeBinding {
+        ComputeBinding::Image(self.image_view)
+    }
+}
+
+/// What a descriptor of a dispatch points at, in binding order.
+#[derive(Clone, Copy, Debug)]
+pub enum ComputeBinding {
+    Buffer(vk::Buffer),
+    Image(vk::ImageView),
+}
+
+impl ComputeBinding {
+    fn descriptor_type(&self) -> vk::DescriptorType {
+        match self {
+            ComputeBinding::Buffer(_) => vk::DescriptorType::STORAGE_BUFFER,
+            ComputeBinding::Image(_) => vk::DescriptorType::STORAGE_IMAGE,
+        }
+    }
+}
+
+/// A compute shader and the layout of what it binds. Descriptor set 0 holds the bindings in order
+/// starting at 0, and push constants start at offset 0.
+pub struct ComputePipeline {
+    pipeline: vk::Pipeline,
+    layout: vk::PipelineLayout,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    bindings: Vec<vk::DescriptorType>,
+}
+
+impl ComputeContext {
+    pub fn new(app_name: &str) -> Result<ComputeContext, InitError> {
+        let entry = unsafe { Entry::load() }?;
+
+        let validation = debug::validation_enabled(&entry, cfg!(debug_assertions))?;
+        let instance = create_instance(&entry, app_name, false, validation)?;
+        let mut debug = validation
+            .then(|| Debug::new(&entry, &instance))
+            .transpose()?;
+
+        let (physical_device, physical_device_properties) =
+            init_physical_device_and_properties(&instance, None)?;
+        let (logical_device, family, queue) = init_compute_device(&instance, physical_device)?;
+        if let Some(debug) = &mut debug {
+            debug.attach(&logical_device);
+        }
+        info!(
+            "Compute context on {:?}, queue family {}",
+            unsafe { std::ffi::CStr::from_ptr(physical_device_properties.device_name.as_ptr()) },
+            family
+        );
+
+        let pool_info = vk::CommandPoolCreateInfo::builder()
+            .queue_family_index(family)
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT);
+        let pool = unsafe { logical_device.create_command_pool(&pool_info, None) }?;
+
+        let allocator = Allocator::new(&AllocatorCreateDesc {
+            instance: instance.clone(),
+            device: logical_device.clone(),
+            physical_device,
+            debug_settings: Default::default(),
+            buffer_device_address: false,
+        })?;
+
+        Ok(ComputeContext {
+            instance,
+            _entry: entry,
+            debug: std::mem::ManuallyDrop::new(debug),
+            logical_device,
+            queue,
+            pool,
+            allocator: std::mem::ManuallyDrop::new(allocator),
+        })
+    }
+
+    fn compute_queue(&self) -> ComputeQueue<'_> {
+        ComputeQueue {
+            logical_device: &self.logical_device,
+            queue: self.queue,
+            pool: self.pool,
+            queue_families: vec![],
+        }
+    }
+
+    /// `len` is in elements. Use `CpuToGpu` for inputs, `GpuToCpu` for results and `GpuOnly` for
+    /// anything that stays on the GPU between dispatches.
+    pub fn create_buffer<T>(
+        &mut self,
+        len: u64,
+        location: MemoryLocation,
+        name: &str,
+    ) -> Result<ComputeBuffer<T>, RuntimeError> {
+        let queue = ComputeQueue {
+            logical_device: &self.logical_device,
+            queue: self.queue,
+            pool: self.pool,
+            queue_families: vec![],
+        };
+        queue.create_buffer(&mut self.allocator, len, location, name)
+    }
+
+    /// `format` must support storage images, e.g. R8G8B8A8_UNORM or R32G32B32A32_SFLOAT.
+    pub fn create_image(
+        &mut self,
+        width: u32,
+        height: u32,
+        format: vk::Format,
+        name: &str,
+    ) -> Result<ComputeImage, RuntimeError> {
+        let queue = ComputeQueue {
+            logical_device: &self.logical_device,
+            queue: self.queue,
+            pool: self.pool,
+            queue_families: vec![],
+        };
+        queue.create_image(&mut self.allocator, width, height, format, name)
+    }
+
+    /// `bindings` are the descriptor types of set 0 in binding order, `push_constant_size` is in
+    /// bytes and may be 0.
+    pub fn create_pipeline(
+        &self,
+        spirv: &[u32],
+        bindings: &[vk::DescriptorType],
+        push_constant_size: u32,
+    ) -> Result<ComputePipeline, RuntimeError> {
+        self.compute_queue()
+            .create_pipeline(spirv, bindings, push_constant_size)
+    }
+
+    /// Runs `pipeline` over `groups` workgroups and waits for it. Everything it writes is visible
+    /// to the host, copies and later dispatches once this returns.
+    pub fn dispatch(
+        &self,
+        pipeline: &ComputePipeline,
+        bindings: &[ComputeBinding],
+        push_constants: &[u8],
+        groups: [u32; 3],
+    ) -> Result<(), RuntimeError> {
+        self.compute_queue()
+            .dispatch(pipeline, bindings, push_constants, groups)
+    }
+
+    /// Copies tightly packed texels from the start of `buffer` into `image`.
+    pub fn copy_buffer_to_image<T>(
+        &self,
+        buffer: &ComputeBuffer<T>,
+        image: &ComputeImage,
+    ) -> Result<(), RuntimeError> {
+        self.compute_queue().copy_buffer_to_image(buffer, image)
+    }
+
+    /// Copies `image` into the start of `buffer`, tightly packed.
+    pub fn copy_image_to_buffer<T>(
+        &self,
+        image: &ComputeImage,
+        buffer: &ComputeBuffer<T>,
+    ) -> Result<(), RuntimeError> {
+        self.compute_queue().copy_image_to_buffer(image, buffer)
+    }
+
+    pub fn destroy_buffer<T>(&mut self, buffer: ComputeBuffer<T>) {
+        destroy_buffer(&self.logical_device, &mut self.allocator, buffer);
+    }
+
+    pub fn destroy_image(&mut self, image: ComputeImage) {
+        destroy_image(&self.logical_device, &mut self.allocator, image);
+    }
+
+    pub fn destroy_pipeline(&self, pipeline: ComputePipeline) {
+        destroy_pipeline(&self.logical_device, pipeline);
+    }
+}
+
+// The queue and pool one-off compute work is submitted with: a `ComputeContext`'s own, or the
+// renderer's compute queue for `Vulkan::dispatch_compute`. Buffers and images it creates are
+// shared between `queue_families` when they differ.
+pub(super) struct ComputeQueue<'a> {
+    pub(super) logical_device: &'a Device,
+    pub(super) queue: vk::Queue,
+    pub(super) pool: vk::CommandPool,
+    pub(super) queue_families: Vec<u32>,
+}
+
+impl ComputeQueue<'_> {
+    // `len` is in elements. Use `CpuToGpu` for inputs, `GpuToCpu` for results and `GpuOnly` for
+    // anything that stays on the GPU between dispatches.
+    pub(super) fn create_buffer<T>(
+        &self,
+        allocator: &mut Allocator,
+        len: u64,
+        location: MemoryLocation,
+        name: &str,
+    ) -> Result<ComputeBuffer<T>, RuntimeError> {
+        let buffer = Buffer::with_queue_families(
+            allocator,
+            self.logical_device,
+            len,
+            vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_SRC
+                | vk::BufferUsageFlags::TRANSFER_DST,
+            name,
+            location,
+            &self.queue_families,
+        )?;
+        Ok(ComputeBuffer { buffer })
+    }
+
+    // `format` must support storage images, e.g. R8G8B8A8_UNORM or R32G32B32A32_SFLOAT.
+    pub(super) fn create_image(
+        &self,
+        allocator: &mut Allocator,
+        width: u32,
+        height: u32,
+        format: vk::Format,
+        name: &str,
+    ) -> Result<ComputeImage, RuntimeError> {
+        let image_create_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(format)
+            .extent(vk::Extent3D {
+                width,
+                height,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(
+                vk::ImageUsageFlags::STORAGE
+                    | vk::ImageUsageFlags::TRANSFER_SRC
+                    | vk::ImageUsageFlags::TRANSFER_DST,
+            )
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+        let image_create_info = match self
+            .queue_families
+            .windows(2)
+            .any(|pair| pair[0] != pair[1])
+        {
+            true => image_create_info
+                .sharing_mode(vk::SharingMode::CONCURRENT)
+                .queue_family_indices(&self.queue_families),
+            false => image_create_info.sharing_mode(vk::SharingMode::EXCLUSIVE),
+        };
+        let image = Image::new(
+            allocator,
+            self.logical_device,
+            &image_create_info,
+            MemoryLocation::GpuOnly,
+            name,
+            None,
+        )?;
+
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .level_count(1)
+            .layer_count(1)
+            .build();
+        let image_view_create_info = vk::ImageViewCreateInfo::builder()
+            .image(image.image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(format)
+            .subresource_range(subresource_range);
+        let image_view = unsafe {
+            self.logical_device
+                .create_image_view(&image_view_create_info, None)
+        }?;
+
+        let barrier = vk::ImageMemoryBarrier::builder()
+            .image(image.image)
+            .old_layout(vk::ImageLayout::UNDEFINED)
+            .new_layout(vk::ImageLayout::GENERAL)
+            .dst_access_mask(vk::AccessFlags::SHADER_WRITE | vk::AccessFlags::TRANSFER_WRITE)
+            .subresource_range(subresource_range)
+            .build();
+        self.submit(|logical_device, command_buffer| unsafe {
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier],
+            );
+        })?;
+
+        Ok(ComputeImage {
+            image,
+            image_view,
+            width,
+            height,
+            format,
+        })
+    }
+
+    // `bindings` are the descriptor types of set 0 in binding order, `push_constant_size` is in
+    // bytes and may be 0.
+    pub(super) fn create_pipeline(
+        &self,
+        spirv: &[u32],
+        bindings: &[vk::DescriptorType],
+        push_constant_size: u32,
+    ) -> Result<ComputePipeline, RuntimeError> {
+        let layout_bindings: Vec<vk::DescriptorSetLayoutBinding> = bindings
+            .iter()
+            .enumerate()
+            .map(|(binding, descriptor_type)| {
+                vk::DescriptorSetLayoutBinding::builder()
+                    .binding(binding as u32)
+                    .descriptor_type(*descriptor_type)
+                    .descriptor_count(1)
+                    .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                    .build()
+            })
+            .collect();
+        let descriptor_set_layout_info =
+            vk::DescriptorSetLayoutCreateInfo::builder().bindings(&layout_bindings);
+        let descriptor_set_layout = unsafe {
+            self.logical_device
+                .create_descriptor_set_layout(&descriptor_set_layout_info, None)
+        }?;
+
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .size(push_constant_size)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .build()];
+        let descriptor_set_layouts = [descriptor_set_layout];
+        let pipelinelayout_info = vk::PipelineLayoutCreateInfo::builder()
+            .push_constant_ranges(if push_constant_size > 0 {
+                &push_constant_ranges[..]
+            } else {
+                &[]
+            })
+            .set_layouts(&descriptor_set_layouts);
+        let layout = unsafe {
+            self.logical_device
+                .create_pipeline_layout(&pipelinelayout_info, None)
+        }?;
+
+        let shader_create_info = vk::ShaderModuleCreateInfo::builder().code(spirv);
+        let shader_module = unsafe {
+            self.logical_device
+                .create_shader_module(&shader_create_info, None)
+        }?;
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+        let pipeline_info = vk::ComputePipelineCreateInfo::builder()
+            .stage(
+                vk::PipelineShaderStageCreateInfo::builder()
+                    .stage(vk::ShaderStageFlags::COMPUTE)
+                    .module(shader_module)
+                    .name(&main_function_name)
+                    .build(),
+            )
+            .layout(layout);
+        let pipeline = unsafe {
+            self.logical_device
+                .create_compute_pipelines(vk::PipelineCache::null(), &[pipeline_info.build()], None)
+                .map_err(|(_, e)| e)
+        };
+        unsafe {
+            self.logical_device
+                .destroy_shader_module(shader_module, None)
+        };
+
+        Ok(ComputePipeline {
+            pipeline: pipeline?[0],
+            layout,
+            descriptor_set_layout,
+            bindings: bindings.to_vec(),
+        })
+    }
+
+    // Runs `pipeline` over `groups` workgroups and waits for it. Everything it writes is visible
+    // to the host, copies and later dispatches once this returns.
+    pub(super) fn dispatch(
+        &self,
+        pipeline: &ComputePipeline,
+        bindings: &[ComputeBinding],
+        push_constants: &[u8],
+        groups: [u32; 3],
+    ) -> Result<(), RuntimeError> {
+        assert_eq!(
+            bindings
+                .iter()
+                .map(ComputeBinding::descriptor_type)
+                .collect::<Vec<_>>(),
+            pipeline.bindings,
+            "bindings don't match the pipeline's layout"
+        );
+
+        let descriptor_pool_sizes: Vec<vk::DescriptorPoolSize> = bindings
+            .iter()
+            .map(|binding| {
+                vk::DescriptorPoolSize::builder()
+                    .ty(binding.descriptor_type())
+                    .descriptor_count(1)
+                    .build()
+            })
+            .collect();
+        let descriptor_pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .pool_sizes(&descriptor_pool_sizes)
+            .max_sets(1);
+        let descriptor_pool = unsafe {
+            self.logical_device
+                .create_descriptor_pool(&descriptor_pool_info, None)
+        }?;
+
+        let result =
+            self.dispatch_with_pool(descriptor_pool, pipeline, bindings, push_constants, groups);
+        unsafe {
+            self.logical_device
+                .destroy_descriptor_pool(descriptor_pool, None)
+        };
+        result
+    }
+
+    fn dispatch_with_pool(
+        &self,
+        descriptor_pool: vk::DescriptorPool,
+        pipeline: &ComputePipeline,
+        bindings: &[ComputeBinding],
+        push_constants: &[u8],
+        groups: [u32; 3],
+    ) -> Result<(), RuntimeError> {
+        let descriptor_set_layouts = [pipeline.descriptor_set_layout];
+        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&descriptor_set_layouts);
+        let descriptor_set = unsafe {
+            self.logical_device
+                .allocate_descriptor_sets(&descriptor_set_allocate_info)
+        }?[0];
+
+        let buffer_infos: Vec<[vk::DescriptorBufferInfo; 1]> = bindings
+            .iter()
+            .map(|binding| {
+                [vk::DescriptorBufferInfo::builder()
+                    .buffer(match binding {
+                        ComputeBinding::Buffer(buffer) => *buffer,
+                        ComputeBinding::Image(_) => vk::Buffer::null(),
+                    })
+                    .range(vk::WHOLE_SIZE)
+                    .build()]
+            })
+            .collect();
+        let image_infos: Vec<[vk::DescriptorImageInfo; 1]> = bindings
+            .iter()
+            .map(|binding| {
+                [vk::DescriptorImageInfo::builder()
+                    .image_view(match binding {
+                        ComputeBinding::Image(image_view) => *image_view,
+                        ComputeBinding::Buffer(_) => vk::ImageView::null(),
+                    })
+                    .image_layout(vk::ImageLayout::GENERAL)
+                    .build()]
+            })
+            .collect();
+        let descriptor_writes: Vec<vk::WriteDescriptorSet> = bindings
+            .iter()
+            .enumerate()
+            .map(|(index, binding)| {
+                let write = vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(index as u32)
+                    .descriptor_type(binding.descriptor_type());
+                match binding {
+                    ComputeBinding::Buffer(_) => write.buffer_info(&buffer_infos[index]),
+                    ComputeBinding::Image(_) => write.image_info(&image_infos[index]),
+                }
+                .build()
+            })
+            .collect();
+        unsafe {
+            self.logical_device
+                .update_descriptor_sets(&descriptor_writes, &[])
+        };
+
+        let barrier = vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(
+                vk::AccessFlags::HOST_READ
+                    | vk::AccessFlags::TRANSFER_READ
+                    | vk::AccessFlags::SHADER_READ
+                    | vk::AccessFlags::SHADER_WRITE,
+            )
+            .build();
+        self.submit(|logical_device, command_buffer| unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                pipeline.pipeline,
+            );
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                pipeline.layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+            if !push_constants.is_empty() {
+                logical_device.cmd_push_constants(
+                    command_buffer,
+                    pipeline.layout,
+                    vk::ShaderStageFlags::COMPUTE,
+                    0,
+                    push_constants,
+                );
+            }
+            logical_device.cmd_dispatch(command_buffer, groups[0], groups[1], groups[2]);
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::HOST
+                    | vk::PipelineStageFlags::TRANSFER
+                    | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[barrier],
+                &[],
+                &[],
+            );
+        })
+    }
+
+    // Copies tightly packed texels from the start of `buffer` into `image`.
+    pub(super) fn copy_buffer_to_image<T>(
+        &self,
+        buffer: &ComputeBuffer<T>,
+        image: &ComputeImage,
+    ) -> Result<(), RuntimeError> {
+        let region = Self::image_region(image);
+        self.submit(|logical_device, command_buffer| unsafe {
+            logical_device.cmd_copy_buffer_to_image(
+                command_buffer,
+                buffer.buffer.buffer,
+                image.image.image,
+                vk::ImageLayout::GENERAL,
+                &[region],
+            );
+            Self::transfer_barrier(logical_device, command_buffer);
+        })
+    }
+
+    // Copies `image` into the start of `buffer`, tightly packed.
+    pub(super) fn copy_image_to_buffer<T>(
+        &self,
+        image: &ComputeImage,
+        buffer: &ComputeBuffer<T>,
+    ) -> Result<(), RuntimeError> {
+        let region = Self::image_region(image);
+        self.submit(|logical_device, command_buffer| unsafe {
+            logical_device.cmd_copy_image_to_buffer(
+                command_buffer,
+                image.image.image,
+                vk::ImageLayout::GENERAL,
+                buffer.buffer.buffer,
+                &[region],
+            );
+            Self::transfer_barrier(logical_device, command_buffer);
+        })
+    }
+
+    fn image_region(image: &ComputeImage) -> vk::BufferImageCopy {
+        vk::BufferImageCopy::builder()
+            .image_subresource(
+                vk::ImageSubresourceLayers::builder()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .layer_count(1)
+                    .build(),
+            )
+            .image_extent(vk::Extent3D {
+                width: image.width,
+                height: image.height,
+                depth: 1,
+            })
+            .build()
+    }
+
+    unsafe fn transfer_barrier(logical_device: &Device, command_buffer: vk::CommandBuffer) {
+        let barrier = vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(
+                vk::AccessFlags::HOST_READ
+                    | vk::AccessFlags::SHADER_READ
+                    | vk::AccessFlags::SHADER_WRITE,
+            )
+            .build();
+        logical_device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::HOST | vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &[barrier],
+            &[],
+            &[],
+        );
+    }
+
+    // Records a one-off command buffer with `record`, submits it and waits for it.
+    fn submit(&self, record: impl FnOnce(&Device, vk::CommandBuffer)) -> Result<(), RuntimeError> {
+        let commandbuf_allocate_info = vk::CommandBufferAllocateInfo::builder()
+            .command_pool(self.pool)
+            .command_buffer_count(1);
+        let command_buffers = unsafe {
+            self.logical_device
+                .allocate_command_buffers(&commandbuf_allocate_info)
+        }?;
+        let cmdbegininfo = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        let result = unsafe {
+            self.logical_device
+                .begin_command_buffer(command_buffers[0], &cmdbegininfo)
+                .and_then(|_| {
+                    record(self.logical_device, command_buffers[0]);
+                    self.logical_device.end_command_buffer(command_buffers[0])
+                })
+                .and_then(|_| {
+                    let submit_infos = [vk::SubmitInfo::builder()
+                        .command_buffers(&command_buffers)
+                        .build()];
+                    let fence = self
+                        .logical_device
+                        .create_fence(&vk::FenceCreateInfo::default(), None)?;
+                    let result = self
+                        .logical_device
+                        .queue_submit(self.queue, &submit_infos, fence)
+                        .and_then(|_| {
+                            self.logical_device
+                                .wait_for_fences(&[fence], true, u64::MAX)
+                        });
+                    self.logical_device.destroy_fence(fence, None);
+                    result
+                })
+        };
+        unsafe {
+            self.logical_device
+                .free_command_buffers(self.pool, &command_buffers)
+        };
+        Ok(result?)
+    }
+}
+
+pub(super) fn destroy_buffer<T>(
+    logical_device: &Device,
+    allocator: &mut Allocator,
+    mut buffer: ComputeBuffer<T>,
+) {
+    unsafe { buffer.buffer.cleanup(allocator, logical_device) };
+}
+
+pub(super) fn destroy_image(
+    logical_device: &Device,
+    allocator: &mut Allocator,
+    mut image: ComputeImage,
+) {
+    unsafe {
+        logical_device.destroy_image_view(image.image_view, None);
+        image.image.cleanup(allocator, logical_device);
+    }
+}
+
+pub(super) fn destroy_pipeline(logical_device: &Device, pipeline: ComputePipeline) {
+    unsafe {
+        logical_device.destroy_pipeline(pipeline.pipeline, None);
+        logical_device.destroy_pipeline_layout(pipeline.layout, None);
+        logical_device.destroy_descriptor_set_layout(pipeline.descriptor_set_layout, None);
+    }
+}
+
+impl Drop for ComputeContext {
+    fn drop(&mut self) {
+        info!("Destroying compute context");
+        unsafe {
+            self.logical_device
+                .device_wait_idle()
+                .expect("something wrong while waiting");
+            self.logical_device.destroy_command_pool(self.pool, None);
+
+            // Buffers, images and pipelines have to be handed back to the destroy functions.
+            let leaked = leaks::report(&self.logical_device);
+            std::mem::ManuallyDrop::drop(&mut self.allocator);
+
+            self.logical_device.destroy_device(None);
+            std::mem::ManuallyDrop::drop(&mut self.debug);
+            self.instance.destroy_instance(None);
+
+            if cfg!(debug_assertions) && leaked > 0 && !std::thread::panicking() {
+                panic!("{} GPU allocations leaked, see the log for which", leaked);
+            }
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use std::time::Duration;
+
+use ash::vk;
+
+use super::{assets::UploadBudget, gc::GcConfig};
+
+/// Per-frame descriptor sets are allocated for this many frames up front.
+pub const MAX_FRAMES_IN_FLIGHT: usize = 3;
+
+/// How often to render while the window can't be seen or isn't being used.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BackgroundMode {
+    /// Keep rendering as fast as possible.
+    Full,
+    /// Render at most this many frames per second.
+    Throttle(f32),
+    /// Stop rendering until the window comes back.
+    Pause,
+}
+
+impl BackgroundMode {
+    // The minimum time between frames, None when paused.
+    pub(super) fn frame_interval(&self) -> Option<Duration> {
+        match self {
+            BackgroundMode::Full => Some(Duration::ZERO),
+            BackgroundMode::Throttle(fps) if *fps > 0.0 => Some(Duration::from_secs_f32(1.0 / fps)),
+            BackgroundMode::Throttle(_) | BackgroundMode::Pause => None,
+        }
+    }
+}
+
+/// The highest frame rate to render at.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameLimit {
+    Unlimited,
+    Fps(f32),
+    /// Match the refresh rate of the monitor the window is on.
+    RefreshRate,
+}
+
+impl FrameLimit {
+    // The present mode that best fits the limit. FIFO paces frames to the display for free, so it
+    // is used unless the limit asks for more frames than the display shows, in which case MAILBOX
+    // keeps latency down without tearing.
+    pub(super) fn present_mode(
+        &self,
+        refresh_rate: Option<f32>,
+        available: &[vk::PresentModeKHR],
+    ) -> vk::PresentModeKHR {
+        let faster_than_display = match (self, refresh_rate) {
+            (FrameLimit::Unlimited, _) => true,
+            (FrameLimit::Fps(fps), Some(refresh_rate)) => *fps > refresh_rate,
+            (FrameLimit::Fps(_), None) | (FrameLimit::RefreshRate, _) => false,
+        };
+        if faster_than_display && available.contains(&vk::PresentModeKHR::MAILBOX) {
+            vk::PresentModeKHR::MAILBOX
+        } else {
+            vk::PresentModeKHR::FIFO
+        }
+    }
+
+    // The minimum time between frames the CPU has to enforce itself, on top of whatever pacing
+    // the present mode already provides.
+    pub(super) fn frame_interval(
+        &self,
+        refresh_rate: Option<f32>,
+        present_mode: vk::PresentModeKHR,
+    ) -> Duration {
+        let fps = match self {
+            FrameLimit::Unlimited => return Duration::ZERO,
+            FrameLimit::Fps(fps) => *fps,
+            FrameLimit::RefreshRate if present_mode == vk::PresentModeKHR::FIFO => {
+                return Duration::ZERO
+            }
+            FrameLimit::RefreshRate => match refresh_rate {
+                Some(refresh_rate) => refresh_rate,
+                None => return Duration::ZERO,
+            },
+        };
+        if fps > 0.0 {
+            Duration::from_secs_f32(1.0 / fps)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+/// How depth values are laid out in the depth buffer. Custom shaders should not assume either, the
+/// view projection matrix they are given already writes depth in the renderer's convention.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepthRange {
+    /// 0 at the near plane, 1 at the far plane, nearer fragments have smaller depth.
+    Standard,
+    /// 1 at the near plane, 0 at the far plane, nearer fragments have larger depth. Spreads float
+    /// precision far more evenly over distance, which matters for large scenes.
+    ReverseZ,
+}
+
+impl DepthRange {
+    /// The value the depth buffer is cleared to, i.e. "infinitely far away".
+    pub fn clear_depth(&self) -> f32 {
+        match self {
+            DepthRange::Standard => 1.0,
+            DepthRange::ReverseZ => 0.0,
+        }
+    }
+
+    /// The depth test passing fragments that are at least as near as what's already drawn.
+    pub fn compare_op(&self) -> vk::CompareOp {
+        match self {
+            DepthRange::Standard => vk::CompareOp::LESS_OR_EQUAL,
+            DepthRange::ReverseZ => vk::CompareOp::GREATER_OR_EQUAL,
+        }
+    }
+
+    // Turns a standard projection into one for this depth range, z' = w - z for reverse-Z.
+    pub(super) fn projection_adjustment(&self) -> na::Matrix4<f32> {
+        match self {
+            DepthRange::Standard => na::Matrix4::identity(),
+            DepthRange::ReverseZ => na::Matrix4::new(
+                1.0, 0.0, 0.0, 0.0, //
+                0.0, 1.0, 0.0, 0.0, //
+                0.0, 0.0, -1.0, 1.0, //
+                0.0, 0.0, 0.0, 1.0,
+            ),
+        }
+    }
+}
+
+/// HDR10 mastering metadata (SMPTE ST 2086 and CTA-861.3) handed to the display so it can tone
+/// map the output. Chromaticities are CIE 1931 xy, luminances are in nits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hdr10 {
+    pub red_primary: [f32; 2],
+    pub green_primary: [f32; 2],
+    pub blue_primary: [f32; 2],
+    pub white_point: [f32; 2],
+    /// Of the display the content was mastered on.
+    pub max_luminance: f32,
+    pub min_luminance: f32,
+    /// MaxCLL, the brightest pixel in the content.
+    pub max_content_light_level: f32,
+    /// MaxFALL, the brightest average over a whole frame.
+    pub max_frame_average_light_level: f32,
+}
+
+impl Default for Hdr10 {
+    // BT.2020 primaries with a D65 white point on a 1000 nit display.
+    fn default() -> Self {
+        Hdr10 {
+            red_primary: [0.708, 0.292],
+            green_primary: [0.170, 0.797],
+            blue_primary: [0.131, 0.046],
+            white_point: [0.3127, 0.3290],
+            max_luminance: 1000.0,
+            min_luminance: 0.001,
+            max_content_light_level: 1000.0,
+            max_frame_average_light_level: 400.0,
+        }
+    }
+}
+
+impl Hdr10 {
+    pub(super) fn metadata(&self) -> vk::HdrMetadataEXT {
+        let xy = |[x, y]: [f32; 2]| vk::XYColorEXT { x, y };
+        vk::HdrMetadataEXT::builder()
+            .display_primary_red(xy(self.red_primary))
+            .display_primary_green(xy(self.green_primary))
+            .display_primary_blue(xy(self.blue_primary))
+            .white_point(xy(self.white_point))
+            .max_luminance(self.max_luminance)
+            .min_luminance(self.min_luminance)
+            .max_content_light_level(self.max_content_light_level)
+            .max_frame_average_light_level(self.max_frame_average_light_level)
+            .build()
+    }
+}
+
+/// Shadows cast by the directional light, see `RendererConfig::shadows`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowConfig {
+    /// Width and height of the shadow map in texels.
+    pub resolution: u32,
+    /// How far from the camera shadows reach in world units. The map covers a square twice this
+    /// wide around the camera, so the shorter it is the sharper the shadows.
+    pub distance: f32,
+    /// How far surfaces are moved along their normal before they're tested against the map, in
+    /// world units. Raise it when lit surfaces shadow themselves in stripes, lower it when
+    /// shadows come loose from what casts them.
+    pub normal_bias: f32,
+}
+
+impl Default for ShadowConfig {
+    fn default() -> Self {
+        ShadowConfig {
+            resolution: 2048,
+            distance: 20.0,
+            normal_bias: 0.05,
+        }
+    }
+}
+
+/// The curve the output pass maps the scene's linear colour into the display's range with, after
+/// the camera's exposure. An HDR10 surface gets the exposed colour PQ encoded without a curve and
+/// leaves the rest to the display.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Tonemapper {
+    /// Cuts everything brighter than white off, what the renderer did before it drew in HDR.
+    /// Debug views are always shown this way.
+    Clamp,
+    /// x / (1 + x), keeps hues and never quite reaches white.
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve, more contrast and highlights that roll off to
+    /// white.
+    #[default]
+    Aces,
+}
+
+/// Renderer settings chosen by the application.
+#[derive(Clone, Debug)]
+pub struct RendererConfig {
+    /// How many frames can be recorded while the GPU is still drawing earlier ones, from 1 to
+    /// `MAX_FRAMES_IN_FLIGHT`. More lets the CPU and GPU overlap further at the cost of latency.
+    /// Fixed when the renderer is created.
+    pub frames_in_flight: usize,
+    /// Used while the window is visible but another window has focus.
+    pub unfocused: BackgroundMode,
+    /// Used while the window is minimised or completely covered, takes priority over `unfocused`.
+    pub occluded: BackgroundMode,
+    /// Applies while the window is in the foreground, background modes can only lower it further.
+    pub frame_limit: FrameLimit,
+    /// Overrides the present mode `frame_limit` would pick, e.g. IMMEDIATE for a vsync off
+    /// setting. Ignored when the surface doesn't support it. See `Vulkan::set_present_mode`.
+    pub present_mode: Option<vk::PresentModeKHR>,
+    /// Fixed when the renderer is created, changing it afterwards has no effect.
+    pub depth_range: DepthRange,
+    pub gc: GcConfig,
+    /// When set the renderer presents to an HDR10 (PQ, BT.2020) surface if the display offers one,
+    /// and sends this metadata with it. Picked when the renderer is created, use
+    /// `Vulkan::set_hdr10` to change the metadata afterwards.
+    pub hdr10: Option<Hdr10>,
+    /// Shadows from the sun (the `Sky`'s or `Vulkan::lighting`'s) when set. Read every frame,
+    /// changing the resolution waits for the GPU to finish before the map is recreated.
+    pub shadows: Option<ShadowConfig>,
+    /// Read every frame, the exposure is the camera's, see `Camera::set_exposure`.
+    pub tonemapper: Tonemapper,
+    /// Turns on the Vulkan validation layer and logs what it reports, on by default in debug
+    /// builds. The JR_VALIDATION environment variable (1 or 0) overrides it, and it's left off if
+    /// the Vulkan SDK isn't installed.
+    pub validation: bool,
+    /// Counts vertices, primitives and shader invocations every frame for `Vulkan::frame_stats`,
+    /// where the device supports it. Fixed when the renderer is created.
+    pub pipeline_statistics: bool,
+    /// Keeps a CPU side copy of every texture and mesh registered, so `Vulkan::recover` can
+    /// upload them again after the device is lost. Costs as much memory again as the resources
+    /// themselves. Fixed when the renderer is created.
+    pub device_lost_recovery: bool,
+    /// Limits how much streamed content is uploaded per frame. Read every frame.
+    pub uploads: UploadBudget,
+    /// Polls the files from `Vulkan::load_texture`, `Vulkan::load_mesh` and
+    /// `Vulkan::add_asset_dependency` and reloads them when they change, on by default in debug
+    /// builds. Read every frame.
+    pub hot_reload: bool,
+}
+
+impl RendererConfig {
+    pub(super) fn present_mode(
+        &self,
+        refresh_rate: Option<f32>,
+        available: &[vk::PresentModeKHR],
+    ) -> vk::PresentModeKHR {
+        match self.present_mode {
+            Some(present_mode) if available.contains(&present_mode) => present_mode,
+            _ => self.frame_limit.present_mode(refresh_rate, available),
+        }
+    }
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        RendererConfig {
+            frames_in_flight: 2,
+            unfocused: BackgroundMode::Full,
+            occluded: BackgroundMode::Pause,
+            frame_limit: FrameLimit::RefreshRate,
+            present_mode: None,
+            depth_range: DepthRange::Standard,
+            gc: GcConfig::default(),
+            hdr10: None,
+            shadows: None,
+            tonemapper: Tonemapper::default(),
+            validation: cfg!(debug_assertions),
+            pipeline_statistics: false,
+            device_lost_recovery: false,
+            uploads: UploadBudget::default(),
+            hot_reload: cfg!(debug_assertions),
+        }
+    }
+}
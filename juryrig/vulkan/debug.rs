@@ -0,0 +1,192 @@
+use std::{
+    ffi::{c_void, CStr, CString},
+    sync::Mutex,
+};
+
+use log::{info, log, warn, Level};
+
+use ash::{
+    extensions::ext::DebugUtils,
+    vk::{self, Handle},
+    Device, Entry, Instance,
+};
+
+use super::toasts::{self, ToastLevel};
+
+// The debug utils of every device with validation on, keyed by device like `leaks`, so objects
+// can be named and commands labelled from wherever they're made without passing them around.
+static DEVICES: Mutex<Vec<(u64, DebugUtils)>> = Mutex::new(Vec::new());
+
+pub(super) struct Debug {
+    debug_utils: DebugUtils,
+    utils_messenger: vk::DebugUtilsMessengerEXT,
+    // Set by `attach`.
+    device: Option<u64>,
+}
+
+fn with_debug_utils(logical_device: &Device, f: impl FnOnce(&DebugUtils)) {
+    let device = logical_device.handle().as_raw();
+    let devices = DEVICES.lock().unwrap();
+    if let Some((_, debug_utils)) = devices.iter().find(|(owner, _)| *owner == device) {
+        f(debug_utils);
+    }
+}
+
+/// Names `object` in validation messages and tools like RenderDoc. Does nothing unless the device
+/// was created with validation on.
+pub fn set_object_name<T: Handle>(logical_device: &Device, object: T, name: &str) {
+    with_debug_utils(logical_device, |debug_utils| {
+        let name = CString::new(name.replace('\0', "")).unwrap();
+        let name_info = vk::DebugUtilsObjectNameInfoEXT::builder()
+            .object_type(T::TYPE)
+            .object_handle(object.as_raw())
+            .object_name(&name);
+        // Only fails when out of host memory, the object just stays unnamed.
+        let _ =
+            unsafe { debug_utils.set_debug_utils_object_name(logical_device.handle(), &name_info) };
+    });
+}
+
+/// Groups the commands recorded until the matching `cmd_end_debug_label` under `name` in capture
+/// tools. Labels nest, and like names they're only recorded with validation on.
+pub fn cmd_begin_debug_label(
+    logical_device: &Device,
+    command_buffer: vk::CommandBuffer,
+    name: &str,
+) {
+    with_debug_utils(logical_device, |debug_utils| {
+        let name = CString::new(name.replace('\0', "")).unwrap();
+        let label = vk::DebugUtilsLabelEXT::builder().label_name(&name);
+        unsafe { debug_utils.cmd_begin_debug_utils_label(command_buffer, &label) };
+    });
+}
+
+pub fn cmd_end_debug_label(logical_device: &Device, command_buffer: vk::CommandBuffer) {
+    with_debug_utils(logical_device, |debug_utils| unsafe {
+        debug_utils.cmd_end_debug_utils_label(command_buffer)
+    });
+}
+
+pub(super) fn validation_layer_name() -> &'static CStr {
+    unsafe { CStr::from_bytes_with_nul_unchecked(b"VK_LAYER_KHRONOS_validation\0") }
+}
+
+// Whether to turn on the validation layer and the messenger logging what it finds. The
+// JR_VALIDATION environment variable overrides `requested`, 1 turns it on and 0 off. Both come
+// with the Vulkan SDK, so it stays off with a warning where they aren't installed.
+pub(super) fn validation_enabled(entry: &Entry, requested: bool) -> Result<bool, vk::Result> {
+    let requested = match std::env::var("JR_VALIDATION").as_deref() {
+        Ok("1") => true,
+        Ok("0") => false,
+        _ => requested,
+    };
+    if !requested {
+        return Ok(false);
+    }
+
+    let layers = entry.enumerate_instance_layer_properties()?;
+    let has_layer = layers.iter().any(|layer| {
+        let layer_name = unsafe { CStr::from_ptr(layer.layer_name.as_ptr()) };
+        layer_name == validation_layer_name()
+    });
+    let extensions = entry.enumerate_instance_extension_properties(None)?;
+    let has_debug_utils = extensions.iter().any(|extension| {
+        let extension_name = unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) };
+        extension_name == DebugUtils::name()
+    });
+    if !has_layer || !has_debug_utils {
+        warn!("Validation was asked for but the Vulkan SDK's validation layer isn't installed");
+        return Ok(false);
+    }
+    info!("Validation layer enabled");
+    Ok(true)
+}
+
+impl Debug {
+    pub(super) fn new(
+        entry: &Entry,
+        instance: &Instance,
+    ) -> std::result::Result<Debug, vk::Result> {
+        let debug_create_info = Self::create_info();
+        let debug_utils = DebugUtils::new(&entry, &instance);
+        let utils_messenger =
+            unsafe { debug_utils.create_debug_utils_messenger(&debug_create_info, None) }?;
+        Ok(Debug {
+            debug_utils,
+            utils_messenger,
+            device: None,
+        })
+    }
+
+    // Lets `set_object_name` and the label helpers work on `logical_device` until this is
+    // dropped.
+    pub(super) fn attach(&mut self, logical_device: &Device) {
+        let device = logical_device.handle().as_raw();
+        DEVICES
+            .lock()
+            .unwrap()
+            .push((device, self.debug_utils.clone()));
+        self.device = Some(device);
+    }
+
+    pub(super) fn create_info() -> vk::DebugUtilsMessengerCreateInfoEXTBuilder<'static> {
+        vk::DebugUtilsMessengerCreateInfoEXT::builder()
+            .message_severity(
+                vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+                    | vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE
+                    | vk::DebugUtilsMessageSeverityFlagsEXT::INFO
+                    | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+            )
+            .message_type(
+                vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+                    | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
+                    | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
+            )
+            .pfn_user_callback(Some(Self::vulkan_debug_utils_callback))
+    }
+
+    unsafe extern "system" fn vulkan_debug_utils_callback(
+        message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+        message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+        p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
+        _p_user_data: *mut c_void,
+    ) -> vk::Bool32 {
+        let message = CStr::from_ptr((*p_callback_data).p_message);
+        let ty = format!("{:?}", message_type).to_lowercase();
+        if message_severity == vk::DebugUtilsMessageSeverityFlagsEXT::ERROR {
+            toasts::raise(ToastLevel::Error, &message.to_string_lossy());
+        } else if message_severity == vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+            && message_type.contains(vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION)
+        {
+            toasts::raise(ToastLevel::Warning, &message.to_string_lossy());
+        }
+        log!(
+            match message_severity {
+                vk::DebugUtilsMessageSeverityFlagsEXT::INFO => Level::Debug,
+                vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => Level::Error,
+                vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE => Level::Trace,
+                vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => Level::Warn,
+                _ => Level::Info,
+            },
+            "VK:{} {:?}",
+            ty,
+            message
+        );
+        vk::FALSE
+    }
+}
+
+impl Drop for Debug {
+    fn drop(&mut self) {
+        if let Some(device) = self.device {
+            DEVICES
+                .lock()
+                .unwrap()
+                .retain(|(owner, _)| *owner != device);
+        }
+        unsafe {
+            self.debug_utils
+                .destroy_debug_utils_messenger(self.utils_messenger, None)
+        };
+    }
+}
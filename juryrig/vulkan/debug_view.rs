@@ -0,0 +1,49 @@
+use super::pipeline_store::RenderState;
+
+/// Replaces the shading of the scene with something that shows what the pipeline was fed. Each
+/// view is a permutation of the scene pipeline selected by a specialization constant, see the end
+/// of shaders/fragment.glsl.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DebugView {
+    #[default]
+    Off = 0,
+    /// World space normals, xyz mapped to rgb.
+    Normals = 1,
+    /// Texture coordinates after the instance's uv rect, repeating every unit.
+    Uvs = 2,
+    /// How many times each pixel was shaded, brighter is more. Depth testing is off so hidden
+    /// surfaces count too.
+    Overdraw = 3,
+    /// A distinct colour per texture index.
+    TextureIndex = 4,
+    /// Distance from the camera, white up close fading to black.
+    Depth = 5,
+    /// Triangle edges only, shaded as normal. Needs the device's `fillModeNonSolid` feature, see
+    /// `Vulkan::supports_debug_view`.
+    Wireframe = 6,
+}
+
+impl DebugView {
+    pub const ALL: [DebugView; 7] = [
+        DebugView::Off,
+        DebugView::Normals,
+        DebugView::Uvs,
+        DebugView::Overdraw,
+        DebugView::TextureIndex,
+        DebugView::Depth,
+        DebugView::Wireframe,
+    ];
+
+    /// The view after this one, wrapping back to `Off`.
+    pub fn next(self) -> DebugView {
+        DebugView::ALL[(self as usize + 1) % DebugView::ALL.len()]
+    }
+
+    // The fixed function state the view is drawn with, the default apart from wireframe.
+    pub(super) fn render_state(self) -> RenderState {
+        RenderState {
+            wireframe: self == DebugView::Wireframe,
+            ..RenderState::default()
+        }
+    }
+}
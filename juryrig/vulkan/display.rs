@@ -0,0 +1,66 @@
+use winit::{monitor::MonitorHandle, window::Window};
+
+#[derive(Clone, Debug)]
+pub struct VideoModeInfo {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u16,
+    pub refresh_rate: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct MonitorInfo {
+    pub name: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    /// Position of the top left corner on the desktop, in physical pixels.
+    pub x: i32,
+    pub y: i32,
+    pub scale_factor: f64,
+    /// In Hz, None if the platform doesn't report it.
+    pub refresh_rate: Option<f32>,
+    /// The fullscreen modes the monitor supports.
+    pub video_modes: Vec<VideoModeInfo>,
+}
+
+impl From<&MonitorHandle> for MonitorInfo {
+    fn from(monitor: &MonitorHandle) -> Self {
+        let size = monitor.size();
+        let position = monitor.position();
+        MonitorInfo {
+            name: monitor.name(),
+            width: size.width,
+            height: size.height,
+            x: position.x,
+            y: position.y,
+            scale_factor: monitor.scale_factor(),
+            refresh_rate: monitor
+                .refresh_rate_millihertz()
+                .map(|millihertz| millihertz as f32 / 1000.0),
+            video_modes: monitor
+                .video_modes()
+                .map(|mode| VideoModeInfo {
+                    width: mode.size().width,
+                    height: mode.size().height,
+                    bit_depth: mode.bit_depth(),
+                    refresh_rate: mode.refresh_rate_millihertz() as f32 / 1000.0,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Every monitor connected to the system.
+pub fn monitors(window: &Window) -> Vec<MonitorInfo> {
+    window
+        .available_monitors()
+        .map(|monitor| MonitorInfo::from(&monitor))
+        .collect()
+}
+
+/// The monitor the window is mostly on.
+pub fn current_monitor(window: &Window) -> Option<MonitorInfo> {
+    window
+        .current_monitor()
+        .map(|monitor| MonitorInfo::from(&monitor))
+}
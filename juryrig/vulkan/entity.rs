@@ -0,0 +1,376 @@
+use std::collections::{HashMap, HashSet};
+
+use ash::{vk, Device};
+use gpu_allocator::vulkan::Allocator;
+use na::{Matrix4, Vector3};
+use uuid::Uuid;
+
+use super::{
+    atlas::SpriteHandle,
+    bounds::Frustum,
+    frame_allocator::{FrameAllocator, FrameSlice},
+    light_probe::LightProbeGrid,
+    material::{MaterialHandle, MaterialStore},
+    mesh::{MeshHandle, MeshStore},
+    pipeline_store::PipelineHandle,
+    render_target::OffscreenTarget,
+    scene::Scene,
+    texture::{TextureHandle, TextureStore, UvRect},
+    InstanceData,
+};
+
+// What `Entities::upload` reads to work out the instances, borrowed from the renderer.
+pub(super) struct UploadContext<'a> {
+    pub(super) textures: &'a TextureStore,
+    pub(super) materials: &'a MaterialStore,
+    pub(super) default_material: &'a MaterialHandle,
+    pub(super) light_probes: &'a LightProbeGrid,
+    pub(super) scene: &'a Scene,
+    pub(super) meshes: &'a MeshStore,
+    // The main camera's.
+    pub(super) frustum: &'a Frustum,
+    // The sun's shadow map's, None while shadows are off.
+    pub(super) shadow_frustum: Option<&'a Frustum>,
+    // Their bakes are staged in batches of their own, see `Batch::view`.
+    pub(super) render_targets: &'a [OffscreenTarget],
+}
+
+/// Something drawn every frame: a mesh with a texture, placed by `transform`. Holding the entity
+/// keeps its mesh and texture alive.
+#[derive(Clone)]
+pub struct Entity {
+    pub mesh: MeshHandle,
+    pub texture: TextureHandle,
+    pub transform: Matrix4<f32>,
+    /// The renderer's default material when None.
+    pub material: Option<MaterialHandle>,
+    pub uv_rect: UvRect,
+    /// Multiplies the texture and material colour, see `InstanceData::tint`.
+    pub tint: [f32; 4],
+    /// Drawn with the scene pipeline when None, see `Vulkan::register_pipeline`.
+    pub pipeline: Option<PipelineHandle>,
+    /// Entities with a key are drawn after those without, lowest key first, e.g. to draw a held
+    /// weapon after the world. Entities sharing a key are still batched by pipeline and mesh.
+    /// See `sort_key`.
+    pub sort_key: Option<u64>,
+}
+
+/// A key for `Entity::sort_key` ordering by `layer`, then by `pipeline` so a layer's draws with
+/// the same pipeline stay together, then by `depth`, nearest first. None is the scene pipeline,
+/// drawn first in its layer. Negative depths count as 0.
+pub fn sort_key(layer: u16, pipeline: Option<&PipelineHandle>, depth: f32) -> u64 {
+    let pipeline = pipeline.map_or(0, PipelineHandle::sort_bits);
+    // Non-negative floats order the same as their bits.
+    ((layer as u64) << 48) | ((pipeline as u64) << 32) | depth.max(0.0).to_bits() as u64
+}
+
+impl Entity {
+    /// At the origin with the default material and pipeline and the whole texture.
+    pub fn new(mesh: MeshHandle, texture: TextureHandle) -> Entity {
+        Entity {
+            mesh,
+            texture,
+            transform: Matrix4::identity(),
+            material: None,
+            uv_rect: UvRect::FULL,
+            tint: [1.0; 4],
+            pipeline: None,
+            sort_key: None,
+        }
+    }
+
+    /// Like `new`, showing one sprite of an atlas on the mesh.
+    pub fn from_sprite(mesh: MeshHandle, sprite: &SpriteHandle) -> Entity {
+        Entity {
+            uv_rect: sprite.uv_rect(),
+            ..Entity::new(mesh, sprite.texture().clone())
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EntityId {
+    id: Uuid,
+}
+
+// A run of instances in the instance buffer that share a view, sort key, pipeline and mesh.
+pub(super) struct Batch {
+    // The frame uniform view of the render target baking the instances, only drawn there. None
+    // for the batches every other view draws.
+    pub(super) view: Option<u32>,
+    pub(super) sort_key: Option<u64>,
+    pub(super) pipeline: Option<PipelineHandle>,
+    pub(super) mesh: MeshHandle,
+    pub(super) first_instance: u32,
+    pub(super) instance_count: u32,
+    // How many of the instances, from the first, the main camera can see. The rest are only
+    // drawn by shadows and render targets.
+    pub(super) visible_count: u32,
+    // The run of instances the sun's shadow map can see. They straddle the end of the visible
+    // ones, so both are a single range.
+    pub(super) shadow_first: u32,
+    pub(super) shadow_count: u32,
+}
+
+// What an instance is batched and ordered by.
+struct Staged {
+    view: Option<u32>,
+    sort_key: Option<u64>,
+    pipeline: Option<PipelineHandle>,
+    mesh: MeshHandle,
+    // Whether the main camera can see the instance.
+    visible: bool,
+    // Whether the sun's shadow map can.
+    casts_shadow: bool,
+}
+
+// The entities added with `Vulkan::add_entity`. Every frame their instances are rebuilt, sorted
+// by sort key, pipeline and mesh so each mesh is drawn with one call per key and pipeline, and
+// written to the frame allocator.
+pub(super) struct Entities {
+    entities: HashMap<Uuid, Entity>,
+    // Left out of every view but the bakes asking for them, see `set_hidden`.
+    hidden: HashSet<Uuid>,
+    // Rebuilt every frame, kept to reuse the allocations.
+    staged: Vec<(Staged, InstanceData)>,
+    instances: Vec<InstanceData>,
+    batches: Vec<Batch>,
+    // Where the last `upload` wrote the instances.
+    uploaded: Option<FrameSlice>,
+}
+
+impl Entities {
+    pub(super) fn new() -> Entities {
+        Entities {
+            entities: HashMap::new(),
+            hidden: HashSet::new(),
+            staged: vec![],
+            instances: vec![],
+            batches: vec![],
+            uploaded: None,
+        }
+    }
+
+    pub(super) fn add(&mut self, entity: Entity) -> EntityId {
+        let id = EntityId { id: Uuid::new_v4() };
+        self.entities.insert(id.id, entity);
+        id
+    }
+
+    pub(super) fn get(&self, id: &EntityId) -> Option<&Entity> {
+        self.entities.get(&id.id)
+    }
+
+    pub(super) fn get_mut(&mut self, id: &EntityId) -> Option<&mut Entity> {
+        self.entities.get_mut(&id.id)
+    }
+
+    pub(super) fn remove(&mut self, id: &EntityId) -> Option<Entity> {
+        self.hidden.remove(&id.id);
+        self.entities.remove(&id.id)
+    }
+
+    // Hidden entities are still drawn into the render targets baking them, for impostors.
+    pub(super) fn set_hidden(&mut self, id: &EntityId, hidden: bool) {
+        match hidden && self.entities.contains_key(&id.id) {
+            true => self.hidden.insert(id.id),
+            false => self.hidden.remove(&id.id),
+        };
+    }
+
+    pub(super) fn iter(&self) -> impl Iterator<Item = (EntityId, &Entity)> {
+        self.entities
+            .iter()
+            .map(|(&id, entity)| (EntityId { id }, entity))
+    }
+
+    // Writes the instance of every entity, and of every entity in the context's scene at its
+    // node's world transform, for frame `slot` and works out the batches to draw them with.
+    // Entities whose texture isn't registered are left out. Those whose mesh bounds are outside
+    // the frustum go at the end of their batch, with those the shadow map sees next to the visible
+    // ones. The entities a render target is baking are staged again after the rest, in batches for
+    // its view alone.
+    pub(super) fn upload(
+        &mut self,
+        frame_allocator: &mut FrameAllocator,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        slot: usize,
+        context: &UploadContext,
+    ) -> Result<(), vk::Result> {
+        let UploadContext {
+            textures,
+            materials,
+            default_material,
+            light_probes,
+            scene,
+            meshes,
+            frustum,
+            shadow_frustum,
+            render_targets,
+        } = context;
+        self.staged.clear();
+        let baked = render_targets
+            .iter()
+            .enumerate()
+            .filter_map(|(index, target)| Some((index as u32 + 1, target.bake.as_ref()?)))
+            .flat_map(|(view, bake)| {
+                bake.iter()
+                    .filter_map(|id| self.entities.get(&id.id))
+                    .map(move |entity| (entity, entity.transform, Some(view)))
+            });
+        let placed = self
+            .entities
+            .iter()
+            .filter(|(id, _)| !self.hidden.contains(id))
+            .map(|(_, entity)| (entity, entity.transform, None))
+            .chain(
+                scene
+                    .drawn()
+                    .map(|(_, entity, transform)| (entity, transform, None)),
+            )
+            .chain(baked);
+        for (entity, transform, view) in placed {
+            let texture_index = match textures.index(&entity.texture) {
+                Some(index) => index,
+                None => continue,
+            };
+            let material = entity.material.as_ref().unwrap_or(default_material);
+            let origin = Vector3::new(transform[(0, 3)], transform[(1, 3)], transform[(2, 3)]);
+            // The sphere is quicker to test and rules most things out, the box catches the rest.
+            let seen_by = |frustum: &Frustum| {
+                meshes.get(&entity.mesh).is_none_or(|mesh| {
+                    frustum.intersects(&mesh.bounding_sphere().transformed(&transform))
+                        && frustum.intersects_aabb(&mesh.aabb().transformed(&transform))
+                })
+            };
+            let visible = view.is_none() && seen_by(frustum);
+            let casts_shadow = view.is_none() && shadow_frustum.is_some_and(seen_by);
+            self.staged.push((
+                Staged {
+                    view,
+                    sort_key: entity.sort_key,
+                    pipeline: entity.pipeline,
+                    mesh: entity.mesh.clone(),
+                    visible,
+                    casts_shadow,
+                },
+                InstanceData {
+                    model: transform.into(),
+                    texture_index,
+                    wind: materials
+                        .get(material)
+                        .map(|material| material.wind_params())
+                        .unwrap_or_default(),
+                    material_index: materials.index(material).unwrap_or(0),
+                    uv_rect: entity.uv_rect,
+                    ambient: light_probes.sample(origin).irradiance_terms(),
+                    tint: entity.tint,
+                },
+            ));
+        }
+        self.staged.sort_by_key(|(staged, _)| {
+            (
+                staged.view,
+                staged.sort_key,
+                staged.pipeline,
+                staged.mesh.id(),
+                cull_order(staged.visible, staged.casts_shadow),
+            )
+        });
+
+        self.batches.clear();
+        self.instances.clear();
+        for (index, (staged, instance)) in self.staged.iter().enumerate() {
+            match self.batches.last_mut() {
+                Some(batch)
+                    if batch.view == staged.view
+                        && batch.sort_key == staged.sort_key
+                        && batch.pipeline == staged.pipeline
+                        && batch.mesh.id() == staged.mesh.id() =>
+                {
+                    batch.instance_count += 1;
+                    batch.visible_count += staged.visible as u32;
+                    if staged.casts_shadow {
+                        if batch.shadow_count == 0 {
+                            batch.shadow_first = index as u32;
+                        }
+                        batch.shadow_count += 1;
+                    }
+                }
+                _ => self.batches.push(Batch {
+                    view: staged.view,
+                    sort_key: staged.sort_key,
+                    pipeline: staged.pipeline,
+                    mesh: staged.mesh.clone(),
+                    first_instance: index as u32,
+                    instance_count: 1,
+                    visible_count: staged.visible as u32,
+                    shadow_first: index as u32,
+                    shadow_count: staged.casts_shadow as u32,
+                }),
+            }
+            self.instances.push(*instance);
+        }
+        self.uploaded = match self.instances.is_empty() {
+            true => None,
+            false => {
+                Some(frame_allocator.push(allocator, logical_device, slot, &self.instances)?)
+            }
+        };
+        Ok(())
+    }
+
+    // The batches from the last `upload`, drawn from `instances`.
+    pub(super) fn batches(&self) -> &[Batch] {
+        &self.batches
+    }
+
+    // Where the last `upload` wrote the instances, None when there were none.
+    pub(super) fn instances(&self) -> Option<FrameSlice> {
+        self.uploaded
+    }
+}
+
+// Orders a batch's instances so the visible ones come first and the shadow casters are a single
+// run: visible casters last among the visible, hidden ones first among the rest.
+fn cull_order(visible: bool, casts_shadow: bool) -> (bool, bool) {
+    (!visible, casts_shadow == visible)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sort_key_orders_by_layer_then_pipeline_then_depth() {
+        let pipeline = PipelineHandle::unregistered();
+        assert!(sort_key(0, None, 1.0) < sort_key(0, None, 2.0));
+        assert!(sort_key(0, None, 1000.0) < sort_key(1, None, 0.5));
+        assert!(sort_key(0, None, 1000.0) < sort_key(0, Some(&pipeline), 0.5));
+        assert!(sort_key(0, Some(&pipeline), 1000.0) < sort_key(1, None, 0.5));
+        assert_eq!(sort_key(0, None, -1.0), sort_key(0, None, 0.0));
+    }
+
+    #[test]
+    fn visible_instances_and_shadow_casters_are_each_one_run() {
+        let mut instances = vec![];
+        for visible in [true, false] {
+            for casts_shadow in [true, false] {
+                instances.extend([(visible, casts_shadow); 2]);
+            }
+        }
+        instances.reverse();
+        instances.sort_by_key(|&(visible, casts_shadow)| cull_order(visible, casts_shadow));
+        let visible = instances.iter().take_while(|(visible, _)| *visible).count();
+        assert_eq!(visible, 4);
+        assert!(instances[visible..].iter().all(|(visible, _)| !visible));
+        let first = instances.iter().position(|(_, casts)| *casts).unwrap();
+        let count = instances[first..]
+            .iter()
+            .take_while(|(_, casts)| *casts)
+            .count();
+        assert_eq!(count, 4);
+        assert!(first < visible && first + count > visible);
+    }
+}
@@ -0,0 +1,97 @@
+use ash::{vk, LoadingError};
+use gpu_allocator::AllocationError;
+
+use super::shaders::ShaderError;
+use super::vertex_format::VertexLayoutError;
+
+#[derive(Debug)]
+pub enum RuntimeError {
+    VKErr(vk::Result),
+    AllocationError(AllocationError),
+    VertexLayoutError(VertexLayoutError),
+    /// A shader file that couldn't be read or compiled.
+    Shader(ShaderError),
+    /// The handle doesn't refer to anything registered with this renderer.
+    InvalidHandle,
+    /// The region doesn't fit in the texture, or the pixels don't fill it or match its format.
+    InvalidRegion,
+    /// Every texture index the renderer has room for is taken, see `Vulkan::max_textures`.
+    TooManyTextures,
+    /// Cubemap faces that aren't square and the same size, or a panorama with no pixels.
+    InvalidCubemap,
+    /// A `ComputeBuffer` read or written from the host that isn't host visible, or data that
+    /// doesn't fit in it.
+    InvalidBufferAccess,
+    /// The device lacks an optional feature this needs, named as in the Vulkan spec.
+    UnsupportedFeature(&'static str),
+    /// The swapchain no longer matches the surface, usually a resize the window hasn't reported
+    /// yet. Nothing was drawn, call `Vulkan::resize_surface` and carry on.
+    SwapchainOutOfDate,
+    /// The GPU crashed, hung or went away. The renderer can't be used any more, drop it and
+    /// create a new one.
+    DeviceLost,
+}
+
+#[derive(Debug)]
+/// Error enum for issues encountered during initialization.
+pub enum InitError {
+    /// Error propagated directly from Vulkan.
+    VKErr(vk::Result),
+    LoadingError(LoadingError),
+    DeviceSelectionError(&'static str),
+    AllocationError(AllocationError),
+    /// The built-in pipeline's vertex inputs don't match its shader.
+    VertexLayoutError(VertexLayoutError),
+}
+
+impl From<vk::Result> for InitError {
+    fn from(value: vk::Result) -> Self {
+        InitError::VKErr(value)
+    }
+}
+
+impl From<LoadingError> for InitError {
+    fn from(value: LoadingError) -> Self {
+        InitError::LoadingError(value)
+    }
+}
+
+impl From<AllocationError> for InitError {
+    fn from(value: AllocationError) -> Self {
+        InitError::AllocationError(value)
+    }
+}
+
+impl From<VertexLayoutError> for InitError {
+    fn from(value: VertexLayoutError) -> Self {
+        InitError::VertexLayoutError(value)
+    }
+}
+
+impl From<vk::Result> for RuntimeError {
+    fn from(value: vk::Result) -> Self {
+        match value {
+            vk::Result::ERROR_OUT_OF_DATE_KHR => RuntimeError::SwapchainOutOfDate,
+            vk::Result::ERROR_DEVICE_LOST => RuntimeError::DeviceLost,
+            _ => RuntimeError::VKErr(value),
+        }
+    }
+}
+
+impl From<AllocationError> for RuntimeError {
+    fn from(value: AllocationError) -> Self {
+        RuntimeError::AllocationError(value)
+    }
+}
+
+impl From<VertexLayoutError> for RuntimeError {
+    fn from(value: VertexLayoutError) -> Self {
+        RuntimeError::VertexLayoutError(value)
+    }
+}
+
+impl From<ShaderError> for RuntimeError {
+    fn from(value: ShaderError) -> Self {
+        RuntimeError::Shader(value)
+    }
+}
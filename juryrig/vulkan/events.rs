@@ -0,0 +1,75 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use ash::vk;
+use uuid::Uuid;
+
+use crate::alloc_telemetry::{self, Subsystem};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AssetKind {
+    Texture,
+    Mesh,
+}
+
+/// Things the renderer did on its own that applications might want to react to.
+#[derive(Clone, Debug)]
+pub enum EngineEvent {
+    /// The swapchain and everything sized to it were rebuilt.
+    SwapchainRecreated {
+        width: u32,
+        height: u32,
+        image_count: usize,
+    },
+    /// The window moved to a display with a different refresh rate or the renderer picked a
+    /// different present mode for it.
+    CapabilitiesChanged {
+        refresh_rate: Option<f32>,
+        present_mode: vk::PresentModeKHR,
+    },
+    /// `id` matches the id of the handle that was returned for the asset.
+    AssetLoaded {
+        kind: AssetKind,
+        id: Uuid,
+    },
+    AssetFailed {
+        kind: AssetKind,
+        error: String,
+    },
+    /// The garbage collector retired a texture whose handles were all dropped, or it was
+    /// unregistered.
+    TextureEvicted {
+        id: Uuid,
+    },
+    /// Same as `TextureEvicted`, for a mesh.
+    MeshEvicted {
+        id: Uuid,
+    },
+    /// With `RendererConfig::hot_reload`, a watched file changed or one it's built from did, see
+    /// `Vulkan::add_asset_dependency`. Sent for each file after the ones it's built from. Files
+    /// from `load_texture` and `load_mesh` are already being loaded again into their handles,
+    /// others are for the application to reload.
+    AssetChanged {
+        path: std::path::PathBuf,
+    },
+}
+
+// Hands every event to every subscriber. Subscribers that dropped their receiver are forgotten
+// the next time something is sent.
+#[derive(Default)]
+pub(super) struct EventBus {
+    subscribers: Vec<Sender<EngineEvent>>,
+}
+
+impl EventBus {
+    pub(super) fn subscribe(&mut self) -> Receiver<EngineEvent> {
+        let (sender, receiver) = channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    pub(super) fn send(&mut self, event: EngineEvent) {
+        let _scope = alloc_telemetry::scope(Subsystem::Events);
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
@@ -0,0 +1,176 @@
+use ash::{vk, Device};
+use gpu_allocator::vulkan::Allocator;
+use log::debug;
+
+use super::{buffer::Buffer, gc::Collector};
+
+// What a frame's buffer starts at, it doubles whenever a frame outgrows it.
+const INITIAL_SIZE: u64 = 256 * 1024;
+
+// Every allocation starts on a multiple of this, the largest `minUniformBufferOffsetAlignment`
+// the spec allows, so any of them can back a uniform buffer.
+const ALIGNMENT: u64 = 256;
+
+// Where `FrameAllocator::push` put something, valid until its frame comes round again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct FrameSlice {
+    pub(super) buffer: vk::Buffer,
+    pub(super) offset: u64,
+}
+
+// A linear allocator over one CpuToGpu buffer per frame in flight, for data written every frame
+// and only read by that frame's commands: entity instances, UI geometry and the frame uniforms.
+// A push is a copy and a pointer bump, and the whole buffer is reused once the frame's fence has
+// signalled, so nothing has to keep and grow buffers of its own.
+pub(super) struct FrameAllocator {
+    buffers: Vec<Option<Buffer<u8>>>,
+    // How much of each frame's buffer has been handed out.
+    heads: Vec<u64>,
+    // Outgrown buffers, kept until the frames reading them are done.
+    retired: Collector<Buffer<u8>>,
+    frame: u64,
+}
+
+impl FrameAllocator {
+    pub(super) fn new(frames_in_flight: usize) -> FrameAllocator {
+        FrameAllocator {
+            buffers: (0..frames_in_flight).map(|_| None).collect(),
+            heads: vec![0; frames_in_flight],
+            retired: Collector::new(),
+            frame: 0,
+        }
+    }
+
+    // Starts frame `slot` over from the beginning of its buffer. The submission last recorded in
+    // the slot must have finished.
+    pub(super) fn begin_frame(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        slot: usize,
+        frame: u64,
+    ) {
+        self.frame = frame;
+        self.heads[slot] = 0;
+        for mut buffer in self
+            .retired
+            .ready(frame, self.buffers.len() as u64 + 1, usize::MAX)
+        {
+            unsafe { buffer.cleanup(allocator, logical_device) };
+        }
+    }
+
+    // Copies `data` into frame `slot`'s buffer. When it doesn't fit the frame moves on to a
+    // buffer twice the size, what was pushed before stays where it is.
+    pub(super) fn push<T: Copy>(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        slot: usize,
+        data: &[T],
+    ) -> Result<FrameSlice, vk::Result> {
+        let bytes = unsafe {
+            std::slice::from_raw_parts(data.as_ptr() as *const u8, std::mem::size_of_val(data))
+        };
+        let size = bytes.len() as u64;
+        let capacity = self.buffers[slot].as_ref().map_or(0, Buffer::len);
+        let (offset, grow) = place(self.heads[slot], capacity, size);
+        if let Some(grown) = grow {
+            let buffer = Buffer::new(
+                allocator,
+                logical_device,
+                grown,
+                vk::BufferUsageFlags::VERTEX_BUFFER
+                    | vk::BufferUsageFlags::INDEX_BUFFER
+                    | vk::BufferUsageFlags::UNIFORM_BUFFER,
+                "frame allocator",
+                gpu_allocator::MemoryLocation::CpuToGpu,
+            )?;
+            if let Some(old) = self.buffers[slot].replace(buffer) {
+                debug!("Frame allocator grew to {} bytes", grown);
+                self.retired.retire(self.frame, old);
+            }
+        }
+        let buffer = self.buffers[slot].as_mut().unwrap();
+        buffer
+            .write(offset as usize, bytes)
+            .expect("frame allocator buffer is not mapped");
+        self.heads[slot] = offset + size;
+        Ok(FrameSlice {
+            buffer: buffer.buffer,
+            offset,
+        })
+    }
+
+    pub(super) fn cleanup(&mut self, allocator: &mut Allocator, logical_device: &Device) {
+        let retired: Vec<_> = self.retired.drain().collect();
+        for mut buffer in self
+            .buffers
+            .iter_mut()
+            .flat_map(Option::take)
+            .chain(retired)
+        {
+            unsafe { buffer.cleanup(allocator, logical_device) };
+        }
+    }
+}
+
+// Where `push` puts `size` bytes in a buffer of `capacity` with `head` bytes handed out, and the
+// size of the buffer to move on to when they don't fit, in which case they go at its start.
+fn place(head: u64, capacity: u64, size: u64) -> (u64, Option<u64>) {
+    let offset = head.next_multiple_of(ALIGNMENT);
+    // Even an empty push needs a buffer to point at.
+    match capacity == 0 || offset + size > capacity {
+        true => {
+            let grown = (capacity * 2)
+                .max(size)
+                .max(INITIAL_SIZE)
+                .next_power_of_two();
+            (0, Some(grown))
+        }
+        false => (offset, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_first_push_makes_a_buffer() {
+        assert_eq!(place(0, 0, 64), (0, Some(INITIAL_SIZE)));
+        assert_eq!(place(0, 0, 0), (0, Some(INITIAL_SIZE)));
+    }
+
+    #[test]
+    fn pushes_follow_the_head_aligned() {
+        assert_eq!(place(0, INITIAL_SIZE, 100), (0, None));
+        assert_eq!(place(100, INITIAL_SIZE, 100), (ALIGNMENT, None));
+        assert_eq!(place(ALIGNMENT, INITIAL_SIZE, 8), (ALIGNMENT, None));
+        assert_eq!(
+            place(INITIAL_SIZE - ALIGNMENT, INITIAL_SIZE, ALIGNMENT),
+            (INITIAL_SIZE - ALIGNMENT, None)
+        );
+    }
+
+    #[test]
+    fn outgrowing_the_buffer_doubles_it() {
+        assert_eq!(
+            place(INITIAL_SIZE - ALIGNMENT, INITIAL_SIZE, ALIGNMENT + 1),
+            (0, Some(INITIAL_SIZE * 2))
+        );
+        // The head is rounded up before checking the fit.
+        assert_eq!(
+            place(INITIAL_SIZE - ALIGNMENT - 1, INITIAL_SIZE, ALIGNMENT + 1),
+            (0, Some(INITIAL_SIZE * 2))
+        );
+    }
+
+    #[test]
+    fn a_push_larger_than_double_gets_a_buffer_big_enough() {
+        let size = INITIAL_SIZE * 5 + 3;
+        let (offset, grown) = place(0, INITIAL_SIZE, size);
+        assert_eq!(offset, 0);
+        assert_eq!(grown, Some(INITIAL_SIZE * 8));
+    }
+}
@@ -0,0 +1,54 @@
+use bumpalo::{collections::Vec as ArenaVec, Bump};
+use log::debug;
+
+/// Scratch memory that lives for one frame. Allocating is a pointer bump and everything is freed
+/// at once when the next frame starts, so per-frame lists don't have to be reallocated or kept
+/// around between frames. Destructors are not run, stick to plain data.
+#[derive(Default)]
+pub struct FrameArena {
+    bump: Bump,
+    // The most the arena has held at the end of a frame, in bytes.
+    peak: usize,
+}
+
+impl FrameArena {
+    pub fn alloc<T>(&self, value: T) -> &mut T {
+        self.bump.alloc(value)
+    }
+
+    pub fn alloc_slice_copy<T: Copy>(&self, values: &[T]) -> &mut [T] {
+        self.bump.alloc_slice_copy(values)
+    }
+
+    pub fn vec<T>(&self) -> ArenaVec<'_, T> {
+        ArenaVec::new_in(&self.bump)
+    }
+
+    pub fn vec_with_capacity<T>(&self, capacity: usize) -> ArenaVec<'_, T> {
+        ArenaVec::with_capacity_in(capacity, &self.bump)
+    }
+
+    pub fn collect<T, I: IntoIterator<Item = T>>(&self, iter: I) -> ArenaVec<'_, T> {
+        ArenaVec::from_iter_in(iter, &self.bump)
+    }
+
+    /// Bytes handed out so far this frame.
+    pub fn allocated_bytes(&self) -> usize {
+        self.bump.allocated_bytes()
+    }
+
+    pub fn peak_bytes(&self) -> usize {
+        self.peak
+    }
+
+    // Frees everything allocated during the last frame. The memory itself is kept, after a few
+    // frames the arena settles at the size the busiest frame needed.
+    pub(super) fn reset(&mut self) {
+        let allocated = self.bump.allocated_bytes();
+        if allocated > self.peak {
+            debug!("Frame arena grew to {} bytes", allocated);
+            self.peak = allocated;
+        }
+        self.bump.reset();
+    }
+}
@@ -0,0 +1,91 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+// How many frames the average frame rate is taken over.
+const FPS_WINDOW: usize = 60;
+
+/// How a frame went on the CPU, returned from `Vulkan::swap_framebuffers`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameTiming {
+    pub frame: u64,
+    /// Since the previous frame started, zero for the first. The step for variable timestep
+    /// updates, or to feed a fixed timestep accumulator.
+    pub delta: Duration,
+    /// Averaged over the last 60 frames, so it doesn't flicker like `1 / delta`.
+    pub fps: f32,
+    /// How long `swap_framebuffers` took to record, submit and present the frame.
+    pub cpu_time: Duration,
+    /// Meshes drawn for the scene, every camera and the shadow map included.
+    pub draw_calls: u32,
+    /// Instances those draws covered.
+    pub instances: u32,
+}
+
+// Counted while a frame is recorded.
+#[derive(Clone, Copy, Debug, Default)]
+pub(super) struct DrawCounts {
+    pub(super) draw_calls: u32,
+    pub(super) instances: u32,
+}
+
+impl DrawCounts {
+    pub(super) fn add(&mut self, instances: u32) {
+        self.draw_calls += 1;
+        self.instances += instances;
+    }
+}
+
+#[derive(Default)]
+pub(super) struct FrameTimer {
+    last_start: Option<Instant>,
+    deltas: VecDeque<Duration>,
+    last: FrameTiming,
+}
+
+impl FrameTimer {
+    // The time since the previous frame started.
+    pub(super) fn start(&mut self, now: Instant) -> Duration {
+        let delta = self
+            .last_start
+            .map_or(Duration::ZERO, |last| now.duration_since(last));
+        self.last_start = Some(now);
+        if !delta.is_zero() {
+            if self.deltas.len() == FPS_WINDOW {
+                self.deltas.pop_front();
+            }
+            self.deltas.push_back(delta);
+        }
+        delta
+    }
+
+    pub(super) fn finish(
+        &mut self,
+        frame: u64,
+        delta: Duration,
+        cpu_time: Duration,
+        counts: DrawCounts,
+    ) -> FrameTiming {
+        let total: Duration = self.deltas.iter().sum();
+        let fps = if total.is_zero() {
+            0.0
+        } else {
+            self.deltas.len() as f32 / total.as_secs_f32()
+        };
+        self.last = FrameTiming {
+            frame,
+            delta,
+            fps,
+            cpu_time,
+            draw_calls: counts.draw_calls,
+            instances: counts.instances,
+        };
+        self.last
+    }
+
+    // The last finished frame.
+    pub(super) fn last(&self) -> FrameTiming {
+        self.last
+    }
+}
@@ -0,0 +1,79 @@
+use std::mem::size_of;
+
+use ash::{vk, Device};
+use gpu_allocator::vulkan::Allocator;
+
+use super::frame_allocator::FrameAllocator;
+
+// What the scene shaders see as the `FrameUniforms` block at set=1, binding=0. Every field is a
+// vec4, uvec4 or mat4 so the std140 layout matches this struct without padding.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(super) struct FrameUniformData {
+    pub(super) view: [[f32; 4]; 4],
+    // Already adjusted for the renderer's `DepthRange`.
+    pub(super) projection: [[f32; 4]; 4],
+    pub(super) view_projection: [[f32; 4]; 4],
+    // xyz: the camera's position in world space, w unused.
+    pub(super) camera_position: [f32; 4],
+    // x: seconds since the renderer started, yzw unused.
+    pub(super) time: [f32; 4],
+    // xy: wind direction, z: wind strength, w unused.
+    pub(super) wind: [f32; 4],
+    // Towards the directional light, w unused.
+    pub(super) sun_direction: [f32; 4],
+    // Colour of the directional light, w unused.
+    pub(super) sun_colour: [f32; 4],
+    // Flat ambient light added to the light probes', w unused.
+    pub(super) ambient: [f32; 4],
+    // x: how many lights the light buffer holds, yzw unused.
+    pub(super) light_count: [u32; 4],
+    // The sun's, what the shadow map was drawn with.
+    pub(super) shadow_view_projection: [[f32; 4]; 4],
+    // x: 1 when shadows are on, y: normal bias, z: one shadow map texel in uv, w unused.
+    pub(super) shadow: [f32; 4],
+}
+
+// One view's uniforms padded to the largest `minUniformBufferOffsetAlignment` the spec allows,
+// so every view starts at an offset any device can bind.
+#[repr(C, align(256))]
+#[derive(Clone, Copy)]
+struct AlignedFrameUniformData(FrameUniformData);
+
+// How far apart the views are, view `n` is `n` times this past the first.
+const FRAME_UNIFORM_STRIDE: u32 = size_of::<AlignedFrameUniformData>() as u32;
+
+// The views of the frame being recorded, the main camera's and one for every render target,
+// written to the frame allocator.
+pub(super) struct FrameUniforms {
+    // Where the last `upload` put the first view.
+    offset: u64,
+}
+
+impl FrameUniforms {
+    pub(super) fn new() -> FrameUniforms {
+        FrameUniforms { offset: 0 }
+    }
+
+    // Writes `views` for frame `slot` and returns the buffer they're in, which only changes when
+    // the frame allocator grows.
+    pub(super) fn upload(
+        &mut self,
+        frame_allocator: &mut FrameAllocator,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        slot: usize,
+        views: &[FrameUniformData],
+    ) -> Result<vk::Buffer, vk::Result> {
+        let aligned: Vec<AlignedFrameUniformData> =
+            views.iter().copied().map(AlignedFrameUniformData).collect();
+        let slice = frame_allocator.push(allocator, logical_device, slot, &aligned)?;
+        self.offset = slice.offset;
+        Ok(slice.buffer)
+    }
+
+    // The dynamic offset that picks `view` out of the last `upload`.
+    pub(super) fn dynamic_offset(&self, view: u32) -> u32 {
+        self.offset as u32 + view * FRAME_UNIFORM_STRIDE
+    }
+}
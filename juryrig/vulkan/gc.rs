@@ -0,0 +1,134 @@
+use std::{collections::VecDeque, sync::Arc};
+
+// Shared by every clone of a handle and the store's own copy. Once the store holds the only
+// reference nobody can ask for the resource any more and it can be collected. Atomic so handles
+// can be sent to and dropped on other threads.
+#[derive(Clone, Debug, Default)]
+pub(super) struct HandleRef(Arc<()>);
+
+impl HandleRef {
+    pub(super) fn is_unreferenced(&self) -> bool {
+        Arc::strong_count(&self.0) == 1
+    }
+}
+
+/// How much garbage collection work is done per frame. Collection is spread over many frames so
+/// dropping a lot of handles at once doesn't cause a hitch.
+#[derive(Clone, Copy, Debug)]
+pub struct GcConfig {
+    /// How many slots of each store are checked for unreferenced handles per frame.
+    pub scan_per_frame: usize,
+    /// How many retired resources are destroyed per frame.
+    pub destroy_per_frame: usize,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        GcConfig {
+            scan_per_frame: 64,
+            destroy_per_frame: 4,
+        }
+    }
+}
+
+// Incremental collection for one store. Scans a few slots every frame, resuming where the last
+// scan stopped, and holds on to retired resources until frames that might still use them are
+// done on the GPU.
+pub(super) struct Collector<T> {
+    cursor: usize,
+    // Resources with the frame they were retired in, oldest first.
+    retired: VecDeque<(u64, T)>,
+}
+
+impl<T> Collector<T> {
+    pub(super) fn new() -> Collector<T> {
+        Collector {
+            cursor: 0,
+            retired: VecDeque::new(),
+        }
+    }
+
+    // The slot indices to check this frame, wrapping around at `len`.
+    pub(super) fn scan(&mut self, len: usize, count: usize) -> impl Iterator<Item = usize> {
+        let start = if len == 0 { 0 } else { self.cursor % len };
+        let count = count.min(len);
+        self.cursor = start + count;
+        (start..start + count).map(move |index| index % len)
+    }
+
+    pub(super) fn retire(&mut self, frame: u64, resource: T) {
+        self.retired.push_back((frame, resource));
+    }
+
+    // Up to `max` resources retired at least `latency` frames before `frame`.
+    pub(super) fn ready(&mut self, frame: u64, latency: u64, max: usize) -> Vec<T> {
+        let mut ready = vec![];
+        while ready.len() < max {
+            match self.retired.front() {
+                Some((retired, _)) if retired + latency <= frame => {
+                    ready.push(self.retired.pop_front().unwrap().1)
+                }
+                _ => break,
+            }
+        }
+        ready
+    }
+
+    // Everything still waiting, for when the device is idle and it can all go at once.
+    pub(super) fn drain(&mut self) -> impl Iterator<Item = T> + '_ {
+        self.retired.drain(..).map(|(_, resource)| resource)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handle_refs_are_unreferenced_once_every_clone_is_dropped() {
+        let store = HandleRef::default();
+        assert!(store.is_unreferenced());
+        let handle = store.clone();
+        let other = handle.clone();
+        assert!(!store.is_unreferenced());
+        drop(handle);
+        assert!(!store.is_unreferenced());
+        drop(other);
+        assert!(store.is_unreferenced());
+    }
+
+    #[test]
+    fn scans_resume_where_the_last_stopped_and_wrap() {
+        let mut collector = Collector::<()>::new();
+        assert_eq!(collector.scan(5, 3).collect::<Vec<_>>(), [0, 1, 2]);
+        assert_eq!(collector.scan(5, 3).collect::<Vec<_>>(), [3, 4, 0]);
+        assert_eq!(collector.scan(5, 3).collect::<Vec<_>>(), [1, 2, 3]);
+        // Never more than the store holds, however much is asked for.
+        assert_eq!(collector.scan(2, 10).collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(collector.scan(0, 10).count(), 0);
+    }
+
+    #[test]
+    fn resources_are_ready_after_the_latency_oldest_first() {
+        let mut collector = Collector::new();
+        collector.retire(1, "a");
+        collector.retire(1, "b");
+        collector.retire(3, "c");
+        assert!(collector.ready(2, 2, usize::MAX).is_empty());
+        assert_eq!(collector.ready(3, 2, usize::MAX), ["a", "b"]);
+        assert!(collector.ready(4, 2, usize::MAX).is_empty());
+        assert_eq!(collector.ready(5, 2, usize::MAX), ["c"]);
+    }
+
+    #[test]
+    fn ready_destroys_at_most_max_per_call() {
+        let mut collector = Collector::new();
+        for resource in 0..5 {
+            collector.retire(0, resource);
+        }
+        assert_eq!(collector.ready(10, 1, 2), [0, 1]);
+        assert_eq!(collector.ready(10, 1, 2), [2, 3]);
+        assert_eq!(collector.drain().collect::<Vec<_>>(), [4]);
+        assert!(collector.ready(10, 1, 2).is_empty());
+    }
+}
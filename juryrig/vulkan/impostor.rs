@@ -0,0 +1,275 @@
+use na::{Matrix4, Unit, Vector3};
+use uuid::Uuid;
+
+use super::{
+    bounds::BoundingSphere,
+    camera::Camera,
+    entity::{sort_key, Entities, EntityId},
+    mesh::MeshHandle,
+    render_target::{OffscreenTarget, RenderTarget},
+};
+
+// How far the camera's direction to an impostor can turn from the one it was baked from before
+// it's baked again, in radians.
+const REBAKE_ANGLE: f32 = 0.2;
+
+// The field of view bakes are drawn with, `Camera`'s default.
+const BAKE_FOVY: f32 = std::f32::consts::FRAC_PI_3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ImpostorHandle {
+    id: Uuid,
+}
+
+// A cluster of entities drawn as one textured quad once the camera is far enough away.
+struct Impostor {
+    id: Uuid,
+    entities: Vec<EntityId>,
+    target: RenderTarget,
+    billboard: EntityId,
+    bounds: BoundingSphere,
+    distance: f32,
+    // The direction from the camera of the last finished bake, None before the first.
+    baked_from: Option<Unit<Vector3<f32>>>,
+    // The direction of the bake waiting to be drawn.
+    baking: Option<Unit<Vector3<f32>>>,
+}
+
+// The impostors made with `Vulkan::bake_impostor`. Each frame, before the entities are uploaded,
+// far clusters are hidden and their billboards shown, and those seen from a new enough angle are
+// baked again into their render target.
+#[derive(Default)]
+pub(super) struct Impostors {
+    impostors: Vec<Impostor>,
+    // Registered with the first impostor and shared by every billboard.
+    pub(super) quad: Option<MeshHandle>,
+}
+
+impl Impostors {
+    pub(super) fn add(
+        &mut self,
+        entities: Vec<EntityId>,
+        target: RenderTarget,
+        billboard: EntityId,
+        bounds: BoundingSphere,
+        distance: f32,
+    ) -> ImpostorHandle {
+        let id = Uuid::new_v4();
+        self.impostors.push(Impostor {
+            id,
+            entities,
+            target,
+            billboard,
+            bounds,
+            distance,
+            baked_from: None,
+            baking: None,
+        });
+        ImpostorHandle { id }
+    }
+
+    // Returns the impostor's render target and billboard, for the renderer to free.
+    pub(super) fn remove(
+        &mut self,
+        handle: &ImpostorHandle,
+        entities: &mut Entities,
+    ) -> Option<(RenderTarget, EntityId)> {
+        let index = self
+            .impostors
+            .iter()
+            .position(|other| other.id == handle.id)?;
+        let impostor = self.impostors.remove(index);
+        for id in &impostor.entities {
+            entities.set_hidden(id, false);
+        }
+        Some((impostor.target, impostor.billboard))
+    }
+
+    // Drops every impostor, showing their clusters again, for when the render targets are gone.
+    pub(super) fn forget(&mut self, entities: &mut Entities) {
+        for impostor in self.impostors.drain(..) {
+            for id in &impostor.entities {
+                entities.set_hidden(id, false);
+            }
+            entities.remove(&impostor.billboard);
+        }
+    }
+
+    // Swaps each cluster and its billboard by the distance from `camera`, and asks for a bake
+    // from targets seen from too far off the angle they were baked from.
+    pub(super) fn update(
+        &mut self,
+        camera: &Camera,
+        entities: &mut Entities,
+        render_targets: &mut [OffscreenTarget],
+    ) {
+        for impostor in &mut self.impostors {
+            let Some(target) = render_targets
+                .iter_mut()
+                .find(|target| target.is(&impostor.target))
+            else {
+                continue;
+            };
+            // Bakes are drawn once and then taken off the target.
+            if target.bake.is_none() {
+                if let Some(direction) = impostor.baking.take() {
+                    impostor.baked_from = Some(direction);
+                }
+            }
+            let offset = impostor.bounds.centre - camera.position();
+            let far = offset.norm() > impostor.distance;
+            let direction = Unit::new_normalize(offset);
+            let stale = impostor
+                .baked_from
+                .is_none_or(|baked| baked.angle(&direction) > REBAKE_ANGLE);
+            if far && stale && impostor.baking.is_none() {
+                target.camera = Some(bake_camera(&impostor.bounds, &direction));
+                target.bake = Some(impostor.entities.clone());
+                impostor.baking = Some(direction);
+            }
+            // Until the first bake is drawn the billboard has nothing to show.
+            let show = far && impostor.baked_from.is_some();
+            for id in &impostor.entities {
+                entities.set_hidden(id, show);
+            }
+            entities.set_hidden(&impostor.billboard, !show);
+            if let (true, Some(billboard)) = (show, entities.get_mut(&impostor.billboard)) {
+                billboard.transform = billboard_transform(&impostor.bounds, &direction);
+                // Farthest first, so nearer billboards blend over farther ones.
+                billboard.sort_key = Some(sort_key(0, None, 1.0 / offset.norm().max(1e-3)));
+            }
+        }
+    }
+}
+
+// A sphere around all of `spheres`, not the smallest but close for clusters of similar sizes.
+// None when there are none.
+pub(super) fn enclosing(spheres: &[BoundingSphere]) -> Option<BoundingSphere> {
+    let centre = spheres
+        .iter()
+        .map(|sphere| sphere.centre)
+        .sum::<Vector3<f32>>()
+        / spheres.len().max(1) as f32;
+    let radius = spheres
+        .iter()
+        .map(|sphere| (sphere.centre - centre).norm() + sphere.radius)
+        .reduce(f32::max)?;
+    Some(BoundingSphere { centre, radius })
+}
+
+// The right and up of a camera looking along `direction` with no roll, as `Camera::set_orientation`
+// works them out.
+fn basis(direction: &Unit<Vector3<f32>>) -> (f32, f32, Vector3<f32>, Vector3<f32>) {
+    let yaw = (-direction.x).atan2(direction.z);
+    let pitch = direction.y.clamp(-1.0, 1.0).asin();
+    let right = Vector3::new(-yaw.cos(), 0.0, -yaw.sin());
+    let up = right.cross(direction).normalize();
+    (yaw, pitch, right, up)
+}
+
+// Looks along `direction` at the middle of `bounds`, from just far enough back for all of it to
+// fit in a square target.
+pub(super) fn bake_camera(bounds: &BoundingSphere, direction: &Unit<Vector3<f32>>) -> Camera {
+    let radius = bounds.radius.max(1e-3);
+    let distance = radius / (0.5 * BAKE_FOVY).sin();
+    let (yaw, pitch, _, _) = basis(direction);
+    let mut camera = Camera::default();
+    camera.aspect = 1.0;
+    camera.set_clip_planes(distance - radius, Some(distance + radius));
+    camera.set_position(bounds.centre - direction.into_inner() * distance);
+    camera.set_orientation(yaw, pitch);
+    camera
+}
+
+// Places the billboard quad, one unit across facing -z, through the middle of `bounds` facing a
+// camera looking along `direction`, the size of the bake camera's view there.
+pub(super) fn billboard_transform(
+    bounds: &BoundingSphere,
+    direction: &Unit<Vector3<f32>>,
+) -> Matrix4<f32> {
+    let radius = bounds.radius.max(1e-3);
+    let size = 2.0 * radius / (0.5 * BAKE_FOVY).cos();
+    let (_, _, right, up) = basis(direction);
+    Matrix4::new(
+        right.x * size,
+        up.x * size,
+        direction.x,
+        bounds.centre.x,
+        right.y * size,
+        up.y * size,
+        direction.y,
+        bounds.centre.y,
+        right.z * size,
+        up.z * size,
+        direction.z,
+        bounds.centre.z,
+        0.0,
+        0.0,
+        0.0,
+        1.0,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use na::Vector4;
+
+    use super::*;
+
+    fn sphere() -> BoundingSphere {
+        BoundingSphere {
+            centre: Vector3::new(3.0, 1.0, -2.0),
+            radius: 2.0,
+        }
+    }
+
+    #[test]
+    fn enclosing_contains_every_sphere() {
+        let spheres = [
+            sphere(),
+            BoundingSphere {
+                centre: Vector3::new(-4.0, 0.0, 0.0),
+                radius: 0.5,
+            },
+        ];
+        let bounds = enclosing(&spheres).unwrap();
+        for sphere in spheres {
+            assert!((sphere.centre - bounds.centre).norm() + sphere.radius <= bounds.radius + 1e-5);
+        }
+        assert!(enclosing(&[]).is_none());
+    }
+
+    #[test]
+    fn bake_camera_frames_the_bounds() {
+        let bounds = sphere();
+        let direction = Unit::new_normalize(Vector3::new(1.0, -0.5, 2.0));
+        let camera = bake_camera(&bounds, &direction);
+        let view_projection = camera.projectionmatrix * camera.viewmatrix;
+        let (_, _, right, up) = basis(&direction);
+        for edge in [right, -right, up, -up, direction.into_inner()] {
+            let point = bounds.centre + edge * bounds.radius;
+            let clip = view_projection * Vector4::new(point.x, point.y, point.z, 1.0);
+            let ndc = clip.xyz() / clip.w;
+            assert!(
+                ndc.x.abs() <= 1.0 + 1e-4 && ndc.y.abs() <= 1.0 + 1e-4,
+                "{:?}",
+                ndc
+            );
+            assert!((0.0..=1.0 + 1e-4).contains(&ndc.z), "{:?}", ndc);
+        }
+    }
+
+    #[test]
+    fn billboard_faces_the_camera() {
+        let bounds = sphere();
+        let direction = Unit::new_normalize(Vector3::new(-1.0, 0.0, 1.0));
+        let transform = billboard_transform(&bounds, &direction);
+        let facing = transform.transform_vector(&Vector3::new(0.0, 0.0, -1.0));
+        assert!((facing + direction.into_inner()).norm() < 1e-5);
+        // The quad's top edge is up in the bake, so the picture isn't flipped.
+        let up = transform.transform_vector(&Vector3::new(0.0, 1.0, 0.0));
+        assert!(up.y > 0.0);
+        let centre = transform.transform_point(&Vector3::zeros().into());
+        assert!((centre.coords - bounds.centre).norm() < 1e-5);
+    }
+}
@@ -0,0 +1,610 @@
+use std::ffi::{CStr, CString};
+
+use ash::{
+    extensions::{ext::DebugUtils, khr},
+    vk::{self, ExtDescriptorIndexingFn},
+    Device, Entry, Instance,
+};
+use log::info;
+
+use super::{
+    debug::{validation_layer_name, Debug},
+    error::InitError,
+    render_graph::{AttachmentPolicy, LoadPolicy, PassDesc, RenderGraph},
+    surface::Surface,
+    swapchain::SCENE_FORMAT,
+};
+
+fn extension_name_pointers(
+    entry: &Entry,
+    presenting: bool,
+    validation: bool,
+) -> Result<Vec<*const i8>, vk::Result> {
+    let mut extension_name_pointers = vec![];
+    if validation {
+        extension_name_pointers.push(DebugUtils::name().as_ptr());
+    }
+    let available = entry.enumerate_instance_extension_properties(None)?;
+    // Newer loaders hide portability drivers like MoltenVK unless this is enabled and asked for
+    // with the `ENUMERATE_PORTABILITY_KHR` flag in `create_instance`.
+    if supports_extension(&available, vk::KhrPortabilityEnumerationFn::name()) {
+        extension_name_pointers.push(vk::KhrPortabilityEnumerationFn::name().as_ptr());
+    }
+    if !presenting {
+        return Ok(extension_name_pointers);
+    }
+
+    extension_name_pointers.push(khr::Surface::name().as_ptr());
+    extension_name_pointers.push(Surface::extention_name_ptr());
+
+    // Optional, surfaces only list HDR colour spaces when it's enabled.
+    if supports_extension(&available, vk::ExtSwapchainColorspaceFn::name()) {
+        extension_name_pointers.push(vk::ExtSwapchainColorspaceFn::name().as_ptr());
+    }
+
+    return Ok(extension_name_pointers);
+}
+
+fn supports_extension(available: &[vk::ExtensionProperties], name: &CStr) -> bool {
+    available.iter().any(|extension| {
+        let extension_name = unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) };
+        extension_name == name
+    })
+}
+
+// Optional device extensions that were found and enabled.
+pub(super) struct DeviceExtensions {
+    pub(super) display_timing: bool,
+    pub(super) hdr_metadata: bool,
+    // Not an extension but just as optional, wireframe `RenderState`s need it.
+    pub(super) fill_mode_non_solid: bool,
+    // Also a feature, for `RendererConfig::pipeline_statistics`.
+    pub(super) pipeline_statistics_query: bool,
+    // Features too, for sampling block compressed textures as they are. Desktop GPUs have BC,
+    // mobile ones ASTC.
+    pub(super) texture_compression_bc: bool,
+    pub(super) texture_compression_astc_ldr: bool,
+}
+
+/// Without `presenting` the surface extensions are left out, for contexts that never draw to a
+/// window. `validation` should come from `debug::validation_enabled`, which checks the layer is
+/// installed.
+pub fn create_instance(
+    entry: &Entry,
+    app_name: &str,
+    presenting: bool,
+    validation: bool,
+) -> std::result::Result<Instance, vk::Result> {
+    let engine_name: CString = CString::new("Juryrig").unwrap();
+    let app_name: CString = CString::new(app_name).unwrap();
+
+    // Layers and extentions
+
+    let layer_name_pointers = match validation {
+        true => vec![validation_layer_name().as_ptr()],
+        false => vec![],
+    };
+    let extension_name_pointers = extension_name_pointers(entry, presenting, validation)?;
+
+    let app_info = vk::ApplicationInfo::builder()
+        // This is the minimum Vulkan api version we are building for, newer versions have shinier
+        // features but are not as widely available
+        .api_version(vk::make_api_version(0, 1, 1, 0))
+        // This is information mainly used in crash logs and the like.
+        // TODO: Get this name and version from whatever is using the engine.
+        .application_name(&app_name)
+        .application_version(vk::make_api_version(
+            0,
+            option_env!("CARGO_PKG_VERSION_MAJOR")
+                .unwrap()
+                .parse::<u32>()
+                .unwrap(),
+            option_env!("CARGO_PKG_VERSION_MINOR")
+                .unwrap()
+                .parse::<u32>()
+                .unwrap(),
+            option_env!("CARGO_PKG_VERSION_PATCH")
+                .unwrap()
+                .parse::<u32>()
+                .unwrap(),
+        ))
+        // Similar to the application stuff this is also mainly used for
+        // debugging and logging purposes
+        .engine_name(&engine_name)
+        .engine_version(vk::make_api_version(
+            0,
+            option_env!("CARGO_PKG_VERSION_MAJOR")
+                .unwrap()
+                .parse::<u32>()
+                .unwrap(),
+            option_env!("CARGO_PKG_VERSION_MINOR")
+                .unwrap()
+                .parse::<u32>()
+                .unwrap(),
+            option_env!("CARGO_PKG_VERSION_PATCH")
+                .unwrap()
+                .parse::<u32>()
+                .unwrap(),
+        ));
+
+    // Instance creation. With validation the messenger is chained on too, so problems creating
+    // and destroying the instance itself are reported.
+    let mut debug_create_info = Debug::create_info();
+    let flags = match supports_extension(
+        &entry.enumerate_instance_extension_properties(None)?,
+        vk::KhrPortabilityEnumerationFn::name(),
+    ) {
+        true => vk::InstanceCreateFlags::ENUMERATE_PORTABILITY_KHR,
+        false => vk::InstanceCreateFlags::empty(),
+    };
+    let mut instance_create_info = vk::InstanceCreateInfo::builder()
+        .flags(flags)
+        .application_info(&app_info)
+        .enabled_layer_names(&layer_name_pointers)
+        .enabled_extension_names(&extension_name_pointers);
+    if validation {
+        instance_create_info = instance_create_info.push_next(&mut debug_create_info);
+    }
+
+    Ok(unsafe { entry.create_instance(&instance_create_info, None) }?)
+}
+
+/// Picks the best scoring device that has everything the renderer needs. Without a surface only
+/// compute is needed, for `ComputeContext`.
+pub fn init_physical_device_and_properties(
+    instance: &Instance,
+    surface: Option<&Surface>,
+) -> Result<(vk::PhysicalDevice, vk::PhysicalDeviceProperties), InitError> {
+    let phys_devs = unsafe { instance.enumerate_physical_devices() }?;
+    if phys_devs.is_empty() {
+        return Err(InitError::DeviceSelectionError(
+            "No Vulkan devices found, check the graphics drivers are installed!",
+        ));
+    }
+
+    let mut chosen: Option<(u32, vk::PhysicalDevice, vk::PhysicalDeviceProperties)> = None;
+    for p in phys_devs {
+        let properties = unsafe { instance.get_physical_device_properties(p) };
+        let name = unsafe { CStr::from_ptr(properties.device_name.as_ptr()) };
+        match score_physical_device(instance, p, &properties, surface)? {
+            Ok(score) => {
+                info!(
+                    "Device {:?} ({:?}) scored {}",
+                    name, properties.device_type, score
+                );
+                if chosen.is_none_or(|(best, _, _)| score > best) {
+                    chosen = Some((score, p, properties));
+                }
+            }
+            Err(reason) => info!("Device {:?} skipped: {}", name, reason),
+        }
+    }
+    chosen
+        .map(|(_, p, properties)| (p, properties))
+        .ok_or(InitError::DeviceSelectionError(match surface {
+            Some(_) => "No device has everything the renderer needs, the log says why!",
+            None => "No device can run compute work, the log says why!",
+        }))
+}
+
+// Discrete GPUs win over integrated ones, which win over software renderers. Dedicated compute
+// and transfer families and bigger textures break ties. Err says what a device is missing.
+fn score_physical_device(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+    properties: &vk::PhysicalDeviceProperties,
+    surface: Option<&Surface>,
+) -> Result<Result<u32, &'static str>, vk::Result> {
+    if properties.api_version < vk::API_VERSION_1_1 {
+        return Ok(Err("needs Vulkan 1.1"));
+    }
+
+    let families = unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+    let has_family = |wanted: vk::QueueFlags, unwanted: vk::QueueFlags| {
+        families.iter().any(|family| {
+            family.queue_count > 0
+                && family.queue_flags.contains(wanted)
+                && !family.queue_flags.intersects(unwanted)
+        })
+    };
+    if !has_family(vk::QueueFlags::COMPUTE, vk::QueueFlags::empty()) {
+        return Ok(Err("no queue family can compute"));
+    }
+
+    if let Some(surface) = surface {
+        let available = unsafe { instance.enumerate_device_extension_properties(physical_device) }?;
+        let required = [
+            khr::Swapchain::name(),
+            khr::BufferDeviceAddress::name(),
+            ExtDescriptorIndexingFn::name(),
+        ];
+        if !required
+            .iter()
+            .all(|name| supports_extension(&available, name))
+        {
+            return Ok(Err(
+                "missing the swapchain, buffer device address or descriptor indexing extension",
+            ));
+        }
+
+        let mut buffer_address_features =
+            vk::PhysicalDeviceBufferDeviceAddressFeaturesKHR::default();
+        let mut indexing_features = vk::PhysicalDeviceDescriptorIndexingFeatures::default();
+        let mut features = vk::PhysicalDeviceFeatures2::builder()
+            .push_next(&mut buffer_address_features)
+            .push_next(&mut indexing_features);
+        unsafe { instance.get_physical_device_features2(physical_device, &mut features) };
+        if features.features.sampler_anisotropy == vk::FALSE
+            || buffer_address_features.buffer_device_address == vk::FALSE
+            || indexing_features.runtime_descriptor_array == vk::FALSE
+            || indexing_features.descriptor_binding_variable_descriptor_count == vk::FALSE
+            || indexing_features.descriptor_binding_partially_bound == vk::FALSE
+            || indexing_features.shader_sampled_image_array_non_uniform_indexing == vk::FALSE
+        {
+            return Ok(Err(
+                "missing anisotropic filtering, buffer device addresses or bindless textures",
+            ));
+        }
+
+        let mut presents = false;
+        for (index, family) in families.iter().enumerate() {
+            if family.queue_count > 0
+                && family.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+                && surface.get_physical_device_surface_support(physical_device, index as u32)?
+            {
+                presents = true;
+                break;
+            }
+        }
+        if !presents {
+            return Ok(Err(
+                "no queue family can both draw and present to the window",
+            ));
+        }
+        if surface.get_formats(physical_device)?.is_empty() {
+            return Ok(Err("the window's surface has no formats"));
+        }
+    }
+
+    let mut score = match properties.device_type {
+        vk::PhysicalDeviceType::DISCRETE_GPU => 10000,
+        vk::PhysicalDeviceType::INTEGRATED_GPU => 5000,
+        vk::PhysicalDeviceType::VIRTUAL_GPU => 2000,
+        vk::PhysicalDeviceType::CPU => 1000,
+        _ => 0,
+    };
+    if has_family(vk::QueueFlags::COMPUTE, vk::QueueFlags::GRAPHICS) {
+        score += 500;
+    }
+    if has_family(
+        vk::QueueFlags::TRANSFER,
+        vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE,
+    ) {
+        score += 250;
+    }
+    score += properties.limits.max_image_dimension2_d / 1024;
+    Ok(Ok(score))
+}
+
+pub(super) fn init_device_and_queues(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+    queue_families: &QueueFamilies,
+) -> Result<(Device, Queues, DeviceExtensions), vk::Result> {
+    let mut device_extension_name_pointers: Vec<*const i8> = vec![
+        khr::Swapchain::name().as_ptr(),
+        khr::BufferDeviceAddress::name().as_ptr(),
+        ExtDescriptorIndexingFn::name().as_ptr(),
+    ];
+    let available = unsafe { instance.enumerate_device_extension_properties(physical_device) }?;
+    // Optional, only enabled where the times it reports can be related to the CPU clock.
+    let display_timing = cfg!(any(target_os = "linux", target_os = "android"))
+        && supports_extension(&available, vk::GoogleDisplayTimingFn::name());
+    if display_timing {
+        device_extension_name_pointers.push(vk::GoogleDisplayTimingFn::name().as_ptr());
+    }
+    let hdr_metadata = supports_extension(&available, vk::ExtHdrMetadataFn::name());
+    if hdr_metadata {
+        device_extension_name_pointers.push(vk::ExtHdrMetadataFn::name().as_ptr());
+    }
+    // Portability drivers like MoltenVK have to have it enabled whenever they offer it.
+    if supports_extension(&available, vk::KhrPortabilitySubsetFn::name()) {
+        device_extension_name_pointers.push(vk::KhrPortabilitySubsetFn::name().as_ptr());
+    }
+
+    // Every role gets its own queue while its family has enough, so at most three per family.
+    let priorities = [1.0f32; 3];
+    let queue_infos: Vec<vk::DeviceQueueCreateInfo> = queue_families
+        .queue_counts
+        .iter()
+        .map(|(family, count)| {
+            vk::DeviceQueueCreateInfo::builder()
+                .queue_family_index(*family)
+                .queue_priorities(&priorities[..*count as usize])
+                .build()
+        })
+        .collect();
+
+    let mut buffer_address_features =
+        vk::PhysicalDeviceBufferDeviceAddressFeaturesKHR::builder().buffer_device_address(true);
+
+    let mut indexing_features = vk::PhysicalDeviceDescriptorIndexingFeatures::builder()
+        .runtime_descriptor_array(true)
+        .descriptor_binding_variable_descriptor_count(true)
+        .descriptor_binding_partially_bound(true)
+        .shader_sampled_image_array_non_uniform_indexing(true);
+
+    let supported_features = unsafe { instance.get_physical_device_features(physical_device) };
+    let fill_mode_non_solid = supported_features.fill_mode_non_solid == vk::TRUE;
+    let pipeline_statistics_query = supported_features.pipeline_statistics_query == vk::TRUE;
+    let texture_compression_bc = supported_features.texture_compression_bc == vk::TRUE;
+    let texture_compression_astc_ldr = supported_features.texture_compression_astc_ldr == vk::TRUE;
+    let enabled_features = vk::PhysicalDeviceFeatures::builder()
+        .sampler_anisotropy(true)
+        .fill_mode_non_solid(fill_mode_non_solid)
+        .pipeline_statistics_query(pipeline_statistics_query)
+        .texture_compression_bc(texture_compression_bc)
+        .texture_compression_astc_ldr(texture_compression_astc_ldr);
+
+    let device_create_info = vk::DeviceCreateInfo::builder()
+        .push_next(&mut buffer_address_features)
+        .push_next(&mut indexing_features)
+        .queue_create_infos(&queue_infos)
+        .enabled_extension_names(&device_extension_name_pointers)
+        .enabled_features(&enabled_features);
+
+    let logical_device =
+        unsafe { instance.create_device(physical_device, &device_create_info, None) }?;
+
+    let get_queue = |queue: QueueAssignment| unsafe {
+        logical_device.get_device_queue(queue.family, queue.index)
+    };
+    let graphics_queue = get_queue(queue_families.mapping.graphics);
+    let compute_queue = get_queue(queue_families.mapping.compute);
+    let transfer_queue = get_queue(queue_families.mapping.transfer);
+
+    Ok((
+        logical_device,
+        Queues {
+            graphics: graphics_queue,
+            compute: compute_queue,
+            transfer: transfer_queue,
+        },
+        DeviceExtensions {
+            display_timing,
+            hdr_metadata,
+            fill_mode_non_solid,
+            pipeline_statistics_query,
+            texture_compression_bc,
+            texture_compression_astc_ldr,
+        },
+    ))
+}
+
+// A device with a single compute queue and no extensions, for `ComputeContext`. Prefers a
+// family without graphics, those are usually the async compute queues.
+pub(super) fn init_compute_device(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+) -> Result<(Device, u32, vk::Queue), InitError> {
+    let queuefamilyproperties =
+        unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+    let family_count = queuefamilyproperties.len() as u32;
+    let flags = |index: u32| {
+        let qfam = &queuefamilyproperties[index as usize];
+        if qfam.queue_count > 0 {
+            qfam.queue_flags
+        } else {
+            vk::QueueFlags::empty()
+        }
+    };
+    let family = (0..family_count)
+        .find(|index| {
+            flags(*index).contains(vk::QueueFlags::COMPUTE)
+                && !flags(*index).contains(vk::QueueFlags::GRAPHICS)
+        })
+        .or_else(|| (0..family_count).find(|index| flags(*index).contains(vk::QueueFlags::COMPUTE)))
+        .ok_or(InitError::DeviceSelectionError(
+            "No queue family can compute!",
+        ))?;
+
+    let priorities = [1.0f32];
+    let queue_infos = [vk::DeviceQueueCreateInfo::builder()
+        .queue_family_index(family)
+        .queue_priorities(&priorities)
+        .build()];
+    let available = unsafe { instance.enumerate_device_extension_properties(physical_device) }?;
+    let mut extension_name_pointers = vec![];
+    if supports_extension(&available, vk::KhrPortabilitySubsetFn::name()) {
+        extension_name_pointers.push(vk::KhrPortabilitySubsetFn::name().as_ptr());
+    }
+    let device_create_info = vk::DeviceCreateInfo::builder()
+        .queue_create_infos(&queue_infos)
+        .enabled_extension_names(&extension_name_pointers);
+    let logical_device =
+        unsafe { instance.create_device(physical_device, &device_create_info, None) }?;
+    let queue = unsafe { logical_device.get_device_queue(family, 0) };
+    Ok((logical_device, family, queue))
+}
+
+/// `depth_format` should come from `swapchain::choose_depth_format`.
+pub fn init_renderpass(
+    logical_device: &ash::Device,
+    depth_format: vk::Format,
+) -> Result<vk::RenderPass, vk::Result> {
+    // The main pass as a one pass frame graph, drawing the scene in linear light for
+    // `init_output_pass` to sample.
+    let mut graph = RenderGraph::new();
+    let colour = graph.import(
+        "scene",
+        SCENE_FORMAT,
+        vk::ImageLayout::UNDEFINED,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    );
+    let depth = graph.import(
+        "depth",
+        depth_format,
+        vk::ImageLayout::UNDEFINED,
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+    );
+    let geometry = graph.add_pass(PassDesc::new("geometry").colour(colour).depth(depth));
+    graph.create_render_pass(logical_device, geometry)
+}
+
+/// Tonemaps the scene onto the swapchain image, and draws the UI and text over it.
+pub fn init_output_pass(
+    logical_device: &ash::Device,
+    format: vk::SurfaceFormatKHR,
+) -> Result<vk::RenderPass, vk::Result> {
+    let mut graph = RenderGraph::new();
+    let scene = graph.import(
+        "scene",
+        SCENE_FORMAT,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    );
+    let colour = graph.import(
+        "swapchain",
+        format.format,
+        vk::ImageLayout::UNDEFINED,
+        vk::ImageLayout::PRESENT_SRC_KHR,
+    );
+    // The tonemap or test pattern covers every pixel, so there's nothing to clear or load.
+    graph.set_policy(
+        colour,
+        AttachmentPolicy {
+            load: LoadPolicy::DontCare,
+            ..AttachmentPolicy::default()
+        },
+    );
+    let output = graph.add_pass(PassDesc::new("output").colour(colour).sample(scene));
+    graph.create_render_pass(logical_device, output)
+}
+
+/// A queue, as an index into the device's queue families and then into the family's queues.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueueAssignment {
+    pub family: u32,
+    pub index: u32,
+}
+
+/// Which queue each kind of work is submitted to. Roles can share a queue.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueueMapping {
+    pub graphics: QueueAssignment,
+    pub compute: QueueAssignment,
+    pub transfer: QueueAssignment,
+}
+
+pub(super) struct QueueFamilies {
+    pub(super) graphics: u32,
+    pub(super) compute: u32,
+    pub(super) transfer: u32,
+    pub(super) mapping: QueueMapping,
+    // How many queues to create in each family that is used.
+    pub(super) queue_counts: Vec<(u32, u32)>,
+}
+
+impl QueueFamilies {
+    // Graphics goes to the first family that can both draw and present. Compute and transfer
+    // don't present, so they only need the right flags. They prefer the first family dedicated
+    // to them, so they can run alongside graphics, and otherwise fall back to compute and then
+    // graphics.
+    pub(super) fn new(
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+        surface: &Surface,
+    ) -> Result<QueueFamilies, InitError> {
+        let queuefamilyproperties =
+            unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+        let family_count = queuefamilyproperties.len() as u32;
+        let flags = |index: u32| {
+            let qfam = &queuefamilyproperties[index as usize];
+            if qfam.queue_count > 0 {
+                qfam.queue_flags
+            } else {
+                vk::QueueFlags::empty()
+            }
+        };
+
+        let mut graphics = None;
+        for index in 0..family_count {
+            if flags(index).contains(vk::QueueFlags::GRAPHICS)
+                && surface.get_physical_device_surface_support(physical_device, index)?
+            {
+                graphics = Some(index);
+                break;
+            }
+        }
+        let graphics = graphics.ok_or(InitError::DeviceSelectionError(
+            "No queue family can both draw and present!",
+        ))?;
+        let compute = (0..family_count)
+            .find(|index| {
+                flags(*index).contains(vk::QueueFlags::COMPUTE)
+                    && !flags(*index).contains(vk::QueueFlags::GRAPHICS)
+            })
+            .unwrap_or(graphics);
+        // Graphics and compute families can always transfer, even when they don't say so.
+        let transfer = (0..family_count)
+            .find(|index| {
+                flags(*index).contains(vk::QueueFlags::TRANSFER)
+                    && !flags(*index).intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+            })
+            .unwrap_or(compute);
+
+        // Hand out queues in order, roles that run out share the last queue of their family.
+        let mut queue_counts: Vec<(u32, u32)> = vec![];
+        let mut assign = |family: u32| {
+            let available = queuefamilyproperties[family as usize].queue_count;
+            let index = match queue_counts.iter_mut().find(|(used, _)| *used == family) {
+                Some((_, count)) if *count < available => {
+                    *count += 1;
+                    *count - 1
+                }
+                Some((_, count)) => *count - 1,
+                None => {
+                    queue_counts.push((family, 1));
+                    0
+                }
+            };
+            QueueAssignment { family, index }
+        };
+        let mapping = QueueMapping {
+            graphics: assign(graphics),
+            compute: assign(compute),
+            transfer: assign(transfer),
+        };
+
+        info!(
+            "Queues: graphics {:?}, compute {:?}{}, transfer {:?}{}",
+            mapping.graphics,
+            mapping.compute,
+            if compute != graphics {
+                " (dedicated)"
+            } else {
+                ""
+            },
+            mapping.transfer,
+            if transfer != compute {
+                " (dedicated)"
+            } else {
+                ""
+            },
+        );
+
+        Ok(QueueFamilies {
+            graphics,
+            compute,
+            transfer,
+            mapping,
+            queue_counts,
+        })
+    }
+}
+
+pub(super) struct Queues {
+    pub(super) graphics: vk::Queue,
+    pub(super) transfer: vk::Queue,
+    pub(super) compute: vk::Queue,
+}
@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+
+use ash::{vk, Device};
+use gpu_allocator::vulkan::Allocator;
+use na::Vector3;
+use uuid::Uuid;
+
+use super::{buffer::Buffer, gc::Collector, light_probe::LightProbeGrid, InstanceData};
+
+const INITIAL_CAPACITY: u64 = 64;
+
+/// A retained instance. Stays valid until it's removed, even though removing other instances
+/// moves it around in the instance buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct InstanceSlot {
+    id: Uuid,
+}
+
+// Instances that live across frames. They are kept densely packed, removing one moves the last
+// instance into its place, and only instances that changed are copied to the GPU. Each frame in
+// flight has its own copy of the buffer, so every change is written once per copy.
+pub(super) struct RetainedInstances {
+    instances: Vec<InstanceData>,
+    // Which slot each instance belongs to, and the reverse.
+    owners: Vec<Uuid>,
+    indices: HashMap<Uuid, usize>,
+    // Bit `n` is set while buffer `n` still holds an old copy of the instance.
+    dirty: Vec<u64>,
+    // Per buffer, the indices with their bit set. Can hold stale or repeated indices, those are
+    // skipped when the bit is already clear.
+    pending: Vec<Vec<usize>>,
+    buffers: Vec<Buffer<InstanceData>>,
+    capacity: u64,
+    // Outgrown buffers, kept until the frames drawing from them are done.
+    retired: Collector<Vec<Buffer<InstanceData>>>,
+}
+
+impl RetainedInstances {
+    pub(super) fn new(frames_in_flight: usize) -> RetainedInstances {
+        assert!(frames_in_flight <= 64, "dirty bits are tracked in a u64");
+        RetainedInstances {
+            instances: vec![],
+            owners: vec![],
+            indices: HashMap::new(),
+            dirty: vec![],
+            pending: vec![vec![]; frames_in_flight],
+            buffers: vec![],
+            capacity: 0,
+            retired: Collector::new(),
+        }
+    }
+
+    pub(super) fn len(&self) -> u32 {
+        self.instances.len() as u32
+    }
+
+    pub(super) fn add(&mut self, instance: InstanceData) -> InstanceSlot {
+        let slot = InstanceSlot { id: Uuid::new_v4() };
+        let index = self.instances.len();
+        self.instances.push(instance);
+        self.owners.push(slot.id);
+        self.indices.insert(slot.id, index);
+        self.dirty.push(0);
+        self.mark_dirty(index);
+        slot
+    }
+
+    pub(super) fn get(&self, slot: &InstanceSlot) -> Option<&InstanceData> {
+        self.indices
+            .get(&slot.id)
+            .map(|index| &self.instances[*index])
+    }
+
+    // Marks the instance changed, even if the caller ends up not touching it.
+    pub(super) fn get_mut(&mut self, slot: &InstanceSlot) -> Option<&mut InstanceData> {
+        let index = *self.indices.get(&slot.id)?;
+        self.mark_dirty(index);
+        Some(&mut self.instances[index])
+    }
+
+    pub(super) fn remove(&mut self, slot: &InstanceSlot) -> Option<InstanceData> {
+        let index = self.indices.remove(&slot.id)?;
+        let removed = self.instances.swap_remove(index);
+        self.owners.swap_remove(index);
+        self.dirty.swap_remove(index);
+        if index < self.instances.len() {
+            self.indices.insert(self.owners[index], index);
+            // Whatever was pending for the moved instance was queued under its old index.
+            self.dirty[index] = 0;
+            self.mark_dirty(index);
+        }
+        Some(removed)
+    }
+
+    fn mark_dirty(&mut self, index: usize) {
+        for (buffer, pending) in self.pending.iter_mut().enumerate() {
+            let bit = 1 << buffer;
+            if self.dirty[index] & bit == 0 {
+                self.dirty[index] |= bit;
+                pending.push(index);
+            }
+        }
+    }
+
+    // Brings buffer `slot` up to date, growing every buffer first if the instances outgrew them.
+    // Ambient light is sampled from `light_probes` as each changed instance is written.
+    pub(super) fn upload(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        slot: usize,
+        frame: u64,
+        light_probes: &LightProbeGrid,
+    ) -> Result<(), vk::Result> {
+        let frames_in_flight = self.pending.len();
+        for buffers in self
+            .retired
+            .ready(frame, frames_in_flight as u64 + 1, usize::MAX)
+        {
+            for mut buffer in buffers {
+                unsafe { buffer.cleanup(allocator, logical_device) };
+            }
+        }
+
+        if self.instances.len() as u64 > self.capacity {
+            let capacity = (self.instances.len() as u64)
+                .next_power_of_two()
+                .max(INITIAL_CAPACITY);
+            let buffers = (0..frames_in_flight)
+                .map(|_| {
+                    Buffer::new(
+                        allocator,
+                        logical_device,
+                        capacity,
+                        vk::BufferUsageFlags::VERTEX_BUFFER,
+                        "retained instances",
+                        gpu_allocator::MemoryLocation::CpuToGpu,
+                    )
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let old = std::mem::replace(&mut self.buffers, buffers);
+            self.retired.retire(frame, old);
+            self.capacity = capacity;
+            for index in 0..self.instances.len() {
+                self.dirty[index] = 0;
+                self.mark_dirty(index);
+            }
+        }
+
+        let bit = 1 << slot;
+        for index in std::mem::take(&mut self.pending[slot]) {
+            if index >= self.instances.len() || self.dirty[index] & bit == 0 {
+                continue;
+            }
+            self.dirty[index] &= !bit;
+            let instance = &mut self.instances[index];
+            let origin = Vector3::new(
+                instance.model[3][0],
+                instance.model[3][1],
+                instance.model[3][2],
+            );
+            instance.ambient = light_probes.sample(origin).irradiance_terms();
+            self.buffers[slot]
+                .write(index, std::slice::from_ref(instance))
+                .expect("retained instance buffer is not mapped");
+        }
+        Ok(())
+    }
+
+    pub(super) fn buffer(&self, slot: usize) -> Option<vk::Buffer> {
+        self.buffers.get(slot).map(|buffer| buffer.buffer)
+    }
+
+    // Every light probe lookup is redone, for after `Vulkan::light_probes` changed.
+    pub(super) fn mark_all_dirty(&mut self) {
+        for index in 0..self.instances.len() {
+            self.mark_dirty(index);
+        }
+    }
+
+    pub(super) fn cleanup(&mut self, allocator: &mut Allocator, logical_device: &Device) {
+        let retired: Vec<_> = self.retired.drain().flatten().collect();
+        for mut buffer in self.buffers.drain(..).chain(retired) {
+            unsafe { buffer.cleanup(allocator, logical_device) };
+        }
+        // Grown again by the next upload if the instances are kept, see `Vulkan::recover`.
+        self.capacity = 0;
+    }
+}
@@ -0,0 +1,67 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use ash::{
+    vk::{self, Handle},
+    Device,
+};
+use log::error;
+
+// Every allocation bound to a buffer or image through `Buffer`, `Image` and `Texture`, keyed by
+// device and object so several renderers can live side by side. gpu_allocator's own shutdown
+// report only knows blocks and offsets, this names what was leaked.
+static LIVE: Mutex<BTreeMap<(u64, i32, u64), Allocation>> = Mutex::new(BTreeMap::new());
+
+struct Allocation {
+    name: String,
+    size: u64,
+}
+
+fn key<T: Handle>(logical_device: &Device, object: T) -> (u64, i32, u64) {
+    (
+        logical_device.handle().as_raw(),
+        T::TYPE.as_raw(),
+        object.as_raw(),
+    )
+}
+
+pub(super) fn track<T: Handle>(logical_device: &Device, object: T, name: &str, size: u64) {
+    let name = name.to_owned();
+    LIVE.lock()
+        .unwrap()
+        .insert(key(logical_device, object), Allocation { name, size });
+}
+
+pub(super) fn untrack<T: Handle>(logical_device: &Device, object: T) {
+    LIVE.lock().unwrap().remove(&key(logical_device, object));
+}
+
+// Logs everything still allocated on `logical_device` and forgets it. Call once everything has
+// been cleaned up, right before the allocator is dropped. Returns how many allocations leaked.
+pub(super) fn report(logical_device: &Device) -> usize {
+    let device = logical_device.handle().as_raw();
+    let mut live = LIVE.lock().unwrap();
+    let leaked: Vec<_> = live
+        .keys()
+        .filter(|(owner, _, _)| *owner == device)
+        .copied()
+        .collect();
+    let mut total = 0;
+    for key in &leaked {
+        let allocation = live.remove(key).unwrap();
+        error!(
+            "Leaked {:?} \"{}\" ({} bytes)",
+            vk::ObjectType::from_raw(key.1),
+            allocation.name,
+            allocation.size
+        );
+        total += allocation.size;
+    }
+    if !leaked.is_empty() {
+        error!(
+            "{} GPU allocations ({} bytes) were not cleaned up",
+            leaked.len(),
+            total
+        );
+    }
+    leaked.len()
+}
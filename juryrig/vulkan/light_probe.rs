@@ -0,0 +1,393 @@
+use std::{f32::consts::PI, path::Path};
+
+use na::Vector3;
+
+// Real spherical harmonic basis constants for bands 0 and 1.
+const Y0: f32 = 0.282095;
+const Y1: f32 = 0.488603;
+
+// Starts every file `LightProbeGrid::save` writes, the last byte is the format version.
+const FILE_MAGIC: &[u8; 8] = b"JRPROBE1";
+// The origin, spacing and size after the magic.
+const FILE_HEADER_LEN: usize = 8 + 4 * 3 + 4 + 4 * 3;
+
+#[derive(Debug)]
+pub enum ProbeFileError {
+    Io(std::io::Error),
+    /// Not written by `LightProbeGrid::save`, or cut short.
+    Invalid(&'static str),
+}
+
+impl From<std::io::Error> for ProbeFileError {
+    fn from(value: std::io::Error) -> Self {
+        ProbeFileError::Io(value)
+    }
+}
+
+/// The light arriving at a point from every direction, as first order spherical harmonics per
+/// colour channel. Four coefficients are too coarse for reflections but plenty for the diffuse
+/// ambient of dynamic objects, and they fit in the instance data.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ShProbe {
+    /// Constant term, then the linear terms along y, z and x. Each is rgb.
+    pub coefficients: [[f32; 3]; 4],
+}
+
+impl ShProbe {
+    /// The same radiance from every direction, lights a white surface with exactly `colour`.
+    pub fn ambient(colour: [f32; 3]) -> ShProbe {
+        let mut probe = ShProbe::default();
+        probe.coefficients[0] = colour.map(|channel| channel * Y0 * 4.0 * PI);
+        probe
+    }
+
+    /// Light arriving from `direction`, i.e. `direction` points from the probe towards the light.
+    pub fn add_directional(&mut self, direction: Vector3<f32>, colour: [f32; 3]) {
+        self.add_sample(direction, colour, 1.0);
+    }
+
+    /// Projects `radiance` seen along `direction` onto the basis, `weight` is the solid angle the
+    /// sample stands for.
+    pub fn add_sample(&mut self, direction: Vector3<f32>, radiance: [f32; 3], weight: f32) {
+        let d = direction.normalize();
+        let basis = [Y0, Y1 * d.y, Y1 * d.z, Y1 * d.x];
+        for (coefficient, basis) in self.coefficients.iter_mut().zip(basis) {
+            for channel in 0..3 {
+                coefficient[channel] += radiance[channel] * basis * weight;
+            }
+        }
+    }
+
+    fn lerp(&self, other: &ShProbe, t: f32) -> ShProbe {
+        let mut probe = *self;
+        for (coefficient, other) in probe.coefficients.iter_mut().zip(&other.coefficients) {
+            for channel in 0..3 {
+                coefficient[channel] += (other[channel] - coefficient[channel]) * t;
+            }
+        }
+        probe
+    }
+
+    // Convolved with a cosine lobe and divided by pi, so the shader gets the diffuse light for a
+    // normal n as `dot(terms[channel], vec4(1, n))`.
+    pub(super) fn irradiance_terms(&self) -> [[f32; 4]; 3] {
+        let [constant, y, z, x] = self.coefficients;
+        let mut terms = [[0.0; 4]; 3];
+        for channel in 0..3 {
+            terms[channel] = [
+                Y0 * constant[channel],
+                2.0 / 3.0 * Y1 * x[channel],
+                2.0 / 3.0 * Y1 * y[channel],
+                2.0 / 3.0 * Y1 * z[channel],
+            ];
+        }
+        terms
+    }
+}
+
+/// Probes on a regular grid. Instances get the probes around their origin blended together, and
+/// positions outside the grid use the nearest probes on its edge.
+#[derive(Clone, Debug)]
+pub struct LightProbeGrid {
+    /// Position of the first probe, the grid extends along +x, +y and +z from it.
+    pub origin: Vector3<f32>,
+    /// Distance between neighbouring probes.
+    pub spacing: f32,
+    size: [u32; 3],
+    // x fastest, then y, then z.
+    probes: Vec<ShProbe>,
+}
+
+impl LightProbeGrid {
+    pub fn new(origin: Vector3<f32>, spacing: f32, size: [u32; 3], fill: ShProbe) -> Self {
+        let size = size.map(|count| count.max(1));
+        LightProbeGrid {
+            origin,
+            spacing,
+            size,
+            probes: vec![fill; (size[0] * size[1] * size[2]) as usize],
+        }
+    }
+
+    /// Fills every probe from `radiance(probe_position, direction)`, sampled in `samples`
+    /// directions spread evenly over the sphere. Slow, do it at load time or on a worker thread.
+    pub fn bake(
+        origin: Vector3<f32>,
+        spacing: f32,
+        size: [u32; 3],
+        samples: u32,
+        mut radiance: impl FnMut(Vector3<f32>, Vector3<f32>) -> [f32; 3],
+    ) -> Self {
+        let mut grid = LightProbeGrid::new(origin, spacing, size, ShProbe::default());
+        let samples = samples.max(1);
+        let weight = 4.0 * PI / samples as f32;
+        // Fibonacci sphere, close to uniform without any randomness.
+        let golden_angle = PI * (3.0 - 5f32.sqrt());
+        let directions: Vec<Vector3<f32>> = (0..samples)
+            .map(|i| {
+                let z = 1.0 - (2.0 * i as f32 + 1.0) / samples as f32;
+                let r = (1.0 - z * z).sqrt();
+                let phi = golden_angle * i as f32;
+                Vector3::new(r * phi.cos(), r * phi.sin(), z)
+            })
+            .collect();
+        for z in 0..grid.size[2] {
+            for y in 0..grid.size[1] {
+                for x in 0..grid.size[0] {
+                    let position = grid.probe_position(x, y, z);
+                    let probe = grid.probe_mut(x, y, z).unwrap();
+                    for direction in &directions {
+                        probe.add_sample(*direction, radiance(position, *direction), weight);
+                    }
+                }
+            }
+        }
+        grid
+    }
+
+    pub fn size(&self) -> [u32; 3] {
+        self.size
+    }
+
+    pub fn probe_position(&self, x: u32, y: u32, z: u32) -> Vector3<f32> {
+        self.origin + Vector3::new(x as f32, y as f32, z as f32) * self.spacing
+    }
+
+    /// For updating probes at runtime, e.g. from a capture or when a light changes.
+    pub fn probe_mut(&mut self, x: u32, y: u32, z: u32) -> Option<&mut ShProbe> {
+        if x >= self.size[0] || y >= self.size[1] || z >= self.size[2] {
+            return None;
+        }
+        let index = x + self.size[0] * (y + self.size[1] * z);
+        self.probes.get_mut(index as usize)
+    }
+
+    fn probe(&self, x: u32, y: u32, z: u32) -> &ShProbe {
+        &self.probes[(x + self.size[0] * (y + self.size[1] * z)) as usize]
+    }
+
+    /// Writes the grid for `load`, e.g. after baking it offline with `bake`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ProbeFileError> {
+        Ok(std::fs::write(path, self.to_bytes())?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<LightProbeGrid, ProbeFileError> {
+        LightProbeGrid::from_bytes(&std::fs::read(path)?)
+    }
+
+    // Little endian: the magic, origin, spacing and size, then every probe's coefficients.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = FILE_MAGIC.to_vec();
+        let floats = self
+            .origin
+            .iter()
+            .copied()
+            .chain([self.spacing])
+            .map(f32::to_le_bytes);
+        bytes.extend(floats.flatten());
+        bytes.extend(self.size.iter().flat_map(|count| count.to_le_bytes()));
+        for probe in &self.probes {
+            bytes.extend(
+                probe
+                    .coefficients
+                    .iter()
+                    .flatten()
+                    .flat_map(|c| c.to_le_bytes()),
+            );
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<LightProbeGrid, ProbeFileError> {
+        if !bytes.starts_with(FILE_MAGIC) {
+            return Err(ProbeFileError::Invalid("not a light probe file"));
+        }
+        if bytes.len() < FILE_HEADER_LEN || !(bytes.len() - FILE_MAGIC.len()).is_multiple_of(4) {
+            return Err(ProbeFileError::Invalid("cut short"));
+        }
+        let words: Vec<[u8; 4]> = bytes[FILE_MAGIC.len()..]
+            .chunks_exact(4)
+            .map(|word| word.try_into().unwrap())
+            .collect();
+        let float = |index: usize| f32::from_le_bytes(words[index]);
+        let size = [4, 5, 6].map(|index| u32::from_le_bytes(words[index]));
+        let count = size.iter().try_fold(1usize, |count, &axis| {
+            count.checked_mul(axis as usize).filter(|&count| count > 0)
+        });
+        let Some(count) = count else {
+            return Err(ProbeFileError::Invalid("empty grid"));
+        };
+        let coefficients = &words[7..];
+        if coefficients.len() != count.saturating_mul(12) {
+            return Err(ProbeFileError::Invalid("wrong number of probes"));
+        }
+        let probes = coefficients
+            .chunks_exact(12)
+            .map(|probe| ShProbe {
+                coefficients: std::array::from_fn(|term| {
+                    std::array::from_fn(|channel| f32::from_le_bytes(probe[term * 3 + channel]))
+                }),
+            })
+            .collect();
+        Ok(LightProbeGrid {
+            origin: Vector3::new(float(0), float(1), float(2)),
+            spacing: float(3),
+            size,
+            probes,
+        })
+    }
+
+    /// The probes around `position` blended trilinearly.
+    pub fn sample(&self, position: Vector3<f32>) -> ShProbe {
+        let grid_position = (position - self.origin) / self.spacing.max(f32::EPSILON);
+        let mut cell = [0; 3];
+        let mut t = [0.0; 3];
+        for axis in 0..3 {
+            let last = (self.size[axis] - 1) as f32;
+            let clamped = grid_position[axis].clamp(0.0, last);
+            cell[axis] = (clamped.floor() as u32).min(self.size[axis].saturating_sub(2));
+            t[axis] = (clamped - cell[axis] as f32).min(1.0);
+        }
+        let next = |axis: usize| (cell[axis] + 1).min(self.size[axis] - 1);
+        let corner = |dx: bool, dy: bool, dz: bool| {
+            self.probe(
+                if dx { next(0) } else { cell[0] },
+                if dy { next(1) } else { cell[1] },
+                if dz { next(2) } else { cell[2] },
+            )
+        };
+        let along_x = |dy: bool, dz: bool| corner(false, dy, dz).lerp(corner(true, dy, dz), t[0]);
+        let along_y = |dz: bool| along_x(false, dz).lerp(&along_x(true, dz), t[1]);
+        along_y(false).lerp(&along_y(true), t[2])
+    }
+}
+
+impl Default for LightProbeGrid {
+    // A single probe with the dim grey ambient the renderer has always used.
+    fn default() -> Self {
+        LightProbeGrid::new(
+            Vector3::zeros(),
+            1.0,
+            [1, 1, 1],
+            ShProbe::ambient([0.2, 0.2, 0.2]),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The diffuse light the shader works out for `normal`, per channel.
+    fn irradiance(probe: &ShProbe, normal: Vector3<f32>) -> [f32; 3] {
+        probe
+            .irradiance_terms()
+            .map(|terms| terms[0] + terms[1] * normal.x + terms[2] * normal.y + terms[3] * normal.z)
+    }
+
+    fn close(a: [f32; 3], b: [f32; 3], tolerance: f32) -> bool {
+        (0..3).all(|channel| (a[channel] - b[channel]).abs() < tolerance)
+    }
+
+    #[test]
+    fn ambient_lights_every_normal_with_its_colour() {
+        let probe = ShProbe::ambient([0.2, 0.5, 1.0]);
+        for normal in [
+            Vector3::x(),
+            -Vector3::y(),
+            Vector3::new(1.0, 1.0, -1.0).normalize(),
+        ] {
+            assert!(close(irradiance(&probe, normal), [0.2, 0.5, 1.0], 1e-4));
+        }
+    }
+
+    #[test]
+    fn baking_constant_radiance_matches_ambient() {
+        let grid = LightProbeGrid::bake(Vector3::zeros(), 1.0, [1, 1, 1], 256, |_, _| {
+            [0.3, 0.6, 0.9]
+        });
+        let probe = grid.sample(Vector3::zeros());
+        let ambient = ShProbe::ambient([0.3, 0.6, 0.9]);
+        for normal in [Vector3::z(), -Vector3::x()] {
+            assert!(close(
+                irradiance(&probe, normal),
+                irradiance(&ambient, normal),
+                1e-2
+            ));
+        }
+    }
+
+    #[test]
+    fn directional_light_is_brightest_facing_it() {
+        let mut probe = ShProbe::default();
+        let towards_light = Vector3::new(0.0, 1.0, 0.0);
+        probe.add_directional(towards_light, [1.0, 1.0, 1.0]);
+        let facing = irradiance(&probe, towards_light)[0];
+        let side = irradiance(&probe, Vector3::x())[0];
+        let away = irradiance(&probe, -towards_light)[0];
+        assert!(facing > side && side > away, "{} {} {}", facing, side, away);
+    }
+
+    #[test]
+    fn sampling_blends_neighbouring_probes() {
+        let mut grid = LightProbeGrid::new(
+            Vector3::new(1.0, 0.0, 0.0),
+            2.0,
+            [2, 1, 1],
+            ShProbe::ambient([0.0; 3]),
+        );
+        *grid.probe_mut(1, 0, 0).unwrap() = ShProbe::ambient([1.0; 3]);
+        let at = |x: f32| irradiance(&grid.sample(Vector3::new(x, 5.0, -5.0)), Vector3::y())[0];
+        assert!(at(1.0).abs() < 1e-4);
+        assert!((at(2.0) - 0.5).abs() < 1e-4);
+        assert!((at(3.0) - 1.0).abs() < 1e-4);
+        // Outside the grid the nearest edge is used.
+        assert!(at(-10.0).abs() < 1e-4);
+        assert!((at(10.0) - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn probes_outside_the_grid_are_none() {
+        let mut grid = LightProbeGrid::new(Vector3::zeros(), 1.0, [2, 3, 0], ShProbe::default());
+        assert_eq!(grid.size(), [2, 3, 1]);
+        assert!(grid.probe_mut(1, 2, 0).is_some());
+        assert!(grid.probe_mut(2, 0, 0).is_none());
+        assert!(grid.probe_mut(0, 0, 1).is_none());
+        assert_eq!(grid.probe_position(1, 2, 0), Vector3::new(1.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn files_round_trip() {
+        let mut grid = LightProbeGrid::new(
+            Vector3::new(-1.0, 2.0, 0.5),
+            1.5,
+            [2, 1, 3],
+            ShProbe::ambient([0.1, 0.2, 0.3]),
+        );
+        grid.probe_mut(1, 0, 2)
+            .unwrap()
+            .add_directional(Vector3::y(), [1.0, 0.5, 0.25]);
+        let read = LightProbeGrid::from_bytes(&grid.to_bytes()).unwrap();
+        assert_eq!(read.origin, grid.origin);
+        assert_eq!(read.spacing, grid.spacing);
+        assert_eq!(read.size(), grid.size());
+        assert_eq!(read.probes, grid.probes);
+    }
+
+    #[test]
+    fn broken_files_are_invalid() {
+        let bytes = LightProbeGrid::default().to_bytes();
+        let invalid = |bytes: &[u8]| {
+            matches!(
+                LightProbeGrid::from_bytes(bytes),
+                Err(ProbeFileError::Invalid(_))
+            )
+        };
+        assert!(invalid(&bytes[..bytes.len() - 4]));
+        assert!(invalid(&bytes[..FILE_HEADER_LEN - 1]));
+        assert!(invalid(b"JRPROBE0"));
+        let mut empty = bytes[..FILE_HEADER_LEN].to_vec();
+        empty[FILE_HEADER_LEN - 4..].copy_from_slice(&0u32.to_le_bytes());
+        assert!(invalid(&empty));
+    }
+}
@@ -0,0 +1,24 @@
+use na::Vector3;
+
+/// The directional light and flat ambient the scene is shaded with, uploaded with the frame
+/// uniforms every frame. A `Sky` replaces the direction and colour with its sun while it is set.
+#[derive(Clone, Copy, Debug)]
+pub struct LightingSettings {
+    /// Towards the light, doesn't have to be normalised.
+    pub direction: Vector3<f32>,
+    /// Linear, can go past 1.
+    pub colour: [f32; 3],
+    /// Added to the light probes' ambient everywhere.
+    pub ambient: [f32; 3],
+}
+
+impl Default for LightingSettings {
+    // White light from up and to the side, no ambient beyond the light probes.
+    fn default() -> Self {
+        LightingSettings {
+            direction: Vector3::new(1.0, 1.0, 1.0),
+            colour: [1.0; 3],
+            ambient: [0.0; 3],
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use ash::{vk, Device};
+use gpu_allocator::vulkan::Allocator;
+use na::Vector3;
+
+use super::buffer::Buffer;
+
+// Lights past this many are left out, the fragment shader loops over every light for every
+// pixel so a scene shouldn't get close.
+pub(super) const MAX_LIGHTS: u32 = 256;
+
+/// A light on top of the directional one in `LightingSettings`. Both kinds fade out smoothly,
+/// reaching nothing at `range`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Light {
+    Point {
+        position: Vector3<f32>,
+        // Linear, can go past 1.
+        colour: [f32; 3],
+        range: f32,
+    },
+    Spot {
+        position: Vector3<f32>,
+        // Where the cone points, doesn't have to be normalised.
+        direction: Vector3<f32>,
+        colour: [f32; 3],
+        range: f32,
+        // Half angles in radians: full light inside `inner_angle`, fading to none at
+        // `outer_angle`.
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+impl Light {
+    fn data(&self) -> LightData {
+        match *self {
+            Light::Point {
+                position,
+                colour,
+                range,
+            } => LightData {
+                position: [position.x, position.y, position.z, range],
+                colour: [colour[0], colour[1], colour[2], 0.0],
+                direction: [0.0; 4],
+                // Past every angle's cosine, so the cone never dims a point light.
+                cone: [-1.0, -2.0, 0.0, 0.0],
+            },
+            Light::Spot {
+                position,
+                direction,
+                colour,
+                range,
+                inner_angle,
+                outer_angle,
+            } => {
+                let direction = direction.normalize();
+                let cos_outer = outer_angle.cos();
+                LightData {
+                    position: [position.x, position.y, position.z, range],
+                    colour: [colour[0], colour[1], colour[2], 0.0],
+                    direction: [direction.x, direction.y, direction.z, 0.0],
+                    // Kept apart so the fade never divides by zero.
+                    cone: [inner_angle.cos().max(cos_outer + 1e-4), cos_outer, 0.0, 0.0],
+                }
+            }
+        }
+    }
+}
+
+// A light as the fragment shader reads it from the light buffer.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct LightData {
+    // xyz: position, w: range.
+    position: [f32; 4],
+    // rgb: colour, a unused.
+    colour: [f32; 4],
+    // xyz: where a spot light points, w unused.
+    direction: [f32; 4],
+    // x: cosine of the inner angle, y: cosine of the outer angle, zw unused.
+    cone: [f32; 4],
+}
+
+// The lights set with `Vulkan::set_lights`, uploaded whole every frame to that frame's buffer.
+pub(super) struct LightStore {
+    lights: Vec<Light>,
+    buffers: Vec<Buffer<LightData>>,
+}
+
+impl LightStore {
+    pub(super) fn new() -> LightStore {
+        LightStore {
+            lights: vec![],
+            buffers: vec![],
+        }
+    }
+
+    pub(super) fn set(&mut self, lights: &[Light]) {
+        if lights.len() > MAX_LIGHTS as usize {
+            log::warn!(
+                "{} lights set, only the first {} are drawn",
+                lights.len(),
+                MAX_LIGHTS
+            );
+        }
+        self.lights.clear();
+        self.lights
+            .extend_from_slice(&lights[..lights.len().min(MAX_LIGHTS as usize)]);
+    }
+
+    pub(super) fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    // How many lights the shader loops over.
+    pub(super) fn count(&self) -> u32 {
+        self.lights.len() as u32
+    }
+
+    // Writes the lights into frame `slot`'s buffer and returns the buffer. The frame's previous
+    // submission must have finished.
+    pub(super) fn upload(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        slot: usize,
+    ) -> Result<vk::Buffer, vk::Result> {
+        while self.buffers.len() <= slot {
+            self.buffers.push(Buffer::new(
+                allocator,
+                logical_device,
+                MAX_LIGHTS as u64,
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+                "lights",
+                gpu_allocator::MemoryLocation::CpuToGpu,
+            )?);
+        }
+        let data: Vec<LightData> = self.lights.iter().map(Light::data).collect();
+        let buffer = &mut self.buffers[slot];
+        buffer.copy(&data).expect("light buffer is not mapped");
+        Ok(buffer.buffer)
+    }
+
+    pub(super) fn cleanup(&mut self, allocator: &mut Allocator, logical_device: &Device) {
+        for buffer in &mut self.buffers {
+            unsafe { buffer.cleanup(allocator, logical_device) };
+        }
+        self.buffers.clear();
+    }
+}
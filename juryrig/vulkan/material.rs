@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+
+use ash::{vk, Device};
+use gpu_allocator::vulkan::Allocator;
+use uuid::Uuid;
+
+use super::buffer::Buffer;
+
+// Materials past this many all read the default material's parameters on the GPU.
+pub(super) const MAX_MATERIALS: u32 = 1024;
+
+/// Per-material vertex sway, used for foliage and other geometry that should move with the wind.
+#[derive(Clone, Copy, Debug)]
+pub struct WindSway {
+    /// How far (in world units per unit of height) the geometry bends at full wind strength.
+    pub amplitude: f32,
+    /// How many sway cycles happen per second.
+    pub frequency: f32,
+}
+
+/// Blinn-Phong highlights of the directional light.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Specular {
+    /// Higher is a smaller, sharper highlight.
+    pub power: f32,
+    /// Colour of the highlight, multiplied with the light's. Black turns highlights off.
+    pub tint: [f32; 3],
+}
+
+impl Default for Specular {
+    fn default() -> Self {
+        Specular {
+            power: 32.0,
+            tint: [0.0; 3],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum MaterialParam {
+    /// When set the vertex shader displaces this material's vertices using the global wind.
+    Wind(Option<WindSway>),
+    /// Multiplied with the texture colour.
+    BaseColour([f32; 4]),
+    Specular(Specular),
+}
+
+/// Parameters are changed through `set_param` so the renderer knows which materials to upload,
+/// it's fine to change them every frame.
+#[derive(Clone, Copy, Debug)]
+pub struct Material {
+    wind: Option<WindSway>,
+    base_colour: [f32; 4],
+    specular: Specular,
+    // Bumped on every change, compared against what each frame's buffer last received.
+    version: u64,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            wind: None,
+            base_colour: [1.0; 4],
+            specular: Specular::default(),
+            version: 0,
+        }
+    }
+}
+
+impl Material {
+    pub fn set_param(&mut self, param: MaterialParam) {
+        match param {
+            MaterialParam::Wind(wind) => self.wind = wind,
+            MaterialParam::BaseColour(colour) => self.base_colour = colour,
+            MaterialParam::Specular(specular) => self.specular = specular,
+        }
+        self.version += 1;
+    }
+
+    pub fn wind(&self) -> Option<WindSway> {
+        self.wind
+    }
+
+    pub fn base_colour(&self) -> [f32; 4] {
+        self.base_colour
+    }
+
+    pub fn specular(&self) -> Specular {
+        self.specular
+    }
+
+    fn data(&self) -> MaterialData {
+        let Specular { power, tint } = self.specular;
+        MaterialData {
+            base_colour: self.base_colour,
+            specular: [tint[0], tint[1], tint[2], power],
+        }
+    }
+
+    // The sway values as they are laid out in the instance buffer, zero amplitude disables sway.
+    pub(super) fn wind_params(&self) -> [f32; 2] {
+        match self.wind {
+            Some(sway) => [sway.amplitude, sway.frequency],
+            None => [0.0, 0.0],
+        }
+    }
+}
+
+/// Global wind state, pushed to the vertex shader once per frame.
+#[derive(Clone, Copy, Debug)]
+pub struct Wind {
+    /// Horizontal direction the wind blows in (x and z in world space).
+    pub direction: na::Vector2<f32>,
+    pub strength: f32,
+}
+
+impl Default for Wind {
+    fn default() -> Self {
+        Wind {
+            direction: na::Vector2::new(1.0, 0.0),
+            strength: 1.0,
+        }
+    }
+}
+
+// A material as the fragment shader reads it from the material buffer.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub(super) struct MaterialData {
+    base_colour: [f32; 4],
+    // rgb: specular tint, a: specular power.
+    specular: [f32; 4],
+}
+
+// One copy of every material's parameters per frame in flight, so a frame can be updated while
+// the GPU is still reading the others.
+struct FrameMaterials {
+    buffer: Buffer<MaterialData>,
+    // The version of each material this buffer holds, None if it was never written.
+    versions: Vec<Option<u64>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MaterialHandle {
+    id: Uuid,
+}
+
+pub(super) struct MaterialStore {
+    materials_map: HashMap<Uuid, u32>,
+    pub materials: Vec<Material>,
+    frames: Vec<FrameMaterials>,
+}
+
+impl MaterialStore {
+    pub(super) fn new() -> MaterialStore {
+        MaterialStore {
+            materials_map: HashMap::new(),
+            materials: vec![],
+            frames: vec![],
+        }
+    }
+
+    pub(super) fn register_material(&mut self, material: Material) -> MaterialHandle {
+        let id = Uuid::new_v4();
+        self.materials.push(material);
+        self.materials_map
+            .insert(id, (self.materials.len() - 1) as u32);
+        MaterialHandle { id }
+    }
+
+    pub(super) fn get(&self, handle: &MaterialHandle) -> Option<&Material> {
+        self.materials_map
+            .get(&handle.id)
+            .map(|index| &self.materials[*index as usize])
+    }
+
+    pub(super) fn get_mut(&mut self, handle: &MaterialHandle) -> Option<&mut Material> {
+        self.materials_map
+            .get(&handle.id)
+            .map(|index| &mut self.materials[*index as usize])
+    }
+
+    // Where the material's parameters live in the material buffer.
+    pub(super) fn index(&self, handle: &MaterialHandle) -> Option<u32> {
+        self.materials_map
+            .get(&handle.id)
+            .copied()
+            .filter(|index| *index < MAX_MATERIALS)
+    }
+
+    // Writes the materials that changed since frame `slot` was last recorded into that frame's
+    // buffer and returns the buffer. The frame's previous submission must have finished.
+    pub(super) fn upload(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        slot: usize,
+    ) -> Result<vk::Buffer, vk::Result> {
+        while self.frames.len() <= slot {
+            let mut buffer = Buffer::new(
+                allocator,
+                logical_device,
+                MAX_MATERIALS as u64,
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+                "materials",
+                gpu_allocator::MemoryLocation::CpuToGpu,
+            )?;
+            buffer
+                .copy(&[Material::default().data(); MAX_MATERIALS as usize])
+                .expect("material buffer is not mapped");
+            self.frames.push(FrameMaterials {
+                buffer,
+                versions: vec![],
+            });
+        }
+
+        let frame = &mut self.frames[slot];
+        frame.versions.resize(self.materials.len(), None);
+        for (index, material) in self
+            .materials
+            .iter()
+            .enumerate()
+            .take(MAX_MATERIALS as usize)
+        {
+            if frame.versions[index] != Some(material.version) {
+                frame
+                    .buffer
+                    .write(index, &[material.data()])
+                    .expect("material buffer is not mapped");
+                frame.versions[index] = Some(material.version);
+            }
+        }
+        Ok(frame.buffer.buffer)
+    }
+
+    pub(super) fn cleanup(&mut self, allocator: &mut Allocator, logical_device: &Device) {
+        for frame in &mut self.frames {
+            unsafe { frame.buffer.cleanup(allocator, logical_device) };
+        }
+        self.frames.clear();
+    }
+}
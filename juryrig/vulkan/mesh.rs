@@ -0,0 +1,304 @@
+use ash::{
+    vk::{self, CommandBuffer},
+    Device,
+};
+use gpu_allocator::vulkan::Allocator;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::jr_mesh::{CompressedMeshVertex, MeshVertex};
+
+use super::{
+    bounds::{Aabb, BoundingSphere, MeshBounds},
+    buffer::{Buffer, Transfer},
+    gc::{Collector, GcConfig, HandleRef},
+    VertexBufferBindings,
+};
+
+#[repr(C)]
+pub struct ShaderVertexData {
+    pub position: na::Vector3<f32>,
+    pub uv: na::Vector2<f32>,
+    pub normal: na::Vector3<f32>,
+}
+
+crate::vertex_input!(ShaderVertexData {
+    5 => position: R32G32B32_SFLOAT,
+    6 => uv: R32G32_SFLOAT,
+    7 => normal: R32G32B32_SFLOAT,
+});
+
+// Laid out the same, so it can be drawn by pipelines built for `ShaderVertexData`.
+crate::vertex_input!(MeshVertex {
+    5 => position: R32G32B32_SFLOAT,
+    6 => uv: R32G32_SFLOAT,
+    7 => normal: R32G32B32_SFLOAT,
+});
+
+crate::vertex_input!(CompressedMeshVertex {
+    5 => position: R16G16B16A16_SFLOAT,
+    6 => uv: R16G16_SFLOAT,
+    7 => normal: R16G16_SNORM,
+});
+
+/// Where a vertex is in its mesh, which is all the renderer needs to know about a vertex type to
+/// work out the mesh's bounds. Vertex types passed to `Vulkan::register_mesh` implement it.
+pub trait VertexPosition {
+    fn position(&self) -> na::Vector3<f32>;
+}
+
+impl VertexPosition for ShaderVertexData {
+    fn position(&self) -> na::Vector3<f32> {
+        self.position
+    }
+}
+
+impl VertexPosition for MeshVertex {
+    fn position(&self) -> na::Vector3<f32> {
+        self.position.into()
+    }
+}
+
+impl VertexPosition for CompressedMeshVertex {
+    fn position(&self) -> na::Vector3<f32> {
+        let [x, y, z, _] = self
+            .position
+            .map(|bits| half::f16::from_bits(bits).to_f32());
+        na::Vector3::new(x, y, z)
+    }
+}
+
+/// A vulkan mesh that will not be changed during runtime, so it lives in GPU only memory. The
+/// vertex type is only known to the pipeline drawing it, so the vertices are stored as raw bytes.
+pub struct StaticMesh {
+    index_buffer: Buffer<u32>,
+    vertex_buffer: Buffer<u8>,
+    bounds: MeshBounds,
+}
+
+impl StaticMesh {
+    pub fn new<V>(
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        index_data: &[u32],
+        vertex_data: &[V],
+        bounds: MeshBounds,
+        transfer: &mut Transfer,
+    ) -> Result<StaticMesh, vk::Result> {
+        let vertex_bytes = unsafe {
+            std::slice::from_raw_parts(
+                vertex_data.as_ptr() as *const u8,
+                std::mem::size_of_val(vertex_data),
+            )
+        };
+        let mut index_buffer = Buffer::new_gpu_only(
+            allocator,
+            logical_device,
+            index_data,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            "index",
+            transfer,
+        )?;
+        let vertex_buffer = Buffer::new_gpu_only(
+            allocator,
+            logical_device,
+            vertex_bytes,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            "vertex",
+            transfer,
+        );
+        let vertex_buffer = match vertex_buffer {
+            Ok(vertex_buffer) => vertex_buffer,
+            Err(e) => {
+                unsafe { index_buffer.cleanup(allocator, logical_device) };
+                return Err(e);
+            }
+        };
+
+        Ok(StaticMesh {
+            index_buffer,
+            vertex_buffer,
+            bounds,
+        })
+    }
+
+    pub fn bind(&self, logical_device: &Device, command_buffer: CommandBuffer) {
+        unsafe {
+            logical_device.cmd_bind_index_buffer(
+                command_buffer,
+                self.index_buffer.buffer,
+                0,
+                vk::IndexType::UINT32,
+            );
+
+            logical_device.cmd_bind_vertex_buffers(
+                command_buffer,
+                VertexBufferBindings::MeshBuffer as u32,
+                &[self.vertex_buffer.buffer],
+                &[0],
+            );
+        }
+    }
+
+    pub fn index_count(&self) -> u64 {
+        self.index_buffer.len()
+    }
+
+    pub fn bounds(&self) -> &MeshBounds {
+        &self.bounds
+    }
+
+    pub fn aabb(&self) -> &Aabb {
+        &self.bounds.aabb
+    }
+
+    pub fn bounding_sphere(&self) -> &BoundingSphere {
+        &self.bounds.sphere
+    }
+
+    pub(crate) unsafe fn cleanup(&mut self, allocator: &mut Allocator, logical_device: &Device) {
+        self.index_buffer.cleanup(allocator, logical_device);
+
+        self.vertex_buffer.cleanup(allocator, logical_device);
+    }
+}
+
+/// The mesh stays alive while any clone of its handle does.
+#[derive(Clone)]
+pub struct MeshHandle {
+    id: Uuid,
+    refs: HandleRef,
+}
+
+impl MeshHandle {
+    /// Identifies the mesh in engine events.
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+}
+
+// Meshes registered at runtime, laid out like `TextureStore`.
+pub(super) struct MeshStore {
+    meshes_map: HashMap<Uuid, u32>,
+    // Collected meshes leave a None behind so the other indices don't move.
+    meshes: Vec<Option<StaticMesh>>,
+    // The store's own copy of each mesh's handle.
+    handles: Vec<Option<MeshHandle>>,
+    free_slots: Vec<u32>,
+    collector: Collector<StaticMesh>,
+}
+
+impl MeshStore {
+    pub(super) fn new() -> MeshStore {
+        MeshStore {
+            meshes_map: HashMap::new(),
+            meshes: vec![],
+            handles: vec![],
+            free_slots: vec![],
+            collector: Collector::new(),
+        }
+    }
+
+    pub(super) fn register_mesh<V>(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        vertex_data: &[V],
+        index_data: &[u32],
+        bounds: MeshBounds,
+        mut transfer: Transfer,
+    ) -> Result<MeshHandle, vk::Result> {
+        let mesh = StaticMesh::new(
+            allocator,
+            logical_device,
+            index_data,
+            vertex_data,
+            bounds,
+            &mut transfer,
+        )?;
+        let handle = MeshHandle {
+            id: Uuid::new_v4(),
+            refs: HandleRef::default(),
+        };
+        let index = match self.free_slots.pop() {
+            Some(index) => {
+                self.meshes[index as usize] = Some(mesh);
+                self.handles[index as usize] = Some(handle.clone());
+                index
+            }
+            None => {
+                self.meshes.push(Some(mesh));
+                self.handles.push(Some(handle.clone()));
+                (self.meshes.len() - 1) as u32
+            }
+        };
+        self.meshes_map.insert(handle.id, index);
+        Ok(handle)
+    }
+
+    pub(super) fn get(&self, handle: &MeshHandle) -> Option<&StaticMesh> {
+        self.meshes_map
+            .get(&handle.id)
+            .and_then(|index| self.meshes[*index as usize].as_ref())
+    }
+
+    // The store's own copy of every mesh's handle.
+    pub(super) fn handles(&self) -> impl Iterator<Item = &MeshHandle> {
+        self.handles.iter().flatten()
+    }
+
+    // Like `TextureStore::adopt`, mesh indices aren't seen outside the store so it keeps its own.
+    pub(super) fn adopt(&mut self, registered: &MeshHandle, handle: MeshHandle) {
+        let Some(index) = self.meshes_map.remove(&registered.id) else {
+            return;
+        };
+        self.meshes_map.insert(handle.id, index);
+        self.handles[index as usize] = Some(handle);
+    }
+
+    // Same as `TextureStore::collect_garbage`, returns the ids of the meshes taken out of the
+    // store.
+    pub(super) fn collect_garbage(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        frame: u64,
+        latency: u64,
+        config: &GcConfig,
+    ) -> Vec<Uuid> {
+        let mut evicted = vec![];
+        for index in self
+            .collector
+            .scan(self.handles.len(), config.scan_per_frame)
+        {
+            let unreferenced = self.handles[index]
+                .as_ref()
+                .is_some_and(|handle| handle.refs.is_unreferenced());
+            if unreferenced {
+                let handle = self.handles[index].take().unwrap();
+                self.meshes_map.remove(&handle.id);
+                evicted.push(handle.id);
+                self.free_slots.push(index as u32);
+                if let Some(mesh) = self.meshes[index].take() {
+                    self.collector.retire(frame, mesh);
+                }
+            }
+        }
+        for mut mesh in self
+            .collector
+            .ready(frame, latency, config.destroy_per_frame)
+        {
+            unsafe { mesh.cleanup(allocator, logical_device) };
+        }
+        evicted
+    }
+
+    pub(super) fn cleanup(&mut self, allocator: &mut Allocator, logical_device: &Device) {
+        for mesh in self.meshes.iter_mut().flatten() {
+            unsafe { mesh.cleanup(allocator, logical_device) };
+        }
+        for mut mesh in self.collector.drain() {
+            unsafe { mesh.cleanup(allocator, logical_device) };
+        }
+    }
+}
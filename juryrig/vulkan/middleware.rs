@@ -0,0 +1,68 @@
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+
+use super::FrameArena;
+
+/// What a middleware needs to create its GPU resources. Handed out when the middleware is added
+/// and again every time the swapchain is recreated.
+pub struct SetupContext<'a> {
+    pub instance: &'a ash::Instance,
+    pub physical_device: vk::PhysicalDevice,
+    pub device: &'a ash::Device,
+    pub allocator: &'a mut Allocator,
+    /// The main render pass, pipelines drawn in `in_main_pass` must be compatible with it.
+    pub render_pass: vk::RenderPass,
+    pub extent: vk::Extent2D,
+    /// The swapchain image's, what `after_main_pass` draws to.
+    pub surface_format: vk::Format,
+    /// The main render pass's colour attachment, the scene in linear light before it's
+    /// tonemapped.
+    pub scene_format: vk::Format,
+    /// The main render pass's depth attachment, see `swapchain::choose_depth_format`.
+    pub depth_format: vk::Format,
+    /// How many frames can be recorded before one is reused, size per-frame resources to this.
+    pub frames_in_flight: usize,
+}
+
+/// The frame currently being recorded.
+pub struct FrameContext<'a> {
+    pub device: &'a ash::Device,
+    pub command_buffer: vk::CommandBuffer,
+    pub render_pass: vk::RenderPass,
+    pub framebuffer: vk::Framebuffer,
+    /// The swapchain image being drawn to, to import into a `RenderGraph` as its backbuffer.
+    pub image_view: vk::ImageView,
+    pub extent: vk::Extent2D,
+    /// Which of the `frames_in_flight` frames this is, for indexing per-frame resources.
+    pub frame_index: usize,
+    pub frame: u64,
+    /// The camera's view projection in the renderer's depth convention.
+    pub view_projection: na::Matrix4<f32>,
+    pub arena: &'a FrameArena,
+}
+
+/// Lets code outside the renderer (UI, plotting, video layers...) record its own commands into
+/// the frame. Every hook has an empty default, implement the ones you need. Middleware runs in
+/// the order it was added.
+pub trait RenderMiddleware {
+    /// Create or recreate anything that depends on the swapchain. Called when the middleware is
+    /// added and after every swapchain recreation, with the device idle.
+    fn prepare(&mut self, _context: &mut SetupContext) -> Result<(), vk::Result> {
+        Ok(())
+    }
+
+    /// Before the main render pass begins, for uploads, compute work and layout transitions.
+    fn before_main_pass(&mut self, _context: &FrameContext) {}
+
+    /// Inside the main render pass after the scene has been drawn, for anything lit or placed in
+    /// the world. It's tonemapped with the scene, before the UI and text are drawn over it.
+    fn in_main_pass(&mut self, _context: &FrameContext) {}
+
+    /// After the scene has been tonemapped onto the swapchain image and the UI and text drawn,
+    /// the image is in PRESENT_SRC_KHR layout.
+    fn after_main_pass(&mut self, _context: &FrameContext) {}
+
+    /// Destroy everything the middleware created. Called with the device idle, before the
+    /// renderer is torn down.
+    fn cleanup(&mut self, _device: &ash::Device, _allocator: &mut Allocator) {}
+}
@@ -0,0 +1,3785 @@
+mod animation;
+mod asset_graph;
+mod assets;
+mod atlas;
+mod bake;
+mod bounds;
+mod budget;
+mod buffer;
+mod camera;
+mod compute;
+mod config;
+mod debug;
+mod debug_view;
+mod display;
+mod entity;
+mod impostor;
+mod initialisation;
+mod instances;
+mod light_probe;
+mod lighting;
+mod lights;
+mod material;
+mod mesh;
+mod middleware;
+mod model;
+mod pick;
+mod pipeline;
+mod pipeline_store;
+mod post;
+mod present_timing;
+mod profiler;
+mod recovery;
+mod render_graph;
+mod render_target;
+mod sampler;
+mod scene;
+mod shaders;
+mod shadow;
+mod sky;
+mod skybox;
+mod spirv;
+mod surface;
+mod swapchain;
+mod test_pattern;
+mod text;
+mod texture;
+mod thumbnail;
+mod toasts;
+mod tonemap;
+#[cfg(feature = "ui")]
+mod ui;
+mod vertex_format;
+
+use crate::alloc_telemetry::{self, AllocationStats, Subsystem};
+use crate::jr_gltf::GltfScene;
+use crate::jr_image::{HDRImage, RGBAImage, RGBAPixel};
+use crate::jr_texture::{BlockFormat, CompressedImage};
+
+use self::{
+    animation::Animator,
+    asset_graph::AssetGraph,
+    assets::{AssetLoader, Finished, LoadedImage},
+    bounds::Frustum,
+    entity::{Entities, UploadContext},
+    events::EventBus,
+    impostor::Impostors,
+    initialisation::{
+        create_instance, init_device_and_queues, init_output_pass,
+        init_physical_device_and_properties, init_renderpass, QueueFamilies, Queues,
+    },
+    instances::RetainedInstances,
+    lights::LightStore,
+    material::MaterialStore,
+    mesh::MeshStore,
+    pipeline_store::PipelineStore,
+    post::PostProcess,
+    present_timing::PresentTimer,
+    shaders::{ShaderTarget, ShaderWatcher, WatchedShaders},
+    sky::SkyPipeline,
+    skybox::SkyboxPipeline,
+    surface::Surface,
+    test_pattern::TestPatternPipeline,
+    text::TextRenderer,
+    texture::TextureStore,
+    thumbnail::Thumbnailer,
+    toasts::Toasts,
+    tonemap::TonemapPipeline,
+};
+use ash::{
+    vk::{self, DescriptorImageInfo},
+    Device, Entry, Instance,
+};
+use gpu_allocator::vulkan::{Allocator, AllocatorCreateDesc};
+use log::{debug, info, warn};
+use na::{Matrix4, Vector2, Vector3};
+use winit::window::Window;
+
+use self::buffer::{StagingRing, Transfer, TransientPool, STAGING_RING_SIZE};
+use self::compute::ComputeQueue;
+use self::debug::Debug;
+use self::frame_allocator::FrameAllocator;
+use self::frame_timer::{DrawCounts, FrameTimer};
+use self::frame_uniforms::{FrameUniformData, FrameUniforms};
+use self::gc::Collector;
+use self::pipeline::{Pipeline, PipelineShaders, WrittenSets};
+use self::profiler::GpuProfiler;
+use self::recovery::{KeptResources, TextureSource};
+use self::render_target::OffscreenTarget;
+use self::shadow::ShadowMap;
+use self::swapchain::{RetiredSwapchain, Swapchain, SwapchainDesc, SCENE_FORMAT};
+use self::texture::TextureDesc;
+#[cfg(feature = "ui")]
+use self::ui::UiRenderer;
+
+mod error;
+mod events;
+mod frame_allocator;
+mod frame_arena;
+mod frame_timer;
+mod frame_uniforms;
+mod gc;
+mod leaks;
+
+pub use self::animation::{Animatable, AnimationHandle, Easing, Repeat, Tween};
+pub use self::asset_graph::DependencyCycle;
+pub use self::assets::{AssetHandle, Registrar, UploadBudget, UploadStats};
+pub use self::atlas::{Atlas, SpriteHandle};
+pub use self::bake::{bake, bake_environment, BakeError};
+pub use self::bounds::{Aabb, BoundingSphere, MeshBounds, Ray};
+pub use self::budget::FrameBudget;
+pub use self::camera::{Camera, CameraController};
+pub use self::compute::{
+    ComputeBinding, ComputeBuffer, ComputeContext, ComputeImage, ComputePipeline,
+};
+pub use self::config::{
+    BackgroundMode, DepthRange, FrameLimit, Hdr10, RendererConfig, ShadowConfig, Tonemapper,
+    MAX_FRAMES_IN_FLIGHT,
+};
+pub use self::debug::{cmd_begin_debug_label, cmd_end_debug_label, set_object_name};
+pub use self::debug_view::DebugView;
+pub use self::display::{current_monitor, monitors, MonitorInfo, VideoModeInfo};
+pub use self::entity::{sort_key, Entity, EntityId};
+pub use self::error::{InitError, RuntimeError};
+pub use self::events::{AssetKind, EngineEvent};
+pub use self::frame_arena::FrameArena;
+pub use self::frame_timer::FrameTiming;
+pub use self::gc::GcConfig;
+pub use self::impostor::ImpostorHandle;
+pub use self::initialisation::{QueueAssignment, QueueMapping};
+pub use self::instances::InstanceSlot;
+pub use self::light_probe::{LightProbeGrid, ProbeFileError, ShProbe};
+pub use self::lighting::LightingSettings;
+pub use self::lights::Light;
+pub use self::material::{Material, MaterialHandle, MaterialParam, Specular, Wind, WindSway};
+pub use self::mesh::{MeshHandle, ShaderVertexData, VertexPosition};
+pub use self::middleware::{FrameContext, RenderMiddleware, SetupContext};
+pub use self::model::{Model, ModelNode, ModelPrimitive};
+pub use self::pick::{PickHit, Picked};
+pub use self::pipeline_store::{
+    BlendMode, CullMode, DepthState, MaterialDesc, PipelineHandle, RenderState,
+};
+pub use self::post::{PostEffect, PostEffectHandle};
+pub use self::present_timing::PresentTiming;
+pub use self::profiler::{FrameStats, PipelineStatistics, ShadowDraws};
+pub use self::render_graph::{
+    AttachmentId, AttachmentPolicy, CompiledGraph, LoadPolicy, PassDesc, PassId, RenderGraph,
+    RenderGraphError, StorePolicy,
+};
+pub use self::render_target::RenderTarget;
+pub use self::sampler::SamplerDesc;
+pub use self::scene::{NodeId, Scene, SceneNode};
+pub use self::shaders::{load_shader, ShaderError, ShaderStage};
+pub use self::sky::Sky;
+pub use self::test_pattern::TestPattern;
+pub use self::text::Font;
+pub use self::texture::{uv_rect_for_cell, TextureHandle, TextureRegion, UvRect};
+pub use self::toasts::ToastLevel;
+pub use self::vertex_format::{
+    assert_attributes_fit, VertexAttribute, VertexFormat, VertexFormatHandle, VertexInput,
+    VertexLayout, VertexLayoutError,
+};
+// For `vertex_input!` in crates that don't depend on ash themselves.
+#[doc(hidden)]
+pub use ash;
+
+#[derive(Copy, Clone)]
+enum VertexBufferBindings {
+    InstanceBuffer = 0,
+    MeshBuffer = 1,
+}
+
+/// What the vertex shader gets per instance, at the locations its `VertexInput` impl in pipeline.rs
+/// gives. Build one with `new` and the `with_` methods so fields added later get their defaults.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct InstanceData {
+    pub model: [[f32; 4]; 4],
+    pub texture_index: u32,
+    /// Wind sway amplitude and frequency, taken from the instance's material.
+    pub wind: [f32; 2],
+    /// Index into the material buffer.
+    pub material_index: u32,
+    /// Which part of the texture the mesh's uvs map to, see `uv_rect_for_cell`.
+    pub uv_rect: UvRect,
+    /// Ambient light at the instance's origin, filled in from `Vulkan::light_probes`.
+    pub ambient: [[f32; 4]; 3],
+    /// Multiplies the texture and material colour, white leaves them as they are.
+    pub tint: [f32; 4],
+}
+
+impl InstanceData {
+    /// Placed by `model`, with texture and material 0, the whole texture, no tint and no wind.
+    pub fn new(model: Matrix4<f32>) -> InstanceData {
+        InstanceData {
+            model: model.into(),
+            texture_index: 0,
+            wind: [0.0; 2],
+            material_index: 0,
+            uv_rect: UvRect::FULL,
+            ambient: [[0.0; 4]; 3],
+            tint: [1.0; 4],
+        }
+    }
+
+    pub fn with_texture(mut self, texture_index: u32) -> InstanceData {
+        self.texture_index = texture_index;
+        self
+    }
+
+    pub fn with_material(mut self, material_index: u32) -> InstanceData {
+        self.material_index = material_index;
+        self
+    }
+
+    pub fn with_uv_rect(mut self, uv_rect: UvRect) -> InstanceData {
+        self.uv_rect = uv_rect;
+        self
+    }
+
+    pub fn with_tint(mut self, tint: [f32; 4]) -> InstanceData {
+        self.tint = tint;
+        self
+    }
+
+    pub fn with_wind(mut self, wind: [f32; 2]) -> InstanceData {
+        self.wind = wind;
+        self
+    }
+}
+
+impl Default for InstanceData {
+    fn default() -> Self {
+        InstanceData::new(Matrix4::identity())
+    }
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub enum LightType {
+    Directional = 0,
+    Point = 1,
+    Spot = 2,
+}
+
+#[repr(C)]
+pub struct LightData {
+    pub type_: LightType,
+    pub position: [f32; 4],
+    pub color: [f32; 4],
+    pub intensity: f32,
+}
+
+struct Pools {
+    graphics: vk::CommandPool,
+    compute: vk::CommandPool,
+}
+
+impl Pools {
+    fn init(
+        logical_device: &ash::Device,
+        queue_families: &QueueFamilies,
+    ) -> Result<Pools, vk::Result> {
+        // Graphics Pool
+        let graphics_commandpool_info = vk::CommandPoolCreateInfo::builder()
+            .queue_family_index(queue_families.graphics)
+            .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER);
+        let commandpool_graphics =
+            unsafe { logical_device.create_command_pool(&graphics_commandpool_info, None) }?;
+
+        // Compute Pool
+        let compute_commandpool_info = vk::CommandPoolCreateInfo::builder()
+            .queue_family_index(queue_families.compute)
+            .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER);
+        let commandpool_compute =
+            unsafe { logical_device.create_command_pool(&compute_commandpool_info, None) }?;
+
+        Ok(Pools {
+            graphics: commandpool_graphics,
+            compute: commandpool_compute,
+        })
+    }
+    fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            logical_device.destroy_command_pool(self.graphics, None);
+            logical_device.destroy_command_pool(self.compute, None);
+        }
+    }
+}
+
+fn load_shader_pair(
+    vertex_shader: &std::path::Path,
+    fragment_shader: &std::path::Path,
+) -> Result<(Vec<u32>, Vec<u32>), ShaderError> {
+    Ok((
+        load_shader(vertex_shader, ShaderStage::Vertex)?,
+        load_shader(fragment_shader, ShaderStage::Fragment)?,
+    ))
+}
+
+// Meshes and textures are uploaded on the transfer queue and drawn on the graphics queue.
+fn upload_transfer<'a>(
+    queue_families: &QueueFamilies,
+    commands: &'a mut TransientPool,
+    staging: &'a mut StagingRing,
+) -> Transfer<'a> {
+    Transfer {
+        queue_families: [queue_families.transfer, queue_families.graphics],
+        commands,
+        staging,
+    }
+}
+
+// For copies that have to wait for frames still drawing, `commands` submits to the graphics
+// queue.
+fn graphics_transfer<'a>(
+    queue_families: &QueueFamilies,
+    commands: &'a mut TransientPool,
+    staging: &'a mut StagingRing,
+) -> Transfer<'a> {
+    Transfer {
+        queue_families: [queue_families.graphics; 2],
+        commands,
+        staging,
+    }
+}
+
+/// The renderer lives on the thread that created it, every method here is for that thread. Other
+/// threads register textures and meshes through a `Registrar`, and the handles it gives back, like
+/// `TextureHandle` and `MeshHandle`, can be sent anywhere.
+pub struct Vulkan {
+    instance: Instance,
+    entry: Entry,
+    debug: std::mem::ManuallyDrop<Option<Debug>>,
+    surface: std::mem::ManuallyDrop<Surface>,
+    physical_device: vk::PhysicalDevice,
+    queue_families: QueueFamilies,
+    logical_device: Device,
+    queues: Queues,
+    swapchain: Swapchain,
+    // Replaced by present mode switches, waiting for the frames that used them to finish.
+    retired_swapchains: Collector<RetiredSwapchain>,
+    // Draws the scene into the swapchain's scene buffer, in linear light.
+    renderpass: vk::RenderPass,
+    // Tonemaps the scene onto the swapchain image and draws the UI and text over it.
+    output_pass: vk::RenderPass,
+    // Runs the `set_post_effects` chain over the scene between the two.
+    post_process: PostProcess,
+    tonemap_pipeline: TonemapPipeline,
+    graphics_pipeline: Pipeline,
+    // Draws into `shadow_map` every frame shadows are on.
+    shadow_pipeline: Pipeline,
+    // Only 1 texel while `config.shadows` is None, see `update_shadow_map`.
+    shadow_map: ShadowMap,
+    shadow_format: (vk::Format, vk::Filter),
+    // Drawn instead of `graphics_pipeline` while a debug view is on.
+    debug_pipeline: Option<Pipeline>,
+    debug_view: DebugView,
+    // Built from user registered vertex formats.
+    custom_pipelines: std::collections::HashMap<VertexFormatHandle, Pipeline>,
+    // Registered with `register_pipeline`, for entities that don't draw with the scene pipeline.
+    pipeline_store: PipelineStore,
+    // Whether the device can draw wireframe `RenderState`s.
+    wireframe_supported: bool,
+    // Whether it can sample BC and ASTC compressed textures, see `register_compressed_texture`.
+    texture_compression_bc: bool,
+    texture_compression_astc_ldr: bool,
+    // Replaces the built-in scene shaders after `load_scene_shaders`.
+    scene_shaders: Option<(Vec<u32>, Vec<u32>)>,
+    shader_watcher: ShaderWatcher,
+    // Files from `load_texture`, `load_mesh` and `add_asset_dependency`, for hot reload.
+    asset_graph: AssetGraph,
+    test_pattern_pipeline: TestPatternPipeline,
+    sky_pipeline: SkyPipeline,
+    skybox_pipeline: SkyboxPipeline,
+    // Drawn instead of the scene while set.
+    test_pattern: Option<TestPattern>,
+    // Created by the first `set_font`.
+    text: Option<TextRenderer>,
+    toasts: Toasts,
+    // Created by the first `draw_ui`.
+    #[cfg(feature = "ui")]
+    ui: Option<UiRenderer>,
+    command_buffer_pools: Pools,
+    command_buffers: Vec<vk::CommandBuffer>,
+    // One-off submissions to the transfer and graphics queues, and the staging memory their
+    // uploads are copied from.
+    transfer_commands: TransientPool,
+    graphics_commands: TransientPool,
+    staging_ring: StagingRing,
+    allocator: std::mem::ManuallyDrop<Allocator>,
+    // Drawn every frame, see `add_entity`.
+    entities: Entities,
+    /// Nodes placed relative to their parents, drawn with the entities.
+    pub scene: Scene,
+    // Instances added with `add_instance`, drawn every frame until removed.
+    retained: RetainedInstances,
+    pub camera: Camera,
+    // Built in, drawn for retained instances.
+    cube: MeshHandle,
+    mesh_store: MeshStore,
+    texture_store: TextureStore,
+    thumbnailer: Option<Thumbnailer>,
+    material_store: MaterialStore,
+    default_material: MaterialHandle,
+    frame_uniforms: FrameUniforms,
+    // Per-frame GPU data: entity instances, frame uniforms and UI geometry.
+    frame_allocator: FrameAllocator,
+    // Drawn before the main pass every frame, in the order they were created.
+    render_targets: Vec<OffscreenTarget>,
+    // Each has a render target of its own in `render_targets`.
+    impostors: Impostors,
+    pub wind: Wind,
+    /// Ambient light for everything drawn, see `LightProbeGrid`.
+    pub light_probes: LightProbeGrid,
+    /// The sun and flat ambient the scene is shaded with.
+    pub lighting: LightingSettings,
+    // Point and spot lights, see `set_lights`.
+    lights: LightStore,
+    /// Drawn behind the scene and used for its directional light when set.
+    pub sky: Option<Sky>,
+    // A cubemap drawn behind the scene instead of the sky, see `set_skybox`.
+    skybox: Option<TextureHandle>,
+    start_time: std::time::Instant,
+    animator: Animator,
+    frame_arena: FrameArena,
+    events: EventBus,
+    assets: AssetLoader,
+    middleware: Vec<Box<dyn RenderMiddleware>>,
+    /// Frame times over these limits are logged.
+    pub budget: FrameBudget,
+    // With `RendererConfig::device_lost_recovery`, for `recover`.
+    kept: Option<KeptResources>,
+    gpu_profiler: Option<GpuProfiler>,
+    // The most recent frame the GPU has finished, see `frame_stats`.
+    frame_stats: Option<FrameStats>,
+    allocation_stats: AllocationStats,
+    // The streamed uploads `update_assets` started this frame.
+    upload_stats: UploadStats,
+    frame_timer: FrameTimer,
+    frame_count: u64,
+    pub config: RendererConfig,
+    focused: bool,
+    occluded: bool,
+    last_frame: Option<std::time::Instant>,
+    // Of the monitor the window is on, in Hz.
+    refresh_rate: Option<f32>,
+    present_mode: vk::PresentModeKHR,
+    present_timer: PresentTimer,
+    // Loaded when the device supports VK_EXT_hdr_metadata.
+    hdr_metadata: Option<vk::ExtHdrMetadataFn>,
+    depth_range: DepthRange,
+    surface_format: vk::SurfaceFormatKHR,
+    depth_format: vk::Format,
+    halt_render: bool,
+}
+
+impl Vulkan {
+    pub fn new(window: &Window) -> std::result::Result<Self, InitError> {
+        Self::with_config(window, RendererConfig::default())
+    }
+
+    pub fn with_config(
+        window: &Window,
+        config: RendererConfig,
+    ) -> std::result::Result<Self, InitError> {
+        let entry = unsafe { Entry::load() }?;
+
+        let validation = debug::validation_enabled(&entry, config.validation)?;
+        let instance = create_instance(&entry, &window.title(), true, validation)?;
+
+        // Vulkan debugging
+        let mut debug = validation
+            .then(|| Debug::new(&entry, &instance))
+            .transpose()?;
+
+        let surface: Surface = Surface::new(&window, &entry, &instance)?;
+
+        let (physical_device, physical_device_properties) =
+            init_physical_device_and_properties(&instance, Some(&surface))?;
+
+        let queue_families = QueueFamilies::new(&instance, physical_device, &surface)?;
+
+        let (logical_device, queues, extensions) =
+            init_device_and_queues(&instance, physical_device, &queue_families)?;
+        if let Some(debug) = &mut debug {
+            debug.attach(&logical_device);
+        }
+        let present_timer =
+            PresentTimer::new(&instance, &logical_device, extensions.display_timing);
+        let hdr_metadata = extensions.hdr_metadata.then(|| {
+            vk::ExtHdrMetadataFn::load(|name| unsafe {
+                std::mem::transmute(
+                    instance.get_device_proc_addr(logical_device.handle(), name.as_ptr()),
+                )
+            })
+        });
+        let surface_format = swapchain::choose_surface_format(
+            &surface.get_formats(physical_device)?,
+            config.hdr10.is_some(),
+        );
+        info!(
+            "Surface format {:?} in {:?}",
+            surface_format.format, surface_format.color_space
+        );
+        let depth_format = swapchain::choose_depth_format(&instance, physical_device).ok_or(
+            InitError::DeviceSelectionError("no depth format can be rendered to"),
+        )?;
+        info!("Depth format {:?}", depth_format);
+
+        let refresh_rate =
+            display::current_monitor(window).and_then(|monitor| monitor.refresh_rate);
+        let present_mode =
+            config.present_mode(refresh_rate, &surface.get_present_modes(physical_device)?);
+        info!(
+            "Presenting with {:?}, refresh rate {:?} Hz",
+            present_mode, refresh_rate
+        );
+
+        let mut allocator = Allocator::new(&AllocatorCreateDesc {
+            instance: instance.clone(),
+            device: logical_device.clone(),
+            physical_device,
+            debug_settings: Default::default(),
+            buffer_device_address: true,
+        })?;
+
+        let frames_in_flight = config.frames_in_flight.clamp(1, MAX_FRAMES_IN_FLIGHT);
+        let mut swapchain = Swapchain::init(
+            &instance,
+            physical_device,
+            &logical_device,
+            &mut allocator,
+            &surface,
+            &queue_families,
+            SwapchainDesc {
+                surface_format,
+                depth_format,
+                present_mode,
+                frames_in_flight,
+            },
+        )?;
+
+        let renderpass = init_renderpass(&logical_device, depth_format)?;
+        let output_pass = init_output_pass(&logical_device, surface_format)?;
+
+        swapchain.create_framebuffers(&logical_device, renderpass, output_pass)?;
+        let post_process = PostProcess::new(
+            &logical_device,
+            &mut allocator,
+            swapchain.extent,
+            swapchain.scene_view(),
+        )?;
+        let tonemap_pipeline = TonemapPipeline::init(
+            &logical_device,
+            &swapchain,
+            &output_pass,
+            post_process.targets(swapchain.scene_view()),
+        )?;
+
+        Pipeline::validate_builtin()?;
+        let mut texture_store =
+            TextureStore::new(&logical_device, &physical_device_properties.limits)?;
+        let graphics_pipeline = Pipeline::init(
+            &logical_device,
+            &renderpass,
+            config.depth_range,
+            texture_store.capacity(),
+        )?;
+        let shadow_format = shadow::choose_shadow_format(&instance, physical_device);
+        let shadow_map = ShadowMap::new(
+            &logical_device,
+            &mut allocator,
+            shadow_format,
+            config.shadows.map_or(1, |shadows| shadows.resolution),
+            config.depth_range,
+        )?;
+        let shadow_pipeline = Pipeline::init_shadow(
+            &logical_device,
+            &shadow_map.render_pass(),
+            config.depth_range,
+            texture_store.capacity(),
+            RenderState::default(),
+        )?;
+        // Test patterns check the display, so they skip tonemapping.
+        let test_pattern_pipeline = TestPatternPipeline::init(&logical_device, &output_pass)?;
+        let sky_pipeline = SkyPipeline::init(&logical_device, &renderpass)?;
+        let skybox_pipeline =
+            SkyboxPipeline::init(&logical_device, &renderpass, config.depth_range)?;
+
+        let pools = Pools::init(&logical_device, &queue_families)?;
+        let mut transfer_commands =
+            TransientPool::new(&logical_device, queues.transfer, queue_families.transfer)?;
+        let mut graphics_commands =
+            TransientPool::new(&logical_device, queues.graphics, queue_families.graphics)?;
+        let mut staging_ring = StagingRing::new(
+            &mut allocator,
+            &logical_device,
+            STAGING_RING_SIZE,
+            &[queue_families.transfer, queue_families.graphics],
+        )?;
+        texture_store
+            .create_placeholder(
+                &mut allocator,
+                &logical_device,
+                upload_transfer(&queue_families, &mut transfer_commands, &mut staging_ring),
+                &mut graphics_commands,
+            )
+            .map_err(|e| match e {
+                RuntimeError::AllocationError(e) => InitError::AllocationError(e),
+                RuntimeError::VKErr(e) => InitError::VKErr(e),
+                RuntimeError::DeviceLost => InitError::VKErr(vk::Result::ERROR_DEVICE_LOST),
+                // Only a device without a single texture index gets here.
+                _ => InitError::DeviceSelectionError("The device has no room for textures"),
+            })?;
+
+        let command_buffers =
+            Self::create_commandbuffers(&logical_device, &pools, frames_in_flight)?;
+
+        let gpu_profiler = GpuProfiler::new(
+            &logical_device,
+            &physical_device_properties.limits,
+            command_buffers.len(),
+            config.pipeline_statistics && extensions.pipeline_statistics_query,
+        )?;
+
+        let entities = Entities::new();
+        let frame_allocator = FrameAllocator::new(command_buffers.len());
+        let retained = RetainedInstances::new(command_buffers.len());
+
+        let mut my_camera = Camera::default();
+        my_camera.move_backward(6f32);
+
+        let index_data: Vec<u32> = vec![
+            0, 1, 2, 3, 4, 5, // front face
+            6, 7, 8, 9, 10, 11, // top face
+            12, 13, 14, 15, 16, 17, // bottom face
+            18, 19, 20, 21, 22, 23, // back face
+            24, 25, 26, 27, 28, 29, // left face
+            30, 31, 32, 33, 34, 35, // right face
+        ];
+
+        let vertex_data = [
+            ShaderVertexData {
+                position: Vector3::new(-1.000000, 1.000000, -1.000000),
+                uv: Vector2::new(0.374988, 0.666810),
+                normal: Vector3::new(-0.0000, 1.0000, -0.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(1.000000, 1.000000, 1.000000),
+                uv: Vector2::new(0.343755, 0.733482),
+                normal: Vector3::new(-0.0000, 1.0000, -0.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(1.000000, 1.000000, -1.000000),
+                uv: Vector2::new(0.343804, 0.666761),
+                normal: Vector3::new(-0.0000, 1.0000, -0.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(1.000000, 1.000000, 1.000000),
+                uv: Vector2::new(0.312471, 0.733295),
+                normal: Vector3::new(-0.0000, -0.0000, 1.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(-1.000000, -1.000000, 1.000000),
+                uv: Vector2::new(0.281288, 0.666747),
+                normal: Vector3::new(-0.0000, -0.0000, 1.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(1.000000, -1.000000, 1.000000),
+                uv: Vector2::new(0.312494, 0.666682),
+                normal: Vector3::new(-0.0000, -0.0000, 1.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(-1.000000, 1.000000, 1.000000),
+                uv: Vector2::new(0.406424, 0.733344),
+                normal: Vector3::new(-1.0000, -0.0000, -0.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(-1.000000, -1.000000, -1.000000),
+                uv: Vector2::new(0.375017, 0.666931),
+                normal: Vector3::new(-1.0000, -0.0000, -0.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(-1.000000, -1.000000, 1.000000),
+                uv: Vector2::new(0.406197, 0.667131),
+                normal: Vector3::new(-1.0000, -0.0000, -0.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(1.000000, -1.000000, -1.000000),
+                uv: Vector2::new(0.374875, 0.667210),
+                normal: Vector3::new(-0.0000, -1.0000, -0.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(-1.000000, -1.000000, 1.000000),
+                uv: Vector2::new(0.343703, 0.733315),
+                normal: Vector3::new(-0.0000, -1.0000, -0.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(-1.000000, -1.000000, -1.000000),
+                uv: Vector2::new(0.343703, 0.667158),
+                normal: Vector3::new(-0.0000, -1.0000, -0.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(1.000000, 1.000000, -1.000000),
+                uv: Vector2::new(0.343723, 0.733344),
+                normal: Vector3::new(1.0000, -0.0000, -0.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(1.000000, -1.000000, 1.000000),
+                uv: Vector2::new(0.312531, 0.666785),
+                normal: Vector3::new(1.0000, -0.0000, -0.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(1.000000, -1.000000, -1.000000),
+                uv: Vector2::new(0.343706, 0.666848),
+                normal: Vector3::new(1.0000, -0.0000, -0.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(-1.000000, 1.000000, -1.000000),
+                uv: Vector2::new(0.406446, 0.733250),
+                normal: Vector3::new(-0.0000, -0.0000, -1.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(1.000000, -1.000000, -1.000000),
+                uv: Vector2::new(0.375170, 0.667212),
+                normal: Vector3::new(-0.0000, -0.0000, -1.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(-1.000000, -1.000000, -1.000000),
+                uv: Vector2::new(0.406162, 0.666986),
+                normal: Vector3::new(-0.0000, -0.0000, -1.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(-1.000000, 1.000000, -1.000000),
+                uv: Vector2::new(0.374988, 0.666810),
+                normal: Vector3::new(-0.0000, 1.0000, -0.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(-1.000000, 1.000000, 1.000000),
+                uv: Vector2::new(0.375027, 0.733414),
+                normal: Vector3::new(-0.0000, 1.0000, -0.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(1.000000, 1.000000, 1.000000),
+                uv: Vector2::new(0.343755, 0.733482),
+                normal: Vector3::new(-0.0000, 1.0000, -0.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(1.000000, 1.000000, 1.000000),
+                uv: Vector2::new(0.312471, 0.733295),
+                normal: Vector3::new(0.0000, -0.0000, 1.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(-1.000000, 1.000000, 1.000000),
+                uv: Vector2::new(0.281267, 0.733332),
+                normal: Vector3::new(0.0000, -0.0000, 1.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(-1.000000, -1.000000, 1.000000),
+                uv: Vector2::new(0.281288, 0.666747),
+                normal: Vector3::new(0.0000, -0.0000, 1.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(-1.000000, 1.000000, 1.000000),
+                uv: Vector2::new(0.406424, 0.733344),
+                normal: Vector3::new(-1.0000, -0.0000, -0.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(-1.000000, 1.000000, -1.000000),
+                uv: Vector2::new(0.375060, 0.733192),
+                normal: Vector3::new(-1.0000, -0.0000, -0.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(-1.000000, -1.000000, -1.000000),
+                uv: Vector2::new(0.375017, 0.666931),
+                normal: Vector3::new(-1.0000, -0.0000, -0.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(1.000000, -1.000000, -1.000000),
+                uv: Vector2::new(0.374875, 0.667210),
+                normal: Vector3::new(-0.0000, -1.0000, -0.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(1.000000, -1.000000, 1.000000),
+                uv: Vector2::new(0.374875, 0.733262),
+                normal: Vector3::new(-0.0000, -1.0000, -0.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(-1.000000, -1.000000, 1.000000),
+                uv: Vector2::new(0.343703, 0.733315),
+                normal: Vector3::new(-0.0000, -1.0000, -0.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(1.000000, 1.000000, -1.000000),
+                uv: Vector2::new(0.343723, 0.733344),
+                normal: Vector3::new(1.0000, -0.0000, -0.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(1.000000, 1.000000, 1.000000),
+                uv: Vector2::new(0.312471, 0.733295),
+                normal: Vector3::new(1.0000, -0.0000, -0.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(1.000000, -1.000000, 1.000000),
+                uv: Vector2::new(0.312531, 0.666785),
+                normal: Vector3::new(1.0000, -0.0000, -0.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(-1.000000, 1.000000, -1.000000),
+                uv: Vector2::new(0.406446, 0.733250),
+                normal: Vector3::new(-0.0000, -0.0000, -1.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(1.000000, 1.000000, -1.000000),
+                uv: Vector2::new(0.375164, 0.733269),
+                normal: Vector3::new(-0.0000, -0.0000, -1.0000),
+            },
+            ShaderVertexData {
+                position: Vector3::new(1.000000, -1.000000, -1.000000),
+                uv: Vector2::new(0.375170, 0.667212),
+                normal: Vector3::new(-0.0000, -0.0000, -1.0000),
+            },
+        ];
+
+        let mut mesh_store = MeshStore::new();
+        let cube = mesh_store.register_mesh(
+            &mut allocator,
+            &logical_device,
+            &vertex_data,
+            &index_data,
+            MeshBounds::from_points(vertex_data.iter().map(VertexPosition::position)),
+            upload_transfer(&queue_families, &mut transfer_commands, &mut staging_ring),
+        )?;
+        let mut material_store = MaterialStore::new();
+        let default_material = material_store.register_material(Material::default());
+        let vulkan = Self {
+            instance,
+            entry,
+            debug: std::mem::ManuallyDrop::new(debug),
+            surface: std::mem::ManuallyDrop::new(surface),
+            surface_format,
+            depth_format,
+            physical_device,
+            queue_families,
+            logical_device,
+            queues,
+            swapchain,
+            retired_swapchains: Collector::new(),
+            renderpass,
+            output_pass,
+            post_process,
+            tonemap_pipeline,
+            graphics_pipeline,
+            shadow_pipeline,
+            shadow_map,
+            shadow_format,
+            debug_pipeline: None,
+            debug_view: DebugView::Off,
+            custom_pipelines: std::collections::HashMap::new(),
+            pipeline_store: PipelineStore::new(),
+            wireframe_supported: extensions.fill_mode_non_solid,
+            texture_compression_bc: extensions.texture_compression_bc,
+            texture_compression_astc_ldr: extensions.texture_compression_astc_ldr,
+            scene_shaders: None,
+            shader_watcher: ShaderWatcher::new(),
+            asset_graph: AssetGraph::new(),
+            test_pattern_pipeline,
+            sky_pipeline,
+            skybox_pipeline,
+            test_pattern: None,
+            text: None,
+            toasts: Toasts::default(),
+            #[cfg(feature = "ui")]
+            ui: None,
+            command_buffer_pools: pools,
+            transfer_commands,
+            graphics_commands,
+            staging_ring,
+            command_buffers,
+            allocator: std::mem::ManuallyDrop::new(allocator),
+            entities,
+            scene: Scene::new(),
+            retained,
+            cube,
+            mesh_store,
+            camera: my_camera,
+            texture_store,
+            thumbnailer: None,
+            material_store,
+            default_material,
+            frame_uniforms: FrameUniforms::new(),
+            frame_allocator,
+            render_targets: vec![],
+            impostors: Impostors::default(),
+            wind: Wind::default(),
+            light_probes: LightProbeGrid::default(),
+            lighting: LightingSettings::default(),
+            lights: LightStore::new(),
+            sky: None,
+            skybox: None,
+            start_time: std::time::Instant::now(),
+            animator: Animator::default(),
+            frame_arena: FrameArena::default(),
+            events: EventBus::default(),
+            assets: AssetLoader::default(),
+            middleware: vec![],
+            budget: FrameBudget::default(),
+            kept: config.device_lost_recovery.then(KeptResources::default),
+            gpu_profiler,
+            frame_stats: None,
+            frame_timer: FrameTimer::default(),
+            allocation_stats: AllocationStats::default(),
+            upload_stats: UploadStats::default(),
+            frame_count: 0,
+            depth_range: config.depth_range,
+            config,
+            focused: true,
+            occluded: false,
+            last_frame: None,
+            refresh_rate,
+            present_mode,
+            present_timer,
+            hdr_metadata,
+            halt_render: false,
+        };
+        vulkan.apply_hdr_metadata();
+        Ok(vulkan)
+    }
+
+    /// Replaces the HDR10 metadata sent to the display, e.g. with the light levels of a video
+    /// that's about to play. Has no effect unless the renderer is presenting to an HDR10 surface.
+    pub fn set_hdr10(&mut self, hdr10: Hdr10) {
+        self.config.hdr10 = Some(hdr10);
+        self.apply_hdr_metadata();
+    }
+
+    /// The queue family and queue each kind of work was given on this device.
+    pub fn queue_mapping(&self) -> QueueMapping {
+        self.queue_families.mapping
+    }
+
+    /// Whether the swapchain is HDR10, i.e. `config.hdr10` was set and the display offered it.
+    pub fn is_hdr10(&self) -> bool {
+        self.surface_format.color_space == vk::ColorSpaceKHR::HDR10_ST2084_EXT
+    }
+
+    // Metadata belongs to the swapchain, so this has to be repeated whenever it's recreated.
+    fn apply_hdr_metadata(&self) {
+        if !self.is_hdr10() {
+            return;
+        }
+        match (&self.hdr_metadata, &self.config.hdr10) {
+            (Some(hdr_metadata), Some(hdr10)) => unsafe {
+                (hdr_metadata.set_hdr_metadata_ext)(
+                    self.logical_device.handle(),
+                    1,
+                    &self.swapchain.swapchain,
+                    &hdr10.metadata(),
+                )
+            },
+            (None, Some(_)) => {
+                warn!("Presenting HDR10 without VK_EXT_hdr_metadata, the display will guess")
+            }
+            _ => {}
+        }
+    }
+
+    /// `sampler` picks how the texture is filtered and wrapped, `SamplerDesc::default()` for
+    /// smooth filtering or `SamplerDesc::NEAREST` for pixel art. Identical descs share a sampler.
+    pub fn register_texture(
+        &mut self,
+        image: &RGBAImage,
+        sampler: SamplerDesc,
+    ) -> Result<TextureHandle, RuntimeError> {
+        let _scope = alloc_telemetry::scope(Subsystem::Textures);
+        let result = self.texture_store.register_texture(
+            &mut self.allocator,
+            &self.logical_device,
+            &image,
+            sampler,
+            upload_transfer(
+                &self.queue_families,
+                &mut self.transfer_commands,
+                &mut self.staging_ring,
+            ),
+        );
+        self.keep_texture(&result, || TextureSource::Image(image.clone(), sampler));
+        self.texture_registered(result)
+    }
+
+    /// Uploads a KTX2 or DDS texture from `jr_texture` with its mip chain, still compressed, so it
+    /// takes a quarter to an eighth of the memory of the same texture as RGBA. When the device
+    /// can't sample the format, BC1 to BC3 are decompressed and registered like `register_texture`
+    /// instead, anything else fails with `RuntimeError::UnsupportedFeature`.
+    pub fn register_compressed_texture(
+        &mut self,
+        image: &CompressedImage,
+        sampler: SamplerDesc,
+    ) -> Result<TextureHandle, RuntimeError> {
+        if !self.supports_compressed_format(image.format) {
+            return match image.decode() {
+                Some(decoded) => self.register_texture(&decoded, sampler),
+                None if image.format.is_astc() => Err(RuntimeError::UnsupportedFeature(
+                    "textureCompressionASTC_LDR",
+                )),
+                None => Err(RuntimeError::UnsupportedFeature("textureCompressionBC")),
+            };
+        }
+        let _scope = alloc_telemetry::scope(Subsystem::Textures);
+        let result = self.texture_store.register_compressed(
+            &mut self.allocator,
+            &self.logical_device,
+            image,
+            sampler,
+            upload_transfer(
+                &self.queue_families,
+                &mut self.transfer_commands,
+                &mut self.staging_ring,
+            ),
+        );
+        self.keep_texture(&result, || {
+            TextureSource::Compressed(image.clone(), sampler)
+        });
+        self.texture_registered(result)
+    }
+
+    /// Whether `register_compressed_texture` can upload `format` without decompressing it, for
+    /// picking between files compressed for desktop and mobile GPUs.
+    pub fn supports_compressed_format(&self, format: BlockFormat) -> bool {
+        match format.is_astc() {
+            true => self.texture_compression_astc_ldr,
+            false => self.texture_compression_bc,
+        }
+    }
+
+    /// Like `register_texture` for linear colour with values past 1, e.g. light maps or an
+    /// environment to draw with the HDR10 output.
+    pub fn register_hdr_texture(
+        &mut self,
+        image: &HDRImage,
+    ) -> Result<TextureHandle, RuntimeError> {
+        let _scope = alloc_telemetry::scope(Subsystem::Textures);
+        let result = self.texture_store.register_hdr_texture(
+            &mut self.allocator,
+            &self.logical_device,
+            image,
+            upload_transfer(
+                &self.queue_families,
+                &mut self.transfer_commands,
+                &mut self.staging_ring,
+            ),
+        );
+        self.keep_texture(&result, || TextureSource::Hdr(image.clone()));
+        self.texture_registered(result)
+    }
+
+    /// A cubemap from six square faces seen from inside the cube with +y up, in the order +x, -x,
+    /// +y, -y, +z, -z. For `set_skybox`, entities can't sample it.
+    pub fn register_cubemap(
+        &mut self,
+        faces: [&RGBAImage; 6],
+    ) -> Result<TextureHandle, RuntimeError> {
+        let _scope = alloc_telemetry::scope(Subsystem::Textures);
+        let result = self.texture_store.register_cubemap(
+            &mut self.allocator,
+            &self.logical_device,
+            faces,
+            upload_transfer(
+                &self.queue_families,
+                &mut self.transfer_commands,
+                &mut self.staging_ring,
+            ),
+        );
+        self.keep_texture(&result, || {
+            TextureSource::Cubemap(Box::new(faces.map(RGBAImage::clone)))
+        });
+        self.texture_registered(result)
+    }
+
+    /// Like `register_cubemap` from one latitude/longitude panorama, the usual layout of HDR
+    /// environment maps. Its centre faces +z and its top row is straight up.
+    pub fn register_equirectangular_cubemap(
+        &mut self,
+        image: &HDRImage,
+    ) -> Result<TextureHandle, RuntimeError> {
+        let _scope = alloc_telemetry::scope(Subsystem::Textures);
+        let result = self.texture_store.register_equirectangular_cubemap(
+            &mut self.allocator,
+            &self.logical_device,
+            image,
+            upload_transfer(
+                &self.queue_families,
+                &mut self.transfer_commands,
+                &mut self.staging_ring,
+            ),
+        );
+        self.keep_texture(&result, || TextureSource::Equirectangular(image.clone()));
+        self.texture_registered(result)
+    }
+
+    /// A transparent black texture for content made at runtime, e.g. a minimap or video frames,
+    /// written with `update_texture`.
+    pub fn create_empty_texture(
+        &mut self,
+        width: u32,
+        height: u32,
+    ) -> Result<TextureHandle, RuntimeError> {
+        let _scope = alloc_telemetry::scope(Subsystem::Textures);
+        let result = self.texture_store.create_empty_texture(
+            &mut self.allocator,
+            &self.logical_device,
+            width,
+            height,
+            upload_transfer(
+                &self.queue_families,
+                &mut self.transfer_commands,
+                &mut self.staging_ring,
+            ),
+            &mut self.graphics_commands,
+        );
+        self.keep_texture(&result, || {
+            TextureSource::Image(RGBAImage::new(width, height), SamplerDesc::default())
+        });
+        self.texture_registered(result)
+    }
+
+    /// A `width` x `height` texture the scene is drawn into every frame from the camera set with
+    /// `set_render_target_camera`, for mirrors, portals and minimaps. Draw its `texture()` on an
+    /// entity to show it.
+    pub fn create_render_target(
+        &mut self,
+        width: u32,
+        height: u32,
+    ) -> Result<RenderTarget, RuntimeError> {
+        let _scope = alloc_telemetry::scope(Subsystem::Textures);
+        let result = self.texture_store.create_render_target_texture(
+            &mut self.allocator,
+            &self.logical_device,
+            TextureDesc {
+                width,
+                height,
+                format: SCENE_FORMAT,
+                usage: vk::ImageUsageFlags::empty(),
+            },
+            upload_transfer(
+                &self.queue_families,
+                &mut self.transfer_commands,
+                &mut self.staging_ring,
+            ),
+            &mut self.graphics_commands,
+        );
+        let texture = self.texture_registered(result)?;
+        let image_view = self
+            .texture_store
+            .get(&texture)
+            .ok_or(RuntimeError::InvalidHandle)?
+            .image_view;
+        let (target, handle) = OffscreenTarget::new(
+            &self.logical_device,
+            &mut self.allocator,
+            texture,
+            image_view,
+            vk::Extent2D { width, height },
+            SCENE_FORMAT,
+            self.depth_format,
+        )?;
+        self.render_targets.push(target);
+        Ok(handle)
+    }
+
+    /// The camera `target` is drawn from, None stops drawing it and leaves the texture as it was.
+    /// Set the camera's aspect to `target.aspect()` so the picture isn't stretched.
+    pub fn set_render_target_camera(
+        &mut self,
+        target: &RenderTarget,
+        camera: Option<Camera>,
+    ) -> Result<(), RuntimeError> {
+        let target = self
+            .render_targets
+            .iter_mut()
+            .find(|other| other.is(target))
+            .ok_or(RuntimeError::InvalidHandle)?;
+        target.camera = camera;
+        Ok(())
+    }
+
+    /// Stops drawing `target` and frees its depth buffer. Its texture lives on until the last
+    /// clone of its handle is dropped, showing the last picture drawn.
+    pub fn destroy_render_target(&mut self, target: &RenderTarget) -> Result<(), RuntimeError> {
+        let index = self
+            .render_targets
+            .iter()
+            .position(|other| other.is(target))
+            .ok_or(RuntimeError::InvalidHandle)?;
+        unsafe { self.logical_device.device_wait_idle() }?;
+        let mut target = self.render_targets.remove(index);
+        target.cleanup(&self.logical_device, &mut self.allocator);
+        Ok(())
+    }
+
+    /// Draws `entities` as one billboard while the camera is more than `distance` from their
+    /// middle, showing a picture of them baked into a `resolution` square render target. It's
+    /// baked again once the camera has moved far enough around them to see another side. Meant
+    /// for distant static geometry: the bounds are worked out now, and a hidden cluster casts no
+    /// shadow.
+    pub fn bake_impostor(
+        &mut self,
+        entities: &[EntityId],
+        resolution: u32,
+        distance: f32,
+    ) -> Result<ImpostorHandle, RuntimeError> {
+        let spheres: Vec<BoundingSphere> = entities
+            .iter()
+            .filter_map(|id| {
+                let entity = self.entities.get(id)?;
+                let mesh = self.mesh_store.get(&entity.mesh)?;
+                Some(mesh.bounding_sphere().transformed(&entity.transform))
+            })
+            .collect();
+        let bounds = impostor::enclosing(&spheres).ok_or(RuntimeError::InvalidHandle)?;
+        let quad = match &self.impostors.quad {
+            Some(quad) => quad.clone(),
+            None => {
+                // One unit across facing -z, the top of the texture up.
+                let vertices: Vec<ShaderVertexData> =
+                    [(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)]
+                        .into_iter()
+                        .map(|(x, y)| ShaderVertexData {
+                            position: Vector3::new(x, y, 0.0),
+                            uv: Vector2::new(x + 0.5, 0.5 - y),
+                            normal: Vector3::new(0.0, 0.0, -1.0),
+                        })
+                        .collect();
+                let quad = self.register_mesh(&vertices, &[0, 1, 2, 2, 3, 0])?;
+                self.impostors.quad = Some(quad.clone());
+                quad
+            }
+        };
+        // Lit when baked, and blended so the clear background around the cluster doesn't show.
+        let pipeline = self.register_pipeline(MaterialDesc::unlit())?;
+        let target = self.create_render_target(resolution, resolution)?;
+        let mut billboard = Entity::new(quad, target.texture().clone());
+        billboard.pipeline = Some(pipeline);
+        let billboard = self.entities.add(billboard);
+        self.entities.set_hidden(&billboard, true);
+        Ok(self
+            .impostors
+            .add(entities.to_vec(), target, billboard, bounds, distance))
+    }
+
+    /// Shows the impostor's entities at every distance again and frees its render target.
+    pub fn destroy_impostor(&mut self, impostor: &ImpostorHandle) -> Result<(), RuntimeError> {
+        let (target, billboard) = self
+            .impostors
+            .remove(impostor, &mut self.entities)
+            .ok_or(RuntimeError::InvalidHandle)?;
+        self.entities.remove(&billboard);
+        self.destroy_render_target(&target)
+    }
+
+    fn compute_queue(&self) -> ComputeQueue<'_> {
+        ComputeQueue {
+            logical_device: &self.logical_device,
+            queue: self.queues.compute,
+            pool: self.command_buffer_pools.compute,
+            queue_families: vec![self.queue_families.graphics, self.queue_families.compute],
+        }
+    }
+
+    /// A storage buffer for `dispatch_compute`, shared with the graphics queue. `len` is in
+    /// elements, see `ComputeContext::create_buffer` for picking `location`.
+    pub fn create_compute_buffer<T>(
+        &mut self,
+        len: u64,
+        location: gpu_allocator::MemoryLocation,
+        name: &str,
+    ) -> Result<ComputeBuffer<T>, RuntimeError> {
+        let queue = ComputeQueue {
+            logical_device: &self.logical_device,
+            queue: self.queues.compute,
+            pool: self.command_buffer_pools.compute,
+            queue_families: vec![self.queue_families.graphics, self.queue_families.compute],
+        };
+        queue.create_buffer(&mut self.allocator, len, location, name)
+    }
+
+    /// A storage image for `dispatch_compute`, in the GENERAL layout. `format` must support
+    /// storage images.
+    pub fn create_compute_image(
+        &mut self,
+        width: u32,
+        height: u32,
+        format: vk::Format,
+        name: &str,
+    ) -> Result<ComputeImage, RuntimeError> {
+        let queue = ComputeQueue {
+            logical_device: &self.logical_device,
+            queue: self.queues.compute,
+            pool: self.command_buffer_pools.compute,
+            queue_families: vec![self.queue_families.graphics, self.queue_families.compute],
+        };
+        queue.create_image(&mut self.allocator, width, height, format, name)
+    }
+
+    /// `bindings` are the descriptor types of set 0 in binding order, `push_constant_size` is in
+    /// bytes and may be 0.
+    pub fn create_compute_pipeline(
+        &self,
+        spirv: &[u32],
+        bindings: &[vk::DescriptorType],
+        push_constant_size: u32,
+    ) -> Result<ComputePipeline, RuntimeError> {
+        self.compute_queue()
+            .create_pipeline(spirv, bindings, push_constant_size)
+    }
+
+    /// Runs `pipeline` over `groups` workgroups on the compute queue and waits for it, for
+    /// particle simulation, image processing and the like. What it writes can be read back or
+    /// used by the next dispatch once this returns.
+    pub fn dispatch_compute(
+        &self,
+        pipeline: &ComputePipeline,
+        bindings: &[ComputeBinding],
+        push_constants: &[u8],
+        groups: [u32; 3],
+    ) -> Result<(), RuntimeError> {
+        self.compute_queue()
+            .dispatch(pipeline, bindings, push_constants, groups)
+    }
+
+    /// Copies tightly packed texels from the start of `buffer` into `image`.
+    pub fn copy_compute_buffer_to_image<T>(
+        &self,
+        buffer: &ComputeBuffer<T>,
+        image: &ComputeImage,
+    ) -> Result<(), RuntimeError> {
+        self.compute_queue().copy_buffer_to_image(buffer, image)
+    }
+
+    /// Copies `image` into the start of `buffer`, tightly packed.
+    pub fn copy_compute_image_to_buffer<T>(
+        &self,
+        image: &ComputeImage,
+        buffer: &ComputeBuffer<T>,
+    ) -> Result<(), RuntimeError> {
+        self.compute_queue().copy_image_to_buffer(image, buffer)
+    }
+
+    /// Compute buffers, images and pipelines aren't tracked, hand each one back before the
+    /// renderer is dropped. Nothing may still be using them.
+    pub fn destroy_compute_buffer<T>(&mut self, buffer: ComputeBuffer<T>) {
+        compute::destroy_buffer(&self.logical_device, &mut self.allocator, buffer);
+    }
+
+    pub fn destroy_compute_image(&mut self, image: ComputeImage) {
+        compute::destroy_image(&self.logical_device, &mut self.allocator, image);
+    }
+
+    pub fn destroy_compute_pipeline(&self, pipeline: ComputePipeline) {
+        compute::destroy_pipeline(&self.logical_device, pipeline);
+    }
+
+    /// Overwrites `region` of a texture registered from an `RGBAImage` with `pixels`, row by row.
+    /// The texture keeps its handle and index, so whatever draws it shows the new pixels from the
+    /// next frame on. Frames already in flight are waited for before the copy.
+    pub fn update_texture(
+        &mut self,
+        handle: &TextureHandle,
+        region: TextureRegion,
+        pixels: &[RGBAPixel],
+    ) -> Result<(), RuntimeError> {
+        let _scope = alloc_telemetry::scope(Subsystem::Textures);
+        self.texture_store.update_texture(
+            &mut self.allocator,
+            &self.logical_device,
+            handle,
+            region,
+            pixels,
+            graphics_transfer(
+                &self.queue_families,
+                &mut self.graphics_commands,
+                &mut self.staging_ring,
+            ),
+        )?;
+        if let Some(kept) = &mut self.kept {
+            kept.update_texture(handle.id(), region, pixels);
+        }
+        Ok(())
+    }
+
+    /// Starts an atlas over a registered 2D texture, to define sprites on. The atlas only holds
+    /// the texture and the sprites' uv rects, nothing is uploaded for it.
+    pub fn create_atlas(&self, texture: &TextureHandle) -> Result<Atlas, RuntimeError> {
+        match self.texture_store.get(texture) {
+            Some(t) if !t.is_cube() => Ok(Atlas::new(texture.clone(), t.width, t.height)),
+            _ => Err(RuntimeError::InvalidHandle),
+        }
+    }
+
+    // Copies what the texture was made from, when `RendererConfig::device_lost_recovery` is on.
+    fn keep_texture(
+        &mut self,
+        result: &Result<TextureHandle, RuntimeError>,
+        source: impl FnOnce() -> TextureSource,
+    ) {
+        if let (Ok(handle), Some(kept)) = (result, &mut self.kept) {
+            kept.keep_texture(handle.id(), source());
+        }
+    }
+
+    fn texture_registered(
+        &mut self,
+        result: Result<TextureHandle, RuntimeError>,
+    ) -> Result<TextureHandle, RuntimeError> {
+        self.events.send(match &result {
+            Ok(handle) => EngineEvent::AssetLoaded {
+                kind: AssetKind::Texture,
+                id: handle.id(),
+            },
+            Err(e) => {
+                toasts::raise(
+                    ToastLevel::Error,
+                    &format!("Texture failed to load: {:?}", e),
+                );
+                EngineEvent::AssetFailed {
+                    kind: AssetKind::Texture,
+                    error: format!("{:?}", e),
+                }
+            }
+        });
+        result
+    }
+
+    /// Uploads a mesh for drawing. `vertex_data` has to match the vertex layout of the pipeline
+    /// that draws it, `ShaderVertexData` for the built-in one. Its bounds are worked out from the
+    /// vertex positions for culling.
+    pub fn register_mesh<V: VertexPosition>(
+        &mut self,
+        vertex_data: &[V],
+        index_data: &[u32],
+    ) -> Result<MeshHandle, RuntimeError> {
+        let bounds = MeshBounds::from_points(vertex_data.iter().map(V::position));
+        self.upload_mesh(vertex_data, index_data, bounds)
+    }
+
+    /// The box and sphere around the mesh in its own space, None once it's been collected.
+    pub fn mesh_bounds(&self, handle: &MeshHandle) -> Option<MeshBounds> {
+        self.mesh_store.get(handle).map(|mesh| *mesh.bounds())
+    }
+
+    // `register_mesh` with the bounds already known, e.g. for vertex bytes kept for recovery.
+    fn upload_mesh<V>(
+        &mut self,
+        vertex_data: &[V],
+        index_data: &[u32],
+        bounds: MeshBounds,
+    ) -> Result<MeshHandle, RuntimeError> {
+        let _scope = alloc_telemetry::scope(Subsystem::Meshes);
+        let result = self
+            .mesh_store
+            .register_mesh(
+                &mut self.allocator,
+                &self.logical_device,
+                vertex_data,
+                index_data,
+                bounds,
+                upload_transfer(
+                    &self.queue_families,
+                    &mut self.transfer_commands,
+                    &mut self.staging_ring,
+                ),
+            )
+            .map_err(RuntimeError::from);
+        if let (Ok(handle), Some(kept)) = (&result, &mut self.kept) {
+            kept.keep_mesh(handle.id(), vertex_data, index_data, bounds);
+        }
+        self.events.send(match &result {
+            Ok(handle) => EngineEvent::AssetLoaded {
+                kind: AssetKind::Mesh,
+                id: handle.id(),
+            },
+            Err(e) => {
+                toasts::raise(ToastLevel::Error, &format!("Mesh failed to load: {:?}", e));
+                EngineEvent::AssetFailed {
+                    kind: AssetKind::Mesh,
+                    error: format!("{:?}", e),
+                }
+            }
+        });
+        result
+    }
+
+    /// Frees the texture now rather than when its last handle is dropped. Every clone of `handle`
+    /// stops working, instances still pointing at its index draw whatever is registered there
+    /// next.
+    pub fn unregister_texture(&mut self, handle: &TextureHandle) -> Result<(), RuntimeError> {
+        if !self.texture_store.unregister_texture(
+            &mut self.allocator,
+            &self.logical_device,
+            handle,
+            self.frame_count,
+        )? {
+            return Err(RuntimeError::InvalidHandle);
+        }
+        if let Some(kept) = &mut self.kept {
+            kept.forget(&handle.id());
+        }
+        self.events
+            .send(EngineEvent::TextureEvicted { id: handle.id() });
+        Ok(())
+    }
+
+    /// Textures are uploaded on the transfer queue while frames carry on drawing. Until the
+    /// upload has finished, usually a frame or two later, instances using the texture show the
+    /// first texture that's ready instead.
+    pub fn is_texture_ready(&self, handle: &TextureHandle) -> bool {
+        self.texture_store.is_ready(handle)
+    }
+
+    /// Blocks until the texture's upload has finished, e.g. before a loading screen goes away.
+    pub fn wait_for_texture(&mut self, handle: &TextureHandle) -> Result<(), RuntimeError> {
+        if self.texture_store.get(handle).is_none() {
+            return Err(RuntimeError::InvalidHandle);
+        }
+        Ok(self.texture_store.wait_for_upload(
+            &mut self.allocator,
+            &self.logical_device,
+            handle,
+            &mut self.graphics_commands,
+        )?)
+    }
+
+    /// Reads and decodes the image on a worker thread, then registers it like `register_texture`
+    /// in a later `swap_framebuffers`. The handle is ready once the upload has finished too.
+    /// `flip_vertically` puts the first row of the file at the bottom. `.ktx2` and `.dds` files go
+    /// through `register_compressed_texture` and are never flipped. With
+    /// `RendererConfig::hot_reload` the file is loaded again into the same handle when it changes.
+    pub fn load_texture(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+        flip_vertically: bool,
+    ) -> AssetHandle<TextureHandle> {
+        self.asset_graph.watch(path.as_ref());
+        self.assets
+            .load_texture(path.as_ref().to_path_buf(), flip_vertically)
+    }
+
+    /// Same as `load_texture` for a mesh file, read through `jr_mesh::load`.
+    pub fn load_mesh(&mut self, path: impl AsRef<std::path::Path>) -> AssetHandle<MeshHandle> {
+        self.asset_graph.watch(path.as_ref());
+        self.assets.load_mesh(path.as_ref().to_path_buf())
+    }
+
+    /// Records that `dependent` is built from `dependency`, e.g. a material file from its
+    /// textures, a mesh from its material or a scene from its meshes, and watches both. With
+    /// `RendererConfig::hot_reload` a change to a file sends `EngineEvent::AssetChanged` for it
+    /// and everything built from it, and nothing else. Refused with the files around the loop if
+    /// `dependency` is already built from `dependent`.
+    pub fn add_asset_dependency(
+        &mut self,
+        dependent: impl AsRef<std::path::Path>,
+        dependency: impl AsRef<std::path::Path>,
+    ) -> Result<(), DependencyCycle> {
+        let result = self
+            .asset_graph
+            .depend(dependent.as_ref(), dependency.as_ref());
+        if let Err(e) = &result {
+            warn!("Asset dependency cycle: {:?}", e.cycle);
+        }
+        result
+    }
+
+    /// Watches a file the application loads itself and nothing is built from, e.g. a scene, see
+    /// `add_asset_dependency`.
+    pub fn watch_asset(&mut self, path: impl AsRef<std::path::Path>) {
+        self.asset_graph.watch(path.as_ref());
+    }
+
+    // Reloads changed files from `load_texture` and `load_mesh`, and tells the application about
+    // every changed file and the ones built from them, dependencies first.
+    fn reload_changed_assets(&mut self) {
+        if !self.config.hot_reload {
+            return;
+        }
+        for path in self.asset_graph.changed() {
+            self.assets.reload(&path);
+            self.events.send(EngineEvent::AssetChanged { path });
+        }
+    }
+
+    /// For registering textures and meshes from other threads, see `Registrar`.
+    pub fn registrar(&mut self) -> Registrar {
+        self.assets.registrar()
+    }
+
+    // Registers what the asset workers finished decoding, or other threads handed to a
+    // `Registrar`, oldest first until `config.uploads` is spent. The rest waits for later frames.
+    fn update_assets(&mut self) {
+        let start = std::time::Instant::now();
+        let mut stats = UploadStats::default();
+        while let Some(finished) = self.assets.next_upload(&self.config.uploads, &stats) {
+            stats.uploads += 1;
+            stats.bytes += finished.bytes();
+            match finished {
+                Finished::Texture(slot, image) => {
+                    let result = match image {
+                        Ok(LoadedImage::Rgba(image)) => self
+                            .register_texture(&image, SamplerDesc::default())
+                            .map_err(|e| format!("{:?}", e)),
+                        Ok(LoadedImage::Compressed(image)) => self
+                            .register_compressed_texture(&image, SamplerDesc::default())
+                            .map_err(|e| format!("{:?}", e)),
+                        Err(error) => Err(self.asset_unreadable(AssetKind::Texture, error)),
+                    };
+                    self.assets.texture_registered(slot, result);
+                }
+                Finished::Mesh(slot, mesh) => {
+                    let result = match mesh {
+                        Ok(mesh) => self
+                            .register_mesh(&mesh.vertices, &mesh.indices)
+                            .map_err(|e| format!("{:?}", e)),
+                        Err(error) => Err(self.asset_unreadable(AssetKind::Mesh, error)),
+                    };
+                    AssetLoader::mesh_registered(slot, result);
+                }
+            }
+            stats.time = start.elapsed();
+        }
+        stats.deferred = self.assets.queued() as u32;
+        self.upload_stats = stats;
+        let texture_store = &self.texture_store;
+        self.assets
+            .check_uploads(|handle| texture_store.is_ready(handle));
+    }
+
+    // Reports a file the asset workers couldn't read or decode, the way a failed registration is.
+    fn asset_unreadable(&mut self, kind: AssetKind, error: String) -> String {
+        toasts::raise(
+            ToastLevel::Error,
+            &format!("{:?} failed to load: {}", kind, error),
+        );
+        self.events.send(EngineEvent::AssetFailed {
+            kind,
+            error: error.clone(),
+        });
+        error
+    }
+
+    /// How many textures can be registered at once, set by the device's limits less the placeholder
+    /// freed slots show. Registering past it fails with `RuntimeError::TooManyTextures` until a
+    /// texture is freed.
+    pub fn max_textures(&self) -> u32 {
+        self.texture_store.capacity().saturating_sub(1)
+    }
+
+    /// Every event sent after this call is queued on the returned receiver. Drain it regularly,
+    /// events pile up until it is read or dropped.
+    pub fn subscribe(&mut self) -> std::sync::mpsc::Receiver<EngineEvent> {
+        self.events.subscribe()
+    }
+
+    /// A copy of a registered texture shrunk to fit in `max_size` x `max_size`, read back to the
+    /// CPU. Waits for the GPU, so keep it out of the frame loop.
+    pub fn texture_thumbnail(
+        &mut self,
+        handle: &TextureHandle,
+        max_size: u32,
+    ) -> Result<RGBAImage, RuntimeError> {
+        self.wait_for_texture(handle)?;
+        let texture = self
+            .texture_store
+            .get(handle)
+            .ok_or(RuntimeError::InvalidHandle)?;
+        if self.thumbnailer.is_none() {
+            self.thumbnailer = Some(Thumbnailer::new(&self.logical_device)?);
+        }
+        let thumbnailer = self.thumbnailer.as_ref().unwrap();
+        Ok(thumbnailer.thumbnail(
+            &mut self.allocator,
+            &self.logical_device,
+            texture,
+            max_size,
+            self.queues.graphics,
+            self.command_buffer_pools.graphics,
+        )?)
+    }
+
+    /// Builds a pipeline for meshes whose vertices are laid out differently from
+    /// `ShaderVertexData`. The vertex shader is checked against the layout (and the per-instance
+    /// attributes at locations 0-4 and 8-14) before anything is created.
+    pub fn register_vertex_format(
+        &mut self,
+        format: VertexFormat,
+    ) -> Result<VertexFormatHandle, RuntimeError> {
+        let pipeline = self.build_pipeline(
+            &format.layout,
+            &format.vertex_shader,
+            &format.fragment_shader,
+            DebugView::Off,
+            RenderState::default(),
+        )?;
+        let handle = VertexFormatHandle {
+            id: uuid::Uuid::new_v4(),
+        };
+        self.custom_pipelines.insert(handle, pipeline);
+        Ok(handle)
+    }
+
+    /// `register_vertex_format` with shaders read from disk, see `load_shader`. The pipeline is
+    /// rebuilt whenever either file changes.
+    pub fn load_vertex_format(
+        &mut self,
+        layout: VertexLayout,
+        vertex_shader: impl AsRef<std::path::Path>,
+        fragment_shader: impl AsRef<std::path::Path>,
+    ) -> Result<VertexFormatHandle, RuntimeError> {
+        let (vertex_code, fragment_code) =
+            load_shader_pair(vertex_shader.as_ref(), fragment_shader.as_ref())?;
+        let handle = self.register_vertex_format(VertexFormat {
+            layout: layout.clone(),
+            vertex_shader: vertex_code,
+            fragment_shader: fragment_code,
+        })?;
+        self.shader_watcher.watch(
+            ShaderTarget::VertexFormat(handle),
+            layout,
+            vertex_shader.as_ref(),
+            fragment_shader.as_ref(),
+        );
+        Ok(handle)
+    }
+
+    /// Replaces the built-in scene shaders with ones read from disk and rebuilds the scene
+    /// pipeline whenever they change, so shading can be iterated on without rebuilding the engine.
+    /// Start from copies of vertex.glsl and fragment.glsl. Waits for the GPU to go idle.
+    pub fn load_scene_shaders(
+        &mut self,
+        vertex_shader: impl AsRef<std::path::Path>,
+        fragment_shader: impl AsRef<std::path::Path>,
+    ) -> Result<(), RuntimeError> {
+        let watched = self.shader_watcher.watch(
+            ShaderTarget::Scene,
+            VertexLayout::shader_vertex_data(),
+            vertex_shader.as_ref(),
+            fragment_shader.as_ref(),
+        );
+        self.rebuild_watched(&watched)
+    }
+
+    /// A scene pipeline built from `desc` for entities to draw with, see `Entity::pipeline`.
+    /// Registering an equal desc again returns the same handle.
+    pub fn register_pipeline(
+        &mut self,
+        desc: MaterialDesc,
+    ) -> Result<PipelineHandle, RuntimeError> {
+        if let Some(handle) = self.pipeline_store.find(&desc) {
+            return Ok(handle);
+        }
+        if desc.state.wireframe && !self.wireframe_supported {
+            return Err(RuntimeError::UnsupportedFeature("fillModeNonSolid"));
+        }
+        let pipeline = self.build_material_pipeline(&desc)?;
+        let depth = match desc.state.depth_variant() {
+            Some(state) => match Pipeline::init_shadow(
+                &self.logical_device,
+                &self.shadow_map.render_pass(),
+                self.depth_range,
+                self.texture_store.capacity(),
+                state,
+            ) {
+                Ok(depth) => Some(depth),
+                Err(e) => {
+                    pipeline.cleanup(&self.logical_device);
+                    return Err(e.into());
+                }
+            },
+            None => None,
+        };
+        Ok(self.pipeline_store.insert(desc, pipeline, depth))
+    }
+
+    /// Entities still pointing at the pipeline go back to the scene pipeline. Waits for the GPU to
+    /// go idle.
+    pub fn unregister_pipeline(&mut self, handle: &PipelineHandle) -> Result<(), RuntimeError> {
+        if self.pipeline_store.get(handle).is_none() {
+            return Err(RuntimeError::InvalidHandle);
+        }
+        unsafe { self.logical_device.device_wait_idle() }?;
+        if let Some((pipeline, depth)) = self.pipeline_store.remove(handle) {
+            for pipeline in std::iter::once(pipeline).chain(depth) {
+                pipeline.cleanup(&self.logical_device);
+            }
+        }
+        Ok(())
+    }
+
+    fn build_material_pipeline(&self, desc: &MaterialDesc) -> Result<Pipeline, RuntimeError> {
+        let (scene_vertex, scene_fragment) = match &self.scene_shaders {
+            Some((vertex_code, fragment_code)) => {
+                (vertex_code.as_slice(), fragment_code.as_slice())
+            }
+            None => (pipeline::VERTEX_SHADER, pipeline::FRAGMENT_SHADER),
+        };
+        self.build_pipeline(
+            &VertexLayout::shader_vertex_data(),
+            desc.vertex_shader.as_deref().unwrap_or(scene_vertex),
+            desc.fragment_shader.as_deref().unwrap_or(scene_fragment),
+            DebugView::Off,
+            desc.state,
+        )
+    }
+
+    // Rebuilds the registered pipelines that use the scene shaders after those change.
+    fn rebuild_scene_pipelines(&mut self) -> Result<(), RuntimeError> {
+        let stale: Vec<(PipelineHandle, MaterialDesc)> = self
+            .pipeline_store
+            .descs()
+            .filter(|(_, desc)| desc.uses_scene_shaders())
+            .map(|(handle, desc)| (handle, desc.clone()))
+            .collect();
+        for (handle, desc) in stale {
+            let pipeline = self.build_material_pipeline(&desc)?;
+            if let Some(retired) = self.pipeline_store.replace(handle, pipeline) {
+                retired.cleanup(&self.logical_device);
+            }
+        }
+        Ok(())
+    }
+
+    // Validates the shaders against `layout` and builds a scene pipeline from them, raising a
+    // toast when they are rejected.
+    fn build_pipeline(
+        &self,
+        layout: &VertexLayout,
+        vertex_shader: &[u32],
+        fragment_shader: &[u32],
+        debug_view: DebugView,
+        state: RenderState,
+    ) -> Result<Pipeline, RuntimeError> {
+        Pipeline::validate_vertex_input(layout, vertex_shader)
+            .map_err(RuntimeError::from)
+            .and_then(|_| {
+                Ok(Pipeline::init_with_shaders(
+                    &self.logical_device,
+                    &self.renderpass,
+                    self.depth_range,
+                    self.texture_store.capacity(),
+                    PipelineShaders {
+                        vertex_shader,
+                        fragment_shader,
+                        depth_only: false,
+                        mesh_layout: layout,
+                        debug_view,
+                        state,
+                    },
+                )?)
+            })
+            .map_err(|e| {
+                toasts::raise(
+                    ToastLevel::Error,
+                    &format!("Shaders were rejected: {:?}", e),
+                );
+                e
+            })
+    }
+
+    // Reloads a watched pipeline's shaders and swaps in the rebuilt pipeline. On failure the old
+    // pipeline is kept, so a typo doesn't take the scene down with it.
+    fn rebuild_watched(&mut self, watched: &WatchedShaders) -> Result<(), RuntimeError> {
+        let (vertex_code, fragment_code) = load_shader_pair(&watched.vertex, &watched.fragment)
+            .map_err(|e| {
+                toasts::raise(
+                    ToastLevel::Error,
+                    &format!("Shaders failed to load: {:?}", e),
+                );
+                e
+            })?;
+        let pipeline = self.build_pipeline(
+            &watched.layout,
+            &vertex_code,
+            &fragment_code,
+            DebugView::Off,
+            RenderState::default(),
+        )?;
+        unsafe { self.logical_device.device_wait_idle() }?;
+        let retired = match watched.target {
+            ShaderTarget::Scene => {
+                self.scene_shaders = Some((vertex_code, fragment_code));
+                Some(std::mem::replace(&mut self.graphics_pipeline, pipeline))
+            }
+            ShaderTarget::VertexFormat(handle) => self.custom_pipelines.insert(handle, pipeline),
+        };
+        if let Some(retired) = retired {
+            retired.cleanup(&self.logical_device);
+        }
+        info!(
+            "Loaded shaders {} and {}",
+            watched.vertex.display(),
+            watched.fragment.display()
+        );
+        match watched.target {
+            ShaderTarget::Scene => {
+                self.rebuild_debug_pipeline()?;
+                self.rebuild_scene_pipelines()
+            }
+            ShaderTarget::VertexFormat(_) => Ok(()),
+        }
+    }
+
+    // Rebuilds the pipelines whose shader files changed since the last check.
+    fn reload_changed_shaders(&mut self) {
+        for watched in self.shader_watcher.changed() {
+            if let Err(e) = self.rebuild_watched(&watched) {
+                warn!("Keeping the previous shaders. {:?}", e);
+            }
+        }
+    }
+
+    pub fn register_material(&mut self, material: Material) -> MaterialHandle {
+        self.material_store.register_material(material)
+    }
+
+    /// Uploads everything in a scene loaded with `jr_gltf::load`. Each glTF material becomes a
+    /// renderer material and each image a texture, images no material uses are skipped.
+    pub fn register_gltf(&mut self, scene: &GltfScene) -> Result<Model, RuntimeError> {
+        let mut textures: Vec<Option<TextureHandle>> = vec![None; scene.images.len()];
+        let mut white: Option<TextureHandle> = None;
+        let mut materials = Vec::with_capacity(scene.materials.len());
+        for gltf_material in &scene.materials {
+            let texture = match gltf_material.base_colour_image {
+                Some(image) => {
+                    if textures[image].is_none() {
+                        textures[image] = Some(
+                            self.register_texture(&scene.images[image], SamplerDesc::default())?,
+                        );
+                    }
+                    textures[image].clone()
+                }
+                None => None,
+            };
+            let mut material = Material::default();
+            material.set_param(MaterialParam::BaseColour(gltf_material.base_colour));
+            materials.push((self.register_material(material), texture));
+        }
+        // glTF's default material is plain white.
+        let default_material = self.register_material(Material::default());
+
+        let mut meshes = Vec::with_capacity(scene.meshes.len());
+        for gltf_mesh in &scene.meshes {
+            let mut primitives = Vec::with_capacity(gltf_mesh.primitives.len());
+            for primitive in &gltf_mesh.primitives {
+                let mesh = self.register_mesh(&primitive.mesh.vertices, &primitive.mesh.indices)?;
+                let (material, texture) = match primitive.material {
+                    Some(material) => materials[material].clone(),
+                    None => (default_material, None),
+                };
+                let texture = match texture {
+                    Some(texture) => texture,
+                    None => match &white {
+                        Some(white) => white.clone(),
+                        None => {
+                            let mut image = RGBAImage::new(1, 1);
+                            image.set_pixel(
+                                0,
+                                0,
+                                RGBAPixel {
+                                    r: 255,
+                                    g: 255,
+                                    b: 255,
+                                    a: 255,
+                                },
+                            );
+                            white
+                                .insert(self.register_texture(&image, SamplerDesc::default())?)
+                                .clone()
+                        }
+                    },
+                };
+                primitives.push(ModelPrimitive {
+                    mesh,
+                    texture,
+                    material,
+                });
+            }
+            meshes.push(primitives);
+        }
+
+        Ok(Model {
+            nodes: scene
+                .nodes
+                .iter()
+                .map(|node| ModelNode {
+                    name: node.name.clone(),
+                    transform: node.transform,
+                    primitives: node
+                        .mesh
+                        .map(|mesh| meshes[mesh].clone())
+                        .unwrap_or_default(),
+                    children: node.children.clone(),
+                })
+                .collect(),
+            roots: scene.roots.clone(),
+        })
+    }
+
+    /// The material used by geometry that has not been given one explicitly.
+    pub fn default_material(&self) -> MaterialHandle {
+        self.default_material
+    }
+
+    pub fn material(&self, handle: &MaterialHandle) -> Option<&Material> {
+        self.material_store.get(handle)
+    }
+
+    pub fn material_mut(&mut self, handle: &MaterialHandle) -> Option<&mut Material> {
+        self.material_store.get_mut(handle)
+    }
+
+    /// Replaces the point and spot lights drawn from the next frame on, call again whenever one
+    /// moves. Only the first `MAX_LIGHTS` (256) are kept.
+    pub fn set_lights(&mut self, lights: &[Light]) {
+        self.lights.set(lights);
+    }
+
+    pub fn lights(&self) -> &[Light] {
+        self.lights.lights()
+    }
+
+    /// Draws `cubemap` behind the scene from the next frame on, in place of the sky's own
+    /// background. A `Sky` still lights the scene. None goes back to the sky or the clear colour.
+    pub fn set_skybox(&mut self, cubemap: Option<TextureHandle>) -> Result<(), RuntimeError> {
+        if let Some(handle) = &cubemap {
+            match self.texture_store.get(handle) {
+                Some(texture) if texture.is_cube() => {}
+                _ => return Err(RuntimeError::InvalidHandle),
+            }
+        }
+        self.skybox = cubemap;
+        Ok(())
+    }
+
+    pub fn skybox(&self) -> Option<&TextureHandle> {
+        self.skybox.as_ref()
+    }
+
+    /// Builds a post effect from a SPIR-V fragment shader, see `PostEffect::Custom` for what it's
+    /// given. It isn't run until it's in `set_post_effects`.
+    pub fn register_post_effect(
+        &mut self,
+        fragment_shader: &[u32],
+    ) -> Result<PostEffectHandle, RuntimeError> {
+        Ok(self
+            .post_process
+            .register(&self.logical_device, fragment_shader)?)
+    }
+
+    /// The effect is dropped from the chain wherever it appears. Waits for the GPU to go idle.
+    pub fn unregister_post_effect(
+        &mut self,
+        handle: &PostEffectHandle,
+    ) -> Result<(), RuntimeError> {
+        if !self.post_process.is_registered(handle) {
+            return Err(RuntimeError::InvalidHandle);
+        }
+        unsafe { self.logical_device.device_wait_idle() }?;
+        self.post_process.unregister(&self.logical_device, handle);
+        Ok(())
+    }
+
+    /// The effects run over the scene from the next frame on, in order, each sampling what the
+    /// one before it drew. They run in linear HDR before tonemapping, and not at all while a debug
+    /// view or test pattern is shown. An empty list turns post-processing off.
+    pub fn set_post_effects(&mut self, effects: &[PostEffect]) -> Result<(), RuntimeError> {
+        let unregistered = effects.iter().any(|effect| {
+            matches!(effect, PostEffect::Custom { effect, .. } if !self.post_process.is_registered(effect))
+        });
+        if unregistered {
+            return Err(RuntimeError::InvalidHandle);
+        }
+        self.post_process.set_effects(effects);
+        Ok(())
+    }
+
+    pub fn post_effects(&self) -> &[PostEffect] {
+        self.post_process.effects()
+    }
+
+    /// Seconds since the renderer was created, this is the clock animations run on.
+    pub fn time(&self) -> f32 {
+        self.start_time.elapsed().as_secs_f32()
+    }
+
+    /// Starts a tween now, `apply` is called with the current value once per frame until the
+    /// tween finishes. E.g. fading a material's sway in over two seconds:
+    /// ```ignore
+    /// v.animate(Tween::new(0.0, 0.3, 2.0), move |v, amplitude| {
+    ///     if let Some(material) = v.material_mut(&foliage) {
+    ///         let sway = WindSway { amplitude, frequency: 0.5 };
+    ///         material.set_param(MaterialParam::Wind(Some(sway)));
+    ///     }
+    /// });
+    /// ```
+    pub fn animate<T, F>(&mut self, tween: Tween<T>, apply: F) -> AnimationHandle
+    where
+        T: Animatable + 'static,
+        F: FnMut(&mut Vulkan, T) + 'static,
+    {
+        let now = self.time();
+        self.animator.add(tween, now, apply)
+    }
+
+    /// Stops an animation, leaving the property at whatever value it was last given.
+    pub fn stop_animation(&mut self, handle: &AnimationHandle) -> bool {
+        self.animator.remove(handle)
+    }
+
+    pub fn is_animating(&self, handle: &AnimationHandle) -> bool {
+        self.animator.contains(handle)
+    }
+
+    /// Replaces the scene with a test pattern for checking the surface format and display
+    /// calibration by eye, None goes back to rendering the scene.
+    pub fn show_test_pattern(&mut self, pattern: Option<TestPattern>) {
+        if let Some(pattern) = pattern {
+            info!(
+                "Showing test pattern {:?} on a {:?} {:?} surface",
+                pattern, self.surface_format.format, self.surface_format.color_space
+            );
+        }
+        self.test_pattern = pattern;
+    }
+
+    pub fn test_pattern(&self) -> Option<TestPattern> {
+        self.test_pattern
+    }
+
+    /// Sets the font `draw_text` uses. The font's texture is kept alive for as long as it's set.
+    pub fn set_font(&mut self, font: Font) -> Result<(), RuntimeError> {
+        let texture = self
+            .texture_store
+            .get(&font.texture)
+            .ok_or(RuntimeError::InvalidHandle)?;
+        let glyph_aspect =
+            (texture.width * font.rows) as f32 / (texture.height * font.columns) as f32;
+        match &mut self.text {
+            Some(text) => text.set_font(font, glyph_aspect),
+            None => {
+                self.text = Some(TextRenderer::new(
+                    &mut self.allocator,
+                    &self.logical_device,
+                    &self.output_pass,
+                    self.command_buffers.len(),
+                    upload_transfer(
+                        &self.queue_families,
+                        &mut self.transfer_commands,
+                        &mut self.staging_ring,
+                    ),
+                    font,
+                    glyph_aspect,
+                )?)
+            }
+        }
+        Ok(())
+    }
+
+    /// Queues text to be drawn over the next frame only, so call it every frame the text should
+    /// stay up. `position` is the top left corner in pixels and `size` the line height in pixels.
+    /// Does nothing until a font is set.
+    pub fn draw_text(&mut self, text: &str, position: [f32; 2], size: f32, colour: [f32; 4]) {
+        if let Some(renderer) = &mut self.text {
+            renderer.queue(text, position, size, colour);
+        }
+    }
+
+    /// Draws `entity` every frame until it's removed. Entities are rebuilt every frame, so they
+    /// suit objects that move a lot, see `add_instance` for ones that mostly don't.
+    pub fn add_entity(&mut self, entity: Entity) -> EntityId {
+        self.entities.add(entity)
+    }
+
+    pub fn entity(&self, id: &EntityId) -> Option<&Entity> {
+        self.entities.get(id)
+    }
+
+    pub fn entity_mut(&mut self, id: &EntityId) -> Option<&mut Entity> {
+        self.entities.get_mut(id)
+    }
+
+    pub fn remove_entity(&mut self, id: &EntityId) -> Option<Entity> {
+        self.entities.remove(id)
+    }
+
+    /// The nearest entity or scene node under pixel `x`, `y` of the window, e.g. the cursor
+    /// position, and where the ray from the camera hits it. Hits are against mesh bounding boxes,
+    /// not triangles, so a pick near the edge of a round mesh can land on its empty corners.
+    pub fn pick(&mut self, x: f32, y: f32) -> Option<PickHit> {
+        let extent = self.swapchain.extent;
+        let ray =
+            self.camera
+                .screen_point_to_ray(x, y, (extent.width as f32, extent.height as f32));
+        self.scene.update_world_transforms();
+        pick::pick(&ray, &self.entities, &self.scene, &self.mesh_store)
+    }
+
+    /// A unit cube centred on the origin, registered when the renderer starts.
+    pub fn cube_mesh(&self) -> MeshHandle {
+        self.cube.clone()
+    }
+
+    /// Adds an instance that's drawn every frame until it's removed, with the cube mesh for now.
+    /// It is only copied to the GPU again after it changes, which suits mostly static scenes far
+    /// better than rebuilding every instance each frame. `ambient` is filled in from the light
+    /// probes.
+    pub fn add_instance(&mut self, instance: InstanceData) -> InstanceSlot {
+        self.retained.add(instance)
+    }
+
+    pub fn instance(&self, slot: &InstanceSlot) -> Option<&InstanceData> {
+        self.retained.get(slot)
+    }
+
+    /// Marks the instance for upload, so only borrow it mutably to change it.
+    pub fn instance_mut(&mut self, slot: &InstanceSlot) -> Option<&mut InstanceData> {
+        self.retained.get_mut(slot)
+    }
+
+    pub fn set_instance_transform(
+        &mut self,
+        slot: &InstanceSlot,
+        model: na::Matrix4<f32>,
+    ) -> Result<(), RuntimeError> {
+        let instance = self
+            .retained
+            .get_mut(slot)
+            .ok_or(RuntimeError::InvalidHandle)?;
+        instance.model = model.into();
+        Ok(())
+    }
+
+    pub fn remove_instance(&mut self, slot: &InstanceSlot) -> Option<InstanceData> {
+        self.retained.remove(slot)
+    }
+
+    /// Retained instances only sample the light probes when they change, call this after
+    /// changing `light_probes` so they all pick it up.
+    pub fn refresh_instance_lighting(&mut self) {
+        self.retained.mark_all_dirty();
+    }
+
+    /// Shows `message` in the corner of the window for a few seconds, for problems worth noticing
+    /// while developing. Only with the `toasts` feature and once a font is set.
+    pub fn toast(&mut self, level: ToastLevel, message: &str) {
+        toasts::raise(level, message);
+    }
+
+    /// Draws egui's output over the next frame, call once per frame with what `Context::run` (or
+    /// `end_frame` and `tessellate`) returned. Nothing is drawn on frames it isn't called for.
+    /// Input is left to the application, e.g. with egui-winit. Paint callbacks are skipped, draw
+    /// those from a `RenderMiddleware` instead.
+    #[cfg(feature = "ui")]
+    pub fn draw_ui(
+        &mut self,
+        primitives: &[egui::ClippedPrimitive],
+        textures_delta: &egui::TexturesDelta,
+        pixels_per_point: f32,
+    ) -> Result<(), RuntimeError> {
+        if self.ui.is_none() {
+            self.ui = Some(UiRenderer::new(
+                &self.logical_device,
+                &self.output_pass,
+                self.command_buffers.len(),
+                self.texture_store.capacity(),
+            )?);
+        }
+        for (id, delta) in &textures_delta.set {
+            let id = match id {
+                egui::TextureId::Managed(id) => *id,
+                // Already in the texture store, see `ui_texture_id`.
+                egui::TextureId::User(_) => continue,
+            };
+            let (width, height) = (delta.image.width() as u32, delta.image.height() as u32);
+            let data: Vec<RGBAPixel> = match &delta.image {
+                egui::ImageData::Color(image) => image.pixels.clone(),
+                egui::ImageData::Font(image) => image.srgba_pixels(None).collect(),
+            }
+            .into_iter()
+            .map(|pixel| RGBAPixel {
+                r: pixel.r(),
+                g: pixel.g(),
+                b: pixel.b(),
+                a: pixel.a(),
+            })
+            .collect();
+            let existing = self.ui.as_ref().unwrap().texture(id).cloned();
+            match (delta.pos, existing) {
+                (Some([x, y]), Some(texture)) => {
+                    let region = TextureRegion {
+                        x: x as u32,
+                        y: y as u32,
+                        width,
+                        height,
+                    };
+                    self.update_texture(&texture, region, &data)?;
+                }
+                _ => {
+                    let texture = self.register_texture(
+                        &RGBAImage {
+                            width,
+                            height,
+                            data,
+                        },
+                        ui::sampler(&delta.options),
+                    )?;
+                    self.ui.as_mut().unwrap().set_texture(id, texture);
+                }
+            }
+        }
+        let ui = self.ui.as_mut().unwrap();
+        ui.free_textures(&textures_delta.free);
+        ui.queue(primitives, pixels_per_point);
+        Ok(())
+    }
+
+    /// The id egui draws a registered texture with, e.g. in `egui::Image`. None once the texture
+    /// has been collected or unregistered.
+    #[cfg(feature = "ui")]
+    pub fn ui_texture_id(&self, texture: &TextureHandle) -> Option<egui::TextureId> {
+        self.texture_store
+            .index(texture)
+            .map(|index| egui::TextureId::User(index as u64))
+    }
+
+    /// Switches the scene to one of the debug visualisations, `DebugView::Off` goes back to normal
+    /// shading. Waits for the GPU to go idle, so it's not for calling every frame. Returns
+    /// `RuntimeError::UnsupportedFeature` for a view the device can't draw.
+    pub fn set_debug_view(&mut self, debug_view: DebugView) -> Result<(), RuntimeError> {
+        if debug_view == self.debug_view {
+            return Ok(());
+        }
+        if !self.supports_debug_view(debug_view) {
+            return Err(RuntimeError::UnsupportedFeature("fillModeNonSolid"));
+        }
+        info!("Debug view {:?}", debug_view);
+        self.debug_view = debug_view;
+        self.rebuild_debug_pipeline()
+    }
+
+    pub fn debug_view(&self) -> DebugView {
+        self.debug_view
+    }
+
+    /// False for `DebugView::Wireframe` on devices that can't draw lines for triangles.
+    pub fn supports_debug_view(&self, debug_view: DebugView) -> bool {
+        debug_view != DebugView::Wireframe || self.wireframe_supported
+    }
+
+    fn rebuild_debug_pipeline(&mut self) -> Result<(), RuntimeError> {
+        unsafe { self.logical_device.device_wait_idle() }?;
+        if let Some(debug_pipeline) = self.debug_pipeline.take() {
+            debug_pipeline.cleanup(&self.logical_device);
+        }
+        if self.debug_view != DebugView::Off {
+            self.debug_pipeline = Some(match &self.scene_shaders {
+                Some((vertex_code, fragment_code)) => self.build_pipeline(
+                    &VertexLayout::shader_vertex_data(),
+                    vertex_code,
+                    fragment_code,
+                    self.debug_view,
+                    self.debug_view.render_state(),
+                )?,
+                None => Pipeline::init_debug_view(
+                    &self.logical_device,
+                    &self.renderpass,
+                    self.depth_range,
+                    self.texture_store.capacity(),
+                    self.debug_view,
+                )?,
+            });
+        }
+        Ok(())
+    }
+
+    /// Hooks `middleware` into every frame from now on, see `RenderMiddleware`.
+    pub fn add_middleware<M: RenderMiddleware + 'static>(
+        &mut self,
+        mut middleware: M,
+    ) -> Result<(), RuntimeError> {
+        self.prepare_middleware(&mut middleware)?;
+        self.middleware.push(Box::new(middleware));
+        Ok(())
+    }
+
+    fn prepare_middleware(
+        &mut self,
+        middleware: &mut dyn RenderMiddleware,
+    ) -> Result<(), vk::Result> {
+        middleware.prepare(&mut SetupContext {
+            instance: &self.instance,
+            physical_device: self.physical_device,
+            device: &self.logical_device,
+            allocator: &mut self.allocator,
+            render_pass: self.renderpass,
+            extent: self.swapchain.extent,
+            surface_format: self.surface_format.format,
+            scene_format: SCENE_FORMAT,
+            depth_format: self.depth_format,
+            frames_in_flight: self.command_buffers.len(),
+        })
+    }
+
+    /// The depth convention the renderer was created with, see `DepthRange`.
+    pub fn depth_range(&self) -> DepthRange {
+        self.depth_range
+    }
+
+    /// Runs `f` with this frame's scratch arena, anything allocated in it stays valid until the
+    /// next frame starts. E.g. collecting the visible entities for this frame without a heap
+    /// allocation:
+    /// ```ignore
+    /// v.with_frame_arena(|v, arena| {
+    ///     let visible = arena.collect(entities.iter().filter(|e| e.visible(&v.camera)));
+    ///     ...
+    /// });
+    /// ```
+    pub fn with_frame_arena<R>(&mut self, f: impl FnOnce(&mut Vulkan, &FrameArena) -> R) -> R {
+        // Taken out for the duration so `f` can still use the renderer mutably.
+        let arena = std::mem::take(&mut self.frame_arena);
+        let result = f(self, &arena);
+        self.frame_arena = arena;
+        result
+    }
+
+    /// Feed with `WindowEvent::Focused`.
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+    }
+
+    /// Feed with `WindowEvent::Occluded`.
+    pub fn set_occluded(&mut self, occluded: bool) {
+        self.occluded = occluded;
+    }
+
+    /// Heap allocations made between the start of the previous frame and the start of the
+    /// current one. All zero unless built with the `alloc-telemetry` feature.
+    pub fn allocation_stats(&self) -> &AllocationStats {
+        &self.allocation_stats
+    }
+
+    /// Per-pass GPU times of the most recent frame the GPU has finished, usually two frames
+    /// behind, with pipeline statistics if `RendererConfig::pipeline_statistics` is set. None on
+    /// devices that can't write timestamps from graphics queues.
+    pub fn frame_stats(&self) -> Option<&FrameStats> {
+        self.frame_stats.as_ref()
+    }
+
+    /// Timestamps of the last 64 presented frames, oldest first. Photon times are measured on
+    /// devices with VK_GOOGLE_display_timing (Linux and Android only) and estimated elsewhere.
+    pub fn present_timings(&self) -> impl Iterator<Item = &PresentTiming> {
+        self.present_timer.timings()
+    }
+
+    /// How long after presenting the most recent frame is expected to reach the display, add this
+    /// to audio or video timestamps to line them up with what is on screen.
+    pub fn display_latency(&self) -> std::time::Duration {
+        self.present_timer
+            .timings()
+            .filter(|timing| timing.measured)
+            .last()
+            .or(self.present_timer.timings().last())
+            .map(|timing| timing.photon - timing.presented)
+            .unwrap_or_default()
+    }
+
+    /// When the next frame should be rendered according to the focus and occlusion settings in
+    /// `config`, None while rendering is paused. Times in the past mean a frame is due now.
+    pub fn next_frame_at(&self) -> Option<std::time::Instant> {
+        let mode = if self.occluded {
+            self.config.occluded
+        } else if !self.focused {
+            self.config.unfocused
+        } else {
+            BackgroundMode::Full
+        };
+        let interval = mode.frame_interval()?.max(
+            self.config
+                .frame_limit
+                .frame_interval(self.refresh_rate, self.present_mode),
+        );
+        Some(match self.last_frame {
+            Some(last_frame) => last_frame + interval,
+            None => std::time::Instant::now(),
+        })
+    }
+
+    /// Re-reads the monitor the window is on and picks the present mode that suits
+    /// `config.frame_limit`. Call it when the window moves to another monitor or the frame limit
+    /// changes, the swapchain is rebuilt if the present mode has to change.
+    pub fn refresh_display(&mut self, window: &Window) -> Result<(), RuntimeError> {
+        let refresh_rate =
+            display::current_monitor(window).and_then(|monitor| monitor.refresh_rate);
+        let present_mode = self.config.present_mode(
+            refresh_rate,
+            &self.surface.get_present_modes(self.physical_device)?,
+        );
+        if refresh_rate != self.refresh_rate || present_mode != self.present_mode {
+            self.events.send(EngineEvent::CapabilitiesChanged {
+                refresh_rate,
+                present_mode,
+            });
+        }
+        self.refresh_rate = refresh_rate;
+        if present_mode != self.present_mode {
+            info!(
+                "Switching to {:?}, refresh rate {:?} Hz",
+                present_mode, self.refresh_rate
+            );
+            self.switch_present_mode(present_mode)?;
+        }
+        Ok(())
+    }
+
+    /// Switches present mode without a restart, e.g. for a vsync setting: FIFO for vsync on,
+    /// IMMEDIATE or MAILBOX for off. Modes the surface doesn't support are ignored in favour of the
+    /// one `config.frame_limit` picks. Stays in effect until `config.present_mode` is changed.
+    pub fn set_present_mode(
+        &mut self,
+        present_mode: vk::PresentModeKHR,
+    ) -> Result<(), RuntimeError> {
+        self.config.present_mode = Some(present_mode);
+        let present_mode = self.config.present_mode(
+            self.refresh_rate,
+            &self.surface.get_present_modes(self.physical_device)?,
+        );
+        if present_mode != self.present_mode {
+            info!("Switching to {:?}", present_mode);
+            self.switch_present_mode(present_mode)?;
+        }
+        Ok(())
+    }
+
+    pub fn present_mode(&self) -> vk::PresentModeKHR {
+        self.present_mode
+    }
+
+    // Only the swapchain is replaced, everything else carries on and the old swapchain is
+    // destroyed once the frames using it are done. Falls back to recreating everything when the
+    // surface changed in a way the new swapchain can't absorb.
+    fn switch_present_mode(
+        &mut self,
+        present_mode: vk::PresentModeKHR,
+    ) -> Result<(), RuntimeError> {
+        self.present_mode = present_mode;
+        match self.swapchain.change_present_mode(
+            self.physical_device,
+            &self.logical_device,
+            &self.surface,
+            &self.queue_families,
+            self.output_pass,
+            present_mode,
+        ) {
+            Ok(retired) => {
+                self.retired_swapchains.retire(self.frame_count, retired);
+                self.apply_hdr_metadata();
+                self.events.send(EngineEvent::SwapchainRecreated {
+                    width: self.swapchain.extent.width,
+                    height: self.swapchain.extent.height,
+                    image_count: self.swapchain.size(),
+                });
+                Ok(())
+            }
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => self.recreate_swapchain(),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Recreates the swapchain at the surface's current size, like `resize_surface` with the
+    // size the window reports. Left for later while the window is minimised.
+    fn recreate_swapchain(&mut self) -> Result<(), RuntimeError> {
+        let extent = self
+            .surface
+            .get_capabilities(self.physical_device)?
+            .current_extent;
+        // The surface takes its size from the swapchain, nothing about it changed.
+        let extent = if extent.width == u32::MAX {
+            self.swapchain.extent
+        } else {
+            extent
+        };
+        if extent.width == 0 || extent.height == 0 {
+            return Ok(());
+        }
+        self.resize_surface(extent.width, extent.height)
+    }
+
+    pub fn resize_surface(&mut self, w: u32, h: u32) -> Result<(), RuntimeError> {
+        // Todo: Resize the render surface using the new width and height rather than inferring it from the surface itself
+        self.halt_render = true;
+        unsafe {
+            self.logical_device.device_wait_idle()?;
+            for retired in self.retired_swapchains.drain() {
+                retired.cleanup(&self.logical_device);
+            }
+            self.swapchain
+                .cleanup(&self.logical_device, &mut self.allocator);
+            self.swapchain = Swapchain::init(
+                &self.instance,
+                self.physical_device,
+                &self.logical_device,
+                &mut self.allocator,
+                &self.surface,
+                &self.queue_families,
+                SwapchainDesc {
+                    surface_format: self.surface_format,
+                    depth_format: self.depth_format,
+                    present_mode: self.present_mode,
+                    frames_in_flight: self.command_buffers.len(),
+                },
+            )?;
+            self.swapchain.create_framebuffers(
+                &self.logical_device,
+                self.renderpass,
+                self.output_pass,
+            )?;
+            self.post_process.resize(
+                &self.logical_device,
+                &mut self.allocator,
+                self.swapchain.extent,
+                self.swapchain.scene_view(),
+            )?;
+            self.tonemap_pipeline.set_sources(
+                &self.logical_device,
+                self.post_process.targets(self.swapchain.scene_view()),
+            );
+            self.apply_hdr_metadata();
+            // The pipelines set their viewport and scissor while recording, so they carry on
+            // as they are.
+        }
+        let mut middleware = std::mem::take(&mut self.middleware);
+        let prepared = middleware
+            .iter_mut()
+            .try_for_each(|middleware| self.prepare_middleware(middleware.as_mut()));
+        self.middleware = middleware;
+        prepared?;
+
+        self.camera.aspect = (w as f32) / (h as f32);
+        self.camera.update_projectionmatrix();
+        self.halt_render = false;
+        self.events.send(EngineEvent::SwapchainRecreated {
+            width: self.swapchain.extent.width,
+            height: self.swapchain.extent.height,
+            image_count: self.swapchain.size(),
+        });
+
+        Ok(())
+    }
+
+    fn create_commandbuffers(
+        logical_device: &ash::Device,
+        pools: &Pools,
+        amount: usize,
+    ) -> Result<Vec<vk::CommandBuffer>, vk::Result> {
+        let commandbuf_allocate_info = vk::CommandBufferAllocateInfo::builder()
+            .command_pool(pools.graphics)
+            .command_buffer_count(amount as u32);
+        let command_buffers =
+            unsafe { logical_device.allocate_command_buffers(&commandbuf_allocate_info) }?;
+        for (slot, command_buffer) in command_buffers.iter().enumerate() {
+            debug::set_object_name(logical_device, *command_buffer, &format!("frame {}", slot));
+        }
+        Ok(command_buffers)
+    }
+
+    // The sun's view projection for its shadow map. One map for every view, fitted around the
+    // main camera.
+    fn shadow_view_projection(&self) -> Matrix4<f32> {
+        let sun_direction = match &self.sky {
+            Some(sky) => sky.sun_direction(),
+            None => self.lighting.direction.normalize(),
+        };
+        shadow::light_view_projection(
+            sun_direction,
+            self.camera.position,
+            &self.config.shadows.unwrap_or_default(),
+            self.depth_range,
+        )
+    }
+
+    // What the scene shaders see of `camera` this frame.
+    fn frame_uniform_data(&self, camera: &Camera) -> FrameUniformData {
+        let (sun_direction, sun_colour) = match &self.sky {
+            Some(sky) => (sky.sun_direction(), sky.sun_colour()),
+            None => (self.lighting.direction.normalize(), self.lighting.colour),
+        };
+        let ambient = self.lighting.ambient;
+        let shadow_view_projection = self.shadow_view_projection();
+        let shadow = match self.config.shadows {
+            Some(shadows) => [
+                1.0,
+                shadows.normal_bias,
+                1.0 / self.shadow_map.resolution() as f32,
+                0.0,
+            ],
+            None => [0.0; 4],
+        };
+        let projection = self.depth_range.projection_adjustment() * camera.projectionmatrix;
+        let position = camera.position;
+        FrameUniformData {
+            view: camera.viewmatrix.into(),
+            projection: projection.into(),
+            view_projection: (projection * camera.viewmatrix).into(),
+            camera_position: [position.x, position.y, position.z, 0.0],
+            time: [self.time(), 0.0, 0.0, 0.0],
+            wind: [
+                self.wind.direction.x,
+                self.wind.direction.y,
+                self.wind.strength,
+                0.0,
+            ],
+            sun_direction: [sun_direction.x, sun_direction.y, sun_direction.z, 0.0],
+            sun_colour: [sun_colour[0], sun_colour[1], sun_colour[2], 0.0],
+            ambient: [ambient[0], ambient[1], ambient[2], 0.0],
+            light_count: [self.lights.count(), 0, 0, 0],
+            shadow_view_projection: shadow_view_projection.into(),
+            shadow,
+        }
+    }
+
+    // Records the sky, entities and retained instances as `camera` sees them into the render
+    // pass being recorded. `view` is the camera's index in this frame's uniforms. A `baking`
+    // view draws only the entities baked for it, see `OffscreenTarget::bake`.
+    fn draw_scene(
+        &self,
+        commandbuffer: vk::CommandBuffer,
+        slot: usize,
+        view: u32,
+        baking: bool,
+        camera: &Camera,
+        counts: &mut DrawCounts,
+    ) {
+        unsafe {
+            let projection = self.depth_range.projection_adjustment() * camera.projectionmatrix;
+            match (&self.sky, self.debug_view) {
+                _ if baking => {}
+                (_, DebugView::Off) if self.skybox_pipeline.is_set(slot) => {
+                    self.skybox_pipeline.draw(
+                        &self.logical_device,
+                        commandbuffer,
+                        slot,
+                        &projection,
+                        &camera.viewmatrix,
+                    );
+                }
+                (Some(sky), DebugView::Off) => {
+                    self.sky_pipeline.draw(
+                        &self.logical_device,
+                        commandbuffer,
+                        sky,
+                        &projection,
+                        &camera.viewmatrix,
+                    );
+                }
+                _ => {}
+            }
+            let scene_pipeline = self
+                .debug_pipeline
+                .as_ref()
+                .unwrap_or(&self.graphics_pipeline);
+            let mut bound = None;
+            if let Some(instances) = self.entities.instances() {
+                self.logical_device.cmd_bind_vertex_buffers(
+                    commandbuffer,
+                    VertexBufferBindings::InstanceBuffer as u32,
+                    &[instances.buffer],
+                    &[instances.offset],
+                );
+                let batches = self.entities.batches().iter();
+                for batch in batches.filter(|batch| batch.view == baking.then_some(view)) {
+                    // Only the main camera's view is culled.
+                    let instance_count = match view {
+                        0 => batch.visible_count,
+                        _ => batch.instance_count,
+                    };
+                    if instance_count == 0 {
+                        continue;
+                    }
+                    // A debug view replaces every pipeline, and entities whose pipeline has been
+                    // unregistered fall back to the scene's.
+                    let pipeline = match (&self.debug_pipeline, &batch.pipeline) {
+                        (None, Some(handle)) => {
+                            self.pipeline_store.get(handle).unwrap_or(scene_pipeline)
+                        }
+                        _ => scene_pipeline,
+                    };
+                    if bound != Some(pipeline.pipeline) {
+                        pipeline.bind(
+                            &self.logical_device,
+                            commandbuffer,
+                            slot,
+                            self.frame_uniforms.dynamic_offset(view),
+                        );
+                        bound = Some(pipeline.pipeline);
+                    }
+                    if let Some(mesh) = self.mesh_store.get(&batch.mesh) {
+                        mesh.bind(&self.logical_device, commandbuffer);
+                        self.logical_device.cmd_draw_indexed(
+                            commandbuffer,
+                            mesh.index_count() as u32,
+                            instance_count,
+                            0,
+                            0,
+                            batch.first_instance,
+                        );
+                        counts.add(instance_count);
+                    }
+                }
+            }
+
+            let cube = self.mesh_store.get(&self.cube).filter(|_| !baking);
+            if let (Some(buffer), Some(cube)) = (self.retained.buffer(slot), cube) {
+                if bound != Some(scene_pipeline.pipeline) {
+                    scene_pipeline.bind(
+                        &self.logical_device,
+                        commandbuffer,
+                        slot,
+                        self.frame_uniforms.dynamic_offset(view),
+                    );
+                }
+                cube.bind(&self.logical_device, commandbuffer);
+                self.logical_device.cmd_bind_vertex_buffers(
+                    commandbuffer,
+                    VertexBufferBindings::InstanceBuffer as u32,
+                    &[buffer],
+                    &[0],
+                );
+                self.logical_device.cmd_draw_indexed(
+                    commandbuffer,
+                    cube.index_count() as u32,
+                    self.retained.len(),
+                    0,
+                    0,
+                    0,
+                );
+                counts.add(self.retained.len());
+            }
+        }
+    }
+
+    // Recreates the shadow map when `config.shadows` asks for another resolution. The shadow
+    // pipeline is kept, every map's render pass is compatible with it.
+    fn update_shadow_map(&mut self) -> Result<(), vk::Result> {
+        let resolution = self.config.shadows.map_or(1, |shadows| shadows.resolution);
+        if resolution.max(1) == self.shadow_map.resolution() {
+            return Ok(());
+        }
+        let shadow_map = ShadowMap::new(
+            &self.logical_device,
+            &mut self.allocator,
+            self.shadow_format,
+            resolution,
+            self.depth_range,
+        )?;
+        unsafe { self.logical_device.device_wait_idle() }?;
+        let mut old = std::mem::replace(&mut self.shadow_map, shadow_map);
+        old.cleanup(&self.logical_device, &mut self.allocator);
+        Ok(())
+    }
+
+    // Draws the scene's depth as the sun sees it into the shadow map, only the entities inside its
+    // view. With shadows off the pass still runs, only clearing, so the map is in the layout the
+    // scene shaders sample it in, and None is returned.
+    fn draw_shadow_map(
+        &self,
+        commandbuffer: vk::CommandBuffer,
+        slot: usize,
+        counts: &mut DrawCounts,
+    ) -> Option<ShadowDraws> {
+        let mut draws = ShadowDraws {
+            light: "sun",
+            ..ShadowDraws::default()
+        };
+        self.shadow_map.begin(
+            &self.logical_device,
+            commandbuffer,
+            self.depth_range.clear_depth(),
+        );
+        if self.config.shadows.is_some() {
+            pipeline::set_viewport_and_scissor(
+                &self.logical_device,
+                commandbuffer,
+                self.shadow_map.extent(),
+            );
+            let mut bound = None;
+            let mut bind = |pipeline: &Pipeline| {
+                if bound != Some(pipeline.pipeline) {
+                    pipeline.bind(
+                        &self.logical_device,
+                        commandbuffer,
+                        slot,
+                        self.frame_uniforms.dynamic_offset(0),
+                    );
+                    bound = Some(pipeline.pipeline);
+                }
+            };
+            unsafe {
+                if let Some(instances) = self.entities.instances() {
+                    self.logical_device.cmd_bind_vertex_buffers(
+                        commandbuffer,
+                        VertexBufferBindings::InstanceBuffer as u32,
+                        &[instances.buffer],
+                        &[instances.offset],
+                    );
+                    // Bakes are only drawn into their render targets.
+                    for batch in self.entities.batches().iter().filter(|b| b.view.is_none()) {
+                        draws.culled += batch.instance_count - batch.shadow_count;
+                        if batch.shadow_count == 0 {
+                            continue;
+                        }
+                        // Registered pipelines draw with their depth-only variant and cast no
+                        // shadow without one. Unregistered ones fall back to the scene's.
+                        let pipeline = match &batch.pipeline {
+                            Some(handle) if self.pipeline_store.get(handle).is_some() => {
+                                match self.pipeline_store.depth_variant(handle) {
+                                    Some(depth) => depth,
+                                    None => continue,
+                                }
+                            }
+                            _ => &self.shadow_pipeline,
+                        };
+                        if let Some(mesh) = self.mesh_store.get(&batch.mesh) {
+                            bind(pipeline);
+                            mesh.bind(&self.logical_device, commandbuffer);
+                            self.logical_device.cmd_draw_indexed(
+                                commandbuffer,
+                                mesh.index_count() as u32,
+                                batch.shadow_count,
+                                0,
+                                0,
+                                batch.shadow_first,
+                            );
+                            counts.add(batch.shadow_count);
+                            draws.draw_calls += 1;
+                            draws.instances += batch.shadow_count;
+                        }
+                    }
+                }
+                let cube = self.mesh_store.get(&self.cube);
+                if let (Some(buffer), Some(cube)) = (self.retained.buffer(slot), cube) {
+                    bind(&self.shadow_pipeline);
+                    cube.bind(&self.logical_device, commandbuffer);
+                    self.logical_device.cmd_bind_vertex_buffers(
+                        commandbuffer,
+                        VertexBufferBindings::InstanceBuffer as u32,
+                        &[buffer],
+                        &[0],
+                    );
+                    self.logical_device.cmd_draw_indexed(
+                        commandbuffer,
+                        cube.index_count() as u32,
+                        self.retained.len(),
+                        0,
+                        0,
+                        0,
+                    );
+                    counts.add(self.retained.len());
+                    draws.draw_calls += 1;
+                    draws.instances += self.retained.len();
+                }
+            }
+        }
+        self.shadow_map.end(&self.logical_device, commandbuffer);
+        self.config.shadows.map(|_| draws)
+    }
+
+    // Draws every render target with a camera, before the main pass that might sample them.
+    // A target's texture can't be sampled while it's drawn to, so the target's own camera
+    // shouldn't see the entities showing it.
+    fn draw_render_targets(
+        &self,
+        commandbuffer: vk::CommandBuffer,
+        slot: usize,
+        clear_colour: [f32; 4],
+        counts: &mut DrawCounts,
+    ) {
+        for (index, target) in self.render_targets.iter().enumerate() {
+            // A target whose texture was unregistered has nothing left to draw to.
+            let camera = match &target.camera {
+                Some(camera) if self.texture_store.get(target.texture()).is_some() => camera,
+                _ => continue,
+            };
+            let baking = target.bake.is_some();
+            // A bake is drawn over nothing, so only the cluster shows on its billboard.
+            target.begin(
+                &self.logical_device,
+                commandbuffer,
+                match baking {
+                    true => [0.0; 4],
+                    false => clear_colour,
+                },
+                self.depth_range.clear_depth(),
+            );
+            pipeline::set_viewport_and_scissor(
+                &self.logical_device,
+                commandbuffer,
+                target.extent(),
+            );
+            // View 0 is the main camera's.
+            let view = index as u32 + 1;
+            self.draw_scene(commandbuffer, slot, view, baking, camera, counts);
+            target.end(&self.logical_device, commandbuffer);
+        }
+    }
+
+    /// Records, submits and presents a frame. Returns how it went on the CPU, or the previous
+    /// frame's timing while rendering is halted. `RuntimeError::SwapchainOutOfDate` is recovered
+    /// from with `resize_surface`, see `RuntimeError`. A suboptimal swapchain is recreated at the
+    /// start of the next frame.
+    pub fn swap_framebuffers(&mut self) -> Result<FrameTiming, RuntimeError> {
+        if self.halt_render {
+            return Ok(self.frame_timer.last());
+        }
+        if self.swapchain.suboptimal {
+            debug!("Swapchain is suboptimal, recreating it");
+            self.recreate_swapchain()?;
+        }
+        let frame_start = std::time::Instant::now();
+        let delta = self.frame_timer.start(frame_start);
+        let mut draw_counts = DrawCounts::default();
+        self.last_frame = Some(frame_start);
+        self.frame_arena.reset();
+        self.allocation_stats = alloc_telemetry::take_frame();
+        self.budget
+            .check_allocations(self.frame_count, &self.allocation_stats);
+        let _scope = alloc_telemetry::scope(Subsystem::Renderer);
+        self.reload_changed_shaders();
+        self.reload_changed_assets();
+        self.update_assets();
+        self.update_shadow_map()?;
+        Animator::update(self, self.time());
+        self.impostors
+            .update(&self.camera, &mut self.entities, &mut self.render_targets);
+        // Every command buffer has been re-recorded by the time this many frames have passed.
+        let textures_scope = alloc_telemetry::scope(Subsystem::Textures);
+        let evicted = self.texture_store.collect_garbage(
+            &mut self.allocator,
+            &self.logical_device,
+            self.frame_count,
+            self.command_buffers.len() as u64 + 1,
+            &self.config.gc,
+        );
+        drop(textures_scope);
+        for id in evicted {
+            if let Some(kept) = &mut self.kept {
+                kept.forget(&id);
+            }
+            self.events.send(EngineEvent::TextureEvicted { id });
+        }
+        let meshes_scope = alloc_telemetry::scope(Subsystem::Meshes);
+        let evicted = self.mesh_store.collect_garbage(
+            &mut self.allocator,
+            &self.logical_device,
+            self.frame_count,
+            self.command_buffers.len() as u64 + 1,
+            &self.config.gc,
+        );
+        drop(meshes_scope);
+        for id in evicted {
+            if let Some(kept) = &mut self.kept {
+                kept.forget(&id);
+            }
+            self.events.send(EngineEvent::MeshEvicted { id });
+        }
+        for retired in self.retired_swapchains.ready(
+            self.frame_count,
+            self.command_buffers.len() as u64 + 1,
+            usize::MAX,
+        ) {
+            unsafe { retired.cleanup(&self.logical_device) };
+        }
+
+        let frame_buffer_info = self
+            .swapchain
+            .get_next_framebuffer(&self.logical_device, self.queues.graphics)?;
+        let slot = frame_buffer_info.frame_index;
+        self.frame_allocator.begin_frame(
+            &mut self.allocator,
+            &self.logical_device,
+            slot,
+            self.frame_count,
+        );
+
+        // Whatever this command buffer recorded last time is about to be overwritten.
+        if let Some(profiler) = &self.gpu_profiler {
+            if let Some(stats) = profiler.read(&self.logical_device, slot) {
+                self.budget.check_gpu(stats.frame, &stats.passes);
+                self.frame_stats = Some(stats);
+            }
+        }
+
+        // Runder commands
+        {
+            let commandbuffer_begininfo = vk::CommandBufferBeginInfo::builder();
+            let commandbuffer = self.command_buffers[slot];
+            unsafe {
+                self.logical_device
+                    .begin_command_buffer(commandbuffer, &commandbuffer_begininfo)?;
+            }
+            if let Some(profiler) = &mut self.gpu_profiler {
+                profiler.begin(
+                    &self.logical_device,
+                    commandbuffer,
+                    slot,
+                    self.frame_count,
+                    &self.allocation_stats,
+                    &self.upload_stats,
+                );
+            }
+            // Before anything in the frame samples them or the texture sets are written.
+            self.texture_store.finish_uploads(
+                &mut self.allocator,
+                &self.logical_device,
+                commandbuffer,
+            )?;
+            let projection =
+                self.depth_range.projection_adjustment() * self.camera.projectionmatrix;
+            let view_projection = projection * self.camera.viewmatrix;
+            let frame_context = FrameContext {
+                device: &self.logical_device,
+                command_buffer: commandbuffer,
+                render_pass: self.renderpass,
+                framebuffer: frame_buffer_info.scene_framebuffer,
+                image_view: frame_buffer_info.image_view,
+                extent: self.swapchain.extent,
+                frame_index: slot,
+                frame: self.frame_count,
+                view_projection,
+                arena: &self.frame_arena,
+            };
+            for middleware in &mut self.middleware {
+                let _scope = alloc_telemetry::scope(Subsystem::Middleware);
+                middleware.before_main_pass(&frame_context);
+            }
+            let materials_scope = alloc_telemetry::scope(Subsystem::Materials);
+            let material_buffer =
+                self.material_store
+                    .upload(&mut self.allocator, &self.logical_device, slot)?;
+            let light_buffer =
+                self.lights
+                    .upload(&mut self.allocator, &self.logical_device, slot)?;
+            drop(materials_scope);
+            // The main camera first, then a view for every render target, drawn or not, so a
+            // target's index in `render_targets` gives its view.
+            let views: Vec<FrameUniformData> = std::iter::once(&self.camera)
+                .chain(
+                    self.render_targets
+                        .iter()
+                        .map(|target| target.camera.as_ref().unwrap_or(&self.camera)),
+                )
+                .map(|camera| self.frame_uniform_data(camera))
+                .collect();
+            let frame_uniform_buffer = self.frame_uniforms.upload(
+                &mut self.frame_allocator,
+                &mut self.allocator,
+                &self.logical_device,
+                slot,
+                &views,
+            )?;
+            let clear_colour = [0.1, 0.1, 0.1, 1.0];
+            let clearvalues = [
+                vk::ClearValue {
+                    color: vk::ClearColorValue {
+                        float32: clear_colour,
+                    },
+                },
+                vk::ClearValue {
+                    depth_stencil: vk::ClearDepthStencilValue {
+                        depth: self.depth_range.clear_depth(),
+                        stencil: 0,
+                    },
+                },
+            ];
+            self.scene.update_world_transforms();
+            let shadow_frustum = self
+                .config
+                .shadows
+                .map(|_| Frustum::from_view_projection(&self.shadow_view_projection()));
+            self.entities.upload(
+                &mut self.frame_allocator,
+                &mut self.allocator,
+                &self.logical_device,
+                slot,
+                &UploadContext {
+                    textures: &self.texture_store,
+                    materials: &self.material_store,
+                    default_material: &self.default_material,
+                    light_probes: &self.light_probes,
+                    scene: &self.scene,
+                    meshes: &self.mesh_store,
+                    frustum: &Frustum::from_view_projection(
+                        &(self.camera.projectionmatrix * self.camera.viewmatrix),
+                    ),
+                    shadow_frustum: shadow_frustum.as_ref(),
+                    render_targets: &self.render_targets,
+                },
+            )?;
+            self.retained.upload(
+                &mut self.allocator,
+                &self.logical_device,
+                slot,
+                self.frame_count,
+                &self.light_probes,
+            )?;
+
+            let renderpass_begininfo = vk::RenderPassBeginInfo::builder()
+                .render_pass(self.renderpass)
+                .framebuffer(frame_buffer_info.scene_framebuffer)
+                .render_area(vk::Rect2D {
+                    offset: vk::Offset2D { x: 0, y: 0 },
+                    extent: self.swapchain.extent,
+                })
+                .clear_values(&clearvalues);
+
+            // Each frame's sets are written when a pipeline is created and again only when what
+            // they point at changes: the texture set after textures come and go, the buffer sets
+            // after their buffer is created. The frame's previous submission has finished by
+            // now, so none of them are in use.
+            let wanted = WrittenSets {
+                textures: Some(self.texture_store.version()),
+                frame_uniforms: frame_uniform_buffer,
+                materials: material_buffer,
+                lights: light_buffer,
+                shadow_map: self.shadow_map.view(),
+            };
+            let textures_stale = std::iter::once(&self.graphics_pipeline)
+                .chain(std::iter::once(&self.shadow_pipeline))
+                .chain(&self.debug_pipeline)
+                .chain(self.custom_pipelines.values())
+                .chain(self.pipeline_store.pipelines())
+                .any(|pipeline| pipeline.written[slot].textures != wanted.textures);
+            let descriptor_image_infos = match textures_stale {
+                true => self.texture_store.get_descriptor_image_info(),
+                false => vec![],
+            };
+            // One view, which one is picked by the dynamic offset when binding.
+            let frame_uniforms_info = [vk::DescriptorBufferInfo {
+                buffer: frame_uniform_buffer,
+                offset: 0,
+                range: std::mem::size_of::<FrameUniformData>() as u64,
+            }];
+            let material_buffer_info = [vk::DescriptorBufferInfo {
+                buffer: material_buffer,
+                offset: 0,
+                range: vk::WHOLE_SIZE,
+            }];
+            let shadow_map_info = [self.shadow_map.descriptor_image_info()];
+            let light_buffer_info = [vk::DescriptorBufferInfo {
+                buffer: light_buffer,
+                offset: 0,
+                range: vk::WHOLE_SIZE,
+            }];
+            let descriptor_writes = self.frame_arena.collect(
+                std::iter::once(&mut self.graphics_pipeline)
+                    .chain(std::iter::once(&mut self.shadow_pipeline))
+                    .chain(&mut self.debug_pipeline)
+                    .chain(self.custom_pipelines.values_mut())
+                    .chain(self.pipeline_store.pipelines_mut())
+                    .flat_map(|pipeline| {
+                        let written = std::mem::replace(&mut pipeline.written[slot], wanted);
+                        // Nothing to point the texture array at until a texture is registered.
+                        let texture_write = (written.textures != wanted.textures
+                            && !descriptor_image_infos.is_empty())
+                        .then(|| vk::WriteDescriptorSet {
+                            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                            dst_set: pipeline.descriptor_sets[slot],
+                            dst_binding: 0,
+                            dst_array_element: 0,
+                            p_image_info: descriptor_image_infos.as_ptr(),
+                            descriptor_count: descriptor_image_infos.len() as u32,
+                            ..Default::default()
+                        });
+                        if texture_write.is_none() {
+                            pipeline.written[slot].textures = written.textures;
+                        }
+                        let frame_uniforms_write = (written.frame_uniforms
+                            != wanted.frame_uniforms)
+                            .then(|| vk::WriteDescriptorSet {
+                                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
+                                dst_set: pipeline.frame_descriptor_sets[slot],
+                                dst_binding: 0,
+                                dst_array_element: 0,
+                                p_buffer_info: frame_uniforms_info.as_ptr(),
+                                descriptor_count: 1,
+                                ..Default::default()
+                            });
+                        let materials_write = (written.materials != wanted.materials).then(|| {
+                            vk::WriteDescriptorSet {
+                                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                                dst_set: pipeline.material_descriptor_sets[slot],
+                                dst_binding: 0,
+                                dst_array_element: 0,
+                                p_buffer_info: material_buffer_info.as_ptr(),
+                                descriptor_count: 1,
+                                ..Default::default()
+                            }
+                        });
+                        let shadow_map_write =
+                            (written.shadow_map != wanted.shadow_map).then(|| {
+                                vk::WriteDescriptorSet {
+                                    descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                                    dst_set: pipeline.frame_descriptor_sets[slot],
+                                    dst_binding: 1,
+                                    dst_array_element: 0,
+                                    p_image_info: shadow_map_info.as_ptr(),
+                                    descriptor_count: 1,
+                                    ..Default::default()
+                                }
+                            });
+                        let lights_write =
+                            (written.lights != wanted.lights).then(|| vk::WriteDescriptorSet {
+                                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                                dst_set: pipeline.material_descriptor_sets[slot],
+                                dst_binding: 1,
+                                dst_array_element: 0,
+                                p_buffer_info: light_buffer_info.as_ptr(),
+                                descriptor_count: 1,
+                                ..Default::default()
+                            });
+                        texture_write
+                            .into_iter()
+                            .chain(frame_uniforms_write)
+                            .chain(materials_write)
+                            .chain(lights_write)
+                            .chain(shadow_map_write)
+                    }),
+            );
+            unsafe {
+                if !descriptor_writes.is_empty() {
+                    self.logical_device
+                        .update_descriptor_sets(&descriptor_writes, &[]);
+                }
+                // A skybox whose texture has been unregistered or is still uploading isn't drawn.
+                let skybox = self
+                    .skybox
+                    .as_ref()
+                    .and_then(|handle| self.texture_store.get(handle))
+                    .filter(|texture| texture.is_ready())
+                    .map(|texture| texture.image_view);
+                self.skybox_pipeline
+                    .write(&self.logical_device, slot, skybox);
+
+                if let Some(profiler) = &mut self.gpu_profiler {
+                    profiler.pass(&self.logical_device, commandbuffer, slot, "shadows");
+                }
+                debug::cmd_begin_debug_label(&self.logical_device, commandbuffer, "shadows");
+                let shadow_draws = self.draw_shadow_map(commandbuffer, slot, &mut draw_counts);
+                debug::cmd_end_debug_label(&self.logical_device, commandbuffer);
+                if let (Some(profiler), Some(draws)) = (&mut self.gpu_profiler, shadow_draws) {
+                    profiler.shadows(slot, draws);
+                }
+                if let Some(profiler) = &mut self.gpu_profiler {
+                    profiler.pass(&self.logical_device, commandbuffer, slot, "render targets");
+                }
+                debug::cmd_begin_debug_label(&self.logical_device, commandbuffer, "render targets");
+                self.draw_render_targets(commandbuffer, slot, clear_colour, &mut draw_counts);
+                debug::cmd_end_debug_label(&self.logical_device, commandbuffer);
+                for target in &mut self.render_targets {
+                    if target.bake.take().is_some() {
+                        target.camera = None;
+                    }
+                }
+                if let Some(profiler) = &mut self.gpu_profiler {
+                    profiler.pass(&self.logical_device, commandbuffer, slot, "main");
+                }
+                debug::cmd_begin_debug_label(&self.logical_device, commandbuffer, "main");
+                self.logical_device.cmd_begin_render_pass(
+                    commandbuffer,
+                    &renderpass_begininfo,
+                    vk::SubpassContents::INLINE,
+                );
+                pipeline::set_viewport_and_scissor(
+                    &self.logical_device,
+                    commandbuffer,
+                    self.swapchain.extent,
+                );
+                if self.test_pattern.is_none() {
+                    self.draw_scene(
+                        commandbuffer,
+                        slot,
+                        0,
+                        false,
+                        &self.camera,
+                        &mut draw_counts,
+                    );
+                }
+                for middleware in &mut self.middleware {
+                    let _scope = alloc_telemetry::scope(Subsystem::Middleware);
+                    middleware.in_main_pass(&frame_context);
+                }
+                self.logical_device.cmd_end_render_pass(commandbuffer);
+
+                // Debug views are shown as the shaders drew them.
+                debug::cmd_end_debug_label(&self.logical_device, commandbuffer);
+                if let Some(profiler) = &mut self.gpu_profiler {
+                    profiler.pass(&self.logical_device, commandbuffer, slot, "post");
+                }
+                debug::cmd_begin_debug_label(&self.logical_device, commandbuffer, "post");
+                let source = match (self.test_pattern, self.debug_view) {
+                    (None, DebugView::Off) => self
+                        .post_process
+                        .record(&self.logical_device, commandbuffer),
+                    _ => 0,
+                };
+
+                let output_begininfo = vk::RenderPassBeginInfo::builder()
+                    .render_pass(self.output_pass)
+                    .framebuffer(frame_buffer_info.framebuffer)
+                    .render_area(vk::Rect2D {
+                        offset: vk::Offset2D { x: 0, y: 0 },
+                        extent: self.swapchain.extent,
+                    })
+                    .clear_values(&clearvalues[..1]);
+                debug::cmd_end_debug_label(&self.logical_device, commandbuffer);
+                if let Some(profiler) = &mut self.gpu_profiler {
+                    profiler.pass(&self.logical_device, commandbuffer, slot, "output");
+                }
+                debug::cmd_begin_debug_label(&self.logical_device, commandbuffer, "output");
+                self.logical_device.cmd_begin_render_pass(
+                    commandbuffer,
+                    &output_begininfo,
+                    vk::SubpassContents::INLINE,
+                );
+                pipeline::set_viewport_and_scissor(
+                    &self.logical_device,
+                    commandbuffer,
+                    self.swapchain.extent,
+                );
+                if let Some(pattern) = self.test_pattern {
+                    self.test_pattern_pipeline
+                        .draw(&self.logical_device, commandbuffer, pattern);
+                } else {
+                    let (tonemapper, exposure) = match self.debug_view {
+                        DebugView::Off => (self.config.tonemapper, self.camera.exposure()),
+                        _ => (Tonemapper::Clamp, 0.0),
+                    };
+                    self.tonemap_pipeline.draw(
+                        &self.logical_device,
+                        commandbuffer,
+                        source,
+                        tonemapper,
+                        exposure,
+                    );
+                }
+
+                #[cfg(feature = "ui")]
+                if let Some(ui) = &mut self.ui {
+                    ui.draw(
+                        &mut self.frame_allocator,
+                        &mut self.allocator,
+                        &self.logical_device,
+                        commandbuffer,
+                        slot,
+                        self.swapchain.extent,
+                        &self.texture_store,
+                    )?;
+                    pipeline::set_viewport_and_scissor(
+                        &self.logical_device,
+                        commandbuffer,
+                        self.swapchain.extent,
+                    );
+                }
+
+                self.toasts.update(frame_start);
+                if let Some(text) = &mut self.text {
+                    self.toasts.queue(text, self.swapchain.extent, frame_start);
+                    if let Some(font) = self
+                        .texture_store
+                        .get(&text.font().texture)
+                        .filter(|font| font.is_ready())
+                    {
+                        text.draw(
+                            &self.logical_device,
+                            commandbuffer,
+                            slot,
+                            self.swapchain.extent,
+                            font.image_view,
+                        );
+                    }
+                }
+
+                self.logical_device.cmd_end_render_pass(commandbuffer);
+                debug::cmd_end_debug_label(&self.logical_device, commandbuffer);
+                for middleware in &mut self.middleware {
+                    let _scope = alloc_telemetry::scope(Subsystem::Middleware);
+                    middleware.after_main_pass(&frame_context);
+                }
+                if let Some(profiler) = &self.gpu_profiler {
+                    profiler.end(&self.logical_device, commandbuffer, slot);
+                }
+                self.logical_device.end_command_buffer(commandbuffer)?;
+            }
+        }
+
+        let command_buffers = [self.command_buffers[slot]];
+
+        let submit_info = [ash::vk::SubmitInfo::builder()
+            .wait_semaphores(&frame_buffer_info.semaphores_available)
+            .wait_dst_stage_mask(&frame_buffer_info.waiting_stages)
+            .command_buffers(&command_buffers)
+            .signal_semaphores(&frame_buffer_info.semaphores_finished)
+            .build()];
+        unsafe {
+            self.logical_device.queue_submit(
+                frame_buffer_info.queue,
+                &submit_info,
+                frame_buffer_info.may_begin_fence,
+            )
+        }?;
+
+        // The frame was submitted either way, so it's counted before a failed present is
+        // reported.
+        let presented = self.swapchain.present_framebuffer(
+            &frame_buffer_info,
+            self.present_timer.present_time(self.frame_count),
+        );
+        self.present_timer.presented(
+            self.frame_count,
+            std::time::Duration::from_secs_f32(1.0 / self.refresh_rate.unwrap_or(60.0)),
+            self.present_mode,
+        );
+        self.present_timer
+            .poll(&self.logical_device, self.swapchain.swapchain);
+
+        let cpu_time = frame_start.elapsed();
+        self.budget.check_cpu(self.frame_count, cpu_time);
+        let timing = self
+            .frame_timer
+            .finish(self.frame_count, delta, cpu_time, draw_counts);
+        self.frame_count += 1;
+        presented?;
+        Ok(timing)
+    }
+
+    /// What the last `swap_framebuffers` returned.
+    pub fn frame_timing(&self) -> FrameTiming {
+        self.frame_timer.last()
+    }
+
+    /// Creates the renderer again on a new device once `swap_framebuffers` has returned
+    /// `RuntimeError::DeviceLost`, e.g. after a driver reset or a GPU timeout. The camera,
+    /// entities, scene, retained instances, materials, lights, sky, skybox, wind, light probes,
+    /// config and event subscribers carry over. With `RendererConfig::device_lost_recovery`
+    /// textures and meshes are uploaded again too, keeping their handles and texture indices.
+    /// Anything else made on the old device, render targets, compute resources, pipelines, vertex
+    /// formats, post effects, fonts and middleware, has to be made again.
+    pub fn recover(mut self, window: &Window) -> std::result::Result<Vulkan, InitError> {
+        warn!("Device lost, creating the renderer again");
+        // Nothing is left running on a lost device, this only fails.
+        let _ = unsafe { self.logical_device.device_wait_idle() };
+        // Their CPU side state carries over, the buffers go with the old device.
+        self.retained
+            .cleanup(&mut self.allocator, &self.logical_device);
+        self.material_store
+            .cleanup(&mut self.allocator, &self.logical_device);
+        self.lights
+            .cleanup(&mut self.allocator, &self.logical_device);
+        let frames_in_flight = self.command_buffers.len();
+        let retained =
+            std::mem::replace(&mut self.retained, RetainedInstances::new(frames_in_flight));
+        let material_store = std::mem::replace(&mut self.material_store, MaterialStore::new());
+        let lights = std::mem::replace(&mut self.lights, LightStore::new());
+        // Render targets aren't made again, so neither are the impostors drawn with them.
+        self.impostors.forget(&mut self.entities);
+        let entities = std::mem::replace(&mut self.entities, Entities::new());
+        let scene = std::mem::take(&mut self.scene);
+        let camera = std::mem::take(&mut self.camera);
+        let light_probes = std::mem::take(&mut self.light_probes);
+        let animator = std::mem::take(&mut self.animator);
+        let events = std::mem::take(&mut self.events);
+        let assets = std::mem::take(&mut self.assets);
+        let skybox = self.skybox.take();
+        let kept = self.kept.take();
+        let textures: Vec<(u32, TextureHandle)> = self
+            .texture_store
+            .handles()
+            .map(|(index, handle)| (index, handle.clone()))
+            .collect();
+        let cube = self.cube.clone();
+        let meshes: Vec<MeshHandle> = self
+            .mesh_store
+            .handles()
+            .filter(|handle| handle.id() != cube.id())
+            .cloned()
+            .collect();
+        let (wind, lighting, sky, budget) = (self.wind, self.lighting, self.sky, self.budget);
+        let (default_material, start_time, frame_count) =
+            (self.default_material, self.start_time, self.frame_count);
+        let test_pattern = self.test_pattern;
+        let config = self.config.clone();
+        // The old swapchain has to be gone before a new one can be made for the window.
+        drop(self);
+
+        let mut vulkan = Vulkan::with_config(window, config)?;
+        let registered = vulkan.cube.clone();
+        vulkan.mesh_store.adopt(&registered, cube.clone());
+        vulkan.cube = cube;
+        if let Some(kept) = &kept {
+            for (index, handle) in textures {
+                let Some(source) = kept.texture(&handle.id()) else {
+                    continue;
+                };
+                let registered = match source {
+                    TextureSource::Image(image, sampler) => {
+                        vulkan.register_texture(image, *sampler)
+                    }
+                    TextureSource::Compressed(image, sampler) => {
+                        vulkan.register_compressed_texture(image, *sampler)
+                    }
+                    TextureSource::Hdr(image) => vulkan.register_hdr_texture(image),
+                    TextureSource::Cubemap(faces) => {
+                        let [px, nx, py, ny, pz, nz] = &**faces;
+                        vulkan.register_cubemap([px, nx, py, ny, pz, nz])
+                    }
+                    TextureSource::Equirectangular(image) => {
+                        vulkan.register_equirectangular_cubemap(image)
+                    }
+                };
+                match registered.and_then(|registered| {
+                    vulkan.wait_for_texture(&registered)?;
+                    Ok(registered)
+                }) {
+                    Ok(registered) => vulkan.texture_store.adopt(&registered, handle, index),
+                    Err(e) => warn!(
+                        "Texture {} could not be uploaded again. {:?}",
+                        handle.id(),
+                        e
+                    ),
+                }
+            }
+            for handle in meshes {
+                let Some((vertex_data, index_data, bounds)) = kept.mesh(&handle.id()) else {
+                    continue;
+                };
+                match vulkan.upload_mesh(vertex_data, index_data, bounds) {
+                    Ok(registered) => vulkan.mesh_store.adopt(&registered, handle),
+                    Err(e) => warn!("Mesh {} could not be uploaded again. {:?}", handle.id(), e),
+                }
+            }
+        }
+        vulkan.kept = kept;
+        vulkan.retained = retained;
+        vulkan.material_store = material_store;
+        vulkan.default_material = default_material;
+        vulkan.lights = lights;
+        vulkan.entities = entities;
+        vulkan.scene = scene;
+        vulkan.camera = camera;
+        vulkan.light_probes = light_probes;
+        vulkan.animator = animator;
+        vulkan.events = events;
+        vulkan.assets = assets;
+        vulkan.skybox = skybox.filter(|handle| vulkan.texture_store.get(handle).is_some());
+        vulkan.wind = wind;
+        vulkan.lighting = lighting;
+        vulkan.sky = sky;
+        vulkan.budget = budget;
+        vulkan.start_time = start_time;
+        vulkan.frame_count = frame_count;
+        vulkan.show_test_pattern(test_pattern);
+        Ok(vulkan)
+    }
+}
+
+impl Drop for Vulkan {
+    fn drop(&mut self) {
+        info!("Destroying vulkan");
+        unsafe {
+            // Fails once the device is lost, there's nothing left running to wait for then.
+            if let Err(e) = self.logical_device.device_wait_idle() {
+                warn!("Could not wait for the device to go idle. {:?}", e);
+            }
+
+            for middleware in &mut self.middleware {
+                middleware.cleanup(&self.logical_device, &mut self.allocator);
+            }
+
+            self.retained
+                .cleanup(&mut self.allocator, &self.logical_device);
+
+            for target in &mut self.render_targets {
+                target.cleanup(&self.logical_device, &mut self.allocator);
+            }
+            self.texture_store
+                .cleanup(&mut self.allocator, &self.logical_device);
+            self.material_store
+                .cleanup(&mut self.allocator, &self.logical_device);
+            self.lights
+                .cleanup(&mut self.allocator, &self.logical_device);
+            self.frame_allocator
+                .cleanup(&mut self.allocator, &self.logical_device);
+            if let Some(thumbnailer) = &self.thumbnailer {
+                thumbnailer.cleanup(&self.logical_device);
+            }
+
+            self.mesh_store
+                .cleanup(&mut self.allocator, &self.logical_device);
+            if let Some(text) = &mut self.text {
+                text.cleanup(&mut self.allocator, &self.logical_device);
+            }
+            #[cfg(feature = "ui")]
+            if let Some(ui) = &mut self.ui {
+                ui.cleanup(&self.logical_device);
+            }
+
+            if let Some(profiler) = &self.gpu_profiler {
+                profiler.cleanup(&self.logical_device);
+            }
+
+            self.transfer_commands.cleanup(&self.logical_device);
+            self.graphics_commands.cleanup(&self.logical_device);
+            self.staging_ring
+                .cleanup(&mut self.allocator, &self.logical_device);
+            self.command_buffer_pools.cleanup(&self.logical_device);
+
+            self.graphics_pipeline.cleanup(&self.logical_device);
+            self.shadow_pipeline.cleanup(&self.logical_device);
+            self.shadow_map
+                .cleanup(&self.logical_device, &mut self.allocator);
+            if let Some(debug_pipeline) = &self.debug_pipeline {
+                debug_pipeline.cleanup(&self.logical_device);
+            }
+            for pipeline in self.custom_pipelines.values() {
+                pipeline.cleanup(&self.logical_device);
+            }
+            self.pipeline_store.cleanup(&self.logical_device);
+            self.test_pattern_pipeline.cleanup(&self.logical_device);
+            self.sky_pipeline.cleanup(&self.logical_device);
+            self.skybox_pipeline.cleanup(&self.logical_device);
+
+            self.post_process
+                .cleanup(&self.logical_device, &mut self.allocator);
+            self.tonemap_pipeline.cleanup(&self.logical_device);
+            self.logical_device
+                .destroy_render_pass(self.renderpass, None);
+            self.logical_device
+                .destroy_render_pass(self.output_pass, None);
+
+            for retired in self.retired_swapchains.drain() {
+                retired.cleanup(&self.logical_device);
+            }
+            self.swapchain
+                .cleanup(&self.logical_device, &mut self.allocator);
+            let leaked = leaks::report(&self.logical_device);
+            std::mem::ManuallyDrop::drop(&mut self.allocator);
+
+            self.logical_device.destroy_device(None);
+            std::mem::ManuallyDrop::drop(&mut self.surface);
+            std::mem::ManuallyDrop::drop(&mut self.debug);
+            self.instance.destroy_instance(None);
+
+            // Finish tearing down first so a leak doesn't take the device and instance with it.
+            if cfg!(debug_assertions) && leaked > 0 && !std::thread::panicking() {
+                panic!("{} GPU allocations leaked, see the log for which", leaked);
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use na::Matrix4;
+
+use super::{entity::Entity, material::MaterialHandle, mesh::MeshHandle, texture::TextureHandle};
+
+/// One glTF primitive after `Vulkan::register_gltf`. Primitives without a base colour texture
+/// share a white one.
+#[derive(Clone)]
+pub struct ModelPrimitive {
+    pub mesh: MeshHandle,
+    pub texture: TextureHandle,
+    pub material: MaterialHandle,
+}
+
+#[derive(Clone)]
+pub struct ModelNode {
+    pub name: Option<String>,
+    /// Relative to the parent node.
+    pub transform: Matrix4<f32>,
+    pub primitives: Vec<ModelPrimitive>,
+    /// Indices into `Model::nodes`.
+    pub children: Vec<usize>,
+}
+
+/// The node graph of a registered glTF scene, with the same node indices as the `GltfScene` it
+/// came from. Holding it keeps its meshes, textures and materials alive.
+#[derive(Clone)]
+pub struct Model {
+    pub nodes: Vec<ModelNode>,
+    pub roots: Vec<usize>,
+}
+
+impl Model {
+    /// Finds a node by its name in the source file.
+    pub fn find_node(&self, name: &str) -> Option<usize> {
+        self.nodes
+            .iter()
+            .position(|node| node.name.as_deref() == Some(name))
+    }
+
+    /// The transform from `node`'s space to the model's, None if `node` isn't reachable from a
+    /// root.
+    pub fn world_transform(&self, node: usize) -> Option<Matrix4<f32>> {
+        let mut found = None;
+        self.walk(Matrix4::identity(), |index, transform| {
+            if index == node {
+                found = Some(*transform);
+            }
+        });
+        found
+    }
+
+    /// An entity for every primitive in the scene, placed by `transform` times its node's
+    /// transform. Add them with `Vulkan::add_entity`.
+    pub fn entities(&self, transform: Matrix4<f32>) -> Vec<Entity> {
+        let mut entities = vec![];
+        self.walk(transform, |index, transform| {
+            for primitive in &self.nodes[index].primitives {
+                let mut entity = Entity::new(primitive.mesh.clone(), primitive.texture.clone());
+                entity.transform = *transform;
+                entity.material = Some(primitive.material);
+                entities.push(entity);
+            }
+        });
+        entities
+    }
+
+    // Visits every node reachable from the roots with its accumulated transform.
+    fn walk(&self, transform: Matrix4<f32>, mut visit: impl FnMut(usize, &Matrix4<f32>)) {
+        let mut stack: Vec<(usize, Matrix4<f32>)> =
+            self.roots.iter().map(|&root| (root, transform)).collect();
+        // glTF forbids cycles, but a hand-edited file shouldn't hang the walk.
+        let mut visited = vec![false; self.nodes.len()];
+        while let Some((index, parent)) = stack.pop() {
+            if std::mem::replace(&mut visited[index], true) {
+                continue;
+            }
+            let node = &self.nodes[index];
+            let transform = parent * node.transform;
+            visit(index, &transform);
+            stack.extend(node.children.iter().map(|&child| (child, transform)));
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use na::Vector3;
+
+use super::{
+    bounds::Ray,
+    entity::{Entities, EntityId},
+    mesh::{MeshHandle, MeshStore},
+    scene::{NodeId, Scene},
+};
+
+/// What a pick hit: an entity from `Vulkan::add_entity`, or a node of `Vulkan::scene` through one
+/// of its entities.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Picked {
+    Entity(EntityId),
+    Node(NodeId),
+}
+
+/// The nearest thing under a point on screen, see `Vulkan::pick`.
+#[derive(Clone)]
+pub struct PickHit {
+    pub picked: Picked,
+    pub mesh: MeshHandle,
+    /// Where the ray entered the mesh's bounding box, in world space.
+    pub position: Vector3<f32>,
+    /// From the camera to `position`.
+    pub distance: f32,
+}
+
+// Tests `ray` against the bounds of every entity and scene node, in each mesh's own space so a
+// rotated box stays tight. Scene world transforms have to be up to date.
+pub(super) fn pick(
+    ray: &Ray,
+    entities: &Entities,
+    scene: &Scene,
+    meshes: &MeshStore,
+) -> Option<PickHit> {
+    let placed = entities
+        .iter()
+        .map(|(id, entity)| (Picked::Entity(id), entity, entity.transform))
+        .chain(
+            scene
+                .drawn()
+                .map(|(id, entity, transform)| (Picked::Node(id), entity, transform)),
+        );
+    let mut nearest: Option<PickHit> = None;
+    for (picked, entity, transform) in placed {
+        let Some(mesh) = meshes.get(&entity.mesh) else {
+            continue;
+        };
+        let Some(local) = ray.into_space(&transform) else {
+            continue;
+        };
+        // The sphere rules out most misses for less work than the box.
+        if local.intersect_sphere(mesh.bounding_sphere()).is_none() {
+            continue;
+        }
+        let Some(distance) = local.intersect_aabb(mesh.aabb()) else {
+            continue;
+        };
+        if nearest.as_ref().is_none_or(|hit| distance < hit.distance) {
+            nearest = Some(PickHit {
+                picked,
+                mesh: entity.mesh.clone(),
+                position: ray.at(distance),
+                distance,
+            });
+        }
+    }
+    nearest
+}
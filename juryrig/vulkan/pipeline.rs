@@ -0,0 +1,631 @@
+use ash::vk::{
+    self, DescriptorSetLayoutBinding, DescriptorSetVariableDescriptorCountAllocateInfo,
+    DescriptorSetVariableDescriptorCountAllocateInfoBuilder,
+    DescriptorSetVariableDescriptorCountAllocateInfoEXT, PipelineDepthStencilStateCreateInfo,
+};
+
+use std::mem::size_of;
+
+use super::{
+    config::{DepthRange, MAX_FRAMES_IN_FLIGHT},
+    debug,
+    debug_view::DebugView,
+    error::RuntimeError,
+    pipeline_store::{BlendMode, CullMode, RenderState},
+    vertex_format::{self, VertexInput, VertexLayout, VertexLayoutError},
+    InstanceData, VertexBufferBindings,
+};
+
+// Every built-in pipeline takes its viewport and scissor from the command buffer, so none of them
+// have to be rebuilt when the swapchain is resized.
+pub(super) const DYNAMIC_STATES: [vk::DynamicState; 2] =
+    [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+
+// Covers the whole of `extent`, for the pipelines using `DYNAMIC_STATES`.
+pub(super) fn set_viewport_and_scissor(
+    logical_device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    extent: vk::Extent2D,
+) {
+    let viewports = [vk::Viewport {
+        x: 0.,
+        y: 0.,
+        width: extent.width as f32,
+        height: extent.height as f32,
+        min_depth: 0.,
+        max_depth: 1.,
+    }];
+    let scissors = [vk::Rect2D {
+        offset: vk::Offset2D { x: 0, y: 0 },
+        extent,
+    }];
+    unsafe {
+        logical_device.cmd_set_viewport(command_buffer, 0, &viewports);
+        logical_device.cmd_set_scissor(command_buffer, 0, &scissors);
+    }
+}
+
+pub(super) const VERTEX_SHADER: &[u32] =
+    vk_shader_macros::include_glsl!("./shaders/vertex.glsl", kind: vert);
+pub(super) const FRAGMENT_SHADER: &[u32] =
+    vk_shader_macros::include_glsl!("./shaders/fragment.glsl", kind: frag);
+const SHADOW_VERTEX_SHADER: &[u32] =
+    vk_shader_macros::include_glsl!("./shaders/shadow.glsl", kind: vert);
+const SHADOW_FRAGMENT_SHADER: &[u32] =
+    vk_shader_macros::include_glsl!("./shaders/shadow_fragment.glsl", kind: frag);
+
+// Location, offset and format of every `InstanceData` field as the vertex shader sees it. The
+// matrices take one location per column.
+crate::vertex_input!(InstanceData {
+    0 => model: R32G32B32A32_SFLOAT,
+    1 => model + 16: R32G32B32A32_SFLOAT,
+    2 => model + 32: R32G32B32A32_SFLOAT,
+    3 => model + 48: R32G32B32A32_SFLOAT,
+    4 => texture_index: R32_UINT,
+    8 => wind: R32G32_SFLOAT,
+    9 => material_index: R32_UINT,
+    10 => uv_rect: R32G32B32A32_SFLOAT,
+    11 => ambient: R32G32B32A32_SFLOAT,
+    12 => ambient + 16: R32G32B32A32_SFLOAT,
+    13 => ambient + 32: R32G32B32A32_SFLOAT,
+    14 => tint: R32G32B32A32_SFLOAT,
+});
+
+// Fields added to `InstanceData` without an attribute above (or padding the compiler slipped in)
+// would otherwise go unnoticed until the shader read garbage.
+const _: () = {
+    let attributes = <InstanceData as VertexInput>::ATTRIBUTES;
+    let mut covered = 0;
+    let mut i = 0;
+    while i < attributes.len() {
+        covered += match vertex_format::format_info(attributes[i].format) {
+            Some((_, size)) => size as usize,
+            None => panic!("an InstanceData attribute uses a format vertex_format doesn't know"),
+        };
+        i += 1;
+    }
+    assert!(
+        covered == size_of::<InstanceData>(),
+        "The InstanceData attributes don't cover every byte of it, update them with the struct"
+    );
+};
+
+// What a scene pipeline is built from besides the render pass it draws in, see
+// `Pipeline::init_with_shaders`.
+pub(super) struct PipelineShaders<'a> {
+    pub(super) vertex_shader: &'a [u32],
+    // May be empty for a depth only pipeline.
+    pub(super) fragment_shader: &'a [u32],
+    // Drawn in a pass with no colour attachment, with a depth bias against shadow acne.
+    pub(super) depth_only: bool,
+    pub(super) mesh_layout: &'a VertexLayout,
+    pub(super) debug_view: DebugView,
+    pub(super) state: RenderState,
+}
+
+// What one frame's descriptor sets were last written with.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub(super) struct WrittenSets {
+    // The `TextureStore::version` of set 0, None before it's first written.
+    pub(super) textures: Option<u64>,
+    pub(super) frame_uniforms: vk::Buffer,
+    pub(super) materials: vk::Buffer,
+    pub(super) lights: vk::Buffer,
+    pub(super) shadow_map: vk::ImageView,
+}
+
+pub(super) struct Pipeline {
+    pub(super) pipeline: vk::Pipeline,
+    pub(super) layout: vk::PipelineLayout,
+    descriptor_pool: vk::DescriptorPool,
+    pub(super) descriptor_sets: Vec<vk::DescriptorSet>,
+    // Per frame in flight, so each set is only written again once what it points at changes.
+    pub(super) written: [WrittenSets; MAX_FRAMES_IN_FLIGHT],
+    // Set 1, the `FrameUniforms` buffer of the frame being recorded and the shadow map.
+    pub(super) frame_descriptor_sets: Vec<vk::DescriptorSet>,
+    // Set 2, the material and light buffers of the frame being recorded.
+    pub(super) material_descriptor_sets: Vec<vk::DescriptorSet>,
+    descriptor_set_layout_texture: vk::DescriptorSetLayout,
+    descriptor_set_layout_frame: vk::DescriptorSetLayout,
+    descriptor_set_layout_material: vk::DescriptorSetLayout,
+}
+
+impl Pipeline {
+    pub(super) fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            logical_device.destroy_pipeline(self.pipeline, None);
+            logical_device.destroy_pipeline_layout(self.layout, None);
+            logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+            logical_device.destroy_descriptor_set_layout(self.descriptor_set_layout_texture, None);
+            logical_device.destroy_descriptor_set_layout(self.descriptor_set_layout_frame, None);
+            logical_device.destroy_descriptor_set_layout(self.descriptor_set_layout_material, None);
+        }
+    }
+
+    // Binds the pipeline and frame `slot`'s descriptor sets, with the frame uniforms at
+    // `frame_uniforms_offset`, see `FrameUniforms::dynamic_offset`.
+    pub(super) fn bind(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        slot: usize,
+        frame_uniforms_offset: u32,
+    ) {
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout,
+                0,
+                &[
+                    self.descriptor_sets[slot],
+                    self.frame_descriptor_sets[slot],
+                    self.material_descriptor_sets[slot],
+                ],
+                &[frame_uniforms_offset],
+            );
+        }
+    }
+
+    pub(super) fn init(
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        depth_range: DepthRange,
+        max_textures: u32,
+    ) -> Result<Pipeline, vk::Result> {
+        Self::init_with_shaders(
+            logical_device,
+            renderpass,
+            depth_range,
+            max_textures,
+            PipelineShaders {
+                vertex_shader: VERTEX_SHADER,
+                fragment_shader: FRAGMENT_SHADER,
+                depth_only: false,
+                mesh_layout: &VertexLayout::shader_vertex_data(),
+                debug_view: DebugView::Off,
+                state: RenderState::default(),
+            },
+        )
+    }
+
+    // The scene pipeline with one of the debug visualisations in place of normal shading.
+    pub(super) fn init_debug_view(
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        depth_range: DepthRange,
+        max_textures: u32,
+        debug_view: DebugView,
+    ) -> Result<Pipeline, vk::Result> {
+        Self::init_with_shaders(
+            logical_device,
+            renderpass,
+            depth_range,
+            max_textures,
+            PipelineShaders {
+                vertex_shader: VERTEX_SHADER,
+                fragment_shader: FRAGMENT_SHADER,
+                depth_only: false,
+                mesh_layout: &VertexLayout::shader_vertex_data(),
+                debug_view: debug_view,
+                state: debug_view.render_state(),
+            },
+        )
+    }
+
+    // Draws the scene's depth into the shadow map as the sun sees it, `renderpass` is
+    // `ShadowMap::render_pass`. `state` is the default for the scene pipeline's, or a registered
+    // pipeline's `RenderState::depth_variant`. Only alpha tested variants have a fragment shader.
+    pub(super) fn init_shadow(
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        depth_range: DepthRange,
+        max_textures: u32,
+        state: RenderState,
+    ) -> Result<Pipeline, vk::Result> {
+        Self::init_with_shaders(
+            logical_device,
+            renderpass,
+            depth_range,
+            max_textures,
+            PipelineShaders {
+                vertex_shader: SHADOW_VERTEX_SHADER,
+                fragment_shader: match state.alpha_test {
+                    true => SHADOW_FRAGMENT_SHADER,
+                    false => &[],
+                },
+                depth_only: true,
+                mesh_layout: &VertexLayout::shader_vertex_data(),
+                debug_view: DebugView::Off,
+                state,
+            },
+        )
+    }
+
+    // Checks `vertex_shader` against everything a pipeline built for `mesh_layout` feeds it,
+    // instance attributes included.
+    pub(super) fn validate_vertex_input(
+        mesh_layout: &VertexLayout,
+        vertex_shader: &[u32],
+    ) -> Result<(), VertexLayoutError> {
+        let (attributes, bindings) = Self::vertex_input(mesh_layout);
+        let strides: Vec<(u32, u32)> = bindings
+            .iter()
+            .map(|binding| (binding.binding, binding.stride))
+            .collect();
+        vertex_format::validate(&attributes, &strides, vertex_shader)
+    }
+
+    // The built-in shader goes through the same checks as registered vertex formats, so
+    // `InstanceData`, the attributes above and vertex.glsl disagreeing is an error at startup
+    // rather than garbage on screen.
+    pub(super) fn validate_builtin() -> Result<(), VertexLayoutError> {
+        Self::validate_vertex_input(&VertexLayout::shader_vertex_data(), VERTEX_SHADER)
+    }
+
+    // The attributes and bindings fed to the vertex shader: per-instance data on one binding and
+    // the mesh's own vertex layout on the other.
+    pub(super) fn vertex_input(
+        mesh_layout: &VertexLayout,
+    ) -> (
+        Vec<vk::VertexInputAttributeDescription>,
+        Vec<vk::VertexInputBindingDescription>,
+    ) {
+        let instance_layout = InstanceData::layout();
+        let mut vertex_attrib_descs =
+            instance_layout.attribute_descriptions(VertexBufferBindings::InstanceBuffer as u32);
+        vertex_attrib_descs
+            .extend(mesh_layout.attribute_descriptions(VertexBufferBindings::MeshBuffer as u32));
+
+        let vertex_binding_descs = vec![
+            instance_layout.binding_description(
+                VertexBufferBindings::InstanceBuffer as u32,
+                vk::VertexInputRate::INSTANCE,
+            ),
+            mesh_layout.binding_description(
+                VertexBufferBindings::MeshBuffer as u32,
+                vk::VertexInputRate::VERTEX,
+            ),
+        ];
+        (vertex_attrib_descs, vertex_binding_descs)
+    }
+
+    // `max_textures` sizes the texture array at set 0, see `TextureStore::capacity`. The shaders'
+    // `state` is ignored where the debug view needs something else, e.g. `DebugView::Overdraw`
+    // blending. An empty `fragment_shader` leaves the fragment stage out.
+    pub(super) fn init_with_shaders(
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        depth_range: DepthRange,
+        max_textures: u32,
+        shaders: PipelineShaders,
+    ) -> Result<Pipeline, vk::Result> {
+        let PipelineShaders {
+            vertex_shader,
+            fragment_shader,
+            depth_only,
+            mesh_layout,
+            debug_view,
+            state,
+        } = shaders;
+        let vertex_shader_create_info = vk::ShaderModuleCreateInfo::builder().code(vertex_shader);
+        let vertex_shader_module =
+            unsafe { logical_device.create_shader_module(&vertex_shader_create_info, None)? };
+
+        let fragment_shader_create_info =
+            vk::ShaderModuleCreateInfo::builder().code(fragment_shader);
+        let fragment_shader_module = match fragment_shader.is_empty() {
+            true => vk::ShaderModule::null(),
+            false => {
+                match unsafe {
+                    logical_device.create_shader_module(&fragment_shader_create_info, None)
+                } {
+                    Ok(module) => module,
+                    Err(e) => {
+                        unsafe { logical_device.destroy_shader_module(vertex_shader_module, None) };
+                        return Err(e);
+                    }
+                }
+            }
+        };
+
+        // The modules are only needed to create the pipeline, so they go whether or not it worked.
+        let pipeline = (|| {
+            let main_function_name = std::ffi::CString::new("main").unwrap();
+
+            // Fragment shaders can declare `layout(constant_id=1) const uint DEBUG_VIEW` for the
+            // `DebugView` being drawn, and `layout(constant_id=2) const bool ALPHA_TEST` for
+            // `RenderState::alpha_test`. `layout(constant_id=0) const bool HDR10_OUTPUT` is always
+            // false now that the scene is drawn in linear light, the output pass encodes it for
+            // HDR10.
+            let specialization_data: [u32; 3] =
+                [vk::FALSE, debug_view as u32, state.alpha_test as u32];
+            let specialization_data = unsafe {
+                std::slice::from_raw_parts(
+                    specialization_data.as_ptr() as *const u8,
+                    std::mem::size_of_val(&specialization_data),
+                )
+            };
+            let specialization_entries = [
+                vk::SpecializationMapEntry {
+                    constant_id: 0,
+                    offset: 0,
+                    size: std::mem::size_of::<vk::Bool32>(),
+                },
+                vk::SpecializationMapEntry {
+                    constant_id: 1,
+                    offset: 4,
+                    size: std::mem::size_of::<u32>(),
+                },
+                vk::SpecializationMapEntry {
+                    constant_id: 2,
+                    offset: 8,
+                    size: std::mem::size_of::<vk::Bool32>(),
+                },
+            ];
+            let specialization_info = vk::SpecializationInfo::builder()
+                .map_entries(&specialization_entries)
+                .data(specialization_data);
+
+            let vertex_shader_stage = vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vertex_shader_module)
+                .name(&main_function_name);
+            let fragment_shader_stage = vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(fragment_shader_module)
+                .name(&main_function_name)
+                .specialization_info(&specialization_info);
+            let mut shader_stages = vec![vertex_shader_stage.build()];
+            if !fragment_shader.is_empty() {
+                shader_stages.push(fragment_shader_stage.build());
+            }
+
+            let (vertex_attrib_descs, vertex_binding_descs) = Self::vertex_input(mesh_layout);
+
+            let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder()
+                .vertex_attribute_descriptions(&vertex_attrib_descs)
+                .vertex_binding_descriptions(&vertex_binding_descs);
+            let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+                .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+            let tessellation_state = vk::PipelineTessellationStateCreateInfo::builder();
+
+            let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+                .viewport_count(1)
+                .scissor_count(1);
+            let dynamic_state_info =
+                vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&DYNAMIC_STATES);
+
+            let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+                .line_width(1.0)
+                .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+                .cull_mode(match state.cull {
+                    CullMode::None => vk::CullModeFlags::NONE,
+                    CullMode::Back => vk::CullModeFlags::BACK,
+                    CullMode::Front => vk::CullModeFlags::FRONT,
+                })
+                .polygon_mode(match state.wireframe {
+                    true => vk::PolygonMode::LINE,
+                    false => vk::PolygonMode::FILL,
+                })
+                // Pushed away from the light, which is towards 0 with reverse-Z.
+                .depth_bias_enable(depth_only)
+                .depth_bias_constant_factor(match depth_range {
+                    DepthRange::Standard => 1.0,
+                    DepthRange::ReverseZ => -1.0,
+                })
+                .depth_bias_slope_factor(match depth_range {
+                    DepthRange::Standard => 1.5,
+                    DepthRange::ReverseZ => -1.5,
+                });
+
+            let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
+                .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+            // Overdraw adds up every fragment instead of keeping the nearest.
+            let overdraw = debug_view == DebugView::Overdraw;
+            let blend = match overdraw {
+                true => BlendMode::Additive,
+                false => state.blend,
+            };
+            let (src_colour, dst_colour) = match blend {
+                BlendMode::Opaque | BlendMode::Alpha => (
+                    vk::BlendFactor::SRC_ALPHA,
+                    vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                ),
+                BlendMode::Additive => (vk::BlendFactor::ONE, vk::BlendFactor::ONE),
+            };
+            let colourblend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+                .blend_enable(blend != BlendMode::Opaque)
+                .src_color_blend_factor(src_colour)
+                .dst_color_blend_factor(dst_colour)
+                .color_blend_op(vk::BlendOp::ADD)
+                .src_alpha_blend_factor(vk::BlendFactor::SRC_ALPHA)
+                .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+                .alpha_blend_op(vk::BlendOp::ADD)
+                .color_write_mask(
+                    vk::ColorComponentFlags::R
+                        | vk::ColorComponentFlags::G
+                        | vk::ColorComponentFlags::B
+                        | vk::ColorComponentFlags::A,
+                )
+                .build()];
+            let colourblend_info =
+                vk::PipelineColorBlendStateCreateInfo::builder().attachments(match depth_only {
+                    true => &[],
+                    false => &colourblend_attachments,
+                });
+
+            let depth_stencil_state = PipelineDepthStencilStateCreateInfo::builder()
+                .depth_test_enable(state.depth.test && !overdraw)
+                .depth_write_enable(state.depth.write && !overdraw)
+                .depth_compare_op(depth_range.compare_op());
+
+            // Only the indices the texture store has handed out are written.
+            let descriptor_binding_flags = [vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT
+                | vk::DescriptorBindingFlags::PARTIALLY_BOUND];
+            let mut descriptorset_layout_binding_flags =
+                vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder()
+                    .binding_flags(&descriptor_binding_flags);
+
+            let layout_bindings = [DescriptorSetLayoutBinding::builder()
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .binding(0)
+                .descriptor_count(max_textures)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .build()];
+
+            let descriptor_set_layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
+                .bindings(&layout_bindings)
+                .push_next(&mut descriptorset_layout_binding_flags);
+
+            let descriptor_set_layout_texture = unsafe {
+                logical_device.create_descriptor_set_layout(&descriptor_set_layout_info, None)
+            }?;
+
+            let frame_layout_bindings = [
+                DescriptorSetLayoutBinding::builder()
+                    .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+                    .binding(0)
+                    .descriptor_count(1)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+                    .build(),
+                DescriptorSetLayoutBinding::builder()
+                    .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                    .binding(1)
+                    .descriptor_count(1)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .build(),
+            ];
+            let frame_layout_info =
+                vk::DescriptorSetLayoutCreateInfo::builder().bindings(&frame_layout_bindings);
+            let descriptor_set_layout_frame =
+                unsafe { logical_device.create_descriptor_set_layout(&frame_layout_info, None) }?;
+
+            // Materials at binding 0, lights at binding 1.
+            let material_layout_bindings = [0, 1].map(|binding| {
+                DescriptorSetLayoutBinding::builder()
+                    .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                    .binding(binding)
+                    .descriptor_count(1)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .build()
+            });
+            let material_layout_info =
+                vk::DescriptorSetLayoutCreateInfo::builder().bindings(&material_layout_bindings);
+            let descriptor_set_layout_material = unsafe {
+                logical_device.create_descriptor_set_layout(&material_layout_info, None)
+            }?;
+
+            let descriptor_pool_sizes = [
+                vk::DescriptorPoolSize::builder()
+                    .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .descriptor_count((max_textures + 1) * MAX_FRAMES_IN_FLIGHT as u32)
+                    .build(),
+                vk::DescriptorPoolSize::builder()
+                    .ty(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+                    .descriptor_count(MAX_FRAMES_IN_FLIGHT as u32)
+                    .build(),
+                vk::DescriptorPoolSize::builder()
+                    .ty(vk::DescriptorType::STORAGE_BUFFER)
+                    .descriptor_count(2 * MAX_FRAMES_IN_FLIGHT as u32)
+                    .build(),
+            ];
+
+            let descriptor_pool_info = vk::DescriptorPoolCreateInfo::builder()
+                .pool_sizes(&descriptor_pool_sizes)
+                .max_sets(3 * MAX_FRAMES_IN_FLIGHT as u32);
+
+            let descriptor_pool =
+                unsafe { logical_device.create_descriptor_pool(&descriptor_pool_info, None) }?;
+
+            // One of each set per frame in flight.
+            let desc_layouts_texture = vec![descriptor_set_layout_texture; MAX_FRAMES_IN_FLIGHT];
+            let variable_counts = [max_textures; MAX_FRAMES_IN_FLIGHT];
+            let mut variable = DescriptorSetVariableDescriptorCountAllocateInfo::builder()
+                .descriptor_counts(&variable_counts);
+            let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(descriptor_pool)
+                .set_layouts(&desc_layouts_texture)
+                .push_next(&mut variable);
+
+            let descriptor_sets =
+                unsafe { logical_device.allocate_descriptor_sets(&descriptor_set_allocate_info) }?;
+
+            let desc_layouts_frame = vec![descriptor_set_layout_frame; MAX_FRAMES_IN_FLIGHT];
+            let frame_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(descriptor_pool)
+                .set_layouts(&desc_layouts_frame);
+            let frame_descriptor_sets =
+                unsafe { logical_device.allocate_descriptor_sets(&frame_set_allocate_info) }?;
+
+            let desc_layouts_material = vec![descriptor_set_layout_material; MAX_FRAMES_IN_FLIGHT];
+            let material_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(descriptor_pool)
+                .set_layouts(&desc_layouts_material);
+            let material_descriptor_sets =
+                unsafe { logical_device.allocate_descriptor_sets(&material_set_allocate_info) }?;
+
+            let descriptor_set_layouts = [
+                descriptor_set_layout_texture,
+                descriptor_set_layout_frame,
+                descriptor_set_layout_material,
+            ];
+
+            let pipelinelayout_info =
+                vk::PipelineLayoutCreateInfo::builder().set_layouts(&descriptor_set_layouts);
+
+            let pipelinelayout =
+                unsafe { logical_device.create_pipeline_layout(&pipelinelayout_info, None) }?;
+
+            let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+                .stages(&shader_stages)
+                .vertex_input_state(&vertex_input_info)
+                .input_assembly_state(&input_assembly_info)
+                .tessellation_state(&tessellation_state)
+                .viewport_state(&viewport_info)
+                .dynamic_state(&dynamic_state_info)
+                .rasterization_state(&rasterizer_info)
+                .multisample_state(&multisampler_info)
+                .color_blend_state(&colourblend_info)
+                .depth_stencil_state(&depth_stencil_state)
+                .layout(pipelinelayout)
+                .render_pass(*renderpass)
+                .subpass(0);
+
+            let graphicspipeline = unsafe {
+                logical_device
+                    .create_graphics_pipelines(
+                        vk::PipelineCache::null(),
+                        &[pipeline_info.build()],
+                        None,
+                    )
+                    .map_err(|(_, e)| e)?
+            }[0];
+            debug::set_object_name(logical_device, graphicspipeline, "scene pipeline");
+            Ok(Pipeline {
+                pipeline: graphicspipeline,
+                layout: pipelinelayout,
+                descriptor_pool,
+                descriptor_sets,
+                written: [WrittenSets::default(); MAX_FRAMES_IN_FLIGHT],
+                frame_descriptor_sets,
+                material_descriptor_sets,
+                descriptor_set_layout_texture,
+                descriptor_set_layout_frame,
+                descriptor_set_layout_material,
+            })
+        })();
+        unsafe {
+            logical_device.destroy_shader_module(fragment_shader_module, None);
+            logical_device.destroy_shader_module(vertex_shader_module, None);
+        }
+        pipeline
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+use super::pipeline::Pipeline;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    /// Replaces whatever is behind it.
+    Opaque,
+    /// Mixed with what's behind it by alpha. What the built-in pipeline does, so cut-out textures
+    /// work without sorting.
+    #[default]
+    Alpha,
+    /// Added to what's behind it, for glows and particles.
+    Additive,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CullMode {
+    #[default]
+    None,
+    Back,
+    Front,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DepthState {
+    /// Hidden behind nearer geometry.
+    pub test: bool,
+    /// Hides geometry drawn after it.
+    pub write: bool,
+}
+
+impl Default for DepthState {
+    fn default() -> Self {
+        DepthState {
+            test: true,
+            write: true,
+        }
+    }
+}
+
+/// The fixed function part of a scene pipeline. The default is the built-in pipeline's.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RenderState {
+    pub blend: BlendMode,
+    pub cull: CullMode,
+    pub depth: DepthState,
+    /// Draws triangle edges only, needs the device's `fillModeNonSolid` feature.
+    pub wireframe: bool,
+    /// Throws away fragments with alpha under one half, for cut-outs like leaves and fences. The
+    /// built-in fragment shaders and the pipeline's shadow both respect it, custom fragment
+    /// shaders can read it as `layout(constant_id=2) const bool ALPHA_TEST`.
+    pub alpha_test: bool,
+}
+
+impl RenderState {
+    // The state of the depth-only variant drawing a pipeline's entities into the shadow map, None
+    // when they cast no shadow because they don't write depth, like glows and particles. Only the
+    // culling and alpha test carry over, so the shadow has the same holes as what casts it.
+    pub(super) fn depth_variant(&self) -> Option<RenderState> {
+        self.depth.write.then_some(RenderState {
+            cull: self.cull,
+            alpha_test: self.alpha_test,
+            ..RenderState::default()
+        })
+    }
+}
+
+/// Everything a scene pipeline is built from. Shaders left as None are the built-in ones (or the
+/// ones from `Vulkan::load_scene_shaders`). Custom shaders get the same descriptor sets and
+/// instance attributes as the built-in ones, see `VertexFormat`, and read `ShaderVertexData`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MaterialDesc {
+    pub vertex_shader: Option<Vec<u32>>,
+    pub fragment_shader: Option<Vec<u32>>,
+    pub state: RenderState,
+}
+
+impl MaterialDesc {
+    /// The built-in shaders without lighting, the texture times the material's colour.
+    pub fn unlit() -> MaterialDesc {
+        MaterialDesc {
+            fragment_shader: Some(
+                vk_shader_macros::include_glsl!(
+                    "./shaders/fragment.glsl",
+                    kind: frag,
+                    define: UNLIT
+                )
+                .to_vec(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    pub(super) fn uses_scene_shaders(&self) -> bool {
+        self.vertex_shader.is_none() || self.fragment_shader.is_none()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PipelineHandle {
+    id: Uuid,
+}
+
+impl PipelineHandle {
+    // 16 bits that are the same for every key built with this pipeline, never 0 so the scene
+    // pipeline's keys come first. Two pipelines may share them, which only costs a rebind.
+    pub(super) fn sort_bits(&self) -> u16 {
+        (self.id.as_u128() as u16).max(1)
+    }
+
+    // A handle to no pipeline, for tests that only compare keys.
+    #[cfg(test)]
+    pub(super) fn unregistered() -> PipelineHandle {
+        PipelineHandle { id: Uuid::new_v4() }
+    }
+}
+
+// A registered pipeline with the depth-only variant its entities are drawn into the shadow map
+// with, see `RenderState::depth_variant`.
+struct Registered {
+    desc: MaterialDesc,
+    pipeline: Pipeline,
+    depth: Option<Pipeline>,
+}
+
+// The pipelines registered with `Vulkan::register_pipeline`. Registering an equal `MaterialDesc`
+// twice hands out the same pipeline.
+pub(super) struct PipelineStore {
+    handles: HashMap<MaterialDesc, PipelineHandle>,
+    pipelines: HashMap<PipelineHandle, Registered>,
+}
+
+impl PipelineStore {
+    pub(super) fn new() -> PipelineStore {
+        PipelineStore {
+            handles: HashMap::new(),
+            pipelines: HashMap::new(),
+        }
+    }
+
+    pub(super) fn find(&self, desc: &MaterialDesc) -> Option<PipelineHandle> {
+        self.handles.get(desc).copied()
+    }
+
+    pub(super) fn insert(
+        &mut self,
+        desc: MaterialDesc,
+        pipeline: Pipeline,
+        depth: Option<Pipeline>,
+    ) -> PipelineHandle {
+        let handle = PipelineHandle { id: Uuid::new_v4() };
+        self.handles.insert(desc.clone(), handle);
+        self.pipelines.insert(
+            handle,
+            Registered {
+                desc,
+                pipeline,
+                depth,
+            },
+        );
+        handle
+    }
+
+    // Swaps in a rebuilt pipeline, returning the old one to be destroyed. The depth-only variant
+    // doesn't use the pipeline's shaders and is kept.
+    pub(super) fn replace(
+        &mut self,
+        handle: PipelineHandle,
+        pipeline: Pipeline,
+    ) -> Option<Pipeline> {
+        self.pipelines
+            .get_mut(&handle)
+            .map(|registered| std::mem::replace(&mut registered.pipeline, pipeline))
+    }
+
+    // The pipeline and its depth-only variant, to be destroyed.
+    pub(super) fn remove(
+        &mut self,
+        handle: &PipelineHandle,
+    ) -> Option<(Pipeline, Option<Pipeline>)> {
+        let registered = self.pipelines.remove(handle)?;
+        self.handles.remove(&registered.desc);
+        Some((registered.pipeline, registered.depth))
+    }
+
+    pub(super) fn get(&self, handle: &PipelineHandle) -> Option<&Pipeline> {
+        self.pipelines
+            .get(handle)
+            .map(|registered| &registered.pipeline)
+    }
+
+    // What the pipeline's entities are drawn into the shadow map with, None when they cast no
+    // shadow or the pipeline isn't registered.
+    pub(super) fn depth_variant(&self, handle: &PipelineHandle) -> Option<&Pipeline> {
+        self.pipelines.get(handle)?.depth.as_ref()
+    }
+
+    pub(super) fn descs(&self) -> impl Iterator<Item = (PipelineHandle, &MaterialDesc)> {
+        self.pipelines
+            .iter()
+            .map(|(handle, registered)| (*handle, &registered.desc))
+    }
+
+    // Depth-only variants included, for writing their descriptor sets.
+    pub(super) fn pipelines(&self) -> impl Iterator<Item = &Pipeline> {
+        self.pipelines
+            .values()
+            .flat_map(|registered| std::iter::once(&registered.pipeline).chain(&registered.depth))
+    }
+
+    pub(super) fn pipelines_mut(&mut self) -> impl Iterator<Item = &mut Pipeline> {
+        self.pipelines.values_mut().flat_map(|registered| {
+            std::iter::once(&mut registered.pipeline).chain(&mut registered.depth)
+        })
+    }
+
+    pub(super) fn cleanup(&mut self, logical_device: &ash::Device) {
+        for pipeline in self.pipelines() {
+            pipeline.cleanup(logical_device);
+        }
+        self.pipelines.clear();
+        self.handles.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn depth_variants_keep_culling_and_alpha_test_only() {
+        let state = RenderState {
+            blend: BlendMode::Additive,
+            cull: CullMode::Back,
+            depth: DepthState {
+                test: false,
+                write: true,
+            },
+            wireframe: true,
+            alpha_test: true,
+        };
+        assert_eq!(
+            state.depth_variant(),
+            Some(RenderState {
+                cull: CullMode::Back,
+                alpha_test: true,
+                ..RenderState::default()
+            })
+        );
+        assert_eq!(
+            RenderState::default().depth_variant(),
+            Some(RenderState::default())
+        );
+    }
+
+    #[test]
+    fn pipelines_not_writing_depth_cast_no_shadow() {
+        let state = RenderState {
+            blend: BlendMode::Additive,
+            depth: DepthState {
+                test: true,
+                write: false,
+            },
+            ..RenderState::default()
+        };
+        assert_eq!(state.depth_variant(), None);
+    }
+}
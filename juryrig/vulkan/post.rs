@@ -0,0 +1,473 @@
+use std::collections::HashMap;
+
+use ash::{vk, Device};
+use gpu_allocator::vulkan::Allocator;
+use uuid::Uuid;
+
+use super::{
+    debug,
+    pipeline::{self, DYNAMIC_STATES},
+    render_graph::{AttachmentId, CompiledGraph, PassDesc, PassId, RenderGraph, RenderGraphError},
+    swapchain::SCENE_FORMAT,
+};
+
+/// A fullscreen pass over the scene, run after the main pass and before tonemapping so it sees
+/// linear HDR colour. See `Vulkan::set_post_effects`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PostEffect {
+    /// Fast approximate antialiasing, smooths the stair steps along edges.
+    Fxaa,
+    /// Darkens towards the corners, by `strength` (0-1) at the very corners, starting `radius` of
+    /// the way out from the centre.
+    Vignette { strength: f32, radius: f32 },
+    /// Raises the scene to 1 / gamma, above 1 lifts the mid tones. The output is already gamma
+    /// encoded for the display, this is for the look.
+    Gamma(f32),
+    /// Adds a glow of `intensity` around whatever is brighter than `threshold`, spread over
+    /// `radius` pixels.
+    Bloom {
+        threshold: f32,
+        intensity: f32,
+        radius: f32,
+    },
+    /// An effect from `Vulkan::register_post_effect`. Its fragment shader reads the uv at
+    /// location 0 and the previous target as a sampler2D at set 0, binding 0, and gets
+    /// `push_constant` { vec4 params; vec2 texel_size; } with `params` as given here.
+    Custom {
+        effect: PostEffectHandle,
+        params: [f32; 4],
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PostEffectHandle {
+    id: Uuid,
+}
+
+#[repr(C)]
+struct PostPushConstants {
+    params: [f32; 4],
+    texel_size: [f32; 2],
+}
+
+// Fxaa, Vignette, Gamma and Bloom, in that order.
+const BUILTIN_EFFECTS: usize = 4;
+
+// Runs the post effects between the main and output passes. They alternate between drawing into
+// a scratch target while sampling the scene and the other way round, so every effect reads what
+// the one before it drew.
+pub(super) struct PostProcess {
+    graph: CompiledGraph,
+    scene: AttachmentId,
+    scratch: AttachmentId,
+    // Draws into the scratch target, then back into the scene.
+    passes: [PassId; 2],
+    framebuffers: [vk::Framebuffer; 2],
+    layout: vk::PipelineLayout,
+    set_layout: vk::DescriptorSetLayout,
+    pool: vk::DescriptorPool,
+    sampler: vk::Sampler,
+    // Sampling the scene, then the scratch target, matching `passes`.
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    builtin: [vk::Pipeline; BUILTIN_EFFECTS],
+    custom: HashMap<PostEffectHandle, vk::Pipeline>,
+    effects: Vec<PostEffect>,
+}
+
+impl PostProcess {
+    // `scene_view` is the swapchain's scene buffer, drawn by the main pass.
+    pub(super) fn new(
+        logical_device: &Device,
+        allocator: &mut Allocator,
+        extent: vk::Extent2D,
+        scene_view: vk::ImageView,
+    ) -> Result<PostProcess, vk::Result> {
+        let mut graph = RenderGraph::new();
+        let scene = graph.import(
+            "scene",
+            SCENE_FORMAT,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+        let scratch = graph.transient("post scratch", SCENE_FORMAT, 1.0);
+        let passes = [
+            graph.add_pass(
+                PassDesc::new("post to scratch")
+                    .colour(scratch)
+                    .sample(scene),
+            ),
+            graph.add_pass(PassDesc::new("post to scene").colour(scene).sample(scratch)),
+        ];
+        let graph = graph
+            .compile(logical_device, allocator, extent)
+            .map_err(|e| match e {
+                RenderGraphError::VKErr(e) => e,
+                // The graph above is always valid.
+                e => unreachable!("{:?}", e),
+            })?;
+
+        let bindings = [vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build()];
+        let set_layout_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+        let set_layout =
+            unsafe { logical_device.create_descriptor_set_layout(&set_layout_info, None) }?;
+
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .size(std::mem::size_of::<PostPushConstants>() as u32)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build()];
+        let set_layouts = [set_layout];
+        let pipelinelayout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let layout = unsafe { logical_device.create_pipeline_layout(&pipelinelayout_info, None) }?;
+
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: 2,
+        }];
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(2)
+            .pool_sizes(&pool_sizes);
+        let pool = unsafe { logical_device.create_descriptor_pool(&pool_info, None) }?;
+        let set_layouts = [set_layout; 2];
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(pool)
+            .set_layouts(&set_layouts);
+        let descriptor_sets = unsafe { logical_device.allocate_descriptor_sets(&allocate_info) }?;
+
+        // Linear so FXAA and bloom can sample between texels.
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .max_lod(0.0);
+        let sampler = unsafe { logical_device.create_sampler(&sampler_info, None) }?;
+
+        // Every pass has one colour attachment of the same format, so they're all compatible
+        // with the first.
+        let render_pass = graph.render_pass(passes[0]);
+        let builtin = [
+            vk_shader_macros::include_glsl!("./shaders/post_fxaa.glsl", kind: frag),
+            vk_shader_macros::include_glsl!("./shaders/post_vignette.glsl", kind: frag),
+            vk_shader_macros::include_glsl!("./shaders/post_gamma.glsl", kind: frag),
+            vk_shader_macros::include_glsl!("./shaders/post_bloom.glsl", kind: frag),
+        ]
+        .map(|code| create_pipeline(logical_device, layout, render_pass, code));
+        let builtin = [builtin[0]?, builtin[1]?, builtin[2]?, builtin[3]?];
+
+        let mut post = PostProcess {
+            graph,
+            scene,
+            scratch,
+            passes,
+            framebuffers: [vk::Framebuffer::null(); 2],
+            layout,
+            set_layout,
+            pool,
+            sampler,
+            descriptor_sets,
+            builtin,
+            custom: HashMap::new(),
+            effects: vec![],
+        };
+        post.point_at(logical_device, scene_view)?;
+        Ok(post)
+    }
+
+    // Framebuffers and descriptor sets for the current scene buffer and scratch target.
+    fn point_at(
+        &mut self,
+        logical_device: &Device,
+        scene_view: vk::ImageView,
+    ) -> Result<(), vk::Result> {
+        let imported = [(self.scene, scene_view)];
+        self.framebuffers = [
+            self.graph
+                .framebuffer(logical_device, self.passes[0], &imported)?,
+            self.graph
+                .framebuffer(logical_device, self.passes[1], &imported)?,
+        ];
+        let targets = self.targets(scene_view);
+        let image_infos: Vec<[vk::DescriptorImageInfo; 1]> = targets
+            .iter()
+            .map(|view| {
+                [vk::DescriptorImageInfo {
+                    sampler: self.sampler,
+                    image_view: *view,
+                    image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                }]
+            })
+            .collect();
+        let writes: Vec<vk::WriteDescriptorSet> = self
+            .descriptor_sets
+            .iter()
+            .zip(&image_infos)
+            .map(|(set, image_info)| {
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(*set)
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(image_info)
+                    .build()
+            })
+            .collect();
+        unsafe { logical_device.update_descriptor_sets(&writes, &[]) };
+        Ok(())
+    }
+
+    // Recreates the scratch target after the swapchain, and so the scene buffer, was recreated.
+    // Nothing may still be executing.
+    pub(super) fn resize(
+        &mut self,
+        logical_device: &Device,
+        allocator: &mut Allocator,
+        extent: vk::Extent2D,
+        scene_view: vk::ImageView,
+    ) -> Result<(), vk::Result> {
+        self.graph.resize(logical_device, allocator, extent)?;
+        self.point_at(logical_device, scene_view)
+    }
+
+    // The scene buffer and the scratch target, what `record`'s result indexes.
+    pub(super) fn targets(&self, scene_view: vk::ImageView) -> [vk::ImageView; 2] {
+        [
+            scene_view,
+            self.graph
+                .view(self.scratch)
+                .expect("the scratch target is transient"),
+        ]
+    }
+
+    pub(super) fn register(
+        &mut self,
+        logical_device: &Device,
+        fragment_shader: &[u32],
+    ) -> Result<PostEffectHandle, vk::Result> {
+        let pipeline = create_pipeline(
+            logical_device,
+            self.layout,
+            self.graph.render_pass(self.passes[0]),
+            fragment_shader,
+        )?;
+        let handle = PostEffectHandle { id: Uuid::new_v4() };
+        self.custom.insert(handle, pipeline);
+        Ok(handle)
+    }
+
+    pub(super) fn is_registered(&self, handle: &PostEffectHandle) -> bool {
+        self.custom.contains_key(handle)
+    }
+
+    // Destroys the effect's pipeline, no frame using it may still be executing.
+    pub(super) fn unregister(&mut self, logical_device: &Device, handle: &PostEffectHandle) {
+        if let Some(pipeline) = self.custom.remove(handle) {
+            unsafe { logical_device.destroy_pipeline(pipeline, None) };
+        }
+    }
+
+    pub(super) fn set_effects(&mut self, effects: &[PostEffect]) {
+        self.effects = effects.to_vec();
+    }
+
+    pub(super) fn effects(&self) -> &[PostEffect] {
+        &self.effects
+    }
+
+    fn pipeline(&self, effect: &PostEffect) -> Option<(vk::Pipeline, [f32; 4])> {
+        match *effect {
+            PostEffect::Fxaa => Some((self.builtin[0], [0.0; 4])),
+            PostEffect::Vignette { strength, radius } => {
+                Some((self.builtin[1], [strength, radius, 0.0, 0.0]))
+            }
+            PostEffect::Gamma(gamma) => Some((self.builtin[2], [gamma, 0.0, 0.0, 0.0])),
+            PostEffect::Bloom {
+                threshold,
+                intensity,
+                radius,
+            } => Some((self.builtin[3], [threshold, intensity, radius, 0.0])),
+            // Unregistered effects are skipped.
+            PostEffect::Custom { effect, params } => {
+                self.custom.get(&effect).map(|pipeline| (*pipeline, params))
+            }
+        }
+    }
+
+    // Records the effects in order between the main and output passes. Returns the index into
+    // `targets` of the one holding the result.
+    pub(super) fn record(
+        &self,
+        logical_device: &Device,
+        command_buffer: vk::CommandBuffer,
+    ) -> usize {
+        let mut source = 0;
+        for effect in &self.effects {
+            let Some((pipeline, params)) = self.pipeline(effect) else {
+                continue;
+            };
+            let pass = self.passes[source];
+            let extent = self.graph.pass_extent(pass);
+            let push_constants = PostPushConstants {
+                params,
+                texel_size: [1.0 / extent.width as f32, 1.0 / extent.height as f32],
+            };
+            // Every pixel is drawn over, the clear is never seen.
+            self.graph.begin_pass_in(
+                logical_device,
+                command_buffer,
+                pass,
+                self.framebuffers[source],
+                &[vk::ClearValue::default()],
+            );
+            pipeline::set_viewport_and_scissor(logical_device, command_buffer, extent);
+            unsafe {
+                logical_device.cmd_bind_pipeline(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline,
+                );
+                logical_device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.layout,
+                    0,
+                    &[self.descriptor_sets[source]],
+                    &[],
+                );
+                logical_device.cmd_push_constants(
+                    command_buffer,
+                    self.layout,
+                    vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    std::slice::from_raw_parts(
+                        &push_constants as *const PostPushConstants as *const u8,
+                        std::mem::size_of::<PostPushConstants>(),
+                    ),
+                );
+                logical_device.cmd_draw(command_buffer, 3, 1, 0, 0);
+            }
+            self.graph.end_pass(logical_device, command_buffer);
+            source = 1 - source;
+        }
+        source
+    }
+
+    pub(super) fn cleanup(&mut self, logical_device: &Device, allocator: &mut Allocator) {
+        unsafe {
+            for pipeline in self.builtin.iter().chain(self.custom.values()) {
+                logical_device.destroy_pipeline(*pipeline, None);
+            }
+            logical_device.destroy_sampler(self.sampler, None);
+            logical_device.destroy_descriptor_pool(self.pool, None);
+            logical_device.destroy_pipeline_layout(self.layout, None);
+            logical_device.destroy_descriptor_set_layout(self.set_layout, None);
+        }
+        self.graph.cleanup(logical_device, allocator);
+    }
+}
+
+// A fullscreen triangle running `fragment_shader` over `render_pass`'s one colour attachment.
+fn create_pipeline(
+    logical_device: &Device,
+    layout: vk::PipelineLayout,
+    render_pass: vk::RenderPass,
+    fragment_shader: &[u32],
+) -> Result<vk::Pipeline, vk::Result> {
+    let vertex_shader_create_info = vk::ShaderModuleCreateInfo::builder()
+        .code(vk_shader_macros::include_glsl!("./shaders/fullscreen.glsl", kind: vert));
+    let vertex_shader_module =
+        unsafe { logical_device.create_shader_module(&vertex_shader_create_info, None)? };
+
+    let fragment_shader_create_info = vk::ShaderModuleCreateInfo::builder().code(fragment_shader);
+    let fragment_shader_module =
+        match unsafe { logical_device.create_shader_module(&fragment_shader_create_info, None) } {
+            Ok(module) => module,
+            Err(e) => {
+                unsafe { logical_device.destroy_shader_module(vertex_shader_module, None) };
+                return Err(e);
+            }
+        };
+
+    let main_function_name = std::ffi::CString::new("main").unwrap();
+
+    let shader_stages = [
+        vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .module(vertex_shader_module)
+            .name(&main_function_name)
+            .build(),
+        vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::FRAGMENT)
+            .module(fragment_shader_module)
+            .name(&main_function_name)
+            .build(),
+    ];
+
+    // The fullscreen triangle is generated from gl_VertexIndex.
+    let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder();
+    let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+    let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+    let dynamic_state_info =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&DYNAMIC_STATES);
+
+    let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .polygon_mode(vk::PolygonMode::FILL);
+
+    let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+    let colourblend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+        .blend_enable(false)
+        .color_write_mask(
+            vk::ColorComponentFlags::R
+                | vk::ColorComponentFlags::G
+                | vk::ColorComponentFlags::B
+                | vk::ColorComponentFlags::A,
+        )
+        .build()];
+    let colourblend_info =
+        vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colourblend_attachments);
+
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(false)
+        .depth_write_enable(false);
+
+    let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&shader_stages)
+        .vertex_input_state(&vertex_input_info)
+        .input_assembly_state(&input_assembly_info)
+        .viewport_state(&viewport_info)
+        .dynamic_state(&dynamic_state_info)
+        .rasterization_state(&rasterizer_info)
+        .multisample_state(&multisampler_info)
+        .color_blend_state(&colourblend_info)
+        .depth_stencil_state(&depth_stencil_state)
+        .layout(layout)
+        .render_pass(render_pass)
+        .subpass(0);
+
+    let pipeline = unsafe {
+        logical_device
+            .create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info.build()], None)
+            .map_err(|(_, e)| e)
+    };
+    unsafe {
+        logical_device.destroy_shader_module(fragment_shader_module, None);
+        logical_device.destroy_shader_module(vertex_shader_module, None);
+    }
+    let pipeline = pipeline?[0];
+    debug::set_object_name(logical_device, pipeline, "post effect pipeline");
+    Ok(pipeline)
+}
@@ -0,0 +1,165 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use ash::{vk, Device, Instance};
+
+// How many frames of timings are kept.
+const HISTORY: usize = 64;
+
+/// When a frame was presented and when it reached the screen, for syncing audio or video to what
+/// the player actually sees.
+#[derive(Clone, Copy, Debug)]
+pub struct PresentTiming {
+    pub frame: u64,
+    /// When the renderer handed the frame to the presentation engine.
+    pub presented: Instant,
+    /// When the frame is expected to light up the display. Starts as an estimate and is replaced
+    /// by the driver's figure a few frames later when it reports one.
+    pub photon: Instant,
+    /// Whether `photon` came from the driver.
+    pub measured: bool,
+}
+
+struct Record {
+    timing: PresentTiming,
+    // CLOCK_MONOTONIC when the frame was presented, the clock the driver reports in.
+    monotonic: Option<u64>,
+}
+
+// Keeps per-present timestamps, refined with VK_GOOGLE_display_timing when the device has it.
+pub(super) struct PresentTimer {
+    display_timing: Option<vk::GoogleDisplayTimingFn>,
+    history: VecDeque<Record>,
+    // The last measured present to photon time.
+    measured_latency: Option<Duration>,
+}
+
+impl PresentTimer {
+    pub(super) fn new(
+        instance: &Instance,
+        logical_device: &Device,
+        display_timing: bool,
+    ) -> PresentTimer {
+        let display_timing = display_timing.then(|| {
+            vk::GoogleDisplayTimingFn::load(|name| unsafe {
+                std::mem::transmute(
+                    instance.get_device_proc_addr(logical_device.handle(), name.as_ptr()),
+                )
+            })
+        });
+        PresentTimer {
+            display_timing,
+            history: VecDeque::with_capacity(HISTORY),
+            measured_latency: None,
+        }
+    }
+
+    // Chained onto the present of `frame` so the driver reports on it.
+    pub(super) fn present_time(&self, frame: u64) -> Option<vk::PresentTimeGOOGLE> {
+        self.display_timing.as_ref().map(|_| vk::PresentTimeGOOGLE {
+            present_id: frame as u32,
+            desired_present_time: 0,
+        })
+    }
+
+    // Records that `frame` was just presented. Until the driver says otherwise the frame is
+    // assumed to show up after the last measured latency, or after one refresh (two with FIFO,
+    // where a frame usually waits behind another) if nothing has been measured.
+    pub(super) fn presented(
+        &mut self,
+        frame: u64,
+        refresh_interval: Duration,
+        present_mode: vk::PresentModeKHR,
+    ) {
+        let presented = Instant::now();
+        let latency = self.measured_latency.unwrap_or(match present_mode {
+            vk::PresentModeKHR::FIFO | vk::PresentModeKHR::FIFO_RELAXED => refresh_interval * 2,
+            _ => refresh_interval,
+        });
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(Record {
+            timing: PresentTiming {
+                frame,
+                presented,
+                photon: presented + latency,
+                measured: false,
+            },
+            monotonic: self.display_timing.as_ref().and_then(|_| monotonic_now()),
+        });
+    }
+
+    // Picks up whatever the driver has reported since the last call.
+    pub(super) fn poll(&mut self, logical_device: &Device, swapchain: vk::SwapchainKHR) {
+        let display_timing = match &self.display_timing {
+            Some(display_timing) => display_timing,
+            None => return,
+        };
+        let mut count = 0;
+        let result = unsafe {
+            (display_timing.get_past_presentation_timing_google)(
+                logical_device.handle(),
+                swapchain,
+                &mut count,
+                std::ptr::null_mut(),
+            )
+        };
+        if result != vk::Result::SUCCESS || count == 0 {
+            return;
+        }
+        let mut past = vec![vk::PastPresentationTimingGOOGLE::default(); count as usize];
+        let result = unsafe {
+            (display_timing.get_past_presentation_timing_google)(
+                logical_device.handle(),
+                swapchain,
+                &mut count,
+                past.as_mut_ptr(),
+            )
+        };
+        if result != vk::Result::SUCCESS && result != vk::Result::INCOMPLETE {
+            return;
+        }
+        past.truncate(count as usize);
+
+        for past in past {
+            let record = self
+                .history
+                .iter_mut()
+                .find(|record| record.timing.frame as u32 == past.present_id);
+            if let Some(record) = record {
+                if let Some(monotonic) = record.monotonic {
+                    let latency =
+                        Duration::from_nanos(past.actual_present_time.saturating_sub(monotonic));
+                    record.timing.photon = record.timing.presented + latency;
+                    record.timing.measured = true;
+                    self.measured_latency = Some(latency);
+                }
+            }
+        }
+    }
+
+    pub(super) fn timings(&self) -> impl Iterator<Item = &PresentTiming> {
+        self.history.iter().map(|record| &record.timing)
+    }
+}
+
+// The driver reports present times against CLOCK_MONOTONIC, which Instant doesn't expose.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn monotonic_now() -> Option<u64> {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    match unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut time) } {
+        0 => Some(time.tv_sec as u64 * 1_000_000_000 + time.tv_nsec as u64),
+        _ => None,
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn monotonic_now() -> Option<u64> {
+    None
+}
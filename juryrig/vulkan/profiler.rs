@@ -0,0 +1,289 @@
+use std::time::Duration;
+
+use ash::{vk, Device};
+
+use crate::alloc_telemetry::AllocationStats;
+
+use super::assets::UploadStats;
+
+// How many passes a frame can time, later ones are folded into the last.
+const MAX_PASSES: usize = 16;
+
+// The counters `PipelineStatistics` is read from, in the order Vulkan writes them.
+const PIPELINE_STATISTICS: vk::QueryPipelineStatisticFlags =
+    vk::QueryPipelineStatisticFlags::from_raw(
+        vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_VERTICES.as_raw()
+            | vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_PRIMITIVES.as_raw()
+            | vk::QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS.as_raw()
+            | vk::QueryPipelineStatisticFlags::CLIPPING_PRIMITIVES.as_raw()
+            | vk::QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS.as_raw(),
+    );
+const PIPELINE_STATISTICS_COUNT: usize = 5;
+
+/// What the GPU did for one frame, from `Vulkan::frame_stats`.
+#[derive(Clone, Debug, Default)]
+pub struct FrameStats {
+    /// The frame these were recorded in, a frame or two behind the one being drawn.
+    pub frame: u64,
+    /// Each timed pass in the order it ran: "shadows", "render targets", "main", "post" and
+    /// "output".
+    pub passes: Vec<(&'static str, Duration)>,
+    /// Only with `RendererConfig::pipeline_statistics` on a device that can count them.
+    pub pipeline: Option<PipelineStatistics>,
+    /// The heap allocations made on the CPU between the frame before this one and this one, see
+    /// `Vulkan::allocation_stats`.
+    pub allocations: AllocationStats,
+    /// What each shadow-casting light drew into its shadow map, empty while shadows are off.
+    /// Only the sun casts shadows for now.
+    pub shadows: Vec<ShadowDraws>,
+    /// The streamed textures and meshes uploaded before the frame was recorded, see
+    /// `RendererConfig::uploads`.
+    pub uploads: UploadStats,
+}
+
+impl FrameStats {
+    /// All the passes together, roughly how long the frame took on the GPU.
+    pub fn gpu_time(&self) -> Duration {
+        self.passes.iter().map(|(_, time)| *time).sum()
+    }
+}
+
+/// One light's shadow map for a frame, see `FrameStats::shadows`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ShadowDraws {
+    /// Which light, "sun" for the directional light.
+    pub light: &'static str,
+    pub draw_calls: u32,
+    /// Instances drawn into the map.
+    pub instances: u32,
+    /// Entity instances left out because they were outside the light's view.
+    pub culled: u32,
+}
+
+/// Counted over the whole frame, every pass included.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PipelineStatistics {
+    pub input_vertices: u64,
+    pub input_primitives: u64,
+    pub vertex_shader_invocations: u64,
+    /// Primitives that made it past clipping, roughly the triangles rasterised.
+    pub clipping_primitives: u64,
+    pub fragment_shader_invocations: u64,
+}
+
+// Times the passes of a frame with a timestamp between each of them, and optionally counts what
+// the pipeline did over the whole frame. Each slot is only read back right before it is recorded
+// again, by which point the GPU has usually finished with it.
+pub(super) struct GpuProfiler {
+    timestamps: vk::QueryPool,
+    statistics: Option<vk::QueryPool>,
+    // Nanoseconds per timestamp tick.
+    timestamp_period: f32,
+    // The frame each slot was last recorded in, and the passes it timed.
+    frames: Vec<Option<u64>>,
+    passes: Vec<Vec<&'static str>>,
+    allocations: Vec<AllocationStats>,
+    shadows: Vec<Vec<ShadowDraws>>,
+    uploads: Vec<UploadStats>,
+}
+
+impl GpuProfiler {
+    // Returns None when the device can't write timestamps on graphics queues.
+    // `pipeline_statistics` is whether the device has the pipelineStatisticsQuery feature
+    // enabled and the application asked for it.
+    pub(super) fn new(
+        logical_device: &Device,
+        limits: &vk::PhysicalDeviceLimits,
+        slots: usize,
+        pipeline_statistics: bool,
+    ) -> Result<Option<GpuProfiler>, vk::Result> {
+        if limits.timestamp_compute_and_graphics == vk::FALSE {
+            return Ok(None);
+        }
+        let query_pool_info = vk::QueryPoolCreateInfo::builder()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count((slots * (MAX_PASSES + 1)) as u32);
+        let timestamps = unsafe { logical_device.create_query_pool(&query_pool_info, None) }?;
+        let statistics = if pipeline_statistics {
+            let query_pool_info = vk::QueryPoolCreateInfo::builder()
+                .query_type(vk::QueryType::PIPELINE_STATISTICS)
+                .pipeline_statistics(PIPELINE_STATISTICS)
+                .query_count(slots as u32);
+            match unsafe { logical_device.create_query_pool(&query_pool_info, None) } {
+                Ok(pool) => Some(pool),
+                Err(e) => {
+                    unsafe { logical_device.destroy_query_pool(timestamps, None) };
+                    return Err(e);
+                }
+            }
+        } else {
+            None
+        };
+        Ok(Some(GpuProfiler {
+            timestamps,
+            statistics,
+            timestamp_period: limits.timestamp_period,
+            frames: vec![None; slots],
+            passes: vec![vec![]; slots],
+            allocations: vec![AllocationStats::default(); slots],
+            shadows: vec![vec![]; slots],
+            uploads: vec![UploadStats::default(); slots],
+        }))
+    }
+
+    fn first_query(slot: usize) -> u32 {
+        (slot * (MAX_PASSES + 1)) as u32
+    }
+
+    // Must be recorded outside any render pass, before the frame's first pass.
+    pub(super) fn begin(
+        &mut self,
+        logical_device: &Device,
+        command_buffer: vk::CommandBuffer,
+        slot: usize,
+        frame: u64,
+        allocations: &AllocationStats,
+        uploads: &UploadStats,
+    ) {
+        unsafe {
+            logical_device.cmd_reset_query_pool(
+                command_buffer,
+                self.timestamps,
+                Self::first_query(slot),
+                MAX_PASSES as u32 + 1,
+            );
+            if let Some(statistics) = self.statistics {
+                logical_device.cmd_reset_query_pool(command_buffer, statistics, slot as u32, 1);
+                logical_device.cmd_begin_query(
+                    command_buffer,
+                    statistics,
+                    slot as u32,
+                    vk::QueryControlFlags::empty(),
+                );
+            }
+        }
+        self.frames[slot] = Some(frame);
+        self.passes[slot].clear();
+        self.allocations[slot] = allocations.clone();
+        self.shadows[slot].clear();
+        self.uploads[slot] = *uploads;
+    }
+
+    // What a light drew into its shadow map this frame.
+    pub(super) fn shadows(&mut self, slot: usize, draws: ShadowDraws) {
+        self.shadows[slot].push(draws);
+    }
+
+    // Ends the pass before, if any, and starts timing `name`. Recorded outside render passes.
+    pub(super) fn pass(
+        &mut self,
+        logical_device: &Device,
+        command_buffer: vk::CommandBuffer,
+        slot: usize,
+        name: &'static str,
+    ) {
+        let passes = &mut self.passes[slot];
+        if passes.len() == MAX_PASSES {
+            return;
+        }
+        let stage = if passes.is_empty() {
+            vk::PipelineStageFlags::TOP_OF_PIPE
+        } else {
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE
+        };
+        unsafe {
+            logical_device.cmd_write_timestamp(
+                command_buffer,
+                stage,
+                self.timestamps,
+                Self::first_query(slot) + passes.len() as u32,
+            );
+        }
+        passes.push(name);
+    }
+
+    // Ends the last pass, outside any render pass.
+    pub(super) fn end(
+        &self,
+        logical_device: &Device,
+        command_buffer: vk::CommandBuffer,
+        slot: usize,
+    ) {
+        unsafe {
+            logical_device.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                self.timestamps,
+                Self::first_query(slot) + self.passes[slot].len() as u32,
+            );
+            if let Some(statistics) = self.statistics {
+                logical_device.cmd_end_query(command_buffer, statistics, slot as u32);
+            }
+        }
+    }
+
+    // What the slot last recorded, None if it hasn't finished or never ran.
+    pub(super) fn read(&self, logical_device: &Device, slot: usize) -> Option<FrameStats> {
+        let frame = self.frames[slot]?;
+        let names = &self.passes[slot];
+        let mut timestamps = vec![0u64; names.len() + 1];
+        unsafe {
+            logical_device.get_query_pool_results(
+                self.timestamps,
+                Self::first_query(slot),
+                timestamps.len() as u32,
+                &mut timestamps,
+                vk::QueryResultFlags::TYPE_64,
+            )
+        }
+        .ok()?;
+        let passes = names
+            .iter()
+            .zip(timestamps.windows(2))
+            .map(|(name, pair)| {
+                let ticks = pair[1].saturating_sub(pair[0]);
+                let nanos = ticks as f64 * self.timestamp_period as f64;
+                (*name, Duration::from_nanos(nanos as u64))
+            })
+            .collect();
+        let pipeline = self.statistics.and_then(|statistics| {
+            let mut counters = [[0u64; PIPELINE_STATISTICS_COUNT]];
+            unsafe {
+                logical_device.get_query_pool_results(
+                    statistics,
+                    slot as u32,
+                    1,
+                    &mut counters,
+                    vk::QueryResultFlags::TYPE_64,
+                )
+            }
+            .ok()?;
+            let [input_vertices, input_primitives, vertex_shader_invocations, clipping_primitives, fragment_shader_invocations] =
+                counters[0];
+            Some(PipelineStatistics {
+                input_vertices,
+                input_primitives,
+                vertex_shader_invocations,
+                clipping_primitives,
+                fragment_shader_invocations,
+            })
+        });
+        Some(FrameStats {
+            frame,
+            passes,
+            pipeline,
+            allocations: self.allocations[slot].clone(),
+            shadows: self.shadows[slot].clone(),
+            uploads: self.uploads[slot],
+        })
+    }
+
+    pub(super) fn cleanup(&self, logical_device: &Device) {
+        unsafe {
+            logical_device.destroy_query_pool(self.timestamps, None);
+            if let Some(statistics) = self.statistics {
+                logical_device.destroy_query_pool(statistics, None);
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+use crate::{
+    jr_image::{HDRImage, RGBAImage, RGBAPixel},
+    jr_texture::CompressedImage,
+};
+
+use super::{bounds::MeshBounds, sampler::SamplerDesc, texture::TextureRegion};
+
+// What a texture was registered from, enough to register it again.
+pub(super) enum TextureSource {
+    Image(RGBAImage, SamplerDesc),
+    Compressed(CompressedImage, SamplerDesc),
+    Hdr(HDRImage),
+    Cubemap(Box<[RGBAImage; 6]>),
+    Equirectangular(HDRImage),
+}
+
+// CPU side copies of the textures and meshes registered while
+// `RendererConfig::device_lost_recovery` is on, for `Vulkan::recover` to upload again on a new
+// device. A copy goes when its resource is collected or unregistered.
+#[derive(Default)]
+pub(super) struct KeptResources {
+    textures: HashMap<Uuid, TextureSource>,
+    // Vertex data as bytes, uploaded again as is whatever the vertex format, with the bounds
+    // that can't be worked out from bytes.
+    meshes: HashMap<Uuid, (Vec<u8>, Vec<u32>, MeshBounds)>,
+}
+
+impl KeptResources {
+    pub(super) fn keep_texture(&mut self, id: Uuid, source: TextureSource) {
+        self.textures.insert(id, source);
+    }
+
+    pub(super) fn keep_mesh<V>(
+        &mut self,
+        id: Uuid,
+        vertex_data: &[V],
+        index_data: &[u32],
+        bounds: MeshBounds,
+    ) {
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                vertex_data.as_ptr() as *const u8,
+                std::mem::size_of_val(vertex_data),
+            )
+        };
+        self.meshes
+            .insert(id, (bytes.to_vec(), index_data.to_vec(), bounds));
+    }
+
+    // Applies an `update_texture` to the copy too, so recovering doesn't bring back old pixels.
+    pub(super) fn update_texture(&mut self, id: Uuid, region: TextureRegion, pixels: &[RGBAPixel]) {
+        let Some(TextureSource::Image(image, _)) = self.textures.get_mut(&id) else {
+            return;
+        };
+        for (row, source) in pixels
+            .chunks(region.width as usize)
+            .take(region.height as usize)
+            .enumerate()
+        {
+            let start = ((region.y as usize + row) * image.width as usize) + region.x as usize;
+            image.data[start..start + source.len()].copy_from_slice(source);
+        }
+    }
+
+    pub(super) fn forget(&mut self, id: &Uuid) {
+        self.textures.remove(id);
+        self.meshes.remove(id);
+    }
+
+    pub(super) fn texture(&self, id: &Uuid) -> Option<&TextureSource> {
+        self.textures.get(id)
+    }
+
+    pub(super) fn mesh(&self, id: &Uuid) -> Option<(&[u8], &[u32], MeshBounds)> {
+        self.meshes
+            .get(id)
+            .map(|(vertices, indices, bounds)| (vertices.as_slice(), indices.as_slice(), *bounds))
+    }
+}
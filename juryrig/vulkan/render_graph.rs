@@ -0,0 +1,1196 @@
+use std::collections::HashMap;
+
+use ash::{vk, Device};
+use gpu_allocator::{vulkan::Allocator, MemoryLocation};
+
+use super::buffer::Image;
+
+// A small frame graph: declare the attachments and the passes that write and sample them, in the
+// order they run, and `compile` works out each pass's render pass, the layouts every attachment
+// moves through, and the dependencies between passes. The built-in main and output passes are
+// declared this way in `init_renderpass` and `init_output_pass`; middleware can compile its own
+// graph in `prepare` and record it in `after_main_pass` for post-processing or UI, importing the
+// swapchain image from `FrameContext`. `set_policy` and `PassDesc::resolve` tune loads, stores
+// and multisample resolves for tile-based GPUs, where every attachment read into or written out
+// of tile memory costs bandwidth.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AttachmentId(usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PassId(usize);
+
+#[derive(Clone, Copy, Debug)]
+enum Origin {
+    // Created and owned by the compiled graph, `scale` times the size of the extent.
+    Transient {
+        scale: f32,
+    },
+    // An image the caller owns and hands to `begin_pass`, e.g. the swapchain image. Its contents
+    // are kept unless `initial` is UNDEFINED.
+    Imported {
+        initial: vk::ImageLayout,
+        final_layout: vk::ImageLayout,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct Attachment {
+    name: String,
+    format: vk::Format,
+    origin: Origin,
+    samples: vk::SampleCountFlags,
+    policy: AttachmentPolicy,
+}
+
+/// What happens to an attachment's contents as a pass using it starts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LoadPolicy {
+    /// Kept when an earlier pass drew it or it was imported with contents, cleared otherwise.
+    /// Resolve targets are always overwritten and never loaded.
+    #[default]
+    Auto,
+    /// Always cleared, even over what an earlier pass drew.
+    Clear,
+    /// Left undefined, for passes that draw over every pixel. Spares a tile-based GPU reading
+    /// the attachment into tile memory or clearing it.
+    DontCare,
+}
+
+/// What happens to an attachment's contents as a pass using it ends.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StorePolicy {
+    /// Written out when a later pass or something outside the graph reads it, or it's an imported
+    /// colour attachment. Discarded otherwise, so unread depth never leaves tile memory.
+    #[default]
+    Auto,
+    /// Always written out, e.g. to look at depth in a capture.
+    Store,
+    /// Never written out. Later passes can't sample it or load it with `LoadPolicy::Auto`.
+    DontCare,
+}
+
+/// How every pass using an attachment loads and stores it, see `RenderGraph::set_policy`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AttachmentPolicy {
+    pub load: LoadPolicy,
+    pub store: StorePolicy,
+}
+
+/// One render pass worth of drawing. Built up like a `VertexLayout`.
+#[derive(Clone, Debug)]
+pub struct PassDesc {
+    name: String,
+    colour: Vec<AttachmentId>,
+    depth: Option<AttachmentId>,
+    sampled: Vec<AttachmentId>,
+    // Multisampled colour attachments and the single sample attachments they're resolved into.
+    resolves: Vec<(AttachmentId, AttachmentId)>,
+}
+
+impl PassDesc {
+    pub fn new(name: &str) -> PassDesc {
+        PassDesc {
+            name: name.to_string(),
+            colour: vec![],
+            depth: None,
+            sampled: vec![],
+            resolves: vec![],
+        }
+    }
+
+    /// Written as colour attachment `location` in the order they are added.
+    pub fn colour(mut self, attachment: AttachmentId) -> PassDesc {
+        self.colour.push(attachment);
+        self
+    }
+
+    pub fn depth(mut self, attachment: AttachmentId) -> PassDesc {
+        self.depth = Some(attachment);
+        self
+    }
+
+    /// Read by the pass's fragment shaders, written by an earlier pass.
+    pub fn sample(mut self, attachment: AttachmentId) -> PassDesc {
+        self.sampled.push(attachment);
+        self
+    }
+
+    /// Resolves multisampled colour attachment `from` of this pass into the single sample `into`
+    /// as the pass ends. On a tile-based GPU the resolve happens in tile memory, and as long as
+    /// nothing reads `from` afterwards its samples are never written out.
+    pub fn resolve(mut self, from: AttachmentId, into: AttachmentId) -> PassDesc {
+        self.resolves.push((from, into));
+        self
+    }
+
+    // Colour attachments first, then depth, then resolve targets, the order of the render pass's
+    // attachments and of the clear values `begin_pass` takes.
+    fn attachments(&self) -> impl Iterator<Item = AttachmentId> + '_ {
+        self.colour
+            .iter()
+            .chain(&self.depth)
+            .chain(self.resolves.iter().map(|(_, into)| into))
+            .copied()
+    }
+}
+
+#[derive(Debug)]
+pub enum RenderGraphError {
+    VKErr(vk::Result),
+    /// A pass samples an attachment that nothing has written yet.
+    ReadBeforeWrite {
+        pass: String,
+        attachment: String,
+    },
+    /// A pass samples an attachment it also draws to.
+    Feedback {
+        pass: String,
+        attachment: String,
+    },
+    /// A depth format used as a colour attachment or the other way around.
+    WrongAttachmentKind {
+        pass: String,
+        attachment: String,
+    },
+    /// A pass without colour or depth attachments.
+    EmptyPass(String),
+    /// A pass's colour and depth attachments with different sample counts.
+    SampleCountMismatch(String),
+    /// A resolve from something other than a multisampled colour attachment of the pass, or into
+    /// something other than a single sample colour attachment of the same format.
+    InvalidResolve {
+        pass: String,
+        attachment: String,
+    },
+    /// A pass reads an attachment an earlier pass discarded with `StorePolicy::DontCare`.
+    ReadAfterDiscard {
+        pass: String,
+        attachment: String,
+    },
+}
+
+impl From<vk::Result> for RenderGraphError {
+    fn from(value: vk::Result) -> Self {
+        RenderGraphError::VKErr(value)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Use {
+    Colour,
+    Depth,
+    Sampled,
+}
+
+impl Use {
+    fn layout(self) -> vk::ImageLayout {
+        match self {
+            Use::Colour => vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            Use::Depth => vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            Use::Sampled => vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }
+    }
+
+    fn stage(self) -> vk::PipelineStageFlags {
+        match self {
+            Use::Colour => vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            Use::Depth => {
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+            }
+            Use::Sampled => vk::PipelineStageFlags::FRAGMENT_SHADER,
+        }
+    }
+
+    fn access(self) -> vk::AccessFlags {
+        match self {
+            Use::Colour => {
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+            }
+            Use::Depth => {
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+            }
+            Use::Sampled => vk::AccessFlags::SHADER_READ,
+        }
+    }
+
+    // What a later use has to wait for, reads don't have to be waited on by other reads.
+    fn writes(self) -> vk::AccessFlags {
+        match self {
+            Use::Colour => vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            Use::Depth => vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            Use::Sampled => vk::AccessFlags::empty(),
+        }
+    }
+}
+
+pub(super) fn is_depth_format(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::D16_UNORM
+            | vk::Format::X8_D24_UNORM_PACK32
+            | vk::Format::D32_SFLOAT
+            | vk::Format::D16_UNORM_S8_UINT
+            | vk::Format::D24_UNORM_S8_UINT
+            | vk::Format::D32_SFLOAT_S8_UINT
+    )
+}
+
+// What `RenderGraph::create_render_pass` builds a pass's render pass from.
+struct PassLayout {
+    // In the order of `PassDesc::attachments`.
+    descriptions: Vec<vk::AttachmentDescription>,
+    // One per colour attachment, UNUSED for those not resolved. Empty without resolves.
+    resolves: Vec<vk::AttachmentReference>,
+    // Waiting on what came before, then signalling shaders outside the graph if they sample it.
+    dependencies: Vec<vk::SubpassDependency>,
+}
+
+#[derive(Default)]
+pub struct RenderGraph {
+    attachments: Vec<Attachment>,
+    passes: Vec<PassDesc>,
+}
+
+impl RenderGraph {
+    pub fn new() -> RenderGraph {
+        RenderGraph::default()
+    }
+
+    /// An attachment the compiled graph creates, `scale` times the size of the extent it's
+    /// compiled with, so 0.5 for a half resolution buffer.
+    pub fn transient(&mut self, name: &str, format: vk::Format, scale: f32) -> AttachmentId {
+        self.add_attachment(name, format, Origin::Transient { scale })
+    }
+
+    /// Like `transient`, with `samples` per pixel. Resolve it for sampling with
+    /// `PassDesc::resolve`.
+    pub fn transient_multisampled(
+        &mut self,
+        name: &str,
+        format: vk::Format,
+        scale: f32,
+        samples: vk::SampleCountFlags,
+    ) -> AttachmentId {
+        let attachment = self.transient(name, format, scale);
+        self.attachments[attachment.0].samples = samples;
+        attachment
+    }
+
+    /// Overrides how every pass using `attachment` loads and stores it. The default works it out
+    /// from the passes, which suits most graphs.
+    pub fn set_policy(&mut self, attachment: AttachmentId, policy: AttachmentPolicy) {
+        self.attachments[attachment.0].policy = policy;
+    }
+
+    /// An image the caller provides to `begin_pass`, arriving in `initial` layout and left in
+    /// `final_layout` once the last pass using it ends. Left in SHADER_READ_ONLY_OPTIMAL, it's
+    /// synchronised with the fragment shaders sampling it outside the graph.
+    pub fn import(
+        &mut self,
+        name: &str,
+        format: vk::Format,
+        initial: vk::ImageLayout,
+        final_layout: vk::ImageLayout,
+    ) -> AttachmentId {
+        self.add_attachment(
+            name,
+            format,
+            Origin::Imported {
+                initial,
+                final_layout,
+            },
+        )
+    }
+
+    fn add_attachment(&mut self, name: &str, format: vk::Format, origin: Origin) -> AttachmentId {
+        self.attachments.push(Attachment {
+            name: name.to_string(),
+            format,
+            origin,
+            samples: vk::SampleCountFlags::TYPE_1,
+            policy: AttachmentPolicy::default(),
+        });
+        AttachmentId(self.attachments.len() - 1)
+    }
+
+    /// Passes run in the order they are added.
+    pub fn add_pass(&mut self, pass: PassDesc) -> PassId {
+        self.passes.push(pass);
+        PassId(self.passes.len() - 1)
+    }
+
+    fn uses(&self, pass: &PassDesc, attachment: AttachmentId) -> Option<Use> {
+        let resolved = pass.resolves.iter().any(|(_, into)| *into == attachment);
+        if pass.colour.contains(&attachment) || resolved {
+            Some(Use::Colour)
+        } else if pass.depth == Some(attachment) {
+            Some(Use::Depth)
+        } else if pass.sampled.contains(&attachment) {
+            Some(Use::Sampled)
+        } else {
+            None
+        }
+    }
+
+    fn previous_use(&self, pass: usize, attachment: AttachmentId) -> Option<Use> {
+        self.passes[..pass]
+            .iter()
+            .rev()
+            .find_map(|earlier| self.uses(earlier, attachment))
+    }
+
+    fn next_use(&self, pass: usize, attachment: AttachmentId) -> Option<Use> {
+        self.passes[pass + 1..]
+            .iter()
+            .find_map(|later| self.uses(later, attachment))
+    }
+
+    fn validate(&self) -> Result<(), RenderGraphError> {
+        for (index, pass) in self.passes.iter().enumerate() {
+            let name = |attachment: AttachmentId| self.attachments[attachment.0].name.clone();
+            if pass.colour.is_empty() && pass.depth.is_none() {
+                return Err(RenderGraphError::EmptyPass(pass.name.clone()));
+            }
+            for attachment in pass.attachments() {
+                let depth = is_depth_format(self.attachments[attachment.0].format);
+                if depth != (pass.depth == Some(attachment)) {
+                    return Err(RenderGraphError::WrongAttachmentKind {
+                        pass: pass.name.clone(),
+                        attachment: name(attachment),
+                    });
+                }
+                // Loading is the only way a later pass reads what an earlier one drew, resolve
+                // targets never load.
+                let policy = self.attachments[attachment.0].policy;
+                let discarded = policy.store == StorePolicy::DontCare
+                    && policy.load == LoadPolicy::Auto
+                    && self.previous_use(index, attachment).is_some()
+                    && !pass.resolves.iter().any(|(_, into)| *into == attachment);
+                if discarded {
+                    return Err(RenderGraphError::ReadAfterDiscard {
+                        pass: pass.name.clone(),
+                        attachment: name(attachment),
+                    });
+                }
+            }
+            let samples = |attachment: AttachmentId| self.attachments[attachment.0].samples;
+            let mut drawn = pass.colour.iter().chain(&pass.depth).map(|id| samples(*id));
+            let first = drawn.next();
+            if drawn.any(|other| Some(other) != first) {
+                return Err(RenderGraphError::SampleCountMismatch(pass.name.clone()));
+            }
+            for &(from, into) in &pass.resolves {
+                let valid = pass.colour.contains(&from)
+                    && samples(from) != vk::SampleCountFlags::TYPE_1
+                    && samples(into) == vk::SampleCountFlags::TYPE_1
+                    && self.attachments[from.0].format == self.attachments[into.0].format
+                    && !pass.colour.contains(&into)
+                    && pass
+                        .resolves
+                        .iter()
+                        .filter(|(_, other)| *other == into)
+                        .count()
+                        == 1;
+                if !valid {
+                    return Err(RenderGraphError::InvalidResolve {
+                        pass: pass.name.clone(),
+                        attachment: name(from),
+                    });
+                }
+            }
+            for &attachment in &pass.sampled {
+                if pass.attachments().any(|written| written == attachment) {
+                    return Err(RenderGraphError::Feedback {
+                        pass: pass.name.clone(),
+                        attachment: name(attachment),
+                    });
+                }
+                let discarded = self.attachments[attachment.0].policy.store
+                    == StorePolicy::DontCare
+                    && self.previous_use(index, attachment).is_some();
+                if discarded {
+                    return Err(RenderGraphError::ReadAfterDiscard {
+                        pass: pass.name.clone(),
+                        attachment: name(attachment),
+                    });
+                }
+                let imported = matches!(
+                    self.attachments[attachment.0].origin,
+                    Origin::Imported { initial, .. } if initial != vk::ImageLayout::UNDEFINED
+                );
+                if !imported && self.previous_use(index, attachment).is_none() {
+                    return Err(RenderGraphError::ReadBeforeWrite {
+                        pass: pass.name.clone(),
+                        attachment: name(attachment),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Derives `pass`'s render pass: attachments start in the layout the previous pass left them
+    // in and end in the one the next use needs, and the pass waits on every earlier write to
+    // what it touches. The first pass also waits on the previous frame's use.
+    pub(super) fn create_render_pass(
+        &self,
+        logical_device: &Device,
+        pass: PassId,
+    ) -> Result<vk::RenderPass, vk::Result> {
+        let PassLayout {
+            descriptions,
+            resolves,
+            dependencies,
+        } = self.pass_layout(pass);
+        let pass = &self.passes[pass.0];
+        let colour_references: Vec<vk::AttachmentReference> = (0..pass.colour.len())
+            .map(|attachment| vk::AttachmentReference {
+                attachment: attachment as u32,
+                layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            })
+            .collect();
+        let depth_reference = vk::AttachmentReference {
+            attachment: pass.colour.len() as u32,
+            layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        };
+        let mut subpass = vk::SubpassDescription::builder()
+            .color_attachments(&colour_references)
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS);
+        if !resolves.is_empty() {
+            subpass = subpass.resolve_attachments(&resolves);
+        }
+        if pass.depth.is_some() {
+            subpass = subpass.depth_stencil_attachment(&depth_reference);
+        }
+        let subpasses = [subpass.build()];
+        let renderpass_info = vk::RenderPassCreateInfo::builder()
+            .attachments(&descriptions)
+            .subpasses(&subpasses)
+            .dependencies(&dependencies);
+        unsafe { logical_device.create_render_pass(&renderpass_info, None) }
+    }
+
+    // The attachments and dependencies of `pass`'s render pass, worked out without a device.
+    fn pass_layout(&self, pass: PassId) -> PassLayout {
+        let index = pass.0;
+        let pass = &self.passes[index];
+        let mut src_stage = vk::PipelineStageFlags::empty();
+        let mut src_access = vk::AccessFlags::empty();
+        let mut dst_stage = vk::PipelineStageFlags::empty();
+        let mut dst_access = vk::AccessFlags::empty();
+        // Writes that shaders outside the graph sample afterwards.
+        let mut outgoing_stage = vk::PipelineStageFlags::empty();
+        let mut outgoing_access = vk::AccessFlags::empty();
+
+        let mut descriptions = vec![];
+        for attachment in pass.attachments() {
+            let usage = self
+                .uses(pass, attachment)
+                .expect("attachment of its own pass");
+            let policy = self.attachments[attachment.0].policy;
+            let resolve_target = pass.resolves.iter().any(|(_, into)| *into == attachment);
+            let (previous, next) = (
+                self.previous_use(index, attachment),
+                self.next_use(index, attachment),
+            );
+            let (initial, final_layout, load) = match self.attachments[attachment.0].origin {
+                Origin::Transient { .. } => (
+                    previous.map_or(vk::ImageLayout::UNDEFINED, Use::layout),
+                    next.map_or(usage.layout(), Use::layout),
+                    previous.is_some(),
+                ),
+                Origin::Imported {
+                    initial,
+                    final_layout,
+                } => (
+                    previous.map_or(initial, Use::layout),
+                    next.map_or(final_layout, Use::layout),
+                    previous.is_some() || initial != vk::ImageLayout::UNDEFINED,
+                ),
+            };
+            // An imported image left for sampling is read by fragment shaders outside the graph,
+            // which have to finish before it's drawn over and wait for it once it's drawn.
+            let sampled_outside = matches!(
+                self.attachments[attachment.0].origin,
+                Origin::Imported { final_layout, .. }
+                    if final_layout == vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+            );
+            // Depth is scratch space unless something reads it later, colour written to an
+            // imported image is what the caller imported it for.
+            let store = next.is_some()
+                || sampled_outside
+                || (usage == Use::Colour
+                    && matches!(
+                        self.attachments[attachment.0].origin,
+                        Origin::Imported { .. }
+                    ));
+            let load_op = match (policy.load, load) {
+                _ if resolve_target => vk::AttachmentLoadOp::DONT_CARE,
+                (LoadPolicy::Auto, true) => vk::AttachmentLoadOp::LOAD,
+                (LoadPolicy::Auto, false) | (LoadPolicy::Clear, _) => vk::AttachmentLoadOp::CLEAR,
+                (LoadPolicy::DontCare, _) => vk::AttachmentLoadOp::DONT_CARE,
+            };
+            let store_op = match (policy.store, store) {
+                (StorePolicy::Auto, true) | (StorePolicy::Store, _) => vk::AttachmentStoreOp::STORE,
+                (StorePolicy::Auto, false) | (StorePolicy::DontCare, _) => {
+                    vk::AttachmentStoreOp::DONT_CARE
+                }
+            };
+            descriptions.push(
+                vk::AttachmentDescription::builder()
+                    .format(self.attachments[attachment.0].format)
+                    .load_op(load_op)
+                    .store_op(store_op)
+                    .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                    .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                    .initial_layout(initial)
+                    .final_layout(final_layout)
+                    .samples(self.attachments[attachment.0].samples)
+                    .build(),
+            );
+
+            let waited_on = previous.unwrap_or(usage);
+            src_stage |= waited_on.stage();
+            src_access |= waited_on.writes();
+            dst_stage |= usage.stage();
+            dst_access |= usage.access();
+            if sampled_outside && previous.is_none() {
+                src_stage |= Use::Sampled.stage();
+            }
+            if sampled_outside && next.is_none() {
+                outgoing_stage |= usage.stage();
+                outgoing_access |= usage.writes();
+            }
+        }
+        for &attachment in &pass.sampled {
+            if let Some(previous) = self.previous_use(index, attachment) {
+                src_stage |= previous.stage();
+                src_access |= previous.writes();
+            }
+            dst_stage |= Use::Sampled.stage();
+            dst_access |= Use::Sampled.access();
+        }
+        if src_stage.is_empty() {
+            src_stage = vk::PipelineStageFlags::TOP_OF_PIPE;
+        }
+
+        // Resolve targets come after the colour and depth attachments.
+        let first_target = pass.colour.len() + pass.depth.is_some() as usize;
+        let resolves = match pass.resolves.is_empty() {
+            true => vec![],
+            false => pass
+                .colour
+                .iter()
+                .map(|colour| vk::AttachmentReference {
+                    attachment: pass
+                        .resolves
+                        .iter()
+                        .position(|(from, _)| from == colour)
+                        .map_or(vk::ATTACHMENT_UNUSED, |index| (first_target + index) as u32),
+                    layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                })
+                .collect(),
+        };
+
+        let mut dependencies = vec![vk::SubpassDependency::builder()
+            .src_subpass(vk::SUBPASS_EXTERNAL)
+            .src_stage_mask(src_stage)
+            .src_access_mask(src_access)
+            .dst_subpass(0)
+            .dst_stage_mask(dst_stage)
+            .dst_access_mask(dst_access)
+            .build()];
+        if !outgoing_stage.is_empty() {
+            dependencies.push(
+                vk::SubpassDependency::builder()
+                    .src_subpass(0)
+                    .src_stage_mask(outgoing_stage)
+                    .src_access_mask(outgoing_access)
+                    .dst_subpass(vk::SUBPASS_EXTERNAL)
+                    .dst_stage_mask(Use::Sampled.stage())
+                    .dst_access_mask(Use::Sampled.access())
+                    .build(),
+            );
+        }
+        PassLayout {
+            descriptions,
+            resolves,
+            dependencies,
+        }
+    }
+
+    /// Checks the graph, then creates every pass's render pass and the transient attachments for
+    /// `extent`.
+    pub fn compile(
+        &self,
+        logical_device: &Device,
+        allocator: &mut Allocator,
+        extent: vk::Extent2D,
+    ) -> Result<CompiledGraph, RenderGraphError> {
+        self.validate()?;
+        let mut compiled = CompiledGraph {
+            attachments: self.attachments.clone(),
+            passes: self.passes.clone(),
+            render_passes: vec![],
+            targets: HashMap::new(),
+            framebuffers: HashMap::new(),
+            extent,
+        };
+        for pass in 0..self.passes.len() {
+            match self.create_render_pass(logical_device, PassId(pass)) {
+                Ok(render_pass) => compiled.render_passes.push(render_pass),
+                Err(e) => {
+                    compiled.cleanup(logical_device, allocator);
+                    return Err(e.into());
+                }
+            }
+        }
+        if let Err(e) = compiled.create_targets(logical_device, allocator) {
+            compiled.cleanup(logical_device, allocator);
+            return Err(e.into());
+        }
+        Ok(compiled)
+    }
+}
+
+struct Target {
+    image: Image,
+    view: vk::ImageView,
+}
+
+/// A graph ready to record. Recreate its attachments with `resize` whenever the extent changes,
+/// e.g. from `RenderMiddleware::prepare`.
+pub struct CompiledGraph {
+    attachments: Vec<Attachment>,
+    passes: Vec<PassDesc>,
+    render_passes: Vec<vk::RenderPass>,
+    targets: HashMap<AttachmentId, Target>,
+    // Keyed by pass and the views it was created with, imported images change every frame.
+    framebuffers: HashMap<(usize, Vec<vk::ImageView>), vk::Framebuffer>,
+    extent: vk::Extent2D,
+}
+
+impl CompiledGraph {
+    pub fn render_pass(&self, pass: PassId) -> vk::RenderPass {
+        self.render_passes[pass.0]
+    }
+
+    /// The view of a transient attachment, for binding it to be sampled. None for imported ones.
+    pub fn view(&self, attachment: AttachmentId) -> Option<vk::ImageView> {
+        self.targets.get(&attachment).map(|target| target.view)
+    }
+
+    fn attachment_extent(&self, attachment: AttachmentId) -> vk::Extent2D {
+        match self.attachments[attachment.0].origin {
+            Origin::Transient { scale } => vk::Extent2D {
+                width: ((self.extent.width as f32 * scale) as u32).max(1),
+                height: ((self.extent.height as f32 * scale) as u32).max(1),
+            },
+            Origin::Imported { .. } => self.extent,
+        }
+    }
+
+    /// The area a pass draws to, the smallest of its attachments.
+    pub fn pass_extent(&self, pass: PassId) -> vk::Extent2D {
+        self.passes[pass.0]
+            .attachments()
+            .map(|attachment| self.attachment_extent(attachment))
+            .fold(self.extent, |smallest, extent| vk::Extent2D {
+                width: smallest.width.min(extent.width),
+                height: smallest.height.min(extent.height),
+            })
+    }
+
+    fn create_targets(
+        &mut self,
+        logical_device: &Device,
+        allocator: &mut Allocator,
+    ) -> Result<(), vk::Result> {
+        for (index, attachment) in self.attachments.iter().enumerate() {
+            let id = AttachmentId(index);
+            if !matches!(attachment.origin, Origin::Transient { .. }) {
+                continue;
+            }
+            let depth = is_depth_format(attachment.format);
+            let mut usage = match depth {
+                true => vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+                false => vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            };
+            // Only ever an attachment, so drivers that can keep it in tile memory may.
+            usage |= match self.passes.iter().any(|pass| pass.sampled.contains(&id)) {
+                true => vk::ImageUsageFlags::SAMPLED,
+                false => vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+            };
+            let extent = self.attachment_extent(id);
+            let image_info = vk::ImageCreateInfo::builder()
+                .image_type(vk::ImageType::TYPE_2D)
+                .format(attachment.format)
+                .extent(vk::Extent3D {
+                    width: extent.width,
+                    height: extent.height,
+                    depth: 1,
+                })
+                .mip_levels(1)
+                .array_layers(1)
+                .samples(attachment.samples)
+                .tiling(vk::ImageTiling::OPTIMAL)
+                .usage(usage)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE);
+            let mut image = Image::new(
+                allocator,
+                logical_device,
+                &image_info,
+                MemoryLocation::GpuOnly,
+                &attachment.name,
+                None,
+            )?;
+            let subresource_range = vk::ImageSubresourceRange::builder()
+                .aspect_mask(match depth {
+                    true => vk::ImageAspectFlags::DEPTH,
+                    false => vk::ImageAspectFlags::COLOR,
+                })
+                .level_count(1)
+                .layer_count(1);
+            let view_info = vk::ImageViewCreateInfo::builder()
+                .image(image.image)
+                .view_type(vk::ImageViewType::TYPE_2D)
+                .format(attachment.format)
+                .subresource_range(*subresource_range);
+            let view = match unsafe { logical_device.create_image_view(&view_info, None) } {
+                Ok(view) => view,
+                Err(e) => {
+                    unsafe { image.cleanup(allocator, logical_device) };
+                    return Err(e);
+                }
+            };
+            self.targets.insert(id, Target { image, view });
+        }
+        Ok(())
+    }
+
+    fn destroy_targets(&mut self, logical_device: &Device, allocator: &mut Allocator) {
+        unsafe {
+            for (_, framebuffer) in self.framebuffers.drain() {
+                logical_device.destroy_framebuffer(framebuffer, None);
+            }
+            for (_, mut target) in self.targets.drain() {
+                logical_device.destroy_image_view(target.view, None);
+                target.image.cleanup(allocator, logical_device);
+            }
+        }
+    }
+
+    /// Recreates the transient attachments for a new extent. Nothing recorded with the old ones
+    /// may still be executing.
+    pub fn resize(
+        &mut self,
+        logical_device: &Device,
+        allocator: &mut Allocator,
+        extent: vk::Extent2D,
+    ) -> Result<(), vk::Result> {
+        self.destroy_targets(logical_device, allocator);
+        self.extent = extent;
+        self.create_targets(logical_device, allocator)
+    }
+
+    /// Begins `pass`'s render pass. `imported` gives the view of every imported attachment the
+    /// pass uses, and `clear_values` one value per attachment: colour, then depth, then resolve
+    /// targets.
+    pub fn begin_pass(
+        &mut self,
+        logical_device: &Device,
+        command_buffer: vk::CommandBuffer,
+        pass: PassId,
+        imported: &[(AttachmentId, vk::ImageView)],
+        clear_values: &[vk::ClearValue],
+    ) -> Result<(), vk::Result> {
+        let framebuffer = self.framebuffer(logical_device, pass, imported)?;
+        self.begin_pass_in(
+            logical_device,
+            command_buffer,
+            pass,
+            framebuffer,
+            clear_values,
+        );
+        Ok(())
+    }
+
+    /// `pass`'s framebuffer with `imported` views, created the first time they're asked for and
+    /// kept until `resize` or `cleanup`.
+    pub fn framebuffer(
+        &mut self,
+        logical_device: &Device,
+        pass: PassId,
+        imported: &[(AttachmentId, vk::ImageView)],
+    ) -> Result<vk::Framebuffer, vk::Result> {
+        let views: Vec<vk::ImageView> = self.passes[pass.0]
+            .attachments()
+            .map(|attachment| match self.targets.get(&attachment) {
+                Some(target) => target.view,
+                None => imported
+                    .iter()
+                    .find(|(id, _)| *id == attachment)
+                    .map(|(_, view)| *view)
+                    .unwrap_or_else(|| {
+                        panic!(
+                            "no view given for imported attachment {}",
+                            self.attachments[attachment.0].name
+                        )
+                    }),
+            })
+            .collect();
+        if let Some(framebuffer) = self.framebuffers.get(&(pass.0, views.clone())) {
+            return Ok(*framebuffer);
+        }
+        let extent = self.pass_extent(pass);
+        let framebuffer_info = vk::FramebufferCreateInfo::builder()
+            .render_pass(self.render_passes[pass.0])
+            .attachments(&views)
+            .width(extent.width)
+            .height(extent.height)
+            .layers(1);
+        let framebuffer = unsafe { logical_device.create_framebuffer(&framebuffer_info, None) }?;
+        self.framebuffers.insert((pass.0, views), framebuffer);
+        Ok(framebuffer)
+    }
+
+    /// Like `begin_pass` with a framebuffer from `framebuffer`, for recording without borrowing
+    /// the graph mutably.
+    pub fn begin_pass_in(
+        &self,
+        logical_device: &Device,
+        command_buffer: vk::CommandBuffer,
+        pass: PassId,
+        framebuffer: vk::Framebuffer,
+        clear_values: &[vk::ClearValue],
+    ) {
+        let begin_info = vk::RenderPassBeginInfo::builder()
+            .render_pass(self.render_passes[pass.0])
+            .framebuffer(framebuffer)
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: self.pass_extent(pass),
+            })
+            .clear_values(clear_values);
+        unsafe {
+            logical_device.cmd_begin_render_pass(
+                command_buffer,
+                &begin_info,
+                vk::SubpassContents::INLINE,
+            );
+        }
+    }
+
+    pub fn end_pass(&self, logical_device: &Device, command_buffer: vk::CommandBuffer) {
+        unsafe { logical_device.cmd_end_render_pass(command_buffer) };
+    }
+
+    pub fn cleanup(&mut self, logical_device: &Device, allocator: &mut Allocator) {
+        self.destroy_targets(logical_device, allocator);
+        for render_pass in self.render_passes.drain(..) {
+            unsafe { logical_device.destroy_render_pass(render_pass, None) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HDR: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+    const DEPTH: vk::Format = vk::Format::D32_SFLOAT;
+    const SWAPCHAIN: vk::Format = vk::Format::B8G8R8A8_SRGB;
+
+    // Geometry into an HDR target with depth, then a post pass sampling it onto the swapchain.
+    fn forward() -> (RenderGraph, [AttachmentId; 3], [PassId; 2]) {
+        let mut graph = RenderGraph::new();
+        let hdr = graph.transient("hdr", HDR, 1.0);
+        let depth = graph.transient("depth", DEPTH, 1.0);
+        let output = graph.import(
+            "swapchain",
+            SWAPCHAIN,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+        );
+        let geometry = graph.add_pass(PassDesc::new("geometry").colour(hdr).depth(depth));
+        let post = graph.add_pass(PassDesc::new("post").colour(output).sample(hdr));
+        (graph, [hdr, depth, output], [geometry, post])
+    }
+
+    #[test]
+    fn layouts_follow_the_pass_order() {
+        let (graph, _, [geometry, post]) = forward();
+        graph.validate().unwrap();
+        let geometry = graph.pass_layout(geometry).descriptions;
+        assert_eq!(geometry[0].initial_layout, vk::ImageLayout::UNDEFINED);
+        assert_eq!(
+            geometry[0].final_layout,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        );
+        assert_eq!(geometry[0].load_op, vk::AttachmentLoadOp::CLEAR);
+        assert_eq!(
+            geometry[1].final_layout,
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+        );
+        let post = graph.pass_layout(post).descriptions;
+        assert_eq!(post[0].initial_layout, vk::ImageLayout::UNDEFINED);
+        assert_eq!(post[0].final_layout, vk::ImageLayout::PRESENT_SRC_KHR);
+    }
+
+    #[test]
+    fn a_second_writer_loads_what_the_first_drew() {
+        let (mut graph, [hdr, _, _], _) = forward();
+        let overlay = graph.add_pass(PassDesc::new("overlay").colour(hdr));
+        let overlay = graph.pass_layout(overlay).descriptions;
+        assert_eq!(overlay[0].load_op, vk::AttachmentLoadOp::LOAD);
+        assert_eq!(
+            overlay[0].initial_layout,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        );
+    }
+
+    #[test]
+    fn sampling_waits_on_the_write() {
+        let (graph, _, [geometry, post]) = forward();
+        let incoming = graph.pass_layout(post).dependencies[0];
+        assert!(incoming
+            .src_stage_mask
+            .contains(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT));
+        assert!(incoming
+            .src_access_mask
+            .contains(vk::AccessFlags::COLOR_ATTACHMENT_WRITE));
+        assert!(incoming
+            .dst_stage_mask
+            .contains(vk::PipelineStageFlags::FRAGMENT_SHADER));
+        assert!(incoming
+            .dst_access_mask
+            .contains(vk::AccessFlags::SHADER_READ));
+        // The first pass waits on the previous frame's writes to the same attachments.
+        let first = graph.pass_layout(geometry).dependencies;
+        assert_eq!(first.len(), 1);
+        assert!(first[0]
+            .src_access_mask
+            .contains(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE));
+    }
+
+    #[test]
+    fn unread_depth_isnt_stored() {
+        let (graph, _, [geometry, _]) = forward();
+        let descriptions = graph.pass_layout(geometry).descriptions;
+        assert_eq!(descriptions[0].store_op, vk::AttachmentStoreOp::STORE);
+        assert_eq!(descriptions[1].store_op, vk::AttachmentStoreOp::DONT_CARE);
+
+        // Once something samples it, it's kept.
+        let (mut graph, [_, depth, _], [geometry, _]) = forward();
+        let hdr = graph.transient("fog", HDR, 1.0);
+        graph.add_pass(PassDesc::new("fog").colour(hdr).sample(depth));
+        let descriptions = graph.pass_layout(geometry).descriptions;
+        assert_eq!(descriptions[1].store_op, vk::AttachmentStoreOp::STORE);
+        assert_eq!(
+            descriptions[1].final_layout,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        );
+    }
+
+    #[test]
+    fn images_sampled_outside_signal_when_drawn() {
+        let mut graph = RenderGraph::new();
+        let target = graph.import(
+            "target",
+            HDR,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+        let pass = graph.add_pass(PassDesc::new("bake").colour(target));
+        let PassLayout {
+            descriptions,
+            dependencies,
+            ..
+        } = graph.pass_layout(pass);
+        assert_eq!(descriptions[0].load_op, vk::AttachmentLoadOp::LOAD);
+        assert_eq!(dependencies.len(), 2);
+        assert_eq!(dependencies[1].dst_subpass, vk::SUBPASS_EXTERNAL);
+        assert!(dependencies[1]
+            .dst_stage_mask
+            .contains(vk::PipelineStageFlags::FRAGMENT_SHADER));
+    }
+
+    #[test]
+    fn validate_rejects_malformed_graphs() {
+        let mut graph = RenderGraph::new();
+        let hdr = graph.transient("hdr", HDR, 1.0);
+        let other = graph.transient("other", HDR, 1.0);
+        graph.add_pass(PassDesc::new("post").colour(other).sample(hdr));
+        assert!(matches!(
+            graph.validate(),
+            Err(RenderGraphError::ReadBeforeWrite { .. })
+        ));
+
+        let mut graph = RenderGraph::new();
+        let hdr = graph.transient("hdr", HDR, 1.0);
+        graph.add_pass(PassDesc::new("loop").colour(hdr).sample(hdr));
+        assert!(matches!(
+            graph.validate(),
+            Err(RenderGraphError::Feedback { .. })
+        ));
+
+        let mut graph = RenderGraph::new();
+        let depth = graph.transient("depth", DEPTH, 1.0);
+        graph.add_pass(PassDesc::new("wrong").colour(depth));
+        assert!(matches!(
+            graph.validate(),
+            Err(RenderGraphError::WrongAttachmentKind { .. })
+        ));
+
+        let mut graph = RenderGraph::new();
+        graph.add_pass(PassDesc::new("empty"));
+        assert!(matches!(
+            graph.validate(),
+            Err(RenderGraphError::EmptyPass(_))
+        ));
+    }
+
+    #[test]
+    fn policies_override_loads_and_stores() {
+        let (mut graph, [hdr, depth, output], [geometry, post]) = forward();
+        graph.set_policy(
+            output,
+            AttachmentPolicy {
+                load: LoadPolicy::DontCare,
+                ..AttachmentPolicy::default()
+            },
+        );
+        graph.set_policy(
+            depth,
+            AttachmentPolicy {
+                store: StorePolicy::Store,
+                ..AttachmentPolicy::default()
+            },
+        );
+        let overlay = graph.add_pass(PassDesc::new("overlay").colour(hdr));
+        graph.set_policy(
+            hdr,
+            AttachmentPolicy {
+                load: LoadPolicy::Clear,
+                ..AttachmentPolicy::default()
+            },
+        );
+        graph.validate().unwrap();
+        let geometry = graph.pass_layout(geometry).descriptions;
+        assert_eq!(geometry[1].store_op, vk::AttachmentStoreOp::STORE);
+        let post = graph.pass_layout(post).descriptions;
+        assert_eq!(post[0].load_op, vk::AttachmentLoadOp::DONT_CARE);
+        // Still stored, it's what the swapchain was imported for.
+        assert_eq!(post[0].store_op, vk::AttachmentStoreOp::STORE);
+        let overlay = graph.pass_layout(overlay).descriptions;
+        assert_eq!(overlay[0].load_op, vk::AttachmentLoadOp::CLEAR);
+    }
+
+    #[test]
+    fn discarded_attachments_cant_be_read_later() {
+        let discard = AttachmentPolicy {
+            store: StorePolicy::DontCare,
+            ..AttachmentPolicy::default()
+        };
+        let (mut graph, [hdr, _, _], _) = forward();
+        graph.set_policy(hdr, discard);
+        assert!(matches!(
+            graph.validate(),
+            Err(RenderGraphError::ReadAfterDiscard { .. })
+        ));
+
+        // Drawing over it again without loading is fine.
+        let mut graph = RenderGraph::new();
+        let scratch = graph.transient("scratch", HDR, 1.0);
+        graph.set_policy(
+            scratch,
+            AttachmentPolicy {
+                load: LoadPolicy::Clear,
+                ..discard
+            },
+        );
+        let first = graph.add_pass(PassDesc::new("first").colour(scratch));
+        graph.add_pass(PassDesc::new("second").colour(scratch));
+        graph.validate().unwrap();
+        let first = graph.pass_layout(first).descriptions;
+        assert_eq!(first[0].store_op, vk::AttachmentStoreOp::DONT_CARE);
+    }
+
+    // Geometry into a 4x MSAA target, resolved on tile into the HDR target the post pass samples.
+    fn multisampled() -> (RenderGraph, [AttachmentId; 3], PassId) {
+        let mut graph = RenderGraph::new();
+        let samples = vk::SampleCountFlags::TYPE_4;
+        let msaa = graph.transient_multisampled("msaa", HDR, 1.0, samples);
+        let depth = graph.transient_multisampled("depth", DEPTH, 1.0, samples);
+        let hdr = graph.transient("hdr", HDR, 1.0);
+        let output = graph.transient("output", SWAPCHAIN, 1.0);
+        let geometry = graph.add_pass(
+            PassDesc::new("geometry")
+                .colour(msaa)
+                .depth(depth)
+                .resolve(msaa, hdr),
+        );
+        graph.add_pass(PassDesc::new("post").colour(output).sample(hdr));
+        (graph, [msaa, depth, hdr], geometry)
+    }
+
+    #[test]
+    fn multisampled_colour_is_resolved_on_tile() {
+        let (graph, _, geometry) = multisampled();
+        graph.validate().unwrap();
+        let PassLayout {
+            descriptions,
+            resolves,
+            ..
+        } = graph.pass_layout(geometry);
+        assert_eq!(descriptions.len(), 3);
+        assert_eq!(descriptions[0].samples, vk::SampleCountFlags::TYPE_4);
+        assert_eq!(descriptions[1].samples, vk::SampleCountFlags::TYPE_4);
+        // Neither the samples nor depth leave tile memory, only the resolved colour does.
+        assert_eq!(descriptions[0].store_op, vk::AttachmentStoreOp::DONT_CARE);
+        assert_eq!(descriptions[1].store_op, vk::AttachmentStoreOp::DONT_CARE);
+        assert_eq!(descriptions[2].samples, vk::SampleCountFlags::TYPE_1);
+        assert_eq!(descriptions[2].load_op, vk::AttachmentLoadOp::DONT_CARE);
+        assert_eq!(descriptions[2].store_op, vk::AttachmentStoreOp::STORE);
+        assert_eq!(
+            descriptions[2].final_layout,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        );
+        assert_eq!(resolves.len(), 1);
+        assert_eq!(resolves[0].attachment, 2);
+    }
+
+    #[test]
+    fn validate_rejects_bad_resolves() {
+        let samples = vk::SampleCountFlags::TYPE_4;
+        let mut graph = RenderGraph::new();
+        let msaa = graph.transient_multisampled("msaa", HDR, 1.0, samples);
+        let depth = graph.transient("depth", DEPTH, 1.0);
+        graph.add_pass(PassDesc::new("mixed").colour(msaa).depth(depth));
+        assert!(matches!(
+            graph.validate(),
+            Err(RenderGraphError::SampleCountMismatch(_))
+        ));
+
+        let mut graph = RenderGraph::new();
+        let msaa = graph.transient_multisampled("msaa", HDR, 1.0, samples);
+        let ldr = graph.transient("ldr", SWAPCHAIN, 1.0);
+        graph.add_pass(PassDesc::new("format").colour(msaa).resolve(msaa, ldr));
+        assert!(matches!(
+            graph.validate(),
+            Err(RenderGraphError::InvalidResolve { .. })
+        ));
+
+        let mut graph = RenderGraph::new();
+        let single = graph.transient("single", HDR, 1.0);
+        let hdr = graph.transient("hdr", HDR, 1.0);
+        graph.add_pass(PassDesc::new("single").colour(single).resolve(single, hdr));
+        assert!(matches!(
+            graph.validate(),
+            Err(RenderGraphError::InvalidResolve { .. })
+        ));
+    }
+}
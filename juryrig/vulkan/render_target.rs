@@ -0,0 +1,160 @@
+use ash::{vk, Device};
+use gpu_allocator::vulkan::Allocator;
+use uuid::Uuid;
+
+use super::{
+    camera::Camera,
+    entity::EntityId,
+    render_graph::{CompiledGraph, PassDesc, PassId, RenderGraph, RenderGraphError},
+    texture::TextureHandle,
+};
+
+/// A texture the scene is drawn into from a camera of its own, for mirrors, portals, minimaps and
+/// security monitors. Give `texture()` to the entities that should show it. Created with
+/// `Vulkan::create_render_target`, and only drawn while it has a camera.
+#[derive(Clone)]
+pub struct RenderTarget {
+    id: Uuid,
+    texture: TextureHandle,
+    width: u32,
+    height: u32,
+}
+
+impl RenderTarget {
+    pub fn texture(&self) -> &TextureHandle {
+        &self.texture
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Width over height, for the aspect of the target's camera.
+    pub fn aspect(&self) -> f32 {
+        self.width as f32 / self.height as f32
+    }
+}
+
+// The renderer's side of a `RenderTarget`: a one pass frame graph drawing to the target's
+// texture, with a depth buffer of its own.
+pub(super) struct OffscreenTarget {
+    id: Uuid,
+    // Keeps the texture registered until the target is destroyed.
+    texture: TextureHandle,
+    pub(super) camera: Option<Camera>,
+    // Draws only these entities, over a transparent background, then takes itself and the
+    // camera off the target. Set by impostors.
+    pub(super) bake: Option<Vec<EntityId>>,
+    graph: CompiledGraph,
+    pass: PassId,
+    framebuffer: vk::Framebuffer,
+}
+
+impl OffscreenTarget {
+    // `colour_format` and `depth_format` are the main render pass's, which keeps the target's
+    // render pass compatible with every scene pipeline.
+    pub(super) fn new(
+        logical_device: &Device,
+        allocator: &mut Allocator,
+        texture: TextureHandle,
+        image_view: vk::ImageView,
+        extent: vk::Extent2D,
+        colour_format: vk::Format,
+        depth_format: vk::Format,
+    ) -> Result<(OffscreenTarget, RenderTarget), vk::Result> {
+        let mut graph = RenderGraph::new();
+        let colour = graph.import(
+            "render target",
+            colour_format,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+        let depth = graph.transient("render target depth", depth_format, 1.0);
+        let pass = graph.add_pass(PassDesc::new("render target").colour(colour).depth(depth));
+        let mut graph = graph
+            .compile(logical_device, allocator, extent)
+            .map_err(|e| match e {
+                RenderGraphError::VKErr(e) => e,
+                // The graph above is always valid.
+                e => unreachable!("{:?}", e),
+            })?;
+        let framebuffer = match graph.framebuffer(logical_device, pass, &[(colour, image_view)]) {
+            Ok(framebuffer) => framebuffer,
+            Err(e) => {
+                graph.cleanup(logical_device, allocator);
+                return Err(e);
+            }
+        };
+        let id = Uuid::new_v4();
+        Ok((
+            OffscreenTarget {
+                id,
+                texture: texture.clone(),
+                camera: None,
+                bake: None,
+                graph,
+                pass,
+                framebuffer,
+            },
+            RenderTarget {
+                id,
+                texture,
+                width: extent.width,
+                height: extent.height,
+            },
+        ))
+    }
+
+    pub(super) fn is(&self, target: &RenderTarget) -> bool {
+        self.id == target.id
+    }
+
+    pub(super) fn extent(&self) -> vk::Extent2D {
+        self.graph.pass_extent(self.pass)
+    }
+
+    pub(super) fn begin(
+        &self,
+        logical_device: &Device,
+        command_buffer: vk::CommandBuffer,
+        clear_colour: [f32; 4],
+        clear_depth: f32,
+    ) {
+        let clear_values = [
+            vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: clear_colour,
+                },
+            },
+            vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: clear_depth,
+                    stencil: 0,
+                },
+            },
+        ];
+        self.graph.begin_pass_in(
+            logical_device,
+            command_buffer,
+            self.pass,
+            self.framebuffer,
+            &clear_values,
+        );
+    }
+
+    pub(super) fn end(&self, logical_device: &Device, command_buffer: vk::CommandBuffer) {
+        self.graph.end_pass(logical_device, command_buffer);
+    }
+
+    pub(super) fn texture(&self) -> &TextureHandle {
+        &self.texture
+    }
+
+    pub(super) fn cleanup(&mut self, logical_device: &Device, allocator: &mut Allocator) {
+        self.graph.cleanup(logical_device, allocator);
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use ash::{vk, Device};
+
+use super::debug;
+
+/// How a texture is filtered and wrapped when it's sampled, see `Vulkan::register_texture`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SamplerDesc {
+    /// When a texel covers more than a pixel.
+    pub mag_filter: vk::Filter,
+    /// When a pixel covers more than a texel.
+    pub min_filter: vk::Filter,
+    /// Used for u and v alike.
+    pub address_mode: vk::SamplerAddressMode,
+    /// The most samples anisotropic filtering takes, None turns it off. Clamped to what the device
+    /// supports.
+    pub anisotropy: Option<f32>,
+    /// Added to the level of detail the shader picks, negative is sharper. Only matters for
+    /// textures with mip levels.
+    pub mip_bias: f32,
+}
+
+impl SamplerDesc {
+    /// Smooth filtering with anisotropy, for most textures.
+    pub const LINEAR: SamplerDesc = SamplerDesc {
+        mag_filter: vk::Filter::LINEAR,
+        min_filter: vk::Filter::LINEAR,
+        address_mode: vk::SamplerAddressMode::REPEAT,
+        anisotropy: Some(16.0),
+        mip_bias: 0.0,
+    };
+
+    /// Every texel stays a hard edged square, for pixel art and atlases of it.
+    pub const NEAREST: SamplerDesc = SamplerDesc {
+        mag_filter: vk::Filter::NEAREST,
+        min_filter: vk::Filter::NEAREST,
+        address_mode: vk::SamplerAddressMode::REPEAT,
+        anisotropy: None,
+        mip_bias: 0.0,
+    };
+
+    // Floats compared by their bits, two descs that are equal get the same sampler.
+    fn key(&self) -> (i32, i32, i32, Option<u32>, u32) {
+        (
+            self.mag_filter.as_raw(),
+            self.min_filter.as_raw(),
+            self.address_mode.as_raw(),
+            self.anisotropy.map(f32::to_bits),
+            self.mip_bias.to_bits(),
+        )
+    }
+}
+
+impl Default for SamplerDesc {
+    fn default() -> Self {
+        SamplerDesc::LINEAR
+    }
+}
+
+// One sampler per distinct `SamplerDesc`, shared by every texture registered with it. Samplers
+// are small and there are only ever a handful, so they're kept until the store is destroyed.
+pub(super) struct SamplerCache {
+    samplers: HashMap<(i32, i32, i32, Option<u32>, u32), vk::Sampler>,
+    max_anisotropy: f32,
+}
+
+impl SamplerCache {
+    pub(super) fn new(limits: &vk::PhysicalDeviceLimits) -> SamplerCache {
+        SamplerCache {
+            samplers: HashMap::new(),
+            max_anisotropy: limits.max_sampler_anisotropy,
+        }
+    }
+
+    pub(super) fn get(
+        &mut self,
+        logical_device: &Device,
+        desc: &SamplerDesc,
+    ) -> Result<vk::Sampler, vk::Result> {
+        if let Some(sampler) = self.samplers.get(&desc.key()) {
+            return Ok(*sampler);
+        }
+        let mipmap_mode = match desc.min_filter {
+            vk::Filter::NEAREST => vk::SamplerMipmapMode::NEAREST,
+            _ => vk::SamplerMipmapMode::LINEAR,
+        };
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(desc.mag_filter)
+            .min_filter(desc.min_filter)
+            .address_mode_u(desc.address_mode)
+            .address_mode_v(desc.address_mode)
+            .address_mode_w(desc.address_mode)
+            .anisotropy_enable(desc.anisotropy.is_some())
+            .max_anisotropy(
+                desc.anisotropy
+                    .unwrap_or(1.0)
+                    .clamp(1.0, self.max_anisotropy),
+            )
+            .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+            .unnormalized_coordinates(false)
+            .compare_enable(false)
+            .compare_op(vk::CompareOp::ALWAYS)
+            .mipmap_mode(mipmap_mode)
+            .mip_lod_bias(desc.mip_bias)
+            .min_lod(0.0)
+            .max_lod(vk::LOD_CLAMP_NONE);
+        let sampler = unsafe { logical_device.create_sampler(&sampler_info, None) }?;
+        debug::set_object_name(logical_device, sampler, &format!("{:?}", desc));
+        self.samplers.insert(desc.key(), sampler);
+        Ok(sampler)
+    }
+
+    pub(super) fn cleanup(&mut self, logical_device: &Device) {
+        for (_, sampler) in self.samplers.drain() {
+            unsafe { logical_device.destroy_sampler(sampler, None) };
+        }
+    }
+}
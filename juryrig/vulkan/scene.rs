@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+
+use na::Matrix4;
+use uuid::Uuid;
+
+use super::{entity::Entity, model::Model};
+
+/// One node of a `Scene`. Its entities are drawn every frame where the node is, each entity's own
+/// transform placing it relative to the node.
+#[derive(Clone)]
+pub struct SceneNode {
+    pub name: Option<String>,
+    /// Relative to the parent node, or to the world for a root.
+    pub transform: Matrix4<f32>,
+    /// Empty for a node that only groups its children.
+    pub entities: Vec<Entity>,
+}
+
+impl SceneNode {
+    /// An empty node placed by `transform`.
+    pub fn new(transform: Matrix4<f32>) -> SceneNode {
+        SceneNode {
+            name: None,
+            transform,
+            entities: vec![],
+        }
+    }
+
+    /// A node drawing `entity` at `transform`.
+    pub fn with_entity(transform: Matrix4<f32>, entity: Entity) -> SceneNode {
+        SceneNode {
+            entities: vec![entity],
+            ..SceneNode::new(transform)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    id: Uuid,
+}
+
+struct Slot {
+    node: SceneNode,
+    parent: Option<Uuid>,
+    children: Vec<Uuid>,
+    // From the last `update_world_transforms`.
+    world: Matrix4<f32>,
+}
+
+/// A hierarchy of nodes, each placed relative to its parent, so moving a node moves everything
+/// under it. The renderer's scene is `Vulkan::scene`, its world transforms are worked out at the
+/// start of every frame and its entities drawn with the ones from `add_entity`.
+#[derive(Default)]
+pub struct Scene {
+    nodes: HashMap<Uuid, Slot>,
+    roots: Vec<Uuid>,
+}
+
+impl Scene {
+    pub fn new() -> Scene {
+        Scene::default()
+    }
+
+    /// Adds `node` under `parent`, or as a root when None or when `parent` isn't in the scene.
+    pub fn add(&mut self, node: SceneNode, parent: Option<NodeId>) -> NodeId {
+        let id = Uuid::new_v4();
+        let parent = parent
+            .map(|parent| parent.id)
+            .filter(|parent| self.nodes.contains_key(parent));
+        match parent.and_then(|parent| self.nodes.get_mut(&parent)) {
+            Some(slot) => slot.children.push(id),
+            None => self.roots.push(id),
+        }
+        self.nodes.insert(
+            id,
+            Slot {
+                world: node.transform,
+                node,
+                parent,
+                children: vec![],
+            },
+        );
+        NodeId { id }
+    }
+
+    /// Adds a node per node of `model` with the same hierarchy, under one new node placed by
+    /// `transform`, which is returned.
+    pub fn add_model(
+        &mut self,
+        model: &Model,
+        transform: Matrix4<f32>,
+        parent: Option<NodeId>,
+    ) -> NodeId {
+        let root = self.add(SceneNode::new(transform), parent);
+        let mut stack: Vec<(usize, NodeId)> =
+            model.roots.iter().map(|&index| (index, root)).collect();
+        // Models are trees, but a hand-edited file shouldn't hang this either.
+        let mut visited = vec![false; model.nodes.len()];
+        while let Some((index, parent)) = stack.pop() {
+            if std::mem::replace(&mut visited[index], true) {
+                continue;
+            }
+            let model_node = &model.nodes[index];
+            let mut node = SceneNode::new(model_node.transform);
+            node.name = model_node.name.clone();
+            node.entities = model_node
+                .primitives
+                .iter()
+                .map(|primitive| {
+                    let mut entity = Entity::new(primitive.mesh.clone(), primitive.texture.clone());
+                    entity.material = Some(primitive.material);
+                    entity
+                })
+                .collect();
+            let id = self.add(node, Some(parent));
+            stack.extend(model_node.children.iter().map(|&child| (child, id)));
+        }
+        root
+    }
+
+    pub fn get(&self, id: &NodeId) -> Option<&SceneNode> {
+        self.nodes.get(&id.id).map(|slot| &slot.node)
+    }
+
+    pub fn get_mut(&mut self, id: &NodeId) -> Option<&mut SceneNode> {
+        self.nodes.get_mut(&id.id).map(|slot| &mut slot.node)
+    }
+
+    /// Removes the node and everything under it, returning the node.
+    pub fn remove(&mut self, id: &NodeId) -> Option<SceneNode> {
+        let slot = self.nodes.remove(&id.id)?;
+        self.detach(id.id, slot.parent);
+        let mut stack = slot.children;
+        while let Some(child) = stack.pop() {
+            if let Some(removed) = self.nodes.remove(&child) {
+                stack.extend(removed.children);
+            }
+        }
+        Some(slot.node)
+    }
+
+    /// Moves the node under `parent`, or to the roots when None, keeping its local transform.
+    /// Returns false without changing anything if either isn't in the scene or `parent` is the
+    /// node or one of its descendants.
+    pub fn set_parent(&mut self, id: &NodeId, parent: Option<NodeId>) -> bool {
+        if !self.nodes.contains_key(&id.id) {
+            return false;
+        }
+        if let Some(parent) = parent {
+            let mut ancestor = Some(parent.id);
+            while let Some(current) = ancestor {
+                if current == id.id {
+                    return false;
+                }
+                ancestor = match self.nodes.get(&current) {
+                    Some(slot) => slot.parent,
+                    None => return false,
+                };
+            }
+        }
+        let previous = self.nodes[&id.id].parent;
+        self.detach(id.id, previous);
+        match parent.and_then(|parent| self.nodes.get_mut(&parent.id)) {
+            Some(slot) => slot.children.push(id.id),
+            None => self.roots.push(id.id),
+        }
+        if let Some(slot) = self.nodes.get_mut(&id.id) {
+            slot.parent = parent.map(|parent| parent.id);
+        }
+        true
+    }
+
+    pub fn parent(&self, id: &NodeId) -> Option<NodeId> {
+        self.nodes.get(&id.id)?.parent.map(|id| NodeId { id })
+    }
+
+    pub fn children(&self, id: &NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        self.nodes
+            .get(&id.id)
+            .into_iter()
+            .flat_map(|slot| slot.children.iter().map(|&id| NodeId { id }))
+    }
+
+    pub fn roots(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.roots.iter().map(|&id| NodeId { id })
+    }
+
+    /// Finds a node by name, any one of them if several share it.
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        self.nodes
+            .iter()
+            .find(|(_, slot)| slot.node.name.as_deref() == Some(name))
+            .map(|(&id, _)| NodeId { id })
+    }
+
+    /// The transform from the node's space to the world's, worked out now from its ancestors' local
+    /// transforms so it's current even between frames.
+    pub fn world_transform(&self, id: &NodeId) -> Option<Matrix4<f32>> {
+        let mut slot = self.nodes.get(&id.id)?;
+        let mut transform = slot.node.transform;
+        while let Some(parent) = slot.parent.and_then(|parent| self.nodes.get(&parent)) {
+            transform = parent.node.transform * transform;
+            slot = parent;
+        }
+        Some(transform)
+    }
+
+    // Works out every node's world transform from the roots down, once per frame before its
+    // entities are drawn.
+    pub(super) fn update_world_transforms(&mut self) {
+        let mut stack: Vec<(Uuid, Matrix4<f32>)> = self
+            .roots
+            .iter()
+            .map(|&root| (root, Matrix4::identity()))
+            .collect();
+        while let Some((id, parent)) = stack.pop() {
+            let Some(slot) = self.nodes.get_mut(&id) else {
+                continue;
+            };
+            slot.world = parent * slot.node.transform;
+            stack.extend(slot.children.iter().map(|&child| (child, slot.world)));
+        }
+    }
+
+    // Every entity in the scene with its node and world transform, as of the last
+    // `update_world_transforms`.
+    pub(super) fn drawn(&self) -> impl Iterator<Item = (NodeId, &Entity, Matrix4<f32>)> {
+        self.nodes.iter().flat_map(|(&id, slot)| {
+            slot.node
+                .entities
+                .iter()
+                .map(move |entity| (NodeId { id }, entity, slot.world * entity.transform))
+        })
+    }
+
+    fn detach(&mut self, id: Uuid, parent: Option<Uuid>) {
+        let siblings = match parent.and_then(|parent| self.nodes.get_mut(&parent)) {
+            Some(slot) => &mut slot.children,
+            None => &mut self.roots,
+        };
+        siblings.retain(|&sibling| sibling != id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use na::Vector3;
+
+    use super::*;
+
+    fn node(x: f32) -> SceneNode {
+        SceneNode::new(Matrix4::new_translation(&Vector3::new(x, 0.0, 0.0)))
+    }
+
+    #[test]
+    fn set_parent_rejects_cycles() {
+        let mut scene = Scene::new();
+        let root = scene.add(node(0.0), None);
+        let child = scene.add(node(0.0), Some(root));
+        let grandchild = scene.add(node(0.0), Some(child));
+        assert!(!scene.set_parent(&root, Some(grandchild)));
+        assert!(!scene.set_parent(&child, Some(child)));
+        assert_eq!(scene.parent(&child), Some(root));
+        assert_eq!(scene.roots().collect::<Vec<_>>(), vec![root]);
+    }
+
+    #[test]
+    fn set_parent_moves_the_node() {
+        let mut scene = Scene::new();
+        let a = scene.add(node(1.0), None);
+        let b = scene.add(node(2.0), None);
+        let child = scene.add(node(3.0), Some(a));
+        assert!(scene.set_parent(&child, Some(b)));
+        assert_eq!(scene.children(&a).count(), 0);
+        assert_eq!(scene.children(&b).collect::<Vec<_>>(), vec![child]);
+        let world = scene.world_transform(&child).unwrap();
+        assert_eq!(world[(0, 3)], 5.0);
+        assert!(scene.set_parent(&child, None));
+        assert_eq!(scene.roots().count(), 3);
+    }
+
+    #[test]
+    fn remove_takes_the_subtree() {
+        let mut scene = Scene::new();
+        let root = scene.add(node(0.0), None);
+        let child = scene.add(node(0.0), Some(root));
+        let grandchild = scene.add(node(0.0), Some(child));
+        let sibling = scene.add(node(0.0), Some(root));
+        assert!(scene.remove(&child).is_some());
+        assert!(scene.get(&child).is_none());
+        assert!(scene.get(&grandchild).is_none());
+        assert_eq!(scene.children(&root).collect::<Vec<_>>(), vec![sibling]);
+        assert!(scene.remove(&child).is_none());
+    }
+}
@@ -0,0 +1,156 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use super::vertex_format::{VertexFormatHandle, VertexLayout};
+
+// Watched files are checked for changes at most this often.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+}
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Io(std::io::Error),
+    /// GLSL that didn't compile, with the compiler's messages.
+    Compile(String),
+    /// GLSL can only be compiled with the `shader-hot-reload` feature, without it load SPIR-V.
+    CompilerUnavailable(PathBuf),
+}
+
+impl From<std::io::Error> for ShaderError {
+    fn from(value: std::io::Error) -> Self {
+        ShaderError::Io(value)
+    }
+}
+
+/// Reads a shader from disk: SPIR-V when the extension is `spv`, GLSL otherwise.
+pub fn load_shader(path: impl AsRef<Path>, stage: ShaderStage) -> Result<Vec<u32>, ShaderError> {
+    let path = path.as_ref();
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) if extension.eq_ignore_ascii_case("spv") => {
+            Ok(ash::util::read_spv(&mut std::fs::File::open(path)?)?)
+        }
+        _ => compile_glsl(path, stage),
+    }
+}
+
+#[cfg(feature = "shader-hot-reload")]
+fn compile_glsl(path: &Path, stage: ShaderStage) -> Result<Vec<u32>, ShaderError> {
+    let source = std::fs::read_to_string(path)?;
+    let unavailable = || ShaderError::CompilerUnavailable(path.to_path_buf());
+    let mut compiler = shaderc::Compiler::new().ok_or_else(unavailable)?;
+    let mut options = shaderc::CompileOptions::new().ok_or_else(unavailable)?;
+    options.set_target_env(
+        shaderc::TargetEnv::Vulkan,
+        shaderc::EnvVersion::Vulkan1_1 as u32,
+    );
+    let kind = match stage {
+        ShaderStage::Vertex => shaderc::ShaderKind::Vertex,
+        ShaderStage::Fragment => shaderc::ShaderKind::Fragment,
+    };
+    let artifact = compiler
+        .compile_into_spirv(
+            &source,
+            kind,
+            &path.to_string_lossy(),
+            "main",
+            Some(&options),
+        )
+        .map_err(|e| ShaderError::Compile(e.to_string()))?;
+    if artifact.get_num_warnings() > 0 {
+        log::warn!("{}", artifact.get_warning_messages());
+    }
+    Ok(artifact.as_binary().to_vec())
+}
+
+#[cfg(not(feature = "shader-hot-reload"))]
+fn compile_glsl(path: &Path, _stage: ShaderStage) -> Result<Vec<u32>, ShaderError> {
+    Err(ShaderError::CompilerUnavailable(path.to_path_buf()))
+}
+
+// A pipeline whose shaders came from files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum ShaderTarget {
+    // The built-in scene pipeline, and the debug view pipeline with it.
+    Scene,
+    VertexFormat(VertexFormatHandle),
+}
+
+#[derive(Clone)]
+pub(super) struct WatchedShaders {
+    pub(super) target: ShaderTarget,
+    pub(super) layout: VertexLayout,
+    pub(super) vertex: PathBuf,
+    pub(super) fragment: PathBuf,
+    modified: [Option<SystemTime>; 2],
+}
+
+impl WatchedShaders {
+    fn modified(&self) -> [Option<SystemTime>; 2] {
+        let modified = |path: &Path| {
+            std::fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        };
+        [modified(&self.vertex), modified(&self.fragment)]
+    }
+}
+
+// Polls the modification times of every pipeline's shader files. There are only ever a handful,
+// so this is cheaper than a file system watcher and works the same everywhere.
+pub(super) struct ShaderWatcher {
+    watched: Vec<WatchedShaders>,
+    last_poll: Instant,
+}
+
+impl ShaderWatcher {
+    pub(super) fn new() -> ShaderWatcher {
+        ShaderWatcher {
+            watched: vec![],
+            last_poll: Instant::now(),
+        }
+    }
+
+    // Starts watching, replacing whatever `target` was watched with before.
+    pub(super) fn watch(
+        &mut self,
+        target: ShaderTarget,
+        layout: VertexLayout,
+        vertex: &Path,
+        fragment: &Path,
+    ) -> WatchedShaders {
+        let mut watched = WatchedShaders {
+            target,
+            layout,
+            vertex: vertex.to_path_buf(),
+            fragment: fragment.to_path_buf(),
+            modified: [None; 2],
+        };
+        watched.modified = watched.modified();
+        self.watched.retain(|other| other.target != target);
+        self.watched.push(watched.clone());
+        watched
+    }
+
+    // The watched pipelines whose files have changed since the last call. Files that can't be
+    // read, e.g. because an editor is halfway through saving them, count as unchanged.
+    pub(super) fn changed(&mut self) -> Vec<WatchedShaders> {
+        if self.watched.is_empty() || self.last_poll.elapsed() < POLL_INTERVAL {
+            return vec![];
+        }
+        self.last_poll = Instant::now();
+        let mut changed = vec![];
+        for watched in &mut self.watched {
+            let modified = watched.modified();
+            if modified.iter().all(Option::is_some) && modified != watched.modified {
+                watched.modified = modified;
+                changed.push(watched.clone());
+            }
+        }
+        changed
+    }
+}
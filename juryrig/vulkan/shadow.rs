@@ -0,0 +1,286 @@
+use ash::{vk, Device, Instance};
+use gpu_allocator::{vulkan::Allocator, MemoryLocation};
+use na::{Matrix4, Vector3};
+
+use super::{
+    buffer::Image,
+    config::{DepthRange, ShadowConfig},
+    render_graph::{CompiledGraph, PassDesc, PassId, RenderGraph, RenderGraphError},
+};
+
+// Depth formats every device can both render to and sample, D16 is required to be.
+const SHADOW_FORMATS: [vk::Format; 2] = [vk::Format::D32_SFLOAT, vk::Format::D16_UNORM];
+
+// The first of `SHADOW_FORMATS` the device can draw to and sample, and whether it can filter it.
+// Filtering lets each comparison blend four texels, on top of the shader's own PCF.
+pub(super) fn choose_shadow_format(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+) -> (vk::Format, vk::Filter) {
+    let needed =
+        vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT | vk::FormatFeatureFlags::SAMPLED_IMAGE;
+    SHADOW_FORMATS
+        .into_iter()
+        .find_map(|format| {
+            let features =
+                unsafe { instance.get_physical_device_format_properties(physical_device, format) }
+                    .optimal_tiling_features;
+            features.contains(needed).then(|| {
+                let filter =
+                    match features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR) {
+                        true => vk::Filter::LINEAR,
+                        false => vk::Filter::NEAREST,
+                    };
+                (format, filter)
+            })
+        })
+        .unwrap_or((vk::Format::D16_UNORM, vk::Filter::NEAREST))
+}
+
+// The orthographic view projection of the directional light, shining towards -`sun_direction`,
+// covering `config.distance` around `centre` in every direction. The centre is snapped to whole
+// texels so shadow edges don't crawl as the camera moves.
+pub(super) fn light_view_projection(
+    sun_direction: Vector3<f32>,
+    centre: Vector3<f32>,
+    config: &ShadowConfig,
+    depth_range: DepthRange,
+) -> Matrix4<f32> {
+    let forward = -sun_direction.normalize();
+    // Any vector not parallel to the light will do for the map's orientation.
+    let reference = match forward.y.abs() > 0.99 {
+        true => Vector3::x(),
+        false => Vector3::y(),
+    };
+    let right = reference.cross(&forward).normalize();
+    let down = forward.cross(&right);
+
+    let half_width = config.distance;
+    let texel = 2.0 * half_width / config.resolution.max(1) as f32;
+    let snap = |value: f32| (value / texel).round() * texel;
+    let (x, y, z) = (right.dot(&centre), down.dot(&centre), forward.dot(&centre));
+    let (x, y) = (snap(x), snap(y));
+
+    // Casters up to twice as far towards the light as the covered area still cast.
+    let near = z - 2.0 * half_width;
+    let far = z + half_width;
+    let projection = Matrix4::new(
+        right.x / half_width,
+        right.y / half_width,
+        right.z / half_width,
+        -x / half_width,
+        down.x / half_width,
+        down.y / half_width,
+        down.z / half_width,
+        -y / half_width,
+        forward.x / (far - near),
+        forward.y / (far - near),
+        forward.z / (far - near),
+        -near / (far - near),
+        0.0,
+        0.0,
+        0.0,
+        1.0,
+    );
+    depth_range.projection_adjustment() * projection
+}
+
+// The directional light's shadow map: a depth image drawn by a one pass graph and left for the
+// scene shaders to sample through a comparison sampler.
+pub(super) struct ShadowMap {
+    image: Image,
+    view: vk::ImageView,
+    sampler: vk::Sampler,
+    graph: CompiledGraph,
+    pass: PassId,
+    framebuffer: vk::Framebuffer,
+    resolution: u32,
+}
+
+impl ShadowMap {
+    // A `resolution` of 1 is what's kept while shadows are off, so the scene pipelines always
+    // have a map to point at.
+    pub(super) fn new(
+        logical_device: &Device,
+        allocator: &mut Allocator,
+        (format, filter): (vk::Format, vk::Filter),
+        resolution: u32,
+        depth_range: DepthRange,
+    ) -> Result<ShadowMap, vk::Result> {
+        let resolution = resolution.max(1);
+        let extent = vk::Extent2D {
+            width: resolution,
+            height: resolution,
+        };
+        let image_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(format)
+            .extent(vk::Extent3D {
+                width: resolution,
+                height: resolution,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let mut image = Image::new(
+            allocator,
+            logical_device,
+            &image_info,
+            MemoryLocation::GpuOnly,
+            "shadow map",
+            None,
+        )?;
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::DEPTH)
+            .level_count(1)
+            .layer_count(1);
+        let view_info = vk::ImageViewCreateInfo::builder()
+            .image(image.image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(format)
+            .subresource_range(*subresource_range);
+        let view = match unsafe { logical_device.create_image_view(&view_info, None) } {
+            Ok(view) => view,
+            Err(e) => {
+                unsafe { image.cleanup(allocator, logical_device) };
+                return Err(e);
+            }
+        };
+
+        // Outside the map counts as lit.
+        let border_colour = match depth_range {
+            DepthRange::Standard => vk::BorderColor::FLOAT_OPAQUE_WHITE,
+            DepthRange::ReverseZ => vk::BorderColor::FLOAT_TRANSPARENT_BLACK,
+        };
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(filter)
+            .min_filter(filter)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .border_color(border_colour)
+            .compare_enable(true)
+            .compare_op(depth_range.compare_op())
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST);
+        let sampler = match unsafe { logical_device.create_sampler(&sampler_info, None) } {
+            Ok(sampler) => sampler,
+            Err(e) => {
+                unsafe {
+                    logical_device.destroy_image_view(view, None);
+                    image.cleanup(allocator, logical_device);
+                }
+                return Err(e);
+            }
+        };
+
+        let mut graph = RenderGraph::new();
+        let depth = graph.import(
+            "shadow map",
+            format,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+        let pass = graph.add_pass(PassDesc::new("shadows").depth(depth));
+        let graph = graph
+            .compile(logical_device, allocator, extent)
+            .map_err(|e| match e {
+                RenderGraphError::VKErr(e) => e,
+                // The graph above is always valid.
+                e => unreachable!("{:?}", e),
+            })
+            .and_then(|mut graph| {
+                match graph.framebuffer(logical_device, pass, &[(depth, view)]) {
+                    Ok(framebuffer) => Ok((graph, framebuffer)),
+                    Err(e) => {
+                        graph.cleanup(logical_device, allocator);
+                        Err(e)
+                    }
+                }
+            });
+        let (graph, framebuffer) = match graph {
+            Ok(graph) => graph,
+            Err(e) => {
+                unsafe {
+                    logical_device.destroy_sampler(sampler, None);
+                    logical_device.destroy_image_view(view, None);
+                    image.cleanup(allocator, logical_device);
+                }
+                return Err(e);
+            }
+        };
+        Ok(ShadowMap {
+            image,
+            view,
+            sampler,
+            graph,
+            pass,
+            framebuffer,
+            resolution,
+        })
+    }
+
+    pub(super) fn resolution(&self) -> u32 {
+        self.resolution
+    }
+
+    pub(super) fn extent(&self) -> vk::Extent2D {
+        self.graph.pass_extent(self.pass)
+    }
+
+    // The shadow pass, every shadow pipeline is built for it.
+    pub(super) fn render_pass(&self) -> vk::RenderPass {
+        self.graph.render_pass(self.pass)
+    }
+
+    // What the scene shaders sample at set 1, binding 1.
+    pub(super) fn descriptor_image_info(&self) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo {
+            sampler: self.sampler,
+            image_view: self.view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }
+    }
+
+    pub(super) fn view(&self) -> vk::ImageView {
+        self.view
+    }
+
+    // Starts the shadow pass, clearing the map to "nothing in the way".
+    pub(super) fn begin(
+        &self,
+        logical_device: &Device,
+        command_buffer: vk::CommandBuffer,
+        clear_depth: f32,
+    ) {
+        let clear_values = [vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: clear_depth,
+                stencil: 0,
+            },
+        }];
+        self.graph.begin_pass_in(
+            logical_device,
+            command_buffer,
+            self.pass,
+            self.framebuffer,
+            &clear_values,
+        );
+    }
+
+    pub(super) fn end(&self, logical_device: &Device, command_buffer: vk::CommandBuffer) {
+        self.graph.end_pass(logical_device, command_buffer);
+    }
+
+    pub(super) fn cleanup(&mut self, logical_device: &Device, allocator: &mut Allocator) {
+        self.graph.cleanup(logical_device, allocator);
+        unsafe {
+            logical_device.destroy_sampler(self.sampler, None);
+            logical_device.destroy_image_view(self.view, None);
+            self.image.cleanup(allocator, logical_device);
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use std::f32::consts::PI;
+
+use ash::vk;
+use na::{Matrix4, Vector3};
+
+use super::{debug, pipeline::DYNAMIC_STATES};
+
+// Zenith optical depths for red (680nm), green (550nm) and blue (440nm) light, shaders/sky.glsl
+// has the same values.
+const RAYLEIGH: [f32; 3] = [0.0399, 0.0973, 0.2388];
+const MIE: f32 = 0.1;
+// Brightness of the sun in the sky shader, tuned so a clear noon sky comes out a mid blue.
+const SUN_ILLUMINANCE: f32 = 20.0;
+
+/// An outdoor sky lit by the sun, drawn behind the scene and used as its directional light.
+/// Positions are +y up, +z north and +x east.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sky {
+    /// Local solar time in hours, 12 is noon.
+    pub time_of_day: f32,
+    /// 0 is the first of January. Moves the sun's path with the seasons.
+    pub day_of_year: f32,
+    /// In degrees, positive north of the equator.
+    pub latitude: f32,
+    /// Scales Mie scattering, 1 is a clear day and higher values whiten the sky and redden the sun.
+    pub haze: f32,
+}
+
+impl Default for Sky {
+    // Mid morning in early summer somewhere in Europe.
+    fn default() -> Self {
+        Sky {
+            time_of_day: 10.0,
+            day_of_year: 172.0,
+            latitude: 45.0,
+            haze: 1.0,
+        }
+    }
+}
+
+impl Sky {
+    /// Moves the time of day on, wrapping around midnight.
+    pub fn advance(&mut self, hours: f32) {
+        self.time_of_day = (self.time_of_day + hours).rem_euclid(24.0);
+    }
+
+    /// Unit vector towards the sun, below the horizon at night.
+    pub fn sun_direction(&self) -> Vector3<f32> {
+        let declination =
+            (-23.44f32).to_radians() * (2.0 * PI / 365.0 * (self.day_of_year + 10.0)).cos();
+        let hour_angle = (15.0 * (self.time_of_day - 12.0)).to_radians();
+        let latitude = self.latitude.to_radians();
+        Vector3::new(
+            -declination.cos() * hour_angle.sin(),
+            latitude.sin() * declination.sin()
+                + latitude.cos() * declination.cos() * hour_angle.cos(),
+            latitude.cos() * declination.sin()
+                - latitude.sin() * declination.cos() * hour_angle.cos(),
+        )
+        .normalize()
+    }
+
+    /// Colour of the sunlight reaching the ground, white at most and fading out as the sun sets.
+    pub fn sun_colour(&self) -> [f32; 3] {
+        let up = self.sun_direction().y;
+        let air_mass = air_mass(up);
+        // Fades over the few degrees the sun takes to sink below the horizon.
+        let above_horizon = ((up + 0.05) / 0.1).clamp(0.0, 1.0);
+        let mie = MIE * self.haze;
+        RAYLEIGH.map(|rayleigh| (-(rayleigh + mie) * air_mass).exp() * above_horizon)
+    }
+}
+
+// Kasten and Young, how much more air there is along a ray than straight up.
+fn air_mass(cos_zenith: f32) -> f32 {
+    let cos_zenith = cos_zenith.clamp(0.0, 1.0);
+    let zenith = cos_zenith.acos().to_degrees();
+    1.0 / (cos_zenith + 0.50572 * (96.07995 - zenith).powf(-1.6364))
+}
+
+#[repr(C)]
+struct SkyPushConstants {
+    inverse_view_projection: [[f32; 4]; 4],
+    sun: [f32; 4],
+    atmosphere: [f32; 4],
+}
+
+// A fullscreen pass drawing the sky before the scene.
+pub(super) struct SkyPipeline {
+    pipeline: vk::Pipeline,
+    layout: vk::PipelineLayout,
+}
+
+impl SkyPipeline {
+    pub(super) fn init(
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+    ) -> Result<SkyPipeline, vk::Result> {
+        let vertex_shader_create_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("./shaders/fullscreen.glsl", kind: vert));
+        let vertex_shader_module =
+            unsafe { logical_device.create_shader_module(&vertex_shader_create_info, None)? };
+
+        let fragment_shader_create_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("./shaders/sky.glsl", kind: frag));
+        let fragment_shader_module =
+            unsafe { logical_device.create_shader_module(&fragment_shader_create_info, None)? };
+
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vertex_shader_module)
+                .name(&main_function_name)
+                .build(),
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(fragment_shader_module)
+                .name(&main_function_name)
+                .build(),
+        ];
+
+        // The fullscreen triangle is generated from gl_VertexIndex.
+        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder();
+        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+        let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&DYNAMIC_STATES);
+
+        let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+            .line_width(1.0)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL);
+
+        let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+        let colourblend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(false)
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+            .build()];
+        let colourblend_info =
+            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colourblend_attachments);
+
+        // Drawn first and leaves the depth buffer cleared, so the whole scene lands in front.
+        let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .size(std::mem::size_of::<SkyPushConstants>() as u32)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build()];
+        let pipelinelayout_info =
+            vk::PipelineLayoutCreateInfo::builder().push_constant_ranges(&push_constant_ranges);
+        let layout = unsafe { logical_device.create_pipeline_layout(&pipelinelayout_info, None) }?;
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_info)
+            .input_assembly_state(&input_assembly_info)
+            .viewport_state(&viewport_info)
+            .dynamic_state(&dynamic_state_info)
+            .rasterization_state(&rasterizer_info)
+            .multisample_state(&multisampler_info)
+            .color_blend_state(&colourblend_info)
+            .depth_stencil_state(&depth_stencil_state)
+            .layout(layout)
+            .render_pass(*renderpass)
+            .subpass(0);
+
+        let pipeline = unsafe {
+            logical_device
+                .create_graphics_pipelines(
+                    vk::PipelineCache::null(),
+                    &[pipeline_info.build()],
+                    None,
+                )
+                .map_err(|(_, e)| e)
+        }?[0];
+        debug::set_object_name(logical_device, pipeline, "sky pipeline");
+        unsafe {
+            logical_device.destroy_shader_module(fragment_shader_module, None);
+            logical_device.destroy_shader_module(vertex_shader_module, None);
+        }
+        Ok(SkyPipeline { pipeline, layout })
+    }
+
+    // Only the rotation of `view` is used, the sky is infinitely far away.
+    pub(super) fn draw(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        sky: &Sky,
+        projection: &Matrix4<f32>,
+        view: &Matrix4<f32>,
+    ) {
+        let mut rotation = *view;
+        for row in 0..3 {
+            rotation[(row, 3)] = 0.0;
+        }
+        let inverse_view_projection = (projection * rotation)
+            .try_inverse()
+            .unwrap_or_else(Matrix4::identity);
+        let sun = sky.sun_direction();
+        let push_constants = SkyPushConstants {
+            inverse_view_projection: inverse_view_projection.into(),
+            sun: [sun.x, sun.y, sun.z, SUN_ILLUMINANCE],
+            atmosphere: [sky.haze, 0.0, 0.0, 0.0],
+        };
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            logical_device.cmd_push_constants(
+                command_buffer,
+                self.layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                std::slice::from_raw_parts(
+                    &push_constants as *const SkyPushConstants as *const u8,
+                    std::mem::size_of::<SkyPushConstants>(),
+                ),
+            );
+            logical_device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+
+    pub(super) fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            logical_device.destroy_pipeline(self.pipeline, None);
+            logical_device.destroy_pipeline_layout(self.layout, None);
+        }
+    }
+}
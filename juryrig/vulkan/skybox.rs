@@ -0,0 +1,270 @@
+use ash::vk;
+use na::Matrix4;
+
+use super::{
+    config::{DepthRange, MAX_FRAMES_IN_FLIGHT},
+    debug,
+    pipeline::DYNAMIC_STATES,
+};
+
+#[repr(C)]
+struct SkyboxPushConstants {
+    view_projection: [[f32; 4]; 4],
+    depth: [f32; 4],
+}
+
+// Draws a cubemap texture behind the scene, see `Vulkan::set_skybox`.
+pub(super) struct SkyboxPipeline {
+    pipeline: vk::Pipeline,
+    layout: vk::PipelineLayout,
+    set_layout: vk::DescriptorSetLayout,
+    pool: vk::DescriptorPool,
+    sampler: vk::Sampler,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    // The cubemap each frame's set points at, None when there is no skybox to draw.
+    written: [Option<vk::ImageView>; MAX_FRAMES_IN_FLIGHT],
+    depth_range: DepthRange,
+}
+
+impl SkyboxPipeline {
+    pub(super) fn init(
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        depth_range: DepthRange,
+    ) -> Result<SkyboxPipeline, vk::Result> {
+        let vertex_shader_create_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("./shaders/skybox_vertex.glsl", kind: vert));
+        let vertex_shader_module =
+            unsafe { logical_device.create_shader_module(&vertex_shader_create_info, None)? };
+
+        let fragment_shader_create_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("./shaders/skybox_fragment.glsl", kind: frag));
+        let fragment_shader_module =
+            unsafe { logical_device.create_shader_module(&fragment_shader_create_info, None)? };
+
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vertex_shader_module)
+                .name(&main_function_name)
+                .build(),
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(fragment_shader_module)
+                .name(&main_function_name)
+                .build(),
+        ];
+
+        // The cube is generated from gl_VertexIndex.
+        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder();
+        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(vk::PrimitiveTopology::TRIANGLE_STRIP);
+
+        let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&DYNAMIC_STATES);
+
+        // The camera is inside the cube, and the strip's winding flips from one face to the next.
+        let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+            .line_width(1.0)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL);
+
+        let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+        let colourblend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(false)
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+            .build()];
+        let colourblend_info =
+            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colourblend_attachments);
+
+        // Drawn first at the cleared depth, which the test lets through, and leaves the depth
+        // buffer as it was so the whole scene lands in front.
+        let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(true)
+            .depth_write_enable(false)
+            .depth_compare_op(depth_range.compare_op());
+
+        let bindings = [vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build()];
+        let set_layout_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+        let set_layout =
+            unsafe { logical_device.create_descriptor_set_layout(&set_layout_info, None) }?;
+
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .size(std::mem::size_of::<SkyboxPushConstants>() as u32)
+            .stage_flags(vk::ShaderStageFlags::VERTEX)
+            .build()];
+        let set_layouts = [set_layout];
+        let pipelinelayout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let layout = unsafe { logical_device.create_pipeline_layout(&pipelinelayout_info, None) }?;
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_info)
+            .input_assembly_state(&input_assembly_info)
+            .viewport_state(&viewport_info)
+            .dynamic_state(&dynamic_state_info)
+            .rasterization_state(&rasterizer_info)
+            .multisample_state(&multisampler_info)
+            .color_blend_state(&colourblend_info)
+            .depth_stencil_state(&depth_stencil_state)
+            .layout(layout)
+            .render_pass(*renderpass)
+            .subpass(0);
+
+        let pipeline = unsafe {
+            logical_device
+                .create_graphics_pipelines(
+                    vk::PipelineCache::null(),
+                    &[pipeline_info.build()],
+                    None,
+                )
+                .map_err(|(_, e)| e)
+        }?[0];
+        debug::set_object_name(logical_device, pipeline, "skybox pipeline");
+        unsafe {
+            logical_device.destroy_shader_module(fragment_shader_module, None);
+            logical_device.destroy_shader_module(vertex_shader_module, None);
+        }
+
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: MAX_FRAMES_IN_FLIGHT as u32,
+        }];
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(MAX_FRAMES_IN_FLIGHT as u32)
+            .pool_sizes(&pool_sizes);
+        let pool = unsafe { logical_device.create_descriptor_pool(&pool_info, None) }?;
+        let set_layouts = [set_layout; MAX_FRAMES_IN_FLIGHT];
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(pool)
+            .set_layouts(&set_layouts);
+        let descriptor_sets = unsafe { logical_device.allocate_descriptor_sets(&allocate_info) }?;
+
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+            .max_lod(0.0);
+        let sampler = unsafe { logical_device.create_sampler(&sampler_info, None) }?;
+
+        Ok(SkyboxPipeline {
+            pipeline,
+            layout,
+            set_layout,
+            pool,
+            sampler,
+            descriptor_sets,
+            written: [None; MAX_FRAMES_IN_FLIGHT],
+            depth_range,
+        })
+    }
+
+    // Points `slot`'s set at `cubemap`, before the frame in that slot is recorded.
+    pub(super) fn write(
+        &mut self,
+        logical_device: &ash::Device,
+        slot: usize,
+        cubemap: Option<vk::ImageView>,
+    ) {
+        if self.written[slot] == cubemap {
+            return;
+        }
+        self.written[slot] = cubemap;
+        if let Some(view) = cubemap {
+            let image_info = [vk::DescriptorImageInfo {
+                sampler: self.sampler,
+                image_view: view,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            }];
+            let write = vk::WriteDescriptorSet::builder()
+                .dst_set(self.descriptor_sets[slot])
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(&image_info)
+                .build();
+            unsafe { logical_device.update_descriptor_sets(&[write], &[]) };
+        }
+    }
+
+    // Whether `slot` has a skybox to draw.
+    pub(super) fn is_set(&self, slot: usize) -> bool {
+        self.written[slot].is_some()
+    }
+
+    // Only the rotation of `view` is used, the skybox is infinitely far away.
+    pub(super) fn draw(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        slot: usize,
+        projection: &Matrix4<f32>,
+        view: &Matrix4<f32>,
+    ) {
+        let mut rotation = *view;
+        for row in 0..3 {
+            rotation[(row, 3)] = 0.0;
+        }
+        let push_constants = SkyboxPushConstants {
+            view_projection: (projection * rotation).into(),
+            depth: [self.depth_range.clear_depth(), 0.0, 0.0, 0.0],
+        };
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout,
+                0,
+                &[self.descriptor_sets[slot]],
+                &[],
+            );
+            logical_device.cmd_push_constants(
+                command_buffer,
+                self.layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                std::slice::from_raw_parts(
+                    &push_constants as *const SkyboxPushConstants as *const u8,
+                    std::mem::size_of::<SkyboxPushConstants>(),
+                ),
+            );
+            logical_device.cmd_draw(command_buffer, 14, 1, 0, 0);
+        }
+    }
+
+    pub(super) fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            logical_device.destroy_sampler(self.sampler, None);
+            logical_device.destroy_descriptor_pool(self.pool, None);
+            logical_device.destroy_pipeline(self.pipeline, None);
+            logical_device.destroy_pipeline_layout(self.layout, None);
+            logical_device.destroy_descriptor_set_layout(self.set_layout, None);
+        }
+    }
+}
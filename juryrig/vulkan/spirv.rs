@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+
+// Just enough of a SPIR-V reader to find the vertex inputs a shader expects.
+
+const MAGIC: u32 = 0x07230203;
+const HEADER_WORDS: usize = 5;
+
+const OP_DECORATE: u32 = 71;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_VARIABLE: u32 = 59;
+
+const DECORATION_LOCATION: u32 = 30;
+const STORAGE_CLASS_INPUT: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ComponentKind {
+    Float,
+    Sint,
+    Uint,
+}
+
+/// A single shader input location.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShaderInput {
+    pub location: u32,
+    pub kind: ComponentKind,
+    pub components: u32,
+}
+
+#[derive(Debug)]
+pub enum ReflectionError {
+    NotSpirv,
+    Truncated,
+    /// An input whose type is something other than a scalar, vector or matrix.
+    UnsupportedInputType(u32),
+}
+
+#[derive(Clone, Copy)]
+enum Type {
+    Scalar(ComponentKind),
+    Vector(u32, u32),
+    Matrix(u32, u32),
+    // The storage class, then the type pointed to.
+    Pointer(u32, u32),
+}
+
+// Returns every location consumed by the module's `in` variables, matrices are expanded into one
+// input per column the same way Vulkan assigns their locations.
+pub(super) fn vertex_inputs(code: &[u32]) -> Result<Vec<ShaderInput>, ReflectionError> {
+    if code.len() < HEADER_WORDS || code[0] != MAGIC {
+        return Err(ReflectionError::NotSpirv);
+    }
+
+    let mut types: HashMap<u32, Type> = HashMap::new();
+    let mut locations: HashMap<u32, u32> = HashMap::new();
+    let mut inputs: Vec<(u32, u32)> = vec![];
+
+    let mut cursor = HEADER_WORDS;
+    while cursor < code.len() {
+        let word_count = (code[cursor] >> 16) as usize;
+        let opcode = code[cursor] & 0xffff;
+        if word_count == 0 || cursor + word_count > code.len() {
+            return Err(ReflectionError::Truncated);
+        }
+        let operands = &code[cursor + 1..cursor + word_count];
+        match opcode {
+            OP_DECORATE if operands.len() >= 3 && operands[1] == DECORATION_LOCATION => {
+                locations.insert(operands[0], operands[2]);
+            }
+            OP_TYPE_INT if operands.len() >= 3 => {
+                let kind = if operands[2] == 1 {
+                    ComponentKind::Sint
+                } else {
+                    ComponentKind::Uint
+                };
+                types.insert(operands[0], Type::Scalar(kind));
+            }
+            OP_TYPE_FLOAT if operands.len() >= 2 => {
+                types.insert(operands[0], Type::Scalar(ComponentKind::Float));
+            }
+            OP_TYPE_VECTOR if operands.len() >= 3 => {
+                types.insert(operands[0], Type::Vector(operands[1], operands[2]));
+            }
+            OP_TYPE_MATRIX if operands.len() >= 3 => {
+                types.insert(operands[0], Type::Matrix(operands[1], operands[2]));
+            }
+            OP_TYPE_POINTER if operands.len() >= 3 => {
+                types.insert(operands[0], Type::Pointer(operands[1], operands[2]));
+            }
+            OP_VARIABLE if operands.len() >= 3 && operands[2] == STORAGE_CLASS_INPUT => {
+                inputs.push((operands[1], operands[0]));
+            }
+            _ => {}
+        }
+        cursor += word_count;
+    }
+
+    let mut result = vec![];
+    for (variable, pointer_type) in inputs {
+        // Built-ins such as gl_VertexIndex have no location and are not fed by vertex buffers.
+        let location = match locations.get(&variable) {
+            Some(location) => *location,
+            None => continue,
+        };
+        let pointee = match types.get(&pointer_type) {
+            Some(Type::Pointer(STORAGE_CLASS_INPUT, pointee)) => *pointee,
+            _ => return Err(ReflectionError::UnsupportedInputType(variable)),
+        };
+        match types.get(&pointee) {
+            Some(Type::Matrix(column_type, columns)) => {
+                let (kind, components) = scalar_or_vector(&types, *column_type, variable)?;
+                for column in 0..*columns {
+                    result.push(ShaderInput {
+                        location: location + column,
+                        kind,
+                        components,
+                    });
+                }
+            }
+            _ => {
+                let (kind, components) = scalar_or_vector(&types, pointee, variable)?;
+                result.push(ShaderInput {
+                    location,
+                    kind,
+                    components,
+                });
+            }
+        }
+    }
+    result.sort_by_key(|input| input.location);
+    Ok(result)
+}
+
+fn scalar_or_vector(
+    types: &HashMap<u32, Type>,
+    id: u32,
+    variable: u32,
+) -> Result<(ComponentKind, u32), ReflectionError> {
+    match types.get(&id) {
+        Some(Type::Scalar(kind)) => Ok((*kind, 1)),
+        Some(Type::Vector(component, count)) => match types.get(component) {
+            Some(Type::Scalar(kind)) => Ok((*kind, *count)),
+            _ => Err(ReflectionError::UnsupportedInputType(variable)),
+        },
+        _ => Err(ReflectionError::UnsupportedInputType(variable)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module(instructions: &[(u32, &[u32])]) -> Vec<u32> {
+        let mut code = vec![MAGIC, 0x00010000, 0, 32, 0];
+        for (opcode, operands) in instructions {
+            code.push(((operands.len() as u32 + 1) << 16) | opcode);
+            code.extend_from_slice(operands);
+        }
+        code
+    }
+
+    const OUTPUT: u32 = 3;
+
+    #[test]
+    fn finds_located_inputs_in_order() {
+        let code = module(&[
+            (OP_DECORATE, &[4, DECORATION_LOCATION, 2]),
+            (OP_DECORATE, &[8, DECORATION_LOCATION, 3]),
+            (OP_DECORATE, &[11, DECORATION_LOCATION, 0]),
+            (OP_TYPE_FLOAT, &[1, 32]),
+            (OP_TYPE_VECTOR, &[2, 1, 3]),
+            (OP_TYPE_POINTER, &[3, STORAGE_CLASS_INPUT, 2]),
+            (OP_VARIABLE, &[3, 4, STORAGE_CLASS_INPUT]),
+            (OP_TYPE_VECTOR, &[5, 1, 4]),
+            (OP_TYPE_MATRIX, &[6, 5, 4]),
+            (OP_TYPE_POINTER, &[7, STORAGE_CLASS_INPUT, 6]),
+            (OP_VARIABLE, &[7, 8, STORAGE_CLASS_INPUT]),
+            (OP_TYPE_INT, &[9, 32, 1]),
+            (OP_TYPE_POINTER, &[10, STORAGE_CLASS_INPUT, 9]),
+            (OP_VARIABLE, &[10, 11, STORAGE_CLASS_INPUT]),
+            // A built-in, with no location.
+            (OP_VARIABLE, &[3, 12, STORAGE_CLASS_INPUT]),
+            (OP_TYPE_POINTER, &[13, OUTPUT, 2]),
+            (OP_VARIABLE, &[13, 14, OUTPUT]),
+        ]);
+        let inputs = vertex_inputs(&code).unwrap();
+        let input = |location, kind, components| ShaderInput {
+            location,
+            kind,
+            components,
+        };
+        assert_eq!(
+            inputs,
+            vec![
+                input(0, ComponentKind::Sint, 1),
+                input(2, ComponentKind::Float, 3),
+                // A mat4 takes a location per column.
+                input(3, ComponentKind::Float, 4),
+                input(4, ComponentKind::Float, 4),
+                input(5, ComponentKind::Float, 4),
+                input(6, ComponentKind::Float, 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_inputs_through_other_pointers() {
+        let code = module(&[
+            (OP_DECORATE, &[4, DECORATION_LOCATION, 0]),
+            (OP_TYPE_FLOAT, &[1, 32]),
+            (OP_TYPE_POINTER, &[3, OUTPUT, 1]),
+            (OP_VARIABLE, &[3, 4, STORAGE_CLASS_INPUT]),
+        ]);
+        assert!(matches!(
+            vertex_inputs(&code),
+            Err(ReflectionError::UnsupportedInputType(4))
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_modules() {
+        assert!(matches!(
+            vertex_inputs(&[0; HEADER_WORDS]),
+            Err(ReflectionError::NotSpirv)
+        ));
+        let mut code = module(&[(OP_TYPE_FLOAT, &[1, 32])]);
+        code.pop();
+        assert!(matches!(
+            vertex_inputs(&code),
+            Err(ReflectionError::Truncated)
+        ));
+    }
+}
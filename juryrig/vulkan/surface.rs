@@ -4,9 +4,16 @@ use winit::window::Window;
 #[cfg(target_family = "windows")]
 use {ash::extensions::khr::Win32Surface, winit::platform::windows::WindowExtWindows};
 
-#[cfg(target_family = "unix")]
+#[cfg(all(target_family = "unix", not(target_os = "macos")))]
 use {ash::extensions::khr::XlibSurface, winit::platform::x11::WindowExtX11};
 
+// There's no Vulkan driver on macOS, MoltenVK translates to Metal and draws into a CAMetalLayer.
+#[cfg(target_os = "macos")]
+use {
+    ash::extensions::ext::MetalSurface,
+    raw_window_handle::{HasRawWindowHandle, RawWindowHandle},
+};
+
 pub(super) struct Surface {
     loader: khr::Surface,
     pub(super) surface: vk::SurfaceKHR,
@@ -33,7 +40,7 @@ impl Surface {
             };
         }
 
-        #[cfg(target_family = "unix")]
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
         fn create_surface(
             window: &Window,
             entry: &Entry,
@@ -48,6 +55,29 @@ impl Surface {
             unsafe { xlib_surface_loader.create_xlib_surface(&x11_create_info, None) }
         }
 
+        #[cfg(target_os = "macos")]
+        fn create_surface(
+            window: &Window,
+            entry: &Entry,
+            instance: &Instance,
+        ) -> std::result::Result<vk::SurfaceKHR, vk::Result> {
+            use raw_window_metal::{appkit, Layer};
+
+            let handle = match window.raw_window_handle() {
+                RawWindowHandle::AppKit(handle) => handle,
+                _ => return Err(vk::Result::ERROR_EXTENSION_NOT_PRESENT),
+            };
+            // Uses the view's layer if it's already a CAMetalLayer, otherwise adds one.
+            let layer = match unsafe { appkit::metal_layer_from_handle(handle) } {
+                Layer::Existing(layer) | Layer::Allocated(layer) => layer,
+                Layer::None => return Err(vk::Result::ERROR_INITIALIZATION_FAILED),
+            };
+            let metal_create_info =
+                vk::MetalSurfaceCreateInfoEXT::builder().layer(layer as *const vk::CAMetalLayer);
+            let metal_surface_loader = MetalSurface::new(&entry, &instance);
+            unsafe { metal_surface_loader.create_metal_surface(&metal_create_info, None) }
+        }
+
         let surface = create_surface(&window, &entry, &instance)?;
 
         let surface_loader = khr::Surface::new(&entry, &instance);
@@ -105,8 +135,11 @@ impl Surface {
         #[cfg(target_family = "windows")]
         return Win32Surface::name().as_ptr();
 
-        #[cfg(target_family = "unix")]
+        #[cfg(all(target_family = "unix", not(target_os = "macos")))]
         return XlibSurface::name().as_ptr();
+
+        #[cfg(target_os = "macos")]
+        return MetalSurface::name().as_ptr();
     }
 }
 
@@ -0,0 +1,660 @@
+use ash::{
+    extensions::khr,
+    vk::{self, Framebuffer, PipelineStageFlags, Queue, SurfaceFormatKHR},
+    Device, Instance,
+};
+use gpu_allocator::{vulkan::Allocator, MemoryLocation};
+use log::warn;
+
+use super::{buffer::Image, render_graph::is_depth_format, surface::Surface, QueueFamilies};
+
+pub(super) struct Swapchain {
+    loader: khr::Swapchain,
+    pub(super) swapchain: vk::SwapchainKHR,
+    image_views: Vec<vk::ImageView>,
+    // One per image, for the output pass.
+    frame_buffers: Vec<vk::Framebuffer>,
+    // The main pass's, drawing to `scene` and `depth`.
+    scene_framebuffer: vk::Framebuffer,
+    pub(super) surface_format: vk::SurfaceFormatKHR,
+    pub(super) extent: vk::Extent2D,
+    // One per frame in flight.
+    image_available: Vec<vk::Semaphore>,
+    may_begin_drawing: Vec<vk::Fence>,
+    // One per image, presenting an image waits on its own semaphore so a later frame can't
+    // signal it while the presentation engine still holds it.
+    rendering_finished: Vec<vk::Semaphore>,
+    amount_of_images: u32,
+    current_frame: usize,
+    // Set when acquiring or presenting reports VK_SUBOPTIMAL_KHR, the renderer recreates the
+    // swapchain before its next frame.
+    pub(super) suboptimal: bool,
+    scene: AttachmentBuffer,
+    depth: AttachmentBuffer,
+}
+
+pub(super) struct FrameBufferInfo {
+    pub(super) semaphores_available: [vk::Semaphore; 1],
+    pub(super) semaphores_finished: [vk::Semaphore; 1],
+    pub(super) may_begin_fence: vk::Fence,
+    pub(super) waiting_stages: [PipelineStageFlags; 1],
+    pub(super) image_index: u32,
+    // Which frame in flight this is, per-frame resources are indexed with it.
+    pub(super) frame_index: usize,
+    // The output pass's, drawing to the swapchain image.
+    pub(super) framebuffer: Framebuffer,
+    // The main pass's, drawing the scene before it's tonemapped.
+    pub(super) scene_framebuffer: Framebuffer,
+    pub(super) image_view: vk::ImageView,
+    pub(super) queue: Queue,
+}
+
+// SDR swapchain formats in order of preference. The output pass writes linear colour and leaves
+// the encoding to the format, so only these come out with the right brightness.
+const SRGB_FORMATS: [vk::Format; 3] = [
+    vk::Format::B8G8R8A8_SRGB,
+    vk::Format::R8G8B8A8_SRGB,
+    vk::Format::A8B8G8R8_SRGB_PACK32,
+];
+
+// With `hdr10` an HDR10 (PQ) format is preferred when there is one. Otherwise the first of
+// `SRGB_FORMATS` in the sRGB colour space, then any format in that colour space, so HDR formats
+// listed first don't get picked by accident. The surface has to offer at least one format.
+pub(super) fn choose_surface_format(formats: &[SurfaceFormatKHR], hdr10: bool) -> SurfaceFormatKHR {
+    // A lone UNDEFINED entry, from older drivers, means any format can be used.
+    if let [format] = formats {
+        if format.format == vk::Format::UNDEFINED {
+            return SurfaceFormatKHR {
+                format: SRGB_FORMATS[0],
+                color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+            };
+        }
+    }
+    let hdr10_format = formats
+        .iter()
+        .filter(|format| format.color_space == vk::ColorSpaceKHR::HDR10_ST2084_EXT)
+        .max_by_key(|format| format.format == vk::Format::A2B10G10R10_UNORM_PACK32);
+    if let (true, Some(format)) = (hdr10, hdr10_format) {
+        return *format;
+    }
+    let srgb_space =
+        |format: &&SurfaceFormatKHR| format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR;
+    let preferred = SRGB_FORMATS.iter().find_map(|preferred| {
+        formats
+            .iter()
+            .filter(srgb_space)
+            .find(|format| format.format == *preferred)
+    });
+    if let Some(format) = preferred {
+        return *format;
+    }
+    let fallback = formats.iter().find(srgb_space).unwrap_or(&formats[0]);
+    warn!(
+        "No sRGB surface format, colours will be too dark on {:?} in {:?}",
+        fallback.format, fallback.color_space
+    );
+    *fallback
+}
+
+// The main pass draws the scene in linear light at this format, and the output pass tonemaps it
+// onto the swapchain image. Every device can render to, blend and sample it.
+pub(super) const SCENE_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+// Depth formats in order of preference. Only depth is used, but the spec guarantees one of the
+// two combined depth/stencil formats, so they cover devices without plain D32_SFLOAT.
+const DEPTH_FORMATS: [vk::Format; 3] = [
+    vk::Format::D32_SFLOAT,
+    vk::Format::D32_SFLOAT_S8_UINT,
+    vk::Format::D24_UNORM_S8_UINT,
+];
+
+// The first of `DEPTH_FORMATS` the device can render depth to with optimal tiling.
+pub(super) fn choose_depth_format(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+) -> Option<vk::Format> {
+    DEPTH_FORMATS.into_iter().find(|format| {
+        let properties =
+            unsafe { instance.get_physical_device_format_properties(physical_device, *format) };
+        properties
+            .optimal_tiling_features
+            .contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+    })
+}
+
+fn has_stencil(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::D32_SFLOAT_S8_UINT | vk::Format::D24_UNORM_S8_UINT
+    )
+}
+
+// The main pass's scene or depth attachment, sized to the swapchain it's created with.
+struct AttachmentBuffer {
+    image: Image,
+    image_view: vk::ImageView,
+}
+
+impl AttachmentBuffer {
+    fn new(
+        logical_device: &Device,
+        allocator: &mut Allocator,
+        queue_families: &QueueFamilies,
+        format: vk::Format,
+        extent: vk::Extent2D,
+        usage: vk::ImageUsageFlags,
+        name: &str,
+    ) -> Result<AttachmentBuffer, vk::Result> {
+        let queuefamilies = [queue_families.graphics];
+        let image_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(format)
+            .extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .queue_family_indices(&queuefamilies);
+        let image = Image::new(
+            allocator,
+            logical_device,
+            &image_info,
+            MemoryLocation::GpuOnly,
+            name,
+            None,
+        )?;
+
+        // An attachment view of a combined format has to cover both aspects.
+        let aspect_mask = match (is_depth_format(format), has_stencil(format)) {
+            (true, true) => vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
+            (true, false) => vk::ImageAspectFlags::DEPTH,
+            (false, _) => vk::ImageAspectFlags::COLOR,
+        };
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(aspect_mask)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1);
+        let imageview_create_info = vk::ImageViewCreateInfo::builder()
+            .image(image.image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(format)
+            .subresource_range(*subresource_range);
+        let image_view = unsafe { logical_device.create_image_view(&imageview_create_info, None) }?;
+        Ok(AttachmentBuffer { image, image_view })
+    }
+
+    unsafe fn cleanup(&mut self, logical_device: &Device, allocator: &mut Allocator) {
+        self.image.cleanup(allocator, logical_device);
+        logical_device.destroy_image_view(self.image_view, None);
+    }
+}
+
+// What `Swapchain::init` makes the swapchain and its attachments with.
+pub(super) struct SwapchainDesc {
+    pub(super) surface_format: SurfaceFormatKHR,
+    pub(super) depth_format: vk::Format,
+    // Falls back to FIFO when the surface doesn't support it.
+    pub(super) present_mode: vk::PresentModeKHR,
+    pub(super) frames_in_flight: usize,
+}
+
+// What a swapchain has one of per image.
+struct ImageResources {
+    image_views: Vec<vk::ImageView>,
+    frame_buffers: Vec<vk::Framebuffer>,
+    rendering_finished: Vec<vk::Semaphore>,
+}
+
+// What's left of a swapchain after `change_present_mode` replaced it.
+pub(super) struct RetiredSwapchain {
+    loader: khr::Swapchain,
+    swapchain: vk::SwapchainKHR,
+    image_views: Vec<vk::ImageView>,
+    frame_buffers: Vec<vk::Framebuffer>,
+    // Presents still queued on the old images may wait on these.
+    rendering_finished: Vec<vk::Semaphore>,
+}
+
+impl RetiredSwapchain {
+    pub(super) unsafe fn cleanup(&self, logical_device: &Device) {
+        for fb in &self.frame_buffers {
+            logical_device.destroy_framebuffer(*fb, None);
+        }
+        for iv in &self.image_views {
+            logical_device.destroy_image_view(*iv, None);
+        }
+        for semaphore in &self.rendering_finished {
+            logical_device.destroy_semaphore(*semaphore, None);
+        }
+        self.loader.destroy_swapchain(self.swapchain, None);
+    }
+}
+
+fn create_swapchain(
+    loader: &khr::Swapchain,
+    surface: &Surface,
+    surface_capabilities: &vk::SurfaceCapabilitiesKHR,
+    queue_families: &QueueFamilies,
+    surface_format: SurfaceFormatKHR,
+    present_mode: vk::PresentModeKHR,
+    old_swapchain: vk::SwapchainKHR,
+) -> Result<vk::SwapchainKHR, vk::Result> {
+    let queuefamilies = [queue_families.graphics];
+    let swapchain_create_info = vk::SwapchainCreateInfoKHR::builder()
+        .surface(surface.surface)
+        .min_image_count(
+            3.max(surface_capabilities.min_image_count)
+                .min(surface_capabilities.max_image_count),
+        )
+        .image_format(surface_format.format)
+        .image_color_space(surface_format.color_space)
+        .image_extent(surface_capabilities.current_extent)
+        .image_array_layers(1)
+        .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+        .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
+        .queue_family_indices(&queuefamilies)
+        .pre_transform(surface_capabilities.current_transform)
+        .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
+        .present_mode(present_mode)
+        .old_swapchain(old_swapchain);
+    unsafe { loader.create_swapchain(&swapchain_create_info, None) }
+}
+
+fn create_image_views(
+    logical_device: &Device,
+    images: &[vk::Image],
+    surface_format: SurfaceFormatKHR,
+) -> Result<Vec<vk::ImageView>, vk::Result> {
+    let mut image_views = Vec::with_capacity(images.len());
+    for image in images {
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1);
+        let imageview_create_info = vk::ImageViewCreateInfo::builder()
+            .image(*image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(surface_format.format)
+            .subresource_range(*subresource_range);
+
+        match unsafe { logical_device.create_image_view(&imageview_create_info, None) } {
+            Ok(imageview) => image_views.push(imageview),
+            Err(e) => {
+                for imageview in image_views {
+                    unsafe { logical_device.destroy_image_view(imageview, None) };
+                }
+                return Err(e);
+            }
+        }
+    }
+    Ok(image_views)
+}
+
+fn create_semaphores(
+    logical_device: &Device,
+    count: usize,
+) -> Result<Vec<vk::Semaphore>, vk::Result> {
+    let semaphoreinfo = vk::SemaphoreCreateInfo::builder();
+    let mut semaphores = Vec::with_capacity(count);
+    for _ in 0..count {
+        match unsafe { logical_device.create_semaphore(&semaphoreinfo, None) } {
+            Ok(semaphore) => semaphores.push(semaphore),
+            Err(e) => {
+                for semaphore in semaphores {
+                    unsafe { logical_device.destroy_semaphore(semaphore, None) };
+                }
+                return Err(e);
+            }
+        }
+    }
+    Ok(semaphores)
+}
+
+impl Swapchain {
+    pub(super) fn init(
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+        logical_device: &Device,
+        allocator: &mut Allocator,
+        surface: &Surface,
+        queue_families: &QueueFamilies,
+        desc: SwapchainDesc,
+    ) -> Result<Swapchain, vk::Result> {
+        let SwapchainDesc {
+            surface_format,
+            depth_format,
+            present_mode,
+            frames_in_flight,
+        } = desc;
+        let surface_capabilities = surface.get_capabilities(physical_device)?;
+        let extent = surface_capabilities.current_extent;
+        let surface_present_modes = surface.get_present_modes(physical_device)?;
+        // FIFO is the only mode every device has to support.
+        let present_mode = if surface_present_modes.contains(&present_mode) {
+            present_mode
+        } else {
+            vk::PresentModeKHR::FIFO
+        };
+
+        let swapchain_loader = khr::Swapchain::new(&instance, &logical_device);
+        let swapchain = create_swapchain(
+            &swapchain_loader,
+            surface,
+            &surface_capabilities,
+            queue_families,
+            surface_format,
+            present_mode,
+            vk::SwapchainKHR::null(),
+        )?;
+        let swapchain_images = unsafe { swapchain_loader.get_swapchain_images(swapchain)? };
+        let amount_of_images = swapchain_images.len() as u32;
+        let image_views = create_image_views(logical_device, &swapchain_images, surface_format)?;
+
+        // Recreated with the swapchain, so they always match the extent.
+        let scene = AttachmentBuffer::new(
+            logical_device,
+            allocator,
+            queue_families,
+            SCENE_FORMAT,
+            extent,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            "scene colour",
+        )?;
+        let depth = AttachmentBuffer::new(
+            logical_device,
+            allocator,
+            queue_families,
+            depth_format,
+            extent,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            "depth buffer",
+        )?;
+
+        let mut image_available = vec![];
+        let mut may_begin_drawing = vec![];
+        let semaphoreinfo = vk::SemaphoreCreateInfo::builder();
+        let fenceinfo = vk::FenceCreateInfo::builder().flags(vk::FenceCreateFlags::SIGNALED);
+        for _ in 0..frames_in_flight {
+            let semaphore_available =
+                unsafe { logical_device.create_semaphore(&semaphoreinfo, None) }?;
+            image_available.push(semaphore_available);
+            let fence = unsafe { logical_device.create_fence(&fenceinfo, None) }?;
+            may_begin_drawing.push(fence);
+        }
+        let rendering_finished = create_semaphores(logical_device, amount_of_images as usize)?;
+
+        Ok(Swapchain {
+            loader: swapchain_loader,
+            swapchain,
+            image_views,
+            extent,
+            surface_format,
+            frame_buffers: vec![],
+            scene_framebuffer: vk::Framebuffer::null(),
+            amount_of_images,
+            image_available,
+            may_begin_drawing,
+            rendering_finished,
+            current_frame: 0,
+            suboptimal: false,
+            scene,
+            depth,
+        })
+    }
+
+    // `renderpass` is the main pass from `init_renderpass` and `output_pass` the one from
+    // `init_output_pass`.
+    pub(super) fn create_framebuffers(
+        &mut self,
+        logical_device: &ash::Device,
+        renderpass: vk::RenderPass,
+        output_pass: vk::RenderPass,
+    ) -> Result<(), vk::Result> {
+        let attachments = [self.scene.image_view, self.depth.image_view];
+        let framebuffer_info = vk::FramebufferCreateInfo::builder()
+            .render_pass(renderpass)
+            .attachments(&attachments)
+            .width(self.extent.width)
+            .height(self.extent.height)
+            .layers(1);
+        self.scene_framebuffer =
+            unsafe { logical_device.create_framebuffer(&framebuffer_info, None) }?;
+        self.frame_buffers =
+            self.framebuffers_for(logical_device, output_pass, &self.image_views)?;
+        Ok(())
+    }
+
+    fn framebuffers_for(
+        &self,
+        logical_device: &ash::Device,
+        output_pass: vk::RenderPass,
+        image_views: &[vk::ImageView],
+    ) -> Result<Vec<vk::Framebuffer>, vk::Result> {
+        let mut frame_buffers = Vec::with_capacity(image_views.len());
+        for iv in image_views {
+            let iview = [*iv];
+            let framebuffer_info = vk::FramebufferCreateInfo::builder()
+                .render_pass(output_pass)
+                .attachments(&iview)
+                .width(self.extent.width)
+                .height(self.extent.height)
+                .layers(1);
+            match unsafe { logical_device.create_framebuffer(&framebuffer_info, None) } {
+                Ok(fb) => frame_buffers.push(fb),
+                Err(e) => {
+                    for fb in frame_buffers {
+                        unsafe { logical_device.destroy_framebuffer(fb, None) };
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(frame_buffers)
+    }
+
+    // The scene the main pass draws, for the output pass to sample.
+    pub(super) fn scene_view(&self) -> vk::ImageView {
+        self.scene.image_view
+    }
+
+    // Swaps in a new swapchain presenting with `present_mode`, keeping the scene and depth
+    // buffers and per-frame sync objects so nothing else has to be rebuilt. Frames still in
+    // flight may be using the old images and their presents waiting on the old images'
+    // semaphores, so those are handed back to be destroyed once the frames are done. Fails with
+    // ERROR_OUT_OF_DATE_KHR when the surface size or image count changed underneath, the whole
+    // swapchain has to be recreated then.
+    pub(super) fn change_present_mode(
+        &mut self,
+        physical_device: vk::PhysicalDevice,
+        logical_device: &Device,
+        surface: &Surface,
+        queue_families: &QueueFamilies,
+        output_pass: vk::RenderPass,
+        present_mode: vk::PresentModeKHR,
+    ) -> Result<RetiredSwapchain, vk::Result> {
+        let surface_capabilities = surface.get_capabilities(physical_device)?;
+        if surface_capabilities.current_extent != self.extent {
+            return Err(vk::Result::ERROR_OUT_OF_DATE_KHR);
+        }
+        let swapchain = create_swapchain(
+            &self.loader,
+            surface,
+            &surface_capabilities,
+            queue_families,
+            self.surface_format,
+            present_mode,
+            self.swapchain,
+        )?;
+        let ImageResources {
+            image_views,
+            frame_buffers,
+            rendering_finished,
+        } = match self.image_resources(logical_device, output_pass, swapchain) {
+            Ok(resources) => resources,
+            Err(e) => {
+                // The old swapchain can't present any more, but it can still be cleaned up
+                // as usual.
+                unsafe { self.loader.destroy_swapchain(swapchain, None) };
+                return Err(e);
+            }
+        };
+        Ok(RetiredSwapchain {
+            loader: self.loader.clone(),
+            swapchain: std::mem::replace(&mut self.swapchain, swapchain),
+            image_views: std::mem::replace(&mut self.image_views, image_views),
+            frame_buffers: std::mem::replace(&mut self.frame_buffers, frame_buffers),
+            rendering_finished: std::mem::replace(&mut self.rendering_finished, rendering_finished),
+        })
+    }
+
+    // The image views, output pass framebuffers and present semaphores of a swapchain replacing
+    // this one, nothing is left behind when it fails.
+    fn image_resources(
+        &self,
+        logical_device: &Device,
+        output_pass: vk::RenderPass,
+        swapchain: vk::SwapchainKHR,
+    ) -> Result<ImageResources, vk::Result> {
+        let swapchain_images = unsafe { self.loader.get_swapchain_images(swapchain) }?;
+        if swapchain_images.len() != self.amount_of_images as usize {
+            return Err(vk::Result::ERROR_OUT_OF_DATE_KHR);
+        }
+        let image_views =
+            create_image_views(logical_device, &swapchain_images, self.surface_format)?;
+        let created = self
+            .framebuffers_for(logical_device, output_pass, &image_views)
+            .and_then(|frame_buffers| {
+                match create_semaphores(logical_device, swapchain_images.len()) {
+                    Ok(semaphores) => Ok((frame_buffers, semaphores)),
+                    Err(e) => {
+                        for fb in frame_buffers {
+                            unsafe { logical_device.destroy_framebuffer(fb, None) };
+                        }
+                        Err(e)
+                    }
+                }
+            });
+        match created {
+            Ok((frame_buffers, rendering_finished)) => Ok(ImageResources {
+                image_views,
+                frame_buffers,
+                rendering_finished,
+            }),
+            Err(e) => {
+                for iv in image_views {
+                    unsafe { logical_device.destroy_image_view(iv, None) };
+                }
+                Err(e)
+            }
+        }
+    }
+
+    pub(super) fn get_next_framebuffer(
+        &mut self,
+        logical_device: &Device,
+        queue: Queue,
+    ) -> Result<FrameBufferInfo, vk::Result> {
+        self.current_frame = (self.current_frame + 1) % self.may_begin_drawing.len();
+        let fence = self.may_begin_drawing[self.current_frame];
+        // The last submission of this frame has to be done before its semaphore and everything
+        // else the frame owns can be used again.
+        unsafe { logical_device.wait_for_fences(&[fence], true, std::u64::MAX) }?;
+        // A suboptimal image can still be drawn and presented, the swapchain is recreated after.
+        let (image_index, suboptimal) = unsafe {
+            self.loader.acquire_next_image(
+                self.swapchain,
+                std::u64::MAX,
+                self.image_available[self.current_frame],
+                ash::vk::Fence::null(),
+            )
+        }?;
+        self.suboptimal |= suboptimal;
+        // Only once an image was acquired, a fence reset without a submission would never be
+        // signalled again.
+        unsafe { logical_device.reset_fences(&[fence]) }?;
+
+        let semaphores_available = [self.image_available[self.current_frame]];
+        let waiting_stages = [ash::vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
+        let semaphores_finished = [self.rendering_finished[image_index as usize]];
+
+        Ok(FrameBufferInfo {
+            semaphores_available,
+            waiting_stages,
+            semaphores_finished,
+            framebuffer: self.frame_buffers[image_index as usize],
+            scene_framebuffer: self.scene_framebuffer,
+            image_view: self.image_views[image_index as usize],
+            image_index,
+            frame_index: self.current_frame,
+            may_begin_fence: fence,
+            queue,
+        })
+    }
+
+    // `present_time` tags the present for VK_GOOGLE_display_timing. Sets `suboptimal` when the
+    // swapchain no longer matches the surface exactly.
+    pub(super) fn present_framebuffer(
+        &mut self,
+        frame_buffer_info: &FrameBufferInfo,
+        present_time: Option<vk::PresentTimeGOOGLE>,
+    ) -> Result<(), vk::Result> {
+        let swapchains = [self.swapchain];
+        let indices = [frame_buffer_info.image_index as u32];
+        let present_times: Vec<_> = present_time.into_iter().collect();
+        let mut present_times_info = vk::PresentTimesInfoGOOGLE::builder().times(&present_times);
+        let mut present_info = ash::vk::PresentInfoKHR::builder()
+            .wait_semaphores(&frame_buffer_info.semaphores_finished)
+            .swapchains(&swapchains)
+            .image_indices(&indices);
+        if !present_times.is_empty() {
+            present_info = present_info.push_next(&mut present_times_info);
+        }
+        let suboptimal = unsafe {
+            self.loader
+                .queue_present(frame_buffer_info.queue, &present_info)
+        }?;
+        self.suboptimal |= suboptimal;
+        Ok(())
+    }
+
+    pub(super) unsafe fn cleanup(
+        &mut self,
+        logical_device: &Device,
+        allocator: &mut Allocator,
+    ) -> () {
+        self.depth.cleanup(logical_device, allocator);
+        self.scene.cleanup(logical_device, allocator);
+        logical_device.destroy_framebuffer(self.scene_framebuffer, None);
+
+        for fence in &self.may_begin_drawing {
+            logical_device.destroy_fence(*fence, None);
+        }
+        for semaphore in &self.image_available {
+            logical_device.destroy_semaphore(*semaphore, None);
+        }
+        for semaphore in &self.rendering_finished {
+            logical_device.destroy_semaphore(*semaphore, None);
+        }
+        for fb in &self.frame_buffers {
+            logical_device.destroy_framebuffer(*fb, None);
+        }
+        for iv in &self.image_views {
+            logical_device.destroy_image_view(*iv, None);
+        }
+        self.loader.destroy_swapchain(self.swapchain, None)
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.frame_buffers.len()
+    }
+}
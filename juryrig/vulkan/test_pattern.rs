@@ -0,0 +1,155 @@
+use ash::vk;
+
+use super::{debug, pipeline::DYNAMIC_STATES};
+
+/// Patterns for checking the surface format visually, see shaders/test_pattern.glsl for what each
+/// one should look like on a correctly configured display.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TestPattern {
+    /// Grey, red, green and blue ramps, smooth and in 16 steps.
+    GradientRamps = 0,
+    /// A 1px checkerboard next to linear and sRGB-encoded 50% grey.
+    Checkerboard = 1,
+}
+
+// A fullscreen pass drawing one of the test patterns instead of the scene.
+pub(super) struct TestPatternPipeline {
+    pub(super) pipeline: vk::Pipeline,
+    pub(super) layout: vk::PipelineLayout,
+}
+
+impl TestPatternPipeline {
+    pub(super) fn init(
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+    ) -> Result<TestPatternPipeline, vk::Result> {
+        let vertex_shader_create_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("./shaders/fullscreen.glsl", kind: vert));
+        let vertex_shader_module =
+            unsafe { logical_device.create_shader_module(&vertex_shader_create_info, None)? };
+
+        let fragment_shader_create_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("./shaders/test_pattern.glsl", kind: frag));
+        let fragment_shader_module =
+            unsafe { logical_device.create_shader_module(&fragment_shader_create_info, None)? };
+
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vertex_shader_module)
+                .name(&main_function_name)
+                .build(),
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(fragment_shader_module)
+                .name(&main_function_name)
+                .build(),
+        ];
+
+        // The fullscreen triangle is generated from gl_VertexIndex.
+        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder();
+        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+        let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&DYNAMIC_STATES);
+
+        let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+            .line_width(1.0)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL);
+
+        let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+        // No blending, the pattern has to reach the swapchain exactly as the shader wrote it.
+        let colourblend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(false)
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+            .build()];
+        let colourblend_info =
+            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colourblend_attachments);
+
+        let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .size(std::mem::size_of::<u32>() as u32)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build()];
+        let pipelinelayout_info =
+            vk::PipelineLayoutCreateInfo::builder().push_constant_ranges(&push_constant_ranges);
+        let layout = unsafe { logical_device.create_pipeline_layout(&pipelinelayout_info, None) }?;
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_info)
+            .input_assembly_state(&input_assembly_info)
+            .viewport_state(&viewport_info)
+            .dynamic_state(&dynamic_state_info)
+            .rasterization_state(&rasterizer_info)
+            .multisample_state(&multisampler_info)
+            .color_blend_state(&colourblend_info)
+            .depth_stencil_state(&depth_stencil_state)
+            .layout(layout)
+            .render_pass(*renderpass)
+            .subpass(0);
+
+        let pipeline = unsafe {
+            logical_device
+                .create_graphics_pipelines(
+                    vk::PipelineCache::null(),
+                    &[pipeline_info.build()],
+                    None,
+                )
+                .map_err(|(_, e)| e)
+        }?[0];
+        debug::set_object_name(logical_device, pipeline, "test pattern pipeline");
+        unsafe {
+            logical_device.destroy_shader_module(fragment_shader_module, None);
+            logical_device.destroy_shader_module(vertex_shader_module, None);
+        }
+        Ok(TestPatternPipeline { pipeline, layout })
+    }
+
+    pub(super) fn draw(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        pattern: TestPattern,
+    ) {
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            logical_device.cmd_push_constants(
+                command_buffer,
+                self.layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                &(pattern as u32).to_ne_bytes(),
+            );
+            logical_device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+
+    pub(super) fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            logical_device.destroy_pipeline(self.pipeline, None);
+            logical_device.destroy_pipeline_layout(self.layout, None);
+        }
+    }
+}
@@ -0,0 +1,421 @@
+use ash::{vk, Device};
+use gpu_allocator::vulkan::Allocator;
+
+use super::{
+    bounds::MeshBounds,
+    buffer::{Buffer, Transfer},
+    debug,
+    mesh::StaticMesh,
+    pipeline::DYNAMIC_STATES,
+    texture::TextureHandle,
+    VertexBufferBindings,
+};
+
+// Glyphs past this many in a frame are dropped.
+const MAX_GLYPHS: usize = 16384;
+
+/// A monospace bitmap font: every glyph in its own cell of a grid, in character order from the
+/// top left.
+#[derive(Clone)]
+pub struct Font {
+    pub texture: TextureHandle,
+    pub columns: u32,
+    pub rows: u32,
+    /// The character in the top left cell.
+    pub first: char,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct GlyphInstance {
+    // Pixels from the top left of the screen, xy: position, zw: size.
+    rect: [f32; 4],
+    // xy: top left, zw: size.
+    uv_rect: [f32; 4],
+    colour: [f32; 4],
+}
+
+// Draws text queued during the frame as instanced quads, one instance per glyph, on top of
+// everything else in the main pass. Only created once a font is set.
+pub(super) struct TextRenderer {
+    pipeline: vk::Pipeline,
+    layout: vk::PipelineLayout,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    // Nearest and clamped, so glyphs stay crisp and don't pick up their neighbours.
+    sampler: vk::Sampler,
+    quad: StaticMesh,
+    // One per frame in flight.
+    instance_buffers: Vec<Buffer<GlyphInstance>>,
+    glyphs: Vec<GlyphInstance>,
+    font: Font,
+    // Width over height of a glyph cell.
+    glyph_aspect: f32,
+}
+
+impl TextRenderer {
+    pub(super) fn new(
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        renderpass: &vk::RenderPass,
+        frames_in_flight: usize,
+        mut transfer: Transfer,
+        font: Font,
+        glyph_aspect: f32,
+    ) -> Result<TextRenderer, vk::Result> {
+        let layout_bindings = [vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build()];
+        let descriptor_set_layout_info =
+            vk::DescriptorSetLayoutCreateInfo::builder().bindings(&layout_bindings);
+        let descriptor_set_layout = unsafe {
+            logical_device.create_descriptor_set_layout(&descriptor_set_layout_info, None)
+        }?;
+
+        let descriptor_pool_sizes = [vk::DescriptorPoolSize::builder()
+            .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(frames_in_flight as u32)
+            .build()];
+        let descriptor_pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .pool_sizes(&descriptor_pool_sizes)
+            .max_sets(frames_in_flight as u32);
+        let descriptor_pool =
+            unsafe { logical_device.create_descriptor_pool(&descriptor_pool_info, None) }?;
+        let descriptor_set_layouts = vec![descriptor_set_layout; frames_in_flight];
+        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&descriptor_set_layouts);
+        let descriptor_sets =
+            unsafe { logical_device.allocate_descriptor_sets(&descriptor_set_allocate_info) }?;
+
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .size(std::mem::size_of::<[f32; 2]>() as u32)
+            .stage_flags(vk::ShaderStageFlags::VERTEX)
+            .build()];
+        let descriptor_set_layouts = [descriptor_set_layout];
+        let pipelinelayout_info = vk::PipelineLayoutCreateInfo::builder()
+            .push_constant_ranges(&push_constant_ranges)
+            .set_layouts(&descriptor_set_layouts);
+        let layout = unsafe { logical_device.create_pipeline_layout(&pipelinelayout_info, None) }?;
+
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::NEAREST)
+            .min_filter(vk::Filter::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST);
+        let sampler = unsafe { logical_device.create_sampler(&sampler_info, None) }?;
+
+        let corners: [[f32; 2]; 4] = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]];
+        let quad = StaticMesh::new(
+            allocator,
+            logical_device,
+            &[0, 1, 2, 2, 1, 3],
+            &corners,
+            // Never culled, text isn't drawn as entities.
+            MeshBounds::default(),
+            &mut transfer,
+        )?;
+
+        let instance_buffers = (0..frames_in_flight)
+            .map(|_| {
+                Buffer::<GlyphInstance>::new(
+                    allocator,
+                    logical_device,
+                    MAX_GLYPHS as u64,
+                    vk::BufferUsageFlags::VERTEX_BUFFER,
+                    "glyphs",
+                    gpu_allocator::MemoryLocation::CpuToGpu,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let pipeline = Self::create_pipeline(logical_device, renderpass, layout)?;
+
+        Ok(TextRenderer {
+            pipeline,
+            layout,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_sets,
+            sampler,
+            quad,
+            instance_buffers,
+            glyphs: vec![],
+            font,
+            glyph_aspect,
+        })
+    }
+
+    fn create_pipeline(
+        logical_device: &Device,
+        renderpass: &vk::RenderPass,
+        layout: vk::PipelineLayout,
+    ) -> Result<vk::Pipeline, vk::Result> {
+        let vertex_shader_create_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("./shaders/text_vertex.glsl", kind: vert));
+        let vertex_shader_module =
+            unsafe { logical_device.create_shader_module(&vertex_shader_create_info, None)? };
+
+        let fragment_shader_create_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("./shaders/text_fragment.glsl", kind: frag));
+        let fragment_shader_module =
+            unsafe { logical_device.create_shader_module(&fragment_shader_create_info, None)? };
+
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vertex_shader_module)
+                .name(&main_function_name)
+                .build(),
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(fragment_shader_module)
+                .name(&main_function_name)
+                .build(),
+        ];
+
+        let instance_attribute = |location: u32, offset: u32, format: vk::Format| {
+            vk::VertexInputAttributeDescription::builder()
+                .binding(VertexBufferBindings::InstanceBuffer as u32)
+                .location(location)
+                .offset(offset)
+                .format(format)
+                .build()
+        };
+        let vertex_attrib_descs = [
+            instance_attribute(0, 0, vk::Format::R32G32B32A32_SFLOAT),
+            instance_attribute(1, 16, vk::Format::R32G32B32A32_SFLOAT),
+            instance_attribute(2, 32, vk::Format::R32G32B32A32_SFLOAT),
+            vk::VertexInputAttributeDescription::builder()
+                .binding(VertexBufferBindings::MeshBuffer as u32)
+                .location(3)
+                .offset(0)
+                .format(vk::Format::R32G32_SFLOAT)
+                .build(),
+        ];
+        let vertex_binding_descs = [
+            vk::VertexInputBindingDescription::builder()
+                .binding(VertexBufferBindings::InstanceBuffer as u32)
+                .stride(std::mem::size_of::<GlyphInstance>() as u32)
+                .input_rate(vk::VertexInputRate::INSTANCE)
+                .build(),
+            vk::VertexInputBindingDescription::builder()
+                .binding(VertexBufferBindings::MeshBuffer as u32)
+                .stride(std::mem::size_of::<[f32; 2]>() as u32)
+                .input_rate(vk::VertexInputRate::VERTEX)
+                .build(),
+        ];
+        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder()
+            .vertex_attribute_descriptions(&vertex_attrib_descs)
+            .vertex_binding_descriptions(&vertex_binding_descs);
+        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+        let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&DYNAMIC_STATES);
+
+        let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+            .line_width(1.0)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL);
+
+        let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+        let colourblend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .alpha_blend_op(vk::BlendOp::ADD)
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+            .build()];
+        let colourblend_info =
+            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colourblend_attachments);
+
+        // Text goes over the scene no matter how close the geometry is.
+        let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_info)
+            .input_assembly_state(&input_assembly_info)
+            .viewport_state(&viewport_info)
+            .dynamic_state(&dynamic_state_info)
+            .rasterization_state(&rasterizer_info)
+            .multisample_state(&multisampler_info)
+            .color_blend_state(&colourblend_info)
+            .depth_stencil_state(&depth_stencil_state)
+            .layout(layout)
+            .render_pass(*renderpass)
+            .subpass(0);
+
+        let pipeline = unsafe {
+            logical_device
+                .create_graphics_pipelines(
+                    vk::PipelineCache::null(),
+                    &[pipeline_info.build()],
+                    None,
+                )
+                .map_err(|(_, e)| e)
+        }?[0];
+        debug::set_object_name(logical_device, pipeline, "text pipeline");
+        unsafe {
+            logical_device.destroy_shader_module(fragment_shader_module, None);
+            logical_device.destroy_shader_module(vertex_shader_module, None);
+        }
+        Ok(pipeline)
+    }
+
+    pub(super) fn font(&self) -> &Font {
+        &self.font
+    }
+
+    // How far each character moves the next one along at line height `size`.
+    pub(super) fn glyph_width(&self, size: f32) -> f32 {
+        size * self.glyph_aspect
+    }
+
+    pub(super) fn set_font(&mut self, font: Font, glyph_aspect: f32) {
+        self.font = font;
+        self.glyph_aspect = glyph_aspect;
+    }
+
+    // Lays out `text` starting at `position`, `size` is the height of a line in pixels. Newlines
+    // start a new line, characters the font doesn't have leave a gap.
+    pub(super) fn queue(&mut self, text: &str, position: [f32; 2], size: f32, colour: [f32; 4]) {
+        let glyph_count = self.font.columns * self.font.rows;
+        let cell = [1.0 / self.font.columns as f32, 1.0 / self.font.rows as f32];
+        let advance = size * self.glyph_aspect;
+        let [mut x, mut y] = position;
+        for character in text.chars() {
+            if character == '\n' {
+                x = position[0];
+                y += size;
+                continue;
+            }
+            let index = (character as u32).wrapping_sub(self.font.first as u32);
+            if index < glyph_count && self.glyphs.len() < MAX_GLYPHS {
+                let (column, row) = (index % self.font.columns, index / self.font.columns);
+                self.glyphs.push(GlyphInstance {
+                    rect: [x, y, advance, size],
+                    uv_rect: [
+                        column as f32 * cell[0],
+                        row as f32 * cell[1],
+                        cell[0],
+                        cell[1],
+                    ],
+                    colour,
+                });
+            }
+            x += advance;
+        }
+    }
+
+    // Draws everything queued since the last call and clears the queue. Must be recorded inside
+    // the main render pass.
+    pub(super) fn draw(
+        &mut self,
+        logical_device: &Device,
+        command_buffer: vk::CommandBuffer,
+        slot: usize,
+        extent: vk::Extent2D,
+        font_view: vk::ImageView,
+    ) {
+        if self.glyphs.is_empty() {
+            return;
+        }
+        self.instance_buffers[slot]
+            .copy(&self.glyphs)
+            .expect("glyph buffer is not mapped");
+
+        let image_info = [vk::DescriptorImageInfo::builder()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(font_view)
+            .sampler(self.sampler)
+            .build()];
+        let descriptor_writes = [vk::WriteDescriptorSet::builder()
+            .dst_set(self.descriptor_sets[slot])
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_info)
+            .build()];
+        let screen_size = [extent.width as f32, extent.height as f32];
+        unsafe {
+            logical_device.update_descriptor_sets(&descriptor_writes, &[]);
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            logical_device.cmd_push_constants(
+                command_buffer,
+                self.layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                std::slice::from_raw_parts(
+                    screen_size.as_ptr() as *const u8,
+                    std::mem::size_of_val(&screen_size),
+                ),
+            );
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout,
+                0,
+                &[self.descriptor_sets[slot]],
+                &[],
+            );
+            self.quad.bind(logical_device, command_buffer);
+            logical_device.cmd_bind_vertex_buffers(
+                command_buffer,
+                VertexBufferBindings::InstanceBuffer as u32,
+                &[self.instance_buffers[slot].buffer],
+                &[0],
+            );
+            logical_device.cmd_draw_indexed(
+                command_buffer,
+                self.quad.index_count() as u32,
+                self.glyphs.len() as u32,
+                0,
+                0,
+                0,
+            );
+        }
+        self.glyphs.clear();
+    }
+
+    pub(super) fn cleanup(&mut self, allocator: &mut Allocator, logical_device: &Device) {
+        unsafe {
+            for buffer in &mut self.instance_buffers {
+                buffer.cleanup(allocator, logical_device);
+            }
+            self.quad.cleanup(allocator, logical_device);
+            logical_device.destroy_sampler(self.sampler, None);
+            logical_device.destroy_pipeline(self.pipeline, None);
+            logical_device.destroy_pipeline_layout(self.layout, None);
+            logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+            logical_device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
@@ -0,0 +1,1393 @@
+use std::collections::HashMap;
+
+use ash::{vk, Device};
+use gpu_allocator::vulkan::{Allocation, AllocationCreateDesc, AllocationScheme, Allocator};
+use half::f16;
+use na::Vector3;
+
+use crate::{
+    jr_image::{HDRImage, RGBAImage, RGBAPixel},
+    jr_texture::{BlockFormat, CompressedImage},
+};
+
+use super::{
+    buffer::{Submission, Transfer, TransientPool},
+    debug,
+    error::{InitError, RuntimeError},
+    gc::{Collector, GcConfig, HandleRef},
+    leaks,
+    sampler::{SamplerCache, SamplerDesc},
+};
+
+use uuid::Uuid;
+
+// The size and format of a texture's image.
+#[derive(Clone, Copy)]
+pub(super) struct TextureDesc {
+    pub(super) width: u32,
+    pub(super) height: u32,
+    pub(super) format: vk::Format,
+    // On top of sampling and being copied to.
+    pub(super) usage: vk::ImageUsageFlags,
+}
+
+pub(super) struct Texture {
+    pub(super) image: vk::Image,
+    pub width: u32,
+    pub height: u32,
+    pub(super) format: vk::Format,
+    pub(super) image_view: vk::ImageView,
+    // From the store's `SamplerCache`, set when the texture is registered.
+    pub(super) sampler: vk::Sampler,
+    // 6 for a cubemap, whose faces are the layers, 1 otherwise.
+    layers: u32,
+    // Only compressed textures come with more than 1, see `register_compressed`.
+    mip_levels: u32,
+    // False until the first upload has finished and the graphics queue owns the image, nothing
+    // may sample it before then.
+    ready: bool,
+    allocation: Option<Allocation>,
+}
+
+// A texture's first upload, copied on the transfer queue while frames carry on. See
+// `TextureStore::finish_uploads`.
+pub(super) struct Upload {
+    submission: Submission,
+    // The transfer and graphics families, see `Transfer`.
+    queue_families: [u32; 2],
+}
+
+impl Upload {
+    fn is_done(&self, logical_device: &Device) -> Result<bool, vk::Result> {
+        self.submission.is_done(logical_device)
+    }
+
+    fn wait(&self, logical_device: &Device) -> Result<(), vk::Result> {
+        self.submission.wait(logical_device)
+    }
+
+    // Only once it is done.
+    unsafe fn cleanup(&mut self, allocator: &mut Allocator, logical_device: &Device) {
+        self.submission.cleanup(allocator, logical_device);
+    }
+}
+
+impl Texture {
+    pub(super) fn new(
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        desc: TextureDesc,
+        name: &str,
+        queue_families: &[u32],
+    ) -> Result<Texture, vk::Result> {
+        Self::with_layers(allocator, logical_device, desc, 1, 1, name, queue_families)
+    }
+
+    // A cubemap with `size` x `size` faces, sampled with a `samplerCube`.
+    pub(super) fn new_cube(
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        size: u32,
+        format: vk::Format,
+        name: &str,
+        queue_families: &[u32],
+    ) -> Result<Texture, vk::Result> {
+        Self::with_layers(
+            allocator,
+            logical_device,
+            TextureDesc {
+                width: size,
+                height: size,
+                format,
+                usage: vk::ImageUsageFlags::empty(),
+            },
+            6,
+            1,
+            name,
+            queue_families,
+        )
+    }
+
+    fn with_layers(
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        desc: TextureDesc,
+        layers: u32,
+        mip_levels: u32,
+        name: &str,
+        queue_families: &[u32],
+    ) -> Result<Texture, vk::Result> {
+        let TextureDesc {
+            width,
+            height,
+            format,
+            usage,
+        } = desc;
+        let (flags, view_type) = match layers {
+            6 => (
+                vk::ImageCreateFlags::CUBE_COMPATIBLE,
+                vk::ImageViewType::CUBE,
+            ),
+            _ => (vk::ImageCreateFlags::empty(), vk::ImageViewType::TYPE_2D),
+        };
+        let image_extent = vk::Extent3D {
+            depth: 1,
+            height: height,
+            width: width,
+        };
+        let image_create_info = vk::ImageCreateInfo::builder()
+            .flags(flags)
+            .extent(image_extent)
+            .format(format)
+            .image_type(vk::ImageType::TYPE_2D)
+            .mip_levels(mip_levels)
+            // Filled by a buffer copy, so the driver is free to lay it out however samples best.
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .usage(usage | vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST)
+            .array_layers(layers)
+            .queue_family_indices(queue_families)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .samples(vk::SampleCountFlags::TYPE_1);
+
+        let image = unsafe { logical_device.create_image(&image_create_info, None)? };
+
+        let requirements = unsafe { logical_device.get_image_memory_requirements(image) };
+        let allocation = allocator
+            .allocate(&AllocationCreateDesc {
+                name: name,
+                requirements,
+                linear: false,
+                location: gpu_allocator::MemoryLocation::GpuOnly,
+                allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+            })
+            .unwrap();
+
+        // Bind memory to the buffer
+        unsafe {
+            logical_device.bind_image_memory(image, allocation.memory(), allocation.offset())?
+        };
+        leaks::track(logical_device, image, name, allocation.size());
+        debug::set_object_name(logical_device, image, name);
+
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .base_array_layer(0)
+            .level_count(mip_levels)
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .layer_count(layers)
+            .build();
+
+        let view_create_info = vk::ImageViewCreateInfo::builder()
+            .image(image)
+            .view_type(view_type)
+            .format(format)
+            .subresource_range(subresource_range);
+
+        let image_view = unsafe { logical_device.create_image_view(&view_create_info, None) }?;
+
+        Ok(Texture {
+            image,
+            width,
+            height,
+            format,
+            image_view,
+            sampler: vk::Sampler::null(),
+            layers,
+            mip_levels,
+            ready: false,
+            allocation: Some(allocation),
+        })
+    }
+
+    pub(super) fn is_cube(&self) -> bool {
+        self.layers == 6
+    }
+
+    pub(super) fn is_ready(&self) -> bool {
+        self.ready
+    }
+
+    // Starts filling the whole texture on `transfer`'s queue without waiting for it. A cubemap's
+    // faces follow each other in `raw`. When `transfer` is in another family than the graphics
+    // queue the image is released to it, and has to be acquired with `acquire` once the upload
+    // is done.
+    pub(super) fn upload<T>(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        raw: &[T],
+        transfer: &mut Transfer,
+    ) -> Result<Upload, vk::Result> {
+        // Staged as bytes so uploads of any texel type go through the same staging memory.
+        let bytes = unsafe {
+            std::slice::from_raw_parts(raw.as_ptr() as *const u8, std::mem::size_of_val(raw))
+        };
+        self.upload_levels(allocator, logical_device, bytes, &[0], transfer)
+    }
+
+    // Like `upload` for every mip level at once, `level_offsets` is where each level starts in
+    // `bytes`, largest first.
+    fn upload_levels(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        bytes: &[u8],
+        level_offsets: &[u64],
+        transfer: &mut Transfer,
+    ) -> Result<Upload, vk::Result> {
+        let copies: Vec<_> = (0..)
+            .zip(level_offsets)
+            .map(|(level, offset)| {
+                let region = TextureRegion {
+                    x: 0,
+                    y: 0,
+                    width: (self.width >> level).max(1),
+                    height: (self.height >> level).max(1),
+                };
+                (level, region, *offset)
+            })
+            .collect();
+        let [transfer_family, graphics_family] = transfer.queue_families;
+        let release = (transfer_family != graphics_family).then_some(transfer.queue_families);
+        let submission = transfer.submit(
+            allocator,
+            logical_device,
+            bytes,
+            |command_buffer, staging, offset| {
+                self.record_copy(
+                    logical_device,
+                    command_buffer,
+                    (staging, offset),
+                    &copies,
+                    vk::ImageLayout::UNDEFINED,
+                    release,
+                )
+            },
+        )?;
+        Ok(Upload {
+            submission,
+            queue_families: transfer.queue_families,
+        })
+    }
+
+    // Takes the image over from the transfer queue `upload` ran on, recorded on the graphics
+    // queue after the upload's fence has signalled. Marks the texture ready.
+    fn acquire(
+        &mut self,
+        logical_device: &Device,
+        command_buffer: vk::CommandBuffer,
+        upload: &Upload,
+    ) {
+        self.ready = true;
+        let [transfer_family, graphics_family] = upload.queue_families;
+        if transfer_family == graphics_family {
+            return;
+        }
+        // Has to match the release in `record_copy`.
+        let barrier = vk::ImageMemoryBarrier::builder()
+            .image(self.image)
+            .src_access_mask(vk::AccessFlags::empty())
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+            .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .src_queue_family_index(transfer_family)
+            .dst_queue_family_index(graphics_family)
+            .subresource_range(self.subresource_range())
+            .build();
+        unsafe {
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier],
+            )
+        };
+    }
+
+    // Overwrites `region` of a texture that has already been uploaded and leaves the rest alone,
+    // on every face of a cubemap.
+    // `transfer` has to submit to the queue frames are drawn on so the barrier waits for them to
+    // finish sampling.
+    pub(super) fn update<T>(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        raw: &[T],
+        region: TextureRegion,
+        transfer: &mut Transfer,
+    ) -> Result<(), vk::Result> {
+        let bytes = unsafe {
+            std::slice::from_raw_parts(raw.as_ptr() as *const u8, std::mem::size_of_val(raw))
+        };
+        let mut submission = transfer.submit(
+            allocator,
+            logical_device,
+            bytes,
+            |command_buffer, staging, offset| {
+                self.record_copy(
+                    logical_device,
+                    command_buffer,
+                    (staging, offset),
+                    &[(0, region, 0)],
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    None,
+                )
+            },
+        )?;
+        let waited = submission.wait(logical_device);
+        unsafe { submission.cleanup(allocator, logical_device) };
+        waited
+    }
+
+    fn subresource_range(&self) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: self.mip_levels,
+            base_array_layer: 0,
+            layer_count: self.layers,
+        }
+    }
+
+    // Copies the staged texels at `offset` in `buffer` into the image and leaves it ready to
+    // sample. Each copy is a mip level, the region of it to fill and where its texels start
+    // after `offset`. With `release` as the
+    // transfer and graphics families the image is handed over to the graphics family instead,
+    // which has to `acquire` it.
+    fn record_copy(
+        &self,
+        logical_device: &Device,
+        command_buffer: vk::CommandBuffer,
+        (buffer, offset): (vk::Buffer, u64),
+        copies: &[(u32, TextureRegion, u64)],
+        old_layout: vk::ImageLayout,
+        release: Option<[u32; 2]>,
+    ) {
+        let (src_access, src_stage) = match old_layout {
+            vk::ImageLayout::UNDEFINED => (
+                vk::AccessFlags::empty(),
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+            ),
+            _ => (
+                vk::AccessFlags::SHADER_READ,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+            ),
+        };
+        let barrier = vk::ImageMemoryBarrier::builder()
+            .image(self.image)
+            .src_access_mask(src_access)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .old_layout(old_layout)
+            .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .subresource_range(self.subresource_range())
+            .build();
+
+        unsafe {
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                src_stage,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier],
+            )
+        };
+        let regions: Vec<_> = copies
+            .iter()
+            .map(|(mip_level, region, level_offset)| vk::BufferImageCopy {
+                buffer_offset: offset + level_offset,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_offset: vk::Offset3D {
+                    x: region.x as i32,
+                    y: region.y as i32,
+                    z: 0,
+                },
+                image_extent: vk::Extent3D {
+                    width: region.width,
+                    height: region.height,
+                    depth: 1,
+                },
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: *mip_level,
+                    base_array_layer: 0,
+                    layer_count: self.layers,
+                },
+            })
+            .collect();
+        unsafe {
+            logical_device.cmd_copy_buffer_to_image(
+                command_buffer,
+                buffer,
+                self.image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &regions,
+            );
+        }
+
+        // A transfer only queue can't name the fragment shader stage, the acquire waits for it
+        // instead.
+        let (dst_access, dst_stage, (src_family, dst_family)) = match release {
+            Some([transfer_family, graphics_family]) => (
+                vk::AccessFlags::empty(),
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                (transfer_family, graphics_family),
+            ),
+            None => (
+                vk::AccessFlags::SHADER_READ,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED),
+            ),
+        };
+        let barrier = vk::ImageMemoryBarrier::builder()
+            .image(self.image)
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(dst_access)
+            .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .src_queue_family_index(src_family)
+            .dst_queue_family_index(dst_family)
+            .subresource_range(self.subresource_range())
+            .build();
+        unsafe {
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier],
+            )
+        };
+    }
+
+    pub(super) unsafe fn cleanup(&mut self, allocator: &mut Allocator, logical_device: &Device) {
+        logical_device.destroy_image_view(self.image_view, None);
+
+        logical_device.destroy_image(self.image, None);
+        leaks::untrack(logical_device, self.image);
+
+        allocator.free(self.allocation.take().unwrap()).unwrap();
+    }
+}
+
+/// The texture stays alive while any clone of its handle does.
+#[derive(Clone)]
+pub struct TextureHandle {
+    id: Uuid,
+    refs: HandleRef,
+}
+
+impl TextureHandle {
+    /// Identifies the texture in engine events.
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+}
+
+/// A rectangle of texels, counted from the top left of the texture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextureRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl TextureRegion {
+    fn fits(&self, texture: &Texture) -> bool {
+        self.width > 0
+            && self.height > 0
+            && self
+                .x
+                .checked_add(self.width)
+                .is_some_and(|x| x <= texture.width)
+            && self
+                .y
+                .checked_add(self.height)
+                .is_some_and(|y| y <= texture.height)
+    }
+}
+
+/// The part of a texture an instance samples from: mesh uvs are scaled by `scale` and then moved
+/// by `offset`, so a mesh with 0-1 uvs can show any cell of an atlas.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UvRect {
+    pub offset: [f32; 2],
+    pub scale: [f32; 2],
+}
+
+impl UvRect {
+    /// Leaves mesh uvs as they are.
+    pub const FULL: UvRect = UvRect {
+        offset: [0.0, 0.0],
+        scale: [1.0, 1.0],
+    };
+
+    /// Width over height of the region in pixels, for sizing quads so the cell isn't stretched.
+    pub fn aspect(&self, atlas: &RGBAImage) -> f32 {
+        (self.scale[0] * atlas.width as f32) / (self.scale[1] * atlas.height as f32)
+    }
+}
+
+impl Default for UvRect {
+    fn default() -> Self {
+        UvRect::FULL
+    }
+}
+
+/// The cell at `column`, `row` of an atlas divided into an even `columns` x `rows` grid, counted
+/// from the top left. The rect is pulled in by half a texel on every side so filtering doesn't
+/// bleed in the neighbouring cells.
+pub fn uv_rect_for_cell(
+    atlas: &RGBAImage,
+    column: u32,
+    row: u32,
+    columns: u32,
+    rows: u32,
+) -> UvRect {
+    let cell = [1.0 / columns as f32, 1.0 / rows as f32];
+    let texel = [1.0 / atlas.width as f32, 1.0 / atlas.height as f32];
+    UvRect {
+        offset: [
+            column as f32 * cell[0] + texel[0] / 2.0,
+            row as f32 * cell[1] + texel[1] / 2.0,
+        ],
+        scale: [(cell[0] - texel[0]).max(0.0), (cell[1] - texel[1]).max(0.0)],
+    }
+}
+
+// The most textures the renderer makes room for, however many more the device could take. Every
+// scene pipeline has a texture array this big for each frame in flight.
+const MAX_TEXTURES: u32 = 4096;
+
+pub(super) struct TextureStore {
+    textures_map: HashMap<Uuid, u32>,
+    // How many texture indices there are, `MAX_TEXTURES` or the device's limit if that's lower.
+    capacity: u32,
+    // Bumped whenever an index starts or stops referring to a texture.
+    version: u64,
+    samplers: SamplerCache,
+    /// Indexed by the texture index instances use, collected textures leave a None behind so the
+    /// other indices don't move.
+    pub textures: Vec<Option<Texture>>,
+    // The store's own copy of each texture's handle.
+    handles: Vec<Option<TextureHandle>>,
+    free_slots: Vec<u32>,
+    collector: Collector<Texture>,
+    // First uploads still running, by texture id.
+    uploads: Vec<(Uuid, Upload)>,
+    // Fills every index nothing can be sampled from, see `create_placeholder`.
+    placeholder: Option<TextureHandle>,
+}
+
+impl TextureStore {
+    pub(super) fn new(
+        logical_device: &Device,
+        limits: &vk::PhysicalDeviceLimits,
+    ) -> Result<TextureStore, InitError> {
+        let mut samplers = SamplerCache::new(limits);
+        // Made up front, creating the store is where running out of samplers should show.
+        samplers.get(logical_device, &SamplerDesc::default())?;
+        let capacity = MAX_TEXTURES
+            .min(limits.max_per_stage_descriptor_samplers)
+            .min(limits.max_per_stage_descriptor_sampled_images)
+            .min(limits.max_descriptor_set_samplers)
+            .min(limits.max_descriptor_set_sampled_images);
+        Ok(TextureStore {
+            textures_map: HashMap::new(),
+            capacity,
+            version: 0,
+            textures: vec![],
+            handles: vec![],
+            free_slots: vec![],
+            collector: Collector::new(),
+            uploads: vec![],
+            placeholder: None,
+            samplers,
+        })
+    }
+
+    // Allocates and registers a transparent black image, to be filled in later with
+    // `update_texture`. Waits for the upload, `graphics` submits to the graphics queue.
+    pub(super) fn create_empty_texture(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        width: u32,
+        height: u32,
+        transfer: Transfer,
+        graphics: &mut TransientPool,
+    ) -> Result<TextureHandle, RuntimeError> {
+        let image = RGBAImage::new(width, height);
+        let handle = self.register_texture(
+            allocator,
+            logical_device,
+            &image,
+            SamplerDesc::default(),
+            transfer,
+        )?;
+        self.wait_for_upload(allocator, logical_device, &handle, graphics)?;
+        Ok(handle)
+    }
+
+    // Writes `pixels`, row by row, over `region` of an 8 bit texture. Waits for the copy, so a
+    // texture streamed every frame should be kept small. `transfer` submits to the graphics
+    // queue.
+    pub(super) fn update_texture(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        handle: &TextureHandle,
+        region: TextureRegion,
+        pixels: &[RGBAPixel],
+        mut transfer: Transfer,
+    ) -> Result<(), RuntimeError> {
+        let index = *self
+            .textures_map
+            .get(&handle.id)
+            .ok_or(RuntimeError::InvalidHandle)?;
+        let texture = self.textures[index as usize]
+            .as_mut()
+            .ok_or(RuntimeError::InvalidHandle)?;
+        if texture.format != vk::Format::R8G8B8A8_SRGB
+            || texture.is_cube()
+            || !region.fits(texture)
+            || pixels.len() != (region.width * region.height) as usize
+        {
+            return Err(RuntimeError::InvalidRegion);
+        }
+        self.wait_for_upload(allocator, logical_device, handle, transfer.commands)?;
+        let texture = self.textures[index as usize]
+            .as_mut()
+            .ok_or(RuntimeError::InvalidHandle)?;
+        texture.update(allocator, logical_device, pixels, region, &mut transfer)?;
+        Ok(())
+    }
+
+    // A transparent texture that can also be drawn to, on top of `desc.usage`. Its format should
+    // be the main render pass's so pipelines built for that can draw to it too. See
+    // `RenderTarget`. Waits for the upload like `create_empty_texture`.
+    pub(super) fn create_render_target_texture(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        desc: TextureDesc,
+        transfer: Transfer,
+        graphics: &mut TransientPool,
+    ) -> Result<TextureHandle, RuntimeError> {
+        let TextureDesc {
+            width,
+            height,
+            format,
+            usage,
+        } = desc;
+        // Every surface format the renderer picks is 32 or 64 bits per texel.
+        let texel_size = match format {
+            vk::Format::R16G16B16A16_SFLOAT | vk::Format::R16G16B16A16_UNORM => 8,
+            _ => 4,
+        };
+        let handle = self.register(
+            allocator,
+            logical_device,
+            TextureDesc {
+                width,
+                height,
+                format,
+                usage: usage | vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            },
+            &vec![0u8; (width * height) as usize * texel_size],
+            SamplerDesc::default(),
+            transfer,
+        )?;
+        self.wait_for_upload(allocator, logical_device, &handle, graphics)?;
+        Ok(handle)
+    }
+
+    pub(super) fn register_texture(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        image: &RGBAImage,
+        sampler: SamplerDesc,
+        transfer: Transfer,
+    ) -> Result<TextureHandle, RuntimeError> {
+        self.register(
+            allocator,
+            logical_device,
+            TextureDesc {
+                width: image.width,
+                height: image.height,
+                format: vk::Format::R8G8B8A8_SRGB,
+                usage: vk::ImageUsageFlags::empty(),
+            },
+            &image.data,
+            sampler,
+            transfer,
+        )
+    }
+
+    // Stored as half floats, which the hardware can filter everywhere unlike full floats. Values
+    // are linear and can go past 1.
+    pub(super) fn register_hdr_texture(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        image: &HDRImage,
+        transfer: Transfer,
+    ) -> Result<TextureHandle, RuntimeError> {
+        let data: Vec<f16> = image
+            .data
+            .iter()
+            .flat_map(|pixel| [pixel.r, pixel.g, pixel.b, pixel.a].map(f16::from_f32))
+            .collect();
+        self.register(
+            allocator,
+            logical_device,
+            TextureDesc {
+                width: image.width,
+                height: image.height,
+                format: vk::Format::R16G16B16A16_SFLOAT,
+                usage: vk::ImageUsageFlags::empty(),
+            },
+            &data,
+            SamplerDesc::default(),
+            transfer,
+        )
+    }
+
+    // The faces are seen from inside the cube with +y up, in the order +x, -x, +y, -y, +z, -z.
+    // They have to be square and all the same size.
+    pub(super) fn register_cubemap(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        faces: [&RGBAImage; 6],
+        transfer: Transfer,
+    ) -> Result<TextureHandle, RuntimeError> {
+        let size = faces[0].width;
+        if size == 0
+            || faces.iter().any(|face| {
+                face.width != size
+                    || face.height != size
+                    || face.data.len() != (size * size) as usize
+            })
+        {
+            return Err(RuntimeError::InvalidCubemap);
+        }
+        let data: Vec<RGBAPixel> = faces
+            .iter()
+            .flat_map(|face| face.data.iter().copied())
+            .collect();
+        self.register_cube(
+            allocator,
+            logical_device,
+            size,
+            vk::Format::R8G8B8A8_SRGB,
+            &data,
+            transfer,
+        )
+    }
+
+    // Resamples a latitude/longitude panorama, e.g. an HDR environment map, onto a half float
+    // cubemap with faces a quarter of its width.
+    pub(super) fn register_equirectangular_cubemap(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        image: &HDRImage,
+        transfer: Transfer,
+    ) -> Result<TextureHandle, RuntimeError> {
+        if image.width == 0
+            || image.height == 0
+            || image.data.len() != (image.width * image.height) as usize
+        {
+            return Err(RuntimeError::InvalidCubemap);
+        }
+        let size = (image.width / 4).max(1);
+        let data = equirectangular_to_cube(image, size);
+        self.register_cube(
+            allocator,
+            logical_device,
+            size,
+            vk::Format::R16G16B16A16_SFLOAT,
+            &data,
+            transfer,
+        )
+    }
+
+    // Uploads every level of `image` as it is, the device has to support sampling its format.
+    pub(super) fn register_compressed(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        image: &CompressedImage,
+        sampler: SamplerDesc,
+        mut transfer: Transfer,
+    ) -> Result<TextureHandle, RuntimeError> {
+        let max_levels = 32 - image.width.max(image.height).leading_zeros();
+        let sizes_match = (0..).zip(&image.levels).all(|(level, data)| {
+            let width = (image.width >> level).max(1);
+            let height = (image.height >> level).max(1);
+            Some(data.len()) == image.format.level_bytes(width, height)
+        });
+        if image.width == 0
+            || image.levels.is_empty()
+            || image.levels.len() as u32 > max_levels
+            || !sizes_match
+        {
+            return Err(RuntimeError::InvalidRegion);
+        }
+        self.check_capacity()?;
+        let sampler = self.samplers.get(logical_device, &sampler)?;
+        let id = Uuid::new_v4();
+        let mut texture = Texture::with_layers(
+            allocator,
+            logical_device,
+            TextureDesc {
+                width: image.width,
+                height: image.height,
+                format: compressed_format(image.format),
+                usage: vk::ImageUsageFlags::empty(),
+            },
+            1,
+            image.levels.len() as u32,
+            format!("t-{}", &id).as_str(),
+            &transfer.queue_families,
+        )?;
+        texture.sampler = sampler;
+        // Every level is a whole number of 8 or 16 byte blocks, so each one starts block aligned.
+        let mut level_offsets = Vec::with_capacity(image.levels.len());
+        let mut data = Vec::with_capacity(image.levels.iter().map(Vec::len).sum());
+        for level in &image.levels {
+            level_offsets.push(data.len() as u64);
+            data.extend_from_slice(level);
+        }
+        match texture.upload_levels(
+            allocator,
+            logical_device,
+            &data,
+            &level_offsets,
+            &mut transfer,
+        ) {
+            Ok(upload) => {
+                self.uploads.push((id, upload));
+                Ok(self.insert(id, texture))
+            }
+            Err(e) => {
+                unsafe { texture.cleanup(allocator, logical_device) };
+                Err(e.into())
+            }
+        }
+    }
+
+    // `data` is laid out the way `format` expects, row by row with no padding.
+    fn register<T>(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        desc: TextureDesc,
+        data: &[T],
+        sampler: SamplerDesc,
+        transfer: Transfer,
+    ) -> Result<TextureHandle, RuntimeError> {
+        self.check_capacity()?;
+        let sampler = self.samplers.get(logical_device, &sampler)?;
+        let id = Uuid::new_v4();
+        let mut texture = Texture::new(
+            allocator,
+            logical_device,
+            desc,
+            format!("t-{}", &id).as_str(),
+            &transfer.queue_families,
+        )?;
+        texture.sampler = sampler;
+        self.start_upload(allocator, logical_device, id, texture, data, transfer)
+    }
+
+    // Six `size` x `size` faces one after the other in `data`, in Vulkan's +x, -x, +y, -y, +z,
+    // -z order.
+    fn register_cube<T>(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        size: u32,
+        format: vk::Format,
+        data: &[T],
+        transfer: Transfer,
+    ) -> Result<TextureHandle, RuntimeError> {
+        self.check_capacity()?;
+        let id = Uuid::new_v4();
+        let texture = Texture::new_cube(
+            allocator,
+            logical_device,
+            size,
+            format,
+            format!("t-{}", &id).as_str(),
+            &transfer.queue_families,
+        )?;
+        self.start_upload(allocator, logical_device, id, texture, data, transfer)
+    }
+
+    // Registers `texture` straight away and leaves its upload running, see `finish_uploads`.
+    fn start_upload<T>(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        id: Uuid,
+        mut texture: Texture,
+        data: &[T],
+        mut transfer: Transfer,
+    ) -> Result<TextureHandle, RuntimeError> {
+        match texture.upload(allocator, logical_device, data, &mut transfer) {
+            Ok(upload) => {
+                self.uploads.push((id, upload));
+                Ok(self.insert(id, texture))
+            }
+            Err(e) => {
+                unsafe { texture.cleanup(allocator, logical_device) };
+                Err(e.into())
+            }
+        }
+    }
+
+    // Called at the start of every frame's command buffer: textures whose upload has finished
+    // are acquired by `command_buffer` and can be sampled from this frame on.
+    pub(super) fn finish_uploads(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        command_buffer: vk::CommandBuffer,
+    ) -> Result<(), vk::Result> {
+        let mut index = 0;
+        while index < self.uploads.len() {
+            if !self.uploads[index].1.is_done(logical_device)? {
+                index += 1;
+                continue;
+            }
+            let (id, mut upload) = self.uploads.swap_remove(index);
+            if let Some(texture) = self.texture_mut(id) {
+                texture.acquire(logical_device, command_buffer, &upload);
+                self.version += 1;
+            }
+            unsafe { upload.cleanup(allocator, logical_device) };
+        }
+        Ok(())
+    }
+
+    // Blocks until the texture's upload has finished and acquires it on `graphics`' queue, for
+    // when it's needed straight away. Does nothing for a texture that's ready.
+    pub(super) fn wait_for_upload(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        handle: &TextureHandle,
+        graphics: &mut TransientPool,
+    ) -> Result<(), vk::Result> {
+        let position = match self.uploads.iter().position(|(id, _)| *id == handle.id) {
+            Some(position) => position,
+            None => return Ok(()),
+        };
+        let (id, mut upload) = self.uploads.swap_remove(position);
+        let result = upload.wait(logical_device).and_then(|_| {
+            let texture = match self.texture_mut(id) {
+                Some(texture) => texture,
+                None => return Ok(()),
+            };
+            let command = graphics.begin(logical_device)?;
+            texture.acquire(logical_device, command.command_buffer, &upload);
+            graphics.submit_and_wait(logical_device, command)
+        });
+        if result.is_ok() {
+            self.version += 1;
+        }
+        unsafe { upload.cleanup(allocator, logical_device) };
+        result
+    }
+
+    fn texture_mut(&mut self, id: Uuid) -> Option<&mut Texture> {
+        let index = *self.textures_map.get(&id)?;
+        self.textures[index as usize].as_mut()
+    }
+
+    fn check_capacity(&self) -> Result<(), RuntimeError> {
+        if self.free_slots.is_empty() && self.textures.len() as u32 >= self.capacity {
+            return Err(RuntimeError::TooManyTextures);
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, id: Uuid, texture: Texture) -> TextureHandle {
+        let handle = TextureHandle {
+            id,
+            refs: HandleRef::default(),
+        };
+        let index = match self.free_slots.pop() {
+            Some(index) => {
+                self.textures[index as usize] = Some(texture);
+                self.handles[index as usize] = Some(handle.clone());
+                index
+            }
+            None => {
+                self.textures.push(Some(texture));
+                self.handles.push(Some(handle.clone()));
+                (self.textures.len() - 1) as u32
+            }
+        };
+        self.textures_map.insert(id, index);
+        self.version += 1;
+        handle
+    }
+
+    pub(super) fn get(&self, handle: &TextureHandle) -> Option<&Texture> {
+        self.textures_map
+            .get(&handle.id)
+            .and_then(|index| self.textures[*index as usize].as_ref())
+    }
+
+    // Takes the texture out of the store whether or not its handles are still around, they all
+    // stop working. It's destroyed once the frames that might draw it are done, like a collected
+    // texture, and its index is given to the next texture registered. Waits for its upload if
+    // that's still running.
+    pub(super) fn unregister_texture(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        handle: &TextureHandle,
+        frame: u64,
+    ) -> Result<bool, vk::Result> {
+        let index = match self.textures_map.remove(&handle.id) {
+            Some(index) => index,
+            None => return Ok(false),
+        };
+        if let Some(position) = self.uploads.iter().position(|(id, _)| *id == handle.id) {
+            let (_, mut upload) = self.uploads.swap_remove(position);
+            let waited = upload.wait(logical_device);
+            unsafe { upload.cleanup(allocator, logical_device) };
+            waited?;
+        }
+        self.handles[index as usize] = None;
+        self.free_slots.push(index);
+        self.version += 1;
+        if let Some(texture) = self.textures[index as usize].take() {
+            self.collector.retire(frame, texture);
+        }
+        Ok(true)
+    }
+
+    // Whether the texture's first upload has finished, so it's drawn rather than the
+    // placeholder.
+    pub(super) fn is_ready(&self, handle: &TextureHandle) -> bool {
+        self.get(handle).is_some_and(Texture::is_ready)
+    }
+
+    // The number of texture indices, the length of the texture array in every scene pipeline.
+    pub(super) fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    // Changes whenever `get_descriptor_image_info` would.
+    pub(super) fn version(&self) -> u64 {
+        self.version
+    }
+
+    // The index instances sample the texture with.
+    pub(super) fn index(&self, handle: &TextureHandle) -> Option<u32> {
+        self.textures_map.get(&handle.id).copied()
+    }
+
+    // The store's own copy of every texture's handle, with its index.
+    pub(super) fn handles(&self) -> impl Iterator<Item = (u32, &TextureHandle)> {
+        self.handles
+            .iter()
+            .enumerate()
+            .filter_map(|(index, handle)| Some((index as u32, handle.as_ref()?)))
+    }
+
+    // Gives a texture registered on a new device the handle and index it had on the lost one, so
+    // handles the application kept and indices written into instances keep working. The texture
+    // stays where it was registered if another one already took the index. Its upload must have
+    // finished.
+    pub(super) fn adopt(&mut self, registered: &TextureHandle, handle: TextureHandle, index: u32) {
+        let Some(from) = self.textures_map.remove(&registered.id) else {
+            return;
+        };
+        let taken = self
+            .textures
+            .get(index as usize)
+            .is_some_and(Option::is_some);
+        let index = if taken && index != from { from } else { index };
+        let texture = self.textures[from as usize].take();
+        self.handles[from as usize] = None;
+        if self.textures.len() <= index as usize {
+            self.textures.resize_with(index as usize + 1, || None);
+            self.handles.resize(index as usize + 1, None);
+        }
+        self.textures[index as usize] = texture;
+        self.textures_map.insert(handle.id, index);
+        self.handles[index as usize] = Some(handle);
+        // Lowest index last, so it's the next one handed out.
+        self.free_slots = (0..self.textures.len() as u32)
+            .rev()
+            .filter(|slot| self.textures[*slot as usize].is_none())
+            .collect();
+        self.version += 1;
+    }
+
+    // Does this frame's share of collection. Textures whose handles have all been dropped are
+    // taken out of the store straight away, but only destroyed once `latency` frames have passed
+    // so command buffers still in flight can finish with them. Returns the ids of the textures
+    // taken out of the store.
+    pub(super) fn collect_garbage(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        frame: u64,
+        latency: u64,
+        config: &GcConfig,
+    ) -> Vec<Uuid> {
+        let mut evicted = vec![];
+        for index in self
+            .collector
+            .scan(self.handles.len(), config.scan_per_frame)
+        {
+            // Left for a later scan while the upload is still writing to it.
+            let uploading = self.textures[index]
+                .as_ref()
+                .is_some_and(|texture| !texture.is_ready());
+            let unreferenced = !uploading
+                && self.handles[index]
+                    .as_ref()
+                    .is_some_and(|handle| handle.refs.is_unreferenced());
+            if unreferenced {
+                let handle = self.handles[index].take().unwrap();
+                self.textures_map.remove(&handle.id);
+                evicted.push(handle.id);
+                self.version += 1;
+                self.free_slots.push(index as u32);
+                if let Some(texture) = self.textures[index].take() {
+                    self.collector.retire(frame, texture);
+                }
+            }
+        }
+        for mut texture in self
+            .collector
+            .ready(frame, latency, config.destroy_per_frame)
+        {
+            unsafe { texture.cleanup(allocator, logical_device) };
+        }
+        evicted
+    }
+
+    pub(super) fn cleanup(&mut self, allocator: &mut Allocator, logical_device: &Device) {
+        self.samplers.cleanup(logical_device);
+
+        for (_, mut upload) in self.uploads.drain(..) {
+            unsafe {
+                let _ = upload.wait(logical_device);
+                upload.cleanup(allocator, logical_device);
+            }
+        }
+        for t in self.textures.iter_mut().flatten() {
+            unsafe {
+                t.cleanup(allocator, logical_device);
+            }
+        }
+        for mut t in self.collector.drain() {
+            unsafe {
+                t.cleanup(allocator, logical_device);
+            }
+        }
+    }
+
+    // Registers the 1x1 transparent texture that fills every index nothing can be sampled from,
+    // so a stale index draws nothing rather than some other texture. Called before anything else
+    // is registered. The store keeps the only handle, so it's never collected.
+    pub(super) fn create_placeholder(
+        &mut self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        transfer: Transfer,
+        graphics: &mut TransientPool,
+    ) -> Result<(), RuntimeError> {
+        let handle = self.register(
+            allocator,
+            logical_device,
+            TextureDesc {
+                width: 1,
+                height: 1,
+                format: vk::Format::R8G8B8A8_SRGB,
+                usage: vk::ImageUsageFlags::empty(),
+            },
+            &[0u8; 4],
+            SamplerDesc::default(),
+            transfer,
+        )?;
+        self.wait_for_upload(allocator, logical_device, &handle, graphics)?;
+        self.placeholder = Some(handle);
+        Ok(())
+    }
+
+    // Collected slots are filled with the placeholder so every descriptor stays valid. Cubemaps
+    // can't go in a 2D texture array, and textures still uploading can't be sampled yet, so
+    // their slots are filled the same way.
+    pub(crate) fn get_descriptor_image_info(&self) -> Vec<vk::DescriptorImageInfo> {
+        let flat = |texture: &&Texture| !texture.is_cube() && texture.is_ready();
+        let placeholder = match self
+            .placeholder
+            .as_ref()
+            .and_then(|handle| self.get(handle))
+        {
+            Some(texture) => texture,
+            None => return vec![],
+        };
+        self.textures
+            .iter()
+            .map(|texture| {
+                let texture = texture.as_ref().filter(flat).unwrap_or(placeholder);
+                vk::DescriptorImageInfo::builder()
+                    .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                    .image_view(texture.image_view)
+                    .sampler(texture.sampler)
+                    .build()
+            })
+            .collect()
+    }
+}
+
+fn compressed_format(format: BlockFormat) -> vk::Format {
+    match format {
+        BlockFormat::Bc1 => vk::Format::BC1_RGBA_UNORM_BLOCK,
+        BlockFormat::Bc1Srgb => vk::Format::BC1_RGBA_SRGB_BLOCK,
+        BlockFormat::Bc2 => vk::Format::BC2_UNORM_BLOCK,
+        BlockFormat::Bc2Srgb => vk::Format::BC2_SRGB_BLOCK,
+        BlockFormat::Bc3 => vk::Format::BC3_UNORM_BLOCK,
+        BlockFormat::Bc3Srgb => vk::Format::BC3_SRGB_BLOCK,
+        BlockFormat::Bc4 => vk::Format::BC4_UNORM_BLOCK,
+        BlockFormat::Bc5 => vk::Format::BC5_UNORM_BLOCK,
+        BlockFormat::Bc7 => vk::Format::BC7_UNORM_BLOCK,
+        BlockFormat::Bc7Srgb => vk::Format::BC7_SRGB_BLOCK,
+        BlockFormat::Astc4x4 => vk::Format::ASTC_4X4_UNORM_BLOCK,
+        BlockFormat::Astc4x4Srgb => vk::Format::ASTC_4X4_SRGB_BLOCK,
+        BlockFormat::Astc6x6 => vk::Format::ASTC_6X6_UNORM_BLOCK,
+        BlockFormat::Astc6x6Srgb => vk::Format::ASTC_6X6_SRGB_BLOCK,
+        BlockFormat::Astc8x8 => vk::Format::ASTC_8X8_UNORM_BLOCK,
+        BlockFormat::Astc8x8Srgb => vk::Format::ASTC_8X8_SRGB_BLOCK,
+    }
+}
+
+// The panorama's centre faces +z and its top row is straight up. Returns the six faces one after
+// the other, in the order `register_cube` takes them.
+fn equirectangular_to_cube(image: &HDRImage, size: u32) -> Vec<f16> {
+    let mut data = Vec::with_capacity((size * size * 6 * 4) as usize);
+    for face in 0..6 {
+        for y in 0..size {
+            for x in 0..size {
+                // -1 to 1 across the face, from the top left texel's centre.
+                let u = 2.0 * (x as f32 + 0.5) / size as f32 - 1.0;
+                let v = 2.0 * (y as f32 + 0.5) / size as f32 - 1.0;
+                let direction = match face {
+                    0 => Vector3::new(1.0, -v, -u),
+                    1 => Vector3::new(-1.0, -v, u),
+                    2 => Vector3::new(u, 1.0, v),
+                    3 => Vector3::new(u, -1.0, -v),
+                    4 => Vector3::new(u, -v, 1.0),
+                    _ => Vector3::new(-u, -v, -1.0),
+                }
+                .normalize();
+                let longitude = direction.x.atan2(direction.z);
+                let latitude = direction.y.clamp(-1.0, 1.0).asin();
+                let texel = sample_bilinear(
+                    image,
+                    0.5 + longitude / (2.0 * std::f32::consts::PI),
+                    0.5 - latitude / std::f32::consts::PI,
+                );
+                data.extend(texel.map(f16::from_f32));
+            }
+        }
+    }
+    data
+}
+
+// Wraps around horizontally and clamps at the poles.
+fn sample_bilinear(image: &HDRImage, u: f32, v: f32) -> [f32; 4] {
+    let x = u * image.width as f32 - 0.5;
+    let y = v * image.height as f32 - 0.5;
+    let (left, top) = (x.floor(), y.floor());
+    let (across, down) = (x - left, y - top);
+    let texel = |x: i64, y: i64| {
+        let x = x.rem_euclid(image.width as i64);
+        let y = y.clamp(0, image.height as i64 - 1);
+        let pixel = &image.data[(y * image.width as i64 + x) as usize];
+        [pixel.r, pixel.g, pixel.b, pixel.a]
+    };
+    let (left, top) = (left as i64, top as i64);
+    let [top_left, top_right, bottom_left, bottom_right] = [
+        texel(left, top),
+        texel(left + 1, top),
+        texel(left, top + 1),
+        texel(left + 1, top + 1),
+    ];
+    std::array::from_fn(|channel| {
+        let upper = top_left[channel] + (top_right[channel] - top_left[channel]) * across;
+        let lower = bottom_left[channel] + (bottom_right[channel] - bottom_left[channel]) * across;
+        upper + (lower - upper) * down
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: [f32; 2], b: [f32; 2]) -> bool {
+        (a[0] - b[0]).abs() < 1e-6 && (a[1] - b[1]).abs() < 1e-6
+    }
+
+    #[test]
+    fn cells_are_inset_by_half_a_texel() {
+        let atlas = RGBAImage::new(64, 32);
+        let rect = uv_rect_for_cell(&atlas, 0, 0, 4, 2);
+        assert!(close(rect.offset, [0.5 / 64.0, 0.5 / 32.0]));
+        assert!(close(rect.scale, [0.25 - 1.0 / 64.0, 0.5 - 1.0 / 32.0]));
+    }
+
+    #[test]
+    fn cells_count_from_the_top_left() {
+        let atlas = RGBAImage::new(64, 32);
+        let rect = uv_rect_for_cell(&atlas, 3, 1, 4, 2);
+        assert!(close(rect.offset, [0.75 + 0.5 / 64.0, 0.5 + 0.5 / 32.0]));
+        // The last cell stops half a texel short of the far edges.
+        let end = [
+            rect.offset[0] + rect.scale[0],
+            rect.offset[1] + rect.scale[1],
+        ];
+        assert!(close(end, [1.0 - 0.5 / 64.0, 1.0 - 0.5 / 32.0]));
+    }
+
+    #[test]
+    fn cells_a_texel_wide_collapse_to_their_centre() {
+        let atlas = RGBAImage::new(4, 4);
+        let rect = uv_rect_for_cell(&atlas, 2, 1, 4, 4);
+        assert!(close(rect.scale, [0.0, 0.0]));
+        assert!(close(rect.offset, [2.5 / 4.0, 1.5 / 4.0]));
+    }
+
+    #[test]
+    fn aspect_is_of_the_cell_in_pixels() {
+        let atlas = RGBAImage::new(128, 32);
+        assert!((UvRect::FULL.aspect(&atlas) - 4.0).abs() < 1e-6);
+        let rect = UvRect {
+            offset: [0.0, 0.0],
+            scale: [0.25, 1.0],
+        };
+        assert!((rect.aspect(&atlas) - 1.0).abs() < 1e-6);
+    }
+}
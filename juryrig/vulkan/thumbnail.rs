@@ -0,0 +1,291 @@
+use ash::{vk, Device};
+use gpu_allocator::vulkan::Allocator;
+
+use crate::jr_image::{RGBAImage, RGBAPixel};
+
+use super::{buffer::Buffer, texture::Texture};
+
+const WORKGROUP_SIZE: u32 = 8;
+
+// Shrinks textures on the GPU and reads the result back, for asset browsers and the like. Only
+// created the first time a thumbnail is asked for.
+pub(super) struct Thumbnailer {
+    pipeline: vk::Pipeline,
+    layout: vk::PipelineLayout,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    // Clamps so the box filter doesn't bleed across edges like the REPEAT sampler textures use.
+    sampler: vk::Sampler,
+}
+
+impl Thumbnailer {
+    pub(super) fn new(logical_device: &Device) -> Result<Thumbnailer, vk::Result> {
+        let layout_bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build(),
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build(),
+        ];
+        let descriptor_set_layout_info =
+            vk::DescriptorSetLayoutCreateInfo::builder().bindings(&layout_bindings);
+        let descriptor_set_layout = unsafe {
+            logical_device.create_descriptor_set_layout(&descriptor_set_layout_info, None)
+        }?;
+
+        let descriptor_pool_sizes = [
+            vk::DescriptorPoolSize::builder()
+                .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .build(),
+            vk::DescriptorPoolSize::builder()
+                .ty(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .build(),
+        ];
+        let descriptor_pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .pool_sizes(&descriptor_pool_sizes)
+            .max_sets(1);
+        let descriptor_pool =
+            unsafe { logical_device.create_descriptor_pool(&descriptor_pool_info, None) }?;
+
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .size(std::mem::size_of::<[u32; 2]>() as u32)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .build()];
+        let descriptor_set_layouts = [descriptor_set_layout];
+        let pipelinelayout_info = vk::PipelineLayoutCreateInfo::builder()
+            .push_constant_ranges(&push_constant_ranges)
+            .set_layouts(&descriptor_set_layouts);
+        let layout = unsafe { logical_device.create_pipeline_layout(&pipelinelayout_info, None) }?;
+
+        let shader_create_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("./shaders/downsample.glsl", kind: comp));
+        let shader_module =
+            unsafe { logical_device.create_shader_module(&shader_create_info, None) }?;
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+        let pipeline_info = vk::ComputePipelineCreateInfo::builder()
+            .stage(
+                vk::PipelineShaderStageCreateInfo::builder()
+                    .stage(vk::ShaderStageFlags::COMPUTE)
+                    .module(shader_module)
+                    .name(&main_function_name)
+                    .build(),
+            )
+            .layout(layout);
+        let pipeline = unsafe {
+            logical_device
+                .create_compute_pipelines(vk::PipelineCache::null(), &[pipeline_info.build()], None)
+                .map_err(|(_, e)| e)
+        }?[0];
+        unsafe { logical_device.destroy_shader_module(shader_module, None) };
+
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST);
+        let sampler = unsafe { logical_device.create_sampler(&sampler_info, None) }?;
+
+        Ok(Thumbnailer {
+            pipeline,
+            layout,
+            descriptor_set_layout,
+            descriptor_pool,
+            sampler,
+        })
+    }
+
+    // Downsamples `texture` so its longest side is at most `max_size`, keeping the aspect ratio.
+    // Textures that already fit are copied at their own size. Blocks until the GPU is done.
+    pub(super) fn thumbnail(
+        &self,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        texture: &Texture,
+        max_size: u32,
+        queue: vk::Queue,
+        pool: vk::CommandPool,
+    ) -> Result<RGBAImage, vk::Result> {
+        let scale = (max_size as f32 / texture.width.max(texture.height) as f32).min(1.0);
+        let width = ((texture.width as f32 * scale).round() as u32).max(1);
+        let height = ((texture.height as f32 * scale).round() as u32).max(1);
+
+        let mut output = Buffer::<RGBAPixel>::new(
+            allocator,
+            logical_device,
+            (width * height) as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            "thumbnail",
+            gpu_allocator::MemoryLocation::GpuToCpu,
+        )?;
+
+        // The descriptor set is always handed back, the pool only has room for one.
+        let downsampled = self.downsample(
+            logical_device,
+            texture,
+            output.buffer,
+            [width, height],
+            queue,
+            pool,
+        );
+        let reset = unsafe {
+            logical_device
+                .reset_descriptor_pool(self.descriptor_pool, vk::DescriptorPoolResetFlags::empty())
+        };
+        let result = downsampled.and(reset).map(|()| {
+            let mut image = RGBAImage::new(width, height);
+            output
+                .read(&mut image.data)
+                .expect("thumbnail buffer is not mapped");
+            image
+        });
+        unsafe { output.cleanup(allocator, logical_device) };
+        result
+    }
+
+    // Dispatches the downsample into `output` and waits for it. Frees its command buffer and fence
+    // whether or not that worked, but leaves the descriptor set for the caller to reset.
+    fn downsample(
+        &self,
+        logical_device: &Device,
+        texture: &Texture,
+        output: vk::Buffer,
+        size: [u32; 2],
+        queue: vk::Queue,
+        pool: vk::CommandPool,
+    ) -> Result<(), vk::Result> {
+        let descriptor_set_layouts = [self.descriptor_set_layout];
+        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(self.descriptor_pool)
+            .set_layouts(&descriptor_set_layouts);
+        let descriptor_set =
+            unsafe { logical_device.allocate_descriptor_sets(&descriptor_set_allocate_info) }?[0];
+        let image_info = [vk::DescriptorImageInfo::builder()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(texture.image_view)
+            .sampler(self.sampler)
+            .build()];
+        let buffer_info = [vk::DescriptorBufferInfo::builder()
+            .buffer(output)
+            .offset(0)
+            .range(vk::WHOLE_SIZE)
+            .build()];
+        let descriptor_writes = [
+            vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(&image_info)
+                .build(),
+            vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(&buffer_info)
+                .build(),
+        ];
+        unsafe { logical_device.update_descriptor_sets(&descriptor_writes, &[]) };
+
+        let commandbuf_allocate_info = vk::CommandBufferAllocateInfo::builder()
+            .command_pool(pool)
+            .command_buffer_count(1);
+        let command_buffers =
+            unsafe { logical_device.allocate_command_buffers(&commandbuf_allocate_info) }?;
+        let command_buffer = command_buffers[0];
+        let fence =
+            match unsafe { logical_device.create_fence(&vk::FenceCreateInfo::default(), None) } {
+                Ok(fence) => fence,
+                Err(e) => {
+                    unsafe { logical_device.free_command_buffers(pool, &command_buffers) };
+                    return Err(e);
+                }
+            };
+
+        let cmdbegininfo = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        let barrier = vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::HOST_READ)
+            .build();
+        let result = (|| unsafe {
+            logical_device.begin_command_buffer(command_buffer, &cmdbegininfo)?;
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline,
+            );
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+            logical_device.cmd_push_constants(
+                command_buffer,
+                self.layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                std::slice::from_raw_parts(
+                    size.as_ptr() as *const u8,
+                    std::mem::size_of_val(&size),
+                ),
+            );
+            logical_device.cmd_dispatch(
+                command_buffer,
+                size[0].div_ceil(WORKGROUP_SIZE),
+                size[1].div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[barrier],
+                &[],
+                &[],
+            );
+            logical_device.end_command_buffer(command_buffer)?;
+
+            let submit_infos = [vk::SubmitInfo::builder()
+                .command_buffers(&command_buffers)
+                .build()];
+            logical_device.queue_submit(queue, &submit_infos, fence)?;
+            // Once submitted nothing it uses can be freed before the GPU is done, even when the
+            // wait fails. Waiting for idle only fails once the device is lost, and then nothing
+            // is running any more.
+            logical_device
+                .wait_for_fences(&[fence], true, u64::MAX)
+                .inspect_err(|_| {
+                    let _ = logical_device.device_wait_idle();
+                })
+        })();
+        unsafe {
+            logical_device.destroy_fence(fence, None);
+            logical_device.free_command_buffers(pool, &command_buffers);
+        }
+        result
+    }
+
+    pub(super) fn cleanup(&self, logical_device: &Device) {
+        unsafe {
+            logical_device.destroy_sampler(self.sampler, None);
+            logical_device.destroy_pipeline(self.pipeline, None);
+            logical_device.destroy_pipeline_layout(self.layout, None);
+            logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+            logical_device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use ash::vk;
+
+use super::text::TextRenderer;
+
+// How long a toast stays up after it was last raised, it fades out over the last second.
+const LIFETIME: Duration = Duration::from_secs(6);
+const MAX_SHOWN: usize = 8;
+// Line height in pixels.
+const SIZE: f32 = 16.0;
+const MARGIN: f32 = 8.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToastLevel {
+    Warning,
+    Error,
+}
+
+impl ToastLevel {
+    fn colour(&self) -> [f32; 3] {
+        match self {
+            ToastLevel::Warning => [1.0, 0.85, 0.3],
+            ToastLevel::Error => [1.0, 0.35, 0.3],
+        }
+    }
+}
+
+// Raised from anywhere, including the validation layer callback, and picked up by the renderer
+// at the start of its next frame.
+static PENDING: Mutex<Vec<(ToastLevel, String)>> = Mutex::new(Vec::new());
+
+// Shows `message` on screen for a few seconds. Does nothing unless built with the `toasts`
+// feature.
+pub(super) fn raise(level: ToastLevel, message: &str) {
+    #[cfg(feature = "toasts")]
+    PENDING.lock().unwrap().push((level, message.to_owned()));
+    #[cfg(not(feature = "toasts"))]
+    let _ = (level, message);
+}
+
+struct Toast {
+    level: ToastLevel,
+    message: String,
+    // Raising the same message again bumps this instead of adding another toast, validation
+    // errors tend to repeat every frame.
+    count: u32,
+    expires: Instant,
+}
+
+// The toasts on screen, newest at the bottom of the window's bottom left corner. Drawn with the
+// text renderer, so nothing shows until a font is set.
+#[derive(Default)]
+pub(super) struct Toasts {
+    shown: VecDeque<Toast>,
+}
+
+impl Toasts {
+    pub(super) fn update(&mut self, now: Instant) {
+        for (level, message) in PENDING.lock().unwrap().drain(..) {
+            match self
+                .shown
+                .iter_mut()
+                .find(|toast| toast.level == level && toast.message == message)
+            {
+                Some(toast) => {
+                    toast.count += 1;
+                    toast.expires = now + LIFETIME;
+                }
+                None => self.shown.push_back(Toast {
+                    level,
+                    message,
+                    count: 1,
+                    expires: now + LIFETIME,
+                }),
+            }
+        }
+        self.shown.retain(|toast| toast.expires > now);
+        while self.shown.len() > MAX_SHOWN {
+            self.shown.pop_front();
+        }
+    }
+
+    pub(super) fn queue(&self, text: &mut TextRenderer, extent: vk::Extent2D, now: Instant) {
+        // Long messages are cut to one line, the full text is in the log.
+        let columns = ((extent.width as f32 - 2.0 * MARGIN) / text.glyph_width(SIZE)) as usize;
+        let mut y = extent.height as f32 - MARGIN - SIZE * self.shown.len() as f32;
+        for toast in &self.shown {
+            let mut line = match toast.count {
+                1 => toast.message.clone(),
+                count => format!("{} (x{})", toast.message, count),
+            };
+            line = line.replace('\n', " ");
+            if line.chars().count() > columns {
+                line = line.chars().take(columns.saturating_sub(3)).collect();
+                line.push_str("...");
+            }
+            let fade = toast
+                .expires
+                .saturating_duration_since(now)
+                .as_secs_f32()
+                .min(1.0);
+            let [r, g, b] = toast.level.colour();
+            text.queue(&line, [MARGIN, y], SIZE, [r, g, b, fade]);
+            y += SIZE;
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use ash::vk;
+
+use super::{config::Tonemapper, debug, pipeline::DYNAMIC_STATES, swapchain::Swapchain};
+
+#[repr(C)]
+struct TonemapPushConstants {
+    exposure: f32,
+    tonemapper: u32,
+}
+
+// A fullscreen pass drawing the scene onto the swapchain image, the first thing in the output
+// pass.
+pub(super) struct TonemapPipeline {
+    pipeline: vk::Pipeline,
+    layout: vk::PipelineLayout,
+    set_layout: vk::DescriptorSetLayout,
+    pool: vk::DescriptorPool,
+    sampler: vk::Sampler,
+    // One for each of the targets the post effects can leave the scene in, only rewritten when
+    // the swapchain is recreated.
+    descriptor_sets: Vec<vk::DescriptorSet>,
+}
+
+impl TonemapPipeline {
+    // `output_pass` is the one from `init_output_pass`, `sources` as for `set_sources`.
+    pub(super) fn init(
+        logical_device: &ash::Device,
+        swapchain: &Swapchain,
+        output_pass: &vk::RenderPass,
+        sources: [vk::ImageView; 2],
+    ) -> Result<TonemapPipeline, vk::Result> {
+        let vertex_shader_create_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("./shaders/fullscreen.glsl", kind: vert));
+        let vertex_shader_module =
+            unsafe { logical_device.create_shader_module(&vertex_shader_create_info, None)? };
+
+        let fragment_shader_create_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("./shaders/tonemap.glsl", kind: frag));
+        let fragment_shader_module =
+            unsafe { logical_device.create_shader_module(&fragment_shader_create_info, None)? };
+
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+
+        let hdr10_output = (swapchain.surface_format.color_space
+            == vk::ColorSpaceKHR::HDR10_ST2084_EXT) as vk::Bool32;
+        let specialization_data = hdr10_output.to_ne_bytes();
+        let specialization_entries = [vk::SpecializationMapEntry {
+            constant_id: 0,
+            offset: 0,
+            size: std::mem::size_of::<vk::Bool32>(),
+        }];
+        let specialization_info = vk::SpecializationInfo::builder()
+            .map_entries(&specialization_entries)
+            .data(&specialization_data);
+
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vertex_shader_module)
+                .name(&main_function_name)
+                .build(),
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(fragment_shader_module)
+                .name(&main_function_name)
+                .specialization_info(&specialization_info)
+                .build(),
+        ];
+
+        // The fullscreen triangle is generated from gl_VertexIndex.
+        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder();
+        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+        let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&DYNAMIC_STATES);
+
+        let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+            .line_width(1.0)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL);
+
+        let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+        let colourblend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(false)
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+            .build()];
+        let colourblend_info =
+            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colourblend_attachments);
+
+        // The output pass has no depth attachment.
+        let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+
+        let bindings = [vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build()];
+        let set_layout_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+        let set_layout =
+            unsafe { logical_device.create_descriptor_set_layout(&set_layout_info, None) }?;
+
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .size(std::mem::size_of::<TonemapPushConstants>() as u32)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build()];
+        let set_layouts = [set_layout];
+        let pipelinelayout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let layout = unsafe { logical_device.create_pipeline_layout(&pipelinelayout_info, None) }?;
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_info)
+            .input_assembly_state(&input_assembly_info)
+            .viewport_state(&viewport_info)
+            .dynamic_state(&dynamic_state_info)
+            .rasterization_state(&rasterizer_info)
+            .multisample_state(&multisampler_info)
+            .color_blend_state(&colourblend_info)
+            .depth_stencil_state(&depth_stencil_state)
+            .layout(layout)
+            .render_pass(*output_pass)
+            .subpass(0);
+
+        let pipeline = unsafe {
+            logical_device
+                .create_graphics_pipelines(
+                    vk::PipelineCache::null(),
+                    &[pipeline_info.build()],
+                    None,
+                )
+                .map_err(|(_, e)| e)
+        }?[0];
+        debug::set_object_name(logical_device, pipeline, "tonemap pipeline");
+        unsafe {
+            logical_device.destroy_shader_module(fragment_shader_module, None);
+            logical_device.destroy_shader_module(vertex_shader_module, None);
+        }
+
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: 2,
+        }];
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(2)
+            .pool_sizes(&pool_sizes);
+        let pool = unsafe { logical_device.create_descriptor_pool(&pool_info, None) }?;
+        let set_layouts = [set_layout; 2];
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(pool)
+            .set_layouts(&set_layouts);
+        let descriptor_sets = unsafe { logical_device.allocate_descriptor_sets(&allocate_info) }?;
+
+        // The scene is exactly the size of the swapchain image, so each pixel reads one texel.
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::NEAREST)
+            .min_filter(vk::Filter::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .max_lod(0.0);
+        let sampler = unsafe { logical_device.create_sampler(&sampler_info, None) }?;
+
+        let tonemap = TonemapPipeline {
+            pipeline,
+            layout,
+            set_layout,
+            pool,
+            sampler,
+            descriptor_sets,
+        };
+        tonemap.set_sources(logical_device, sources);
+        Ok(tonemap)
+    }
+
+    // Points the pass at the scene buffer and the post effects' scratch target, from
+    // `PostProcess::targets`, with no frame using the old ones in flight.
+    pub(super) fn set_sources(&self, logical_device: &ash::Device, sources: [vk::ImageView; 2]) {
+        let image_infos = sources.map(|view| {
+            [vk::DescriptorImageInfo {
+                sampler: self.sampler,
+                image_view: view,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            }]
+        });
+        let writes: Vec<vk::WriteDescriptorSet> = self
+            .descriptor_sets
+            .iter()
+            .zip(&image_infos)
+            .map(|(set, image_info)| {
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(*set)
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(image_info)
+                    .build()
+            })
+            .collect();
+        unsafe { logical_device.update_descriptor_sets(&writes, &[]) };
+    }
+
+    // `source` indexes the views given to `set_sources`, what `PostProcess::record` returned.
+    // `exposure` is in stops, like `Camera::exposure`.
+    pub(super) fn draw(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        source: usize,
+        tonemapper: Tonemapper,
+        exposure: f32,
+    ) {
+        let push_constants = TonemapPushConstants {
+            exposure: exposure.exp2(),
+            tonemapper: tonemapper as u32,
+        };
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout,
+                0,
+                &[self.descriptor_sets[source]],
+                &[],
+            );
+            logical_device.cmd_push_constants(
+                command_buffer,
+                self.layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                std::slice::from_raw_parts(
+                    &push_constants as *const TonemapPushConstants as *const u8,
+                    std::mem::size_of::<TonemapPushConstants>(),
+                ),
+            );
+            logical_device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+
+    pub(super) fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            logical_device.destroy_sampler(self.sampler, None);
+            logical_device.destroy_descriptor_pool(self.pool, None);
+            logical_device.destroy_pipeline(self.pipeline, None);
+            logical_device.destroy_pipeline_layout(self.layout, None);
+            logical_device.destroy_descriptor_set_layout(self.set_layout, None);
+        }
+    }
+}
@@ -0,0 +1,497 @@
+use std::collections::HashMap;
+use std::mem::{offset_of, size_of};
+
+use ash::{vk, Device};
+use egui::{
+    epaint::{Primitive, Vertex},
+    ClippedPrimitive, TextureId,
+};
+use gpu_allocator::vulkan::Allocator;
+
+use super::{
+    debug,
+    frame_allocator::FrameAllocator,
+    pipeline::DYNAMIC_STATES,
+    sampler::SamplerDesc,
+    texture::{TextureHandle, TextureStore},
+};
+
+// egui's filtering for one of its textures. Edges are clamped, egui never tiles them.
+pub(super) fn sampler(options: &egui::TextureOptions) -> SamplerDesc {
+    let filter = |filter| match filter {
+        egui::TextureFilter::Nearest => vk::Filter::NEAREST,
+        egui::TextureFilter::Linear => vk::Filter::LINEAR,
+    };
+    SamplerDesc {
+        mag_filter: filter(options.magnification),
+        min_filter: filter(options.minification),
+        address_mode: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        anisotropy: None,
+        mip_bias: 0.0,
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct UiPushConstants {
+    screen_size: [f32; 2],
+    texture_index: u32,
+}
+
+// One egui mesh, drawn from the vertices and indices the frame wrote for every mesh.
+struct UiDraw {
+    // In points.
+    clip_rect: egui::Rect,
+    texture: TextureId,
+    first_index: u32,
+    index_count: u32,
+    vertex_offset: i32,
+}
+
+// Draws egui's output over the scene in the main pass. Textures come from the `TextureStore`
+// through the same kind of bindless array the scene pipelines use, egui's own (the font atlas
+// among them) are registered there as it asks for them. Only created by the first `draw_ui`.
+pub(super) struct UiRenderer {
+    pipeline: vk::Pipeline,
+    layout: vk::PipelineLayout,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    // The `TextureStore::version` each frame's set was last written with.
+    written: Vec<Option<u64>>,
+    // Linear and clamped, so the edges of the font atlas don't wrap around.
+    sampler: vk::Sampler,
+    // egui's managed textures.
+    textures: HashMap<u64, TextureHandle>,
+    // Freed once the frame is recorded, egui may still draw with them this frame.
+    to_free: Vec<TextureId>,
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    draws: Vec<UiDraw>,
+    pixels_per_point: f32,
+}
+
+impl UiRenderer {
+    pub(super) fn new(
+        logical_device: &Device,
+        renderpass: &vk::RenderPass,
+        frames_in_flight: usize,
+        max_textures: u32,
+    ) -> Result<UiRenderer, vk::Result> {
+        // Only the indices the texture store has handed out are written.
+        let descriptor_binding_flags = [vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT
+            | vk::DescriptorBindingFlags::PARTIALLY_BOUND];
+        let mut descriptorset_layout_binding_flags =
+            vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder()
+                .binding_flags(&descriptor_binding_flags);
+        let layout_bindings = [vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(max_textures)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build()];
+        let descriptor_set_layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .bindings(&layout_bindings)
+            .push_next(&mut descriptorset_layout_binding_flags);
+        let descriptor_set_layout = unsafe {
+            logical_device.create_descriptor_set_layout(&descriptor_set_layout_info, None)
+        }?;
+
+        let descriptor_pool_sizes = [vk::DescriptorPoolSize::builder()
+            .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(max_textures * frames_in_flight as u32)
+            .build()];
+        let descriptor_pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .pool_sizes(&descriptor_pool_sizes)
+            .max_sets(frames_in_flight as u32);
+        let descriptor_pool =
+            unsafe { logical_device.create_descriptor_pool(&descriptor_pool_info, None) }?;
+        let descriptor_set_layouts = vec![descriptor_set_layout; frames_in_flight];
+        let variable_counts = vec![max_textures; frames_in_flight];
+        let mut variable = vk::DescriptorSetVariableDescriptorCountAllocateInfo::builder()
+            .descriptor_counts(&variable_counts);
+        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&descriptor_set_layouts)
+            .push_next(&mut variable);
+        let descriptor_sets =
+            unsafe { logical_device.allocate_descriptor_sets(&descriptor_set_allocate_info) }?;
+
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .size(size_of::<UiPushConstants>() as u32)
+            .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+            .build()];
+        let descriptor_set_layouts = [descriptor_set_layout];
+        let pipelinelayout_info = vk::PipelineLayoutCreateInfo::builder()
+            .push_constant_ranges(&push_constant_ranges)
+            .set_layouts(&descriptor_set_layouts);
+        let layout = unsafe { logical_device.create_pipeline_layout(&pipelinelayout_info, None) }?;
+
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST);
+        let sampler = unsafe { logical_device.create_sampler(&sampler_info, None) }?;
+
+        let pipeline = Self::create_pipeline(logical_device, renderpass, layout)?;
+
+        Ok(UiRenderer {
+            pipeline,
+            layout,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_sets,
+            written: vec![None; frames_in_flight],
+            sampler,
+            textures: HashMap::new(),
+            to_free: vec![],
+            vertices: vec![],
+            indices: vec![],
+            draws: vec![],
+            pixels_per_point: 1.0,
+        })
+    }
+
+    fn create_pipeline(
+        logical_device: &Device,
+        renderpass: &vk::RenderPass,
+        layout: vk::PipelineLayout,
+    ) -> Result<vk::Pipeline, vk::Result> {
+        let vertex_shader_create_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("./shaders/ui_vertex.glsl", kind: vert));
+        let vertex_shader_module =
+            unsafe { logical_device.create_shader_module(&vertex_shader_create_info, None)? };
+
+        let fragment_shader_create_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("./shaders/ui_fragment.glsl", kind: frag));
+        let fragment_shader_module =
+            unsafe { logical_device.create_shader_module(&fragment_shader_create_info, None)? };
+
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vertex_shader_module)
+                .name(&main_function_name)
+                .build(),
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(fragment_shader_module)
+                .name(&main_function_name)
+                .build(),
+        ];
+
+        let attribute = |location: u32, offset: usize, format: vk::Format| {
+            vk::VertexInputAttributeDescription::builder()
+                .binding(0)
+                .location(location)
+                .offset(offset as u32)
+                .format(format)
+                .build()
+        };
+        let vertex_attrib_descs = [
+            attribute(0, offset_of!(Vertex, pos), vk::Format::R32G32_SFLOAT),
+            attribute(1, offset_of!(Vertex, uv), vk::Format::R32G32_SFLOAT),
+            attribute(2, offset_of!(Vertex, color), vk::Format::R8G8B8A8_UNORM),
+        ];
+        let vertex_binding_descs = [vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(size_of::<Vertex>() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX)
+            .build()];
+        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder()
+            .vertex_attribute_descriptions(&vertex_attrib_descs)
+            .vertex_binding_descriptions(&vertex_binding_descs);
+        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+        let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&DYNAMIC_STATES);
+
+        let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+            .line_width(1.0)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL);
+
+        let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+        // egui's colours are premultiplied.
+        let colourblend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::ONE)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_DST_ALPHA)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+            .alpha_blend_op(vk::BlendOp::ADD)
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+            .build()];
+        let colourblend_info =
+            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colourblend_attachments);
+
+        // The UI goes over the scene no matter how close the geometry is.
+        let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_info)
+            .input_assembly_state(&input_assembly_info)
+            .viewport_state(&viewport_info)
+            .dynamic_state(&dynamic_state_info)
+            .rasterization_state(&rasterizer_info)
+            .multisample_state(&multisampler_info)
+            .color_blend_state(&colourblend_info)
+            .depth_stencil_state(&depth_stencil_state)
+            .layout(layout)
+            .render_pass(*renderpass)
+            .subpass(0);
+
+        let pipeline = unsafe {
+            logical_device
+                .create_graphics_pipelines(
+                    vk::PipelineCache::null(),
+                    &[pipeline_info.build()],
+                    None,
+                )
+                .map_err(|(_, e)| e)
+        }?[0];
+        debug::set_object_name(logical_device, pipeline, "ui pipeline");
+        unsafe {
+            logical_device.destroy_shader_module(fragment_shader_module, None);
+            logical_device.destroy_shader_module(vertex_shader_module, None);
+        }
+        Ok(pipeline)
+    }
+
+    pub(super) fn texture(&self, id: u64) -> Option<&TextureHandle> {
+        self.textures.get(&id)
+    }
+
+    // Replaces egui's texture `id`, the old one is collected once nothing draws with it.
+    pub(super) fn set_texture(&mut self, id: u64, texture: TextureHandle) {
+        self.textures.insert(id, texture);
+    }
+
+    pub(super) fn free_textures(&mut self, ids: &[TextureId]) {
+        self.to_free.extend_from_slice(ids);
+    }
+
+    // Replaces whatever was queued with this frame's egui output.
+    pub(super) fn queue(&mut self, primitives: &[ClippedPrimitive], pixels_per_point: f32) {
+        self.vertices.clear();
+        self.indices.clear();
+        self.draws.clear();
+        self.pixels_per_point = pixels_per_point;
+        for primitive in primitives {
+            match &primitive.primitive {
+                Primitive::Mesh(mesh) => {
+                    self.draws.push(UiDraw {
+                        clip_rect: primitive.clip_rect,
+                        texture: mesh.texture_id,
+                        first_index: self.indices.len() as u32,
+                        index_count: mesh.indices.len() as u32,
+                        vertex_offset: self.vertices.len() as i32,
+                    });
+                    self.vertices.extend_from_slice(&mesh.vertices);
+                    self.indices.extend_from_slice(&mesh.indices);
+                }
+                // Callbacks are for painting with the backend directly, use a `RenderMiddleware`.
+                Primitive::Callback(_) => {}
+            }
+        }
+    }
+
+    // Draws what was queued since the last call and clears the queue. Must be recorded inside the
+    // main render pass.
+    pub(super) fn draw(
+        &mut self,
+        frame_allocator: &mut FrameAllocator,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        command_buffer: vk::CommandBuffer,
+        slot: usize,
+        extent: vk::Extent2D,
+        texture_store: &TextureStore,
+    ) -> Result<(), vk::Result> {
+        if !self.draws.is_empty() {
+            self.record(
+                frame_allocator,
+                allocator,
+                logical_device,
+                command_buffer,
+                slot,
+                extent,
+                texture_store,
+            )?;
+        }
+        self.draws.clear();
+        for id in self.to_free.drain(..) {
+            if let TextureId::Managed(id) = id {
+                self.textures.remove(&id);
+            }
+        }
+        Ok(())
+    }
+
+    fn record(
+        &mut self,
+        frame_allocator: &mut FrameAllocator,
+        allocator: &mut Allocator,
+        logical_device: &Device,
+        command_buffer: vk::CommandBuffer,
+        slot: usize,
+        extent: vk::Extent2D,
+        texture_store: &TextureStore,
+    ) -> Result<(), vk::Result> {
+        if self.written[slot] != Some(texture_store.version()) {
+            let image_infos: Vec<vk::DescriptorImageInfo> = texture_store
+                .get_descriptor_image_info()
+                .into_iter()
+                .map(|info| vk::DescriptorImageInfo {
+                    sampler: self.sampler,
+                    ..info
+                })
+                .collect();
+            if !image_infos.is_empty() {
+                let descriptor_writes = [vk::WriteDescriptorSet::builder()
+                    .dst_set(self.descriptor_sets[slot])
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&image_infos)
+                    .build()];
+                unsafe { logical_device.update_descriptor_sets(&descriptor_writes, &[]) };
+                self.written[slot] = Some(texture_store.version());
+            }
+        }
+
+        let vertices = frame_allocator.push(allocator, logical_device, slot, &self.vertices)?;
+        let indices = frame_allocator.push(allocator, logical_device, slot, &self.indices)?;
+
+        let mut push_constants = UiPushConstants {
+            screen_size: [
+                extent.width as f32 / self.pixels_per_point,
+                extent.height as f32 / self.pixels_per_point,
+            ],
+            texture_index: 0,
+        };
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout,
+                0,
+                &[self.descriptor_sets[slot]],
+                &[],
+            );
+            logical_device.cmd_bind_vertex_buffers(
+                command_buffer,
+                0,
+                &[vertices.buffer],
+                &[vertices.offset],
+            );
+            logical_device.cmd_bind_index_buffer(
+                command_buffer,
+                indices.buffer,
+                indices.offset,
+                vk::IndexType::UINT32,
+            );
+        }
+        for draw in &self.draws {
+            let index = match draw.texture {
+                TextureId::Managed(id) => self
+                    .textures
+                    .get(&id)
+                    .and_then(|texture| texture_store.index(texture)),
+                // `Vulkan::ui_texture_id` hands out texture store indices.
+                TextureId::User(index) => Some(index as u32),
+            };
+            let scissor = match (index, self.scissor(draw.clip_rect, extent)) {
+                (Some(index), Some(scissor)) => {
+                    push_constants.texture_index = index;
+                    scissor
+                }
+                _ => continue,
+            };
+            unsafe {
+                logical_device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+                logical_device.cmd_push_constants(
+                    command_buffer,
+                    self.layout,
+                    vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    std::slice::from_raw_parts(
+                        &push_constants as *const UiPushConstants as *const u8,
+                        size_of::<UiPushConstants>(),
+                    ),
+                );
+                logical_device.cmd_draw_indexed(
+                    command_buffer,
+                    draw.index_count,
+                    1,
+                    draw.first_index,
+                    draw.vertex_offset,
+                    0,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // `clip_rect` in pixels and clamped to the screen, None when nothing of it is on screen.
+    fn scissor(&self, clip_rect: egui::Rect, extent: vk::Extent2D) -> Option<vk::Rect2D> {
+        let to_pixels = |points: f32, max: u32| {
+            (points * self.pixels_per_point)
+                .round()
+                .clamp(0.0, max as f32) as u32
+        };
+        let (left, top) = (
+            to_pixels(clip_rect.min.x, extent.width),
+            to_pixels(clip_rect.min.y, extent.height),
+        );
+        let (right, bottom) = (
+            to_pixels(clip_rect.max.x, extent.width),
+            to_pixels(clip_rect.max.y, extent.height),
+        );
+        (right > left && bottom > top).then(|| vk::Rect2D {
+            offset: vk::Offset2D {
+                x: left as i32,
+                y: top as i32,
+            },
+            extent: vk::Extent2D {
+                width: right - left,
+                height: bottom - top,
+            },
+        })
+    }
+
+    pub(super) fn cleanup(&mut self, logical_device: &Device) {
+        unsafe {
+            logical_device.destroy_sampler(self.sampler, None);
+            logical_device.destroy_pipeline(self.pipeline, None);
+            logical_device.destroy_pipeline_layout(self.layout, None);
+            logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+            logical_device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+        self.textures.clear();
+    }
+}
@@ -0,0 +1,265 @@
+use ash::vk;
+use uuid::Uuid;
+
+use crate::jr_mesh::CompressedMeshVertex;
+
+use super::{
+    mesh::ShaderVertexData,
+    spirv::{self, ComponentKind, ReflectionError},
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct VertexAttribute {
+    pub location: u32,
+    pub offset: u32,
+    pub format: vk::Format,
+}
+
+/// Describes how a mesh's vertex buffer is laid out, one entry per shader input location.
+#[derive(Clone, Debug)]
+pub struct VertexLayout {
+    pub stride: u32,
+    pub attributes: Vec<VertexAttribute>,
+}
+
+impl VertexLayout {
+    pub fn new(stride: u32) -> VertexLayout {
+        VertexLayout {
+            stride,
+            attributes: vec![],
+        }
+    }
+
+    pub fn attribute(mut self, location: u32, offset: u32, format: vk::Format) -> VertexLayout {
+        self.attributes.push(VertexAttribute {
+            location,
+            offset,
+            format,
+        });
+        self
+    }
+
+    // The layout of `ShaderVertexData`, used by the built-in pipeline.
+    pub(super) fn shader_vertex_data() -> VertexLayout {
+        ShaderVertexData::layout()
+    }
+
+    /// The layout of `jr_mesh::CompressedMeshVertex`: half float positions and uvs, and octahedral
+    /// normals the vertex shader has to unpack.
+    pub fn compressed() -> VertexLayout {
+        CompressedMeshVertex::layout()
+    }
+
+    pub(super) fn attribute_descriptions(
+        &self,
+        binding: u32,
+    ) -> Vec<vk::VertexInputAttributeDescription> {
+        self.attributes
+            .iter()
+            .map(|attribute| {
+                vk::VertexInputAttributeDescription::builder()
+                    .binding(binding)
+                    .location(attribute.location)
+                    .offset(attribute.offset)
+                    .format(attribute.format)
+                    .build()
+            })
+            .collect()
+    }
+
+    pub(super) fn binding_description(
+        &self,
+        binding: u32,
+        input_rate: vk::VertexInputRate,
+    ) -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::builder()
+            .binding(binding)
+            .stride(self.stride)
+            .input_rate(input_rate)
+            .build()
+    }
+}
+
+/// A vertex type that knows its own layout, so offsets and the stride follow the struct instead of
+/// being written out by hand. Implement it with `vertex_input!`, which takes the offsets from the
+/// fields and checks at compile time that every attribute fits in the struct.
+pub trait VertexInput: Sized {
+    const ATTRIBUTES: &'static [VertexAttribute];
+
+    fn layout() -> VertexLayout {
+        VertexLayout {
+            stride: std::mem::size_of::<Self>() as u32,
+            attributes: Self::ATTRIBUTES.to_vec(),
+        }
+    }
+}
+
+// Implements `VertexInput` for a `#[repr(C)]` struct from its shader locations, fields and formats,
+// e.g. for `ShaderVertexData`:
+//
+//     vertex_input!(ShaderVertexData {
+//         5 => position: R32G32B32_SFLOAT,
+//         6 => uv: R32G32_SFLOAT,
+//         7 => normal: R32G32B32_SFLOAT,
+//     });
+//
+// A field read as several attributes, like a matrix one column per location, takes a byte offset
+// into the field after its name: `1 => model + 16: R32G32B32A32_SFLOAT`.
+#[macro_export]
+macro_rules! vertex_input {
+    ($vertex:ty {
+        $($location:literal => $field:ident $(+ $offset:literal)?: $format:ident),* $(,)?
+    }) => {
+        impl $crate::vulkan::VertexInput for $vertex {
+            const ATTRIBUTES: &'static [$crate::vulkan::VertexAttribute] = &[$(
+                $crate::vulkan::VertexAttribute {
+                    location: $location,
+                    offset: (std::mem::offset_of!($vertex, $field) $(+ $offset)?) as u32,
+                    format: $crate::vulkan::ash::vk::Format::$format,
+                }
+            ),*];
+        }
+
+        const _: () = $crate::vulkan::assert_attributes_fit(
+            <$vertex as $crate::vulkan::VertexInput>::ATTRIBUTES,
+            std::mem::size_of::<$vertex>(),
+        );
+    };
+}
+
+/// Fails the build from `vertex_input!` if an attribute has a format `format_info` doesn't know or
+/// reaches past the end of its struct.
+#[doc(hidden)]
+pub const fn assert_attributes_fit(attributes: &[VertexAttribute], size: usize) {
+    let mut i = 0;
+    while i < attributes.len() {
+        let attribute = attributes[i];
+        let attribute_size = match format_info(attribute.format) {
+            Some((_, size)) => size as usize,
+            None => panic!("a vertex attribute uses a format vertex_format doesn't know"),
+        };
+        assert!(
+            attribute.offset as usize + attribute_size <= size,
+            "a vertex attribute reaches past the end of its struct"
+        );
+        i += 1;
+    }
+}
+
+#[derive(Debug)]
+pub enum VertexLayoutError {
+    Reflection(ReflectionError),
+    /// Two attributes (possibly one of them an instance attribute) claim the same location.
+    DuplicateLocation(u32),
+    /// The attribute does not fit inside the stride.
+    AttributeOutOfBounds(u32),
+    UnsupportedFormat(vk::Format),
+    /// The shader reads a location nothing provides.
+    MissingLocation(u32),
+    /// The shader and the layout disagree on the type at a location.
+    TypeMismatch(u32),
+}
+
+impl From<ReflectionError> for VertexLayoutError {
+    fn from(value: ReflectionError) -> Self {
+        VertexLayoutError::Reflection(value)
+    }
+}
+
+/// A user supplied vertex layout together with the shaders that consume it. The shaders get the
+/// same descriptor sets as the built-in ones: textures at set 0, the `FrameUniforms` block from
+/// vertex.glsl at set 1 and materials at set 2.
+#[derive(Clone)]
+pub struct VertexFormat {
+    pub layout: VertexLayout,
+    pub vertex_shader: Vec<u32>,
+    pub fragment_shader: Vec<u32>,
+}
+
+impl VertexFormat {
+    /// The built-in shaders reading `VertexLayout::compressed`.
+    pub fn compressed() -> VertexFormat {
+        VertexFormat {
+            layout: VertexLayout::compressed(),
+            vertex_shader: vk_shader_macros::include_glsl!(
+                "./shaders/vertex.glsl",
+                kind: vert,
+                define: COMPRESSED_VERTICES
+            )
+            .to_vec(),
+            fragment_shader: vk_shader_macros::include_glsl!("./shaders/fragment.glsl", kind: frag)
+                .to_vec(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VertexFormatHandle {
+    pub(super) id: Uuid,
+}
+
+// The component type a format delivers to a shader and its size in bytes.
+pub(super) const fn format_info(format: vk::Format) -> Option<(ComponentKind, u32)> {
+    Some(match format {
+        vk::Format::R32_SFLOAT => (ComponentKind::Float, 4),
+        vk::Format::R32G32_SFLOAT => (ComponentKind::Float, 8),
+        vk::Format::R32G32B32_SFLOAT => (ComponentKind::Float, 12),
+        vk::Format::R32G32B32A32_SFLOAT => (ComponentKind::Float, 16),
+        vk::Format::R16G16_SFLOAT => (ComponentKind::Float, 4),
+        vk::Format::R16G16B16A16_SFLOAT => (ComponentKind::Float, 8),
+        vk::Format::R8G8B8A8_UNORM => (ComponentKind::Float, 4),
+        vk::Format::R8G8B8A8_SNORM => (ComponentKind::Float, 4),
+        vk::Format::R16G16_SNORM => (ComponentKind::Float, 4),
+        vk::Format::R32_UINT => (ComponentKind::Uint, 4),
+        vk::Format::R32G32_UINT => (ComponentKind::Uint, 8),
+        vk::Format::R32G32B32_UINT => (ComponentKind::Uint, 12),
+        vk::Format::R32G32B32A32_UINT => (ComponentKind::Uint, 16),
+        vk::Format::R8G8B8A8_UINT => (ComponentKind::Uint, 4),
+        vk::Format::R32_SINT => (ComponentKind::Sint, 4),
+        vk::Format::R32G32_SINT => (ComponentKind::Sint, 8),
+        vk::Format::R32G32B32_SINT => (ComponentKind::Sint, 12),
+        vk::Format::R32G32B32A32_SINT => (ComponentKind::Sint, 16),
+        _ => return None,
+    })
+}
+
+// Checks a vertex shader against every attribute the pipeline will feed it. Extra attributes the
+// shader ignores are allowed, anything the shader reads must be provided with a matching type.
+pub(super) fn validate(
+    attributes: &[vk::VertexInputAttributeDescription],
+    strides: &[(u32, u32)],
+    vertex_shader: &[u32],
+) -> Result<(), VertexLayoutError> {
+    for (index, attribute) in attributes.iter().enumerate() {
+        if attributes[..index]
+            .iter()
+            .any(|other| other.location == attribute.location)
+        {
+            return Err(VertexLayoutError::DuplicateLocation(attribute.location));
+        }
+        let (_, size) = format_info(attribute.format)
+            .ok_or(VertexLayoutError::UnsupportedFormat(attribute.format))?;
+        let stride = strides
+            .iter()
+            .find(|(binding, _)| *binding == attribute.binding)
+            .map(|(_, stride)| *stride)
+            .unwrap_or(0);
+        if attribute.offset + size > stride {
+            return Err(VertexLayoutError::AttributeOutOfBounds(attribute.location));
+        }
+    }
+
+    for input in spirv::vertex_inputs(vertex_shader)? {
+        let attribute = attributes
+            .iter()
+            .find(|attribute| attribute.location == input.location)
+            .ok_or(VertexLayoutError::MissingLocation(input.location))?;
+        let (kind, _) = format_info(attribute.format)
+            .ok_or(VertexLayoutError::UnsupportedFormat(attribute.format))?;
+        // Component counts may differ, Vulkan drops extra components and fills in missing ones.
+        if kind != input.kind {
+            return Err(VertexLayoutError::TypeMismatch(input.location));
+        }
+    }
+    Ok(())
+}
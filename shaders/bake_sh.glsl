@@ -0,0 +1,46 @@
+#version 450
+
+// Projects one row of an equirectangular environment per invocation onto first order spherical
+// harmonics, for the CPU to sum. Matches `texel_direction` in bake.rs.
+
+layout(local_size_x=64)in;
+
+layout(set=0,binding=0)readonly buffer Environment{
+    vec4 texels[];
+};
+layout(set=0,binding=1)writeonly buffer Rows{
+    // Four rgb coefficients per row: constant, then the linear terms along y, z and x.
+    vec4 coefficients[];
+};
+
+layout(push_constant)uniform constants{
+    uvec2 size;
+}PushConstants;
+
+const float PI=3.14159265;
+const float Y0=0.282095;
+const float Y1=0.488603;
+
+void main(){
+    uint row=gl_GlobalInvocationID.x;
+    uvec2 size=PushConstants.size;
+    if(row>=size.y){
+        return;
+    }
+    // The top row is straight up and the centre column faces +z.
+    float latitude=(0.5-(float(row)+0.5)/float(size.y))*PI;
+    float weight=cos(latitude)*(PI/float(size.y))*(2.0*PI/float(size.x));
+    vec3 sums[4]=vec3[4](vec3(0),vec3(0),vec3(0),vec3(0));
+    for(uint column=0;column<size.x;column++){
+        float longitude=((float(column)+0.5)/float(size.x)-0.5)*2.0*PI;
+        vec3 d=vec3(cos(latitude)*sin(longitude),sin(latitude),cos(latitude)*cos(longitude));
+        vec3 radiance=texels[row*size.x+column].rgb*weight;
+        sums[0]+=radiance*Y0;
+        sums[1]+=radiance*Y1*d.y;
+        sums[2]+=radiance*Y1*d.z;
+        sums[3]+=radiance*Y1*d.x;
+    }
+    for(uint i=0;i<4;i++){
+        coefficients[row*4+i]=vec4(sums[i],0.0);
+    }
+}
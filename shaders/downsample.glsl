@@ -0,0 +1,38 @@
+#version 450
+
+// Shrinks a texture into a tightly packed RGBA8 buffer for reading back on the CPU.
+
+layout(local_size_x=8,local_size_y=8)in;
+
+layout(set=0,binding=0)uniform sampler2D source;
+layout(set=0,binding=1)writeonly buffer Output{
+    uint pixels[];
+};
+
+layout(push_constant)uniform constants{
+    uvec2 size;
+}PushConstants;
+
+vec3 linear_to_srgb(vec3 colour){
+    return mix(colour*12.92,1.055*pow(colour,vec3(1.0/2.4))-0.055,step(0.0031308,colour));
+}
+
+void main(){
+    uvec2 pixel=gl_GlobalInvocationID.xy;
+    if(any(greaterThanEqual(pixel,PushConstants.size))){
+        return;
+    }
+    // Box filter over the area of the source the output pixel covers. 4x4 bilinear taps cover up
+    // to 8x8 texels exactly, larger footprints are approximated.
+    vec2 texel=1.0/vec2(PushConstants.size);
+    vec4 sum=vec4(0);
+    for(int y=0;y<4;y++){
+        for(int x=0;x<4;x++){
+            vec2 uv=(vec2(pixel)+(vec2(x,y)+0.5)/4.0)*texel;
+            sum+=textureLod(source,uv,0);
+        }
+    }
+    // Sampling decoded the sRGB source, so the average is in linear space and needs encoding.
+    vec4 colour=sum/16.0;
+    pixels[pixel.y*PushConstants.size.x+pixel.x]=packUnorm4x8(vec4(linear_to_srgb(colour.rgb),colour.a));
+}
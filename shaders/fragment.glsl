@@ -3,22 +3,144 @@
 
 layout(set=0,binding=0)uniform sampler2D tex_samplers[];
 
+struct MaterialData{
+    vec4 base_colour;
+    // rgb: tint, a: power
+    vec4 specular;
+};
+layout(std430,set=2,binding=0)readonly buffer Materials{
+    MaterialData materials[];
+};
+
+// Drawn by shadow.glsl, compared against by the sampler.
+layout(set=1,binding=1)uniform sampler2DShadow shadow_map;
+
+// See LightData in lights.rs.
+struct LightData{
+    // xyz: position, w: range
+    vec4 position;
+    vec4 colour;
+    // xyz: where a spot light points
+    vec4 direction;
+    // x: cosine of the inner angle, y: cosine of the outer angle
+    vec4 cone;
+};
+layout(std430,set=2,binding=1)readonly buffer Lights{
+    LightData lights[];
+};
+
+// The same block as in vertex.glsl.
+layout(std140,set=1,binding=0)uniform FrameUniforms{
+    mat4 view;
+    mat4 projection;
+    mat4 view_projection;
+    vec4 camera_position;
+    vec4 time;
+    vec4 wind;
+    vec4 sun_direction;
+    vec4 sun_colour;
+    vec4 ambient;
+    // x: lights in the light buffer
+    uvec4 light_count;
+    // The sun's, for the shadow map
+    mat4 shadow_view_projection;
+    // x: 1 when shadows are on, y: normal bias, z: one shadow map texel in uv
+    vec4 shadow;
+}Frame;
+
 layout(location=0)in vec2 uv_from_vertex_shader;
 layout(location=1)in vec3 normal_from_vertex_shader;
 layout(location=2)in flat uint tex_id_from_vertex_shader;
+layout(location=3)in flat uint material_id_from_vertex_shader;
+layout(location=4)in vec3 ambient_from_vertex_shader;
+layout(location=5)in float view_depth_from_vertex_shader;
+layout(location=6)in vec3 world_position_from_vertex_shader;
+layout(location=7)in vec4 tint_from_vertex_shader;
 
 
 layout(location=0)out vec4 output_colour;
 
+// Which DebugView to draw instead of the shaded scene, 0 for none.
+layout(constant_id=1)const uint DEBUG_VIEW=0;
+
+// Throws away fragments with alpha under one half, see RenderState::alpha_test.
+layout(constant_id=2)const bool ALPHA_TEST=false;
+
+// Adds the diffuse and Blinn-Phong specular light arriving from `to_light` with `radiance`.
+void add_light(vec3 normal,vec3 to_light,vec3 to_camera,vec3 radiance,float power,
+               inout vec3 diffuse,inout vec3 specular){
+    float lambert=max(dot(normal,to_light),0);
+    vec3 halfway=normalize(to_light+to_camera);
+    // Only on the lit side so the highlight doesn't show through the back.
+    float highlight=lambert>0?pow(max(dot(normal,halfway),0),power):0.0;
+    diffuse+=lambert*radiance;
+    specular+=highlight*radiance;
+}
+
+// How much of the sun reaches `world_position`, from 0 in shadow to 1 lit. Averages a 3x3 grid
+// of comparisons to soften the edges.
+float sun_visibility(vec3 world_position,vec3 normal){
+    if(Frame.shadow.x==0){
+        return 1;
+    }
+    vec4 coords=Frame.shadow_view_projection*vec4(world_position+normal*Frame.shadow.y,1);
+    // Past the far end of the map nothing is known, call it lit.
+    if(coords.z<0||coords.z>1){
+        return 1;
+    }
+    vec2 uv=coords.xy*0.5+0.5;
+    float lit=0;
+    for(int x=-1;x<=1;x++){
+        for(int y=-1;y<=1;y++){
+            lit+=texture(shadow_map,vec3(uv+vec2(x,y)*Frame.shadow.z,coords.z));
+        }
+    }
+    return lit/9;
+}
+
 void main(){
-    vec4 albedo = texture(tex_samplers[tex_id_from_vertex_shader], uv_from_vertex_shader);
-    // This is the same as above but with a hard-coded branch to select the texture: This works properly
-    // vec4 albedo;
-    // if (tex_id_from_vertex_shader == 0) {
-    //     albedo = texture(tex_samplers[0], uv_from_vertex_shader);
-    // } else {
-    //     albedo = texture(tex_samplers[1], uv_from_vertex_shader);
-    // }
-    float light = clamp(dot(normal_from_vertex_shader, normalize(vec3(1,1,1))), 0.2, 1);
-    output_colour =  vec4(albedo.rgb * light, albedo.a);
+    MaterialData material = materials[material_id_from_vertex_shader];
+    // Instances drawn together can use different textures, so the index isn't uniform.
+    vec4 albedo = texture(tex_samplers[nonuniformEXT(tex_id_from_vertex_shader)], uv_from_vertex_shader)
+        * material.base_colour * tint_from_vertex_shader;
+    if(ALPHA_TEST && albedo.a < 0.5){
+        discard;
+    }
+#ifdef UNLIT
+    output_colour = albedo;
+#else
+    vec3 normal = normalize(normal_from_vertex_shader);
+    vec3 world_position = world_position_from_vertex_shader;
+    vec3 to_camera = normalize(Frame.camera_position.xyz - world_position);
+    vec3 diffuse = ambient_from_vertex_shader + Frame.ambient.rgb;
+    vec3 specular = vec3(0);
+    vec3 sun = Frame.sun_colour.rgb * sun_visibility(world_position, normal);
+    add_light(normal, Frame.sun_direction.xyz, to_camera, sun, material.specular.a, diffuse, specular);
+    for(uint i = 0; i < Frame.light_count.x; i++){
+        LightData light = lights[i];
+        vec3 offset = light.position.xyz - world_position;
+        float light_distance = length(offset);
+        vec3 to_light = offset / max(light_distance, 1e-4);
+        // Inverse square, windowed to reach nothing at the light's range.
+        float window = clamp(1 - pow(light_distance / light.position.w, 4), 0, 1);
+        float falloff = window * window / (light_distance * light_distance + 1);
+        float cone = clamp((dot(-to_light, light.direction.xyz) - light.cone.y) / (light.cone.x - light.cone.y), 0, 1);
+        add_light(normal, to_light, to_camera, light.colour.rgb * falloff * cone, material.specular.a,
+            diffuse, specular);
+    }
+    output_colour = vec4(albedo.rgb * diffuse + material.specular.rgb * specular, albedo.a);
+#endif
+    if(DEBUG_VIEW==1){
+        output_colour=vec4(normal_from_vertex_shader*0.5+0.5,1);
+    }else if(DEBUG_VIEW==2){
+        output_colour=vec4(fract(uv_from_vertex_shader),0,1);
+    }else if(DEBUG_VIEW==3){
+        // Added up by the blend state, ten layers reach full red.
+        output_colour=vec4(0.1,0.03,0.01,1);
+    }else if(DEBUG_VIEW==4){
+        uint id=tex_id_from_vertex_shader;
+        output_colour=vec4(fract(vec3(id)*vec3(0.618034,0.414214,0.732051)+0.2),1);
+    }else if(DEBUG_VIEW==5){
+        output_colour=vec4(vec3(exp(-view_depth_from_vertex_shader/10.0)),1);
+    }
 }
@@ -0,0 +1,11 @@
+#version 450
+
+// A single triangle covering the whole screen, no vertex buffers needed.
+
+layout(location=0)out vec2 uv_for_fragment_shader;
+
+void main(){
+    vec2 uv=vec2((gl_VertexIndex<<1)&2,gl_VertexIndex&2);
+    uv_for_fragment_shader=uv;
+    gl_Position=vec4(uv*2-1,0,1);
+}
@@ -0,0 +1,47 @@
+#version 450
+
+// Spreads light brighter than the threshold into a glow around it. One pass over rings of taps
+// rather than a chain of blurred mips, so keep the radius to tens of pixels.
+
+layout(set=0,binding=0)uniform sampler2D source;
+
+layout(push_constant)uniform constants{
+    // x: threshold, y: intensity, z: radius in pixels
+    vec4 params;
+    vec2 texel_size;
+}PushConstants;
+
+layout(location=0)in vec2 uv_from_vertex_shader;
+
+layout(location=0)out vec4 output_colour;
+
+const int RINGS=4;
+const int TAPS=8;
+const float PI=3.14159265;
+
+// What of the colour at `uv` is above the threshold, keeping its hue.
+vec3 bright(vec2 uv){
+    vec3 colour=max(texture(source,uv).rgb,vec3(0));
+    float brightness=max(colour.r,max(colour.g,colour.b));
+    return colour*(max(brightness-PushConstants.params.x,0.0)/max(brightness,0.0001));
+}
+
+void main(){
+    vec2 uv=uv_from_vertex_shader;
+    vec3 glow=vec3(0);
+    float total=0;
+    for(int ring=1;ring<=RINGS;ring++){
+        float radius=PushConstants.params.z*float(ring)/float(RINGS);
+        // Nearer rings count for more, so the glow fades out.
+        float weight=float(RINGS-ring+1);
+        for(int tap=0;tap<TAPS;tap++){
+            // Each ring is turned half a tap from the last to fill the gaps.
+            float angle=(float(tap)+0.5*float(ring))*(2.0*PI/float(TAPS));
+            vec2 offset=vec2(cos(angle),sin(angle))*radius*PushConstants.texel_size;
+            glow+=bright(uv+offset)*weight;
+            total+=weight;
+        }
+    }
+    vec3 colour=texture(source,uv).rgb;
+    output_colour=vec4(colour+glow/total*PushConstants.params.y,1);
+}
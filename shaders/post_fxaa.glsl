@@ -0,0 +1,51 @@
+#version 450
+
+// Fast approximate antialiasing, Timothy Lottes' FXAA without the edge search: blurs along the
+// direction the edge runs, and backs off to a shorter blur if the longer one picked up the other
+// side.
+
+layout(set=0,binding=0)uniform sampler2D source;
+
+layout(push_constant)uniform constants{
+    vec4 params;
+    vec2 texel_size;
+}PushConstants;
+
+layout(location=0)in vec2 uv_from_vertex_shader;
+
+layout(location=0)out vec4 output_colour;
+
+const float REDUCE_MIN=1.0/128.0;
+const float REDUCE_MUL=1.0/8.0;
+const float SPAN_MAX=8.0;
+
+// The scene is HDR, squash it into 0-1 first so the edges of bright lights aren't all that's
+// found.
+float luma(vec3 colour){
+    colour=max(colour,vec3(0));
+    return dot(colour/(1.0+colour),vec3(0.299,0.587,0.114));
+}
+
+void main(){
+    vec2 uv=uv_from_vertex_shader;
+    vec2 texel=PushConstants.texel_size;
+    float luma_nw=luma(texture(source,uv+vec2(-1,-1)*texel).rgb);
+    float luma_ne=luma(texture(source,uv+vec2(1,-1)*texel).rgb);
+    float luma_sw=luma(texture(source,uv+vec2(-1,1)*texel).rgb);
+    float luma_se=luma(texture(source,uv+vec2(1,1)*texel).rgb);
+    float luma_m=luma(texture(source,uv).rgb);
+    float luma_min=min(luma_m,min(min(luma_nw,luma_ne),min(luma_sw,luma_se)));
+    float luma_max=max(luma_m,max(max(luma_nw,luma_ne),max(luma_sw,luma_se)));
+
+    vec2 direction=vec2(-((luma_nw+luma_ne)-(luma_sw+luma_se)),(luma_nw+luma_sw)-(luma_ne+luma_se));
+    float reduce=max((luma_nw+luma_ne+luma_sw+luma_se)*0.25*REDUCE_MUL,REDUCE_MIN);
+    float scale=1.0/(min(abs(direction.x),abs(direction.y))+reduce);
+    direction=clamp(direction*scale,vec2(-SPAN_MAX),vec2(SPAN_MAX))*texel;
+
+    vec3 near=0.5*(texture(source,uv+direction*(1.0/3.0-0.5)).rgb
+                  +texture(source,uv+direction*(2.0/3.0-0.5)).rgb);
+    vec3 far=0.5*near+0.25*(texture(source,uv-direction*0.5).rgb
+                           +texture(source,uv+direction*0.5).rgb);
+    float luma_far=luma(far);
+    output_colour=vec4((luma_far<luma_min||luma_far>luma_max)?near:far,1);
+}
@@ -0,0 +1,20 @@
+#version 450
+
+// Raises the scene to 1 / gamma, above 1 lifts the mid tones and below 1 sinks them.
+
+layout(set=0,binding=0)uniform sampler2D source;
+
+layout(push_constant)uniform constants{
+    // x: gamma
+    vec4 params;
+    vec2 texel_size;
+}PushConstants;
+
+layout(location=0)in vec2 uv_from_vertex_shader;
+
+layout(location=0)out vec4 output_colour;
+
+void main(){
+    vec3 colour=max(texture(source,uv_from_vertex_shader).rgb,vec3(0));
+    output_colour=vec4(pow(colour,vec3(1.0/PushConstants.params.x)),1);
+}
@@ -0,0 +1,23 @@
+#version 450
+
+// Darkens the scene towards the corners.
+
+layout(set=0,binding=0)uniform sampler2D source;
+
+layout(push_constant)uniform constants{
+    // x: how much is taken off at the corners, y: how far out it starts, 0 at the centre and 1 at
+    // the corners.
+    vec4 params;
+    vec2 texel_size;
+}PushConstants;
+
+layout(location=0)in vec2 uv_from_vertex_shader;
+
+layout(location=0)out vec4 output_colour;
+
+void main(){
+    vec3 colour=texture(source,uv_from_vertex_shader).rgb;
+    float from_centre=length(uv_from_vertex_shader*2-1)/sqrt(2.0);
+    float darkening=smoothstep(PushConstants.params.y,1.0,from_centre)*PushConstants.params.x;
+    output_colour=vec4(colour*clamp(1.0-darkening,0.0,1.0),1);
+}
@@ -0,0 +1,57 @@
+#version 450
+
+// Draws the scene's depth as the sun sees it, for the shadow map. Moves vertices the same way as
+// vertex.glsl so swaying geometry casts swaying shadows, and passes on what shadow_fragment.glsl
+// needs for the alpha test.
+
+// See FrameUniformData in frame_uniforms.rs.
+layout(std140,set=1,binding=0)uniform FrameUniforms{
+    mat4 view;
+    mat4 projection;
+    mat4 view_projection;
+    vec4 camera_position;
+    // x: seconds since the renderer started
+    vec4 time;
+    // xy: wind direction, z: wind strength
+    vec4 wind;
+    vec4 sun_direction;
+    vec4 sun_colour;
+    vec4 ambient;
+    // x: lights in the light buffer
+    uvec4 light_count;
+    // The sun's, for the shadow map
+    mat4 shadow_view_projection;
+    // x: 1 when shadows are on, y: normal bias, z: one shadow map texel in uv
+    vec4 shadow;
+}Frame;
+
+layout(location=0)in mat4 model;
+layout(location=4)in uint tex_id;
+layout(location=5)in vec3 position;
+layout(location=6)in vec2 uv;
+// x: sway amplitude, y: sway frequency
+layout(location=8)in vec2 sway;
+layout(location=9)in uint material_id;
+// xy: offset, zw: scale, applied to the mesh's uvs
+layout(location=10)in vec4 uv_rect;
+layout(location=14)in vec4 tint;
+
+// At the locations fragment.glsl reads them from vertex.glsl.
+layout(location=0)out vec2 uv_for_fragment_shader;
+layout(location=2)out uint tex_id_for_fragment_shader;
+layout(location=3)out uint material_id_for_fragment_shader;
+layout(location=7)out vec4 tint_for_fragment_shader;
+
+void main(){
+    vec4 world_position=model*vec4(position,1);
+    if(sway.x>0){
+        float phase=Frame.time.x*sway.y*6.2831853+world_position.x+world_position.z;
+        float bend=sway.x*Frame.wind.z*sin(phase)*max(position.y,0);
+        world_position.xz+=Frame.wind.xy*bend;
+    }
+    gl_Position=Frame.shadow_view_projection*world_position;
+    uv_for_fragment_shader=uv_rect.xy+uv*uv_rect.zw;
+    tex_id_for_fragment_shader=tex_id;
+    material_id_for_fragment_shader=material_id;
+    tint_for_fragment_shader=tint;
+}
@@ -0,0 +1,30 @@
+#version 450
+#extension GL_EXT_nonuniform_qualifier : require
+
+// The alpha test of depth-only variants whose material has `RenderState::alpha_test` set. Throws
+// away the fragments fragment.glsl would, so cut-outs cast cut-out shadows.
+
+layout(set=0,binding=0)uniform sampler2D tex_samplers[];
+
+// The same as in fragment.glsl.
+struct MaterialData{
+    vec4 base_colour;
+    // rgb: tint, a: power
+    vec4 specular;
+};
+layout(std430,set=2,binding=0)readonly buffer Materials{
+    MaterialData materials[];
+};
+
+layout(location=0)in vec2 uv_from_vertex_shader;
+layout(location=2)in flat uint tex_id_from_vertex_shader;
+layout(location=3)in flat uint material_id_from_vertex_shader;
+layout(location=7)in vec4 tint_from_vertex_shader;
+
+void main(){
+    float alpha=texture(tex_samplers[nonuniformEXT(tex_id_from_vertex_shader)],uv_from_vertex_shader).a
+        *materials[material_id_from_vertex_shader].base_colour.a*tint_from_vertex_shader.a;
+    if(alpha<0.5){
+        discard;
+    }
+}
@@ -0,0 +1,57 @@
+#version 450
+
+// Single scattering through a uniform atmosphere, see Sky in sky.rs for the parameters.
+
+layout(push_constant)uniform constants{
+    // From clip space to a world space direction, ignores the camera's position.
+    mat4 inverse_view_projection;
+    // xyz: towards the sun, w: sun illuminance
+    vec4 sun;
+    // x: haze, scales Mie scattering
+    vec4 atmosphere;
+}PushConstants;
+
+layout(location=0)in vec2 uv_from_vertex_shader;
+
+layout(location=0)out vec4 output_colour;
+
+// Zenith optical depths for red, green and blue light, the same as in sky.rs.
+const vec3 RAYLEIGH=vec3(0.0399,0.0973,0.2388);
+const vec3 MIE=vec3(0.1);
+const float MIE_G=0.76;
+const float PI=3.14159265;
+// Angular radius of the sun.
+const float SUN_COS=0.99998;
+
+// Kasten and Young, how much more air there is along a ray than straight up.
+float air_mass(float cos_zenith){
+    float zenith=degrees(acos(clamp(cos_zenith,0.0,1.0)));
+    return 1.0/(max(cos_zenith,0.0)+0.50572*pow(96.07995-zenith,-1.6364));
+}
+
+void main(){
+    vec4 point=PushConstants.inverse_view_projection*vec4(uv_from_vertex_shader*2-1,0.5,1);
+    vec3 view=normalize(point.xyz/point.w);
+    vec3 sun=PushConstants.sun.xyz;
+    vec3 mie=MIE*PushConstants.atmosphere.x;
+    vec3 extinction=RAYLEIGH+mie;
+
+    float cos_theta=dot(view,sun);
+    float rayleigh_phase=3.0/(16.0*PI)*(1.0+cos_theta*cos_theta);
+    float mie_phase=(1.0-MIE_G*MIE_G)/(4.0*PI*pow(1.0+MIE_G*MIE_G-2.0*MIE_G*cos_theta,1.5));
+
+    // Below the horizon looks at the ground, lit by the sky's colour at the horizon.
+    float up=max(view.y,0.0);
+    vec3 view_depth=extinction*air_mass(up);
+    vec3 sun_transmittance=exp(-extinction*air_mass(sun.y));
+    vec3 scattering=(RAYLEIGH*rayleigh_phase+mie*mie_phase)/extinction;
+    vec3 sky=PushConstants.sun.w*sun_transmittance*scattering*(1.0-exp(-view_depth));
+    if(cos_theta>SUN_COS&&view.y>=0){
+        sky+=PushConstants.sun.w*exp(-view_depth);
+    }
+    if(view.y<0){
+        sky*=0.3;
+    }
+
+    output_colour=vec4(1.0-exp(-sky),1);
+}
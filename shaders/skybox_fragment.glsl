@@ -0,0 +1,11 @@
+#version 450
+
+layout(set=0,binding=0)uniform samplerCube skybox;
+
+layout(location=0)in vec3 direction_for_fragment_shader;
+
+layout(location=0)out vec4 output_colour;
+
+void main(){
+    output_colour=vec4(texture(skybox,normalize(direction_for_fragment_shader)).rgb,1);
+}
@@ -0,0 +1,21 @@
+#version 450
+
+// A cube around the camera from gl_VertexIndex, 14 vertices drawn as a triangle strip.
+
+layout(push_constant)uniform constants{
+    // The camera's projection and the rotation of its view, the cube doesn't move with it.
+    mat4 view_projection;
+    // x: the depth the cube is drawn at, the far end of the depth range
+    vec4 depth;
+}PushConstants;
+
+layout(location=0)out vec3 direction_for_fragment_shader;
+
+void main(){
+    int bit=1<<gl_VertexIndex;
+    vec3 position=vec3((0x287a&bit)!=0,(0x02af&bit)!=0,(0x31e3&bit)!=0)*2-1;
+    direction_for_fragment_shader=position;
+    vec4 clip=PushConstants.view_projection*vec4(position,1);
+    // Infinitely far away whatever the camera's far plane.
+    gl_Position=vec4(clip.xy,PushConstants.depth.x*clip.w,clip.w);
+}
@@ -0,0 +1,45 @@
+#version 450
+
+// Procedural patterns for checking the swapchain's colour space. Colours are written as linear
+// values, an sRGB swapchain encodes them on the way out and a UNORM one does not.
+
+layout(push_constant)uniform constants{
+    uint pattern;
+}PushConstants;
+
+layout(location=0)in vec2 uv;
+
+layout(location=0)out vec4 output_colour;
+
+const uint GRADIENT_RAMPS=0;
+const uint CHECKERBOARD=1;
+
+vec3 gradient_ramps(){
+    // Four bands: grey, red, green and blue ramps from black to full intensity. The top half of
+    // each band is smooth and the bottom half is quantised into 16 steps that should all be
+    // distinguishable, crushed steps at either end mean the gamma is off.
+    float band=floor(uv.y*4);
+    float within=fract(uv.y*4);
+    float value=within<0.5?uv.x:floor(uv.x*16)/15;
+    vec3 tint=band==0?vec3(1):band==1?vec3(1,0,0):band==2?vec3(0,1,0):vec3(0,0,1);
+    return tint*value;
+}
+
+vec3 checkerboard(){
+    // Left: a one pixel black and white checkerboard, which averages to 50% linear light.
+    // Middle: a flat 50% linear grey, which should blend in with the checkerboard from a distance.
+    // Right: 50% grey as an sRGB code value (0.214 linear), which should look noticeably darker.
+    if(uv.x<1.0/3){
+        ivec2 pixel=ivec2(gl_FragCoord.xy);
+        return vec3(float((pixel.x+pixel.y)&1));
+    }
+    if(uv.x<2.0/3){
+        return vec3(0.5);
+    }
+    return vec3(0.214);
+}
+
+void main(){
+    vec3 colour=PushConstants.pattern==CHECKERBOARD?checkerboard():gradient_ramps();
+    output_colour=vec4(colour,1);
+}
@@ -0,0 +1,13 @@
+#version 450
+
+layout(set=0,binding=0)uniform sampler2D font;
+
+layout(location=0)in vec2 uv_from_vertex_shader;
+layout(location=1)in vec4 colour_from_vertex_shader;
+
+layout(location=0)out vec4 output_colour;
+
+// Glyphs are expected to be white on transparent, so the instance colour tints them.
+void main(){
+    output_colour=colour_from_vertex_shader*texture(font,uv_from_vertex_shader);
+}
@@ -0,0 +1,25 @@
+#version 450
+
+// One instance per glyph, the unit quad is stretched over the glyph's rectangle.
+
+layout(push_constant)uniform constants{
+    // Of the render target, in pixels.
+    vec2 screen_size;
+}PushConstants;
+
+// Pixels from the top left corner of the screen, xy: position, zw: size
+layout(location=0)in vec4 rect;
+// Within the font texture, xy: top left, zw: size
+layout(location=1)in vec4 uv_rect;
+layout(location=2)in vec4 colour;
+layout(location=3)in vec2 corner;
+
+layout(location=0)out vec2 uv_for_fragment_shader;
+layout(location=1)out vec4 colour_for_fragment_shader;
+
+void main(){
+    vec2 position=rect.xy+corner*rect.zw;
+    gl_Position=vec4(position/PushConstants.screen_size*2-1,0,1);
+    uv_for_fragment_shader=uv_rect.xy+corner*uv_rect.zw;
+    colour_for_fragment_shader=colour;
+}
@@ -0,0 +1,52 @@
+#version 450
+
+// Maps the scene, drawn in linear light, into the swapchain's range. See Tonemapper in config.rs.
+
+layout(set=0,binding=0)uniform sampler2D scene;
+
+layout(push_constant)uniform constants{
+    // 2 to the power of the camera's exposure.
+    float exposure;
+    // 0: clamp, 1: Reinhard, 2: ACES
+    uint tonemapper;
+}PushConstants;
+
+layout(location=0)in vec2 uv_from_vertex_shader;
+
+layout(location=0)out vec4 output_colour;
+
+// Set when presenting to an HDR10 surface, which expects PQ encoded BT.2020.
+layout(constant_id=0)const bool HDR10_OUTPUT=false;
+// Where SDR white sits on an HDR display, from BT.2408.
+const float PAPER_WHITE_NITS=203.0;
+
+vec3 bt709_to_bt2020(vec3 colour){
+    return mat3(0.6274,0.0691,0.0164,
+                0.3293,0.9195,0.0880,
+                0.0433,0.0114,0.8956)*colour;
+}
+
+// SMPTE ST 2084, takes absolute luminance in nits.
+vec3 pq_encode(vec3 nits){
+    vec3 y=pow(clamp(nits/10000.0,0.0,1.0),vec3(0.1593017578125));
+    return pow((0.8359375+18.8515625*y)/(1.0+18.6875*y),vec3(78.84375));
+}
+
+// Krzysztof Narkowicz's fit of the ACES reference rendering and output transforms.
+vec3 aces(vec3 colour){
+    return clamp((colour*(2.51*colour+0.03))/(colour*(2.43*colour+0.59)+0.14),0.0,1.0);
+}
+
+void main(){
+    vec3 colour=max(texture(scene,uv_from_vertex_shader).rgb*PushConstants.exposure,vec3(0));
+    if(HDR10_OUTPUT){
+        output_colour=vec4(pq_encode(bt709_to_bt2020(colour)*PAPER_WHITE_NITS),1);
+        return;
+    }
+    if(PushConstants.tonemapper==1){
+        colour=colour/(1.0+colour);
+    }else if(PushConstants.tonemapper==2){
+        colour=aces(colour);
+    }
+    output_colour=vec4(clamp(colour,0.0,1.0),1);
+}
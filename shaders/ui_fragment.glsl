@@ -0,0 +1,19 @@
+#version 450
+#extension GL_EXT_nonuniform_qualifier : require
+
+layout(set=0,binding=0)uniform sampler2D textures[];
+
+layout(push_constant)uniform constants{
+    vec2 screen_size;
+    uint texture_index;
+}PushConstants;
+
+layout(location=0)in vec2 uv_from_vertex_shader;
+layout(location=1)in vec4 colour_from_vertex_shader;
+
+layout(location=0)out vec4 output_colour;
+
+// Both the vertex colour and the texture are premultiplied, see the pipeline's blending.
+void main(){
+    output_colour=colour_from_vertex_shader*texture(textures[PushConstants.texture_index],uv_from_vertex_shader);
+}
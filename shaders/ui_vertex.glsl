@@ -0,0 +1,31 @@
+#version 450
+
+// egui's vertices, in points from the top left corner of the screen.
+
+layout(push_constant)uniform constants{
+    // Of the render target, in points.
+    vec2 screen_size;
+    // The `TextureStore` index of the mesh's texture.
+    uint texture_index;
+}PushConstants;
+
+layout(location=0)in vec2 position;
+layout(location=1)in vec2 uv;
+// sRGB with premultiplied alpha.
+layout(location=2)in vec4 colour;
+
+layout(location=0)out vec2 uv_for_fragment_shader;
+layout(location=1)out vec4 colour_for_fragment_shader;
+
+vec3 linear_from_srgb(vec3 srgb){
+    vec3 lower=srgb/12.92;
+    vec3 higher=pow((srgb+0.055)/1.055,vec3(2.4));
+    return mix(higher,lower,lessThan(srgb,vec3(0.04045)));
+}
+
+void main(){
+    gl_Position=vec4(position/PushConstants.screen_size*2-1,0,1);
+    uv_for_fragment_shader=uv;
+    // The swapchain and textures are sRGB, so blending happens on linear colours.
+    colour_for_fragment_shader=vec4(linear_from_srgb(colour.rgb),colour.a);
+}
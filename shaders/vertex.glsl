@@ -1,22 +1,89 @@
 #version 450
 
-layout(push_constant)uniform constants{
-    mat4 proj;
-}PushConstants;
+// See FrameUniformData in frame_uniforms.rs.
+layout(std140,set=1,binding=0)uniform FrameUniforms{
+    mat4 view;
+    mat4 projection;
+    mat4 view_projection;
+    vec4 camera_position;
+    // x: seconds since the renderer started
+    vec4 time;
+    // xy: wind direction, z: wind strength
+    vec4 wind;
+    vec4 sun_direction;
+    vec4 sun_colour;
+    vec4 ambient;
+    // x: lights in the light buffer
+    uvec4 light_count;
+    // The sun's, for the shadow map
+    mat4 shadow_view_projection;
+    // x: 1 when shadows are on, y: normal bias, z: one shadow map texel in uv
+    vec4 shadow;
+}Frame;
 
 layout(location=0)in mat4 model;
 layout(location=4)in uint tex_id;
 layout(location=5)in vec3 position;
 layout(location=6)in vec2 uv;
+#ifdef COMPRESSED_VERTICES
+// Octahedral encoded, see jr_mesh::CompressedMeshVertex.
+layout(location=7)in vec2 packed_normal;
+#else
 layout(location=7)in vec3 normal;
+#endif
+// x: sway amplitude, y: sway frequency
+layout(location=8)in vec2 sway;
+layout(location=9)in uint material_id;
+// xy: offset, zw: scale, applied to the mesh's uvs
+layout(location=10)in vec4 uv_rect;
+// Spherical harmonic ambient per colour channel, see ShProbe::irradiance_terms
+layout(location=11)in vec4 ambient_r;
+layout(location=12)in vec4 ambient_g;
+layout(location=13)in vec4 ambient_b;
+// Multiplies the albedo
+layout(location=14)in vec4 tint;
 
 layout(location=0)out vec2 uv_for_fragment_shader;
 layout(location=1)out vec3 normal_for_fragment_shader;
 layout(location=2)out uint tex_id_for_fragment_shader;
+layout(location=3)out uint material_id_for_fragment_shader;
+layout(location=4)out vec3 ambient_for_fragment_shader;
+// Distance along the view direction, for the depth debug view.
+layout(location=5)out float view_depth_for_fragment_shader;
+// For the specular highlight's view direction.
+layout(location=6)out vec3 world_position_for_fragment_shader;
+layout(location=7)out vec4 tint_for_fragment_shader;
+
+#ifdef COMPRESSED_VERTICES
+vec3 decode_normal(vec2 e){
+    vec3 n=vec3(e,1-abs(e.x)-abs(e.y));
+    if(n.z<0){
+        n.xy=(1-abs(n.yx))*vec2(n.x>=0?1:-1,n.y>=0?1:-1);
+    }
+    return normalize(n);
+}
+#endif
 
 void main(){
-    gl_Position=PushConstants.proj*model*vec4(position,1);
+#ifdef COMPRESSED_VERTICES
+    vec3 normal=decode_normal(packed_normal);
+#endif
+    vec4 world_position=model*vec4(position,1);
+    if(sway.x>0){
+        // Offset the phase by world position so neighbouring instances don't move in lockstep,
+        // and scale by height so the base of the mesh stays planted.
+        float phase=Frame.time.x*sway.y*6.2831853+world_position.x+world_position.z;
+        float bend=sway.x*Frame.wind.z*sin(phase)*max(position.y,0);
+        world_position.xz+=Frame.wind.xy*bend;
+    }
+    gl_Position=Frame.view_projection*world_position;
+    world_position_for_fragment_shader=world_position.xyz;
+    view_depth_for_fragment_shader=gl_Position.w;
     tex_id_for_fragment_shader = tex_id;
-    uv_for_fragment_shader=uv;
+    material_id_for_fragment_shader=material_id;
+    uv_for_fragment_shader=uv_rect.xy+uv*uv_rect.zw;
+    tint_for_fragment_shader=tint;
     normal_for_fragment_shader=normalize(mat3(model)*normal);
+    vec4 sh_normal=vec4(1,normal_for_fragment_shader);
+    ambient_for_fragment_shader=max(vec3(dot(ambient_r,sh_normal),dot(ambient_g,sh_normal),dot(ambient_b,sh_normal)),0);
 }